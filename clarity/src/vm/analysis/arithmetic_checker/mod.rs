@@ -27,6 +27,7 @@ use crate::vm::types::{
 };
 
 use crate::vm::variables::NativeVariables;
+use crate::vm::ClarityVersion;
 use std::collections::HashMap;
 
 pub use super::errors::{
@@ -44,7 +45,9 @@ mod tests;
 ///  any database operations, traits, or iterating operations (e.g., list
 ///  operations)
 ///
-pub struct ArithmeticOnlyChecker();
+pub struct ArithmeticOnlyChecker<'a> {
+    clarity_version: &'a ClarityVersion,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Error {
@@ -67,14 +70,16 @@ impl std::fmt::Display for Error {
     }
 }
 
-impl ArithmeticOnlyChecker {
+impl<'a> ArithmeticOnlyChecker<'a> {
     pub fn check_contract_cost_eligible(contract_analysis: &mut ContractAnalysis) {
         let is_eligible = ArithmeticOnlyChecker::run(contract_analysis).is_ok();
         contract_analysis.is_cost_contract_eligible = is_eligible;
     }
 
     pub fn run(contract_analysis: &ContractAnalysis) -> Result<(), Error> {
-        let checker = ArithmeticOnlyChecker();
+        let checker = ArithmeticOnlyChecker {
+            clarity_version: &contract_analysis.clarity_version,
+        };
         for exp in contract_analysis.expressions.iter() {
             checker.check_top_levels(&exp)?;
         }
@@ -156,7 +161,7 @@ impl ArithmeticOnlyChecker {
         function: &str,
         args: &[SymbolicExpression],
     ) -> Option<Result<(), Error>> {
-        NativeFunctions::lookup_by_name(function)
+        NativeFunctions::lookup_by_name_at_version(function, self.clarity_version)
             .map(|function| self.check_native_function(function, args))
     }
 
@@ -169,8 +174,8 @@ impl ArithmeticOnlyChecker {
         match function {
             FetchVar | GetBlockInfo | GetTokenBalance | GetAssetOwner | FetchEntry | SetEntry
             | DeleteEntry | InsertEntry | SetVar | MintAsset | MintToken | TransferAsset
-            | TransferToken | ContractCall | StxTransfer | StxBurn | AtBlock | GetStxBalance
-            | GetTokenSupply | BurnToken | BurnAsset => {
+            | TransferToken | ContractCall | StxTransfer | StxTransferMemo | StxBurn | AtBlock
+            | GetStxBalance | GetStxAccount | GetTokenSupply | BurnToken | BurnAsset => {
                 return Err(Error::FunctionNotPermitted(function));
             }
             Append | Concat | AsMaxLen | ContractOf | PrincipalOf | ListCons | Print
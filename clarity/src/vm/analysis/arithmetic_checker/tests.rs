@@ -24,6 +24,7 @@ use crate::vm::functions::define::DefineFunctions;
 use crate::vm::functions::NativeFunctions;
 use crate::vm::types::QualifiedContractIdentifier;
 use crate::vm::variables::NativeVariables;
+use crate::vm::ClarityVersion;
 
 fn arithmetic_check(contract: &str) -> Result<(), Error> {
     let contract_identifier = QualifiedContractIdentifier::transient();
@@ -33,6 +34,7 @@ fn arithmetic_check(contract: &str) -> Result<(), Error> {
         contract_identifier,
         expressions,
         LimitedCostTracker::new_free(),
+        ClarityVersion::Clarity1,
     );
 
     ArithmeticOnlyChecker::run(&analysis)
@@ -14,12 +14,16 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::vm::analysis::cost_bounds::CostBound;
+use crate::vm::analysis::errors::CheckErrors;
 use crate::vm::analysis::types::ContractAnalysis;
 use crate::vm::types::{
-    FixedFunction, FunctionArg, FunctionType, TupleTypeSignature, TypeSignature,
+    BufferLength, FixedFunction, FunctionArg, FunctionType, SequenceSubtype, StringSubtype,
+    StringUTF8Length, TupleTypeSignature, TypeSignature,
 };
 use crate::vm::ClarityName;
 use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 
 pub fn build_contract_interface(contract_analysis: &ContractAnalysis) -> ContractInterface {
     let mut contract_interface = ContractInterface::new();
@@ -41,6 +45,7 @@ pub fn build_contract_interface(contract_analysis: &ContractAnalysis) -> Contrac
         cost_track: _,
         contract_interface: _,
         is_cost_contract_eligible: _,
+        clarity_version: _,
     } = contract_analysis;
 
     contract_interface
@@ -211,6 +216,46 @@ impl ContractInterfaceAtomType {
             }
         }
     }
+
+    /// The inverse of `from_type_signature`.  The interface does not say which trait a
+    /// `trait_reference` implements, so it comes back as a `PrincipalType`.
+    pub fn to_type_signature(&self) -> Result<TypeSignature, CheckErrors> {
+        use crate::vm::analysis::contract_interface_builder::ContractInterfaceAtomType::*;
+
+        let sig = match self {
+            none => TypeSignature::NoType,
+            int128 => TypeSignature::IntType,
+            uint128 => TypeSignature::UIntType,
+            bool => TypeSignature::BoolType,
+            principal | trait_reference => TypeSignature::PrincipalType,
+            buffer { length } => TypeSignature::SequenceType(SequenceSubtype::BufferType(
+                BufferLength::try_from(*length)?,
+            )),
+            string_ascii { length } => TypeSignature::SequenceType(SequenceSubtype::StringType(
+                StringSubtype::ASCII(BufferLength::try_from(*length)?),
+            )),
+            string_utf8 { length } => TypeSignature::SequenceType(SequenceSubtype::StringType(
+                StringSubtype::UTF8(StringUTF8Length::try_from(*length)?),
+            )),
+            tuple(entries) => {
+                let mut type_data = vec![];
+                for entry in entries.iter() {
+                    let name = ClarityName::try_from(entry.name.clone())
+                        .map_err(|_| CheckErrors::BadTupleFieldName)?;
+                    type_data.push((name, entry.type_f.to_type_signature()?));
+                }
+                TypeSignature::TupleType(TupleTypeSignature::try_from(type_data)?)
+            }
+            optional(inner) => TypeSignature::new_option(inner.to_type_signature()?)?,
+            response { ok, error } => {
+                TypeSignature::new_response(ok.to_type_signature()?, error.to_type_signature()?)?
+            }
+            list { type_f, length } => {
+                TypeSignature::list_of(type_f.to_type_signature()?, *length)?
+            }
+        };
+        Ok(sig)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -245,6 +290,9 @@ pub struct ContractInterfaceFunction {
     pub access: ContractInterfaceFunctionAccess,
     pub args: Vec<ContractInterfaceFunctionArg>,
     pub outputs: ContractInterfaceFunctionOutput,
+    /// The worst-case cost of calling the function, if it has been computed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_bound: Option<CostBound>,
 }
 
 impl ContractInterfaceFunction {
@@ -272,6 +320,7 @@ impl ContractInterfaceFunction {
                     }
                     _ => panic!("Contract functions should only have fixed function arguments!"),
                 },
+                cost_bound: None,
             })
             .collect()
     }
@@ -373,6 +422,17 @@ impl ContractInterface {
     pub fn serialize(&self) -> String {
         serde_json::to_string(self).expect("Failed to serialize contract interface")
     }
+
+    /// Attach the cost bounds computed by `cost_bounds::compute_cost_bounds` to the
+    /// functions they were computed for.
+    pub fn set_cost_bounds(&mut self, cost_bounds: &BTreeMap<ClarityName, CostBound>) {
+        for function in self.functions.iter_mut() {
+            function.cost_bound = ClarityName::try_from(function.name.clone())
+                .ok()
+                .and_then(|name| cost_bounds.get(&name))
+                .cloned();
+        }
+    }
 }
 
 #[test]
@@ -398,3 +458,20 @@ fn test_string_rename_utf8() {
         "{\"name\":\"test-utf8\",\"type\":{\"string-utf8\":{\"length\":32}}}"
     );
 }
+
+#[test]
+fn test_to_type_signature() {
+    let sigs = [
+        "int",
+        "(buff 20)",
+        "(string-ascii 10)",
+        "(string-utf8 10)",
+        "(optional (list 5 { a: uint, b: principal }))",
+        "(response (tuple (c bool)) int)",
+    ];
+    for sig in sigs.iter() {
+        let type_sig = TypeSignature::from(*sig);
+        let atom_type = ContractInterfaceAtomType::from_type_signature(&type_sig);
+        assert_eq!(atom_type.to_type_signature().unwrap(), type_sig);
+    }
+}
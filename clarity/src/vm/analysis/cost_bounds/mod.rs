@@ -0,0 +1,695 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Static worst-case cost bounds for a contract's public and read-only functions.
+//!
+//! The bound of a function is computed by walking its body and charging, for every
+//! expression, what the interpreter would charge when evaluating it in Stacks 2.05, with
+//! every value as large as its type allows: lists at their max length, buffers and strings
+//! at their declared length, both branches of an `if` priced and the dearer one kept, and
+//! every `fold`, `map` and `filter` running once per element of a full list.  The cost
+//! functions themselves are evaluated with the given cost tracker, so the bounds follow
+//! whatever cost contract (e.g., `costs-2`) the tracker was loaded with.
+//!
+//! The cost of a `contract-call?` to another contract, beyond evaluating its arguments, is
+//! not known here, so bounds of functions which make one are marked as incomplete.  The
+//! bounds do not include the cost of loading the contract for a transaction, or memory.
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::vm::analysis::ContractAnalysis;
+use crate::vm::callables::CallableType;
+use crate::vm::costs::cost_functions::ClarityCostFunction;
+use crate::vm::costs::{CostErrors, CostTracker, ExecutionCost};
+use crate::vm::functions::define::DefineFunctionsParsed;
+use crate::vm::functions::{lookup_reserved_functions, NativeFunctions};
+use crate::vm::representations::{ClarityName, SymbolicExpression, SymbolicExpressionType};
+use crate::vm::types::signatures::TypeSignature;
+use crate::vm::types::{FunctionType, SequenceSubtype, StringSubtype};
+use crate::vm::variables::NativeVariables;
+
+#[cfg(test)]
+mod tests;
+
+/// An upper bound on the cost of one call of a function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostBound {
+    pub cost: ExecutionCost,
+    /// False if the function calls other contracts, whose costs are not included in `cost`.
+    pub complete: bool,
+}
+
+impl CostBound {
+    fn zero() -> CostBound {
+        CostBound {
+            cost: ExecutionCost::zero(),
+            complete: true,
+        }
+    }
+
+    fn add(&mut self, other: &CostBound) {
+        let cost = &mut self.cost;
+        cost.write_length = cost.write_length.saturating_add(other.cost.write_length);
+        cost.write_count = cost.write_count.saturating_add(other.cost.write_count);
+        cost.read_length = cost.read_length.saturating_add(other.cost.read_length);
+        cost.read_count = cost.read_count.saturating_add(other.cost.read_count);
+        cost.runtime = cost.runtime.saturating_add(other.cost.runtime);
+        self.complete &= other.complete;
+    }
+
+    fn add_cost(&mut self, cost: &ExecutionCost) {
+        self.add(&CostBound {
+            cost: cost.clone(),
+            complete: true,
+        });
+    }
+
+    fn multiply(&self, times: u64) -> CostBound {
+        let cost = &self.cost;
+        CostBound {
+            cost: ExecutionCost {
+                write_length: cost.write_length.saturating_mul(times),
+                write_count: cost.write_count.saturating_mul(times),
+                read_length: cost.read_length.saturating_mul(times),
+                read_count: cost.read_count.saturating_mul(times),
+                runtime: cost.runtime.saturating_mul(times),
+            },
+            complete: self.complete,
+        }
+    }
+
+    /// The bound of evaluating either `self` or `other`.
+    fn max(&self, other: &CostBound) -> CostBound {
+        CostBound {
+            cost: ExecutionCost::max_cost(self.cost.clone(), other.cost.clone()),
+            complete: self.complete && other.complete,
+        }
+    }
+}
+
+/// The most bytes a value of type `t` can serialize to.
+fn max_serialized_size(t: &TypeSignature) -> u64 {
+    use crate::vm::types::TypeSignature::*;
+    match t {
+        NoType | BoolType => 1,
+        IntType | UIntType => 17,
+        // a contract principal: type prefix, version, hash160, and a length-prefixed name
+        PrincipalType | TraitReferenceType(_) => 1 + 1 + 20 + 1 + 128,
+        SequenceType(SequenceSubtype::BufferType(len))
+        | SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(len))) => {
+            5 + u64::from(u32::from(len))
+        }
+        SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(len))) => {
+            5 + 4 * u64::from(u32::from(len))
+        }
+        SequenceType(SequenceSubtype::ListType(list_type)) => {
+            5 + u64::from(list_type.get_max_len())
+                .saturating_mul(max_serialized_size(list_type.get_list_item_type()))
+        }
+        TupleType(tuple_type) => {
+            5 + tuple_type
+                .get_type_map()
+                .iter()
+                .map(|(name, t)| 1 + name.len() as u64 + max_serialized_size(t))
+                .sum::<u64>()
+        }
+        OptionalType(t) => 1 + max_serialized_size(t),
+        ResponseType(v) => 1 + cmp::max(max_serialized_size(&v.0), max_serialized_size(&v.1)),
+    }
+}
+
+/// The max length of a sequence type, and the type of its elements.
+fn sequence_bounds(t: &TypeSignature) -> Option<(u64, TypeSignature)> {
+    match t {
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list_type)) => Some((
+            u64::from(list_type.get_max_len()),
+            list_type.get_list_item_type().clone(),
+        )),
+        TypeSignature::SequenceType(SequenceSubtype::BufferType(len)) => {
+            Some((u64::from(u32::from(len)), TypeSignature::min_buffer()))
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(len))) => {
+            Some((u64::from(u32::from(len)), TypeSignature::min_string_ascii()))
+        }
+        TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(len))) => {
+            Some((u64::from(u32::from(len)), TypeSignature::min_string_utf8()))
+        }
+        _ => None,
+    }
+}
+
+fn fixed_argument_types(function_type: &FunctionType) -> Vec<TypeSignature> {
+    match function_type {
+        FunctionType::Fixed(fixed) => fixed.args.iter().map(|a| a.signature.clone()).collect(),
+        _ => vec![],
+    }
+}
+
+struct CostBoundChecker<'a, C: CostTracker> {
+    contract_analysis: &'a ContractAnalysis,
+    cost_tracker: &'a mut C,
+    function_bodies: HashMap<&'a str, &'a SymbolicExpression>,
+    /// The bounds of calling each function of the contract, as they are computed.
+    call_bounds: HashMap<&'a str, CostBound>,
+    computing: HashSet<&'a str>,
+}
+
+impl<'a, C: CostTracker> CostBoundChecker<'a, C> {
+    fn charge(
+        &mut self,
+        bound: &mut CostBound,
+        cost_function: ClarityCostFunction,
+        input: u64,
+    ) -> Result<(), CostErrors> {
+        let cost = self.cost_tracker.compute_cost(cost_function, &[input])?;
+        bound.add_cost(&cost);
+        Ok(())
+    }
+
+    fn type_of(&self, expr: &SymbolicExpression) -> Option<&'a TypeSignature> {
+        self.contract_analysis
+            .type_map
+            .as_ref()
+            .and_then(|type_map| type_map.get_type(expr))
+    }
+
+    fn function_type(&self, name: &str) -> Option<&'a FunctionType> {
+        self.contract_analysis
+            .get_public_function_type(name)
+            .or_else(|| self.contract_analysis.get_read_only_function_type(name))
+            .or_else(|| self.contract_analysis.get_private_function(name))
+    }
+
+    /// The bound of applying a function of this contract to already evaluated arguments.
+    fn call_bound(&mut self, name: &'a str) -> Result<CostBound, CostErrors> {
+        if let Some(bound) = self.call_bounds.get(name) {
+            return Ok(bound.clone());
+        }
+        let (function_type, body) = match (self.function_type(name), self.function_bodies.get(name))
+        {
+            (Some(function_type), Some(body)) => (function_type, *body),
+            // the type checker rejects recursion, but don't rely on that here
+            _ => {
+                return Ok(CostBound {
+                    complete: false,
+                    ..CostBound::zero()
+                })
+            }
+        };
+        if !self.computing.insert(name) {
+            return Ok(CostBound {
+                complete: false,
+                ..CostBound::zero()
+            });
+        }
+
+        let argument_types = fixed_argument_types(function_type);
+        let mut bound = CostBound::zero();
+        self.charge(
+            &mut bound,
+            ClarityCostFunction::UserFunctionApplication,
+            argument_types.len() as u64,
+        )?;
+        for argument_type in argument_types.iter() {
+            self.charge(
+                &mut bound,
+                ClarityCostFunction::InnerTypeCheckCost,
+                u64::from(argument_type.size()),
+            )?;
+        }
+        bound.add(&self.expression_bound(body, 0)?);
+
+        self.computing.remove(name);
+        self.call_bounds.insert(name, bound.clone());
+        Ok(bound)
+    }
+
+    /// The bound of applying the function named `name` to already evaluated arguments of
+    /// the given types, as `map`, `filter` and `fold` do.
+    fn apply_bound(
+        &mut self,
+        name: &'a str,
+        argument_types: &[TypeSignature],
+    ) -> Result<CostBound, CostErrors> {
+        match lookup_reserved_functions(name, &self.contract_analysis.clarity_version) {
+            Some(CallableType::NativeFunction(_, _, cost_function)) => {
+                let mut bound = CostBound::zero();
+                self.charge(&mut bound, cost_function, argument_types.len() as u64)?;
+                Ok(bound)
+            }
+            Some(CallableType::NativeFunction205(_, _, cost_function, _)) => {
+                let mut bound = CostBound::zero();
+                let size = argument_types.iter().map(max_serialized_size).sum();
+                self.charge(&mut bound, cost_function, size)?;
+                Ok(bound)
+            }
+            // special functions can't be passed to iterating functions
+            Some(_) => Ok(CostBound {
+                complete: false,
+                ..CostBound::zero()
+            }),
+            None => self.call_bound(name),
+        }
+    }
+
+    fn sum_bounds(
+        &mut self,
+        exprs: &'a [SymbolicExpression],
+        depth: u64,
+    ) -> Result<CostBound, CostErrors> {
+        let mut bound = CostBound::zero();
+        for expr in exprs.iter() {
+            bound.add(&self.expression_bound(expr, depth)?);
+        }
+        Ok(bound)
+    }
+
+    fn size_of(&self, expr: &SymbolicExpression) -> u64 {
+        self.type_of(expr).map(|t| u64::from(t.size())).unwrap_or(0)
+    }
+
+    fn serialized_size_of(&self, expr: &SymbolicExpression) -> u64 {
+        self.type_of(expr).map(max_serialized_size).unwrap_or(0)
+    }
+
+    /// The bound of evaluating `expr` in a local context nested `depth` levels deep.
+    fn expression_bound(
+        &mut self,
+        expr: &'a SymbolicExpression,
+        depth: u64,
+    ) -> Result<CostBound, CostErrors> {
+        match expr.expr {
+            SymbolicExpressionType::AtomValue(_)
+            | SymbolicExpressionType::LiteralValue(_)
+            | SymbolicExpressionType::Field(_)
+            | SymbolicExpressionType::TraitReference(..) => Ok(CostBound::zero()),
+            SymbolicExpressionType::Atom(ref name) => self.variable_bound(expr, name, depth),
+            SymbolicExpressionType::List(ref list) => {
+                let (name, args) = match list.split_first() {
+                    Some((function, args)) => match function.match_atom() {
+                        Some(name) => (name, args),
+                        None => return Ok(CostBound::zero()),
+                    },
+                    None => return Ok(CostBound::zero()),
+                };
+                let mut bound = CostBound::zero();
+                self.charge(&mut bound, ClarityCostFunction::LookupFunction, 0)?;
+                match NativeFunctions::lookup_by_name_at_version(
+                    name,
+                    &self.contract_analysis.clarity_version,
+                ) {
+                    Some(native) => bound.add(&self.native_bound(native, expr, args, depth)?),
+                    None => {
+                        bound.add(&self.sum_bounds(args, depth)?);
+                        bound.add(&self.call_bound(name)?);
+                    }
+                }
+                Ok(bound)
+            }
+        }
+    }
+
+    fn variable_bound(
+        &mut self,
+        expr: &SymbolicExpression,
+        name: &str,
+        depth: u64,
+    ) -> Result<CostBound, CostErrors> {
+        let mut bound = CostBound::zero();
+        match NativeVariables::lookup_by_name(name) {
+            Some(NativeVariables::BlockHeight)
+            | Some(NativeVariables::BurnBlockHeight)
+            | Some(NativeVariables::TotalLiquidMicroSTX) => {
+                self.charge(&mut bound, ClarityCostFunction::FetchVar, 1)?;
+            }
+            Some(_) => {}
+            None => {
+                self.charge(&mut bound, ClarityCostFunction::LookupVariableDepth, depth)?;
+                let size = match self.type_of(expr) {
+                    Some(t) => u64::from(t.size()),
+                    None => self
+                        .contract_analysis
+                        .get_variable_type(name)
+                        .map(|t| u64::from(t.size()))
+                        .unwrap_or(0),
+                };
+                self.charge(&mut bound, ClarityCostFunction::LookupVariableSize, size)?;
+            }
+        }
+        Ok(bound)
+    }
+
+    fn native_bound(
+        &mut self,
+        native: NativeFunctions,
+        expr: &'a SymbolicExpression,
+        args: &'a [SymbolicExpression],
+        depth: u64,
+    ) -> Result<CostBound, CostErrors> {
+        use crate::vm::functions::NativeFunctions::*;
+
+        let mut bound = CostBound::zero();
+        match native {
+            If => {
+                self.charge(&mut bound, ClarityCostFunction::If, 0)?;
+                if let [condition, then_branch, else_branch] = args {
+                    bound.add(&self.expression_bound(condition, depth)?);
+                    let then_bound = self.expression_bound(then_branch, depth)?;
+                    let else_bound = self.expression_bound(else_branch, depth)?;
+                    bound.add(&then_bound.max(&else_bound));
+                }
+            }
+            Asserts => {
+                self.charge(&mut bound, ClarityCostFunction::Asserts, 0)?;
+                bound.add(&self.sum_bounds(args, depth)?);
+            }
+            Match => {
+                self.charge(&mut bound, ClarityCostFunction::Match, 0)?;
+                match args {
+                    // (match opt some-name some-branch none-branch)
+                    [input, _, some_branch, none_branch] => {
+                        bound.add(&self.expression_bound(input, depth)?);
+                        let some_bound = self.expression_bound(some_branch, depth + 1)?;
+                        let none_bound = self.expression_bound(none_branch, depth)?;
+                        bound.add(&some_bound.max(&none_bound));
+                    }
+                    // (match resp ok-name ok-branch err-name err-branch)
+                    [input, _, ok_branch, _, err_branch] => {
+                        bound.add(&self.expression_bound(input, depth)?);
+                        let ok_bound = self.expression_bound(ok_branch, depth + 1)?;
+                        let err_bound = self.expression_bound(err_branch, depth + 1)?;
+                        bound.add(&ok_bound.max(&err_bound));
+                    }
+                    _ => {}
+                }
+            }
+            And | Or => {
+                let cost_function = if native == And {
+                    ClarityCostFunction::And
+                } else {
+                    ClarityCostFunction::Or
+                };
+                self.charge(&mut bound, cost_function, args.len() as u64)?;
+                bound.add(&self.sum_bounds(args, depth)?);
+            }
+            Let => {
+                if let Some((bindings, body)) = args.split_first() {
+                    let bindings = bindings.match_list().unwrap_or(&[]);
+                    self.charge(&mut bound, ClarityCostFunction::Let, bindings.len() as u64)?;
+                    for binding in bindings.iter() {
+                        if let Some([_, value]) = binding.match_list() {
+                            bound.add(&self.expression_bound(value, depth + 1)?);
+                        }
+                    }
+                    bound.add(&self.sum_bounds(body, depth + 1)?);
+                }
+            }
+            Map | Filter | Fold => {
+                let function_name = match args.first().and_then(|f| f.match_atom()) {
+                    Some(name) => name,
+                    None => return Ok(bound),
+                };
+                let sequences = match native {
+                    Fold => args.get(1..2).unwrap_or(&[]),
+                    _ => &args[1..],
+                };
+                let cost_function = match native {
+                    Map => ClarityCostFunction::Map,
+                    Filter => ClarityCostFunction::Filter,
+                    _ => ClarityCostFunction::Fold,
+                };
+                let input = if native == Map { args.len() as u64 } else { 0 };
+                self.charge(&mut bound, cost_function, input)?;
+                self.charge(&mut bound, ClarityCostFunction::LookupFunction, 0)?;
+                bound.add(&self.sum_bounds(&args[1..], depth)?);
+
+                // the function is applied once per element of the shortest sequence
+                let mut iterations = u64::MAX;
+                let mut argument_types = vec![];
+                for sequence in sequences.iter() {
+                    match self.type_of(sequence).and_then(sequence_bounds) {
+                        Some((max_len, element_type)) => {
+                            iterations = cmp::min(iterations, max_len);
+                            argument_types.push(element_type);
+                        }
+                        None => {
+                            iterations = 0;
+                            bound.complete = false;
+                        }
+                    }
+                }
+                if native == Fold {
+                    // the accumulator has the type of the fold's result
+                    argument_types
+                        .push(self.type_of(expr).cloned().unwrap_or(TypeSignature::NoType));
+                }
+                let apply_bound = self.apply_bound(function_name, &argument_types)?;
+                bound.add(&apply_bound.multiply(iterations));
+            }
+            Concat => {
+                bound.add(&self.sum_bounds(args, depth)?);
+                let length = args
+                    .iter()
+                    .filter_map(|arg| self.type_of(arg).and_then(sequence_bounds))
+                    .map(|(max_len, _)| max_len)
+                    .sum();
+                self.charge(&mut bound, ClarityCostFunction::Concat, length)?;
+            }
+            Append => {
+                bound.add(&self.sum_bounds(args, depth)?);
+                let entry_size = args
+                    .first()
+                    .and_then(|list| self.type_of(list))
+                    .and_then(|t| match t {
+                        TypeSignature::SequenceType(SequenceSubtype::ListType(list_type)) => {
+                            Some(u64::from(list_type.get_list_item_type().size()))
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or(0);
+                let element_size = args.get(1).map(|e| self.size_of(e)).unwrap_or(0);
+                self.charge(
+                    &mut bound,
+                    ClarityCostFunction::Append,
+                    cmp::max(entry_size, element_size),
+                )?;
+            }
+            AsMaxLen => {
+                if let Some(sequence) = args.first() {
+                    bound.add(&self.expression_bound(sequence, depth)?);
+                }
+                self.charge(&mut bound, ClarityCostFunction::AsMaxLen, 0)?;
+            }
+            ListCons => {
+                bound.add(&self.sum_bounds(args, depth)?);
+                let size = args.iter().map(|arg| self.size_of(arg)).sum();
+                self.charge(&mut bound, ClarityCostFunction::ListCons, size)?;
+            }
+            FetchVar | SetVar => {
+                let value_type = args
+                    .first()
+                    .and_then(|name| name.match_atom())
+                    .and_then(|name| self.contract_analysis.get_persisted_variable_type(name));
+                let size = value_type.map(max_serialized_size).unwrap_or(0);
+                if native == SetVar {
+                    bound.add(&self.sum_bounds(&args[1..], depth)?);
+                    self.charge(&mut bound, ClarityCostFunction::SetVar, size)?;
+                } else {
+                    self.charge(&mut bound, ClarityCostFunction::FetchVar, size)?;
+                }
+            }
+            FetchEntry | SetEntry | InsertEntry | DeleteEntry => {
+                bound.add(&self.sum_bounds(args.get(1..).unwrap_or(&[]), depth)?);
+                let size = args
+                    .first()
+                    .and_then(|name| name.match_atom())
+                    .and_then(|name| self.contract_analysis.get_map_type(name))
+                    .map(|(key_type, value_type)| {
+                        // entries are stored as optionals
+                        max_serialized_size(key_type) + 1 + max_serialized_size(value_type)
+                    })
+                    .unwrap_or(0);
+                let cost_function = if native == FetchEntry {
+                    ClarityCostFunction::FetchEntry
+                } else {
+                    ClarityCostFunction::SetEntry
+                };
+                self.charge(&mut bound, cost_function, size)?;
+            }
+            TupleCons => {
+                self.charge(
+                    &mut bound,
+                    ClarityCostFunction::TupleCons,
+                    args.len() as u64,
+                )?;
+                for pair in args.iter() {
+                    if let Some([_, value]) = pair.match_list() {
+                        bound.add(&self.expression_bound(value, depth)?);
+                    }
+                }
+            }
+            TupleGet => {
+                if let Some(tuple) = args.get(1) {
+                    bound.add(&self.expression_bound(tuple, depth)?);
+                    let fields = match self.type_of(tuple) {
+                        Some(TypeSignature::TupleType(tuple_type)) => tuple_type.len(),
+                        Some(TypeSignature::OptionalType(inner)) => match **inner {
+                            TypeSignature::TupleType(ref tuple_type) => tuple_type.len(),
+                            _ => 0,
+                        },
+                        _ => 0,
+                    };
+                    self.charge(&mut bound, ClarityCostFunction::TupleGet, fields)?;
+                }
+            }
+            Print => {
+                bound.add(&self.sum_bounds(args, depth)?);
+                let size = args.first().map(|arg| self.size_of(arg)).unwrap_or(0);
+                self.charge(&mut bound, ClarityCostFunction::Print, size)?;
+            }
+            ContractCall => {
+                self.charge(&mut bound, ClarityCostFunction::ContractCall, 0)?;
+                bound.add(&self.sum_bounds(args.get(2..).unwrap_or(&[]), depth)?);
+                bound.complete = false;
+            }
+            AsContract | AtBlock => {
+                if native == AtBlock {
+                    self.charge(&mut bound, ClarityCostFunction::AtBlock, 0)?;
+                }
+                bound.add(&self.sum_bounds(args, depth)?);
+            }
+            ContractOf => {
+                self.charge(&mut bound, ClarityCostFunction::ContractOf, 0)?;
+            }
+            PrincipalOf | Secp256k1Recover | Secp256k1Verify | GetStxBalance | GetStxAccount
+            | StxTransfer | StxTransferMemo | StxBurn => {
+                let cost_function = match native {
+                    PrincipalOf => ClarityCostFunction::PrincipalOf,
+                    Secp256k1Recover => ClarityCostFunction::Secp256k1recover,
+                    Secp256k1Verify => ClarityCostFunction::Secp256k1verify,
+                    GetStxBalance => ClarityCostFunction::StxBalance,
+                    GetStxAccount => ClarityCostFunction::StxGetAccount,
+                    StxTransferMemo => ClarityCostFunction::StxTransferMemo,
+                    _ => ClarityCostFunction::StxTransfer,
+                };
+                self.charge(&mut bound, cost_function, 0)?;
+                bound.add(&self.sum_bounds(args, depth)?);
+            }
+            GetBlockInfo => {
+                self.charge(&mut bound, ClarityCostFunction::BlockInfo, 0)?;
+                bound.add(&self.sum_bounds(args.get(1..).unwrap_or(&[]), depth)?);
+            }
+            GetTokenBalance | TransferToken | MintToken | GetTokenSupply | BurnToken => {
+                let cost_function = match native {
+                    GetTokenBalance => ClarityCostFunction::FtBalance,
+                    TransferToken => ClarityCostFunction::FtTransfer,
+                    MintToken => ClarityCostFunction::FtMint,
+                    GetTokenSupply => ClarityCostFunction::FtSupply,
+                    _ => ClarityCostFunction::FtBurn,
+                };
+                self.charge(&mut bound, cost_function, 0)?;
+                bound.add(&self.sum_bounds(args.get(1..).unwrap_or(&[]), depth)?);
+            }
+            GetAssetOwner | TransferAsset | MintAsset | BurnAsset => {
+                let asset_size = args
+                    .first()
+                    .and_then(|name| name.match_atom())
+                    .and_then(|name| self.contract_analysis.non_fungible_tokens.get(name))
+                    .map(max_serialized_size)
+                    .unwrap_or(0);
+                let cost_function = match native {
+                    GetAssetOwner => ClarityCostFunction::NftOwner,
+                    TransferAsset => ClarityCostFunction::NftTransfer,
+                    MintAsset => ClarityCostFunction::NftMint,
+                    _ => {
+                        // burning charges once before and once after looking up the asset
+                        self.charge(&mut bound, ClarityCostFunction::NftBurn, 0)?;
+                        ClarityCostFunction::NftBurn
+                    }
+                };
+                self.charge(&mut bound, cost_function, asset_size)?;
+                bound.add(&self.sum_bounds(args.get(1..).unwrap_or(&[]), depth)?);
+            }
+            _ => {
+                // the remaining functions evaluate all of their arguments, and then charge
+                //  their cost function for the number or size of the arguments.
+                bound.add(&self.sum_bounds(args, depth)?);
+                match lookup_reserved_functions(
+                    native.get_name_str(),
+                    &self.contract_analysis.clarity_version,
+                ) {
+                    Some(CallableType::NativeFunction(_, _, cost_function)) => {
+                        self.charge(&mut bound, cost_function, args.len() as u64)?;
+                    }
+                    Some(CallableType::NativeFunction205(_, _, cost_function, _)) => {
+                        let size = args.iter().map(|arg| self.serialized_size_of(arg)).sum();
+                        self.charge(&mut bound, cost_function, size)?;
+                    }
+                    _ => bound.complete = false,
+                }
+            }
+        }
+        Ok(bound)
+    }
+}
+
+/// Compute the worst-case cost of calling each public and read-only function of a contract
+/// which has just been analyzed, using `cost_tracker`'s cost functions.  The tracker is not
+/// charged for anything.
+pub fn compute_cost_bounds<C: CostTracker>(
+    contract_analysis: &ContractAnalysis,
+    cost_tracker: &mut C,
+) -> Result<BTreeMap<ClarityName, CostBound>, CostErrors> {
+    if contract_analysis.type_map.is_none() {
+        return Err(CostErrors::CostComputationFailed(
+            "Cost bounds require the type-checked expressions of the contract".into(),
+        ));
+    }
+
+    let mut function_bodies = HashMap::new();
+    for expr in contract_analysis.expressions.iter() {
+        let (signature, body) = match DefineFunctionsParsed::try_parse(expr) {
+            Ok(Some(DefineFunctionsParsed::PrivateFunction { signature, body }))
+            | Ok(Some(DefineFunctionsParsed::ReadOnlyFunction { signature, body }))
+            | Ok(Some(DefineFunctionsParsed::PublicFunction { signature, body })) => {
+                (signature, body)
+            }
+            _ => continue,
+        };
+        if let Some(name) = signature.first().and_then(|name| name.match_atom()) {
+            function_bodies.insert(name.as_str(), body);
+        }
+    }
+
+    let mut checker = CostBoundChecker {
+        contract_analysis,
+        cost_tracker,
+        function_bodies,
+        call_bounds: HashMap::new(),
+        computing: HashSet::new(),
+    };
+
+    let mut bounds = BTreeMap::new();
+    for name in contract_analysis
+        .public_function_types
+        .keys()
+        .chain(contract_analysis.read_only_function_types.keys())
+    {
+        bounds.insert(name.clone(), checker.call_bound(name.as_str())?);
+    }
+    Ok(bounds)
+}
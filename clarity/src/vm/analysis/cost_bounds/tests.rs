@@ -0,0 +1,215 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use crate::vm::analysis::cost_bounds::{compute_cost_bounds, max_serialized_size, CostBound};
+use crate::vm::analysis::mem_type_check;
+use crate::vm::costs::cost_functions::ClarityCostFunction;
+use crate::vm::costs::{CostErrors, CostTracker, ExecutionCost};
+use crate::vm::types::{FunctionType, QualifiedContractIdentifier, TypeSignature, Value};
+use crate::vm::ClarityName;
+
+/// Costs `input + 1` runtime for every cost function, plus a read or a write of `input`
+/// bytes for the data functions.
+struct LinearCosts;
+
+impl CostTracker for LinearCosts {
+    fn compute_cost(
+        &mut self,
+        cost_function: ClarityCostFunction,
+        input: &[u64],
+    ) -> Result<ExecutionCost, CostErrors> {
+        let mut cost = ExecutionCost::runtime(input[0] + 1);
+        match cost_function {
+            ClarityCostFunction::FetchVar | ClarityCostFunction::FetchEntry => {
+                cost.read_count = 1;
+                cost.read_length = input[0];
+            }
+            ClarityCostFunction::SetVar | ClarityCostFunction::SetEntry => {
+                cost.write_count = 1;
+                cost.write_length = input[0];
+            }
+            _ => {}
+        }
+        Ok(cost)
+    }
+    fn add_cost(&mut self, _cost: ExecutionCost) -> Result<(), CostErrors> {
+        panic!("Cost bounds should not charge the tracker");
+    }
+    fn add_memory(&mut self, _memory: u64) -> Result<(), CostErrors> {
+        Ok(())
+    }
+    fn drop_memory(&mut self, _memory: u64) {}
+    fn reset_memory(&mut self) {}
+    fn short_circuit_contract_call(
+        &mut self,
+        _contract: &QualifiedContractIdentifier,
+        _function: &ClarityName,
+        _input: &[u64],
+    ) -> Result<bool, CostErrors> {
+        Ok(false)
+    }
+}
+
+fn bounds(contract: &str) -> BTreeMap<ClarityName, CostBound> {
+    let (_, analysis) = mem_type_check(contract).unwrap();
+    compute_cost_bounds(&analysis, &mut LinearCosts).unwrap()
+}
+
+fn bound(contract: &str, function: &str) -> CostBound {
+    bounds(contract).remove(function).unwrap()
+}
+
+#[test]
+fn test_simple_function() {
+    // function application: 1 argument + type check of an int
+    // (+ a 1): function lookup + add of 2 arguments + lookup of `a` at depth 0 + its size
+    let contract = "(define-read-only (f (a int)) (+ a 1))";
+    assert_eq!(
+        bound(contract, "f"),
+        CostBound {
+            cost: ExecutionCost::runtime(2 + 17 + 1 + 3 + 1 + 17),
+            complete: true,
+        }
+    );
+
+    // private functions are included in their callers, but not reported on their own
+    let contract = "(define-private (g (a int)) (+ a 1))
+        (define-public (f (a int)) (ok (g a)))";
+    let bounds = bounds(contract);
+    assert_eq!(bounds.len(), 1);
+    let f = &bounds["f"];
+    assert_eq!(f.cost.runtime, 2 + 17 + 1 + 2 + 1 + 1 + 17 + 41);
+}
+
+#[test]
+fn test_branches() {
+    // only the dearer branch is counted
+    let contract = "(define-read-only (f (a bool))
+        (if a (+ 1 2 3 4 5) (+ 1 2)))";
+    let cheap_branch = "(define-read-only (f (a bool))
+        (if a (+ 1) (+ 1 2)))";
+    let runtime = bound(contract, "f").cost.runtime;
+    assert_eq!(runtime - bound(cheap_branch, "f").cost.runtime, 5 - 2);
+
+    // the `some` branch of a `match` looks up its binding one level deeper
+    let contract = "(define-read-only (f (a (optional int)))
+        (match a x (+ x 1) 0))";
+    let optional_size =
+        u64::from(TypeSignature::OptionalType(Box::new(TypeSignature::IntType)).size());
+    let expected = 2 + (optional_size + 1) // function application
+        + 1 + 1 // match
+        + 1 + (optional_size + 1) // a
+        + 1 + 3 + 2 + 17; // (+ x 1)
+    assert_eq!(bound(contract, "f").cost.runtime, expected);
+}
+
+fn list_size(max_len: u32) -> u64 {
+    u64::from(
+        TypeSignature::list_of(TypeSignature::IntType, max_len)
+            .unwrap()
+            .size(),
+    )
+}
+
+#[test]
+fn test_iteration() {
+    let contract = "(define-private (add (a int) (b int)) (+ a b))
+        (define-read-only (sum-10 (l (list 10 int))) (fold add l 0))
+        (define-read-only (sum-20 (l (list 20 int))) (fold add l 0))";
+    let bounds = bounds(contract);
+    let add = 3 + 17 + 17 + 1 + 3 + 1 + 17 + 1 + 17;
+    // the type check and lookup of the longer list, and 10 more applications of `add`
+    assert_eq!(
+        bounds["sum-20"].cost.runtime - bounds["sum-10"].cost.runtime,
+        2 * (list_size(20) - list_size(10)) + 10 * add
+    );
+    assert!(bounds["sum-10"].complete);
+
+    // `map` runs over the shortest of its lists
+    let contract = "(define-read-only (f (a (list 5 int)) (b (list 50 int))) (map + a b))";
+    let runtime = bound(contract, "f").cost.runtime;
+    let contract = "(define-read-only (f (a (list 5 int)) (b (list 500 int))) (map + a b))";
+    assert_eq!(
+        bound(contract, "f").cost.runtime - runtime,
+        2 * (list_size(500) - list_size(50))
+    );
+}
+
+#[test]
+fn test_data_costs() {
+    let contract = "(define-map balances principal uint)
+        (define-data-var total uint u0)
+        (define-read-only (get-balance (who principal))
+          (default-to u0 (map-get? balances who)))
+        (define-public (set-total (amount uint))
+          (ok (var-set total amount)))";
+    let bounds = bounds(contract);
+
+    let entry_size = max_serialized_size(&TypeSignature::PrincipalType)
+        + 1
+        + max_serialized_size(&TypeSignature::UIntType);
+    assert_eq!(bounds["get-balance"].cost.read_count, 1);
+    assert_eq!(bounds["get-balance"].cost.read_length, entry_size);
+    assert_eq!(bounds["get-balance"].cost.write_count, 0);
+
+    assert_eq!(bounds["set-total"].cost.write_count, 1);
+    assert_eq!(
+        bounds["set-total"].cost.write_length,
+        max_serialized_size(&TypeSignature::UIntType)
+    );
+}
+
+#[test]
+fn test_contract_calls_are_incomplete() {
+    let contract = "(define-trait adder ((add (int) (response int int))))
+        (define-public (f (callee <adder>) (n int)) (contract-call? callee add n))
+        (define-public (g (n int)) (ok n))";
+    let bounds = bounds(contract);
+    assert!(!bounds["f"].complete);
+    assert!(bounds["g"].complete);
+}
+
+#[test]
+fn test_no_type_map() {
+    let (_, mut analysis) = mem_type_check("(define-public (g (n int)) (ok n))").unwrap();
+    analysis.type_map = None;
+    assert!(compute_cost_bounds(&analysis, &mut LinearCosts).is_err());
+}
+
+#[test]
+fn test_max_serialized_size() {
+    let sizes = [
+        ("(list 3 (buff 4))", 5 + 3 * 9),
+        ("(string-utf8 10)", 5 + 40),
+        ("(response int bool)", 18),
+        ("(tuple (a int) (bc bool))", 5 + (1 + 1 + 17) + (1 + 2 + 1)),
+    ];
+    for (type_repr, expected) in sizes.iter() {
+        let contract = format!("(define-read-only (f (a {})) true)", type_repr);
+        let (_, analysis) = mem_type_check(&contract).unwrap();
+        let arg_type = match analysis.get_read_only_function_type("f").unwrap() {
+            FunctionType::Fixed(function) => function.args[0].signature.clone(),
+            _ => panic!("Expected a fixed function type"),
+        };
+        assert_eq!(max_serialized_size(&arg_type), *expected);
+    }
+
+    // the largest value of a type serializes to its max size
+    let value = Value::string_utf8_from_bytes("\u{1F600}".repeat(10).into_bytes()).unwrap();
+    assert_eq!(value.serialized_size() as u64, 5 + 40);
+}
@@ -0,0 +1,500 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Opt-in lints over a contract which has already passed analysis.  Unlike the analysis
+//! passes, lints never reject a contract: they report `Level::Warning` diagnostics for
+//! code which is valid but is probably not what the author intended.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::vm::analysis::ContractAnalysis;
+use crate::vm::diagnostic::Diagnostic;
+use crate::vm::functions::define::DefineFunctionsParsed;
+use crate::vm::representations::{ClarityName, SymbolicExpression};
+
+#[cfg(test)]
+mod tests;
+
+/// A warning reported by one of the lints.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct LintWarning {
+    /// The name of the lint which reported the warning, e.g. `unused-let-binding`.
+    pub lint: String,
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+}
+
+pub trait LintPass {
+    const NAME: &'static str;
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic>;
+}
+
+fn collect<L: LintPass>(contract_analysis: &ContractAnalysis, warnings: &mut Vec<LintWarning>) {
+    for diagnostic in L::run_lint(contract_analysis) {
+        warnings.push(LintWarning {
+            lint: L::NAME.to_string(),
+            diagnostic,
+        });
+    }
+}
+
+/// Run every lint over an analyzed contract, returning the warnings in source order.
+pub fn run_lints(contract_analysis: &ContractAnalysis) -> Vec<LintWarning> {
+    let mut warnings = vec![];
+    collect::<UnusedLetBinding>(contract_analysis, &mut warnings);
+    collect::<UnusedPrivateFunction>(contract_analysis, &mut warnings);
+    collect::<UnwrapPanicOnInput>(contract_analysis, &mut warnings);
+    collect::<TxSenderInAsContract>(contract_analysis, &mut warnings);
+    collect::<UncheckedContractCall>(contract_analysis, &mut warnings);
+    collect::<DuplicateAssertError>(contract_analysis, &mut warnings);
+
+    warnings.sort_by_key(|warning| {
+        warning
+            .diagnostic
+            .spans
+            .first()
+            .map(|span| (span.start_line, span.start_column))
+    });
+    warnings
+}
+
+fn warning(expr: &SymbolicExpression, message: String, suggestion: &str) -> Diagnostic {
+    let mut diagnostic = Diagnostic::warning(message, Some(suggestion.to_string()));
+    push_span(&mut diagnostic, expr);
+    diagnostic
+}
+
+#[cfg(feature = "developer-mode")]
+fn push_span(diagnostic: &mut Diagnostic, expr: &SymbolicExpression) {
+    diagnostic.spans.push(expr.span.clone());
+}
+
+#[cfg(not(feature = "developer-mode"))]
+fn push_span(_diagnostic: &mut Diagnostic, _expr: &SymbolicExpression) {}
+
+/// The name of the function applied by `expr`, if it is a function application.
+fn function_name(expr: &SymbolicExpression) -> Option<&str> {
+    expr.match_list()
+        .and_then(|list| list.first())
+        .and_then(|first| first.match_atom())
+        .map(|name| name.as_str())
+}
+
+/// The arguments of the function applied by `expr`.
+fn arguments(expr: &SymbolicExpression) -> &[SymbolicExpression] {
+    match expr.match_list() {
+        Some(list) if !list.is_empty() => &list[1..],
+        _ => &[],
+    }
+}
+
+fn contains_atom(expr: &SymbolicExpression, name: &str) -> bool {
+    if let Some(atom) = expr.match_atom() {
+        return atom.as_str() == name;
+    }
+    match expr.match_list() {
+        Some(list) => list.iter().any(|expr| contains_atom(expr, name)),
+        None => false,
+    }
+}
+
+/// Visit `expr` and every expression nested in it.  `f` is given each expression along
+/// with the enclosing list expressions, innermost last.
+fn visit<'a, F>(expr: &'a SymbolicExpression, parents: &mut Vec<&'a SymbolicExpression>, f: &mut F)
+where
+    F: FnMut(&'a SymbolicExpression, &[&'a SymbolicExpression]),
+{
+    f(expr, parents);
+    if let Some(list) = expr.match_list() {
+        parents.push(expr);
+        for child in list.iter() {
+            visit(child, parents, f);
+        }
+        parents.pop();
+    }
+}
+
+fn visit_contract<'a, F>(contract_analysis: &'a ContractAnalysis, mut f: F)
+where
+    F: FnMut(&'a SymbolicExpression, &[&'a SymbolicExpression]),
+{
+    let mut parents = vec![];
+    for expr in contract_analysis.expressions.iter() {
+        visit(expr, &mut parents, &mut f);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Private,
+    ReadOnly,
+    Public,
+}
+
+struct FunctionDefinition<'a> {
+    kind: FunctionKind,
+    name: &'a ClarityName,
+    /// The signature's `(name type)` pairs.
+    parameters: &'a [SymbolicExpression],
+    body: &'a SymbolicExpression,
+    define: &'a SymbolicExpression,
+}
+
+fn function_definitions(contract_analysis: &ContractAnalysis) -> Vec<FunctionDefinition<'_>> {
+    let mut definitions = vec![];
+    for expr in contract_analysis.expressions.iter() {
+        let (kind, signature, body) = match DefineFunctionsParsed::try_parse(expr) {
+            Ok(Some(DefineFunctionsParsed::PrivateFunction { signature, body })) => {
+                (FunctionKind::Private, signature, body)
+            }
+            Ok(Some(DefineFunctionsParsed::ReadOnlyFunction { signature, body })) => {
+                (FunctionKind::ReadOnly, signature, body)
+            }
+            Ok(Some(DefineFunctionsParsed::PublicFunction { signature, body })) => {
+                (FunctionKind::Public, signature, body)
+            }
+            _ => continue,
+        };
+        if let Some(name) = signature.first().and_then(|name| name.match_atom()) {
+            definitions.push(FunctionDefinition {
+                kind,
+                name,
+                parameters: &signature[1..],
+                body,
+                define: expr,
+            });
+        }
+    }
+    definitions
+}
+
+fn parameter_name(parameter: &SymbolicExpression) -> Option<&ClarityName> {
+    parameter
+        .match_list()
+        .and_then(|pair| pair.first())
+        .and_then(|name| name.match_atom())
+}
+
+/// `let` bindings whose value is never referenced by a later binding or the body.
+pub struct UnusedLetBinding;
+
+impl LintPass for UnusedLetBinding {
+    const NAME: &'static str = "unused-let-binding";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        visit_contract(contract_analysis, |expr, _| {
+            if function_name(expr) != Some("let") {
+                return;
+            }
+            let args = arguments(expr);
+            let bindings = match args.first().and_then(|bindings| bindings.match_list()) {
+                Some(bindings) => bindings,
+                None => return,
+            };
+            // bindings are evaluated in order, and may not shadow other names, so any
+            //  occurrence of the name after the binding refers to it.
+            for (i, binding) in bindings.iter().enumerate() {
+                let name = match binding
+                    .match_list()
+                    .and_then(|pair| pair.first())
+                    .and_then(|name| name.match_atom())
+                {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let used = bindings[i + 1..]
+                    .iter()
+                    .filter_map(|binding| binding.match_list().and_then(|pair| pair.get(1)))
+                    .chain(args[1..].iter())
+                    .any(|expr| contains_atom(expr, name));
+                if !used {
+                    diagnostics.push(warning(
+                        binding,
+                        format!("let binding '{}' is never used", name),
+                        "Remove the binding, or evaluate its value in the body if it is only needed for its side effects",
+                    ));
+                }
+            }
+        });
+        diagnostics
+    }
+}
+
+/// Private functions which are never called from the contract, either directly or as the
+/// function argument of `map`, `filter` or `fold`.
+pub struct UnusedPrivateFunction;
+
+const HIGHER_ORDER_FUNCTIONS: &[&str] = &["map", "filter", "fold"];
+
+impl LintPass for UnusedPrivateFunction {
+    const NAME: &'static str = "unused-private-function";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let definitions = function_definitions(contract_analysis);
+
+        let mut called: HashSet<&str> = HashSet::new();
+        for expr in contract_analysis.expressions.iter() {
+            // only function bodies are searched for calls, so that signatures (and trait
+            //  method signatures) are not mistaken for them.
+            if function_name(expr) == Some("define-trait") {
+                continue;
+            }
+            let body = definitions
+                .iter()
+                .find(|definition| std::ptr::eq(definition.define, expr))
+                .map(|definition| definition.body)
+                .unwrap_or(expr);
+            visit(body, &mut vec![], &mut |expr, parents| {
+                let name = match function_name(expr) {
+                    Some(name) => name,
+                    None => return,
+                };
+                // a `(name value)` pair inside `(tuple ...)` is a field, not a call
+                if parents.last().and_then(|parent| function_name(parent)) != Some("tuple") {
+                    called.insert(name);
+                }
+                if HIGHER_ORDER_FUNCTIONS.contains(&name) {
+                    if let Some(function) = arguments(expr).first().and_then(|f| f.match_atom()) {
+                        called.insert(function.as_str());
+                    }
+                }
+            });
+        }
+
+        definitions
+            .iter()
+            .filter(|definition| definition.kind == FunctionKind::Private)
+            .filter(|definition| !called.contains(definition.name.as_str()))
+            .map(|definition| {
+                warning(
+                    definition.define,
+                    format!("private function '{}' is never called", definition.name),
+                    "Remove the function, or call it from a public or read-only function",
+                )
+            })
+            .collect()
+    }
+}
+
+/// `unwrap-panic` on values derived from the arguments of a public function.  A caller can
+/// make such a transaction abort without any indication of what went wrong.
+pub struct UnwrapPanicOnInput;
+
+impl LintPass for UnwrapPanicOnInput {
+    const NAME: &'static str = "unwrap-panic-on-input";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for definition in function_definitions(contract_analysis) {
+            if definition.kind != FunctionKind::Public {
+                continue;
+            }
+            let mut tainted: HashSet<&str> = definition
+                .parameters
+                .iter()
+                .filter_map(parameter_name)
+                .map(|name| name.as_str())
+                .collect();
+
+            // names bound by `let` to values derived from tainted names are tainted too
+            let mut lets = vec![];
+            visit(definition.body, &mut vec![], &mut |expr, _| {
+                if function_name(expr) == Some("let") {
+                    lets.push(expr);
+                }
+            });
+            for let_expr in lets {
+                let bindings = arguments(let_expr)
+                    .first()
+                    .and_then(|bindings| bindings.match_list())
+                    .unwrap_or(&[]);
+                for binding in bindings.iter().filter_map(|binding| binding.match_list()) {
+                    if let (Some(name), Some(value)) = (
+                        binding.first().and_then(|name| name.match_atom()),
+                        binding.get(1),
+                    ) {
+                        if tainted.iter().any(|input| contains_atom(value, input)) {
+                            tainted.insert(name.as_str());
+                        }
+                    }
+                }
+            }
+
+            visit(definition.body, &mut vec![], &mut |expr, _| {
+                let name = match function_name(expr) {
+                    Some(name @ "unwrap-panic") | Some(name @ "unwrap-err-panic") => name,
+                    _ => return,
+                };
+                let input = arguments(expr).first().and_then(|value| {
+                    tainted
+                        .iter()
+                        .find(|input| contains_atom(value, input))
+                        .cloned()
+                });
+                if let Some(input) = input {
+                    diagnostics.push(warning(
+                        expr,
+                        format!(
+                            "{} on a value derived from '{}', which is chosen by the caller of public function '{}'",
+                            name, input, definition.name
+                        ),
+                        "Use unwrap! or unwrap-err! with an error code, so that callers can tell why the transaction failed",
+                    ));
+                }
+            });
+        }
+        diagnostics
+    }
+}
+
+/// Authorization checks on `tx-sender` inside `as-contract`, where `tx-sender` is the
+/// contract itself rather than the caller.  A check is an `is-eq` involving `tx-sender`, or
+/// the condition of an `asserts!` or `if`, or the input of a `match`, which depends on it.
+pub struct TxSenderInAsContract;
+
+const CONDITIONAL_FUNCTIONS: &[&str] = &["asserts!", "if", "match"];
+
+impl LintPass for TxSenderInAsContract {
+    const NAME: &'static str = "tx-sender-in-as-contract";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut reported = HashSet::new();
+        visit_contract(contract_analysis, |expr, parents| {
+            if expr.match_atom().map(|atom| atom.as_str()) != Some("tx-sender") {
+                return;
+            }
+            let inside = match parents
+                .iter()
+                .rposition(|parent| function_name(parent) == Some("as-contract"))
+            {
+                Some(as_contract) => &parents[as_contract + 1..],
+                None => return,
+            };
+            // walk outwards from tx-sender to the innermost check which depends on it
+            let mut child = expr;
+            let mut check = None;
+            for parent in inside.iter().rev() {
+                match function_name(parent) {
+                    Some("is-eq") => {
+                        check = Some(*parent);
+                        break;
+                    }
+                    Some(name) if CONDITIONAL_FUNCTIONS.contains(&name) => {
+                        if arguments(parent)
+                            .first()
+                            .map_or(false, |condition| std::ptr::eq(condition, child))
+                        {
+                            check = Some(child);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                child = parent;
+            }
+            if let Some(check) = check {
+                if reported.insert(check.id) {
+                    diagnostics.push(warning(
+                        check,
+                        "tx-sender is checked inside as-contract, where it is the contract's own principal".to_string(),
+                        "Check the caller's authorization before entering as-contract, or bind tx-sender with let outside of it",
+                    ));
+                }
+            }
+        });
+        diagnostics
+    }
+}
+
+/// `contract-call?` responses which are wrapped in another value instead of being checked.
+/// An `err` response from the callee then does not abort the calling transaction.
+pub struct UncheckedContractCall;
+
+const WRAPPING_FUNCTIONS: &[&str] = &["ok", "err", "some", "print", "list"];
+
+impl LintPass for UncheckedContractCall {
+    const NAME: &'static str = "unchecked-contract-call";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        visit_contract(contract_analysis, |expr, parents| {
+            if function_name(expr) != Some("contract-call?") {
+                return;
+            }
+            let mut enclosing = parents.iter().rev().map(|parent| function_name(parent));
+            let wrapped = match (enclosing.next(), enclosing.next()) {
+                (Some(Some(parent)), _) if WRAPPING_FUNCTIONS.contains(&parent) => true,
+                // a tuple field is a `(name value)` pair inside `(tuple ...)`
+                (_, Some(Some("tuple"))) => true,
+                _ => false,
+            };
+            if wrapped {
+                diagnostics.push(warning(
+                    expr,
+                    "the response of this contract-call? is never checked, so an err from the callee will not abort the transaction".to_string(),
+                    "Use try!, unwrap! or match to handle the response",
+                ));
+            }
+        });
+        diagnostics
+    }
+}
+
+/// `asserts!` expressions in one function which throw identical values, so that callers
+/// cannot tell which check failed.
+pub struct DuplicateAssertError;
+
+impl LintPass for DuplicateAssertError {
+    const NAME: &'static str = "duplicate-assert-error";
+
+    fn run_lint(contract_analysis: &ContractAnalysis) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for definition in function_definitions(contract_analysis) {
+            let mut thrown: BTreeMap<String, Vec<&SymbolicExpression>> = BTreeMap::new();
+            visit(definition.body, &mut vec![], &mut |expr, _| {
+                if function_name(expr) != Some("asserts!") {
+                    return;
+                }
+                if let Some(value) = arguments(expr).get(1) {
+                    thrown.entry(value.to_string()).or_default().push(expr);
+                }
+            });
+
+            for (value, asserts) in thrown.iter() {
+                if asserts.len() < 2 {
+                    continue;
+                }
+                let mut diagnostic = Diagnostic::warning(
+                    format!(
+                        "{} asserts! in function '{}' throw the same value {}",
+                        asserts.len(),
+                        definition.name,
+                        value
+                    ),
+                    Some("Give each check its own error code, so that callers can tell which one failed".to_string()),
+                );
+                for expr in asserts.iter() {
+                    push_span(&mut diagnostic, expr);
+                }
+                diagnostics.push(diagnostic);
+            }
+        }
+        diagnostics
+    }
+}
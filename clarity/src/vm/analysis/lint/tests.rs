@@ -0,0 +1,179 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::vm::analysis::lint::{run_lints, LintWarning};
+use crate::vm::analysis::mem_type_check;
+use crate::vm::diagnostic::Level;
+
+fn lint(contract: &str) -> Vec<LintWarning> {
+    let (_, analysis) = mem_type_check(contract).unwrap();
+    run_lints(&analysis)
+}
+
+fn lint_names_and_lines(contract: &str) -> Vec<(String, u32)> {
+    lint(contract)
+        .into_iter()
+        .map(|warning| {
+            assert_eq!(warning.diagnostic.level, Level::Warning);
+            (warning.lint, warning.diagnostic.spans[0].start_line)
+        })
+        .collect()
+}
+
+#[test]
+fn test_clean_contract() {
+    let contract = "(define-data-var counter uint u0)
+        (define-private (bump (by uint))
+          (begin (var-set counter (+ (var-get counter) by)) (ok by)))
+        (define-public (increment (by uint))
+          (let ((current (var-get counter))
+                (next (+ current by)))
+            (asserts! (< next u100) (err u1))
+            (asserts! (> by u0) (err u2))
+            (bump by)))";
+    assert_eq!(lint(contract), vec![]);
+}
+
+#[test]
+fn test_unused_let_binding() {
+    let contract = "(define-read-only (foo (a int))
+          (let ((unused (+ a 1))
+                (used (* a 2))
+                (result (+ used 1)))
+            result))";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![("unused-let-binding".to_string(), 2)]
+    );
+}
+
+#[test]
+fn test_unused_private_function() {
+    let contract = "(define-private (helper (a int)) (+ a 1))
+        (define-private (unused (a int)) (- a 1))
+        (define-private (mapped (a int)) (* a 2))
+        (define-read-only (foo (a int)) (helper a))
+        (define-read-only (bar) (map mapped (list 1 2)))";
+    let warnings = lint(contract);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].lint, "unused-private-function");
+    assert_eq!(warnings[0].diagnostic.spans[0].start_line, 2);
+    assert!(warnings[0].diagnostic.message.contains("'unused'"));
+
+    // names which only appear as tuple fields or trait methods are not calls
+    let contract = "(define-trait named ((field (int) (response int int))))
+        (define-map fields { field: int } { value: int })
+        (define-private (field (a int)) a)
+        (define-read-only (foo) { field: 1 })";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![("unused-private-function".to_string(), 3)]
+    );
+}
+
+#[test]
+fn test_unwrap_panic_on_input() {
+    let contract = "(define-map balances principal uint)
+        (define-public (withdraw (amount uint) (who principal))
+          (let ((balance (unwrap-panic (map-get? balances who)))
+                (fixed (unwrap-panic (map-get? balances tx-sender))))
+            (ok (+ balance fixed amount))))
+        (define-read-only (peek (who principal))
+          (unwrap-panic (map-get? balances who)))";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![("unwrap-panic-on-input".to_string(), 3)]
+    );
+}
+
+#[test]
+fn test_tx_sender_in_as_contract() {
+    let contract = "(define-constant owner tx-sender)
+        (define-public (pay (amount uint) (to principal))
+          (begin
+            (asserts! (is-eq tx-sender owner) (err u1))
+            (as-contract (begin
+              (asserts! (is-eq tx-sender owner) (err u2))
+              (stx-transfer? amount tx-sender to)))))";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![("tx-sender-in-as-contract".to_string(), 6)]
+    );
+
+    // checks other than is-eq, in asserts!, if and match
+    let contract = "(define-map admins principal bool)
+        (define-public (guarded (amount uint) (to principal))
+          (as-contract (begin
+            (asserts! (default-to false (map-get? admins tx-sender)) (err u1))
+            (try! (if (is-some (map-get? admins tx-sender)) (ok true) (err u2)))
+            (match (map-get? admins tx-sender) admin (ok true) (err u3)))))
+        (define-public (unguarded (amount uint) (to principal))
+          (as-contract (if (> amount u10)
+            (stx-transfer? amount tx-sender to)
+            (ok false))))";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![
+            ("tx-sender-in-as-contract".to_string(), 4),
+            ("tx-sender-in-as-contract".to_string(), 5),
+            ("tx-sender-in-as-contract".to_string(), 6),
+        ]
+    );
+}
+
+#[test]
+fn test_unchecked_contract_call() {
+    let contract = "(define-trait callee ((foo () (response uint uint))))
+        (define-public (checked (c <callee>))
+          (begin (try! (contract-call? c foo)) (ok true)))
+        (define-public (returned (c <callee>))
+          (contract-call? c foo))
+        (define-public (unchecked (c <callee>))
+          (ok (contract-call? c foo)))
+        (define-public (in-tuple (c <callee>))
+          (ok { result: (contract-call? c foo) }))";
+    assert_eq!(
+        lint_names_and_lines(contract),
+        vec![
+            ("unchecked-contract-call".to_string(), 7),
+            ("unchecked-contract-call".to_string(), 9)
+        ]
+    );
+}
+
+#[test]
+fn test_duplicate_assert_error() {
+    let contract = "(define-public (foo (a uint) (b uint))
+          (begin
+            (asserts! (> a u0) (err u1))
+            (asserts! (> b u0) (err u1))
+            (asserts! (> a b) (err u2))
+            (ok (- a b))))
+        (define-public (bar (a uint))
+          (begin
+            (asserts! (> a u0) (err u1))
+            (ok a)))";
+    let warnings = lint(contract);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].lint, "duplicate-assert-error");
+    let lines: Vec<u32> = warnings[0]
+        .diagnostic
+        .spans
+        .iter()
+        .map(|span| span.start_line)
+        .collect();
+    assert_eq!(lines, vec![3, 4]);
+}
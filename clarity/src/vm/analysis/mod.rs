@@ -17,7 +17,9 @@
 pub mod analysis_db;
 pub mod arithmetic_checker;
 pub mod contract_interface_builder;
+pub mod cost_bounds;
 pub mod errors;
+pub mod lint;
 pub mod read_only_checker;
 pub mod trait_checker;
 pub mod type_checker;
@@ -31,6 +33,7 @@ use crate::vm::costs::LimitedCostTracker;
 use crate::vm::database::STORE_CONTRACT_SRC_INTERFACE;
 use crate::vm::representations::SymbolicExpression;
 use crate::vm::types::{QualifiedContractIdentifier, TypeSignature};
+use crate::vm::ClarityVersion;
 
 pub use self::analysis_db::AnalysisDatabase;
 pub use self::errors::{CheckError, CheckErrors, CheckResult};
@@ -45,6 +48,14 @@ use crate::vm::ast::ASTRules;
 
 /// Used by CLI tools like the docs generator. Not used in production
 pub fn mem_type_check(snippet: &str) -> CheckResult<(Option<TypeSignature>, ContractAnalysis)> {
+    mem_type_check_version(snippet, ClarityVersion::latest())
+}
+
+/// Type check `snippet` as a contract of the given Clarity version. Not used in production
+pub fn mem_type_check_version(
+    snippet: &str,
+    clarity_version: ClarityVersion,
+) -> CheckResult<(Option<TypeSignature>, ContractAnalysis)> {
     let contract_identifier = QualifiedContractIdentifier::transient();
     let mut contract = build_ast_with_rules(
         &contract_identifier,
@@ -63,6 +74,7 @@ pub fn mem_type_check(snippet: &str) -> CheckResult<(Option<TypeSignature>, Cont
         &mut analysis_db,
         false,
         cost_tracker,
+        clarity_version,
     ) {
         Ok(x) => {
             // return the first type result of the type checker
@@ -95,6 +107,7 @@ pub fn type_check(
         // for the type check tests, the cost tracker's epoch doesn't
         //  matter: the costs in those tests are all free anyways.
         LimitedCostTracker::new_free(),
+        ClarityVersion::latest(),
     )
     .map_err(|(e, _cost_tracker)| e)
 }
@@ -105,11 +118,13 @@ pub fn run_analysis(
     analysis_db: &mut AnalysisDatabase,
     save_contract: bool,
     cost_tracker: LimitedCostTracker,
+    clarity_version: ClarityVersion,
 ) -> Result<ContractAnalysis, (CheckError, LimitedCostTracker)> {
     let mut contract_analysis = ContractAnalysis::new(
         contract_identifier.clone(),
         expressions.to_vec(),
         cost_tracker,
+        clarity_version,
     );
     let result = analysis_db.execute(|db| {
         ReadOnlyChecker::run_pass(&mut contract_analysis, db)?;
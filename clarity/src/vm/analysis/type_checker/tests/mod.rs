@@ -15,23 +15,24 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::vm::analysis::errors::CheckErrors;
-use crate::vm::analysis::mem_type_check;
 use crate::vm::analysis::type_check;
 use crate::vm::analysis::type_checker::{TypeChecker, TypeResult, TypingContext};
 use crate::vm::analysis::types::ContractAnalysis;
 use crate::vm::analysis::AnalysisDatabase;
+use crate::vm::analysis::{mem_type_check, mem_type_check_version};
 use crate::vm::ast::errors::ParseErrors;
 use crate::vm::ast::{build_ast, parse};
 use crate::vm::contexts::OwnedEnvironment;
 use crate::vm::representations::SymbolicExpression;
 use crate::vm::types::{
     FixedFunction, FunctionType, PrincipalData, QualifiedContractIdentifier, TypeSignature, Value,
-    BUFF_32, BUFF_64,
+    BUFF_32, BUFF_34, BUFF_64,
 };
 
 use crate::vm::database::MemoryBackingStore;
 use crate::vm::types::TypeSignature::{BoolType, IntType, PrincipalType, SequenceType, UIntType};
 use crate::vm::types::{SequenceSubtype::*, StringSubtype::*};
+use crate::vm::ClarityVersion;
 
 use std::convert::TryInto;
 
@@ -181,8 +182,16 @@ fn test_stx_ops() {
         "(stx-burn? u10 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)",
         "(stx-transfer? u10 tx-sender 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)",
         "(stx-get-balance 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)",
+        "(stx-account 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)",
+        "(stx-transfer-memo? u10 tx-sender 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G 0x0102)",
+    ];
+    let expected = [
+        "(response bool uint)",
+        "(response bool uint)",
+        "uint",
+        "(tuple (locked uint) (unlock-height uint) (unlocked uint))",
+        "(response bool uint)",
     ];
-    let expected = ["(response bool uint)", "(response bool uint)", "uint"];
 
     let bad = [
         "(stx-transfer? u4 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
@@ -194,7 +203,12 @@ fn test_stx_ops() {
         "(stx-burn? u4 true)",
         "(stx-burn? u4 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
         "(stx-get-balance true)",
-        "(stx-get-balance 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)"
+        "(stx-get-balance 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
+        "(stx-account true)",
+        "(stx-account 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
+        "(stx-transfer-memo? u4 tx-sender 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
+        "(stx-transfer-memo? u4 tx-sender 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR u1)",
+        "(stx-transfer-memo? u4 tx-sender 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 0x0000000000000000000000000000000000000000000000000000000000000000000000)",
     ];
     let bad_expected = [
         CheckErrors::IncorrectArgumentCount(3, 2),
@@ -207,6 +221,14 @@ fn test_stx_ops() {
         CheckErrors::IncorrectArgumentCount(2, 3),
         CheckErrors::TypeError(PrincipalType, BoolType),
         CheckErrors::IncorrectArgumentCount(1, 2),
+        CheckErrors::TypeError(PrincipalType, BoolType),
+        CheckErrors::IncorrectArgumentCount(1, 2),
+        CheckErrors::IncorrectArgumentCount(4, 3),
+        CheckErrors::TypeError(BUFF_34.clone(), UIntType),
+        CheckErrors::TypeError(
+            BUFF_34.clone(),
+            SequenceType(BufferType(35u32.try_into().unwrap())),
+        ),
     ];
 
     for (good_test, expected) in good.iter().zip(expected.iter()) {
@@ -221,6 +243,40 @@ fn test_stx_ops() {
     }
 }
 
+#[test]
+fn test_clarity2_natives_unavailable_in_clarity1() {
+    let uses_natives = [
+        (
+            "stx-account",
+            "(stx-account 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)",
+        ),
+        (
+            "stx-transfer-memo?",
+            "(stx-transfer-memo? u10 tx-sender 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G 0x0102)",
+        ),
+    ];
+    for (name, uses_native) in uses_natives.iter() {
+        assert_eq!(
+            mem_type_check_version(uses_native, ClarityVersion::Clarity1)
+                .unwrap_err()
+                .err,
+            CheckErrors::UnknownFunction(name.to_string())
+        );
+        mem_type_check_version(uses_native, ClarityVersion::Clarity2).unwrap();
+    }
+
+    // a Clarity1 contract may still use the name for its own definitions
+    let defines_name = "(define-read-only (stx-account (p principal)) u7)
+                        (stx-account 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)";
+    assert_eq!(
+        mem_type_check_version(defines_name, ClarityVersion::Clarity1)
+            .unwrap()
+            .0
+            .unwrap(),
+        UIntType
+    );
+}
+
 #[test]
 fn test_destructuring_opts() {
     let good = [
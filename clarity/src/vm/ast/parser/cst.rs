@@ -0,0 +1,299 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A concrete syntax tree for tooling such as the formatter.  Unlike the expressions
+//! produced by `parse`, the tree keeps comments, tuple separators and the source text of
+//! every token, so that a contract can be printed back without losing anything but its
+//! whitespace.  The layout of that whitespace can be recovered from the nodes' spans.
+
+use super::{inner_lex, parse, LexItem};
+use crate::vm::ast::errors::{ParseError, ParseErrors, ParseResult};
+use crate::vm::ast::stack_depth_checker::AST_CALL_STACK_DEPTH_BUFFER;
+use crate::vm::representations::Span;
+use crate::vm::MAX_CALL_STACK_DEPTH;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstNodeType {
+    List(Vec<CstNode>),
+    /// The items of a `{ ... }` tuple literal, including its `Colon`s and `Comma`s.
+    Tuple(Vec<CstNode>),
+    /// An atom or literal value, exactly as written in the source.
+    Token(String),
+    /// A comment, including its leading `;;`.
+    Comment(String),
+    Colon,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+    pub node: CstNodeType,
+    pub span: Span,
+}
+
+impl CstNode {
+    pub fn match_list(&self) -> Option<&[CstNode]> {
+        match self.node {
+            CstNodeType::List(ref list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn match_token(&self) -> Option<&str> {
+        match self.node {
+            CstNodeType::Token(ref text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn is_comment(&self) -> bool {
+        matches!(self.node, CstNodeType::Comment(_))
+    }
+}
+
+/// The line and column of a byte offset into the source.  These are computed here rather
+/// than taken from the lexer, whose line numbers lag behind after consecutive blank lines.
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(input: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        line_starts.extend(input.match_indices('\n').map(|(ix, _)| ix + 1));
+        LineIndex { line_starts }
+    }
+
+    fn position(&self, offset: usize) -> (u32, u32) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        (
+            (line + 1) as u32,
+            (offset - self.line_starts[line] + 1) as u32,
+        )
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        let (start_line, start_column) = self.position(start);
+        let (end_line, end_column) = self.position(end.saturating_sub(1).max(start));
+        Span {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// Converts the positions reported by the lexer, which are the ones used in the spans of
+/// parsed expressions and of parse and analysis errors, into true source positions.  The
+/// lexer only advances one line per token, so its positions fall behind after consecutive
+/// newlines.
+#[derive(Debug, Clone)]
+pub struct PositionMap {
+    /// The lexer's and the true position of the start of each token, in source order.
+    tokens: Vec<((u32, u32), (u32, u32))>,
+}
+
+impl PositionMap {
+    pub fn new(input: &str) -> ParseResult<PositionMap> {
+        let mut token_ranges = vec![];
+        let lexed = inner_lex(input, u64::MAX, Some(&mut token_ranges))?;
+        let lines = LineIndex::new(input);
+        let tokens = lexed
+            .iter()
+            .zip(token_ranges)
+            .map(|((_, line, column), (start, _))| ((*line, *column), lines.position(start)))
+            .collect();
+        Ok(PositionMap { tokens })
+    }
+
+    /// The true position of a `(line, column)` reported by the lexer.
+    pub fn position(&self, line: u32, column: u32) -> (u32, u32) {
+        // the lexer's positions increase from token to token, so find the last token which
+        //  starts at or before the position.
+        let ix = match self
+            .tokens
+            .binary_search_by(|(lexed, _)| lexed.cmp(&(line, column)))
+        {
+            Ok(ix) => ix,
+            Err(0) => return (line, column),
+            Err(ix) => ix - 1,
+        };
+        let ((lexed_line, lexed_column), (true_line, true_column)) = self.tokens[ix];
+        if lexed_line != line {
+            return (line, column);
+        }
+        (true_line, true_column + (column - lexed_column))
+    }
+
+    pub fn span(&self, span: &Span) -> Span {
+        if span.start_line == 0 {
+            // no known location
+            return span.clone();
+        }
+        let (start_line, start_column) = self.position(span.start_line, span.start_column);
+        let (end_line, end_column) = self.position(span.end_line, span.end_column);
+        Span {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// Parse a contract into its concrete syntax tree.  A contract is accepted exactly when
+/// `parse` accepts it, and fails with the same error otherwise.
+pub fn parse_lossless(input: &str) -> ParseResult<Vec<CstNode>> {
+    parse(input)?;
+
+    let mut token_ranges = vec![];
+    let lexed = inner_lex(
+        input,
+        AST_CALL_STACK_DEPTH_BUFFER + (MAX_CALL_STACK_DEPTH as u64) + 1,
+        Some(&mut token_ranges),
+    )?;
+
+    let lines = LineIndex::new(input);
+
+    // each open list or tuple, with its items and where it starts
+    let mut stack: Vec<(Vec<CstNode>, usize)> = vec![];
+    let mut outputs = vec![];
+
+    for ((item, ..), (start, end)) in lexed.into_iter().zip(token_ranges) {
+        let text = &input[start..end];
+        let node = match item {
+            LexItem::Whitespace => continue,
+            LexItem::LeftParen | LexItem::LeftCurly => {
+                stack.push((vec![], start));
+                continue;
+            }
+            LexItem::RightParen | LexItem::RightCurly => {
+                let (items, list_start) = stack
+                    .pop()
+                    .ok_or_else(|| ParseError::new(ParseErrors::ClosingParenthesisUnexpected))?;
+                let node = if let LexItem::RightParen = item {
+                    CstNodeType::List(items)
+                } else {
+                    CstNodeType::Tuple(items)
+                };
+                CstNode {
+                    node,
+                    span: lines.span(list_start, end),
+                }
+            }
+            LexItem::ColonSeparator => CstNode {
+                node: CstNodeType::Colon,
+                span: lines.span(start, end),
+            },
+            LexItem::CommaSeparator => CstNode {
+                node: CstNodeType::Comma,
+                span: lines.span(start, end),
+            },
+            LexItem::Comment(comment) => CstNode {
+                node: CstNodeType::Comment(comment),
+                span: lines.span(start, end),
+            },
+            LexItem::LiteralValue(..)
+            | LexItem::SugaredContractIdentifier(..)
+            | LexItem::SugaredFieldIdentifier(..)
+            | LexItem::FieldIdentifier(..)
+            | LexItem::TraitReference(..)
+            | LexItem::Variable(_) => CstNode {
+                node: CstNodeType::Token(text.to_string()),
+                span: lines.span(start, end),
+            },
+        };
+        match stack.last_mut() {
+            Some((items, ..)) => items.push(node),
+            None => outputs.push(node),
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(ParseError::new(ParseErrors::ClosingParenthesisExpected));
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_lossless() {
+        let contract = ";; header\n(define-constant a { x: u1, y: \"s\" }) ;; trailing\n\n(foo 'SP000000000000000000002Q6VF78.bar)";
+        let nodes = parse_lossless(contract).unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes[0].node, CstNodeType::Comment(";; header".into()));
+        assert_eq!(nodes[2].node, CstNodeType::Comment(";; trailing".into()));
+        assert_eq!(nodes[2].span.start_line, 2);
+
+        let define = nodes[1].match_list().unwrap();
+        assert_eq!(define[0].match_token(), Some("define-constant"));
+        match define[2].node {
+            CstNodeType::Tuple(ref items) => {
+                let texts: Vec<_> = items
+                    .iter()
+                    .map(|item| match item.node {
+                        CstNodeType::Token(ref text) => text.as_str(),
+                        CstNodeType::Colon => ":",
+                        CstNodeType::Comma => ",",
+                        _ => panic!("Unexpected tuple item"),
+                    })
+                    .collect();
+                assert_eq!(texts, vec!["x", ":", "u1", ",", "y", ":", "\"s\""]);
+            }
+            _ => panic!("Expected a tuple"),
+        }
+
+        let call = nodes[3].match_list().unwrap();
+        assert_eq!(
+            call[1].match_token(),
+            Some("'SP000000000000000000002Q6VF78.bar")
+        );
+        assert_eq!(nodes[3].span.start_line, 4);
+
+        assert!(parse_lossless("(foo (bar)").is_err());
+    }
+
+    #[test]
+    fn test_position_map() {
+        let contract = "(a)\n\n\n(b c)\n\n  (d)";
+        let expressions = parse(contract).unwrap();
+        let positions = PositionMap::new(contract).unwrap();
+        let spans: Vec<_> = expressions
+            .iter()
+            .map(|e| {
+                let span = positions.span(&e.span);
+                (
+                    span.start_line,
+                    span.start_column,
+                    span.end_line,
+                    span.end_column,
+                )
+            })
+            .collect();
+        assert_eq!(spans, vec![(1, 1, 1, 3), (4, 1, 4, 5), (6, 3, 6, 5)]);
+
+        let atom = &expressions[1].match_list().unwrap()[1];
+        let span = positions.span(&atom.span);
+        assert_eq!((span.start_line, span.start_column), (4, 4));
+    }
+}
@@ -29,6 +29,8 @@ use std::convert::TryInto;
 use crate::vm::ast::stack_depth_checker::AST_CALL_STACK_DEPTH_BUFFER;
 use crate::vm::MAX_CALL_STACK_DEPTH;
 
+pub mod cst;
+
 pub const CONTRACT_MIN_NAME_LENGTH: usize = 1;
 pub const CONTRACT_MAX_NAME_LENGTH: usize = 40;
 
@@ -46,11 +48,14 @@ pub enum LexItem {
     CommaSeparator,
     ColonSeparator,
     Whitespace,
+    /// A `;;` comment, only produced when lexing for the concrete syntax tree.
+    Comment(String),
 }
 
 #[derive(Debug)]
 enum TokenType {
     Whitespace,
+    Comment,
     Comma,
     Colon,
     LParens,
@@ -127,7 +132,6 @@ lazy_static! {
     );
     pub static ref CLARITY_NAME_REGEX: String =
         format!(r#"([[:word:]]|[-!?+<>=/*]){{1,{}}}"#, MAX_STRING_LEN);
-
     static ref lex_matchers: Vec<LexMatcher> = vec![
         LexMatcher::new(
             r##"u"(?P<value>((\\")|([[ -~]&&[^"]]))*)""##,
@@ -137,7 +141,7 @@ lazy_static! {
             r##""(?P<value>((\\")|([[ -~]&&[^"]]))*)""##,
             TokenType::StringASCIILiteral,
         ),
-        LexMatcher::new(";;[ -~]*", TokenType::Whitespace), // ;; comments.
+        LexMatcher::new(";;[ -~]*", TokenType::Comment),
         LexMatcher::new("[\n]+", TokenType::Whitespace),
         LexMatcher::new("[ \t]+", TokenType::Whitespace),
         LexMatcher::new("[,]", TokenType::Comma),
@@ -187,7 +191,13 @@ lazy_static! {
 }
 
 /// Lex the contract, permitting nesting of lists and tuples up to `max_nesting`.
-fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u32)>> {
+/// If `token_ranges` is given, comments are kept as `LexItem::Comment`s, and the byte range
+/// of each lexed item in `input` is pushed onto it.
+fn inner_lex(
+    input: &str,
+    max_nesting: u64,
+    mut token_ranges: Option<&mut Vec<(usize, usize)>>,
+) -> ParseResult<Vec<(LexItem, u32, u32)>> {
     let mut context = LexContext::ExpectNothing;
 
     let mut line_indices = get_lines_at(input);
@@ -218,6 +228,7 @@ fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u3
             if let Some(captures) = matcher.matcher.captures(current_slice) {
                 let whole_match = captures.get(0).unwrap();
                 assert_eq!(whole_match.start(), 0);
+                let token_start = munch_index;
                 munch_index += whole_match.end();
 
                 match context {
@@ -230,6 +241,7 @@ fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u3
                             TokenType::RParens => Ok(()),
                             TokenType::RCurly => Ok(()),
                             TokenType::Whitespace => Ok(()),
+                            TokenType::Comment => Ok(()),
                             TokenType::Comma => Ok(()),
                             TokenType::Colon => Ok(()),
                             _ => Err(ParseError::new(ParseErrors::SeparatorExpected(
@@ -243,6 +255,7 @@ fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u3
                             TokenType::RParens => Ok(()),
                             TokenType::RCurly => Ok(()),
                             TokenType::Whitespace => Ok(()),
+                            TokenType::Comment => Ok(()),
                             TokenType::Comma => Ok(()),
                             TokenType::Colon => Ok(()),
                             _ => Err(ParseError::new(ParseErrors::SeparatorExpectedAfterColon(
@@ -275,6 +288,16 @@ fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u3
                         context = LexContext::ExpectNothing;
                         Ok(LexItem::Whitespace)
                     }
+                    TokenType::Comment => {
+                        context = LexContext::ExpectNothing;
+                        if token_ranges.is_some() {
+                            Ok(LexItem::Comment(
+                                current_slice[..whole_match.end()].to_string(),
+                            ))
+                        } else {
+                            Ok(LexItem::Whitespace)
+                        }
+                    }
                     TokenType::Comma => {
                         context = LexContext::ExpectNothing;
                         Ok(LexItem::CommaSeparator)
@@ -433,6 +456,9 @@ fn inner_lex(input: &str, max_nesting: u64) -> ParseResult<Vec<(LexItem, u32, u3
                     }
                 }?;
 
+                if let Some(ref mut token_ranges) = token_ranges {
+                    token_ranges.push((token_start, munch_index));
+                }
                 result.push((token, current_line, column_pos));
                 column_pos += whole_match.end() as u32;
                 did_match = true;
@@ -454,6 +480,7 @@ pub fn lex(input: &str) -> ParseResult<Vec<(LexItem, u32, u32)>> {
     inner_lex(
         input,
         AST_CALL_STACK_DEPTH_BUFFER + (MAX_CALL_STACK_DEPTH as u64) + 1,
+        None,
     )
 }
 
@@ -696,7 +723,7 @@ pub fn parse_lexed(mut input: Vec<(LexItem, u32, u32)>) -> ParseResult<Vec<PreSy
                     }
                 };
             }
-            LexItem::Whitespace => (),
+            LexItem::Whitespace | LexItem::Comment(_) => (),
         };
     }
 
@@ -722,12 +749,13 @@ pub fn parse(input: &str) -> ParseResult<Vec<PreSymbolicExpression>> {
     let lexed = inner_lex(
         input,
         AST_CALL_STACK_DEPTH_BUFFER + (MAX_CALL_STACK_DEPTH as u64) + 1,
+        None,
     )?;
     parse_lexed(lexed)
 }
 
 pub fn parse_no_stack_limit(input: &str) -> ParseResult<Vec<PreSymbolicExpression>> {
-    let lexed = inner_lex(input, u64::MAX)?;
+    let lexed = inner_lex(input, u64::MAX, None)?;
     parse_lexed(lexed)
 }
 
@@ -12,7 +12,7 @@ use crate::vm::database::ClarityDatabase;
 use crate::vm::errors::Error as InterpreterError;
 use crate::vm::events::StacksTransactionEvent;
 use crate::vm::types::{PrincipalData, QualifiedContractIdentifier};
-use crate::vm::{ast, SymbolicExpression, Value};
+use crate::vm::{ast, ClarityVersion, SymbolicExpression, Value};
 use stacks_common::types::StacksEpochId;
 use std::fmt;
 
@@ -167,6 +167,7 @@ pub trait TransactionConnection: ClarityConnection {
         contract_content: &str,
         ast_rules: ASTRules,
     ) -> Result<(ContractAST, ContractAnalysis), Error> {
+        let clarity_version = ClarityVersion::default_for_epoch(self.get_epoch());
         self.with_analysis_db(|db, mut cost_track| {
             let ast_result =
                 ast::build_ast_with_rules(identifier, contract_content, &mut cost_track, ast_rules);
@@ -182,6 +183,7 @@ pub trait TransactionConnection: ClarityConnection {
                 db,
                 false,
                 cost_track,
+                clarity_version,
             );
 
             match result {
@@ -40,32 +40,30 @@ use crate::vm::representations::{ClarityName, ContractName, SymbolicExpression};
 use crate::vm::stx_transfer_consolidated;
 use crate::vm::types::signatures::FunctionSignature;
 use crate::vm::types::{
-    AssetIdentifier, PrincipalData, QualifiedContractIdentifier, TraitIdentifier, TypeSignature,
-    Value,
+    AssetIdentifier, BuffData, PrincipalData, QualifiedContractIdentifier, TraitIdentifier,
+    TypeSignature, Value,
 };
-use crate::vm::{eval, is_reserved};
+use crate::vm::{eval, is_reserved, ClarityVersion, EvalHook};
 use crate::{types::chainstate::StacksBlockId, types::StacksEpochId};
 
 use crate::vm::costs::cost_functions::ClarityCostFunction;
 use serde::Serialize;
 
-use crate::vm::coverage::CoverageReporter;
-
 pub const MAX_CONTEXT_DEPTH: u16 = 256;
 
 // TODO:
 //    hide the environment's instance variables.
 //     we don't want many of these changing after instantiation.
-pub struct Environment<'a, 'b> {
-    pub global_context: &'a mut GlobalContext<'b>,
+pub struct Environment<'a, 'b, 'hooks> {
+    pub global_context: &'a mut GlobalContext<'b, 'hooks>,
     pub contract_context: &'a ContractContext,
     pub call_stack: &'a mut CallStack,
     pub sender: Option<PrincipalData>,
     pub caller: Option<PrincipalData>,
 }
 
-pub struct OwnedEnvironment<'a> {
-    context: GlobalContext<'a>,
+pub struct OwnedEnvironment<'a, 'hooks> {
+    context: GlobalContext<'a, 'hooks>,
     default_contract: ContractContext,
     call_stack: CallStack,
 }
@@ -183,14 +181,15 @@ pub struct EventBatch {
      and is responsible for committing/rolling-back transactions as they error or
      abort.
 */
-pub struct GlobalContext<'a> {
+pub struct GlobalContext<'a, 'hooks> {
     asset_maps: Vec<AssetMap>,
     pub event_batches: Vec<EventBatch>,
     pub database: ClarityDatabase<'a>,
     read_only: Vec<bool>,
     pub cost_track: LimitedCostTracker,
     pub mainnet: bool,
-    pub coverage_reporting: Option<CoverageReporter>,
+    /// Hooks invoked around the evaluation of each expression (see `EvalHook`).
+    pub eval_hooks: Vec<&'hooks mut dyn EvalHook>,
     /// This is the epoch of the the block that this transaction is executing within.
     epoch_id: StacksEpochId,
 }
@@ -211,6 +210,9 @@ pub struct ContractContext {
     pub meta_nft: HashMap<ClarityName, NonFungibleTokenMetadata>,
     pub meta_ft: HashMap<ClarityName, FungibleTokenMetadata>,
     pub data_size: u64,
+    /// the Clarity version this contract was deployed with
+    #[serde(default)]
+    pub clarity_version: ClarityVersion,
 }
 
 pub struct LocalContext<'a> {
@@ -525,13 +527,16 @@ impl EventBatch {
     }
 }
 
-impl<'a> OwnedEnvironment<'a> {
+impl<'a, 'hooks> OwnedEnvironment<'a, 'hooks> {
     #[cfg(any(test, feature = "testing"))]
-    pub fn new(database: ClarityDatabase<'a>) -> OwnedEnvironment<'a> {
+    pub fn new(database: ClarityDatabase<'a>) -> OwnedEnvironment<'a, 'hooks> {
         let epoch = StacksEpochId::Epoch2_05;
         OwnedEnvironment {
             context: GlobalContext::new(false, database, LimitedCostTracker::new_free(), epoch),
-            default_contract: ContractContext::new(QualifiedContractIdentifier::transient()),
+            default_contract: ContractContext::new(
+                QualifiedContractIdentifier::transient(),
+                ClarityVersion::default_for_epoch(epoch),
+            ),
             call_stack: CallStack::new(),
         }
     }
@@ -541,29 +546,29 @@ impl<'a> OwnedEnvironment<'a> {
         mut database: ClarityDatabase<'a>,
         epoch: StacksEpochId,
         use_mainnet: bool,
-    ) -> OwnedEnvironment<'a> {
+    ) -> OwnedEnvironment<'a, 'hooks> {
         let cost_track = LimitedCostTracker::new_max_limit(&mut database, epoch, use_mainnet)
             .expect("FAIL: problem instantiating cost tracking");
         OwnedEnvironment {
             context: GlobalContext::new(use_mainnet, database, cost_track, epoch),
-            default_contract: ContractContext::new(QualifiedContractIdentifier::transient()),
+            default_contract: ContractContext::new(
+                QualifiedContractIdentifier::transient(),
+                ClarityVersion::default_for_epoch(epoch),
+            ),
             call_stack: CallStack::new(),
         }
     }
 
-    pub fn set_coverage_reporter(&mut self, reporter: CoverageReporter) {
-        self.context.coverage_reporting = Some(reporter)
-    }
-
-    pub fn take_coverage_reporter(&mut self) -> Option<CoverageReporter> {
-        self.context.coverage_reporting.take()
+    /// Install a hook to be invoked around every expression evaluated in this environment.
+    pub fn add_eval_hook(&mut self, hook: &'hooks mut dyn EvalHook) {
+        self.context.eval_hooks.push(hook);
     }
 
     pub fn new_free(
         mainnet: bool,
         database: ClarityDatabase<'a>,
         epoch_id: StacksEpochId,
-    ) -> OwnedEnvironment<'a> {
+    ) -> OwnedEnvironment<'a, 'hooks> {
         OwnedEnvironment {
             context: GlobalContext::new(
                 mainnet,
@@ -571,7 +576,10 @@ impl<'a> OwnedEnvironment<'a> {
                 LimitedCostTracker::new_free(),
                 epoch_id,
             ),
-            default_contract: ContractContext::new(QualifiedContractIdentifier::transient()),
+            default_contract: ContractContext::new(
+                QualifiedContractIdentifier::transient(),
+                ClarityVersion::default_for_epoch(epoch_id),
+            ),
             call_stack: CallStack::new(),
         }
     }
@@ -581,10 +589,13 @@ impl<'a> OwnedEnvironment<'a> {
         database: ClarityDatabase<'a>,
         cost_tracker: LimitedCostTracker,
         epoch_id: StacksEpochId,
-    ) -> OwnedEnvironment<'a> {
+    ) -> OwnedEnvironment<'a, 'hooks> {
         OwnedEnvironment {
             context: GlobalContext::new(mainnet, database, cost_tracker, epoch_id),
-            default_contract: ContractContext::new(QualifiedContractIdentifier::transient()),
+            default_contract: ContractContext::new(
+                QualifiedContractIdentifier::transient(),
+                ClarityVersion::default_for_epoch(epoch_id),
+            ),
             call_stack: CallStack::new(),
         }
     }
@@ -592,7 +603,7 @@ impl<'a> OwnedEnvironment<'a> {
     pub fn get_exec_environment<'b>(
         &'b mut self,
         sender: Option<PrincipalData>,
-    ) -> Environment<'b, 'a> {
+    ) -> Environment<'b, 'a, 'hooks> {
         Environment::new(
             &mut self.context,
             &self.default_contract,
@@ -608,7 +619,7 @@ impl<'a> OwnedEnvironment<'a> {
         f: F,
     ) -> std::result::Result<(A, AssetMap, Vec<StacksTransactionEvent>), E>
     where
-        E: From<crate::vm::errors::Error>,
+        E: From<crate::vm::errors::Error> + fmt::Debug,
         F: FnOnce(&mut Environment) -> std::result::Result<A, E>,
     {
         assert!(self.context.is_top_level());
@@ -619,6 +630,16 @@ impl<'a> OwnedEnvironment<'a> {
             f(&mut exec_env)
         };
 
+        if !self.context.eval_hooks.is_empty() {
+            let completion = match result {
+                Ok(_) => Ok(()),
+                Err(ref e) => Err(format!("{:?}", e)),
+            };
+            for hook in self.context.eval_hooks.iter_mut() {
+                hook.did_complete(completion.clone());
+            }
+        }
+
         match result {
             Ok(return_value) => {
                 let (asset_map, event_batch) = self.commit()?;
@@ -758,7 +779,7 @@ impl<'a> OwnedEnvironment<'a> {
     }
 }
 
-impl CostTracker for Environment<'_, '_> {
+impl CostTracker for Environment<'_, '_, '_> {
     fn compute_cost(
         &mut self,
         cost_function: ClarityCostFunction,
@@ -792,7 +813,7 @@ impl CostTracker for Environment<'_, '_> {
     }
 }
 
-impl CostTracker for GlobalContext<'_> {
+impl CostTracker for GlobalContext<'_, '_> {
     fn compute_cost(
         &mut self,
         cost_function: ClarityCostFunction,
@@ -824,7 +845,7 @@ impl CostTracker for GlobalContext<'_> {
     }
 }
 
-impl<'a, 'b> Environment<'a, 'b> {
+impl<'a, 'b, 'hooks> Environment<'a, 'b, 'hooks> {
     // Environments pack a reference to the global context (which is basically the db),
     //   the current contract context, a call stack, and the current sender.
     // Essentially, the point of the Environment struct is to prevent all the eval functions
@@ -833,12 +854,12 @@ impl<'a, 'b> Environment<'a, 'b> {
     //   contract context), a single "invocation" will end up creating multiple environment
     //   objects as context changes occur.
     pub fn new(
-        global_context: &'a mut GlobalContext<'b>,
+        global_context: &'a mut GlobalContext<'b, 'hooks>,
         contract_context: &'a ContractContext,
         call_stack: &'a mut CallStack,
         sender: Option<PrincipalData>,
         caller: Option<PrincipalData>,
-    ) -> Environment<'a, 'b> {
+    ) -> Environment<'a, 'b, 'hooks> {
         Environment {
             global_context,
             contract_context,
@@ -848,7 +869,10 @@ impl<'a, 'b> Environment<'a, 'b> {
         }
     }
 
-    pub fn nest_as_principal<'c>(&'c mut self, sender: PrincipalData) -> Environment<'c, 'b> {
+    pub fn nest_as_principal<'c>(
+        &'c mut self,
+        sender: PrincipalData,
+    ) -> Environment<'c, 'b, 'hooks> {
         Environment::new(
             self.global_context,
             self.contract_context,
@@ -858,7 +882,10 @@ impl<'a, 'b> Environment<'a, 'b> {
         )
     }
 
-    pub fn nest_with_caller<'c>(&'c mut self, caller: PrincipalData) -> Environment<'c, 'b> {
+    pub fn nest_with_caller<'c>(
+        &'c mut self,
+        caller: PrincipalData,
+    ) -> Environment<'c, 'b, 'hooks> {
         Environment::new(
             self.global_context,
             self.contract_context,
@@ -1131,9 +1158,11 @@ impl<'a, 'b> Environment<'a, 'b> {
             let memory_use = contract_string.len() as u64;
             self.add_memory(memory_use)?;
 
+            let clarity_version = ClarityVersion::default_for_epoch(*self.epoch());
             let result = Contract::initialize_from_ast(
                 contract_identifier.clone(),
                 contract_content,
+                clarity_version,
                 &mut self.global_context,
             );
             self.drop_memory(memory_use);
@@ -1171,7 +1200,7 @@ impl<'a, 'b> Environment<'a, 'b> {
         amount: u128,
     ) -> Result<Value> {
         self.global_context.begin();
-        let result = stx_transfer_consolidated(self, from, to, amount);
+        let result = stx_transfer_consolidated(self, from, to, amount, None);
         match result {
             Ok(value) => match value.clone().expect_result() {
                 Ok(_) => {
@@ -1231,11 +1260,13 @@ impl<'a, 'b> Environment<'a, 'b> {
         sender: PrincipalData,
         recipient: PrincipalData,
         amount: u128,
+        memo: Option<BuffData>,
     ) -> Result<()> {
         let event_data = STXTransferEventData {
             sender,
             recipient,
             amount,
+            memo,
         };
 
         if let Some(batch) = self.global_context.event_batches.last_mut() {
@@ -1386,14 +1417,14 @@ impl<'a, 'b> Environment<'a, 'b> {
     }
 }
 
-impl<'a> GlobalContext<'a> {
+impl<'a, 'hooks> GlobalContext<'a, 'hooks> {
     // Instantiate a new Global Context
     pub fn new(
         mainnet: bool,
-        database: ClarityDatabase,
+        database: ClarityDatabase<'a>,
         cost_track: LimitedCostTracker,
         epoch_id: StacksEpochId,
-    ) -> GlobalContext {
+    ) -> GlobalContext<'a, 'hooks> {
         GlobalContext {
             database,
             cost_track,
@@ -1402,7 +1433,7 @@ impl<'a> GlobalContext<'a> {
             event_batches: Vec::new(),
             mainnet,
             epoch_id,
-            coverage_reporting: None,
+            eval_hooks: vec![],
         }
     }
 
@@ -1567,7 +1598,10 @@ impl<'a> GlobalContext<'a> {
 }
 
 impl ContractContext {
-    pub fn new(contract_identifier: QualifiedContractIdentifier) -> Self {
+    pub fn new(
+        contract_identifier: QualifiedContractIdentifier,
+        clarity_version: ClarityVersion,
+    ) -> Self {
         Self {
             contract_identifier,
             variables: HashMap::new(),
@@ -1580,6 +1614,7 @@ impl ContractContext {
             meta_data_var: HashMap::new(),
             meta_nft: HashMap::new(),
             meta_ft: HashMap::new(),
+            clarity_version,
         }
     }
 
@@ -1603,7 +1638,7 @@ impl ContractContext {
     }
 
     pub fn is_name_used(&self, name: &str) -> bool {
-        is_reserved(name)
+        is_reserved(name, &self.clarity_version)
             || self.variables.contains_key(name)
             || self.functions.contains_key(name)
             || self.persisted_names.contains(name)
@@ -20,7 +20,7 @@ use crate::vm::contexts::{ContractContext, Environment, GlobalContext, LocalCont
 use crate::vm::errors::InterpreterResult as Result;
 use crate::vm::representations::SymbolicExpression;
 use crate::vm::types::QualifiedContractIdentifier;
-use crate::vm::{apply, eval_all, Value};
+use crate::vm::{apply, eval_all, ClarityVersion, Value};
 use std::convert::TryInto;
 
 #[derive(Serialize, Deserialize)]
@@ -34,9 +34,10 @@ impl Contract {
     pub fn initialize_from_ast(
         contract_identifier: QualifiedContractIdentifier,
         contract: &ContractAST,
+        clarity_version: ClarityVersion,
         global_context: &mut GlobalContext,
     ) -> Result<Contract> {
-        let mut contract_context = ContractContext::new(contract_identifier);
+        let mut contract_context = ContractContext::new(contract_identifier, clarity_version);
 
         eval_all(&contract.expressions, &mut contract_context, global_context)?;
 
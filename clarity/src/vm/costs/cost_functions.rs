@@ -120,6 +120,8 @@ define_named_enum!(ClarityCostFunction {
     BlockInfo("cost_block_info"),
     StxBalance("cost_stx_balance"),
     StxTransfer("cost_stx_transfer"),
+    StxTransferMemo("cost_stx_transfer_memo"),
+    StxGetAccount("cost_stx_account"),
     FtMint("cost_ft_mint"),
     FtTransfer("cost_ft_transfer"),
     FtBalance("cost_ft_balance"),
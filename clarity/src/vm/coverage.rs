@@ -5,7 +5,8 @@ use std::{
 };
 
 use crate::vm::types::QualifiedContractIdentifier;
-use crate::vm::SymbolicExpression;
+use crate::vm::{Environment, LocalContext};
+use crate::vm::{EvalHook, SymbolicExpression};
 use serde_json::Value as JsonValue;
 
 use super::functions::define::DefineFunctionsParsed;
@@ -222,3 +223,20 @@ impl CoverageReporter {
         Ok(())
     }
 }
+
+impl EvalHook for CoverageReporter {
+    fn will_begin_eval(
+        &mut self,
+        env: &mut Environment,
+        _context: &LocalContext,
+        expr: &SymbolicExpression,
+    ) {
+        let contract = &env.contract_context.contract_identifier;
+        self.report_eval(expr, contract);
+        // the function name of an application is never eval'ed on its own,
+        //   so report it along with the application
+        if let Some(function_variable) = expr.match_list().and_then(|children| children.first()) {
+            self.report_eval(function_variable, contract);
+        }
+    }
+}
@@ -242,6 +242,11 @@ impl<'db, 'conn> STXBalanceSnapshot<'db, 'conn> {
         }
     }
 
+    pub fn get_locked_balance(&self) -> (u128, u64) {
+        self.balance
+            .get_locked_balance_at_burn_block(self.burn_block_height)
+    }
+
     pub fn has_locked_tokens(&self) -> bool {
         self.balance
             .has_locked_tokens_at_burn_block(self.burn_block_height)
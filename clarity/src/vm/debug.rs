@@ -0,0 +1,553 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::vm::ast::{self, ASTRules};
+use crate::vm::errors::{Error, InterpreterResult as Result, RuntimeErrorType};
+use crate::vm::types::QualifiedContractIdentifier;
+use crate::vm::{eval, Environment, EvalHook, LocalContext, SymbolicExpression, Value};
+
+const HELP: &str = "Commands:
+  s, step                  evaluate the next expression, stepping into function bodies
+  n, next                  step over the current expression
+  f, finish                run until the enclosing expression finishes
+  c, continue              run until the next breakpoint
+  b, break [contract:]line set a breakpoint (the current contract if none is given)
+  d, delete <id>           delete a breakpoint
+  breakpoints              list the breakpoints
+  l, locals                show the local variable bindings
+  bt, backtrace            show the call stack
+  vars                     show the current contract's data variables
+  p, print <expr>          evaluate an expression here (state changes are rolled back)
+  q, quit                  stop debugging and run to completion
+  h, help                  show this message
+An empty line repeats the previous command.";
+
+/// A location in a contract at which execution pauses.  A breakpoint without a contract
+/// matches the line in every contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub contract: Option<QualifiedContractIdentifier>,
+    pub line: u32,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.contract {
+            Some(ref contract) => write!(f, "{}: {}:{}", self.id, contract, self.line),
+            None => write!(f, "{}: *:{}", self.id, self.line),
+        }
+    }
+}
+
+/// Parse a breakpoint specification of the form `[contract:]line`.
+pub fn parse_breakpoint(
+    spec: &str,
+) -> std::result::Result<(Option<QualifiedContractIdentifier>, u32), String> {
+    let (contract, line) = match spec.rfind(':') {
+        Some(index) => {
+            let contract = QualifiedContractIdentifier::parse(&spec[..index])
+                .map_err(|e| format!("Invalid contract identifier: {}", e))?;
+            (Some(contract), &spec[index + 1..])
+        }
+        None => (None, spec),
+    };
+    let line = line
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid line number: {}", line))?;
+    Ok((contract, line))
+}
+
+/// What the debugger is waiting for before it pauses again.  Depths are eval nesting
+/// depths, as tracked by `Debugger::depth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepState {
+    /// Pause before the next expression.
+    Step,
+    /// Pause before the next expression that is not nested in the one paused at this depth.
+    Next(usize),
+    /// Pause before the next expression that is shallower than this depth.
+    Finish(usize),
+    /// Pause only at breakpoints.
+    Continue,
+    /// Never pause again.
+    Detached,
+}
+
+enum Resume {
+    Paused,
+    Running,
+}
+
+/// An interactive step debugger for Clarity, installed as an `EvalHook`.  Commands are read
+/// from `input` whenever execution pauses, and everything the debugger reports is written to
+/// `output`.  Breakpoints match against the line numbers in expression spans.
+pub struct Debugger<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: usize,
+    state: StepState,
+    /// The number of expressions currently being evaluated.
+    depth: usize,
+    /// The contract and line of the most recently begun expression, so that a breakpoint
+    /// fires once when its line is reached instead of once per expression on it.
+    last_line: Option<(QualifiedContractIdentifier, u32)>,
+    last_command: String,
+    sources: HashMap<QualifiedContractIdentifier, Option<Vec<String>>>,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// Create a debugger which pauses before the first expression it sees.
+    pub fn new(input: R, output: W) -> Debugger<R, W> {
+        Debugger {
+            input,
+            output,
+            breakpoints: vec![],
+            next_breakpoint_id: 1,
+            state: StepState::Step,
+            depth: 0,
+            last_line: None,
+            last_command: String::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Only pause once a breakpoint is hit, instead of before the first expression.
+    pub fn run_to_breakpoint(&mut self) {
+        self.state = StepState::Continue;
+    }
+
+    pub fn add_breakpoint(
+        &mut self,
+        contract: Option<QualifiedContractIdentifier>,
+        line: u32,
+    ) -> &Breakpoint {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push(Breakpoint { id, contract, line });
+        self.breakpoints
+            .last()
+            .expect("BUG: breakpoint was just added")
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    fn should_pause(
+        &self,
+        contract: &QualifiedContractIdentifier,
+        expr: &SymbolicExpression,
+    ) -> bool {
+        match self.state {
+            StepState::Step => true,
+            StepState::Next(depth) if self.depth <= depth => true,
+            StepState::Finish(depth) if self.depth < depth => true,
+            StepState::Detached => false,
+            _ => self.hits_breakpoint(contract, expr.span.start_line),
+        }
+    }
+
+    fn hits_breakpoint(&self, contract: &QualifiedContractIdentifier, line: u32) -> bool {
+        if let Some((ref last_contract, last_line)) = self.last_line {
+            if last_contract == contract && last_line == line {
+                return false;
+            }
+        }
+        self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.line == line
+                && breakpoint
+                    .contract
+                    .as_ref()
+                    .map(|c| c == contract)
+                    .unwrap_or(true)
+        })
+    }
+
+    fn source_line(
+        &mut self,
+        env: &mut Environment,
+        contract: &QualifiedContractIdentifier,
+        line: u32,
+    ) -> Option<String> {
+        let lines = self.sources.entry(contract.clone()).or_insert_with(|| {
+            env.global_context
+                .database
+                .get_contract_src(contract)
+                .map(|src| src.lines().map(|line| line.to_string()).collect())
+        });
+        lines
+            .as_ref()
+            .and_then(|lines| lines.get((line as usize).checked_sub(1)?))
+            .cloned()
+    }
+
+    fn show_location(
+        &mut self,
+        env: &mut Environment,
+        expr: &SymbolicExpression,
+    ) -> io::Result<()> {
+        let contract = env.contract_context.contract_identifier.clone();
+        writeln!(
+            self.output,
+            "-> {}:{}:{}",
+            &contract, expr.span.start_line, expr.span.start_column
+        )?;
+        if let Some(source) = self.source_line(env, &contract, expr.span.start_line) {
+            writeln!(self.output, "{:>5} | {}", expr.span.start_line, source)?;
+        }
+        Ok(())
+    }
+
+    fn read_command(&mut self) -> io::Result<Option<String>> {
+        write!(self.output, "(debug) ")?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            Ok(Some(self.last_command.clone()))
+        } else {
+            self.last_command = line.to_string();
+            Ok(Some(line.to_string()))
+        }
+    }
+
+    /// Interact with the user until they resume execution.
+    fn pause(
+        &mut self,
+        env: &mut Environment,
+        context: &LocalContext,
+        expr: &SymbolicExpression,
+    ) -> io::Result<()> {
+        self.show_location(env, expr)?;
+        loop {
+            let command = match self.read_command()? {
+                Some(command) => command,
+                None => {
+                    // out of input: let the program finish
+                    self.state = StepState::Detached;
+                    return Ok(());
+                }
+            };
+            if let Resume::Running = self.run_command(env, context, &command)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        env: &mut Environment,
+        context: &LocalContext,
+        command: &str,
+    ) -> io::Result<Resume> {
+        let (name, argument) = match command.find(char::is_whitespace) {
+            Some(index) => (&command[..index], command[index..].trim()),
+            None => (command, ""),
+        };
+        match name {
+            "s" | "step" => {
+                self.state = StepState::Step;
+                return Ok(Resume::Running);
+            }
+            "n" | "next" => {
+                self.state = StepState::Next(self.depth);
+                return Ok(Resume::Running);
+            }
+            "f" | "finish" => {
+                self.state = StepState::Finish(self.depth);
+                return Ok(Resume::Running);
+            }
+            "c" | "continue" => {
+                self.state = StepState::Continue;
+                return Ok(Resume::Running);
+            }
+            "q" | "quit" => {
+                self.state = StepState::Detached;
+                return Ok(Resume::Running);
+            }
+            "b" | "break" => match parse_breakpoint(argument) {
+                Ok((contract, line)) => {
+                    let contract =
+                        contract.or_else(|| Some(env.contract_context.contract_identifier.clone()));
+                    let breakpoint = self.add_breakpoint(contract, line).to_string();
+                    writeln!(self.output, "Set breakpoint {}", breakpoint)?;
+                }
+                Err(msg) => writeln!(self.output, "{}", msg)?,
+            },
+            "d" | "delete" => match argument.parse::<usize>() {
+                Ok(id) => {
+                    let count = self.breakpoints.len();
+                    self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+                    if self.breakpoints.len() == count {
+                        writeln!(self.output, "No breakpoint {}", id)?;
+                    }
+                }
+                Err(_) => writeln!(self.output, "Usage: delete <id>")?,
+            },
+            "breakpoints" => {
+                if self.breakpoints.is_empty() {
+                    writeln!(self.output, "No breakpoints")?;
+                }
+                for breakpoint in self.breakpoints.iter() {
+                    writeln!(self.output, "{}", breakpoint)?;
+                }
+            }
+            "l" | "locals" => {
+                let locals = collect_locals(context);
+                if locals.is_empty() {
+                    writeln!(self.output, "No local variables")?;
+                }
+                for (name, value) in locals.iter() {
+                    writeln!(self.output, "{} = {}", name, value)?;
+                }
+            }
+            "bt" | "backtrace" => {
+                let stack = env.call_stack.make_stack_trace();
+                if stack.is_empty() {
+                    writeln!(self.output, "Not in a function call")?;
+                }
+                for (index, function) in stack.iter().rev().enumerate() {
+                    writeln!(self.output, "#{} {}", index, function)?;
+                }
+            }
+            "vars" => {
+                let contract = env.contract_context.contract_identifier.clone();
+                let mut names: Vec<_> = env.contract_context.meta_data_var.iter().collect();
+                names.sort_by(|a, b| a.0.cmp(b.0));
+                if names.is_empty() {
+                    writeln!(self.output, "No data variables in {}", &contract)?;
+                }
+                for (name, metadata) in names.into_iter() {
+                    match env
+                        .global_context
+                        .database
+                        .lookup_variable(&contract, name, metadata)
+                    {
+                        Ok(value) => writeln!(self.output, "{} = {}", name, value)?,
+                        Err(e) => writeln!(self.output, "{}: {}", name, e)?,
+                    }
+                }
+            }
+            "p" | "print" => match eval_in_place(env, context, argument) {
+                Ok(value) => writeln!(self.output, "{}", value)?,
+                Err(e) => writeln!(self.output, "Error: {}", e)?,
+            },
+            "h" | "help" => writeln!(self.output, "{}", HELP)?,
+            "" => {}
+            _ => writeln!(
+                self.output,
+                "Unknown command `{}`. Type `help` for a list of commands.",
+                name
+            )?,
+        }
+        Ok(Resume::Paused)
+    }
+}
+
+/// All of the variables visible in `context`, with inner bindings shadowing outer ones.
+fn collect_locals(context: &LocalContext) -> BTreeMap<String, Value> {
+    let mut locals = BTreeMap::new();
+    let mut current = Some(context);
+    while let Some(context) = current {
+        for (name, value) in context.variables.iter() {
+            locals
+                .entry(name.to_string())
+                .or_insert_with(|| value.clone());
+        }
+        current = context.parent;
+    }
+    locals
+}
+
+/// Evaluate `program` as though it appeared where execution is paused.  It runs without
+/// being charged to the cost tracker, and its state changes are rolled back.
+fn eval_in_place(env: &mut Environment, context: &LocalContext, program: &str) -> Result<Value> {
+    let contract_identifier = env.contract_context.contract_identifier.clone();
+    let parsed = ast::build_ast_with_rules(
+        &contract_identifier,
+        program,
+        &mut (),
+        ASTRules::PrecheckSize,
+    )?
+    .expressions;
+    let expr = match parsed.as_slice() {
+        [expr] => expr,
+        _ => {
+            return Err(Error::from(RuntimeErrorType::ParseError(
+                "Expected a single expression".to_string(),
+            )))
+        }
+    };
+    env.global_context.begin();
+    let result = env.run_free(|env| eval(expr, env, context));
+    env.global_context.roll_back();
+    result
+}
+
+impl<R: BufRead, W: Write> EvalHook for Debugger<R, W> {
+    fn will_begin_eval(
+        &mut self,
+        env: &mut Environment,
+        context: &LocalContext,
+        expr: &SymbolicExpression,
+    ) {
+        let contract = env.contract_context.contract_identifier.clone();
+        if self.should_pause(&contract, expr) {
+            if let Err(e) = self.pause(env, context, expr) {
+                error!("Debugger I/O failed, detaching: {}", e);
+                self.state = StepState::Detached;
+            }
+        }
+        self.last_line = Some((contract, expr.span.start_line));
+        self.depth += 1;
+    }
+
+    fn did_finish_eval(
+        &mut self,
+        _env: &mut Environment,
+        _context: &LocalContext,
+        expr: &SymbolicExpression,
+        result: &Result<Value>,
+    ) {
+        self.depth -= 1;
+        if self.state == StepState::Next(self.depth) {
+            // report the value of the expression that was stepped over
+            let outcome = match result {
+                Ok(value) => writeln!(self.output, "{} => {}", expr, value),
+                Err(e) => writeln!(self.output, "{} => error: {}", expr, e),
+            };
+            if let Err(e) = outcome {
+                error!("Debugger I/O failed, detaching: {}", e);
+                self.state = StepState::Detached;
+            }
+        }
+    }
+
+    fn did_complete(&mut self, result: std::result::Result<(), String>) {
+        if self.state == StepState::Detached {
+            return;
+        }
+        let outcome = match result {
+            Ok(()) => writeln!(self.output, "Execution finished"),
+            Err(e) => writeln!(self.output, "Execution failed: {}", e),
+        };
+        if let Err(e) = outcome {
+            error!("Debugger I/O failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::vm::ast::ASTRules;
+    use crate::vm::contexts::OwnedEnvironment;
+    use crate::vm::database::MemoryBackingStore;
+    use crate::vm::types::{PrincipalData, StandardPrincipalData};
+
+    const CONTRACT: &str = "(define-data-var counter int 0)
+(define-private (double (x int))
+  (* x 2))
+(define-public (bump (n int))
+  (let ((d (double n)))
+    (var-set counter (+ (var-get counter) d))
+    (ok (var-get counter))))";
+
+    fn debug_bump(debugger: &mut Debugger<Cursor<&[u8]>, Vec<u8>>) -> Value {
+        let contract_identifier = QualifiedContractIdentifier::local("debugged").unwrap();
+        let mut marf = MemoryBackingStore::new();
+        let mut owned_env = OwnedEnvironment::new(marf.as_clarity_db());
+        owned_env
+            .initialize_contract(
+                contract_identifier.clone(),
+                CONTRACT,
+                ASTRules::PrecheckSize,
+            )
+            .unwrap();
+        owned_env.add_eval_hook(debugger);
+        let sender: PrincipalData = StandardPrincipalData::transient().into();
+        owned_env
+            .execute_transaction(
+                sender,
+                contract_identifier,
+                "bump",
+                &[SymbolicExpression::atom_value(Value::Int(5))],
+            )
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_breakpoint_inspection() {
+        let input = "locals\nbt\np (var-set counter 100)\nvars\np (+ x 1)\nc\n";
+        let mut debugger = Debugger::new(Cursor::new(input.as_bytes()), vec![]);
+        debugger.add_breakpoint(None, 3);
+        debugger.run_to_breakpoint();
+
+        let result = debug_bump(&mut debugger);
+        assert_eq!(result, Value::okay(Value::Int(10)).unwrap());
+
+        let output = String::from_utf8(debugger.output).unwrap();
+        assert!(output.contains("-> S1G2081040G2081040G2081040G208105NK8PE5.debugged:3:3"));
+        assert!(output.contains("    3 |   (* x 2))"));
+        assert!(output.contains("x = 5"));
+        assert!(output.contains("double"));
+        // the var-set was rolled back before `vars`
+        assert!(output.contains("counter = 0"));
+        assert!(output.contains("6\n"));
+        assert!(output.contains("Execution finished"));
+    }
+
+    #[test]
+    fn test_step_and_next() {
+        // step into the let, step over its binding's call to `double`, then detach
+        let input = "s\nn\nq\n";
+        let mut debugger = Debugger::new(Cursor::new(input.as_bytes()), vec![]);
+
+        let result = debug_bump(&mut debugger);
+        assert_eq!(result, Value::okay(Value::Int(10)).unwrap());
+
+        let output = String::from_utf8(debugger.output).unwrap();
+        assert!(output.contains("debugged:5:3"));
+        assert!(output.contains("debugged:5:12"));
+        assert!(output.contains("( double n ) => 10"));
+        assert!(output.contains("debugged:6:5"));
+        assert!(!output.contains("debugged:3:3"));
+        assert!(!output.contains("Execution finished"));
+    }
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(parse_breakpoint("12"), Ok((None, 12)));
+        assert_eq!(
+            parse_breakpoint("S1G2081040G2081040G2081040G208105NK8PE5.foo:7"),
+            Ok((Some(QualifiedContractIdentifier::local("foo").unwrap()), 7))
+        );
+        assert!(parse_breakpoint("foo:7").is_err());
+        assert!(parse_breakpoint("S1G2081040G2081040G2081040G208105NK8PE5.foo:x").is_err());
+    }
+}
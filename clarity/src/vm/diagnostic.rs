@@ -18,10 +18,12 @@ use crate::vm::representations::Span;
 use std::fmt;
 
 /// In a near future, we can go further in our static analysis and provide different levels
-/// of diagnostics, such as warnings, hints, best practices, etc.
+/// of diagnostics, such as hints, best practices, etc.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum Level {
     Error,
+    /// Reported by opt-in lints: the contract is valid, but probably not what was intended.
+    Warning,
 }
 
 pub trait DiagnosableError {
@@ -47,6 +49,15 @@ impl Diagnostic {
         }
     }
 
+    pub fn warning(message: String, suggestion: Option<String>) -> Diagnostic {
+        Diagnostic {
+            spans: vec![],
+            level: Level::Warning,
+            message,
+            suggestion,
+        }
+    }
+
     pub fn add_span(&mut self, start_line: u32, start_column: u32, end_line: u32, end_column: u32) {
         self.spans.push(Span {
             start_line,
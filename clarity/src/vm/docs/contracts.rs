@@ -1,4 +1,4 @@
-use crate::vm::analysis::{mem_type_check, ContractAnalysis};
+use crate::vm::analysis::{mem_type_check_version, ContractAnalysis};
 use crate::vm::docs::{get_input_type_string, get_output_type_string, get_signature};
 use crate::vm::types::{FunctionType, Value};
 
@@ -11,7 +11,7 @@ use crate::vm::contexts::GlobalContext;
 use crate::vm::costs::LimitedCostTracker;
 use crate::vm::database::MemoryBackingStore;
 use crate::vm::types::QualifiedContractIdentifier;
-use crate::vm::{self, ContractContext};
+use crate::vm::{self, ClarityVersion, ContractContext};
 
 const DOCS_GENERATION_EPOCH: StacksEpochId = StacksEpochId::Epoch2_05;
 
@@ -67,7 +67,10 @@ fn get_constant_value(var_name: &str, contract_content: &str) -> Value {
 
 fn doc_execute(program: &str) -> Result<Option<Value>, vm::Error> {
     let contract_id = QualifiedContractIdentifier::transient();
-    let mut contract_context = ContractContext::new(contract_id.clone());
+    let mut contract_context = ContractContext::new(
+        contract_id.clone(),
+        ClarityVersion::default_for_epoch(DOCS_GENERATION_EPOCH),
+    );
     let mut marf = MemoryBackingStore::new();
     let conn = marf.as_clarity_db();
     let mut global_context = GlobalContext::new(
@@ -84,8 +87,11 @@ fn doc_execute(program: &str) -> Result<Option<Value>, vm::Error> {
 }
 
 pub fn make_docs(content: &str, support_docs: &ContractSupportDocs) -> ContractRef {
-    let (_, contract_analysis) =
-        mem_type_check(content).expect("BUG: failed to type check boot contract");
+    let (_, contract_analysis) = mem_type_check_version(
+        content,
+        ClarityVersion::default_for_epoch(DOCS_GENERATION_EPOCH),
+    )
+    .expect("BUG: failed to type check boot contract");
 
     let ContractAnalysis {
         public_function_types,
@@ -1581,6 +1581,23 @@ principal isn't materialized, it returns 0.
 ",
 };
 
+const STX_GET_ACCOUNT: SimpleFunctionAPI = SimpleFunctionAPI {
+    name: None,
+    signature: "(stx-account owner)",
+    description: "`stx-account` is used to query the STX account of the `owner` principal.
+
+This function returns a tuple with the canonical account representation for an STX account.
+This includes the current amount of unlocked STX, the current amount of locked STX, and the
+unlock height for any locked STX, all in microstacks. Locked STX whose unlock height has already
+been reached are reported as unlocked. In the event that the `owner` principal isn't
+materialized, all three values are 0.
+",
+    example: "
+(stx-account 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR) ;; Returns (tuple (locked u0) (unlock-height u0) (unlocked u0))
+(stx-account (as-contract tx-sender)) ;; Returns (tuple (locked u0) (unlock-height u0) (unlocked u1000))
+",
+};
+
 const STX_TRANSFER: SimpleFunctionAPI = SimpleFunctionAPI {
     name: None,
     signature: "(stx-transfer? amount sender recipient)",
@@ -1603,6 +1620,23 @@ one of the following error codes:
 "
 };
 
+const STX_TRANSFER_MEMO: SimpleFunctionAPI = SimpleFunctionAPI {
+    name: None,
+    signature: "(stx-transfer-memo? amount sender recipient memo)",
+    description: "`stx-transfer-memo?` is similar to `stx-transfer?`, except that it adds a `memo` field.
+
+The `memo` is a buffer of at most 34 bytes, like the memo of a token-transfer transaction. It is
+emitted alongside the transfer in the resulting `stx_transfer_event`, so that the recipient can
+attribute the transfer, but it has no other effect.
+
+This function returns (ok true) if the transfer is successful, or, on an error, returns the same codes as `stx-transfer?`.
+",
+    example: "
+(as-contract
+  (stx-transfer-memo? u60 tx-sender 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 0x010203)) ;; Returns (ok true)
+"
+};
+
 const STX_BURN: SimpleFunctionAPI = SimpleFunctionAPI {
     name: None,
     signature: "(stx-burn? amount sender)",
@@ -1708,7 +1742,9 @@ fn make_api_reference(function: &NativeFunctions) -> FunctionAPI {
         GetTokenSupply => make_for_special(&GET_TOKEN_SUPPLY, name),
         AtBlock => make_for_special(&AT_BLOCK, name),
         GetStxBalance => make_for_simple_native(&STX_GET_BALANCE, &GetStxBalance, name),
+        GetStxAccount => make_for_simple_native(&STX_GET_ACCOUNT, &GetStxAccount, name),
         StxTransfer => make_for_simple_native(&STX_TRANSFER, &StxTransfer, name),
+        StxTransferMemo => make_for_simple_native(&STX_TRANSFER_MEMO, &StxTransferMemo, name),
         StxBurn => make_for_simple_native(&STX_BURN, &StxBurn, name),
     }
 }
@@ -1799,6 +1835,36 @@ pub fn make_json_api_reference() -> String {
     )
 }
 
+fn function_markdown(api: &FunctionAPI) -> String {
+    format!(
+        "```clarity\n{}\n```\n\n`{}` -> `{}`\n\n{}\n\n**Example**\n```clarity\n{}\n```",
+        api.signature,
+        api.input_type,
+        api.output_type,
+        api.description,
+        api.example.trim_end()
+    )
+}
+
+/// The reference documentation of a native function, define form or keyword, formatted as
+/// markdown for display in editors.
+pub fn make_markdown_reference(name: &str) -> Option<String> {
+    if let Some(function) = NativeFunctions::lookup_by_name(name) {
+        return Some(function_markdown(&make_api_reference(&function)));
+    }
+    if let Some(define_type) = DefineFunctions::lookup_by_name(name) {
+        return Some(function_markdown(&make_define_reference(&define_type)));
+    }
+    let keyword = NativeVariables::lookup_by_name(name).and_then(|v| make_keyword_reference(&v))?;
+    Some(format!(
+        "```clarity\n{}\n```\n\n`{}`\n\n{}\n\n**Example**\n```clarity\n{}\n```",
+        keyword.name,
+        keyword.output_type,
+        keyword.description,
+        keyword.example.trim_end()
+    ))
+}
+
 #[cfg(test)]
 mod test {
     use crate::vm::{
@@ -1807,8 +1873,8 @@ mod test {
         database::{BurnStateDB, HeadersDB, STXBalance},
         eval_all, execute,
         types::PrincipalData,
-        ContractContext, Error, GlobalContext, LimitedCostTracker, QualifiedContractIdentifier,
-        Value,
+        ClarityVersion, ContractContext, Error, GlobalContext, LimitedCostTracker,
+        QualifiedContractIdentifier, Value,
     };
     use stacks_common::types::{StacksEpochId, PEER_VERSION_EPOCH_2_0};
 
@@ -1940,12 +2006,13 @@ mod test {
         }
 
         let conn = store.as_docs_clarity_db();
-        let mut contract_context = ContractContext::new(contract_id.clone());
+        let mut contract_context =
+            ContractContext::new(contract_id.clone(), ClarityVersion::latest());
         let mut global_context = GlobalContext::new(
             false,
             conn,
             LimitedCostTracker::new_free(),
-            StacksEpochId::Epoch2_05,
+            StacksEpochId::Epoch21,
         );
 
         global_context
@@ -147,15 +147,21 @@ pub struct STXTransferEventData {
     pub sender: PrincipalData,
     pub recipient: PrincipalData,
     pub amount: u128,
+    /// Only set for transfers made with `stx-transfer-memo?`
+    pub memo: Option<BuffData>,
 }
 
 impl STXTransferEventData {
     pub fn json_serialize(&self) -> serde_json::Value {
-        json!({
+        let mut out = json!({
             "sender": format!("{}",self.sender),
             "recipient": format!("{}",self.recipient),
             "amount": format!("{}", self.amount),
-        })
+        });
+        if let Some(ref memo) = self.memo {
+            out["memo"] = json!(format!("{}", memo));
+        }
+        out
     }
 }
 
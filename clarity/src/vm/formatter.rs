@@ -0,0 +1,384 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rewrites Clarity source into a canonical layout.
+//!
+//! An expression is printed on one line if it contains no comments and fits within
+//! `MAX_LINE_WIDTH`.  Otherwise, a list's function name and first argument stay on the
+//! opening line and its remaining arguments each get their own line, indented by `INDENT`
+//! from the opening parenthesis.  Lists which do not start with a name (such as `let`
+//! bindings) align their items with the first one, and tuples put each field on its own
+//! line.  Comments stay on their own line, or at the end of the line of the expression
+//! they followed.  At most one blank line is kept wherever the source had blank lines.
+
+use std::fmt;
+
+use crate::vm::ast::errors::ParseError;
+use crate::vm::ast::parser::cst::{parse_lossless, CstNode, CstNodeType};
+
+pub const MAX_LINE_WIDTH: usize = 80;
+pub const INDENT: usize = 2;
+
+/// Functions whose first argument is not kept on the opening line when they are broken
+/// across lines.
+const NO_INLINE_ARGUMENT: &[&str] = &["begin", "list"];
+
+#[derive(Debug)]
+pub enum FormatError {
+    Parse(ParseError),
+    /// The formatted source parses to different tokens than the original, or formatting it
+    /// again would change it.  Either is a bug in the formatter.
+    Unstable(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Parse(e) => write!(f, "{}", e),
+            FormatError::Unstable(reason) => write!(f, "Formatter bug: {}", reason),
+        }
+    }
+}
+
+impl From<ParseError> for FormatError {
+    fn from(e: ParseError) -> Self {
+        FormatError::Parse(e)
+    }
+}
+
+/// The expression printed on a single line, or `None` if it contains a comment.
+fn flat(node: &CstNode) -> Option<String> {
+    match node.node {
+        CstNodeType::Token(ref text) => Some(text.clone()),
+        CstNodeType::Comment(_) => None,
+        CstNodeType::Colon => Some(":".to_string()),
+        CstNodeType::Comma => Some(",".to_string()),
+        CstNodeType::List(ref items) => {
+            let items: Option<Vec<String>> = items.iter().map(flat).collect();
+            Some(format!("({})", items?.join(" ")))
+        }
+        CstNodeType::Tuple(ref items) => {
+            if items.is_empty() {
+                return Some("{}".to_string());
+            }
+            let mut out = String::new();
+            for item in items.iter() {
+                match item.node {
+                    CstNodeType::Colon => out.push(':'),
+                    CstNodeType::Comma => out.push(','),
+                    _ => {
+                        out.push(' ');
+                        out.push_str(&flat(item)?);
+                    }
+                }
+            }
+            Some(format!("{{{} }}", out))
+        }
+    }
+}
+
+/// Whether `node` should be printed at the end of the line which `prev_line` ended on.
+fn is_trailing_comment(node: &CstNode, prev_line: Option<u32>) -> bool {
+    node.is_comment() && prev_line == Some(node.span.start_line)
+}
+
+/// Whether the source had a blank line between the previous node and `node`.
+fn follows_blank_line(node: &CstNode, prev_line: Option<u32>) -> bool {
+    match prev_line {
+        Some(prev_line) => node.span.start_line > prev_line + 1,
+        None => false,
+    }
+}
+
+struct Printer {
+    out: String,
+    column: usize,
+    /// Whether the current line ends in a comment, so nothing else may be written on it.
+    in_comment: bool,
+}
+
+impl Printer {
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column += text.len();
+    }
+
+    fn newline(&mut self, indent: usize, blank_line: bool) {
+        if blank_line {
+            self.out.push('\n');
+        }
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+        self.in_comment = false;
+    }
+
+    /// Start a new line if the current one ends in a comment.
+    fn end_comment(&mut self, indent: usize) {
+        if self.in_comment {
+            self.newline(indent, false);
+        }
+    }
+
+    fn print_comment(&mut self, text: &str, trailing: bool, indent: usize, blank_line: bool) {
+        if trailing {
+            self.write(" ");
+        } else {
+            self.newline(indent, blank_line);
+        }
+        self.write(text);
+        self.in_comment = true;
+    }
+
+    fn print_node(&mut self, node: &CstNode) {
+        match node.node {
+            CstNodeType::Comment(ref text) => {
+                self.write(text);
+                self.in_comment = true;
+            }
+            CstNodeType::List(ref items) | CstNodeType::Tuple(ref items) => match flat(node) {
+                Some(ref flat) if self.column + flat.len() <= MAX_LINE_WIDTH => self.write(flat),
+                _ if node.match_list().is_some() => self.print_broken_list(node, items),
+                _ => self.print_broken_tuple(node, items),
+            },
+            // tokens are printed as written, even if they are too long for the line
+            CstNodeType::Token(ref text) => self.write(text),
+            CstNodeType::Colon => self.write(":"),
+            CstNodeType::Comma => self.write(","),
+        }
+    }
+
+    fn print_broken_list(&mut self, node: &CstNode, items: &[CstNode]) {
+        let start = self.column;
+        let mut prev_line = Some(node.span.start_line);
+        let mut items = items.iter().peekable();
+        self.write("(");
+
+        let indent = match items.peek() {
+            Some(head) if head.match_token().is_some() => {
+                let head = items.next().unwrap();
+                self.print_node(head);
+                prev_line = Some(head.span.end_line);
+                let inline_argument = !NO_INLINE_ARGUMENT.contains(&head.match_token().unwrap());
+                if let Some(argument) = items.peek() {
+                    if inline_argument && !argument.is_comment() {
+                        self.write(" ");
+                        self.print_node(argument);
+                        prev_line = Some(argument.span.end_line);
+                        items.next();
+                    }
+                }
+                start + INDENT
+            }
+            Some(first) if !first.is_comment() => {
+                // not a function application: align items with the first one
+                let first = items.next().unwrap();
+                self.print_node(first);
+                prev_line = Some(first.span.end_line);
+                start + 1
+            }
+            _ => start + INDENT,
+        };
+
+        for item in items {
+            if let CstNodeType::Comment(ref text) = item.node {
+                let trailing = is_trailing_comment(item, prev_line);
+                self.print_comment(text, trailing, indent, follows_blank_line(item, prev_line));
+            } else {
+                self.newline(indent, follows_blank_line(item, prev_line));
+                self.print_node(item);
+            }
+            prev_line = Some(item.span.end_line);
+        }
+
+        self.end_comment(start);
+        self.write(")");
+    }
+
+    fn print_broken_tuple(&mut self, node: &CstNode, items: &[CstNode]) {
+        let start = self.column;
+        let indent = start + INDENT;
+        let mut prev_line = Some(node.span.start_line);
+        let mut after_colon = false;
+        self.write("{");
+
+        for item in items.iter() {
+            match item.node {
+                CstNodeType::Comment(ref text) => {
+                    let trailing = is_trailing_comment(item, prev_line);
+                    self.print_comment(text, trailing, indent, follows_blank_line(item, prev_line));
+                }
+                CstNodeType::Colon | CstNodeType::Comma => {
+                    self.end_comment(indent);
+                    self.write(if let CstNodeType::Colon = item.node {
+                        ":"
+                    } else {
+                        ","
+                    });
+                    after_colon = item.node == CstNodeType::Colon;
+                }
+                _ => {
+                    if after_colon && !self.in_comment {
+                        self.write(" ");
+                    } else {
+                        self.newline(indent, follows_blank_line(item, prev_line));
+                    }
+                    self.print_node(item);
+                    after_colon = false;
+                }
+            }
+            prev_line = Some(item.span.end_line);
+        }
+
+        self.newline(start, false);
+        self.write("}");
+    }
+}
+
+fn print(nodes: &[CstNode]) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        column: 0,
+        in_comment: false,
+    };
+    let mut prev_line = None;
+    for node in nodes.iter() {
+        if is_trailing_comment(node, prev_line) {
+            printer.write(" ");
+        } else if prev_line.is_some() {
+            printer.newline(0, follows_blank_line(node, prev_line));
+        }
+        printer.print_node(node);
+        prev_line = Some(node.span.end_line);
+    }
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+/// Whether two trees have the same nodes, ignoring their layout.
+fn same_tokens(a: &[CstNode], b: &[CstNode]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .all(|(a, b)| match (&a.node, &b.node) {
+                (CstNodeType::List(a), CstNodeType::List(b))
+                | (CstNodeType::Tuple(a), CstNodeType::Tuple(b)) => same_tokens(a, b),
+                (a, b) => a == b,
+            })
+}
+
+/// Format a contract into the canonical layout.  The result is checked to contain the same
+/// tokens and comments as `source`, and to be left unchanged by formatting it again.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let nodes = parse_lossless(source)?;
+    let formatted = print(&nodes);
+
+    let reparsed = parse_lossless(&formatted)?;
+    if !same_tokens(&nodes, &reparsed) {
+        return Err(FormatError::Unstable(
+            "formatted source does not have the same tokens".into(),
+        ));
+    }
+    if print(&reparsed) != formatted {
+        return Err(FormatError::Unstable(
+            "formatted source changes when formatted again".into(),
+        ));
+    }
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_formats_to(source: &str, expected: &str) {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_short_expressions_are_flat() {
+        assert_formats_to(
+            "(define-map   tokens {account: principal}\n  { balance: uint })\n\n\n\n(define-data-var   counter int 0)",
+            "(define-map tokens { account: principal } { balance: uint })\n\n(define-data-var counter int 0)\n",
+        );
+        assert_formats_to("", "");
+        assert_formats_to("(foo (bar) {})", "(foo (bar) {})\n");
+    }
+
+    #[test]
+    fn test_long_expressions_are_broken() {
+        let source = "(define-public (transfer (amount uint) (recipient principal)) (let ((sender-balance (get-balance tx-sender)) (remaining-balance (- sender-balance amount))) (asserts! (>= sender-balance amount) (err u1)) (begin (map-set balances { owner: tx-sender } { balance: remaining-balance }) (ok true))))";
+        let expected = "\
+(define-public (transfer (amount uint) (recipient principal))
+  (let ((sender-balance (get-balance tx-sender))
+        (remaining-balance (- sender-balance amount)))
+    (asserts! (>= sender-balance amount) (err u1))
+    (begin
+      (map-set balances { owner: tx-sender } { balance: remaining-balance })
+      (ok true))))
+";
+        assert_formats_to(source, expected);
+    }
+
+    #[test]
+    fn test_broken_tuple() {
+        let source = "(define-read-only (get-info) { name: \"a fairly long name which forces the tuple to be broken\", symbol: \"SYM\", decimals: u6 })";
+        let expected = "\
+(define-read-only (get-info)
+  {
+    name: \"a fairly long name which forces the tuple to be broken\",
+    symbol: \"SYM\",
+    decimals: u6
+  })
+";
+        assert_formats_to(source, expected);
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let source = ";; a counter\n(define-data-var counter int 0) ;; starts at zero\n\n(define-public (increment)\n  ;; bump it\n  (begin (var-set counter (+ (var-get counter) 1)) ;; no overflow check\n    (ok (var-get counter)) ;; the new value\n  ))";
+        let expected = "\
+;; a counter
+(define-data-var counter int 0) ;; starts at zero
+
+(define-public (increment)
+  ;; bump it
+  (begin
+    (var-set counter (+ (var-get counter) 1)) ;; no overflow check
+    (ok (var-get counter)) ;; the new value
+  ))
+";
+        assert_formats_to(source, expected);
+
+        // a comment before a tuple separator must not swallow it
+        let source = "{ a: 1 ;; one\n, b: 2 }";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, "{\n  a: 1 ;; one\n  ,\n  b: 2\n}\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match format_source("(define-public (foo)") {
+            Err(FormatError::Parse(_)) => {}
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
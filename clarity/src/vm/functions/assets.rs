@@ -25,7 +25,8 @@ use crate::vm::errors::{
 };
 use crate::vm::representations::SymbolicExpression;
 use crate::vm::types::{
-    AssetIdentifier, BlockInfoProperty, BuffData, OptionalData, PrincipalData, TypeSignature, Value,
+    AssetIdentifier, BlockInfoProperty, BuffData, OptionalData, PrincipalData, SequenceData,
+    TupleData, TypeSignature, Value,
 };
 use crate::vm::{eval, Environment, LocalContext};
 use std::convert::TryFrom;
@@ -115,14 +116,54 @@ pub fn special_stx_balance(
     }
 }
 
+/// Query the full STX account of a principal: its spendable balance, and the amount and unlock
+/// height of any STX still locked at the current burn block height.  Tokens whose unlock height
+/// has passed are reported as unlocked, just as `stx-get-balance` does.
+pub fn special_stx_account(
+    args: &[SymbolicExpression],
+    env: &mut Environment,
+    context: &LocalContext,
+) -> Result<Value> {
+    check_argument_count(1, args)?;
+
+    runtime_cost(ClarityCostFunction::StxGetAccount, env, 0)?;
+
+    let owner = eval(&args[0], env, context)?;
+
+    if let Value::Principal(ref principal) = owner {
+        let (unlocked, (locked, unlock_height)) = {
+            let snapshot = env
+                .global_context
+                .database
+                .get_stx_balance_snapshot(principal);
+            (
+                snapshot.get_available_balance(),
+                snapshot.get_locked_balance(),
+            )
+        };
+        Ok(Value::from(
+            TupleData::from_data(vec![
+                ("unlocked".into(), Value::UInt(unlocked)),
+                ("locked".into(), Value::UInt(locked)),
+                ("unlock-height".into(), Value::UInt(unlock_height as u128)),
+            ])
+            .expect("BUG: failed to construct stx-account tuple"),
+        ))
+    } else {
+        Err(CheckErrors::TypeValueError(TypeSignature::PrincipalType, owner).into())
+    }
+}
+
 /// Do a "consolidated" STX transfer.
 /// If the 'from' principal has locked STX, and they have unlocked, then process the STX unlock
 /// and update its balance in addition to spending tokens out of it.
+/// The `memo`, if given, is attached to the emitted STX transfer event.
 pub fn stx_transfer_consolidated(
     env: &mut Environment,
     from: &PrincipalData,
     to: &PrincipalData,
     amount: u128,
+    memo: Option<&BuffData>,
 ) -> Result<Value> {
     if amount == 0 {
         return clarity_ecode!(StxErrorCodes::NON_POSITIVE_AMOUNT);
@@ -153,7 +194,7 @@ pub fn stx_transfer_consolidated(
     sender_snapshot.transfer_to(to, amount)?;
 
     env.global_context.log_stx_transfer(&from, amount)?;
-    env.register_stx_transfer_event(from.clone(), to.clone(), amount)?;
+    env.register_stx_transfer_event(from.clone(), to.clone(), amount, memo.cloned())?;
     Ok(Value::okay_true())
 }
 
@@ -173,7 +214,34 @@ pub fn special_stx_transfer(
     if let (Value::Principal(ref from), Value::Principal(ref to), Value::UInt(amount)) =
         (&from_val, to_val, amount_val)
     {
-        stx_transfer_consolidated(env, from, to, amount)
+        stx_transfer_consolidated(env, from, to, amount, None)
+    } else {
+        Err(CheckErrors::BadTransferSTXArguments.into())
+    }
+}
+
+pub fn special_stx_transfer_memo(
+    args: &[SymbolicExpression],
+    env: &mut Environment,
+    context: &LocalContext,
+) -> Result<Value> {
+    check_argument_count(4, args)?;
+
+    runtime_cost(ClarityCostFunction::StxTransferMemo, env, 0)?;
+
+    let amount_val = eval(&args[0], env, context)?;
+    let from_val = eval(&args[1], env, context)?;
+    let to_val = eval(&args[2], env, context)?;
+    let memo_val = eval(&args[3], env, context)?;
+
+    if let (
+        Value::Principal(ref from),
+        Value::Principal(ref to),
+        Value::UInt(amount),
+        Value::Sequence(SequenceData::Buffer(ref memo)),
+    ) = (&from_val, to_val, amount_val, memo_val)
+    {
+        stx_transfer_consolidated(env, from, to, amount, Some(memo))
     } else {
         Err(CheckErrors::BadTransferSTXArguments.into())
     }
@@ -31,7 +31,7 @@ use crate::vm::types::{
     BuffData, CharType, PrincipalData, ResponseData, SequenceData, TypeSignature, Value, BUFF_32,
     BUFF_33, BUFF_65,
 };
-use crate::vm::{eval, Environment, LocalContext};
+use crate::vm::{eval, ClarityVersion, Environment, LocalContext};
 use stacks_common::address::AddressHashMode;
 use stacks_common::util::hash;
 
@@ -50,7 +50,9 @@ macro_rules! switch_on_global_epoch {
                     panic!("Executing Clarity method during Epoch 1.0, before Clarity")
                 }
                 StacksEpochId::Epoch20 => $Epoch2Version(args, env, context),
-                StacksEpochId::Epoch2_05 => $Epoch205Version(args, env, context),
+                StacksEpochId::Epoch2_05 | StacksEpochId::Epoch21 => {
+                    $Epoch205Version(args, env, context)
+                }
             }
         }
     };
@@ -147,14 +149,36 @@ define_named_enum!(NativeFunctions {
     BurnToken("ft-burn?"),
     BurnAsset("nft-burn?"),
     GetStxBalance("stx-get-balance"),
+    GetStxAccount("stx-account"),
     StxTransfer("stx-transfer?"),
+    StxTransferMemo("stx-transfer-memo?"),
     StxBurn("stx-burn?"),
 });
 
-pub fn lookup_reserved_functions(name: &str) -> Option<CallableType> {
+impl NativeFunctions {
+    /// The first Clarity version in which this native function is defined
+    pub fn get_version(&self) -> ClarityVersion {
+        use crate::vm::functions::NativeFunctions::*;
+        match self {
+            GetStxAccount | StxTransferMemo => ClarityVersion::Clarity2,
+            _ => ClarityVersion::Clarity1,
+        }
+    }
+
+    /// Look up a native function by name, as seen by a contract of the given Clarity version.
+    ///  Natives introduced after `version` are not defined (or reserved) for that contract.
+    pub fn lookup_by_name_at_version(
+        name: &str,
+        version: &ClarityVersion,
+    ) -> Option<NativeFunctions> {
+        NativeFunctions::lookup_by_name(name).filter(|native| native.get_version() <= *version)
+    }
+}
+
+pub fn lookup_reserved_functions(name: &str, version: &ClarityVersion) -> Option<CallableType> {
     use crate::vm::callables::CallableType::{NativeFunction, NativeFunction205, SpecialFunction};
     use crate::vm::functions::NativeFunctions::*;
-    if let Some(native_function) = NativeFunctions::lookup_by_name(name) {
+    if let Some(native_function) = NativeFunctions::lookup_by_name_at_version(name, version) {
         let callable = match native_function {
             Add => NativeFunction(
                 "native_add",
@@ -420,7 +444,12 @@ pub fn lookup_reserved_functions(name: &str) -> Option<CallableType> {
             ),
             AtBlock => SpecialFunction("special_at_block", &database::special_at_block),
             GetStxBalance => SpecialFunction("special_stx_balance", &assets::special_stx_balance),
+            GetStxAccount => SpecialFunction("special_stx_account", &assets::special_stx_account),
             StxTransfer => SpecialFunction("special_stx_transfer", &assets::special_stx_transfer),
+            StxTransferMemo => SpecialFunction(
+                "special_stx_transfer_memo",
+                &assets::special_stx_transfer_memo,
+            ),
             StxBurn => SpecialFunction("special_stx_burn", &assets::special_stx_burn),
         };
         Some(callable)
@@ -581,7 +610,7 @@ fn special_let(
 
     finally_drop_memory!( env, memory_use; {
         handle_binding_list::<_, Error>(bindings, |binding_name, var_sexp| {
-            if is_reserved(binding_name) ||
+            if is_reserved(binding_name, &env.contract_context.clarity_version) ||
                 env.contract_context.lookup_function(binding_name).is_some() ||
                 inner_context.lookup_variable(binding_name).is_some() {
                     return Err(CheckErrors::NameAlreadyUsed(binding_name.clone().into()).into())
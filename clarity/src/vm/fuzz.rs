@@ -0,0 +1,654 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Property-based fuzzing of contract functions: random arguments of a function's
+//! parameter types, and shrinking of failing arguments to minimal counterexamples.
+//! Executing the function is left to the caller.
+
+use rand::Rng;
+
+use crate::vm::database::ClaritySerializable;
+use crate::vm::types::signatures::{ListTypeData, TupleTypeSignature};
+use crate::vm::types::{
+    ASCIIData, BuffData, CharType, ListData, OptionalData, PrincipalData, ResponseData,
+    SequenceData, SequenceSubtype, StandardPrincipalData, StringSubtype, TupleData, TypeSignature,
+    UTF8Data, Value,
+};
+
+/// The most candidates tried while shrinking a counterexample.
+pub const MAX_SHRINK_ATTEMPTS: usize = 1000;
+
+/// How a call of a fuzzed function went wrong.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FuzzFailure {
+    /// The call aborted, e.g., with an `unwrap-panic` of `none`.
+    RuntimeError { error: String },
+    /// An invariant didn't evaluate to `true` after the call.
+    InvariantViolated { invariant: String, result: String },
+}
+
+impl FuzzFailure {
+    /// Whether a shrunk input still shows the same problem.
+    fn same_kind(&self, other: &FuzzFailure) -> bool {
+        match (self, other) {
+            (FuzzFailure::RuntimeError { .. }, FuzzFailure::RuntimeError { .. }) => true,
+            (
+                FuzzFailure::InvariantViolated { invariant, .. },
+                FuzzFailure::InvariantViolated {
+                    invariant: other, ..
+                },
+            ) => invariant == other,
+            _ => false,
+        }
+    }
+}
+
+/// The arguments of a failing call, after shrinking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// The run which first failed, counting from 0.
+    pub run: u32,
+    pub arguments: Vec<Value>,
+    pub failure: FuzzFailure,
+}
+
+/// A counterexample as saved to disk, for replaying it later.  Arguments are kept in their
+/// consensus serialization, so that they are read back with exactly their original types.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub contract_identifier: String,
+    pub function: String,
+    pub sender: String,
+    pub arguments: Vec<String>,
+    pub failure: FuzzFailure,
+}
+
+impl ReplayFile {
+    pub fn new(
+        contract_identifier: String,
+        function: String,
+        sender: String,
+        counterexample: &Counterexample,
+    ) -> ReplayFile {
+        ReplayFile {
+            contract_identifier,
+            function,
+            sender,
+            arguments: counterexample
+                .arguments
+                .iter()
+                .map(|argument| argument.serialize())
+                .collect(),
+            failure: counterexample.failure.clone(),
+        }
+    }
+
+    /// Read back the arguments, checking them against the function's parameter types.
+    pub fn decode_arguments(&self, argument_types: &[TypeSignature]) -> Result<Vec<Value>, String> {
+        if self.arguments.len() != argument_types.len() {
+            return Err(format!(
+                "Expected {} arguments, found {}",
+                argument_types.len(),
+                self.arguments.len()
+            ));
+        }
+        self.arguments
+            .iter()
+            .zip(argument_types.iter())
+            .map(|(argument, argument_type)| {
+                Value::try_deserialize_hex(argument, argument_type)
+                    .map_err(|e| format!("Bad argument {}: {}", argument, e))
+            })
+            .collect()
+    }
+}
+
+/// Whether random values of `t` can be generated: values of trait types must be contracts
+/// implementing the trait, which aren't generated.
+pub fn can_generate(t: &TypeSignature) -> bool {
+    match t {
+        TypeSignature::TraitReferenceType(_) | TypeSignature::NoType => false,
+        TypeSignature::SequenceType(SequenceSubtype::ListType(list_type)) => {
+            can_generate(list_type.get_list_item_type())
+        }
+        TypeSignature::TupleType(tuple_type) => {
+            tuple_type.get_type_map().values().all(can_generate)
+        }
+        TypeSignature::OptionalType(t) => can_generate(t),
+        TypeSignature::ResponseType(v) => can_generate(&v.0) && can_generate(&v.1),
+        _ => true,
+    }
+}
+
+/// Generates random values of a type.  Principals are picked from `principals` about half of
+/// the time, so that the sender and other known accounts show up in arguments.
+pub struct ValueGenerator {
+    principals: Vec<PrincipalData>,
+    address_version: u8,
+}
+
+impl ValueGenerator {
+    pub fn new(principals: Vec<PrincipalData>, address_version: u8) -> ValueGenerator {
+        ValueGenerator {
+            principals,
+            address_version,
+        }
+    }
+
+    /// A length up to `max_len`, often either extreme.
+    fn random_length<R: Rng>(rng: &mut R, max_len: u32) -> usize {
+        let len = match rng.gen_range(0, 4) {
+            0 => 0,
+            1 => max_len,
+            _ => rng.gen_range(0, max_len + 1),
+        };
+        len as usize
+    }
+
+    fn random_int<R: Rng>(rng: &mut R) -> i128 {
+        match rng.gen_range(0, 4) {
+            0 => *[0, 1, -1, i128::MAX, i128::MIN]
+                .get(rng.gen_range(0, 5))
+                .unwrap(),
+            1 => rng.gen_range(-100, 101),
+            2 => i128::from(rng.gen::<i64>()),
+            _ => rng.gen(),
+        }
+    }
+
+    fn random_uint<R: Rng>(rng: &mut R) -> u128 {
+        match rng.gen_range(0, 4) {
+            0 => *[0, 1, u128::MAX].get(rng.gen_range(0, 3)).unwrap(),
+            1 => rng.gen_range(0, 101),
+            2 => u128::from(rng.gen::<u64>()),
+            _ => rng.gen(),
+        }
+    }
+
+    fn random_principal<R: Rng>(&self, rng: &mut R) -> PrincipalData {
+        if !self.principals.is_empty() && rng.gen() {
+            self.principals[rng.gen_range(0, self.principals.len())].clone()
+        } else {
+            PrincipalData::Standard(StandardPrincipalData(self.address_version, rng.gen()))
+        }
+    }
+
+    /// A random value of type `t`, which must satisfy `can_generate`.
+    pub fn random_value<R: Rng>(&self, rng: &mut R, t: &TypeSignature) -> Value {
+        match t {
+            TypeSignature::IntType => Value::Int(Self::random_int(rng)),
+            TypeSignature::UIntType => Value::UInt(Self::random_uint(rng)),
+            TypeSignature::BoolType => Value::Bool(rng.gen()),
+            TypeSignature::PrincipalType => Value::Principal(self.random_principal(rng)),
+            TypeSignature::SequenceType(SequenceSubtype::BufferType(len)) => {
+                let len = Self::random_length(rng, u32::from(len));
+                let data = (0..len).map(|_| rng.gen()).collect();
+                Value::Sequence(SequenceData::Buffer(BuffData { data }))
+            }
+            TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::ASCII(len))) => {
+                let len = Self::random_length(rng, u32::from(len));
+                // printable characters, and the odd newline or tab
+                let data = (0..len)
+                    .map(|_| match rng.gen_range(0, 20) {
+                        0 => b'\n',
+                        1 => b'\t',
+                        _ => rng.gen_range(0x20, 0x7f),
+                    })
+                    .collect();
+                Value::Sequence(SequenceData::String(CharType::ASCII(ASCIIData { data })))
+            }
+            TypeSignature::SequenceType(SequenceSubtype::StringType(StringSubtype::UTF8(len))) => {
+                let len = Self::random_length(rng, u32::from(len));
+                let data = (0..len)
+                    .map(|_| {
+                        let c: char = if rng.gen() {
+                            rng.gen_range(0x20u8, 0x7f).into()
+                        } else {
+                            rng.gen()
+                        };
+                        c.to_string().into_bytes()
+                    })
+                    .collect();
+                Value::Sequence(SequenceData::String(CharType::UTF8(UTF8Data { data })))
+            }
+            TypeSignature::SequenceType(SequenceSubtype::ListType(list_type)) => {
+                let len = Self::random_length(rng, list_type.get_max_len());
+                let data = (0..len)
+                    .map(|_| self.random_value(rng, list_type.get_list_item_type()))
+                    .collect();
+                Value::list_with_type(data, list_type.clone())
+                    .expect("FATAL: generated a list which its type does not admit")
+            }
+            TypeSignature::TupleType(tuple_type) => {
+                let data = tuple_type
+                    .get_type_map()
+                    .iter()
+                    .map(|(name, t)| (name.clone(), self.random_value(rng, t)))
+                    .collect();
+                Value::Tuple(
+                    TupleData::from_data_typed(data, tuple_type)
+                        .expect("FATAL: generated a tuple which its type does not admit"),
+                )
+            }
+            TypeSignature::OptionalType(t) => {
+                if rng.gen_range(0, 4) == 0 {
+                    Value::none()
+                } else {
+                    Value::Optional(OptionalData {
+                        data: Some(Box::new(self.random_value(rng, t))),
+                    })
+                }
+            }
+            TypeSignature::ResponseType(v) => {
+                let committed = rng.gen();
+                let t = if committed { &v.0 } else { &v.1 };
+                Value::Response(ResponseData {
+                    committed,
+                    data: Box::new(self.random_value(rng, t)),
+                })
+            }
+            TypeSignature::NoType | TypeSignature::TraitReferenceType(_) => {
+                panic!("Cannot generate values of type {}", t)
+            }
+        }
+    }
+}
+
+fn list_with_type(data: Vec<Value>, type_signature: &ListTypeData) -> Option<Value> {
+    Value::list_with_type(data, type_signature.clone()).ok()
+}
+
+fn tuple_with_type(data: &TupleData, type_signature: &TupleTypeSignature) -> Option<Value> {
+    let data = data
+        .data_map
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    TupleData::from_data_typed(data, type_signature)
+        .ok()
+        .map(Value::Tuple)
+}
+
+/// Shorter versions of a sequence: empty, its first half, and without its last element.
+fn shorter<T: Clone>(data: &[T]) -> Vec<Vec<T>> {
+    let mut candidates = vec![];
+    if !data.is_empty() {
+        candidates.push(vec![]);
+    }
+    if data.len() > 2 {
+        candidates.push(data[..data.len() / 2].to_vec());
+    }
+    if data.len() > 1 {
+        candidates.push(data[..data.len() - 1].to_vec());
+    }
+    candidates
+}
+
+/// Values of the same type as `value`, but simpler, simplest first.  Shrinking eventually runs
+/// out of candidates, since each candidate is strictly simpler than `value`.
+pub fn shrink_value(value: &Value) -> Vec<Value> {
+    let mut candidates = vec![];
+    match value {
+        Value::Int(i) => {
+            if *i != 0 {
+                candidates.push(Value::Int(0));
+                if i / 2 != 0 {
+                    candidates.push(Value::Int(i / 2));
+                }
+                if (i - i.signum()) != 0 && (i - i.signum()) != i / 2 {
+                    candidates.push(Value::Int(i - i.signum()));
+                }
+            }
+        }
+        Value::UInt(u) => {
+            if *u != 0 {
+                candidates.push(Value::UInt(0));
+                if u / 2 != 0 {
+                    candidates.push(Value::UInt(u / 2));
+                }
+                if u - 1 != 0 && u - 1 != u / 2 {
+                    candidates.push(Value::UInt(u - 1));
+                }
+            }
+        }
+        Value::Bool(true) => candidates.push(Value::Bool(false)),
+        Value::Bool(false) | Value::Principal(_) => {}
+        Value::Sequence(SequenceData::Buffer(buff)) => {
+            for data in shorter(&buff.data) {
+                candidates.push(Value::Sequence(SequenceData::Buffer(BuffData { data })));
+            }
+            if let Some(i) = buff.data.iter().position(|b| *b != 0) {
+                let mut data = buff.data.clone();
+                data[i] = 0;
+                candidates.push(Value::Sequence(SequenceData::Buffer(BuffData { data })));
+            }
+        }
+        Value::Sequence(SequenceData::String(CharType::ASCII(string))) => {
+            for data in shorter(&string.data) {
+                candidates.push(Value::Sequence(SequenceData::String(CharType::ASCII(
+                    ASCIIData { data },
+                ))));
+            }
+            if let Some(i) = string.data.iter().position(|c| *c != b'a') {
+                let mut data = string.data.clone();
+                data[i] = b'a';
+                candidates.push(Value::Sequence(SequenceData::String(CharType::ASCII(
+                    ASCIIData { data },
+                ))));
+            }
+        }
+        Value::Sequence(SequenceData::String(CharType::UTF8(string))) => {
+            for data in shorter(&string.data) {
+                candidates.push(Value::Sequence(SequenceData::String(CharType::UTF8(
+                    UTF8Data { data },
+                ))));
+            }
+            if let Some(i) = string.data.iter().position(|c| c.as_slice() != b"a") {
+                let mut data = string.data.clone();
+                data[i] = b"a".to_vec();
+                candidates.push(Value::Sequence(SequenceData::String(CharType::UTF8(
+                    UTF8Data { data },
+                ))));
+            }
+        }
+        Value::Sequence(SequenceData::List(ListData {
+            data,
+            type_signature,
+        })) => {
+            for shorter_data in shorter(data) {
+                candidates.extend(list_with_type(shorter_data, type_signature));
+            }
+            // without each element
+            if data.len() > 1 {
+                for i in 0..(data.len() - 1) {
+                    let mut fewer = data.clone();
+                    fewer.remove(i);
+                    candidates.extend(list_with_type(fewer, type_signature));
+                }
+            }
+            for (i, item) in data.iter().enumerate() {
+                for simpler in shrink_value(item) {
+                    let mut simpler_data = data.clone();
+                    simpler_data[i] = simpler;
+                    candidates.extend(list_with_type(simpler_data, type_signature));
+                }
+            }
+        }
+        Value::Tuple(tuple) => {
+            for (name, field) in tuple.data_map.iter() {
+                for simpler in shrink_value(field) {
+                    let mut simpler_tuple = tuple.clone();
+                    simpler_tuple.data_map.insert(name.clone(), simpler);
+                    candidates.extend(tuple_with_type(&simpler_tuple, &tuple.type_signature));
+                }
+            }
+        }
+        Value::Optional(OptionalData { data: Some(inner) }) => {
+            candidates.push(Value::none());
+            for simpler in shrink_value(inner) {
+                candidates.push(Value::Optional(OptionalData {
+                    data: Some(Box::new(simpler)),
+                }));
+            }
+        }
+        Value::Optional(OptionalData { data: None }) => {}
+        Value::Response(ResponseData { committed, data }) => {
+            for simpler in shrink_value(data) {
+                candidates.push(Value::Response(ResponseData {
+                    committed: *committed,
+                    data: Box::new(simpler),
+                }));
+            }
+        }
+    }
+    candidates
+}
+
+/// Greedily replace arguments with simpler ones, for as long as `fails` still holds, trying at
+/// most `max_attempts` candidates.
+pub fn shrink_arguments<F>(
+    mut arguments: Vec<Value>,
+    max_attempts: usize,
+    mut fails: F,
+) -> Vec<Value>
+where
+    F: FnMut(&[Value]) -> bool,
+{
+    let mut attempts = 0;
+    loop {
+        let mut shrunk = false;
+        for i in 0..arguments.len() {
+            for candidate in shrink_value(&arguments[i]) {
+                if attempts >= max_attempts {
+                    return arguments;
+                }
+                attempts += 1;
+
+                let mut trial = arguments.clone();
+                trial[i] = candidate;
+                if fails(&trial) {
+                    arguments = trial;
+                    shrunk = true;
+                    break;
+                }
+            }
+        }
+        if !shrunk {
+            return arguments;
+        }
+    }
+}
+
+/// Call a function with `runs` sets of random arguments, stopping at the first which fails.
+/// The failing arguments are shrunk to a minimal set which fails in the same way.
+pub fn fuzz<R, F>(
+    rng: &mut R,
+    generator: &ValueGenerator,
+    argument_types: &[TypeSignature],
+    runs: u32,
+    mut call: F,
+) -> Option<Counterexample>
+where
+    R: Rng,
+    F: FnMut(&[Value]) -> Option<FuzzFailure>,
+{
+    for run in 0..runs {
+        let arguments: Vec<_> = argument_types
+            .iter()
+            .map(|t| generator.random_value(rng, t))
+            .collect();
+        let failure = match call(&arguments) {
+            Some(failure) => failure,
+            None => continue,
+        };
+
+        let arguments = shrink_arguments(arguments, MAX_SHRINK_ATTEMPTS, |arguments| {
+            call(arguments).map_or(false, |other| failure.same_kind(&other))
+        });
+        // report the failure of the shrunk arguments, rather than of the original ones
+        let failure = call(&arguments).unwrap_or(failure);
+        return Some(Counterexample {
+            run,
+            arguments,
+            failure,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use crate::vm::types::QualifiedContractIdentifier;
+
+    fn generator() -> ValueGenerator {
+        ValueGenerator::new(
+            vec![PrincipalData::Standard(StandardPrincipalData::transient())],
+            26,
+        )
+    }
+
+    fn parse_type(repr: &str) -> TypeSignature {
+        use crate::vm::ast::parse;
+        let expressions = parse(&QualifiedContractIdentifier::transient(), repr).unwrap();
+        TypeSignature::parse_type_repr(&expressions[0], &mut ()).unwrap()
+    }
+
+    #[test]
+    fn test_random_values_are_admitted() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let generator = generator();
+        for repr in [
+            "int",
+            "(buff 3)",
+            "(string-ascii 4)",
+            "(string-utf8 4)",
+            "(list 5 (optional (tuple (a uint) (b (response principal bool)))))",
+            "(list 2 (list 3 (string-utf8 2)))",
+        ]
+        .iter()
+        {
+            let t = parse_type(repr);
+            assert!(can_generate(&t));
+            let mut saw_empty = false;
+            for _ in 0..200 {
+                let value = generator.random_value(&mut rng, &t);
+                assert!(t.admits(&value).unwrap(), "{} does not admit {}", t, value);
+                if let Value::Sequence(ref data) = value {
+                    saw_empty |= data.len() == 0;
+                }
+                // values round-trip through the serialization of replay files
+                assert_eq!(
+                    Value::try_deserialize_hex(&value.serialize(), &t).unwrap(),
+                    value
+                );
+            }
+            assert!(saw_empty || *repr == "int");
+        }
+
+        let trait_type = TypeSignature::TraitReferenceType(crate::vm::types::TraitIdentifier {
+            name: "t".into(),
+            contract_identifier: QualifiedContractIdentifier::transient(),
+        });
+        assert!(!can_generate(&TypeSignature::OptionalType(Box::new(
+            trait_type
+        ))));
+    }
+
+    #[test]
+    fn test_shrink_value() {
+        assert_eq!(
+            shrink_value(&Value::Int(-10)),
+            vec![Value::Int(0), Value::Int(-5), Value::Int(-9)]
+        );
+        assert_eq!(shrink_value(&Value::UInt(1)), vec![Value::UInt(0)]);
+        assert!(shrink_value(&Value::Int(0)).is_empty());
+
+        let t = parse_type("(list 4 (optional int))");
+        let list = Value::list_with_type(
+            vec![Value::some(Value::Int(3)).unwrap(), Value::none()],
+            match t {
+                TypeSignature::SequenceType(SequenceSubtype::ListType(ref list_type)) => {
+                    list_type.clone()
+                }
+                _ => panic!(),
+            },
+        )
+        .unwrap();
+        let candidates = shrink_value(&list);
+        assert_eq!(format!("{}", candidates[0]), "()");
+        for candidate in candidates.iter() {
+            assert!(t.admits(candidate).unwrap());
+            assert_ne!(candidate, &list);
+        }
+        assert!(candidates
+            .iter()
+            .any(|candidate| format!("{}", candidate) == "(none none)"));
+    }
+
+    #[test]
+    fn test_fuzz_and_shrink() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let argument_types = vec![parse_type("uint"), parse_type("(list 10 uint)")];
+
+        // fails whenever the list holds an element greater than the first argument
+        let counterexample = fuzz(&mut rng, &generator(), &argument_types, 100, |arguments| {
+            let bound = match arguments[0] {
+                Value::UInt(bound) => bound,
+                _ => panic!(),
+            };
+            match arguments[1] {
+                Value::Sequence(SequenceData::List(ref list)) => list
+                    .data
+                    .iter()
+                    .find(|item| match item {
+                        Value::UInt(item) => *item > bound,
+                        _ => panic!(),
+                    })
+                    .map(|item| FuzzFailure::RuntimeError {
+                        error: format!("{} > u{}", item, bound),
+                    }),
+                _ => panic!(),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(format!("{}", counterexample.arguments[0]), "u0");
+        assert_eq!(format!("{}", counterexample.arguments[1]), "(u1)");
+        assert_eq!(
+            counterexample.failure,
+            FuzzFailure::RuntimeError {
+                error: "u1 > u0".into()
+            }
+        );
+
+        // never fails
+        assert!(fuzz(&mut rng, &generator(), &argument_types, 20, |_| None).is_none());
+    }
+
+    #[test]
+    fn test_replay_file() {
+        let t = parse_type("(optional (buff 2))");
+        let counterexample = Counterexample {
+            run: 3,
+            arguments: vec![Value::some(Value::buff_from(vec![1, 2]).unwrap()).unwrap()],
+            failure: FuzzFailure::InvariantViolated {
+                invariant: "total-is-positive".into(),
+                result: "false".into(),
+            },
+        };
+        let replay = ReplayFile::new(
+            "S1G2081040G2081040G2081040G208105NK8PE5.tokens".into(),
+            "transfer".into(),
+            "S1G2081040G2081040G2081040G208105NK8PE5".into(),
+            &counterexample,
+        );
+        let json = serde_json::to_string(&replay).unwrap();
+        assert!(json.contains("\"kind\":\"invariant_violated\""));
+        let replay: ReplayFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            replay.decode_arguments(&[t.clone()]).unwrap(),
+            counterexample.arguments
+        );
+        assert!(replay.decode_arguments(&[t.clone(), t]).is_err());
+        assert!(replay.decode_arguments(&[TypeSignature::IntType]).is_err());
+    }
+}
@@ -0,0 +1,987 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A language server for Clarity, speaking the Language Server Protocol over stdio.
+//!
+//! Every `.clar` file in the workspace is a contract named after the file, deployed by the
+//! transient principal, so `(contract-call? .other-file ...)` resolves to `other-file.clar`.
+//! Documents are checked with the same parser and analysis passes as `clarity-cli check`,
+//! after analyzing the workspace contracts which they reference.
+
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::Value as JsonValue;
+
+use crate::vm::analysis::lint::run_lints;
+use crate::vm::analysis::{run_analysis, AnalysisDatabase, ContractAnalysis};
+use crate::vm::ast::parser::cst::PositionMap;
+use crate::vm::ast::{build_ast_with_rules, ASTRules};
+use crate::vm::costs::LimitedCostTracker;
+use crate::vm::database::MemoryBackingStore;
+use crate::vm::diagnostic::{Diagnostic, Level};
+use crate::vm::docs::{get_input_type_string, get_output_type_string, make_markdown_reference};
+use crate::vm::functions::define::DefineFunctions;
+use crate::vm::functions::NativeFunctions;
+use crate::vm::representations::{ContractName, Span, SymbolicExpression};
+use crate::vm::types::{
+    FunctionType, PrincipalData, QualifiedContractIdentifier, StandardPrincipalData, Value,
+};
+use crate::vm::variables::NativeVariables;
+use crate::vm::ClarityVersion;
+
+#[cfg(test)]
+mod tests;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const COMPLETION_KIND_FUNCTION: u32 = 3;
+const COMPLETION_KIND_VARIABLE: u32 = 6;
+const COMPLETION_KIND_KEYWORD: u32 = 14;
+const COMPLETION_KIND_CONSTANT: u32 = 21;
+
+/// Read one message, returning `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<JsonValue>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+    })?;
+    let mut body = vec![0; content_length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &JsonValue) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serve requests read from `input` until the client asks the server to exit, returning
+/// the process exit code.
+pub fn run<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<i32> {
+    let mut server = Server::new(output);
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(1),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                warn!("Failed to read LSP message: {}", e);
+                server.send(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": e.to_string() }
+                }))?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(exit_code) = server.handle_message(&message)? {
+            return Ok(exit_code);
+        }
+    }
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) = u8::from_str_radix(&path[i + 1..i + 3], 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn lsp_position(line: u32, column: u32) -> JsonValue {
+    json!({ "line": line.saturating_sub(1), "character": column.saturating_sub(1) })
+}
+
+/// Convert a span, whose lines and columns start at 1 and whose end is inclusive, to an LSP
+/// range, whose lines and characters start at 0 and whose end is exclusive.
+fn lsp_range(span: &Span) -> JsonValue {
+    json!({
+        "start": lsp_position(span.start_line, span.start_column),
+        "end": lsp_position(span.end_line, span.end_column + 1),
+    })
+}
+
+fn contains(span: &Span, line: u32, column: u32) -> bool {
+    (span.start_line, span.start_column) <= (line, column)
+        && (line, column) <= (span.end_line, span.end_column)
+}
+
+/// The text of the source covered by `span`.
+fn span_text(text: &str, span: &Span) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = vec![];
+    for line in span.start_line..=span.end_line {
+        let source = match lines.get(line.saturating_sub(1) as usize) {
+            Some(source) => *source,
+            None => break,
+        };
+        let start = if line == span.start_line {
+            span.start_column.saturating_sub(1) as usize
+        } else {
+            0
+        };
+        let end = if line == span.end_line {
+            (span.end_column as usize).min(source.len())
+        } else {
+            source.len()
+        };
+        out.push(source.get(start..end).unwrap_or(""));
+    }
+    out.join("\n")
+}
+
+/// The `;;` comment lines directly above `line`.
+fn doc_comment(text: &str, line: u32) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut comment = vec![];
+    let mut ix = line.saturating_sub(1) as usize;
+    while ix > 0 {
+        ix -= 1;
+        match lines.get(ix).map(|l| l.trim()) {
+            Some(l) if l.starts_with(";;") => comment.push(l.trim_start_matches(';').trim()),
+            _ => break,
+        }
+    }
+    if comment.is_empty() {
+        return None;
+    }
+    comment.reverse();
+    Some(comment.join("\n"))
+}
+
+fn function_name(expr: &SymbolicExpression) -> Option<&str> {
+    expr.match_list()
+        .and_then(|list| list.first())
+        .and_then(|first| first.match_atom())
+        .map(|name| name.as_str())
+}
+
+fn contract_reference(expr: &SymbolicExpression) -> Option<&QualifiedContractIdentifier> {
+    match expr
+        .match_literal_value()
+        .or_else(|| expr.match_atom_value())
+    {
+        Some(Value::Principal(PrincipalData::Contract(contract_identifier))) => {
+            Some(contract_identifier)
+        }
+        _ => expr
+            .match_field()
+            .map(|trait_identifier| &trait_identifier.contract_identifier),
+    }
+}
+
+fn collect_contract_references(
+    expr: &SymbolicExpression,
+    references: &mut Vec<QualifiedContractIdentifier>,
+) {
+    if let Some(contract_identifier) = contract_reference(expr) {
+        if !references.contains(contract_identifier) {
+            references.push(contract_identifier.clone());
+        }
+    }
+    if let Some(list) = expr.match_list() {
+        for child in list.iter() {
+            collect_contract_references(child, references);
+        }
+    }
+}
+
+/// The expression naming what a top-level `define-*` expression defines.
+fn definition_name(expr: &SymbolicExpression) -> Option<&SymbolicExpression> {
+    DefineFunctions::lookup_by_name(function_name(expr)?)?;
+    let name = expr.match_list()?.get(1)?;
+    match name.match_list() {
+        // functions are defined by a signature, `(name (arg type) ...)`
+        Some(signature) => signature.first(),
+        None => Some(name),
+    }
+}
+
+/// The names of `(name value)` pairs, such as `let` bindings or function arguments.
+fn pair_names(pairs: &[SymbolicExpression]) -> Vec<&SymbolicExpression> {
+    pairs
+        .iter()
+        .filter_map(|pair| pair.match_list().and_then(|pair| pair.first()))
+        .filter(|name| name.match_atom().is_some())
+        .collect()
+}
+
+/// The names bound by `expr` for its own body: `let` bindings, function arguments, or the
+/// bindings of a `match`.
+fn local_bindings(expr: &SymbolicExpression) -> Vec<&SymbolicExpression> {
+    let list = match expr.match_list() {
+        Some(list) => list,
+        None => return vec![],
+    };
+    match function_name(expr) {
+        Some("let") => list
+            .get(1)
+            .and_then(|bindings| bindings.match_list())
+            .map(pair_names)
+            .unwrap_or_default(),
+        Some("define-public") | Some("define-private") | Some("define-read-only") => list
+            .get(1)
+            .and_then(|signature| signature.match_list())
+            .map(|signature| pair_names(signature.get(1..).unwrap_or(&[])))
+            .unwrap_or_default(),
+        Some("match") if list.len() == 5 => vec![&list[2]],
+        Some("match") if list.len() == 6 => vec![&list[2], &list[4]],
+        _ => vec![],
+    }
+}
+
+struct Document {
+    contract_identifier: QualifiedContractIdentifier,
+    text: String,
+    /// Whether the editor has the document open, rather than it being read from disk.
+    open: bool,
+    /// The expressions of the last version of the document which parsed, used to complete
+    /// names while the document is being edited.
+    last_parsed: Option<(Vec<SymbolicExpression>, PositionMap)>,
+}
+
+/// The result of checking one document.
+struct CheckedContract {
+    expressions: Vec<SymbolicExpression>,
+    analysis: Option<ContractAnalysis>,
+    positions: Option<PositionMap>,
+    /// The diagnostics, with true source positions, and the lint which reported each one.
+    diagnostics: Vec<(Diagnostic, Option<String>)>,
+}
+
+impl CheckedContract {
+    fn span(&self, expr: &SymbolicExpression) -> Span {
+        match self.positions {
+            Some(ref positions) => positions.span(&expr.span),
+            None => expr.span.clone(),
+        }
+    }
+
+    /// The expressions enclosing a position, outermost first.
+    fn enclosing(&self, line: u32, column: u32) -> Vec<&SymbolicExpression> {
+        let mut chain = vec![];
+        let mut candidates: &[SymbolicExpression] = &self.expressions;
+        loop {
+            let found = candidates
+                .iter()
+                .find(|expr| contains(&self.span(expr), line, column));
+            match found {
+                Some(expr) => {
+                    chain.push(expr);
+                    candidates = match expr.match_list() {
+                        Some(list) => list,
+                        None => break,
+                    };
+                }
+                None => break,
+            }
+        }
+        chain
+    }
+
+    fn top_level_definition(
+        &self,
+        name: &str,
+    ) -> Option<(&SymbolicExpression, &SymbolicExpression)> {
+        self.expressions.iter().find_map(|expr| {
+            definition_name(expr)
+                .filter(|defined| defined.match_atom().map(|n| n.as_str()) == Some(name))
+                .map(|defined| (expr, defined))
+        })
+    }
+
+    /// The definition of the name at the end of `chain`, looking through the enclosing
+    /// expressions' local bindings before the contract's top-level definitions.
+    fn resolve<'a>(
+        &'a self,
+        chain: &[&'a SymbolicExpression],
+        name: &str,
+    ) -> Option<&'a SymbolicExpression> {
+        for expr in chain.iter().rev() {
+            let binding = local_bindings(expr)
+                .into_iter()
+                .find(|binding| binding.match_atom().map(|n| n.as_str()) == Some(name));
+            if binding.is_some() {
+                return binding;
+            }
+        }
+        self.top_level_definition(name).map(|(_, defined)| defined)
+    }
+
+    fn function_type(&self, name: &str) -> Option<&FunctionType> {
+        let analysis = self.analysis.as_ref()?;
+        analysis
+            .get_public_function_type(name)
+            .or_else(|| analysis.get_read_only_function_type(name))
+            .or_else(|| analysis.get_private_function(name))
+    }
+
+    /// Hover text for a name defined at the top level of this contract.
+    fn definition_markdown(&self, text: &str, name: &str) -> Option<String> {
+        let (define, defined) = self.top_level_definition(name)?;
+        let define_span = self.span(define);
+        let mut markdown = match (
+            function_name(define),
+            define.match_list().and_then(|l| l.get(1)),
+        ) {
+            (Some(kind @ "define-public"), Some(signature))
+            | (Some(kind @ "define-private"), Some(signature))
+            | (Some(kind @ "define-read-only"), Some(signature)) => {
+                let mut markdown = format!(
+                    "```clarity\n({} {})\n```",
+                    kind,
+                    span_text(text, &self.span(signature))
+                );
+                if let Some(function_type) = self.function_type(name) {
+                    markdown.push_str(&format!(
+                        "\n\n`{}` -> `{}`",
+                        get_input_type_string(function_type),
+                        get_output_type_string(function_type)
+                    ));
+                }
+                markdown
+            }
+            _ => format!("```clarity\n{}\n```", span_text(text, &define_span)),
+        };
+        if defined.match_atom().is_some() {
+            if let Some(comment) = doc_comment(text, define_span.start_line) {
+                markdown.push_str(&format!("\n\n{}", comment));
+            }
+        }
+        Some(markdown)
+    }
+}
+
+pub struct Server<W: Write> {
+    output: W,
+    /// Every contract in the workspace, by URI.
+    documents: BTreeMap<String, Document>,
+    deployer: StandardPrincipalData,
+    /// Whether to report the warnings of the opt-in lints along with analysis errors.
+    lint: bool,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(output: W) -> Server<W> {
+        Server {
+            output,
+            documents: BTreeMap::new(),
+            deployer: StandardPrincipalData::transient(),
+            lint: false,
+            shutdown: false,
+        }
+    }
+
+    fn send(&mut self, message: JsonValue) -> io::Result<()> {
+        write_message(&mut self.output, &message)
+    }
+
+    fn contract_identifier_for(&self, uri: &str) -> QualifiedContractIdentifier {
+        let stem = uri
+            .rsplit('/')
+            .next()
+            .unwrap_or(uri)
+            .trim_end_matches(".clar")
+            .to_string();
+        let name = ContractName::try_from(stem)
+            .unwrap_or_else(|_| ContractName::try_from("contract".to_string()).unwrap());
+        QualifiedContractIdentifier::new(self.deployer.clone(), name)
+    }
+
+    fn set_document(&mut self, uri: &str, text: String, open: bool) {
+        let contract_identifier = self.contract_identifier_for(uri);
+        let last_parsed = self.documents.remove(uri).and_then(|doc| doc.last_parsed);
+        self.documents.insert(
+            uri.to_string(),
+            Document {
+                contract_identifier,
+                text,
+                open,
+                last_parsed,
+            },
+        );
+    }
+
+    fn load_workspace(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read workspace directory {:?}: {}", dir, e);
+                return;
+            }
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                self.load_workspace(&path);
+            } else if path.extension().map(|ext| ext == "clar").unwrap_or(false) {
+                if let Ok(text) = fs::read_to_string(&path) {
+                    self.set_document(&path_to_uri(&path), text, false);
+                }
+            }
+        }
+    }
+
+    fn uri_of(&self, contract_identifier: &QualifiedContractIdentifier) -> Option<&str> {
+        self.documents
+            .iter()
+            .find(|(_, doc)| &doc.contract_identifier == contract_identifier)
+            .map(|(uri, _)| uri.as_str())
+    }
+
+    /// Analyze a workspace contract and the contracts it references, saving the analyses.
+    /// Failures are not reported here: they are reported on the dependency's own document.
+    fn analyze_dependency(
+        &self,
+        analysis_db: &mut AnalysisDatabase,
+        contract_identifier: &QualifiedContractIdentifier,
+        visiting: &mut HashSet<QualifiedContractIdentifier>,
+    ) {
+        if visiting.contains(contract_identifier) || analysis_db.has_contract(contract_identifier) {
+            return;
+        }
+        let document = match self.uri_of(contract_identifier) {
+            Some(uri) => &self.documents[uri],
+            None => return,
+        };
+        visiting.insert(contract_identifier.clone());
+        let mut expressions = match build_ast_with_rules(
+            contract_identifier,
+            &document.text,
+            &mut (),
+            ASTRules::PrecheckSize,
+        ) {
+            Ok(ast) => ast.expressions,
+            Err(_) => return,
+        };
+        let mut references = vec![];
+        for expr in expressions.iter() {
+            collect_contract_references(expr, &mut references);
+        }
+        for reference in references.iter() {
+            self.analyze_dependency(analysis_db, reference, visiting);
+        }
+        if let Err((e, _)) = run_analysis(
+            contract_identifier,
+            &mut expressions,
+            analysis_db,
+            true,
+            LimitedCostTracker::new_free(),
+            ClarityVersion::latest(),
+        ) {
+            debug!(
+                "Dependency {} failed analysis: {}",
+                contract_identifier, e.err
+            );
+        }
+    }
+
+    fn check(&self, uri: &str) -> Option<CheckedContract> {
+        let document = self.documents.get(uri)?;
+        let contract_identifier = &document.contract_identifier;
+        let positions = PositionMap::new(&document.text).ok();
+        let mut diagnostics = vec![];
+
+        let ast = build_ast_with_rules(
+            contract_identifier,
+            &document.text,
+            &mut (),
+            ASTRules::PrecheckSize,
+        );
+        let mut expressions = match ast {
+            Ok(ast) => ast.expressions,
+            Err(e) => {
+                diagnostics.push((e.diagnostic, None));
+                return Some(CheckedContract {
+                    expressions: vec![],
+                    analysis: None,
+                    positions,
+                    diagnostics,
+                });
+            }
+        };
+
+        let mut store = MemoryBackingStore::new();
+        let mut analysis_db = store.as_analysis_db();
+        analysis_db.begin();
+        let mut visiting = HashSet::new();
+        visiting.insert(contract_identifier.clone());
+        let mut references = vec![];
+        for expr in expressions.iter() {
+            collect_contract_references(expr, &mut references);
+        }
+        for reference in references.iter() {
+            self.analyze_dependency(&mut analysis_db, reference, &mut visiting);
+        }
+
+        let analysis = match run_analysis(
+            contract_identifier,
+            &mut expressions,
+            &mut analysis_db,
+            false,
+            LimitedCostTracker::new_free(),
+            ClarityVersion::latest(),
+        ) {
+            Ok(analysis) => {
+                if self.lint {
+                    for warning in run_lints(&analysis) {
+                        diagnostics.push((warning.diagnostic, Some(warning.lint)));
+                    }
+                }
+                Some(analysis)
+            }
+            Err((e, _)) => {
+                diagnostics.push((e.diagnostic, None));
+                None
+            }
+        };
+
+        if let Some(ref positions) = positions {
+            for (diagnostic, _) in diagnostics.iter_mut() {
+                diagnostic.spans = diagnostic.spans.iter().map(|s| positions.span(s)).collect();
+            }
+        }
+
+        Some(CheckedContract {
+            expressions,
+            analysis,
+            positions,
+            diagnostics,
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let checked = match self.check(uri) {
+            Some(checked) => checked,
+            None => return Ok(()),
+        };
+        if let Some(document) = self.documents.get_mut(uri) {
+            if !checked.expressions.is_empty() {
+                if let Ok(positions) = PositionMap::new(&document.text) {
+                    document.last_parsed = Some((checked.expressions.clone(), positions));
+                }
+            }
+        }
+
+        let diagnostics: Vec<JsonValue> = checked
+            .diagnostics
+            .iter()
+            .map(|(diagnostic, lint)| {
+                let range = match diagnostic.spans.first() {
+                    Some(span) if span.start_line > 0 => lsp_range(span),
+                    _ => lsp_range(&Span {
+                        start_line: 1,
+                        start_column: 1,
+                        end_line: 1,
+                        end_column: 0,
+                    }),
+                };
+                let mut message = diagnostic.message.clone();
+                if let Some(ref suggestion) = diagnostic.suggestion {
+                    message.push_str(&format!("\n{}", suggestion));
+                }
+                let mut lsp_diagnostic = json!({
+                    "range": range,
+                    "severity": if diagnostic.level == Level::Error { 1 } else { 2 },
+                    "source": "clarity",
+                    "message": message,
+                });
+                if let Some(lint) = lint {
+                    lsp_diagnostic["code"] = json!(lint);
+                }
+                if diagnostic.spans.len() > 1 {
+                    lsp_diagnostic["relatedInformation"] = diagnostic.spans[1..]
+                        .iter()
+                        .map(|span| {
+                            json!({
+                                "location": { "uri": uri, "range": lsp_range(span) },
+                                "message": "also here",
+                            })
+                        })
+                        .collect();
+                }
+                lsp_diagnostic
+            })
+            .collect();
+
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    /// Re-check every open document, since a change to one contract can change the
+    /// diagnostics of the contracts which call it.
+    fn publish_all_diagnostics(&mut self) -> io::Result<()> {
+        let open: Vec<String> = self
+            .documents
+            .iter()
+            .filter(|(_, doc)| doc.open)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in open.iter() {
+            self.publish_diagnostics(uri)?;
+        }
+        Ok(())
+    }
+
+    /// The document and 1-based position named by `textDocument/*` request parameters.
+    fn text_document_position(params: &JsonValue) -> Option<(String, u32, u32)> {
+        let uri = params
+            .get("textDocument")?
+            .get("uri")?
+            .as_str()?
+            .to_string();
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_u64()? as u32;
+        let character = position.get("character")?.as_u64()? as u32;
+        Some((uri, line + 1, character + 1))
+    }
+
+    fn hover(&self, uri: &str, line: u32, column: u32) -> Option<JsonValue> {
+        let checked = self.check(uri)?;
+        let text = &self.documents.get(uri)?.text;
+        let chain = checked.enclosing(line, column);
+        let leaf = *chain.last()?;
+        let range = lsp_range(&checked.span(leaf));
+
+        let markdown = if let Some((callee, function)) = contract_call_target(&chain) {
+            let callee_uri = self.uri_of(callee)?;
+            let callee_checked = self.check(callee_uri)?;
+            callee_checked.definition_markdown(&self.documents[callee_uri].text, function)?
+        } else if let Some(name) = leaf.match_atom() {
+            let name = name.as_str();
+            if let Some(markdown) = make_markdown_reference(name) {
+                markdown
+            } else if let Some(markdown) = checked.definition_markdown(text, name) {
+                markdown
+            } else {
+                let variable_type = checked
+                    .analysis
+                    .as_ref()
+                    .and_then(|analysis| analysis.type_map.as_ref())
+                    .and_then(|type_map| type_map.get_type(leaf));
+                match variable_type {
+                    Some(variable_type) => format!("```clarity\n{}: {}\n```", name, variable_type),
+                    None => return None,
+                }
+            }
+        } else if let Some(contract_identifier) = contract_reference(leaf) {
+            format!("```clarity\n'{}\n```", contract_identifier)
+        } else {
+            return None;
+        };
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": markdown },
+            "range": range,
+        }))
+    }
+
+    fn definition(&self, uri: &str, line: u32, column: u32) -> Option<JsonValue> {
+        let checked = self.check(uri)?;
+        let chain = checked.enclosing(line, column);
+        let leaf = *chain.last()?;
+
+        if let Some((callee, function)) = contract_call_target(&chain) {
+            let callee_uri = self.uri_of(callee)?;
+            let callee_checked = self.check(callee_uri)?;
+            let (_, defined) = callee_checked.top_level_definition(function)?;
+            return Some(json!({
+                "uri": callee_uri,
+                "range": lsp_range(&callee_checked.span(defined)),
+            }));
+        }
+        if let Some(contract_identifier) = contract_reference(leaf) {
+            let callee_uri = self.uri_of(contract_identifier)?;
+            return Some(json!({
+                "uri": callee_uri,
+                "range": lsp_range(&Span {
+                    start_line: 1,
+                    start_column: 1,
+                    end_line: 1,
+                    end_column: 0,
+                }),
+            }));
+        }
+        let name = leaf.match_atom()?;
+        let defined = checked.resolve(&chain[..chain.len() - 1], name)?;
+        Some(json!({ "uri": uri, "range": lsp_range(&checked.span(defined)) }))
+    }
+
+    fn completion(&self, uri: &str, line: u32, column: u32) -> Option<JsonValue> {
+        let document = self.documents.get(uri)?;
+        let mut items = vec![];
+
+        for name in NativeFunctions::ALL_NAMES.iter() {
+            items.push(json!({
+                "label": name,
+                "kind": COMPLETION_KIND_FUNCTION,
+                "documentation": make_markdown_reference(name)
+                    .map(|value| json!({ "kind": "markdown", "value": value })),
+            }));
+        }
+        for name in DefineFunctions::ALL_NAMES.iter() {
+            items.push(json!({
+                "label": name,
+                "kind": COMPLETION_KIND_KEYWORD,
+                "documentation": make_markdown_reference(name)
+                    .map(|value| json!({ "kind": "markdown", "value": value })),
+            }));
+        }
+        for name in NativeVariables::ALL_NAMES.iter() {
+            items.push(json!({
+                "label": name,
+                "kind": COMPLETION_KIND_KEYWORD,
+                "documentation": make_markdown_reference(name)
+                    .map(|value| json!({ "kind": "markdown", "value": value })),
+            }));
+        }
+
+        // the document is usually mid-edit, so fall back to the last version which parsed
+        let checked = match document.last_parsed {
+            Some((ref expressions, ref positions)) => CheckedContract {
+                expressions: expressions.clone(),
+                analysis: None,
+                positions: Some(positions.clone()),
+                diagnostics: vec![],
+            },
+            None => self.check(uri)?,
+        };
+
+        for expr in checked.expressions.iter() {
+            let name = match definition_name(expr).and_then(|name| name.match_atom()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let kind = match function_name(expr) {
+                Some("define-public") | Some("define-private") | Some("define-read-only") => {
+                    COMPLETION_KIND_FUNCTION
+                }
+                Some("define-constant") => COMPLETION_KIND_CONSTANT,
+                _ => COMPLETION_KIND_VARIABLE,
+            };
+            items.push(json!({ "label": name.as_str(), "kind": kind }));
+        }
+        for expr in checked.enclosing(line, column) {
+            for binding in local_bindings(expr) {
+                if let Some(name) = binding.match_atom() {
+                    items.push(json!({ "label": name.as_str(), "kind": COMPLETION_KIND_VARIABLE }));
+                }
+            }
+        }
+
+        Some(json!({ "isIncomplete": false, "items": items }))
+    }
+
+    fn handle_request(
+        &mut self,
+        method: &str,
+        params: &JsonValue,
+    ) -> Result<JsonValue, (i64, String)> {
+        let invalid_params = || (INVALID_PARAMS, format!("Invalid params for {}", method));
+        match method {
+            "initialize" => {
+                if let Some(root) = params.get("rootUri").and_then(|uri| uri.as_str()) {
+                    if let Some(path) = uri_to_path(root) {
+                        self.load_workspace(&path);
+                    }
+                }
+                if let Some(lint) = params
+                    .get("initializationOptions")
+                    .and_then(|options| options.get("lint"))
+                    .and_then(|lint| lint.as_bool())
+                {
+                    self.lint = lint;
+                }
+                Ok(json!({
+                    "capabilities": {
+                        // full document sync
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "completionProvider": { "triggerCharacters": ["(", "."] },
+                    },
+                    "serverInfo": { "name": "clarity-lsp", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(JsonValue::Null)
+            }
+            "textDocument/hover" => {
+                let (uri, line, column) =
+                    Self::text_document_position(params).ok_or_else(invalid_params)?;
+                Ok(self.hover(&uri, line, column).unwrap_or(JsonValue::Null))
+            }
+            "textDocument/definition" => {
+                let (uri, line, column) =
+                    Self::text_document_position(params).ok_or_else(invalid_params)?;
+                Ok(self
+                    .definition(&uri, line, column)
+                    .unwrap_or(JsonValue::Null))
+            }
+            "textDocument/completion" => {
+                let (uri, line, column) =
+                    Self::text_document_position(params).ok_or_else(invalid_params)?;
+                Ok(self
+                    .completion(&uri, line, column)
+                    .unwrap_or(JsonValue::Null))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method {}", method))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &JsonValue) -> io::Result<()> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|doc| doc.get("uri"))
+            .and_then(|uri| uri.as_str())
+            .map(|uri| uri.to_string());
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.set_document(&uri, text.to_string(), true);
+                self.publish_all_diagnostics()
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // with full sync, the last change holds the whole document
+                let text = params
+                    .get("contentChanges")
+                    .and_then(|changes| changes.as_array())
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(|text| text.as_str());
+                if let Some(text) = text {
+                    self.set_document(&uri, text.to_string(), true);
+                }
+                self.publish_all_diagnostics()
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                // the file stays part of the workspace, as it is on disk
+                match uri_to_path(&uri).and_then(|path| fs::read_to_string(path).ok()) {
+                    Some(text) => self.set_document(&uri, text, false),
+                    None => {
+                        self.documents.remove(&uri);
+                    }
+                }
+                self.send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }))?;
+                self.publish_all_diagnostics()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Handle one message from the client, returning the exit code once the client has
+    /// asked the server to exit.
+    pub fn handle_message(&mut self, message: &JsonValue) -> io::Result<Option<i32>> {
+        let method = match message.get("method").and_then(|m| m.as_str()) {
+            Some(method) => method,
+            // a response to a request from the server, which it does not send
+            None => return Ok(None),
+        };
+        let params = message.get("params").cloned().unwrap_or(JsonValue::Null);
+
+        if method == "exit" {
+            return Ok(Some(if self.shutdown { 0 } else { 1 }));
+        }
+        match message.get("id") {
+            Some(id) => {
+                let response = match self.handle_request(method, &params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, error)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": error },
+                    }),
+                };
+                self.send(response)?;
+            }
+            None => self.handle_notification(method, &params)?,
+        }
+        Ok(None)
+    }
+}
+
+/// If the innermost expression of `chain` is the function name of a `contract-call?` to a
+/// known contract, the called contract and function.
+fn contract_call_target<'a>(
+    chain: &[&'a SymbolicExpression],
+) -> Option<(&'a QualifiedContractIdentifier, &'a str)> {
+    if chain.len() < 2 {
+        return None;
+    }
+    let leaf = chain[chain.len() - 1];
+    let call = chain[chain.len() - 2];
+    let list = call.match_list()?;
+    if function_name(call) != Some("contract-call?") || !std::ptr::eq(list.get(2)?, leaf) {
+        return None;
+    }
+    Some((contract_reference(&list[1])?, leaf.match_atom()?.as_str()))
+}
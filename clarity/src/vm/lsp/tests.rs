@@ -0,0 +1,329 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::io::Cursor;
+
+use serde_json::Value as JsonValue;
+
+use crate::vm::lsp::{path_to_uri, read_message, run, uri_to_path, write_message, Server};
+
+const CALLEE: &str = ";; Add one to a number.
+(define-read-only (increment (n int))
+  (+ n 1))
+";
+
+const CALLER: &str = "(define-constant limit 10)
+
+
+(define-public (bump (n int))
+  (let ((next (contract-call? .callee increment n)))
+    (asserts! (< next limit) (err u1))
+    (ok next)))
+";
+
+fn framed(messages: &[JsonValue]) -> Vec<u8> {
+    let mut input = vec![];
+    for message in messages {
+        write_message(&mut input, message).unwrap();
+    }
+    input
+}
+
+/// Take the messages which the server has sent so far.
+fn sent(server: &mut Server<Vec<u8>>) -> Vec<JsonValue> {
+    let mut output = Cursor::new(std::mem::take(&mut server.output));
+    let mut messages = vec![];
+    while let Some(message) = read_message(&mut output).unwrap() {
+        messages.push(message);
+    }
+    messages
+}
+
+fn request(server: &mut Server<Vec<u8>>, method: &str, params: JsonValue) -> JsonValue {
+    server
+        .handle_message(&json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params }))
+        .unwrap();
+    let mut messages = sent(server);
+    let response = messages.pop().unwrap();
+    assert_eq!(response["id"], 7);
+    response
+}
+
+fn notify(server: &mut Server<Vec<u8>>, method: &str, params: JsonValue) -> Vec<JsonValue> {
+    server
+        .handle_message(&json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+        .unwrap();
+    sent(server)
+}
+
+fn position(uri: &str, line: u32, character: u32) -> JsonValue {
+    json!({
+        "textDocument": { "uri": uri },
+        "position": { "line": line, "character": character },
+    })
+}
+
+/// A server whose workspace holds the callee and caller contracts, with the caller open.
+fn workspace(name: &str) -> (Server<Vec<u8>>, String, String) {
+    let dir = std::env::temp_dir().join(format!("clarity-lsp-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("callee.clar"), CALLEE).unwrap();
+    fs::write(dir.join("caller.clar"), CALLER).unwrap();
+
+    let mut server = Server::new(vec![]);
+    let response = request(
+        &mut server,
+        "initialize",
+        json!({ "rootUri": path_to_uri(&dir) }),
+    );
+    assert_eq!(response["result"]["capabilities"]["hoverProvider"], true);
+
+    let callee_uri = path_to_uri(&dir.join("callee.clar"));
+    let caller_uri = path_to_uri(&dir.join("caller.clar"));
+    let published = notify(
+        &mut server,
+        "textDocument/didOpen",
+        json!({ "textDocument": { "uri": caller_uri, "languageId": "clarity", "version": 1, "text": CALLER } }),
+    );
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+    (server, callee_uri, caller_uri)
+}
+
+#[test]
+fn test_lifecycle() {
+    let input = framed(&[
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "rootUri": null } }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/symbol", "params": {} }),
+        json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]);
+    let mut output = vec![];
+    assert_eq!(run(Cursor::new(input), &mut output).unwrap(), 0);
+
+    let mut output = Cursor::new(output);
+    let initialized = read_message(&mut output).unwrap().unwrap();
+    assert_eq!(initialized["id"], 1);
+    assert_eq!(initialized["result"]["serverInfo"]["name"], "clarity-lsp");
+    let unsupported = read_message(&mut output).unwrap().unwrap();
+    assert_eq!(unsupported["error"]["code"], -32601);
+    let shutdown = read_message(&mut output).unwrap().unwrap();
+    assert_eq!(shutdown["result"], JsonValue::Null);
+    assert!(read_message(&mut output).unwrap().is_none());
+
+    // exiting without a shutdown request is an error
+    let input = framed(&[json!({ "jsonrpc": "2.0", "method": "exit" })]);
+    assert_eq!(run(Cursor::new(input), vec![]).unwrap(), 1);
+}
+
+#[test]
+fn test_uris() {
+    let path = std::path::Path::new("/tmp/my contracts/a.clar");
+    let uri = path_to_uri(path);
+    assert_eq!(uri, "file:///tmp/my%20contracts/a.clar");
+    assert_eq!(uri_to_path(&uri).unwrap(), path);
+    assert!(uri_to_path("untitled:Untitled-1").is_none());
+}
+
+#[test]
+fn test_diagnostics() {
+    let (mut server, _, caller_uri) = workspace("diagnostics");
+
+    // the error follows blank lines, which the lexer's own positions do not count
+    let broken = CALLER.replace("(< next limit)", "(< next u10)");
+    let published = notify(
+        &mut server,
+        "textDocument/didChange",
+        json!({ "textDocument": { "uri": caller_uri, "version": 2 }, "contentChanges": [{ "text": broken }] }),
+    );
+    let diagnostics = published[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 5);
+
+    let published = notify(
+        &mut server,
+        "textDocument/didChange",
+        json!({ "textDocument": { "uri": caller_uri, "version": 3 }, "contentChanges": [{ "text": "(define-public (f)" }] }),
+    );
+    assert_eq!(
+        published[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let published = notify(
+        &mut server,
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": caller_uri } }),
+    );
+    assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+}
+
+#[test]
+fn test_hover() {
+    let (mut server, _, caller_uri) = workspace("hover");
+
+    // `asserts!`
+    let hover = request(
+        &mut server,
+        "textDocument/hover",
+        position(&caller_uri, 5, 6),
+    );
+    let text = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert!(text.starts_with("```clarity\n(asserts! bool-expr thrown-value)"));
+    assert_eq!(hover["result"]["range"]["start"]["character"], 5);
+
+    // `increment`, across the `contract-call?`
+    let hover = request(
+        &mut server,
+        "textDocument/hover",
+        position(&caller_uri, 4, 42),
+    );
+    let text = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert_eq!(
+        text,
+        "```clarity\n(define-read-only (increment (n int)))\n```\n\n`int` -> `int`\n\nAdd one to a number."
+    );
+
+    // `limit`
+    let hover = request(
+        &mut server,
+        "textDocument/hover",
+        position(&caller_uri, 5, 23),
+    );
+    let text = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert_eq!(text, "```clarity\n(define-constant limit 10)\n```");
+
+    // `next`, a let binding
+    let hover = request(
+        &mut server,
+        "textDocument/hover",
+        position(&caller_uri, 6, 9),
+    );
+    let text = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert_eq!(text, "```clarity\nnext: int\n```");
+
+    // whitespace
+    let hover = request(
+        &mut server,
+        "textDocument/hover",
+        position(&caller_uri, 1, 0),
+    );
+    assert_eq!(hover["result"], JsonValue::Null);
+}
+
+#[test]
+fn test_definition() {
+    let (mut server, callee_uri, caller_uri) = workspace("definition");
+
+    let definition = request(
+        &mut server,
+        "textDocument/definition",
+        position(&caller_uri, 4, 42),
+    );
+    assert_eq!(definition["result"]["uri"], callee_uri.as_str());
+    assert_eq!(
+        definition["result"]["range"],
+        json!({ "start": { "line": 1, "character": 19 }, "end": { "line": 1, "character": 28 } })
+    );
+
+    let definition = request(
+        &mut server,
+        "textDocument/definition",
+        position(&caller_uri, 4, 33),
+    );
+    assert_eq!(definition["result"]["uri"], callee_uri.as_str());
+
+    // `n`, the function argument
+    let definition = request(
+        &mut server,
+        "textDocument/definition",
+        position(&caller_uri, 4, 48),
+    );
+    assert_eq!(definition["result"]["uri"], caller_uri.as_str());
+    assert_eq!(
+        definition["result"]["range"]["start"],
+        json!({ "line": 3, "character": 22 })
+    );
+
+    // `limit`
+    let definition = request(
+        &mut server,
+        "textDocument/definition",
+        position(&caller_uri, 5, 23),
+    );
+    assert_eq!(
+        definition["result"]["range"]["start"],
+        json!({ "line": 0, "character": 17 })
+    );
+}
+
+#[test]
+fn test_completion() {
+    let (mut server, _, caller_uri) = workspace("completion");
+
+    // complete within the body of the `let`, while the document does not parse
+    let editing = CALLER.replace("(ok next)", "(ok (ne");
+    notify(
+        &mut server,
+        "textDocument/didChange",
+        json!({ "textDocument": { "uri": caller_uri, "version": 2 }, "contentChanges": [{ "text": editing }] }),
+    );
+    let completion = request(
+        &mut server,
+        "textDocument/completion",
+        position(&caller_uri, 6, 6),
+    );
+    let labels: Vec<&str> = completion["result"]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for expected in [
+        "map-get?",
+        "define-map",
+        "tx-sender",
+        "limit",
+        "bump",
+        "n",
+        "next",
+    ]
+    .iter()
+    {
+        assert!(labels.contains(expected), "missing {}", expected);
+    }
+
+    // outside of `bump`, its bindings are not in scope
+    let completion = request(
+        &mut server,
+        "textDocument/completion",
+        position(&caller_uri, 1, 0),
+    );
+    let labels: Vec<&str> = completion["result"]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert!(labels.contains(&"bump"));
+    assert!(!labels.contains(&"next"));
+}
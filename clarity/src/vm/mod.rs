@@ -39,6 +39,15 @@ pub mod analysis;
 pub mod docs;
 
 pub mod coverage;
+#[cfg(feature = "developer-mode")]
+pub mod debug;
+pub mod formatter;
+#[cfg(feature = "developer-mode")]
+pub mod fuzz;
+#[cfg(feature = "developer-mode")]
+pub mod lsp;
+pub mod profiler;
+pub mod version;
 
 pub mod events;
 
@@ -69,6 +78,7 @@ pub use crate::vm::types::Value;
 use crate::vm::types::{
     PrincipalData, QualifiedContractIdentifier, TraitIdentifier, TypeSignature,
 };
+pub use crate::vm::version::ClarityVersion;
 
 pub use crate::vm::representations::{
     ClarityName, ContractName, SymbolicExpression, SymbolicExpressionType,
@@ -81,6 +91,35 @@ use std::convert::{TryFrom, TryInto};
 
 pub const MAX_CALL_STACK_DEPTH: usize = 64;
 
+/// Hooks into the evaluation of Clarity code.  Any number of hooks can be installed in a
+/// `GlobalContext`; `eval` invokes each of them, in installation order, before and after it
+/// evaluates an expression, and the top-level execution in an `OwnedEnvironment` notifies
+/// them when it completes.  Coverage reporting, debuggers, and profilers are built on this.
+pub trait EvalHook {
+    /// Called before `expr` is evaluated.
+    fn will_begin_eval(
+        &mut self,
+        _env: &mut Environment,
+        _context: &LocalContext,
+        _expr: &SymbolicExpression,
+    ) {
+    }
+
+    /// Called after `expr` is evaluated, with the result of evaluating it.
+    fn did_finish_eval(
+        &mut self,
+        _env: &mut Environment,
+        _context: &LocalContext,
+        _expr: &SymbolicExpression,
+        _result: &Result<Value>,
+    ) {
+    }
+
+    /// Called once the top-level execution completes, with the error (if any) that it
+    /// produced.
+    fn did_complete(&mut self, _result: std::result::Result<(), String>) {}
+}
+
 fn lookup_variable(name: &str, context: &LocalContext, env: &mut Environment) -> Result<Value> {
     if name.starts_with(char::is_numeric) || name.starts_with('\'') {
         Err(InterpreterError::BadSymbolicRepresentation(format!(
@@ -118,7 +157,9 @@ fn lookup_variable(name: &str, context: &LocalContext, env: &mut Environment) ->
 pub fn lookup_function(name: &str, env: &mut Environment) -> Result<CallableType> {
     runtime_cost(ClarityCostFunction::LookupFunction, env, 0)?;
 
-    if let Some(result) = functions::lookup_reserved_functions(name) {
+    if let Some(result) =
+        functions::lookup_reserved_functions(name, &env.contract_context.clarity_version)
+    {
         Ok(result)
     } else {
         let user_function = env
@@ -230,37 +271,43 @@ pub fn eval<'a>(
         Atom, AtomValue, Field, List, LiteralValue, TraitReference,
     };
 
-    if let Some(ref mut coverage_tracker) = env.global_context.coverage_reporting {
-        coverage_tracker.report_eval(exp, &env.contract_context.contract_identifier);
+    if !env.global_context.eval_hooks.is_empty() {
+        let mut eval_hooks = std::mem::take(&mut env.global_context.eval_hooks);
+        for hook in eval_hooks.iter_mut() {
+            hook.will_begin_eval(env, context, exp);
+        }
+        env.global_context.eval_hooks = eval_hooks;
     }
 
-    match exp.expr {
+    let result = match exp.expr {
         AtomValue(ref value) | LiteralValue(ref value) => Ok(value.clone()),
         Atom(ref value) => lookup_variable(&value, context, env),
-        List(ref children) => {
-            let (function_variable, rest) = children
-                .split_first()
-                .ok_or(CheckErrors::NonFunctionApplication)?;
-
-            if let Some(ref mut coverage_tracker) = env.global_context.coverage_reporting {
-                coverage_tracker.report_eval(
-                    &function_variable,
-                    &env.contract_context.contract_identifier,
-                );
-            }
+        List(ref children) => children
+            .split_first()
+            .ok_or(CheckErrors::NonFunctionApplication.into())
+            .and_then(|(function_variable, rest)| {
+                let function_name = function_variable
+                    .match_atom()
+                    .ok_or(CheckErrors::BadFunctionName)?;
+                let f = lookup_function(&function_name, env)?;
+                apply(&f, &rest, env, context)
+            }),
+        TraitReference(_, _) | Field(_) => unreachable!("can't be evaluated"),
+    };
 
-            let function_name = function_variable
-                .match_atom()
-                .ok_or(CheckErrors::BadFunctionName)?;
-            let f = lookup_function(&function_name, env)?;
-            apply(&f, &rest, env, context)
+    if !env.global_context.eval_hooks.is_empty() {
+        let mut eval_hooks = std::mem::take(&mut env.global_context.eval_hooks);
+        for hook in eval_hooks.iter_mut() {
+            hook.did_finish_eval(env, context, exp, &result);
         }
-        TraitReference(_, _) | Field(_) => unreachable!("can't be evaluated"),
+        env.global_context.eval_hooks = eval_hooks;
     }
+
+    result
 }
 
-pub fn is_reserved(name: &str) -> bool {
-    if let Some(_result) = functions::lookup_reserved_functions(name) {
+pub fn is_reserved(name: &str, version: &ClarityVersion) -> bool {
+    if let Some(_result) = functions::lookup_reserved_functions(name, version) {
         true
     } else if variables::is_reserved_name(name) {
         true
@@ -425,7 +472,10 @@ pub fn execute_in_epoch(
     use crate::vm::database::MemoryBackingStore;
 
     let contract_id = QualifiedContractIdentifier::transient();
-    let mut contract_context = ContractContext::new(contract_id.clone());
+    let mut contract_context = ContractContext::new(
+        contract_id.clone(),
+        ClarityVersion::default_for_epoch(epoch),
+    );
     let mut marf = MemoryBackingStore::new();
     let conn = marf.as_clarity_db();
     let mut global_context =
@@ -443,13 +493,13 @@ mod test {
     use crate::vm::callables::{DefineType, DefinedFunction};
     use crate::vm::costs::LimitedCostTracker;
     use crate::vm::database::MemoryBackingStore;
-    use crate::vm::errors::RuntimeErrorType;
+    use crate::vm::errors::{Error, RuntimeErrorType};
     use crate::vm::eval;
     use crate::vm::execute;
     use crate::vm::types::{QualifiedContractIdentifier, TypeSignature};
     use crate::vm::{
-        CallStack, ContractContext, Environment, GlobalContext, LocalContext, SymbolicExpression,
-        Value,
+        CallStack, ClarityVersion, ContractContext, Environment, EvalHook, GlobalContext,
+        LocalContext, SymbolicExpression, Value,
     };
     use std::collections::HashMap;
 
@@ -482,7 +532,10 @@ mod test {
         );
 
         let context = LocalContext::new();
-        let mut contract_context = ContractContext::new(QualifiedContractIdentifier::transient());
+        let mut contract_context = ContractContext::new(
+            QualifiedContractIdentifier::transient(),
+            ClarityVersion::Clarity1,
+        );
 
         let mut marf = MemoryBackingStore::new();
         let mut global_context = GlobalContext::new(
@@ -509,4 +562,86 @@ mod test {
         );
         assert_eq!(Ok(Value::Int(64)), eval(&content[0], &mut env, &context));
     }
+
+    #[derive(Default)]
+    struct CountingHook {
+        begun: u32,
+        finished: u32,
+        errors: u32,
+    }
+
+    impl EvalHook for CountingHook {
+        fn will_begin_eval(
+            &mut self,
+            _env: &mut Environment,
+            _context: &LocalContext,
+            _expr: &SymbolicExpression,
+        ) {
+            self.begun += 1;
+        }
+
+        fn did_finish_eval(
+            &mut self,
+            _env: &mut Environment,
+            _context: &LocalContext,
+            _expr: &SymbolicExpression,
+            result: &Result<Value, Error>,
+        ) {
+            self.finished += 1;
+            if result.is_err() {
+                self.errors += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_hooks() {
+        // (+ 1 (- 3 2)) evaluates the outer list, `1`, the inner list, `3` and `2`
+        let content = SymbolicExpression::list(Box::new([
+            SymbolicExpression::atom("+".into()),
+            SymbolicExpression::atom_value(Value::Int(1)),
+            SymbolicExpression::list(Box::new([
+                SymbolicExpression::atom("-".into()),
+                SymbolicExpression::atom_value(Value::Int(3)),
+                SymbolicExpression::atom_value(Value::Int(2)),
+            ])),
+        ]));
+        let unbound = SymbolicExpression::atom("unbound".into());
+
+        let mut first = CountingHook::default();
+        let mut second = CountingHook::default();
+        {
+            let context = LocalContext::new();
+            let contract_context = ContractContext::new(
+                QualifiedContractIdentifier::transient(),
+                ClarityVersion::Clarity1,
+            );
+            let mut marf = MemoryBackingStore::new();
+            let mut global_context = GlobalContext::new(
+                false,
+                marf.as_clarity_db(),
+                LimitedCostTracker::new_free(),
+                StacksEpochId::Epoch2_05,
+            );
+            global_context.eval_hooks.push(&mut first);
+            global_context.eval_hooks.push(&mut second);
+
+            let mut call_stack = CallStack::new();
+            let mut env = Environment::new(
+                &mut global_context,
+                &contract_context,
+                &mut call_stack,
+                None,
+                None,
+            );
+            assert_eq!(Ok(Value::Int(2)), eval(&content, &mut env, &context));
+            assert!(eval(&unbound, &mut env, &context).is_err());
+        }
+
+        for hook in [first, second].iter() {
+            assert_eq!(hook.begun, 6);
+            assert_eq!(hook.finished, 6);
+            assert_eq!(hook.errors, 1);
+        }
+    }
 }
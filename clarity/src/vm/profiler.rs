@@ -0,0 +1,261 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use crate::vm::costs::ExecutionCost;
+use crate::vm::errors::InterpreterResult as Result;
+use crate::vm::{Environment, EvalHook, LocalContext, SymbolicExpression, Value};
+
+/// The number of expressions listed in the summary of a profiled execution.
+pub const PROFILE_TABLE_ROWS: usize = 20;
+
+/// The cost dimension used to weigh a profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileWeight {
+    Runtime,
+    ReadCount,
+    ReadLength,
+    WriteCount,
+    WriteLength,
+}
+
+impl ProfileWeight {
+    pub fn lookup_by_name(name: &str) -> Option<ProfileWeight> {
+        match name {
+            "runtime" => Some(ProfileWeight::Runtime),
+            "read_count" => Some(ProfileWeight::ReadCount),
+            "read_length" => Some(ProfileWeight::ReadLength),
+            "write_count" => Some(ProfileWeight::WriteCount),
+            "write_length" => Some(ProfileWeight::WriteLength),
+            _ => None,
+        }
+    }
+
+    pub fn of(&self, cost: &ExecutionCost) -> u64 {
+        match self {
+            ProfileWeight::Runtime => cost.runtime,
+            ProfileWeight::ReadCount => cost.read_count,
+            ProfileWeight::ReadLength => cost.read_length,
+            ProfileWeight::WriteCount => cost.write_count,
+            ProfileWeight::WriteLength => cost.write_length,
+        }
+    }
+}
+
+/// The costs attributed to one function application in a contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    /// The applied function and where the application appears, e.g. `map-get? (SP000...foo:12)`
+    pub label: String,
+    pub calls: u64,
+    /// Costs charged while evaluating this expression, but not its nested applications.
+    pub self_cost: ExecutionCost,
+    /// All costs charged while evaluating this expression.
+    pub total_cost: ExecutionCost,
+}
+
+struct Frame {
+    label: String,
+    start: ExecutionCost,
+    children: ExecutionCost,
+}
+
+/// An `EvalHook` which attributes the costs charged by the cost tracker to the function
+/// applications that incurred them.  Costs charged while evaluating an expression which is
+/// not a function application (an atom or a literal) are attributed to the enclosing
+/// application.
+pub struct CostProfiler {
+    /// One entry per expression currently being evaluated, `None` if the expression is
+    /// not a function application.
+    stack: Vec<Option<Frame>>,
+    /// The self cost of each distinct stack of applications.
+    folded: BTreeMap<Vec<String>, ExecutionCost>,
+    entries: HashMap<String, ProfileEntry>,
+}
+
+fn saturating_diff(later: &ExecutionCost, earlier: &ExecutionCost) -> ExecutionCost {
+    ExecutionCost {
+        write_length: later.write_length.saturating_sub(earlier.write_length),
+        write_count: later.write_count.saturating_sub(earlier.write_count),
+        read_length: later.read_length.saturating_sub(earlier.read_length),
+        read_count: later.read_count.saturating_sub(earlier.read_count),
+        runtime: later.runtime.saturating_sub(earlier.runtime),
+    }
+}
+
+fn saturating_add(total: &mut ExecutionCost, other: &ExecutionCost) {
+    total.write_length = total.write_length.saturating_add(other.write_length);
+    total.write_count = total.write_count.saturating_add(other.write_count);
+    total.read_length = total.read_length.saturating_add(other.read_length);
+    total.read_count = total.read_count.saturating_add(other.read_count);
+    total.runtime = total.runtime.saturating_add(other.runtime);
+}
+
+#[cfg(feature = "developer-mode")]
+fn expression_line(expr: &SymbolicExpression) -> u32 {
+    expr.span.start_line
+}
+
+#[cfg(not(feature = "developer-mode"))]
+fn expression_line(_expr: &SymbolicExpression) -> u32 {
+    0
+}
+
+impl Default for CostProfiler {
+    fn default() -> CostProfiler {
+        CostProfiler::new()
+    }
+}
+
+impl CostProfiler {
+    pub fn new() -> CostProfiler {
+        CostProfiler {
+            stack: vec![],
+            folded: BTreeMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The profiled applications, most expensive first by their self cost in `weight`.
+    pub fn entries(&self, weight: ProfileWeight) -> Vec<&ProfileEntry> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by(|a, b| {
+            weight
+                .of(&b.self_cost)
+                .cmp(&weight.of(&a.self_cost))
+                .then_with(|| a.label.cmp(&b.label))
+        });
+        entries
+    }
+
+    /// Write the profile in the folded-stack format read by flame graph tools: one line per
+    /// distinct stack of applications, with frames separated by `;`, followed by the self
+    /// cost of the innermost frame in `weight`.
+    pub fn write_folded<W: Write>(&self, out: &mut W, weight: ProfileWeight) -> io::Result<()> {
+        for (stack, cost) in self.folded.iter() {
+            let value = weight.of(cost);
+            if value > 0 {
+                writeln!(out, "{} {}", stack.join(";"), value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a table of the `count` most expensive applications by their self cost in `weight`.
+    pub fn write_table<W: Write>(
+        &self,
+        out: &mut W,
+        weight: ProfileWeight,
+        count: usize,
+    ) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:>8} {:>12} {:>12} {:>10} {:>11} {:>11} {:>12}  expression",
+            "calls",
+            "runtime",
+            "total_rt",
+            "read_count",
+            "read_length",
+            "write_count",
+            "write_length"
+        )?;
+        for entry in self.entries(weight).into_iter().take(count) {
+            writeln!(
+                out,
+                "{:>8} {:>12} {:>12} {:>10} {:>11} {:>11} {:>12}  {}",
+                entry.calls,
+                entry.self_cost.runtime,
+                entry.total_cost.runtime,
+                entry.self_cost.read_count,
+                entry.self_cost.read_length,
+                entry.self_cost.write_count,
+                entry.self_cost.write_length,
+                entry.label
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl EvalHook for CostProfiler {
+    fn will_begin_eval(
+        &mut self,
+        env: &mut Environment,
+        _context: &LocalContext,
+        expr: &SymbolicExpression,
+    ) {
+        let function_name = expr
+            .match_list()
+            .and_then(|list| list.first())
+            .and_then(|first| first.match_atom());
+        let frame = function_name.map(|function_name| Frame {
+            label: format!(
+                "{} ({}:{})",
+                function_name,
+                env.contract_context.contract_identifier,
+                expression_line(expr)
+            ),
+            start: env.global_context.cost_track.get_total(),
+            children: ExecutionCost::zero(),
+        });
+        self.stack.push(frame);
+    }
+
+    fn did_finish_eval(
+        &mut self,
+        env: &mut Environment,
+        _context: &LocalContext,
+        _expr: &SymbolicExpression,
+        _result: &Result<Value>,
+    ) {
+        let frame = match self.stack.pop() {
+            Some(Some(frame)) => frame,
+            _ => return,
+        };
+        let total_cost = saturating_diff(&env.global_context.cost_track.get_total(), &frame.start);
+        let self_cost = saturating_diff(&total_cost, &frame.children);
+
+        let mut stack: Vec<String> = self
+            .stack
+            .iter()
+            .filter_map(|frame| frame.as_ref().map(|frame| frame.label.clone()))
+            .collect();
+        if let Some(parent) = self.stack.iter_mut().rev().find_map(|frame| frame.as_mut()) {
+            saturating_add(&mut parent.children, &total_cost);
+        }
+
+        let entry = self
+            .entries
+            .entry(frame.label.clone())
+            .or_insert_with(|| ProfileEntry {
+                label: frame.label.clone(),
+                calls: 0,
+                self_cost: ExecutionCost::zero(),
+                total_cost: ExecutionCost::zero(),
+            });
+        entry.calls += 1;
+        saturating_add(&mut entry.self_cost, &self_cost);
+        saturating_add(&mut entry.total_cost, &total_cost);
+
+        stack.push(frame.label);
+        saturating_add(
+            self.folded.entry(stack).or_insert_with(ExecutionCost::zero),
+            &self_cost,
+        );
+    }
+}
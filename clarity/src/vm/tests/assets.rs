@@ -17,6 +17,7 @@
 use crate::vm::ast::ASTRules;
 use crate::vm::contexts::{AssetMap, AssetMapEntry, GlobalContext, OwnedEnvironment};
 use crate::vm::contracts::Contract;
+use crate::vm::database::MemoryBackingStore;
 use crate::vm::errors::{CheckErrors, Error, RuntimeErrorType};
 use crate::vm::events::StacksTransactionEvent;
 use crate::vm::execute as vm_execute;
@@ -25,8 +26,9 @@ use crate::vm::tests::{
     execute, is_committed, is_err_code, symbols_from_values, with_memory_environment,
 };
 use crate::vm::types::{
-    AssetIdentifier, PrincipalData, QualifiedContractIdentifier, ResponseData, Value,
+    AssetIdentifier, PrincipalData, QualifiedContractIdentifier, ResponseData, TupleData, Value,
 };
+use stacks_common::types::StacksEpochId;
 use stacks_common::util::hash::hex_bytes;
 
 const FIRST_CLASS_TOKENS: &str = "(define-fungible-token stackaroos)
@@ -1351,3 +1353,120 @@ fn test_all() {
         with_memory_environment(test, true);
     }
 }
+
+#[test]
+fn test_stx_account_epoch_activation() {
+    let legacy = "(define-read-only (stx-account (p principal)) u7)
+                  (define-read-only (check (p principal)) (stx-account p))";
+    let account = "(define-read-only (check (p principal)) (stx-account p))";
+
+    let p1 = match execute("'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR") {
+        Value::Principal(PrincipalData::Standard(data)) => data,
+        _ => panic!(),
+    };
+    let legacy_id = QualifiedContractIdentifier::new(p1.clone(), "legacy".into());
+    let account_205_id = QualifiedContractIdentifier::new(p1.clone(), "account-205".into());
+    let account_21_id = QualifiedContractIdentifier::new(p1.clone(), "account-21".into());
+    let legacy_21_id = QualifiedContractIdentifier::new(p1.clone(), "legacy-21".into());
+    let args = symbols_from_values(vec![Value::from(p1.clone())]);
+
+    let mut store = MemoryBackingStore::new();
+
+    // before activation, `stx-account` is an ordinary name
+    {
+        let mut owned_env =
+            OwnedEnvironment::new_free(false, store.as_clarity_db(), StacksEpochId::Epoch2_05);
+        owned_env
+            .initialize_contract(legacy_id.clone(), legacy, ASTRules::PrecheckSize)
+            .unwrap();
+        owned_env
+            .initialize_contract(account_205_id.clone(), account, ASTRules::PrecheckSize)
+            .unwrap();
+
+        let (result, ..) = execute_transaction(
+            &mut owned_env,
+            p1.clone().into(),
+            &legacy_id,
+            "check",
+            &args,
+        )
+        .unwrap();
+        assert_eq!(result, Value::UInt(7));
+
+        let err = execute_transaction(
+            &mut owned_env,
+            p1.clone().into(),
+            &account_205_id,
+            "check",
+            &args,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            CheckErrors::UndefinedFunction("stx-account".to_string()).into()
+        );
+    }
+
+    // after activation, contracts deployed earlier keep their meaning, and new contracts
+    //  get the native.
+    {
+        let mut owned_env =
+            OwnedEnvironment::new_free(false, store.as_clarity_db(), StacksEpochId::Epoch21);
+
+        let (result, ..) = execute_transaction(
+            &mut owned_env,
+            p1.clone().into(),
+            &legacy_id,
+            "check",
+            &args,
+        )
+        .unwrap();
+        assert_eq!(result, Value::UInt(7));
+
+        let err = execute_transaction(
+            &mut owned_env,
+            p1.clone().into(),
+            &account_205_id,
+            "check",
+            &args,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            CheckErrors::UndefinedFunction("stx-account".to_string()).into()
+        );
+
+        let err = owned_env
+            .initialize_contract(legacy_21_id.clone(), legacy, ASTRules::PrecheckSize)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CheckErrors::NameAlreadyUsed("stx-account".to_string()).into()
+        );
+
+        owned_env
+            .initialize_contract(account_21_id.clone(), account, ASTRules::PrecheckSize)
+            .unwrap();
+        owned_env.stx_faucet(&p1.clone().into(), 10);
+
+        let (result, ..) = execute_transaction(
+            &mut owned_env,
+            p1.clone().into(),
+            &account_21_id,
+            "check",
+            &args,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            Value::from(
+                TupleData::from_data(vec![
+                    ("unlocked".into(), Value::UInt(10)),
+                    ("locked".into(), Value::UInt(0)),
+                    ("unlock-height".into(), Value::UInt(0)),
+                ])
+                .unwrap()
+            )
+        );
+    }
+}
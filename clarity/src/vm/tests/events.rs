@@ -27,12 +27,20 @@ use crate::vm::database::MemoryBackingStore;
 use crate::vm::tests::{TEST_BURN_STATE_DB, TEST_HEADER_DB};
 
 fn helper_execute(contract: &str, method: &str) -> (Value, Vec<StacksTransactionEvent>) {
+    helper_execute_epoch(contract, method, StacksEpochId::Epoch2_05)
+}
+
+fn helper_execute_epoch(
+    contract: &str,
+    method: &str,
+    epoch: StacksEpochId,
+) -> (Value, Vec<StacksTransactionEvent>) {
     let contract_id = QualifiedContractIdentifier::local("contract").unwrap();
     let address = "'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR";
     let sender = execute(address).expect_principal();
 
     let mut marf_kv = MemoryBackingStore::new();
-    let mut owned_env = OwnedEnvironment::new(marf_kv.as_clarity_db());
+    let mut owned_env = OwnedEnvironment::new_free(false, marf_kv.as_clarity_db(), epoch);
 
     {
         let mut env = owned_env.get_exec_environment(None);
@@ -97,13 +105,52 @@ fn test_emit_stx_transfer_ok() {
         Some(StacksTransactionEvent::STXEvent(STXEventType::STXTransferEvent(data))) => {
             assert_eq!(data.amount, 10u128);
             assert_eq!(
-                Value::Principal(data.sender),
+                Value::Principal(data.sender.clone()),
                 execute("'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR")
             );
             assert_eq!(
-                Value::Principal(data.recipient),
+                Value::Principal(data.recipient.clone()),
                 execute("'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G")
             );
+            // plain transfers carry no memo, and don't emit one to observers
+            assert_eq!(data.memo, None);
+            assert!(data.json_serialize().get("memo").is_none());
+        }
+        _ => panic!("assertion failed"),
+    };
+}
+
+#[test]
+fn test_emit_stx_transfer_memo_ok() {
+    let contract = "(define-constant sender 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)
+        (define-constant recipient 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G)
+        (define-public (emit-event-ok)
+            (begin
+                (unwrap-panic (stx-transfer-memo? u10 sender recipient 0x010203))
+                (ok u1)))";
+
+    let (value, mut events) =
+        helper_execute_epoch(contract, "emit-event-ok", StacksEpochId::Epoch21);
+    assert_eq!(value, Value::okay(Value::UInt(1)).unwrap());
+    assert_eq!(events.len(), 1);
+    match events.pop() {
+        Some(StacksTransactionEvent::STXEvent(STXEventType::STXTransferEvent(data))) => {
+            assert_eq!(data.amount, 10u128);
+            assert_eq!(
+                Value::Principal(data.sender.clone()),
+                execute("'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR")
+            );
+            assert_eq!(
+                Value::Principal(data.recipient.clone()),
+                execute("'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G")
+            );
+            assert_eq!(
+                data.memo,
+                Some(BuffData {
+                    data: vec![1, 2, 3]
+                })
+            );
+            assert_eq!(data.json_serialize()["memo"], "010203");
         }
         _ => panic!("assertion failed"),
     };
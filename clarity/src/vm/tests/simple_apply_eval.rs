@@ -26,7 +26,9 @@ use crate::vm::types::signatures::*;
 use crate::vm::types::{BuffData, QualifiedContractIdentifier, TypeSignature};
 use crate::vm::types::{PrincipalData, ResponseData, SequenceData, SequenceSubtype};
 use crate::vm::{eval, execute as vm_execute};
-use crate::vm::{CallStack, ContractContext, Environment, GlobalContext, LocalContext, Value};
+use crate::vm::{
+    CallStack, ClarityVersion, ContractContext, Environment, GlobalContext, LocalContext, Value,
+};
 use stacks_common::address::c32;
 use stacks_common::address::AddressHashMode;
 use stacks_common::address::C32_ADDRESS_VERSION_MAINNET_SINGLESIG;
@@ -383,7 +385,10 @@ fn test_simple_if_functions() {
         );
 
         let context = LocalContext::new();
-        let mut contract_context = ContractContext::new(QualifiedContractIdentifier::transient());
+        let mut contract_context = ContractContext::new(
+            QualifiedContractIdentifier::transient(),
+            ClarityVersion::Clarity1,
+        );
         let mut marf = MemoryBackingStore::new();
         let mut global_context = GlobalContext::new(
             false,
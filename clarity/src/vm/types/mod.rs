@@ -40,7 +40,7 @@ pub use crate::vm::types::signatures::{
     parse_name_type_pairs, AssetIdentifier, BufferLength, FixedFunction, FunctionArg,
     FunctionSignature, FunctionType, ListTypeData, SequenceSubtype, StringSubtype,
     StringUTF8Length, TupleTypeSignature, TypeSignature, BUFF_1, BUFF_20, BUFF_32, BUFF_33,
-    BUFF_64, BUFF_65,
+    BUFF_34, BUFF_64, BUFF_65,
 };
 
 pub const MAX_VALUE_SIZE: u32 = 1024 * 1024; // 1MB
@@ -977,6 +977,10 @@ impl Value {
 }
 
 impl BuffData {
+    pub fn empty() -> Self {
+        Self { data: Vec::new() }
+    }
+
     pub fn len(&self) -> BufferLength {
         self.data.len().try_into().unwrap()
     }
@@ -138,6 +138,9 @@ lazy_static! {
     pub static ref BUFF_33: TypeSignature = SequenceType(SequenceSubtype::BufferType(
         BufferLength::try_from(33u32).expect("BUG: Legal Clarity buffer length marked invalid")
     ));
+    pub static ref BUFF_34: TypeSignature = SequenceType(SequenceSubtype::BufferType(
+        BufferLength::try_from(34u32).expect("BUG: Legal Clarity buffer length marked invalid")
+    ));
     pub static ref BUFF_20: TypeSignature = SequenceType(SequenceSubtype::BufferType(
         BufferLength::try_from(20u32).expect("BUG: Legal Clarity buffer length marked invalid")
     ));
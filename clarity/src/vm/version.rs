@@ -0,0 +1,63 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+use crate::types::StacksEpochId;
+
+/// The Clarity language version a contract was deployed with. This governs which native
+///  functions a contract may call (and which names are reserved), and is fixed at deploy time:
+///  a contract keeps its version across later epoch transitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ClarityVersion {
+    Clarity1,
+    Clarity2,
+}
+
+impl Default for ClarityVersion {
+    fn default() -> ClarityVersion {
+        // contracts stored before versioning was introduced are all Clarity1
+        ClarityVersion::Clarity1
+    }
+}
+
+impl fmt::Display for ClarityVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClarityVersion::Clarity1 => write!(f, "Clarity 1"),
+            ClarityVersion::Clarity2 => write!(f, "Clarity 2"),
+        }
+    }
+}
+
+impl ClarityVersion {
+    /// The version given to contracts deployed in `epoch_id`
+    pub fn default_for_epoch(epoch_id: StacksEpochId) -> ClarityVersion {
+        match epoch_id {
+            StacksEpochId::Epoch10 => {
+                panic!(
+                    "Attempted to get a Clarity version for Epoch 1.0 where Clarity does not exist"
+                )
+            }
+            StacksEpochId::Epoch20 | StacksEpochId::Epoch2_05 => ClarityVersion::Clarity1,
+            StacksEpochId::Epoch21 => ClarityVersion::Clarity2,
+        }
+    }
+
+    pub fn latest() -> ClarityVersion {
+        ClarityVersion::Clarity2
+    }
+}
@@ -20,11 +20,15 @@
 #![allow(non_upper_case_globals)]
 
 extern crate blockstack_lib;
+#[macro_use]
+extern crate serde_derive;
 
 use std::convert::TryFrom;
 use std::io::prelude::*;
 use std::io::Read;
-use std::{env, fs, io};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use std::{env, fs, io, thread};
 
 use blockstack_lib::address::b58;
 use blockstack_lib::address::AddressHashMode;
@@ -32,23 +36,36 @@ use blockstack_lib::burnchains::bitcoin::address::{
     ADDRESS_VERSION_MAINNET_SINGLESIG, ADDRESS_VERSION_TESTNET_SINGLESIG,
 };
 use blockstack_lib::burnchains::Address;
+use blockstack_lib::burnchains::Txid;
 use blockstack_lib::chainstate::stacks::StacksBlockHeader;
 use blockstack_lib::chainstate::stacks::{
+    AssetInfo, FungibleConditionCode, NonfungibleConditionCode, PostConditionPrincipal,
     StacksBlock, StacksMicroblock, StacksPrivateKey, StacksPublicKey, StacksTransaction,
     StacksTransactionSigner, TokenTransferMemo, TransactionAnchorMode, TransactionAuth,
-    TransactionContractCall, TransactionPayload, TransactionSmartContract,
-    TransactionSpendingCondition, TransactionVersion, C32_ADDRESS_VERSION_MAINNET_SINGLESIG,
-    C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+    TransactionAuthField, TransactionAuthFlags, TransactionContractCall, TransactionPayload,
+    TransactionPostCondition, TransactionPostConditionMode, TransactionPublicKeyEncoding,
+    TransactionSmartContract, TransactionSpendingCondition, TransactionVersion,
+    C32_ADDRESS_VERSION_MAINNET_SINGLESIG, C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
 };
 use blockstack_lib::codec::{Error as CodecError, StacksMessageCodec};
 use blockstack_lib::core::{CHAIN_ID_MAINNET, CHAIN_ID_TESTNET};
-use blockstack_lib::net::Error as NetError;
+use blockstack_lib::net::http::StacksHttp;
+use blockstack_lib::net::{
+    Error as NetError, HttpRequestMetadata, HttpRequestType, HttpResponseType, PeerHost, TipRequest,
+};
 use blockstack_lib::types::chainstate::StacksAddress;
+use blockstack_lib::util::secp256k1::MESSAGE_SIGNATURE_ENCODED_SIZE;
 use blockstack_lib::util::{hash::hex_bytes, hash::to_hex, log, retry::LogReader};
 use blockstack_lib::util_lib::strings::StacksString;
+use blockstack_lib::vm::analysis::contract_interface_builder::{
+    ContractInterface, ContractInterfaceAtomType, ContractInterfaceFunction,
+    ContractInterfaceFunctionAccess,
+};
+use blockstack_lib::vm::database::ClaritySerializable;
+use blockstack_lib::vm::types::TupleData;
 use blockstack_lib::vm::{
     errors::{Error as ClarityError, RuntimeErrorType},
-    types::PrincipalData,
+    types::{PrincipalData, QualifiedContractIdentifier},
     ClarityName, ContractName, Value,
 };
 
@@ -63,6 +80,10 @@ This CLI has these methods:
   contract-call      used to generate and sign a contract-call transaction
   generate-sk        used to generate a secret key for transaction signing
   token-transfer     used to generate and sign a transfer transaction
+  sign-multisig      used to add a signature to a multisig transaction
+  finalize-multisig  used to check that a multisig or sponsored transaction is fully signed
+  sponsor            used to sponsor (and sign) a sponsored transaction
+  multisig-address   used to get the Stacks address of a multisig account
   addresses          used to get both Bitcoin and Stacks addresses from a private key
   decode-tx          used to decode a hex-encoded transaction into a human-readable representation
   decode-header      used to decode a hex-encoded Stacks header into a human-readable representation
@@ -88,6 +109,35 @@ is that the miner chooses, but you can decide which with the following options:
 
   --microblock-only  indicates to mine this transaction only in a microblock
   --block-only       indicates to mine this transaction only in a block
+
+A multisig account can be the origin instead of a secret key.  In that case, leave out the
+secret key, and this command outputs an unsigned transaction for `sign-multisig`:
+
+  --multisig [num-sigs] [public-key,...]  the origin is a num-sigs-of-n P2SH multisig account
+  --p2wsh                                 the multisig account is P2WSH instead of P2SH
+
+  --sponsored        indicates that another account will pay the fee with `sponsor`
+
+Post-conditions abort the transaction if it moves assets other than as expected.  They are
+added with these options, which can be repeated:
+
+  --stx-post-condition [principal] [condition] [amount]
+  --ft-post-condition [principal] [contract-identifier::asset-name] [condition] [amount]
+  --nft-post-condition [principal] [contract-identifier::asset-name] [condition] [value]
+  --post-conditions [file.json]   reads post-conditions in the format `decode-tx` prints
+                                  as `post_condition_spec`
+  --post-condition-mode [allow|deny]  whether to allow asset transfers that no post-condition
+                                      covers (default: deny)
+
+The principal is `origin`, an address or a contract identifier.  Fungible conditions are
+sent-eq, sent-gt, sent-ge, sent-lt or sent-le, and non-fungible conditions are sent or
+not-sent.  A non-fungible asset's value is a Clarity expression, e.g. `u1`.
+
+With `--node [url]`, e.g. `--node http://localhost:20443`, the fee-rate and nonce can be `auto`
+to look them up from that node, and a fully-signed transaction is broadcast to the node.  This
+command then waits for the transaction to show up as unconfirmed, and outputs it, its txid and
+its status as JSON.  `auto` fees use the node's middle fee estimate, and are 0 for the origin
+of a sponsored transaction.
 ";
 
 const CALL_USAGE: &str = "blockstack-cli (options) contract-call [origin-secret-key-hex] [fee-rate] [nonce] [contract-publisher-address] [contract-name] [function-name] [args...]
@@ -102,12 +152,49 @@ is that the miner chooses, but you can decide which with the following options:
   --microblock-only  indicates to mine this transaction only in a microblock
   --block-only       indicates to mine this transaction only in a block
 
-Arguments are supplied in one of two ways: through script evaluation or via hex encoding
-of the value serialization format. The method for supplying arguments is chosen by
+A multisig account can be the origin instead of a secret key.  In that case, leave out the
+secret key, and this command outputs an unsigned transaction for `sign-multisig`:
+
+  --multisig [num-sigs] [public-key,...]  the origin is a num-sigs-of-n P2SH multisig account
+  --p2wsh                                 the multisig account is P2WSH instead of P2SH
+
+  --sponsored        indicates that another account will pay the fee with `sponsor`
+
+Post-conditions abort the transaction if it moves assets other than as expected.  They are
+added with these options, which can be repeated:
+
+  --stx-post-condition [principal] [condition] [amount]
+  --ft-post-condition [principal] [contract-identifier::asset-name] [condition] [amount]
+  --nft-post-condition [principal] [contract-identifier::asset-name] [condition] [value]
+  --post-conditions [file.json]   reads post-conditions in the format `decode-tx` prints
+                                  as `post_condition_spec`
+  --post-condition-mode [allow|deny]  whether to allow asset transfers that no post-condition
+                                      covers (default: deny)
+
+The principal is `origin`, an address or a contract identifier.  Fungible conditions are
+sent-eq, sent-gt, sent-ge, sent-lt or sent-le, and non-fungible conditions are sent or
+not-sent.  A non-fungible asset's value is a Clarity expression, e.g. `u1`.
+
+With `--node [url]`, e.g. `--node http://localhost:20443`, the fee-rate and nonce can be `auto`
+to look them up from that node, and a fully-signed transaction is broadcast to the node.  This
+command then waits for the transaction to show up as unconfirmed, and outputs it, its txid and
+its status as JSON.  `auto` fees use the node's middle fee estimate, and are 0 for the origin
+of a sponsored transaction.
+
+Arguments are supplied in one of three ways: through script evaluation, via hex encoding
+of the value serialization format, or as JSON. The method for supplying arguments is chosen by
 prefacing each argument with a flag:
 
   -e  indicates the argument should be _evaluated_
   -x  indicates the argument that a serialized Clarity value is being passed (hex-serialized)
+  -j  indicates the argument is JSON, to be converted using the function's declared type
+
+JSON arguments need the contract's interface, as served by the node's /v2/contracts/interface
+endpoint.  It is read from `--abi [file.json]`, or else fetched from `--node`.  Integers are
+JSON numbers or decimal strings, buffers are hex strings, principals and strings are strings,
+`none` is null, a `some` is its inner value, responses are {\"ok\": ...} or {\"error\": ...},
+tuples are objects and lists are arrays.  Whenever the interface is available, the number and
+types of all the arguments are checked before the transaction is signed.
 
 e.g.,
 
@@ -116,6 +203,9 @@ e.g.,
                        -e \"(+ 1 2)\" \\
                        -x 0000000000000000000000000000000001 \\
                        -x 050011deadbeef11ababffff11deadbeef11ababffff
+
+   blockstack-cli contract-call --abi foo-contract.json $secret_key 10 0 SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4 \\
+      foo-contract send-many -j '[{\"to\": \"SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4\", \"amount\": 10}]'
 ";
 
 const TOKEN_TRANSFER_USAGE: &str = "blockstack-cli (options) token-transfer [origin-secret-key-hex] [fee-rate] [nonce] [recipient-address] [amount] [memo] [args...]
@@ -129,6 +219,82 @@ is that the miner chooses, but you can decide which with the following options:
 
   --microblock-only  indicates to mine this transaction only in a microblock
   --block-only       indicates to mine this transaction only in a block
+
+A multisig account can be the origin instead of a secret key.  In that case, leave out the
+secret key, and this command outputs an unsigned transaction for `sign-multisig`:
+
+  --multisig [num-sigs] [public-key,...]  the origin is a num-sigs-of-n P2SH multisig account
+  --p2wsh                                 the multisig account is P2WSH instead of P2SH
+
+  --sponsored        indicates that another account will pay the fee with `sponsor`
+
+Post-conditions abort the transaction if it moves assets other than as expected.  They are
+added with these options, which can be repeated:
+
+  --stx-post-condition [principal] [condition] [amount]
+  --ft-post-condition [principal] [contract-identifier::asset-name] [condition] [amount]
+  --nft-post-condition [principal] [contract-identifier::asset-name] [condition] [value]
+  --post-conditions [file.json]   reads post-conditions in the format `decode-tx` prints
+                                  as `post_condition_spec`
+  --post-condition-mode [allow|deny]  whether to allow asset transfers that no post-condition
+                                      covers (default: deny)
+
+The principal is `origin`, an address or a contract identifier.  Fungible conditions are
+sent-eq, sent-gt, sent-ge, sent-lt or sent-le, and non-fungible conditions are sent or
+not-sent.  A non-fungible asset's value is a Clarity expression, e.g. `u1`.
+
+With `--node [url]`, e.g. `--node http://localhost:20443`, the fee-rate and nonce can be `auto`
+to look them up from that node, and a fully-signed transaction is broadcast to the node.  This
+command then waits for the transaction to show up as unconfirmed, and outputs it, its txid and
+its status as JSON.  `auto` fees use the node's middle fee estimate, and are 0 for the origin
+of a sponsored transaction.
+";
+
+const SIGN_MULTISIG_USAGE: &str =
+    "blockstack-cli (options) sign-multisig [signer-secret-key-hex] [transaction-hex]
+
+The sign-multisig command adds a signature to an unsigned or partially-signed multisig
+transaction made with `--multisig`, either to its origin or to its sponsor.  If successful,
+this command outputs the hex string encoding of the transaction to stdout, and exits with
+code 0.  Pass the output on to the next signer.
+
+Each signature commits to the signatures before it, so signers must sign in the order of
+their public keys in `--multisig`.  Signers that do not sign can be skipped.  A sponsor can
+only sign once the origin is fully signed.";
+
+const FINALIZE_MULTISIG_USAGE: &str = "blockstack-cli (options) finalize-multisig [transaction-hex]
+
+The finalize-multisig command checks that a transaction has all of its signatures and that
+they are valid.  If so, it outputs the hex string encoding of the transaction, ready to
+broadcast, and exits with code 0.  Otherwise, it reports how many signatures are missing.
+
+With `--node [url]`, the transaction is broadcast to that node.";
+
+const SPONSOR_USAGE: &str =
+    "blockstack-cli (options) sponsor [sponsor-secret-key-hex] [fee-rate] [nonce] [transaction-hex]
+
+The sponsor command takes a signed transaction made with `--sponsored`, sets the sponsor's
+fee and nonce, and signs it as the sponsor.  If successful, this command outputs the hex
+string encoding of the transaction to stdout, and exits with code 0.
+
+A multisig account can be the sponsor instead of a secret key.  In that case, leave out the
+secret key, and sign the output with `sign-multisig`:
+
+  --multisig [num-sigs] [public-key,...]  the sponsor is a num-sigs-of-n P2SH multisig account
+  --p2wsh                                 the multisig account is P2WSH instead of P2SH
+
+With `--node [url]`, the fee-rate and nonce can be `auto` to look them up from that node, and
+a fully-signed transaction is broadcast to the node.
+";
+
+const MULTISIG_ADDRESS_USAGE: &str =
+    "blockstack-cli (options) multisig-address [num-sigs] [public-key,...]
+
+The multisig-address command calculates the Stacks address of a num-sigs-of-n multisig
+account from its signers' public keys, in order.  If successful, this command outputs the
+address to stdout, formatted as JSON, and exits with code 0.
+
+  --p2wsh            calculate a P2WSH address instead of a P2SH address
 ";
 
 const GENERATE_USAGE: &str = "blockstack-cli (options) generate-sk
@@ -146,6 +312,7 @@ const DECODE_TRANSACTION_USAGE: &str =
     "blockstack-cli (options) decode-tx [transaction-hex-or-stdin]
 
 The decode-tx command decodes a serialized Stacks transaction and prints it to stdout as JSON.
+Multisig transactions that are still missing signatures are decoded as well.
 The transaction, if given, must be a hex string.  Alternatively, you may pass `-` instead, and the
 raw binary transaction will be read from stdin.";
 
@@ -282,25 +449,6 @@ fn make_contract_call(
     })
 }
 
-fn make_standard_single_sig_tx(
-    version: TransactionVersion,
-    chain_id: u32,
-    payload: TransactionPayload,
-    publicKey: &StacksPublicKey,
-    nonce: u64,
-    tx_fee: u64,
-) -> StacksTransaction {
-    let mut spending_condition =
-        TransactionSpendingCondition::new_singlesig_p2pkh(publicKey.clone())
-            .expect("Failed to create p2pkh spending condition from public key.");
-    spending_condition.set_nonce(nonce);
-    spending_condition.set_tx_fee(tx_fee);
-    let auth = TransactionAuth::Standard(spending_condition);
-    let mut tx = StacksTransaction::new(version, auth, payload);
-    tx.chain_id = chain_id;
-    tx
-}
-
 fn sign_transaction_single_sig_standard(
     transaction: &str,
     secret_key: &StacksPrivateKey,
@@ -354,6 +502,970 @@ fn parse_anchor_mode(
     }
 }
 
+/// The account authorizing one side -- origin or sponsor -- of a transaction.
+struct TransactionSigner {
+    /// Secret key of a single-signature account, which signs right away.  Multisig
+    /// accounts have no key here: their signers sign later with `sign-multisig`.
+    secret_key: Option<StacksPrivateKey>,
+    /// Multisig conditions start out with one public key field per signer, in order.
+    condition: TransactionSpendingCondition,
+}
+
+/// Remove `flag` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    if let Some(ix) = args.iter().position(|x| x == flag) {
+        args.remove(ix);
+        true
+    } else {
+        false
+    }
+}
+
+fn make_multisig_condition(
+    num_sigs: &str,
+    public_keys: &str,
+    p2wsh: bool,
+) -> Result<TransactionSpendingCondition, CliError> {
+    let num_sigs: u16 = num_sigs.parse()?;
+    let public_keys = public_keys
+        .split(',')
+        .map(|pk| StacksPublicKey::from_hex(pk))
+        .collect::<Result<Vec<_>, _>>()?;
+    if num_sigs == 0 || usize::from(num_sigs) > public_keys.len() {
+        return Err(CliError::Message(format!(
+            "A multisig account needs between 1 and {} signatures",
+            public_keys.len()
+        )));
+    }
+    if p2wsh && public_keys.iter().any(|pk| !pk.compressed()) {
+        return Err("P2WSH multisig accounts only accept compressed public keys".into());
+    }
+
+    let mut condition = if p2wsh {
+        TransactionSpendingCondition::new_multisig_p2wsh(num_sigs, public_keys.clone())
+    } else {
+        TransactionSpendingCondition::new_multisig_p2sh(num_sigs, public_keys.clone())
+    }
+    .ok_or("Failed to create multisig spending condition from public keys")?;
+    if let TransactionSpendingCondition::Multisig(ref mut multisig) = condition {
+        for public_key in public_keys.into_iter() {
+            multisig.push_public_key(public_key);
+        }
+    }
+    Ok(condition)
+}
+
+/// Parse the signer of a transaction: either `--multisig [num-sigs] [public-key,...]` (and
+/// optionally `--p2wsh`), or a secret key as the first positional argument.
+fn parse_signer(args: &mut Vec<String>, usage: &str) -> Result<TransactionSigner, CliError> {
+    let p2wsh = take_flag(args, "--p2wsh");
+    if let Some(ix) = args.iter().position(|x| x == "--multisig") {
+        if ix + 2 >= args.len() {
+            return Err(CliError::Message(format!("USAGE:\n {}", usage)));
+        }
+        let multisig_args: Vec<String> = args.drain(ix..ix + 3).collect();
+        let condition = make_multisig_condition(&multisig_args[1], &multisig_args[2], p2wsh)?;
+        return Ok(TransactionSigner {
+            secret_key: None,
+            condition,
+        });
+    }
+    if p2wsh {
+        return Err("--p2wsh requires --multisig".into());
+    }
+    if args.is_empty() {
+        return Err(CliError::Message(format!(
+            "Incorrect argument count supplied \n\nUSAGE:\n {}",
+            usage
+        )));
+    }
+
+    let secret_key = StacksPrivateKey::from_hex(&args.remove(0))?;
+    let condition = TransactionSpendingCondition::new_singlesig_p2pkh(
+        StacksPublicKey::from_private(&secret_key),
+    )
+    .expect("Failed to create p2pkh spending condition from public key.");
+    Ok(TransactionSigner {
+        secret_key: Some(secret_key),
+        condition,
+    })
+}
+
+fn serialize_transaction(tx: &StacksTransaction) -> String {
+    let mut tx_bytes = vec![];
+    tx.consensus_serialize(&mut tx_bytes)
+        .expect("FATAL: invalid transaction");
+    to_hex(&tx_bytes)
+}
+
+fn deserialize_transaction(transaction: &str) -> Result<StacksTransaction, CliError> {
+    Ok(StacksTransaction::deserialize_partially_signed(
+        &mut io::Cursor::new(&hex_bytes(transaction)?),
+    )?)
+}
+
+/// Post-conditions in the format of `--post-conditions` files, which `decode-tx` prints back
+/// as `post_condition_spec`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PostConditionSpec {
+    /// `allow` or `deny`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<String>,
+    #[serde(default)]
+    post_conditions: Vec<PostConditionEntry>,
+}
+
+/// One post-condition.  Principals are `origin`, a standard address or a contract identifier,
+/// and assets are `[contract-identifier]::[asset-name]`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum PostConditionEntry {
+    Stx {
+        principal: String,
+        condition: String,
+        amount: u64,
+    },
+    Ft {
+        principal: String,
+        asset: String,
+        condition: String,
+        amount: u64,
+    },
+    /// The asset's value is given either as a Clarity expression, or hex-serialized.
+    Nft {
+        principal: String,
+        asset: String,
+        condition: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value_hex: Option<String>,
+    },
+}
+
+fn parse_post_condition_mode(mode: &str) -> Result<TransactionPostConditionMode, CliError> {
+    match mode {
+        "allow" => Ok(TransactionPostConditionMode::Allow),
+        "deny" => Ok(TransactionPostConditionMode::Deny),
+        _ => Err(CliError::Message(format!(
+            "Unknown post-condition mode `{}`: expected `allow` or `deny`",
+            mode
+        ))),
+    }
+}
+
+fn parse_post_condition_principal(principal: &str) -> Result<PostConditionPrincipal, CliError> {
+    if principal == "origin" {
+        return Ok(PostConditionPrincipal::Origin);
+    }
+    match PrincipalData::parse(principal)? {
+        PrincipalData::Standard(standard) => Ok(PostConditionPrincipal::Standard(standard.into())),
+        PrincipalData::Contract(contract) => Ok(PostConditionPrincipal::Contract(
+            contract.issuer.into(),
+            contract.name,
+        )),
+    }
+}
+
+fn parse_asset_info(asset: &str) -> Result<AssetInfo, CliError> {
+    let (contract, asset_name) = asset.split_once("::").ok_or_else(|| {
+        CliError::Message(format!(
+            "Bad asset `{}`: expected [contract-identifier]::[asset-name]",
+            asset
+        ))
+    })?;
+    let contract = QualifiedContractIdentifier::parse(contract)?;
+    Ok(AssetInfo {
+        contract_address: contract.issuer.into(),
+        contract_name: contract.name,
+        asset_name: ClarityName::try_from(asset_name.to_string())?,
+    })
+}
+
+fn parse_fungible_condition_code(condition: &str) -> Result<FungibleConditionCode, CliError> {
+    match condition {
+        "sent-eq" => Ok(FungibleConditionCode::SentEq),
+        "sent-gt" => Ok(FungibleConditionCode::SentGt),
+        "sent-ge" => Ok(FungibleConditionCode::SentGe),
+        "sent-lt" => Ok(FungibleConditionCode::SentLt),
+        "sent-le" => Ok(FungibleConditionCode::SentLe),
+        _ => Err(CliError::Message(format!(
+            "Unknown fungible post-condition `{}`: expected sent-eq, sent-gt, sent-ge, sent-lt or sent-le",
+            condition
+        ))),
+    }
+}
+
+fn parse_nonfungible_condition_code(condition: &str) -> Result<NonfungibleConditionCode, CliError> {
+    match condition {
+        "sent" => Ok(NonfungibleConditionCode::Sent),
+        "not-sent" => Ok(NonfungibleConditionCode::NotSent),
+        _ => Err(CliError::Message(format!(
+            "Unknown non-fungible post-condition `{}`: expected sent or not-sent",
+            condition
+        ))),
+    }
+}
+
+impl PostConditionEntry {
+    fn to_post_condition(&self) -> Result<TransactionPostCondition, CliError> {
+        match self {
+            PostConditionEntry::Stx {
+                principal,
+                condition,
+                amount,
+            } => Ok(TransactionPostCondition::STX(
+                parse_post_condition_principal(principal)?,
+                parse_fungible_condition_code(condition)?,
+                *amount,
+            )),
+            PostConditionEntry::Ft {
+                principal,
+                asset,
+                condition,
+                amount,
+            } => Ok(TransactionPostCondition::Fungible(
+                parse_post_condition_principal(principal)?,
+                parse_asset_info(asset)?,
+                parse_fungible_condition_code(condition)?,
+                *amount,
+            )),
+            PostConditionEntry::Nft {
+                principal,
+                asset,
+                condition,
+                value,
+                value_hex,
+            } => {
+                let value = match (value, value_hex) {
+                    (Some(value), None) => blockstack_lib::clarity_cli::vm_execute(value)?
+                        .ok_or("Supplied post-condition value did not evaluate to a Value")?,
+                    (None, Some(value_hex)) => Value::try_deserialize_hex_untyped(value_hex)?,
+                    _ => return Err(
+                        "Non-fungible post-conditions need exactly one of `value` or `value_hex`"
+                            .into(),
+                    ),
+                };
+                Ok(TransactionPostCondition::Nonfungible(
+                    parse_post_condition_principal(principal)?,
+                    parse_asset_info(asset)?,
+                    value,
+                    parse_nonfungible_condition_code(condition)?,
+                ))
+            }
+        }
+    }
+
+    fn from_post_condition(post_condition: &TransactionPostCondition) -> PostConditionEntry {
+        let principal = |principal: &PostConditionPrincipal| match principal {
+            PostConditionPrincipal::Origin => "origin".to_string(),
+            PostConditionPrincipal::Standard(address) => address.to_string(),
+            PostConditionPrincipal::Contract(address, name) => format!("{}.{}", address, name),
+        };
+        let asset = |asset: &AssetInfo| {
+            format!(
+                "{}.{}::{}",
+                asset.contract_address, asset.contract_name, asset.asset_name
+            )
+        };
+        let fungible_condition = |condition: &FungibleConditionCode| {
+            match condition {
+                FungibleConditionCode::SentEq => "sent-eq",
+                FungibleConditionCode::SentGt => "sent-gt",
+                FungibleConditionCode::SentGe => "sent-ge",
+                FungibleConditionCode::SentLt => "sent-lt",
+                FungibleConditionCode::SentLe => "sent-le",
+            }
+            .to_string()
+        };
+        match post_condition {
+            TransactionPostCondition::STX(pc_principal, condition, amount) => {
+                PostConditionEntry::Stx {
+                    principal: principal(pc_principal),
+                    condition: fungible_condition(condition),
+                    amount: *amount,
+                }
+            }
+            TransactionPostCondition::Fungible(pc_principal, asset_info, condition, amount) => {
+                PostConditionEntry::Ft {
+                    principal: principal(pc_principal),
+                    asset: asset(asset_info),
+                    condition: fungible_condition(condition),
+                    amount: *amount,
+                }
+            }
+            TransactionPostCondition::Nonfungible(pc_principal, asset_info, value, condition) => {
+                PostConditionEntry::Nft {
+                    principal: principal(pc_principal),
+                    asset: asset(asset_info),
+                    condition: match condition {
+                        NonfungibleConditionCode::Sent => "sent",
+                        NonfungibleConditionCode::NotSent => "not-sent",
+                    }
+                    .to_string(),
+                    value: None,
+                    value_hex: Some(value.serialize()),
+                }
+            }
+        }
+    }
+}
+
+/// Options shared by the commands that build a transaction.
+struct TransactionOptions {
+    anchor_mode: TransactionAnchorMode,
+    sponsored: bool,
+    /// Left as the transaction's default if not given
+    post_condition_mode: Option<TransactionPostConditionMode>,
+    post_conditions: Vec<TransactionPostCondition>,
+    node: Option<NodeClient>,
+}
+
+/// Remove the `count` arguments following the flag at `ix`, along with the flag itself.
+fn take_flag_args(
+    args: &mut Vec<String>,
+    ix: usize,
+    count: usize,
+    usage: &str,
+) -> Result<Vec<String>, CliError> {
+    if ix + count >= args.len() {
+        return Err(CliError::Message(format!("USAGE:\n {}", usage)));
+    }
+    Ok(args.drain(ix..ix + count + 1).skip(1).collect())
+}
+
+fn parse_transaction_options(
+    args: &mut Vec<String>,
+    usage: &str,
+) -> Result<TransactionOptions, CliError> {
+    let anchor_mode = parse_anchor_mode(args, usage)?;
+    let sponsored = take_flag(args, "--sponsored");
+    let node = parse_node(args, usage)?;
+
+    // post-conditions keep the order they are given in
+    let mut spec = PostConditionSpec::default();
+    let mut ix = 0;
+    while ix < args.len() {
+        match args[ix].as_str() {
+            "--post-condition-mode" => {
+                spec.mode = Some(take_flag_args(args, ix, 1, usage)?.remove(0));
+            }
+            "--post-conditions" => {
+                let path = take_flag_args(args, ix, 1, usage)?.remove(0);
+                let file_spec: PostConditionSpec =
+                    serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+                        CliError::Message(format!("Bad post-condition file {}: {}", path, e))
+                    })?;
+                if file_spec.mode.is_some() {
+                    spec.mode = file_spec.mode;
+                }
+                spec.post_conditions.extend(file_spec.post_conditions);
+            }
+            "--stx-post-condition" => {
+                let mut flag_args = take_flag_args(args, ix, 3, usage)?.into_iter();
+                spec.post_conditions.push(PostConditionEntry::Stx {
+                    principal: flag_args.next().unwrap(),
+                    condition: flag_args.next().unwrap(),
+                    amount: flag_args.next().unwrap().parse()?,
+                });
+            }
+            "--ft-post-condition" => {
+                let mut flag_args = take_flag_args(args, ix, 4, usage)?.into_iter();
+                spec.post_conditions.push(PostConditionEntry::Ft {
+                    principal: flag_args.next().unwrap(),
+                    asset: flag_args.next().unwrap(),
+                    condition: flag_args.next().unwrap(),
+                    amount: flag_args.next().unwrap().parse()?,
+                });
+            }
+            "--nft-post-condition" => {
+                let mut flag_args = take_flag_args(args, ix, 4, usage)?.into_iter();
+                spec.post_conditions.push(PostConditionEntry::Nft {
+                    principal: flag_args.next().unwrap(),
+                    asset: flag_args.next().unwrap(),
+                    condition: flag_args.next().unwrap(),
+                    value: flag_args.next(),
+                    value_hex: None,
+                });
+            }
+            _ => ix += 1,
+        }
+    }
+
+    let post_condition_mode = match spec.mode {
+        Some(ref mode) => Some(parse_post_condition_mode(mode)?),
+        None => None,
+    };
+    let post_conditions = spec
+        .post_conditions
+        .iter()
+        .map(|entry| entry.to_post_condition())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(TransactionOptions {
+        anchor_mode,
+        sponsored,
+        post_condition_mode,
+        post_conditions,
+        node,
+    })
+}
+
+/// How long to wait for a node to answer, and for a broadcast transaction to show up in
+/// `/v2/transactions/unconfirmed`
+const NODE_TIMEOUT_SECS: u64 = 30;
+
+/// A node to look up nonces and fees from, and to broadcast transactions to (`--node`).
+struct NodeClient {
+    host: PeerHost,
+    addr: SocketAddr,
+}
+
+impl NodeClient {
+    fn new(url: &str) -> Result<NodeClient, CliError> {
+        let url = url::Url::parse(url)
+            .map_err(|e| CliError::Message(format!("Bad node URL {}: {}", url, e)))?;
+        if url.scheme() != "http" {
+            return Err("Only http:// node URLs are supported".into());
+        }
+        let host = url.host_str().ok_or("Node URL has no host")?.to_string();
+        let port = url.port_or_known_default().ok_or("Node URL has no port")?;
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| CliError::Message(format!("Failed to resolve {}", host)))?;
+        Ok(NodeClient {
+            host: PeerHost::from_host_port(host, port),
+            addr,
+        })
+    }
+
+    fn metadata(&self) -> HttpRequestMetadata {
+        HttpRequestMetadata::from_host(self.host.clone(), None)
+    }
+
+    fn request(&self, request: HttpRequestType) -> Result<HttpResponseType, CliError> {
+        let response = StacksHttp::send_request_blocking(
+            &self.addr,
+            &request,
+            Duration::from_secs(NODE_TIMEOUT_SECS),
+        )?;
+        match response {
+            HttpResponseType::BadRequestJSON(_, reason) => Err(CliError::Message(format!(
+                "Node rejected {}: {}",
+                request.request_path(),
+                reason
+            ))),
+            HttpResponseType::BadRequest(_, reason)
+            | HttpResponseType::Unauthorized(_, reason)
+            | HttpResponseType::PaymentRequired(_, reason)
+            | HttpResponseType::Forbidden(_, reason)
+            | HttpResponseType::ServerError(_, reason)
+            | HttpResponseType::ServiceUnavailable(_, reason)
+            | HttpResponseType::Error(_, _, reason) => Err(CliError::Message(format!(
+                "Node rejected {}: {}",
+                request.request_path(),
+                reason
+            ))),
+            response => Ok(response),
+        }
+    }
+
+    fn get_nonce(&self, address: &StacksAddress) -> Result<u64, CliError> {
+        let principal = PrincipalData::Standard(address.clone().into());
+        match self.request(HttpRequestType::GetAccount(
+            self.metadata(),
+            principal,
+            TipRequest::UseLatestAnchoredTip,
+            false,
+        ))? {
+            HttpResponseType::GetAccount(_, account) => Ok(account.nonce),
+            response => Err(unexpected_response(&response)),
+        }
+    }
+
+    fn get_contract_interface(
+        &self,
+        address: &StacksAddress,
+        contract_name: &ContractName,
+    ) -> Result<ContractInterface, CliError> {
+        match self.request(HttpRequestType::GetContractABI(
+            self.metadata(),
+            address.clone(),
+            contract_name.clone(),
+            TipRequest::UseLatestAnchoredTip,
+        ))? {
+            HttpResponseType::GetContractABI(_, interface) => Ok(interface),
+            HttpResponseType::NotFound(..) => Err(CliError::Message(format!(
+                "Node has no contract {}.{}",
+                address, contract_name
+            ))),
+            response => Err(unexpected_response(&response)),
+        }
+    }
+
+    /// Estimate the fee of a transaction with `payload` and `estimated_len` bytes, at the
+    /// node's middle fee rate.
+    fn estimate_fee(
+        &self,
+        payload: &TransactionPayload,
+        estimated_len: u64,
+    ) -> Result<u64, CliError> {
+        match self.request(HttpRequestType::FeeRateEstimate(
+            self.metadata(),
+            payload.clone(),
+            estimated_len,
+        ))? {
+            HttpResponseType::TransactionFeeEstimation(_, estimate) => estimate
+                .estimations
+                .get(1)
+                .map(|estimation| estimation.fee)
+                .ok_or_else(|| "Node did not return a middle fee estimate".into()),
+            response => Err(unexpected_response(&response)),
+        }
+    }
+
+    /// Broadcast a signed transaction, and wait for it to show up as unconfirmed.  Returns the
+    /// transaction and its status as JSON.
+    fn broadcast(&self, tx: &StacksTransaction) -> Result<String, CliError> {
+        let txid = match self.request(HttpRequestType::PostTransaction(
+            self.metadata(),
+            tx.clone(),
+            None,
+        ))? {
+            HttpResponseType::TransactionID(_, txid) => txid,
+            response => return Err(unexpected_response(&response)),
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(NODE_TIMEOUT_SECS);
+        loop {
+            match self.request(HttpRequestType::GetTransactionUnconfirmed(
+                self.metadata(),
+                txid.clone(),
+            ))? {
+                HttpResponseType::UnconfirmedTransaction(_, unconfirmed) => {
+                    let result = serde_json::json!({
+                        "txid": txid.to_hex(),
+                        "tx": serialize_transaction(tx),
+                        "status": unconfirmed.status,
+                    });
+                    return Ok(serde_json::to_string_pretty(&result)
+                        .expect("Failed to serialize broadcast result to JSON"));
+                }
+                HttpResponseType::NotFound(..) => {}
+                response => return Err(unexpected_response(&response)),
+            }
+            if Instant::now() >= deadline {
+                return Err(CliError::Message(format!(
+                    "Transaction {} was accepted, but did not show up as unconfirmed within {} seconds (it may already be mined)",
+                    txid, NODE_TIMEOUT_SECS
+                )));
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+fn unexpected_response(response: &HttpResponseType) -> CliError {
+    CliError::Message(format!("Unexpected response from node: {:?}", response))
+}
+
+/// Remove `--node [url]` from `args`.
+fn parse_node(args: &mut Vec<String>, usage: &str) -> Result<Option<NodeClient>, CliError> {
+    match args.iter().position(|x| x == "--node") {
+        Some(ix) => {
+            let url = take_flag_args(args, ix, 1, usage)?.remove(0);
+            Ok(Some(NodeClient::new(&url)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Load the interface of the contract being called, from `--abi [file.json]` if given, or
+/// else from `--node`.  Returns `None` if neither is given.
+fn load_contract_interface(
+    abi_path: Option<String>,
+    node: &Option<NodeClient>,
+    address: &StacksAddress,
+    contract_name: &ContractName,
+) -> Result<Option<ContractInterface>, CliError> {
+    if let Some(path) = abi_path {
+        let interface = serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+            CliError::Message(format!(
+                "Failed to parse contract interface {}: {}",
+                path, e
+            ))
+        })?;
+        return Ok(Some(interface));
+    }
+    match node {
+        Some(node) => Ok(Some(node.get_contract_interface(address, contract_name)?)),
+        None => Ok(None),
+    }
+}
+
+fn find_public_function<'a>(
+    interface: &'a ContractInterface,
+    function_name: &str,
+) -> Result<&'a ContractInterfaceFunction, CliError> {
+    let function = interface
+        .functions
+        .iter()
+        .find(|function| function.name == function_name)
+        .ok_or_else(|| {
+            CliError::Message(format!("The contract has no function {}", function_name))
+        })?;
+    if function.access != ContractInterfaceFunctionAccess::public {
+        return Err(CliError::Message(format!(
+            "{} is not a public function",
+            function_name
+        )));
+    }
+    Ok(function)
+}
+
+fn json_to_integer<T: std::str::FromStr>(
+    json: &serde_json::Value,
+    path: &str,
+    expected: &str,
+) -> Result<T, CliError> {
+    // integers too big for a JSON number are given as strings
+    let repr = match json {
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        _ => String::new(),
+    };
+    repr.parse()
+        .map_err(|_| CliError::Message(format!("{}: expected {}, got {}", path, expected, json)))
+}
+
+/// Convert a JSON argument to a Clarity value of type `atom_type`.  `path` locates the part
+/// of the argument being converted in error messages.
+fn json_to_value(
+    json: &serde_json::Value,
+    atom_type: &ContractInterfaceAtomType,
+    path: &str,
+) -> Result<Value, CliError> {
+    use serde_json::Value as Json;
+
+    let mismatch = |expected: &str| {
+        CliError::Message(format!("{}: expected {}, got {}", path, expected, json))
+    };
+    let invalid = |e: ClarityError| CliError::Message(format!("{}: {}", path, e));
+
+    let value = match (atom_type, json) {
+        (ContractInterfaceAtomType::none, Json::Null) => Value::none(),
+        (ContractInterfaceAtomType::int128, _) => {
+            Value::Int(json_to_integer(json, path, "an int")?)
+        }
+        (ContractInterfaceAtomType::uint128, _) => {
+            Value::UInt(json_to_integer(json, path, "a uint")?)
+        }
+        (ContractInterfaceAtomType::bool, Json::Bool(b)) => Value::Bool(*b),
+        (ContractInterfaceAtomType::principal, Json::String(s))
+        | (ContractInterfaceAtomType::trait_reference, Json::String(s)) => {
+            Value::Principal(PrincipalData::parse(s).map_err(|_| mismatch("a principal"))?)
+        }
+        (ContractInterfaceAtomType::buffer { .. }, Json::String(s)) => {
+            let hex = s.strip_prefix("0x").unwrap_or(s);
+            let bytes = hex_bytes(hex).map_err(|_| mismatch("a hex buffer"))?;
+            Value::buff_from(bytes).map_err(invalid)?
+        }
+        (ContractInterfaceAtomType::string_ascii { .. }, Json::String(s)) => {
+            Value::string_ascii_from_bytes(s.as_bytes().to_vec()).map_err(invalid)?
+        }
+        (ContractInterfaceAtomType::string_utf8 { .. }, Json::String(s)) => {
+            Value::string_utf8_from_bytes(s.as_bytes().to_vec()).map_err(invalid)?
+        }
+        (ContractInterfaceAtomType::optional(_), Json::Null) => Value::none(),
+        (ContractInterfaceAtomType::optional(inner), _) => {
+            Value::some(json_to_value(json, inner, path)?).map_err(invalid)?
+        }
+        (ContractInterfaceAtomType::response { ok, error }, Json::Object(fields))
+            if fields.len() == 1 =>
+        {
+            if let Some(inner) = fields.get("ok") {
+                Value::okay(json_to_value(inner, ok, &format!("{}.ok", path))?).map_err(invalid)?
+            } else if let Some(inner) = fields.get("error") {
+                Value::error(json_to_value(inner, error, &format!("{}.error", path))?)
+                    .map_err(invalid)?
+            } else {
+                return Err(mismatch("{\"ok\": ...} or {\"error\": ...}"));
+            }
+        }
+        (ContractInterfaceAtomType::tuple(entries), Json::Object(fields)) => {
+            if let Some(name) = fields
+                .keys()
+                .find(|name| !entries.iter().any(|entry| &entry.name == *name))
+            {
+                return Err(CliError::Message(format!(
+                    "{}: unexpected tuple field {}",
+                    path, name
+                )));
+            }
+            let mut data = vec![];
+            for entry in entries.iter() {
+                let field_path = format!("{}.{}", path, entry.name);
+                let field = fields.get(&entry.name).ok_or_else(|| {
+                    CliError::Message(format!("{}: missing tuple field", field_path))
+                })?;
+                data.push((
+                    ClarityName::try_from(entry.name.clone())?,
+                    json_to_value(field, &entry.type_f, &field_path)?,
+                ));
+            }
+            Value::Tuple(TupleData::from_data(data).map_err(invalid)?)
+        }
+        (ContractInterfaceAtomType::list { type_f, .. }, Json::Array(items)) => {
+            let mut values = vec![];
+            for (i, item) in items.iter().enumerate() {
+                values.push(json_to_value(item, type_f, &format!("{}[{}]", path, i))?);
+            }
+            Value::list_from(values).map_err(invalid)?
+        }
+        (ContractInterfaceAtomType::none, _) => return Err(mismatch("null")),
+        (ContractInterfaceAtomType::bool, _) => return Err(mismatch("a bool")),
+        (ContractInterfaceAtomType::principal, _)
+        | (ContractInterfaceAtomType::trait_reference, _) => return Err(mismatch("a principal")),
+        (ContractInterfaceAtomType::buffer { .. }, _) => return Err(mismatch("a hex buffer")),
+        (ContractInterfaceAtomType::string_ascii { .. }, _)
+        | (ContractInterfaceAtomType::string_utf8 { .. }, _) => return Err(mismatch("a string")),
+        (ContractInterfaceAtomType::response { .. }, _) => {
+            return Err(mismatch("{\"ok\": ...} or {\"error\": ...}"))
+        }
+        (ContractInterfaceAtomType::tuple(_), _) => return Err(mismatch("an object")),
+        (ContractInterfaceAtomType::list { .. }, _) => return Err(mismatch("an array")),
+    };
+    Ok(value)
+}
+
+/// Convert the `index`th of `num_args` arguments from JSON, using its type in `function`.
+fn parse_json_arg(
+    function: Option<&ContractInterfaceFunction>,
+    index: usize,
+    num_args: usize,
+    input: &str,
+) -> Result<Value, CliError> {
+    let function =
+        function.ok_or("`-j` arguments need the contract interface, from --abi or --node")?;
+    let arg = function.args.get(index).ok_or_else(|| {
+        CliError::Message(format!(
+            "{} takes {} arguments, but {} were supplied",
+            function.name,
+            function.args.len(),
+            num_args
+        ))
+    })?;
+    let json = serde_json::from_str(input).map_err(|e| {
+        CliError::Message(format!("Argument {} is not valid JSON: {}", arg.name, e))
+    })?;
+    json_to_value(&json, &arg.type_f, &arg.name)
+}
+
+/// Check that `values` are the right number and types of arguments for `function`.
+fn check_function_args(
+    function: &ContractInterfaceFunction,
+    values: &[Value],
+) -> Result<(), CliError> {
+    if values.len() != function.args.len() {
+        return Err(CliError::Message(format!(
+            "{} takes {} arguments, but {} were supplied",
+            function.name,
+            function.args.len(),
+            values.len()
+        )));
+    }
+    for (arg, value) in function.args.iter().zip(values.iter()) {
+        let (admitted, expected) = match arg.type_f {
+            // the interface does not say which trait, but it must be a contract
+            ContractInterfaceAtomType::trait_reference => (
+                matches!(value, Value::Principal(PrincipalData::Contract(_))),
+                "trait reference".to_string(),
+            ),
+            _ => {
+                let type_sig = arg.type_f.to_type_signature().map_err(|e| {
+                    CliError::Message(format!("Bad type for argument {}: {}", arg.name, e))
+                })?;
+                (
+                    type_sig.admits(value).unwrap_or(false),
+                    type_sig.to_string(),
+                )
+            }
+        };
+        if !admitted {
+            return Err(CliError::Message(format!(
+                "Argument {} must be a {}, but got {}",
+                arg.name, expected, value
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a fee rate or nonce, which may be `auto` to look it up from `--node`.
+fn parse_auto(arg: &str, node: &Option<NodeClient>) -> Result<Option<u64>, CliError> {
+    if arg == "auto" {
+        if node.is_none() {
+            return Err("`auto` fees and nonces need --node".into());
+        }
+        Ok(None)
+    } else {
+        Ok(Some(arg.parse()?))
+    }
+}
+
+/// Use the given nonce, or look up the account's next nonce from the node.
+fn lookup_nonce(
+    nonce: Option<u64>,
+    node: &Option<NodeClient>,
+    condition: &TransactionSpendingCondition,
+    version: TransactionVersion,
+) -> Result<u64, CliError> {
+    match (nonce, node) {
+        (Some(nonce), _) => Ok(nonce),
+        (None, Some(node)) => node.get_nonce(&match version {
+            TransactionVersion::Mainnet => condition.address_mainnet(),
+            TransactionVersion::Testnet => condition.address_testnet(),
+        }),
+        (None, None) => Err("`auto` fees and nonces need --node".into()),
+    }
+}
+
+/// How long `tx` will be once all of its signers have signed: each multisig signer still to
+/// sign turns a public key field into a longer signature field.
+fn estimate_signed_len(tx: &StacksTransaction) -> u64 {
+    let mut len = serialize_transaction(tx).len() as u64 / 2;
+    for condition in [Some(tx.auth.origin()), tx.auth.sponsor()].iter().flatten() {
+        if let TransactionSpendingCondition::Multisig(_) = condition {
+            let unsigned = condition
+                .signatures_required()
+                .saturating_sub(condition.num_signatures());
+            len += u64::from(unsigned) * u64::from(MESSAGE_SIGNATURE_ENCODED_SIZE - 33);
+        }
+    }
+    len
+}
+
+/// Output a transaction, broadcasting it first if a node is given and it is fully signed.
+fn output_transaction(
+    tx: &StacksTransaction,
+    node: &Option<NodeClient>,
+) -> Result<String, CliError> {
+    match node {
+        Some(node) if tx.verify().is_ok() => node.broadcast(tx),
+        _ => Ok(serialize_transaction(tx)),
+    }
+}
+
+/// Build a transaction authorized by `origin`, and sign it if the origin has a single
+/// signature.  A sponsored transaction carries a placeholder sponsor until `sponsor` fills it
+/// in.  Returns the hex-encoded transaction, or the broadcast result if it is fully signed
+/// and a node is given.
+fn make_transaction(
+    version: TransactionVersion,
+    chain_id: u32,
+    payload: TransactionPayload,
+    options: TransactionOptions,
+    origin: TransactionSigner,
+    nonce: Option<u64>,
+    tx_fee: Option<u64>,
+) -> Result<String, CliError> {
+    let mut condition = origin.condition;
+    condition.set_nonce(lookup_nonce(nonce, &options.node, &condition, version)?);
+    condition.set_tx_fee(tx_fee.unwrap_or(0));
+    let auth = if options.sponsored {
+        TransactionAuth::Sponsored(
+            condition,
+            TransactionSpendingCondition::new_initial_sighash(),
+        )
+    } else {
+        TransactionAuth::Standard(condition)
+    };
+    let mut tx = StacksTransaction::new(version, auth, payload);
+    tx.chain_id = chain_id;
+    tx.anchor_mode = options.anchor_mode;
+    if let Some(post_condition_mode) = options.post_condition_mode {
+        tx.post_condition_mode = post_condition_mode;
+    }
+    tx.post_conditions = options.post_conditions;
+
+    // the sponsor pays the fee of a sponsored transaction
+    if let (None, Some(node), false) = (tx_fee, &options.node, options.sponsored) {
+        let tx_fee = node.estimate_fee(&tx.payload, estimate_signed_len(&tx))?;
+        tx.set_tx_fee(tx_fee);
+    }
+
+    if let Some(secret_key) = origin.secret_key {
+        let mut tx_signer = StacksTransactionSigner::new(&tx);
+        tx_signer.sign_origin(&secret_key)?;
+        tx = tx_signer.get_tx_incomplete();
+    }
+    output_transaction(&tx, &options.node)
+}
+
+/// Replace `secret_key`'s public key field in a multisig condition with its signature.
+/// Each signature commits to the signatures before it, so signers must sign in the order of
+/// their public keys.  Returns false if the key is not an unsigned signer of the condition.
+fn sign_multisig_condition(
+    condition: &mut TransactionSpendingCondition,
+    initial_sighash: &Txid,
+    auth_flag: &TransactionAuthFlags,
+    secret_key: &StacksPrivateKey,
+) -> Result<bool, CliError> {
+    let multisig = match condition {
+        TransactionSpendingCondition::Multisig(ref mut multisig) => multisig,
+        TransactionSpendingCondition::Singlesig(_) => return Ok(false),
+    };
+    let public_key = StacksPublicKey::from_private(secret_key);
+    let slot = match multisig
+        .fields
+        .iter()
+        .position(|field| field.as_public_key().as_ref() == Some(&public_key))
+    {
+        Some(slot) => slot,
+        None => return Ok(false),
+    };
+
+    let num_sigs = multisig.fields.iter().filter(|f| f.is_signature()).count();
+    if num_sigs >= usize::from(multisig.signatures_required) {
+        return Err(CliError::Message(format!(
+            "Transaction already has all {} required signatures",
+            multisig.signatures_required
+        )));
+    }
+    if multisig.fields[slot + 1..].iter().any(|f| f.is_signature()) {
+        return Err("A signer after this key has already signed: multisig signers must sign in the order of their public keys".into());
+    }
+
+    let mut sighash = initial_sighash.clone();
+    for field in multisig.fields[..slot].iter() {
+        if let TransactionAuthField::Signature(ref key_encoding, ref signature) = field {
+            let (_, next_sighash) = TransactionSpendingCondition::next_verification(
+                &sighash,
+                auth_flag,
+                multisig.tx_fee,
+                multisig.nonce,
+                key_encoding,
+                signature,
+            )?;
+            sighash = next_sighash;
+        }
+    }
+    let (signature, _) = TransactionSpendingCondition::next_signature(
+        &sighash,
+        auth_flag,
+        multisig.tx_fee,
+        multisig.nonce,
+        secret_key,
+    )?;
+    let key_encoding = if public_key.compressed() {
+        TransactionPublicKeyEncoding::Compressed
+    } else {
+        TransactionPublicKeyEncoding::Uncompressed
+    };
+    multisig.fields[slot] = TransactionAuthField::Signature(key_encoding, signature);
+    Ok(true)
+}
+
 fn handle_contract_publish(
     args_slice: &[String],
     version: TransactionVersion,
@@ -364,18 +1476,18 @@ fn handle_contract_publish(
     if args.len() >= 1 && args[0] == "-h" {
         return Err(CliError::Message(format!("USAGE:\n {}", PUBLISH_USAGE)));
     }
-    if args.len() != 5 {
+    let options = parse_transaction_options(&mut args, PUBLISH_USAGE)?;
+    let publisher = parse_signer(&mut args, PUBLISH_USAGE)?;
+    if args.len() != 4 {
         return Err(CliError::Message(format!(
             "Incorrect argument count supplied \n\nUSAGE:\n {}",
             PUBLISH_USAGE
         )));
     }
-    let anchor_mode = parse_anchor_mode(&mut args, PUBLISH_USAGE)?;
-    let sk_publisher = &args[0];
-    let tx_fee = args[1].parse()?;
-    let nonce = args[2].parse()?;
-    let contract_name = &args[3];
-    let contract_file = &args[4];
+    let tx_fee = parse_auto(&args[0], &options.node)?;
+    let nonce = parse_auto(&args[1], &options.node)?;
+    let contract_name = &args[2];
+    let contract_file = &args[3];
 
     let contract_contents = if contract_file == "-" {
         let mut buffer = String::new();
@@ -385,31 +1497,16 @@ fn handle_contract_publish(
         fs::read_to_string(contract_file)?
     };
 
-    let sk_publisher = StacksPrivateKey::from_hex(sk_publisher)?;
-
     let payload = make_contract_publish(contract_name.clone(), contract_contents)?;
-    let mut unsigned_tx = make_standard_single_sig_tx(
+    make_transaction(
         version,
         chain_id,
         payload.into(),
-        &StacksPublicKey::from_private(&sk_publisher),
+        options,
+        publisher,
         nonce,
         tx_fee,
-    );
-    unsigned_tx.anchor_mode = anchor_mode;
-
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx
-        .consensus_serialize(&mut unsigned_tx_bytes)
-        .expect("FATAL: invalid transaction");
-    let signed_tx =
-        sign_transaction_single_sig_standard(&to_hex(&unsigned_tx_bytes), &sk_publisher)?;
-
-    let mut signed_tx_bytes = vec![];
-    signed_tx
-        .consensus_serialize(&mut signed_tx_bytes)
-        .expect("FATAL: invalid signed transaction");
-    Ok(to_hex(&signed_tx_bytes))
+    )
 }
 
 fn handle_contract_call(
@@ -421,29 +1518,44 @@ fn handle_contract_call(
     if args.len() >= 1 && args[0] == "-h" {
         return Err(CliError::Message(format!("USAGE:\n {}", CALL_USAGE)));
     }
-    if args.len() < 6 {
+    let options = parse_transaction_options(&mut args, CALL_USAGE)?;
+    let abi_path = match args.iter().position(|x| x == "--abi") {
+        Some(ix) => Some(take_flag_args(&mut args, ix, 1, CALL_USAGE)?.remove(0)),
+        None => None,
+    };
+    let origin = parse_signer(&mut args, CALL_USAGE)?;
+    if args.len() < 5 {
         return Err(CliError::Message(format!(
             "Incorrect argument count supplied \n\nUSAGE:\n {}",
             CALL_USAGE
         )));
     }
-    let anchor_mode = parse_anchor_mode(&mut args, CALL_USAGE)?;
-    let sk_origin = &args[0];
-    let tx_fee = args[1].parse()?;
-    let nonce = args[2].parse()?;
-    let contract_address = &args[3];
-    let contract_name = &args[4];
-    let function_name = &args[5];
+    let tx_fee = parse_auto(&args[0], &options.node)?;
+    let nonce = parse_auto(&args[1], &options.node)?;
+    let contract_address = &args[2];
+    let contract_name = &args[3];
+    let function_name = &args[4];
 
-    let val_args = &args[6..];
+    let val_args = &args[5..];
 
     if val_args.len() % 2 != 0 {
         return Err(
-            "contract-call arguments must be supplied as a list of `-e ...` or `-x 0000...` pairs"
+            "contract-call arguments must be supplied as a list of `-e ...`, `-x 0000...` or `-j ...` pairs"
                 .into(),
         );
     }
 
+    let interface = load_contract_interface(
+        abi_path,
+        &options.node,
+        &StacksAddress::from_string(contract_address).ok_or("Failed to parse contract address")?,
+        &ContractName::try_from(contract_name.clone())?,
+    )?;
+    let function = match &interface {
+        Some(interface) => Some(find_public_function(interface, function_name)?),
+        None => None,
+    };
+
     let mut arg_iterator = 0;
     let mut values = Vec::new();
     while arg_iterator < val_args.len() {
@@ -457,8 +1569,11 @@ fn handle_contract_call(
                 blockstack_lib::clarity_cli::vm_execute(input)?
                     .ok_or("Supplied argument did not evaluate to a Value")?
             },
+            "-j" => {
+                parse_json_arg(function, values.len(), val_args.len() / 2, input)?
+            },
             _ => {
-                return Err("contract-call arguments must be supplied as a list of `-e ...` or `-x 0000...` pairs".into())
+                return Err("contract-call arguments must be supplied as a list of `-e ...`, `-x 0000...` or `-j ...` pairs".into())
             }
         };
 
@@ -466,7 +1581,10 @@ fn handle_contract_call(
         arg_iterator += 2;
     }
 
-    let sk_origin = StacksPrivateKey::from_hex(sk_origin)?;
+    // catch arity and type errors before signing
+    if let Some(function) = function {
+        check_function_args(function, &values)?;
+    }
 
     let payload = make_contract_call(
         contract_address.clone(),
@@ -474,27 +1592,15 @@ fn handle_contract_call(
         function_name.clone(),
         values,
     )?;
-    let mut unsigned_tx = make_standard_single_sig_tx(
+    make_transaction(
         version,
         chain_id,
         payload.into(),
-        &StacksPublicKey::from_private(&sk_origin),
+        options,
+        origin,
         nonce,
         tx_fee,
-    );
-    unsigned_tx.anchor_mode = anchor_mode;
-
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx
-        .consensus_serialize(&mut unsigned_tx_bytes)
-        .expect("FATAL: invalid transaction");
-    let signed_tx = sign_transaction_single_sig_standard(&to_hex(&unsigned_tx_bytes), &sk_origin)?;
-
-    let mut signed_tx_bytes = vec![];
-    signed_tx
-        .consensus_serialize(&mut signed_tx_bytes)
-        .expect("FATAL: invalid signed transaction");
-    Ok(to_hex(&signed_tx_bytes))
+    )
 }
 
 fn handle_token_transfer(
@@ -509,24 +1615,24 @@ fn handle_token_transfer(
             TOKEN_TRANSFER_USAGE
         )));
     }
-    if args.len() < 5 {
+    let options = parse_transaction_options(&mut args, TOKEN_TRANSFER_USAGE)?;
+    let origin = parse_signer(&mut args, TOKEN_TRANSFER_USAGE)?;
+    if args.len() < 4 {
         return Err(CliError::Message(format!(
             "Incorrect argument count supplied \n\nUSAGE:\n {}",
             TOKEN_TRANSFER_USAGE
         )));
     }
 
-    let anchor_mode = parse_anchor_mode(&mut args, TOKEN_TRANSFER_USAGE)?;
-    let sk_origin = StacksPrivateKey::from_hex(&args[0])?;
-    let tx_fee = args[1].parse()?;
-    let nonce = args[2].parse()?;
+    let tx_fee = parse_auto(&args[0], &options.node)?;
+    let nonce = parse_auto(&args[1], &options.node)?;
     let recipient_address =
-        PrincipalData::parse(&args[3]).map_err(|_e| "Failed to parse recipient")?;
-    let amount = &args[4].parse()?;
+        PrincipalData::parse(&args[2]).map_err(|_e| "Failed to parse recipient")?;
+    let amount = &args[3].parse()?;
     let memo = {
         let mut memo = [0; 34];
-        let mut bytes = if args.len() == 6 {
-            args[5].as_bytes().to_vec()
+        let mut bytes = if args.len() == 5 {
+            args[4].as_bytes().to_vec()
         } else {
             vec![]
         };
@@ -536,27 +1642,161 @@ fn handle_token_transfer(
     };
 
     let payload = TransactionPayload::TokenTransfer(recipient_address, *amount, memo);
-    let mut unsigned_tx = make_standard_single_sig_tx(
-        version,
-        chain_id,
-        payload,
-        &StacksPublicKey::from_private(&sk_origin),
-        nonce,
-        tx_fee,
-    );
-    unsigned_tx.anchor_mode = anchor_mode;
+    make_transaction(version, chain_id, payload, options, origin, nonce, tx_fee)
+}
 
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx
-        .consensus_serialize(&mut unsigned_tx_bytes)
-        .expect("FATAL: invalid transaction");
-    let signed_tx = sign_transaction_single_sig_standard(&to_hex(&unsigned_tx_bytes), &sk_origin)?;
+fn handle_sign_multisig(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if (args.len() >= 1 && args[0] == "-h") || args.len() != 2 {
+        return Err(CliError::Message(format!(
+            "USAGE:\n {}",
+            SIGN_MULTISIG_USAGE
+        )));
+    }
+    let secret_key = StacksPrivateKey::from_hex(&args[0])?;
+    let mut tx = deserialize_transaction(&args[1])?;
+
+    let initial_sighash = {
+        let mut initial_tx = tx.clone();
+        initial_tx.auth = initial_tx.auth.into_initial_sighash_auth();
+        initial_tx.txid()
+    };
+    // the sponsor's signatures follow on from the origin's, so it can only sign once the
+    // origin is complete
+    let origin_sighash = tx.verify_origin().ok();
+
+    let signed = match tx.auth {
+        TransactionAuth::Standard(ref mut origin) => sign_multisig_condition(
+            origin,
+            &initial_sighash,
+            &TransactionAuthFlags::AuthStandard,
+            &secret_key,
+        )?,
+        TransactionAuth::Sponsored(ref mut origin, ref mut sponsor) => {
+            sign_multisig_condition(
+                origin,
+                &initial_sighash,
+                &TransactionAuthFlags::AuthStandard,
+                &secret_key,
+            )? || match origin_sighash {
+                Some(origin_sighash) => sign_multisig_condition(
+                    sponsor,
+                    &origin_sighash,
+                    &TransactionAuthFlags::AuthSponsored,
+                    &secret_key,
+                )?,
+                None => false,
+            }
+        }
+    };
+    if !signed {
+        return Err(CliError::Message(format!(
+            "{} is not an unsigned multisig signer of this transaction (sponsors can only sign once the origin is fully signed)",
+            StacksPublicKey::from_private(&secret_key).to_hex()
+        )));
+    }
+    Ok(serialize_transaction(&tx))
+}
+
+fn handle_finalize_multisig(
+    args_slice: &[String],
+    _version: TransactionVersion,
+) -> Result<String, CliError> {
+    let mut args = args_slice.to_vec();
+    let node = parse_node(&mut args, FINALIZE_MULTISIG_USAGE)?;
+    if (args.len() >= 1 && args[0] == "-h") || args.len() != 1 {
+        return Err(CliError::Message(format!(
+            "USAGE:\n {}",
+            FINALIZE_MULTISIG_USAGE
+        )));
+    }
+    let tx = deserialize_transaction(&args[0])?;
+    if let Err(e) = tx.verify() {
+        let mut status = vec![];
+        for (name, condition) in [
+            ("origin", Some(tx.auth.origin())),
+            ("sponsor", tx.auth.sponsor()),
+        ] {
+            if let Some(condition) = condition {
+                status.push(format!(
+                    "{} has {} of {} signatures",
+                    name,
+                    condition.num_signatures(),
+                    condition.signatures_required()
+                ));
+            }
+        }
+        return Err(CliError::Message(format!(
+            "Transaction is not ready to broadcast ({}): {}",
+            status.join(", "),
+            e
+        )));
+    }
+    output_transaction(&tx, &node)
+}
+
+fn handle_sponsor(args_slice: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    let mut args = args_slice.to_vec();
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", SPONSOR_USAGE)));
+    }
+    let node = parse_node(&mut args, SPONSOR_USAGE)?;
+    let sponsor = parse_signer(&mut args, SPONSOR_USAGE)?;
+    if args.len() != 3 {
+        return Err(CliError::Message(format!(
+            "Incorrect argument count supplied \n\nUSAGE:\n {}",
+            SPONSOR_USAGE
+        )));
+    }
+    let tx_fee = parse_auto(&args[0], &node)?;
+    let nonce = parse_auto(&args[1], &node)?;
+    let tx = deserialize_transaction(&args[2])?;
+
+    let mut condition = sponsor.condition;
+    condition.set_nonce(lookup_nonce(nonce, &node, &condition, tx.version)?);
+    match (tx_fee, &node) {
+        (None, Some(node)) => {
+            let mut sponsored_tx = tx.clone();
+            sponsored_tx
+                .auth
+                .set_sponsor(condition.clone())
+                .map_err(|e| CliError::Message(format!("Cannot sponsor transaction: {}", e)))?;
+            condition
+                .set_tx_fee(node.estimate_fee(&tx.payload, estimate_signed_len(&sponsored_tx))?);
+        }
+        _ => condition.set_tx_fee(tx_fee.unwrap_or(0)),
+    }
 
-    let mut signed_tx_bytes = vec![];
-    signed_tx
-        .consensus_serialize(&mut signed_tx_bytes)
-        .expect("FATAL: invalid signed transaction");
-    Ok(to_hex(&signed_tx_bytes))
+    let mut tx_signer = StacksTransactionSigner::new_sponsor(&tx, condition)
+        .map_err(|e| CliError::Message(format!("Cannot sponsor transaction: {}", e)))?;
+    if let Some(secret_key) = sponsor.secret_key {
+        tx_signer.sign_sponsor(&secret_key)?;
+    }
+    output_transaction(&tx_signer.get_tx_incomplete(), &node)
+}
+
+fn get_multisig_address(
+    args_slice: &[String],
+    version: TransactionVersion,
+) -> Result<String, CliError> {
+    let mut args = args_slice.to_vec();
+    let p2wsh = take_flag(&mut args, "--p2wsh");
+    if (args.len() >= 1 && args[0] == "-h") || args.len() != 2 {
+        return Err(CliError::Message(format!(
+            "USAGE:\n {}",
+            MULTISIG_ADDRESS_USAGE
+        )));
+    }
+    let condition = make_multisig_condition(&args[0], &args[1], p2wsh)?;
+    let address = match version {
+        TransactionVersion::Mainnet => condition.address_mainnet(),
+        TransactionVersion::Testnet => condition.address_testnet(),
+    };
+    Ok(format!(
+        "{{
+    \"STX\": \"{}\"
+}}",
+        &address
+    ))
 }
 
 fn generate_secret_key(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
@@ -652,8 +1892,29 @@ fn decode_transaction(args: &[String], _version: TransactionVersion) -> Result<S
     let mut cursor = io::Cursor::new(&tx_str);
     let mut debug_cursor = LogReader::from_reader(&mut cursor);
 
-    match StacksTransaction::consensus_deserialize(&mut debug_cursor) {
-        Ok(tx) => Ok(serde_json::to_string(&tx).expect("Failed to serialize transaction to JSON")),
+    match StacksTransaction::deserialize_partially_signed(&mut debug_cursor) {
+        Ok(tx) => {
+            let mut tx_json =
+                serde_json::to_value(&tx).expect("Failed to serialize transaction to JSON");
+            let post_condition_spec = PostConditionSpec {
+                mode: Some(
+                    match tx.post_condition_mode {
+                        TransactionPostConditionMode::Allow => "allow",
+                        TransactionPostConditionMode::Deny => "deny",
+                    }
+                    .to_string(),
+                ),
+                post_conditions: tx
+                    .post_conditions
+                    .iter()
+                    .map(PostConditionEntry::from_post_condition)
+                    .collect(),
+            };
+            tx_json["post_condition_spec"] = serde_json::to_value(&post_condition_spec)
+                .expect("Failed to serialize post-conditions to JSON");
+            Ok(serde_json::to_string_pretty(&tx_json)
+                .expect("Failed to serialize transaction to JSON"))
+        }
         Err(e) => {
             let mut ret = String::new();
             ret.push_str(&format!("Failed to decode transaction: {:?}\n", &e));
@@ -817,6 +2078,10 @@ fn main_handler(mut argv: Vec<String>) -> Result<String, CliError> {
             "contract-call" => handle_contract_call(args, tx_version, chain_id),
             "publish" => handle_contract_publish(args, tx_version, chain_id),
             "token-transfer" => handle_token_transfer(args, tx_version, chain_id),
+            "sign-multisig" => handle_sign_multisig(args, tx_version),
+            "finalize-multisig" => handle_finalize_multisig(args, tx_version),
+            "sponsor" => handle_sponsor(args, tx_version),
+            "multisig-address" => get_multisig_address(args, tx_version),
             "generate-sk" => generate_secret_key(args, tx_version),
             "addresses" => get_addresses(args, tx_version),
             "decode-tx" => decode_transaction(args, tx_version),
@@ -834,6 +2099,12 @@ fn main_handler(mut argv: Vec<String>) -> Result<String, CliError> {
 mod test {
     use super::*;
 
+    use blockstack_lib::net::{
+        AccountEntryResponse, HttpResponseMetadata, HttpVersion, RPCFeeEstimate,
+        RPCFeeEstimateResponse, UnconfirmedTransactionResponse, UnconfirmedTransactionStatus,
+    };
+    use blockstack_lib::vm::costs::ExecutionCost;
+
     #[test]
     fn generate_should_work() {
         assert!(main_handler(vec!["generate-sk".into(), "--testnet".into()]).is_ok());
@@ -965,6 +2236,130 @@ mod test {
         assert!(main_handler(to_string_vec(&tt_args)).is_err());
     }
 
+    #[test]
+    fn typed_cc() {
+        let abi = r#"{
+            "functions": [
+                {
+                    "name": "send-many",
+                    "access": "public",
+                    "args": [
+                        {
+                            "name": "recipients",
+                            "type": { "list": { "type": { "tuple": [
+                                { "name": "amount", "type": "uint128" },
+                                { "name": "to", "type": "principal" }
+                            ] }, "length": 10 } }
+                        },
+                        { "name": "memo", "type": { "optional": { "buffer": { "length": 4 } } } },
+                        { "name": "delta", "type": "int128" }
+                    ],
+                    "outputs": { "type": { "response": { "ok": "bool", "error": "uint128" } } }
+                },
+                {
+                    "name": "helper",
+                    "access": "private",
+                    "args": [],
+                    "outputs": { "type": "bool" }
+                }
+            ],
+            "variables": [],
+            "maps": [],
+            "fungible_tokens": [],
+            "non_fungible_tokens": []
+        }"#;
+        let abi_path = env::temp_dir().join("blockstack-cli-typed-cc.json");
+        fs::write(&abi_path, abi).unwrap();
+        let abi_path = abi_path.to_str().unwrap();
+
+        let cc_args = |args: &[&str]| {
+            let mut cc_args = vec![
+                "contract-call",
+                "--abi",
+                abi_path,
+                "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+                "1",
+                "0",
+                "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+                "foo-contract",
+                "send-many",
+            ];
+            cc_args.extend_from_slice(args);
+            main_handler(to_string_vec(&cc_args))
+        };
+
+        // JSON arguments are encoded just like the equivalent Clarity expressions
+        let typed = cc_args(&[
+            "-j",
+            r#"[{"to": "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4", "amount": 10}]"#,
+            "-j",
+            r#""0xdeadbeef""#,
+            "-j",
+            r#""-170141183460469231731687303715884105728""#,
+        ])
+        .unwrap();
+        let evaluated = cc_args(&[
+            "-e",
+            "(list { to: 'SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4, amount: u10 })",
+            "-e",
+            "(some 0xdeadbeef)",
+            "-e",
+            "-170141183460469231731687303715884105728",
+        ])
+        .unwrap();
+        assert_eq!(typed, evaluated);
+
+        let error = |args: &[&str]| format!("{}", cc_args(args).unwrap_err());
+
+        // arity
+        assert!(error(&["-j", "[]", "-j", "null"]).contains("takes 3 arguments, but 2"));
+        assert!(
+            error(&["-j", "[]", "-j", "null", "-j", "1", "-j", "2"]).contains("takes 3 arguments")
+        );
+
+        // types, in JSON arguments
+        assert!(error(&["-j", "[]", "-j", "null", "-j", "true"]).contains("delta: expected an int"));
+        assert!(error(&[
+            "-j",
+            r#"[{"to": "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4"}]"#,
+            "-j",
+            "null",
+            "-j",
+            "1"
+        ])
+        .contains("recipients[0].amount: missing tuple field"));
+        assert!(error(&["-j", "[]", "-j", r#""0x0102030405""#, "-j", "1"])
+            .contains("Argument memo must be a (optional (buff 4))"));
+
+        // ... and in the others
+        assert!(
+            error(&["-j", "[]", "-j", "null", "-e", "u1"]).contains("Argument delta must be a int")
+        );
+
+        // only public functions can be called
+        let mut helper_args = to_string_vec(&[
+            "contract-call",
+            "--abi",
+            abi_path,
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "foo-contract",
+            "helper",
+        ]);
+        assert!(
+            format!("{}", main_handler(helper_args.clone()).unwrap_err())
+                .contains("helper is not a public function")
+        );
+
+        // JSON arguments need the interface
+        helper_args.drain(1..3);
+        helper_args.extend(to_string_vec(&["-j", "1"]));
+        assert!(format!("{}", main_handler(helper_args).unwrap_err())
+            .contains("need the contract interface"));
+    }
+
     #[test]
     fn simple_cc() {
         let cc_args = [
@@ -1136,6 +2531,369 @@ mod test {
         assert!(result.contains("ST36T883PDD2EK4PHVTA5GFHC8NQW6558XJQX6Q3K"));
     }
 
+    #[test]
+    fn multisig_sponsored_token_transfer() {
+        let sks: Vec<StacksPrivateKey> = (0..3).map(|_| StacksPrivateKey::new()).collect();
+        let pks: Vec<String> = sks
+            .iter()
+            .map(|sk| StacksPublicKey::from_private(sk).to_hex())
+            .collect();
+        let pks = pks.join(",");
+        let sponsor_sk = StacksPrivateKey::new();
+
+        let tt_args = [
+            "--testnet",
+            "token-transfer",
+            "--multisig",
+            "2",
+            &pks,
+            "--sponsored",
+            "0",
+            "5",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10",
+        ];
+        let unsigned = main_handler(to_string_vec(&tt_args)).unwrap();
+        let tx = deserialize_transaction(&unsigned).unwrap();
+        assert_eq!(tx.auth.origin().num_signatures(), 0);
+        assert_eq!(tx.auth.get_origin_nonce(), 5);
+
+        let address_args = ["--testnet", "multisig-address", "2", &pks];
+        let address = main_handler(to_string_vec(&address_args)).unwrap();
+        assert!(address.contains(&tx.origin_address().to_string()));
+
+        // the first and third signers sign, in order
+        let sign = |sk: &StacksPrivateKey, tx: &str| {
+            main_handler(to_string_vec(&["sign-multisig", &sk.to_hex(), tx]))
+        };
+        let signed_one = sign(&sks[0], &unsigned).unwrap();
+        let err = main_handler(to_string_vec(&["finalize-multisig", &signed_one])).unwrap_err();
+        assert!(format!("{}", err).contains("origin has 1 of 2 signatures"));
+
+        let signed_two = sign(&sks[2], &signed_one).unwrap();
+        assert!(format!("{}", sign(&sks[1], &signed_two).unwrap_err())
+            .contains("already has all 2 required signatures"));
+        let err = main_handler(to_string_vec(&["finalize-multisig", &signed_two])).unwrap_err();
+        assert!(format!("{}", err).contains("sponsor has 0 of 1 signatures"));
+
+        // signers can't sign out of order
+        let out_of_order = sign(&sks[2], &unsigned).unwrap();
+        assert!(format!("{}", sign(&sks[0], &out_of_order).unwrap_err())
+            .contains("must sign in the order"));
+
+        // the sponsor can only sign once the origin is complete
+        let sponsor_args = ["sponsor", &sponsor_sk.to_hex(), "300", "7"];
+        assert!(
+            main_handler(to_string_vec(&[&sponsor_args[..], &[&signed_one]].concat())).is_err()
+        );
+        let sponsored =
+            main_handler(to_string_vec(&[&sponsor_args[..], &[&signed_two]].concat())).unwrap();
+        let finalized = main_handler(to_string_vec(&["finalize-multisig", &sponsored])).unwrap();
+
+        let tx = deserialize_transaction(&finalized).unwrap();
+        tx.verify().unwrap();
+        assert_eq!(tx.get_tx_fee(), 300);
+        assert_eq!(tx.auth.get_sponsor_nonce(), Some(7));
+    }
+
+    #[test]
+    fn multisig_sponsor() {
+        let sks: Vec<StacksPrivateKey> = (0..2).map(|_| StacksPrivateKey::new()).collect();
+        let pks: Vec<String> = sks
+            .iter()
+            .map(|sk| StacksPublicKey::from_private(sk).to_hex())
+            .collect();
+        let pks = pks.join(",");
+
+        let origin_signed = main_handler(to_string_vec(&[
+            "contract-call",
+            "--sponsored",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "0",
+            "1",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "foo-contract",
+            "transfer-fookens",
+            "-e",
+            "(+ 1 2)",
+        ]))
+        .unwrap();
+        let unsigned = main_handler(to_string_vec(&[
+            "sponsor",
+            "--multisig",
+            "2",
+            &pks,
+            "--p2wsh",
+            "100",
+            "2",
+            &origin_signed,
+        ]))
+        .unwrap();
+
+        let mut tx = unsigned;
+        for sk in sks.iter() {
+            tx = main_handler(to_string_vec(&["sign-multisig", &sk.to_hex(), &tx])).unwrap();
+        }
+        let finalized = main_handler(to_string_vec(&["finalize-multisig", &tx])).unwrap();
+        deserialize_transaction(&finalized)
+            .unwrap()
+            .verify()
+            .unwrap();
+    }
+
+    #[test]
+    fn post_conditions() {
+        let cc_args = [
+            "contract-call",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "foo-contract",
+            "transfer-fookens",
+            "--stx-post-condition",
+            "origin",
+            "sent-le",
+            "100",
+            "--ft-post-condition",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens",
+            "sent-eq",
+            "10",
+            "--nft-post-condition",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::names",
+            "not-sent",
+            "(tuple (id u1))",
+            "--post-condition-mode",
+            "allow",
+            "-e",
+            "(+ 1 2)",
+        ];
+        let tx_hex = main_handler(to_string_vec(&cc_args)).unwrap();
+        let tx = deserialize_transaction(&tx_hex).unwrap();
+        assert_eq!(tx.post_condition_mode, TransactionPostConditionMode::Allow);
+        assert_eq!(tx.post_conditions.len(), 3);
+        assert_eq!(
+            tx.post_conditions[0],
+            TransactionPostCondition::STX(
+                PostConditionPrincipal::Origin,
+                FungibleConditionCode::SentLe,
+                100
+            )
+        );
+        match tx.post_conditions[2] {
+            TransactionPostCondition::Nonfungible(_, ref asset, ref value, ref condition) => {
+                assert_eq!(asset.asset_name.as_str(), "names");
+                assert_eq!(
+                    value,
+                    &blockstack_lib::clarity_cli::vm_execute("(tuple (id u1))")
+                        .unwrap()
+                        .unwrap()
+                );
+                assert_eq!(condition, &NonfungibleConditionCode::NotSent);
+            }
+            _ => panic!("Expected a non-fungible post-condition"),
+        }
+        if let TransactionPayload::ContractCall(ref cc) = tx.payload {
+            assert_eq!(cc.function_args, vec![Value::Int(3)]);
+        }
+
+        // decode-tx prints a spec that builds the same post-conditions
+        let decoded = main_handler(to_string_vec(&["decode-tx", &tx_hex])).unwrap();
+        let decoded: serde_json::Value = serde_json::from_str(&decoded).unwrap();
+        let spec = &decoded["post_condition_spec"];
+        assert_eq!(spec["mode"], "allow");
+        assert_eq!(spec["post_conditions"][0]["principal"], "origin");
+        assert_eq!(spec["post_conditions"][1]["type"], "ft");
+
+        let spec_path = env::temp_dir().join("blockstack-cli-post-conditions.json");
+        let spec_path = spec_path.to_str().unwrap();
+        fs::write(spec_path, spec.to_string()).unwrap();
+        let tt_args = [
+            "token-transfer",
+            "--post-conditions",
+            spec_path,
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "10",
+        ];
+        let tt = deserialize_transaction(&main_handler(to_string_vec(&tt_args)).unwrap()).unwrap();
+        assert_eq!(tt.post_condition_mode, tx.post_condition_mode);
+        assert_eq!(tt.post_conditions, tx.post_conditions);
+
+        let bad_args = [
+            "token-transfer",
+            "--stx-post-condition",
+            "origin",
+            "sent-most",
+            "100",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "10",
+        ];
+        assert!(
+            format!("{}", main_handler(to_string_vec(&bad_args)).unwrap_err())
+                .contains("Unknown fungible post-condition")
+        );
+    }
+
+    /// Serve `count` requests as a stand-in node, answering each with `respond(request line,
+    /// body)`.  Returns the node's URL, and a handle that joins to the request lines served.
+    fn fake_node<F>(count: usize, respond: F) -> (String, thread::JoinHandle<Vec<String>>)
+    where
+        F: Fn(&str, &[u8]) -> HttpResponseType + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut request_lines = vec![];
+            for _ in 0..count {
+                let (mut sock, _) = listener.accept().unwrap();
+                let mut buf = vec![];
+                let mut byte = [0u8; 1];
+                while !buf.ends_with(b"\r\n\r\n") {
+                    sock.read_exact(&mut byte).unwrap();
+                    buf.push(byte[0]);
+                }
+                let headers = String::from_utf8(buf).unwrap();
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| {
+                        let line = line.to_lowercase();
+                        line.strip_prefix("content-length: ")
+                            .map(|len| len.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                let mut body = vec![0u8; content_length];
+                sock.read_exact(&mut body).unwrap();
+
+                let request_line = headers.lines().next().unwrap().to_string();
+                let response = respond(&request_line, &body);
+                let mut http = StacksHttp::new(sock.peer_addr().unwrap());
+                let mut response_bytes = vec![];
+                response.send(&mut http, &mut response_bytes).unwrap();
+                sock.write_all(&response_bytes).unwrap();
+                request_lines.push(request_line);
+            }
+            request_lines
+        });
+        (url, handle)
+    }
+
+    fn fake_node_metadata() -> HttpResponseMetadata {
+        HttpResponseMetadata::new(
+            HttpVersion::Http11,
+            HttpResponseMetadata::make_request_id(),
+            None,
+            false,
+            None,
+        )
+    }
+
+    #[test]
+    fn broadcast_to_node() {
+        let posted_tx = std::sync::Mutex::new(None);
+        let polled = std::sync::atomic::AtomicBool::new(false);
+        let (url, node) = fake_node(5, move |request_line, body| {
+            let md = fake_node_metadata();
+            if request_line.starts_with("GET /v2/accounts/") {
+                HttpResponseType::GetAccount(
+                    md,
+                    AccountEntryResponse {
+                        balance: "0x0".into(),
+                        locked: "0x0".into(),
+                        unlock_height: 0,
+                        nonce: 42,
+                        balance_proof: None,
+                        nonce_proof: None,
+                    },
+                )
+            } else if request_line.starts_with("POST /v2/fees/transaction") {
+                let estimations = [1, 2, 3]
+                    .iter()
+                    .map(|rate| RPCFeeEstimate {
+                        fee_rate: *rate as f64,
+                        fee: rate * 100,
+                    })
+                    .collect();
+                HttpResponseType::TransactionFeeEstimation(
+                    md,
+                    RPCFeeEstimateResponse {
+                        estimated_cost: ExecutionCost::zero(),
+                        estimated_cost_scalar: 1,
+                        estimations,
+                        cost_scalar_change_by_byte: 1.0,
+                    },
+                )
+            } else if request_line.starts_with("POST /v2/transactions") {
+                let tx = StacksTransaction::consensus_deserialize(&mut &body[..]).unwrap();
+                *posted_tx.lock().unwrap() = Some(to_hex(body));
+                HttpResponseType::TransactionID(md, tx.txid())
+            } else if request_line.starts_with("GET /v2/transactions/unconfirmed/") {
+                // the first poll misses the transaction
+                if !polled.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    HttpResponseType::NotFound(md, "not found".into())
+                } else {
+                    HttpResponseType::UnconfirmedTransaction(
+                        md,
+                        UnconfirmedTransactionResponse {
+                            tx: posted_tx.lock().unwrap().clone().unwrap(),
+                            status: UnconfirmedTransactionStatus::Mempool,
+                        },
+                    )
+                }
+            } else {
+                panic!("Unexpected request {}", request_line);
+            }
+        });
+
+        let tt_args = [
+            "token-transfer",
+            "--node",
+            &url,
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "auto",
+            "auto",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "10",
+        ];
+        let result = main_handler(to_string_vec(&tt_args)).unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["status"], "Mempool");
+
+        let tx = deserialize_transaction(result["tx"].as_str().unwrap()).unwrap();
+        tx.verify().unwrap();
+        assert_eq!(result["txid"], tx.txid().to_hex());
+        assert_eq!(tx.get_origin_nonce(), 42);
+        // the middle estimate
+        assert_eq!(tx.get_tx_fee(), 200);
+
+        let request_lines = node.join().unwrap();
+        assert!(request_lines[0].starts_with("GET /v2/accounts/SP"));
+        assert!(request_lines[1].starts_with("POST /v2/fees/transaction"));
+        assert!(request_lines[2].starts_with("POST /v2/transactions"));
+        assert!(request_lines[3].starts_with("GET /v2/transactions/unconfirmed/"));
+
+        // `auto` needs a node
+        let tt_args = [
+            "token-transfer",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "auto",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "10",
+        ];
+        assert!(
+            format!("{}", main_handler(to_string_vec(&tt_args)).unwrap_err())
+                .contains("need --node")
+        );
+    }
+
     #[test]
     fn simple_decode_tx() {
         let tx_args = [
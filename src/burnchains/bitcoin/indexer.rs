@@ -19,6 +19,7 @@ use std::cmp;
 use std::fs;
 use std::net;
 use std::net::Shutdown;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::path;
@@ -40,6 +41,8 @@ use crate::burnchains::bitcoin::BitcoinNetworkType;
 use crate::burnchains::Error as burnchain_error;
 use crate::burnchains::MagicBytes;
 use crate::burnchains::BLOCKSTACK_MAGIC_MAINNET;
+use crate::net::socks;
+use crate::net::socks::SocksTarget;
 use crate::types::chainstate::BurnchainHeaderHash;
 
 use stacks_common::deps_common::bitcoin::blockdata::block::LoneBlockHeader;
@@ -117,6 +120,8 @@ pub struct BitcoinIndexerConfig {
     pub first_block: u64,
     pub magic_bytes: MagicBytes,
     pub epochs: Option<Vec<StacksEpoch>>,
+    /// SOCKS5 proxy to reach the peer through, if any
+    pub proxy: Option<SocketAddr>,
 }
 
 #[derive(Debug)]
@@ -152,6 +157,7 @@ impl BitcoinIndexerConfig {
             first_block: 0,
             magic_bytes: BLOCKSTACK_MAGIC_MAINNET.clone(),
             epochs: None,
+            proxy: None,
         }
     }
 }
@@ -193,7 +199,19 @@ impl BitcoinIndexer {
     /// Bitcoin peer.  If we fail to connect, this method sets the socket
     /// to None.
     fn reconnect_peer(&mut self) -> Result<(), btc_error> {
-        match net::TcpStream::connect((self.config.peer_host.as_str(), self.config.peer_port)) {
+        let sock_res = match self.config.proxy {
+            Some(ref proxy) => socks::connect_blocking(
+                proxy,
+                SocksTarget::from_host(&self.config.peer_host, self.config.peer_port),
+                Duration::from_secs(self.runtime.timeout),
+            )
+            .map_err(|e| format!("{:?}", &e)),
+            None => {
+                net::TcpStream::connect((self.config.peer_host.as_str(), self.config.peer_port))
+                    .map_err(|e| format!("{:?}", &e))
+            }
+        };
+        match sock_res {
             Ok(s) => {
                 // Disable Nagle algorithm
                 s.set_nodelay(true).map_err(|_e| {
@@ -1432,6 +1450,7 @@ mod test {
             first_block: 0,
             magic_bytes: MagicBytes([105, 100]),
             epochs: None,
+            proxy: None,
         };
 
         if fs::metadata(&indexer_conf.spv_headers_path).is_ok() {
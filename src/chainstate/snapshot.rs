@@ -0,0 +1,1244 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020-2022 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Chainstate snapshots.
+//!
+//! A snapshot is a copy of a node's chainstate, sortition DB, burnchain DB and burnchain headers,
+//! laid out the same way as they are in the node's working directory, plus a manifest.  The
+//! manifest commits to each file's SHA-256 hash, and to a `SnapshotAnchor` -- the consensus hash
+//! of the sortition at the start of a chosen reward cycle, and the MARF root hash of the Stacks
+//! block that was most recently elected and processed as of that sortition.
+//!
+//! A node bootstrapping from a snapshot checks the file hashes, checks that the anchor agrees
+//! with the anchor that several independent peers report for the same reward cycle (via
+//! `GET /v2/snapshot/anchor/:reward_cycle`), and then checks that the installed databases
+//! actually produce that anchor, down to the MARF roots of the anchor block.  Only the anchor
+//! and its history are vouched for, so everything else is then truncated away: Stacks blocks
+//! that are not ancestors of the anchor block, and sortitions, burnchain blocks and burnchain
+//! headers past the anchor's burnchain block.  The node downloads and processes those again
+//! itself, just like a node that had synced up to the anchor on its own.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags, ToSql, NO_PARAMS};
+use sha2::{Digest, Sha256};
+
+use crate::burnchains::Burnchain;
+use crate::chainstate::burn::db::sortdb::SortitionDB;
+use crate::chainstate::stacks::db::StacksChainState;
+use crate::chainstate::stacks::index::trie_sql;
+use crate::chainstate::stacks::Error as chainstate_error;
+use crate::core::FIRST_BURNCHAIN_CONSENSUS_HASH;
+use crate::core::FIRST_STACKS_BLOCK_HASH;
+use crate::types::chainstate::{
+    BlockHeaderHash, ConsensusHash, SortitionId, StacksBlockId, TrieHash,
+};
+use crate::util_lib::db::Error as db_error;
+use crate::util_lib::db::{
+    query_row_columns, sqlite_open, tx_begin_immediate, u64_to_sql, DBTx, FromColumn,
+};
+
+use stacks_common::util::hash::to_hex;
+
+/// Name of the manifest file in a snapshot directory
+pub const SNAPSHOT_MANIFEST_NAME: &'static str = "manifest.json";
+
+/// Current snapshot format version
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Paths (relative to a node's working directory) that go into a snapshot.  These are the
+/// chainstate, the burnchain DB and sortition DB, and the burnchain headers.
+const SNAPSHOT_PATHS: &'static [&'static str] = &["chainstate", "burnchain", "headers.sqlite"];
+
+/// Paths (relative to a node's working directory) that are left out of a snapshot, since they
+/// are specific to the node that exported it.
+const SNAPSHOT_EXCLUDED_PATHS: &'static [&'static str] = &[
+    "chainstate/mempool.sqlite",
+    "chainstate/mempool.sqlite-wal",
+    "chainstate/mempool.sqlite-shm",
+    "chainstate/estimates",
+];
+
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read or write a snapshot file
+    IOError(io::Error),
+    /// Failed to query a snapshot's databases
+    DBError(db_error),
+    /// Failed to open or query the snapshot's chainstate
+    ChainstateError(chainstate_error),
+    /// The manifest is missing or malformed
+    InvalidManifest(String),
+    /// A file's contents do not match the manifest
+    CorruptFile(String),
+    /// The snapshot's databases, or our peers, disagree with the manifest's anchor
+    AnchorMismatch(String),
+    /// The requested anchor is not known
+    NoSuchAnchor,
+    /// The destination already has chain state in it
+    AlreadyExists(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IOError(ref e) => fmt::Display::fmt(e, f),
+            Error::DBError(ref e) => fmt::Display::fmt(e, f),
+            Error::ChainstateError(ref e) => fmt::Display::fmt(e, f),
+            Error::InvalidManifest(ref s) => write!(f, "Invalid snapshot manifest: {}", s),
+            Error::CorruptFile(ref s) => write!(f, "Corrupt snapshot file: {}", s),
+            Error::AnchorMismatch(ref s) => write!(f, "Snapshot anchor mismatch: {}", s),
+            Error::NoSuchAnchor => write!(f, "No such snapshot anchor"),
+            Error::AlreadyExists(ref s) => write!(f, "Already exists: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::IOError(ref e) => Some(e),
+            Error::DBError(ref e) => Some(e),
+            Error::ChainstateError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IOError(e)
+    }
+}
+
+impl From<db_error> for Error {
+    fn from(e: db_error) -> Error {
+        Error::DBError(e)
+    }
+}
+
+impl From<chainstate_error> for Error {
+    fn from(e: chainstate_error) -> Error {
+        Error::ChainstateError(e)
+    }
+}
+
+/// The point in the chain history a snapshot commits to.  Every node on the same burnchain fork
+/// that has processed the same blocks up to the reward cycle start computes the same anchor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotAnchor {
+    /// reward cycle whose first burnchain block this anchor is taken at
+    pub reward_cycle: u64,
+    /// height of the first burnchain block in that reward cycle
+    pub burn_block_height: u64,
+    /// consensus hash of the sortition at that height
+    pub consensus_hash: ConsensusHash,
+    /// the Stacks block most recently elected (and processed) as of that sortition
+    pub stacks_block_consensus_hash: ConsensusHash,
+    pub stacks_block_hash: BlockHeaderHash,
+    pub stacks_block_height: u64,
+    /// MARF root hash of that Stacks block's chain state
+    pub index_root: TrieHash,
+}
+
+impl SnapshotAnchor {
+    /// Load the anchor for the given reward cycle from the canonical burnchain fork.
+    /// Returns Ok(None) if the reward cycle hasn't started yet.
+    pub fn load(
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        burnchain: &Burnchain,
+        reward_cycle: u64,
+    ) -> Result<Option<SnapshotAnchor>, Error> {
+        let burn_block_height = burnchain.reward_cycle_to_block_height(reward_cycle);
+        SnapshotAnchor::load_at_height(sortdb, chainstate, reward_cycle, burn_block_height)
+    }
+
+    /// Load the anchor at the given burnchain height on the canonical burnchain fork.
+    /// Returns Ok(None) if there's no sortition at that height yet.
+    fn load_at_height(
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        reward_cycle: u64,
+        burn_block_height: u64,
+    ) -> Result<Option<SnapshotAnchor>, Error> {
+        let tip = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())?;
+        if burn_block_height > tip.block_height {
+            return Ok(None);
+        }
+
+        let anchor_sn = match SortitionDB::get_ancestor_snapshot(
+            &sortdb.index_conn(),
+            burn_block_height,
+            &tip.sortition_id,
+        )? {
+            Some(sn) => sn,
+            None => {
+                return Ok(None);
+            }
+        };
+
+        // Find the latest processed Stacks block elected at or before this sortition.  Unlike
+        // the snapshot's canonical Stacks tip, this only depends on which blocks were processed,
+        // not on when they were processed.
+        let mut cursor = anchor_sn.clone();
+        let header_info = loop {
+            if cursor.sortition {
+                if let Some(header_info) = StacksChainState::get_anchored_block_header_info(
+                    chainstate.db(),
+                    &cursor.consensus_hash,
+                    &cursor.winning_stacks_block_hash,
+                )? {
+                    break header_info;
+                }
+            }
+            if cursor.block_height <= sortdb.first_block_height {
+                // nothing processed yet; anchor at the boot block
+                match StacksChainState::get_anchored_block_header_info(
+                    chainstate.db(),
+                    &FIRST_BURNCHAIN_CONSENSUS_HASH,
+                    &FIRST_STACKS_BLOCK_HASH,
+                )? {
+                    Some(header_info) => break header_info,
+                    None => {
+                        return Err(Error::NoSuchAnchor);
+                    }
+                }
+            }
+            cursor = SortitionDB::get_block_snapshot(sortdb.conn(), &cursor.parent_sortition_id)?
+                .ok_or(Error::DBError(db_error::NotFoundError))?;
+        };
+
+        Ok(Some(SnapshotAnchor {
+            reward_cycle,
+            burn_block_height,
+            consensus_hash: anchor_sn.consensus_hash,
+            stacks_block_consensus_hash: header_info.consensus_hash,
+            stacks_block_hash: header_info.anchored_header.block_hash(),
+            stacks_block_height: header_info.stacks_block_height,
+            index_root: header_info.index_root,
+        }))
+    }
+}
+
+/// A file in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    /// path relative to the snapshot directory, with '/' separators
+    pub path: String,
+    pub size: u64,
+    /// hex-encoded SHA-256 of the file's contents
+    pub sha256: String,
+}
+
+/// The contents of a snapshot's manifest.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub mainnet: bool,
+    pub chain_id: u32,
+    pub anchor: SnapshotAnchor,
+    pub files: Vec<SnapshotFile>,
+}
+
+impl SnapshotManifest {
+    /// Read a snapshot's manifest
+    pub fn load(snapshot_dir: &Path) -> Result<SnapshotManifest, Error> {
+        let path = snapshot_dir.join(SNAPSHOT_MANIFEST_NAME);
+        let bytes = fs::read(&path)?;
+        let manifest: SnapshotManifest = serde_json::from_slice(&bytes)
+            .map_err(|e| Error::InvalidManifest(format!("{:?}: {}", &path, &e)))?;
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {} (expected {})",
+                manifest.version, SNAPSHOT_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+
+    fn store(&self, snapshot_dir: &Path) -> Result<(), Error> {
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::InvalidManifest(format!("{}", &e)))?;
+        fs::write(snapshot_dir.join(SNAPSHOT_MANIFEST_NAME), &bytes)?;
+        Ok(())
+    }
+
+    /// Check that every file in the snapshot matches its size and hash in the manifest
+    pub fn verify_files(&self, snapshot_dir: &Path) -> Result<(), Error> {
+        for file in self.files.iter() {
+            let path = snapshot_path(snapshot_dir, &file.path)?;
+            let (size, sha256) = hash_file(&path).map_err(|e| {
+                Error::CorruptFile(format!("failed to read {}: {:?}", &file.path, &e))
+            })?;
+            if size != file.size || sha256 != file.sha256 {
+                return Err(Error::CorruptFile(format!(
+                    "{} has size {} and hash {}, but the manifest says {} and {}",
+                    &file.path, size, &sha256, file.size, &file.sha256
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolve a manifest path inside a snapshot (or node) directory, refusing paths that would
+/// escape it.
+fn snapshot_path(dir: &Path, rel_path: &str) -> Result<PathBuf, Error> {
+    let mut path = dir.to_path_buf();
+    for part in rel_path.split('/') {
+        if part.len() == 0 || part == "." || part == ".." || part.contains('\\') {
+            return Err(Error::InvalidManifest(format!("invalid path {}", rel_path)));
+        }
+        path.push(part);
+    }
+    Ok(path)
+}
+
+/// Get a file's size and hex-encoded SHA-256 hash
+fn hash_file(path: &Path) -> Result<(u64, String), io::Error> {
+    let mut fd = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 65536];
+    let mut size = 0;
+    loop {
+        let nr = fd.read(&mut buf)?;
+        if nr == 0 {
+            break;
+        }
+        hasher.update(&buf[0..nr]);
+        size += nr as u64;
+    }
+    Ok((size, to_hex(&hasher.finalize())))
+}
+
+/// Recursively copy `rel_path` from `src_dir` into `dest_dir`, skipping excluded paths.
+/// Appends the relative paths of the copied files to `copied`.
+fn copy_tree(
+    src_dir: &Path,
+    dest_dir: &Path,
+    rel_path: &str,
+    copied: &mut Vec<String>,
+) -> Result<(), Error> {
+    if SNAPSHOT_EXCLUDED_PATHS.contains(&rel_path) {
+        return Ok(());
+    }
+    let src = snapshot_path(src_dir, rel_path)?;
+    let dest = snapshot_path(dest_dir, rel_path)?;
+    let metadata = match fs::metadata(&src) {
+        Ok(md) => md,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(e.into());
+        }
+    };
+
+    if metadata.is_dir() {
+        fs::create_dir_all(&dest)?;
+        let mut names = vec![];
+        for entry in fs::read_dir(&src)? {
+            let name = entry?.file_name();
+            let name = name.to_str().ok_or_else(|| {
+                Error::InvalidManifest(format!("non-UTF-8 file name in {:?}", &src))
+            })?;
+            names.push(name.to_string());
+        }
+        names.sort();
+        for name in names.into_iter() {
+            copy_tree(src_dir, dest_dir, &format!("{}/{}", rel_path, name), copied)?;
+        }
+    } else {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&src, &dest)?;
+        copied.push(rel_path.to_string());
+    }
+    Ok(())
+}
+
+fn chainstate_path_str(dir: &Path) -> String {
+    dir.join("chainstate").to_string_lossy().to_string()
+}
+
+fn sortdb_path_str(dir: &Path) -> String {
+    dir.join("burnchain")
+        .join("sortition")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Load the anchor at the given burnchain height from the databases in a node's working
+/// directory (i.e. the directory with `chainstate/` and `burnchain/` in it).
+fn load_anchor_from_dir(
+    dir: &Path,
+    mainnet: bool,
+    chain_id: u32,
+    reward_cycle: u64,
+    burn_block_height: u64,
+) -> Result<Option<SnapshotAnchor>, Error> {
+    let sortdb = SortitionDB::open(&sortdb_path_str(dir), false)?;
+    let (chainstate, _) =
+        StacksChainState::open(mainnet, chain_id, &chainstate_path_str(dir), None)?;
+    SnapshotAnchor::load_at_height(&sortdb, &chainstate, reward_cycle, burn_block_height)
+}
+
+/// Export a snapshot of the node whose working directory is `node_dir`, anchored at the start of
+/// `reward_cycle`, into `snapshot_dir` (which must not exist yet).  The node must not be running.
+pub fn export_snapshot(
+    node_dir: &Path,
+    snapshot_dir: &Path,
+    burnchain: &Burnchain,
+    mainnet: bool,
+    chain_id: u32,
+    reward_cycle: u64,
+) -> Result<SnapshotManifest, Error> {
+    if snapshot_dir.exists() {
+        return Err(Error::AlreadyExists(format!("{:?}", snapshot_dir)));
+    }
+    fs::create_dir_all(snapshot_dir)?;
+
+    let mut paths = vec![];
+    for rel_path in SNAPSHOT_PATHS.iter() {
+        copy_tree(node_dir, snapshot_dir, rel_path, &mut paths)?;
+    }
+
+    // compute the anchor from the copy, so it's consistent with what's in the snapshot
+    let burn_block_height = burnchain.reward_cycle_to_block_height(reward_cycle);
+    let anchor = load_anchor_from_dir(
+        snapshot_dir,
+        mainnet,
+        chain_id,
+        reward_cycle,
+        burn_block_height,
+    )?
+    .ok_or(Error::NoSuchAnchor)?;
+
+    // opening the databases may have touched them, so hash them afterwards
+    let mut files = vec![];
+    for rel_path in paths.into_iter() {
+        let path = snapshot_path(snapshot_dir, &rel_path)?;
+        if !path.exists() {
+            continue;
+        }
+        let (size, sha256) = hash_file(&path)?;
+        files.push(SnapshotFile {
+            path: rel_path,
+            size,
+            sha256,
+        });
+    }
+
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        mainnet,
+        chain_id,
+        anchor,
+        files,
+    };
+    manifest.store(snapshot_dir)?;
+    Ok(manifest)
+}
+
+/// Install a snapshot's files into a node's working directory, which must not have any chain
+/// state in it yet.  Checks the files' hashes as they're copied.
+pub fn install_snapshot(
+    snapshot_dir: &Path,
+    node_dir: &Path,
+    manifest: &SnapshotManifest,
+) -> Result<(), Error> {
+    for rel_path in SNAPSHOT_PATHS.iter() {
+        if snapshot_path(node_dir, rel_path)?.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "{:?}",
+                node_dir.join(rel_path)
+            )));
+        }
+    }
+
+    manifest.verify_files(snapshot_dir)?;
+    for file in manifest.files.iter() {
+        let src = snapshot_path(snapshot_dir, &file.path)?;
+        let dest = snapshot_path(node_dir, &file.path)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&src, &dest)?;
+    }
+    Ok(())
+}
+
+/// Remove whatever chain state `install_snapshot` put into a node's working directory
+pub fn uninstall_snapshot(node_dir: &Path) -> Result<(), Error> {
+    for rel_path in SNAPSHOT_PATHS.iter() {
+        let path = snapshot_path(node_dir, rel_path)?;
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that the chain state in a node's working directory produces the manifest's anchor.
+/// The anchor's `index_root` is only a column in the anchor block's header row, so it is checked
+/// against the root of the header index MARF itself, and the Clarity MARF's root is checked
+/// against the state root that the anchor block's header (and thus its hash) commits to.
+pub fn verify_installed_snapshot(
+    node_dir: &Path,
+    manifest: &SnapshotManifest,
+) -> Result<(), Error> {
+    let sortdb = SortitionDB::open(&sortdb_path_str(node_dir), false)?;
+    let (mut chainstate, _) = StacksChainState::open(
+        manifest.mainnet,
+        manifest.chain_id,
+        &chainstate_path_str(node_dir),
+        None,
+    )?;
+    let anchor = SnapshotAnchor::load_at_height(
+        &sortdb,
+        &chainstate,
+        manifest.anchor.reward_cycle,
+        manifest.anchor.burn_block_height,
+    )?
+    .ok_or(Error::NoSuchAnchor)?;
+
+    if anchor != manifest.anchor {
+        return Err(Error::AnchorMismatch(format!(
+            "chain state has anchor {:?}, but the manifest says {:?}",
+            &anchor, &manifest.anchor
+        )));
+    }
+
+    let anchor_block_id = StacksBlockId::new(
+        &anchor.stacks_block_consensus_hash,
+        &anchor.stacks_block_hash,
+    );
+    let index_root = chainstate
+        .state_index
+        .get_root_hash_at(&anchor_block_id)
+        .map_err(chainstate_error::MARFError)?;
+    if index_root != anchor.index_root {
+        return Err(Error::AnchorMismatch(format!(
+            "header index MARF has root {} at {}, but the anchor says {}",
+            &index_root, &anchor_block_id, &anchor.index_root
+        )));
+    }
+
+    // the boot block's header is a placeholder that doesn't commit to the boot state
+    if anchor.stacks_block_height > 0 {
+        let header_info = StacksChainState::get_anchored_block_header_info(
+            chainstate.db(),
+            &anchor.stacks_block_consensus_hash,
+            &anchor.stacks_block_hash,
+        )?
+        .ok_or(Error::NoSuchAnchor)?;
+        let state_root = chainstate
+            .clarity_state
+            .with_marf(|marf| marf.get_root_hash_at(&anchor_block_id))
+            .map_err(chainstate_error::MARFError)?;
+        if state_root != header_info.anchored_header.state_index_root {
+            return Err(Error::AnchorMismatch(format!(
+                "Clarity MARF has root {} at {}, but the block header says {}",
+                &state_root, &anchor_block_id, &header_info.anchored_header.state_index_root
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A Stacks block being truncated away, and its index block hash
+type TruncatedBlock = (ConsensusHash, BlockHeaderHash, StacksBlockId);
+
+/// Truncate the chain state installed from a snapshot back to the manifest's anchor, so that the
+/// node only starts out with state that `verify_installed_snapshot` vouched for.  Only call this
+/// after that check passes.
+pub fn truncate_installed_snapshot(
+    node_dir: &Path,
+    manifest: &SnapshotManifest,
+) -> Result<(), Error> {
+    let anchor = &manifest.anchor;
+    let truncated_blocks = truncate_chainstate(node_dir, anchor)?;
+    truncate_sortdb(node_dir, anchor, &truncated_blocks)?;
+    truncate_burnchain(node_dir, anchor.burn_block_height)?;
+    Ok(())
+}
+
+fn open_snapshot_db(path: &Path) -> Result<Connection, Error> {
+    sqlite_open(path, OpenFlags::SQLITE_OPEN_READ_WRITE, true)
+        .map_err(|e| Error::DBError(db_error::SqliteError(e)))
+}
+
+/// Query (consensus hash, block hash, index block hash) triples
+fn query_blocks(
+    conn: &Connection,
+    qry: &str,
+    args: &[&dyn ToSql],
+) -> Result<Vec<TruncatedBlock>, Error> {
+    let mut stmt = conn.prepare(qry).map_err(db_error::SqliteError)?;
+    let rows = stmt
+        .query_and_then(args, |row| -> Result<TruncatedBlock, db_error> {
+            Ok((
+                ConsensusHash::from_column(row, "consensus_hash")?,
+                BlockHeaderHash::from_column(row, "block_hash")?,
+                StacksBlockId::from_column(row, "index_block_hash")?,
+            ))
+        })
+        .map_err(db_error::SqliteError)?;
+    let mut blocks = vec![];
+    for row in rows {
+        blocks.push(row?);
+    }
+    Ok(blocks)
+}
+
+fn execute(tx: &DBTx, sql: &str, args: &[&dyn ToSql]) -> Result<(), Error> {
+    tx.execute(sql, args).map_err(db_error::SqliteError)?;
+    Ok(())
+}
+
+/// Remove every Stacks block that isn't the anchor block or one of its ancestors, from both the
+/// chainstate DB and the header index and Clarity MARFs.  Returns the removed blocks.
+fn truncate_chainstate(
+    node_dir: &Path,
+    anchor: &SnapshotAnchor,
+) -> Result<Vec<TruncatedBlock>, Error> {
+    let chainstate_path = node_dir.join("chainstate");
+    let blocks_path = StacksChainState::blocks_path(chainstate_path.clone());
+    let blocks_path_str = blocks_path.to_string_lossy().to_string();
+    let anchor_block_id = StacksBlockId::new(
+        &anchor.stacks_block_consensus_hash,
+        &anchor.stacks_block_hash,
+    );
+
+    let mut index_conn = open_snapshot_db(&StacksChainState::header_index_root_path(
+        chainstate_path.clone(),
+    ))?;
+    let mut clarity_conn =
+        open_snapshot_db(&StacksChainState::vm_state_index_marf_path(chainstate_path))?;
+
+    let kept: HashSet<StacksBlockId> = query_row_columns(
+        &index_conn,
+        "WITH RECURSIVE ancestors(index_block_hash) AS (
+            SELECT ?1
+            UNION
+            SELECT block_headers.parent_block_id FROM block_headers
+                JOIN ancestors ON block_headers.index_block_hash = ancestors.index_block_hash
+         ) SELECT index_block_hash FROM ancestors",
+        &[&anchor_block_id],
+        "index_block_hash",
+    )?
+    .into_iter()
+    .collect();
+
+    let mut truncated = query_blocks(
+        &index_conn,
+        "SELECT consensus_hash, block_hash, index_block_hash FROM block_headers
+         UNION
+         SELECT consensus_hash, anchored_block_hash AS block_hash, index_block_hash FROM staging_blocks",
+        &[],
+    )?;
+    truncated.retain(|(_, _, block_id)| !kept.contains(block_id));
+
+    info!(
+        "Truncate {} Stacks block(s) past snapshot anchor {}",
+        truncated.len(),
+        &anchor_block_id
+    );
+
+    let tx = tx_begin_immediate(&mut index_conn)?;
+    for (consensus_hash, block_hash, block_id) in truncated.iter() {
+        let microblock_hashes: Vec<BlockHeaderHash> = query_row_columns(
+            &tx,
+            "SELECT microblock_hash FROM staging_microblocks WHERE index_block_hash = ?1",
+            &[block_id],
+            "microblock_hash",
+        )?;
+        for microblock_hash in microblock_hashes.iter() {
+            execute(
+                &tx,
+                "DELETE FROM staging_microblocks_data WHERE block_hash = ?1",
+                &[microblock_hash],
+            )?;
+        }
+
+        let block_args: &[&dyn ToSql] = &[block_id];
+        let hash_args: &[&dyn ToSql] = &[consensus_hash, block_hash];
+        execute(
+            &tx,
+            "DELETE FROM staging_microblocks WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM staging_blocks WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM block_headers WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM payments WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM transactions WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM epoch_transitions WHERE block_id = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM pruned_blocks WHERE index_block_hash = ?1",
+            block_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM user_supporters WHERE consensus_hash = ?1 AND block_hash = ?2",
+            hash_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM staging_user_burn_support WHERE consensus_hash = ?1 AND anchored_block_hash = ?2",
+            hash_args,
+        )?;
+        trie_sql::drop_confirmed_trie(&tx, block_id).map_err(chainstate_error::MARFError)?;
+    }
+
+    // only the anchor's (truncated) children could have confirmed its microblocks
+    execute(
+        &tx,
+        "UPDATE staging_microblocks SET processed = 0 WHERE index_block_hash = ?1",
+        &[&anchor_block_id],
+    )?;
+    trie_sql::clear_lock_data(&tx).map_err(chainstate_error::MARFError)?;
+    tx.commit().map_err(db_error::SqliteError)?;
+
+    let tx = tx_begin_immediate(&mut clarity_conn)?;
+    for (_, _, block_id) in truncated.iter() {
+        execute(
+            &tx,
+            "DELETE FROM metadata_table WHERE blockhash = ?1",
+            &[block_id],
+        )?;
+        trie_sql::drop_confirmed_trie(&tx, block_id).map_err(chainstate_error::MARFError)?;
+    }
+    // unconfirmed state is rebuilt from the canonical tip, which is now the anchor
+    execute(&tx, "DELETE FROM marf_data WHERE unconfirmed = 1", &[])?;
+    trie_sql::clear_lock_data(&tx).map_err(chainstate_error::MARFError)?;
+    tx.commit().map_err(db_error::SqliteError)?;
+
+    // the downloader would take a stored block file to mean the block doesn't need fetching
+    for (_, _, block_id) in truncated.iter() {
+        let block_path = StacksChainState::get_index_block_path(&blocks_path_str, block_id)?;
+        match fs::remove_file(&block_path) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(truncated)
+}
+
+/// Remove every sortition past the anchor's burnchain block, and forget that the truncated
+/// Stacks blocks were ever accepted.
+fn truncate_sortdb(
+    node_dir: &Path,
+    anchor: &SnapshotAnchor,
+    truncated_blocks: &[TruncatedBlock],
+) -> Result<(), Error> {
+    // (burnchain height, consensus hash, block hash, Stacks height) of the remaining Stacks
+    // blocks, i.e. the anchor block's ancestry, in order
+    let kept_blocks: Vec<(u64, ConsensusHash, BlockHeaderHash, u64)> = {
+        let index_conn = open_snapshot_db(&StacksChainState::header_index_root_path(
+            node_dir.join("chainstate"),
+        ))?;
+        let mut stmt = index_conn
+            .prepare("SELECT burn_header_height, consensus_hash, block_hash, block_height FROM block_headers ORDER BY burn_header_height ASC")
+            .map_err(db_error::SqliteError)?;
+        let rows = stmt
+            .query_and_then(NO_PARAMS, |row| -> Result<_, db_error> {
+                Ok((
+                    u64::from_column(row, "burn_header_height")?,
+                    ConsensusHash::from_column(row, "consensus_hash")?,
+                    BlockHeaderHash::from_column(row, "block_hash")?,
+                    u64::from_column(row, "block_height")?,
+                ))
+            })
+            .map_err(db_error::SqliteError)?;
+        let mut kept_blocks = vec![];
+        for row in rows {
+            kept_blocks.push(row?);
+        }
+        kept_blocks
+    };
+
+    let mut conn = open_snapshot_db(
+        &node_dir
+            .join("burnchain")
+            .join("sortition")
+            .join("marf.sqlite"),
+    )?;
+    let tx = tx_begin_immediate(&mut conn)?;
+    let height_args: &[&dyn ToSql] = &[&u64_to_sql(anchor.burn_block_height)?];
+
+    let sortition_ids: Vec<SortitionId> = query_row_columns(
+        &tx,
+        "SELECT sortition_id FROM snapshots WHERE block_height > ?1",
+        height_args,
+        "sortition_id",
+    )?;
+    info!(
+        "Truncate {} sortition(s) past snapshot anchor {}",
+        sortition_ids.len(),
+        &anchor.consensus_hash
+    );
+    for sortition_id in sortition_ids.iter() {
+        let args: &[&dyn ToSql] = &[sortition_id];
+        execute(
+            &tx,
+            "DELETE FROM block_commit_parents WHERE block_commit_sortition_id = ?1",
+            args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM block_commits WHERE sortition_id = ?1",
+            args,
+        )?;
+        execute(&tx, "DELETE FROM leader_keys WHERE sortition_id = ?1", args)?;
+        execute(
+            &tx,
+            "DELETE FROM user_burn_support WHERE sortition_id = ?1",
+            args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM snapshot_transition_ops WHERE sortition_id = ?1",
+            args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM missed_commits WHERE intended_sortition_id = ?1",
+            args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM canonical_accepted_stacks_blocks WHERE tip_consensus_hash IN (SELECT consensus_hash FROM snapshots WHERE sortition_id = ?1)",
+            args,
+        )?;
+        execute(&tx, "DELETE FROM snapshots WHERE sortition_id = ?1", args)?;
+        trie_sql::drop_confirmed_trie(&tx, sortition_id).map_err(chainstate_error::MARFError)?;
+    }
+    execute(
+        &tx,
+        "DELETE FROM stack_stx WHERE block_height > ?1",
+        height_args,
+    )?;
+    execute(
+        &tx,
+        "DELETE FROM transfer_stx WHERE block_height > ?1",
+        height_args,
+    )?;
+
+    for (consensus_hash, block_hash, _) in truncated_blocks.iter() {
+        let hash_args: &[&dyn ToSql] = &[consensus_hash, block_hash];
+        execute(
+            &tx,
+            "UPDATE snapshots SET stacks_block_accepted = 0, stacks_block_height = 0, arrival_index = 0 WHERE consensus_hash = ?1 AND winning_stacks_block_hash = ?2",
+            hash_args,
+        )?;
+        execute(
+            &tx,
+            "DELETE FROM canonical_accepted_stacks_blocks WHERE consensus_hash = ?1 AND stacks_block_hash = ?2",
+            hash_args,
+        )?;
+
+        // sortitions whose canonical Stacks tip was this block get the highest remaining
+        // block that had been elected by then
+        let mut stmt = tx
+            .prepare("SELECT sortition_id, block_height FROM snapshots WHERE canonical_stacks_tip_consensus_hash = ?1 AND canonical_stacks_tip_hash = ?2")
+            .map_err(db_error::SqliteError)?;
+        let rows = stmt
+            .query_and_then(hash_args, |row| -> Result<_, db_error> {
+                Ok((
+                    SortitionId::from_column(row, "sortition_id")?,
+                    u64::from_column(row, "block_height")?,
+                ))
+            })
+            .map_err(db_error::SqliteError)?;
+        let mut stale_tips = vec![];
+        for row in rows {
+            stale_tips.push(row?);
+        }
+        drop(stmt);
+
+        for (sortition_id, burn_height) in stale_tips.into_iter() {
+            let (tip_consensus_hash, tip_block_hash, tip_height) = kept_blocks
+                .iter()
+                .take_while(|(block_burn_height, ..)| *block_burn_height <= burn_height)
+                .last()
+                .map(|(_, ch, bhh, height)| (ch.clone(), bhh.clone(), *height))
+                .unwrap_or((
+                    FIRST_BURNCHAIN_CONSENSUS_HASH.clone(),
+                    FIRST_STACKS_BLOCK_HASH.clone(),
+                    0,
+                ));
+            let args: &[&dyn ToSql] = &[
+                &u64_to_sql(tip_height)?,
+                &tip_block_hash,
+                &tip_consensus_hash,
+                &sortition_id,
+            ];
+            execute(
+                &tx,
+                "UPDATE snapshots SET canonical_stacks_tip_height = ?1, canonical_stacks_tip_hash = ?2, canonical_stacks_tip_consensus_hash = ?3 WHERE sortition_id = ?4",
+                args,
+            )?;
+        }
+    }
+    trie_sql::clear_lock_data(&tx).map_err(chainstate_error::MARFError)?;
+    tx.commit().map_err(db_error::SqliteError)?;
+    Ok(())
+}
+
+/// Remove every burnchain block and burnchain header past `burn_block_height`
+fn truncate_burnchain(node_dir: &Path, burn_block_height: u64) -> Result<(), Error> {
+    let height_args: &[&dyn ToSql] = &[&u64_to_sql(burn_block_height)?];
+
+    let mut conn = open_snapshot_db(&node_dir.join("burnchain").join("burnchain.sqlite"))?;
+    let tx = tx_begin_immediate(&mut conn)?;
+    execute(
+        &tx,
+        "DELETE FROM burnchain_db_block_ops WHERE block_hash IN (SELECT block_hash FROM burnchain_db_block_headers WHERE block_height > ?1)",
+        height_args,
+    )?;
+    execute(
+        &tx,
+        "DELETE FROM burnchain_db_block_headers WHERE block_height > ?1",
+        height_args,
+    )?;
+    tx.commit().map_err(db_error::SqliteError)?;
+
+    let headers_path = node_dir.join("headers.sqlite");
+    if headers_path.exists() {
+        let mut conn = open_snapshot_db(&headers_path)?;
+        let tx = tx_begin_immediate(&mut conn)?;
+        execute(&tx, "DELETE FROM headers WHERE height > ?1", height_args)?;
+        tx.commit().map_err(db_error::SqliteError)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::burnchains::db::BurnchainDB;
+    use crate::net::test::{TestPeer, TestPeerConfig};
+
+    fn make_node_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(format!("/tmp/stacks-snapshot-test-{}", name));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("chainstate/vm")).unwrap();
+        fs::create_dir_all(dir.join("chainstate/estimates")).unwrap();
+        fs::create_dir_all(dir.join("burnchain/sortition")).unwrap();
+        fs::write(dir.join("chainstate/vm/index.sqlite"), b"index").unwrap();
+        fs::write(dir.join("chainstate/mempool.sqlite"), b"mempool").unwrap();
+        fs::write(
+            dir.join("chainstate/estimates/fee_estimator.sqlite"),
+            b"fees",
+        )
+        .unwrap();
+        fs::write(dir.join("burnchain/sortition/marf.sqlite"), b"sortition").unwrap();
+        fs::write(dir.join("burnchain/burnchain.sqlite"), b"burnchain").unwrap();
+        fs::write(dir.join("peer.sqlite"), b"peers").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_snapshot_copy_and_verify_files() {
+        let node_dir = make_node_dir("copy-node");
+        let snapshot_dir = PathBuf::from("/tmp/stacks-snapshot-test-copy-snapshot");
+        if snapshot_dir.exists() {
+            fs::remove_dir_all(&snapshot_dir).unwrap();
+        }
+
+        let mut copied = vec![];
+        for rel_path in SNAPSHOT_PATHS.iter() {
+            copy_tree(&node_dir, &snapshot_dir, rel_path, &mut copied).unwrap();
+        }
+
+        // node-specific state is left out
+        assert_eq!(
+            copied,
+            vec![
+                "chainstate/vm/index.sqlite".to_string(),
+                "burnchain/burnchain.sqlite".to_string(),
+                "burnchain/sortition/marf.sqlite".to_string(),
+            ]
+        );
+
+        let files: Vec<_> = copied
+            .into_iter()
+            .map(|path| {
+                let (size, sha256) = hash_file(&snapshot_dir.join(&path)).unwrap();
+                SnapshotFile { path, size, sha256 }
+            })
+            .collect();
+        assert_eq!(files[0].size, 5);
+        assert_eq!(
+            files[0].sha256,
+            "1bc04b5291c26a46d918139138b992d2de976d6851d0893b0476b85bfbdfc6e6"
+        );
+
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            mainnet: false,
+            chain_id: 0x80000000,
+            anchor: SnapshotAnchor {
+                reward_cycle: 1,
+                burn_block_height: 2,
+                consensus_hash: ConsensusHash([0x01; 20]),
+                stacks_block_consensus_hash: ConsensusHash([0x02; 20]),
+                stacks_block_hash: BlockHeaderHash([0x03; 32]),
+                stacks_block_height: 4,
+                index_root: TrieHash([0x05; 32]),
+            },
+            files,
+        };
+        manifest.store(&snapshot_dir).unwrap();
+        let loaded = SnapshotManifest::load(&snapshot_dir).unwrap();
+        assert_eq!(loaded, manifest);
+        loaded.verify_files(&snapshot_dir).unwrap();
+
+        // install into a fresh node dir
+        let new_node_dir = PathBuf::from("/tmp/stacks-snapshot-test-copy-new-node");
+        if new_node_dir.exists() {
+            fs::remove_dir_all(&new_node_dir).unwrap();
+        }
+        install_snapshot(&snapshot_dir, &new_node_dir, &manifest).unwrap();
+        assert_eq!(
+            fs::read(new_node_dir.join("burnchain/burnchain.sqlite")).unwrap(),
+            b"burnchain".to_vec()
+        );
+
+        // won't install over existing chain state
+        match install_snapshot(&snapshot_dir, &new_node_dir, &manifest) {
+            Err(Error::AlreadyExists(..)) => {}
+            x => panic!("Expected AlreadyExists, got {:?}", &x),
+        }
+        uninstall_snapshot(&new_node_dir).unwrap();
+        assert!(!new_node_dir.join("chainstate").exists());
+
+        // tampering is detected
+        fs::write(
+            snapshot_dir.join("burnchain/burnchain.sqlite"),
+            b"burnchaim",
+        )
+        .unwrap();
+        match loaded.verify_files(&snapshot_dir) {
+            Err(Error::CorruptFile(..)) => {}
+            x => panic!("Expected CorruptFile, got {:?}", &x),
+        }
+        match install_snapshot(&snapshot_dir, &new_node_dir, &manifest) {
+            Err(Error::CorruptFile(..)) => {}
+            x => panic!("Expected CorruptFile, got {:?}", &x),
+        }
+    }
+
+    fn copy_dir(src: &Path, dest: &Path) {
+        fs::create_dir_all(dest).unwrap();
+        for entry in fs::read_dir(src).unwrap() {
+            let entry = entry.unwrap();
+            let dest_path = dest.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &dest_path);
+            } else {
+                fs::copy(&entry.path(), &dest_path).unwrap();
+            }
+        }
+    }
+
+    /// Lay out a test peer's chain state the way it is in a node's working directory
+    fn make_node_dir_from_peer(peer: &TestPeer, name: &str) -> PathBuf {
+        let dir = PathBuf::from(format!("/tmp/stacks-snapshot-test-{}", name));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        copy_dir(
+            &PathBuf::from(&peer.chainstate_path),
+            &dir.join("chainstate"),
+        );
+        // this has the sortition DB and the burnchain DB in it
+        copy_dir(
+            &PathBuf::from(&Burnchain::get_chainstate_path_str(
+                &peer.config.burnchain.working_dir,
+            )),
+            &dir.join("burnchain"),
+        );
+        dir
+    }
+
+    #[test]
+    fn test_verify_and_truncate_installed_snapshot() {
+        let peer_config = TestPeerConfig::new("test_verify_and_truncate_installed_snapshot", 0, 0);
+        let mut peer = TestPeer::new(peer_config);
+        let chain_id = peer.config.network_id;
+
+        let mut block_ids = vec![];
+        for _ in 0..10 {
+            let (burn_ops, stacks_block, microblocks) = peer.make_default_tenure();
+            let (_, _, consensus_hash) = peer.next_burnchain_block(burn_ops);
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+            block_ids.push(StacksBlockId::new(
+                &consensus_hash,
+                &stacks_block.block_hash(),
+            ));
+        }
+        let tip_height =
+            SortitionDB::get_canonical_burn_chain_tip(peer.sortdb.as_ref().unwrap().conn())
+                .unwrap()
+                .block_height;
+        let anchor_height = tip_height - 4;
+
+        let node_dir = make_node_dir_from_peer(&peer, "truncate-node");
+        let anchor = load_anchor_from_dir(&node_dir, false, chain_id, 1, anchor_height)
+            .unwrap()
+            .unwrap();
+        assert_eq!(anchor.burn_block_height, anchor_height);
+        let anchor_block_id = StacksBlockId::new(
+            &anchor.stacks_block_consensus_hash,
+            &anchor.stacks_block_hash,
+        );
+        assert_eq!(anchor_block_id, block_ids[5]);
+
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            mainnet: false,
+            chain_id,
+            anchor: anchor.clone(),
+            files: vec![],
+        };
+        verify_installed_snapshot(&node_dir, &manifest).unwrap();
+
+        // an index root that the header row agrees with, but the MARF doesn't, is caught
+        {
+            let forged_dir = make_node_dir_from_peer(&peer, "truncate-forged-node");
+            let forged_root = TrieHash([0x11; 32]);
+            let conn = open_snapshot_db(&StacksChainState::header_index_root_path(
+                forged_dir.join("chainstate"),
+            ))
+            .unwrap();
+            conn.execute(
+                "UPDATE block_headers SET index_root = ?1 WHERE index_block_hash = ?2",
+                &[&forged_root as &dyn ToSql, &anchor_block_id],
+            )
+            .unwrap();
+            let mut forged_manifest = manifest.clone();
+            forged_manifest.anchor.index_root = forged_root;
+            match verify_installed_snapshot(&forged_dir, &forged_manifest) {
+                Err(Error::AnchorMismatch(..)) => {}
+                x => panic!("Expected AnchorMismatch, got {:?}", &x),
+            }
+        }
+
+        truncate_installed_snapshot(&node_dir, &manifest).unwrap();
+
+        // the anchor still checks out, and is now the tip of both chains
+        verify_installed_snapshot(&node_dir, &manifest).unwrap();
+        let sortdb = SortitionDB::open(&sortdb_path_str(&node_dir), false).unwrap();
+        let (mut chainstate, _) =
+            StacksChainState::open(false, chain_id, &chainstate_path_str(&node_dir), None).unwrap();
+        let burn_tip = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn()).unwrap();
+        assert_eq!(burn_tip.block_height, anchor_height);
+        assert_eq!(burn_tip.consensus_hash, anchor.consensus_hash);
+        assert_eq!(
+            burn_tip.canonical_stacks_tip_consensus_hash,
+            anchor.stacks_block_consensus_hash
+        );
+        assert_eq!(burn_tip.canonical_stacks_tip_hash, anchor.stacks_block_hash);
+
+        let burnchain_db = BurnchainDB::open(
+            &node_dir
+                .join("burnchain")
+                .join("burnchain.sqlite")
+                .to_string_lossy(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            burnchain_db.get_canonical_chain_tip().unwrap().block_height,
+            anchor_height
+        );
+
+        // the anchor's ancestors are kept, and everything after it is gone
+        for (i, block_id) in block_ids.iter().enumerate() {
+            let header = StacksChainState::get_stacks_block_header_info_by_index_block_hash(
+                chainstate.db(),
+                block_id,
+            )
+            .unwrap();
+            let block_path =
+                StacksChainState::get_index_block_path(&chainstate.blocks_path, block_id).unwrap();
+            let has_trie = chainstate.state_index.get_root_hash_at(block_id).is_ok();
+            if i <= 5 {
+                assert!(header.is_some());
+                assert!(PathBuf::from(&block_path).exists());
+                assert!(has_trie);
+            } else {
+                assert!(header.is_none());
+                assert!(!PathBuf::from(&block_path).exists());
+                assert!(!has_trie);
+                assert!(!chainstate
+                    .clarity_state
+                    .with_marf(|marf| marf.get_root_hash_at(block_id))
+                    .is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_path_escape() {
+        let dir = PathBuf::from("/tmp/snapshot");
+        assert_eq!(
+            snapshot_path(&dir, "chainstate/vm/index.sqlite").unwrap(),
+            PathBuf::from("/tmp/snapshot/chainstate/vm/index.sqlite")
+        );
+        assert!(snapshot_path(&dir, "../etc/passwd").is_err());
+        assert!(snapshot_path(&dir, "chainstate/../../etc/passwd").is_err());
+        assert!(snapshot_path(&dir, "/etc/passwd").is_err());
+        assert!(snapshot_path(&dir, "chainstate//index.sqlite").is_err());
+    }
+}
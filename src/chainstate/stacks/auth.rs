@@ -138,6 +138,17 @@ impl StacksMessageCodec for MultisigSpendingCondition {
 
     fn consensus_deserialize<R: Read>(
         fd: &mut R,
+    ) -> Result<MultisigSpendingCondition, codec_error> {
+        MultisigSpendingCondition::deserialize(fd, false)
+    }
+}
+
+impl MultisigSpendingCondition {
+    /// Decode a multisig spending condition.  If `partially_signed` is true, the condition may
+    /// have fewer signatures than it requires, as it does while it is passed between signers.
+    fn deserialize<R: Read>(
+        fd: &mut R,
+        partially_signed: bool,
     ) -> Result<MultisigSpendingCondition, codec_error> {
         let hash_mode_u8: u8 = read_next(fd)?;
         let hash_mode = MultisigHashMode::from_u8(hash_mode_u8).ok_or(
@@ -183,7 +194,9 @@ impl StacksMessageCodec for MultisigSpendingCondition {
         }
 
         // must be given the right number of signatures
-        if num_sigs_given != signatures_required {
+        if num_sigs_given != signatures_required
+            && !(partially_signed && num_sigs_given < signatures_required)
+        {
             test_debug!(
                 "Failed to deserialize multisig spending condition: got {} sigs, expected {}",
                 num_sigs_given,
@@ -215,9 +228,7 @@ impl StacksMessageCodec for MultisigSpendingCondition {
             signatures_required,
         })
     }
-}
 
-impl MultisigSpendingCondition {
     pub fn push_signature(
         &mut self,
         key_encoding: TransactionPublicKeyEncoding,
@@ -483,6 +494,16 @@ impl StacksMessageCodec for TransactionSpendingCondition {
 
     fn consensus_deserialize<R: Read>(
         fd: &mut R,
+    ) -> Result<TransactionSpendingCondition, codec_error> {
+        TransactionSpendingCondition::deserialize(fd, false)
+    }
+}
+
+impl TransactionSpendingCondition {
+    /// Decode a spending condition, which may be partially signed if `partially_signed` is true.
+    fn deserialize<R: Read>(
+        fd: &mut R,
+        partially_signed: bool,
     ) -> Result<TransactionSpendingCondition, codec_error> {
         // peek the hash mode byte
         let hash_mode_u8: u8 = read_next(fd)?;
@@ -493,7 +514,7 @@ impl StacksMessageCodec for TransactionSpendingCondition {
                 let cond = SinglesigSpendingCondition::consensus_deserialize(&mut rrd)?;
                 TransactionSpendingCondition::Singlesig(cond)
             } else if MultisigHashMode::from_u8(hash_mode_u8).is_some() {
-                let cond = MultisigSpendingCondition::consensus_deserialize(&mut rrd)?;
+                let cond = MultisigSpendingCondition::deserialize(&mut rrd, partially_signed)?;
                 TransactionSpendingCondition::Multisig(cond)
             } else {
                 test_debug!("Invalid address hash mode {}", hash_mode_u8);
@@ -506,9 +527,7 @@ impl StacksMessageCodec for TransactionSpendingCondition {
 
         Ok(cond)
     }
-}
 
-impl TransactionSpendingCondition {
     pub fn new_singlesig_p2pkh(pubkey: StacksPublicKey) -> Option<TransactionSpendingCondition> {
         let key_encoding = if pubkey.compressed() {
             TransactionPublicKeyEncoding::Compressed
@@ -870,15 +889,26 @@ impl StacksMessageCodec for TransactionAuth {
     }
 
     fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TransactionAuth, codec_error> {
+        TransactionAuth::deserialize(fd, false)
+    }
+}
+
+impl TransactionAuth {
+    /// Decode a transaction authorization, whose multisig spending conditions may be partially
+    /// signed if `partially_signed` is true.
+    pub fn deserialize<R: Read>(
+        fd: &mut R,
+        partially_signed: bool,
+    ) -> Result<TransactionAuth, codec_error> {
         let type_id: u8 = read_next(fd)?;
         let auth = match type_id {
             x if x == TransactionAuthFlags::AuthStandard as u8 => {
-                let origin_auth: TransactionSpendingCondition = read_next(fd)?;
+                let origin_auth = TransactionSpendingCondition::deserialize(fd, partially_signed)?;
                 TransactionAuth::Standard(origin_auth)
             }
             x if x == TransactionAuthFlags::AuthSponsored as u8 => {
-                let origin_auth: TransactionSpendingCondition = read_next(fd)?;
-                let sponsor_auth: TransactionSpendingCondition = read_next(fd)?;
+                let origin_auth = TransactionSpendingCondition::deserialize(fd, partially_signed)?;
+                let sponsor_auth = TransactionSpendingCondition::deserialize(fd, partially_signed)?;
                 TransactionAuth::Sponsored(origin_auth, sponsor_auth)
             }
             _ => {
@@ -891,9 +921,7 @@ impl StacksMessageCodec for TransactionAuth {
         };
         Ok(auth)
     }
-}
 
-impl TransactionAuth {
     pub fn from_p2pkh(privk: &StacksPrivateKey) -> Option<TransactionAuth> {
         match TransactionSpendingCondition::new_singlesig_p2pkh(StacksPublicKey::from_private(
             privk,
@@ -214,6 +214,12 @@ fn cost_2_contract_is_arithmetic_only() {
     check_arithmetic_only(BOOT_CODE_COSTS_2);
 }
 
+#[test]
+fn cost_3_contract_is_arithmetic_only() {
+    use crate::chainstate::stacks::boot::BOOT_CODE_COSTS_3;
+    check_arithmetic_only(BOOT_CODE_COSTS_3);
+}
+
 impl HeadersDB for TestSimHeadersDB {
     fn get_burn_header_hash_for_block(
         &self,
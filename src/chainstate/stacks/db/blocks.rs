@@ -486,6 +486,102 @@ impl MicroblockStreamData {
     }
 }
 
+impl BlockRangeStreamData {
+    /// Send pending framing bytes.  Returns the number of bytes written.
+    fn stream_framing<W: Write>(&mut self, fd: &mut W, count: u64) -> Result<u64, Error> {
+        let end = cmp::min(
+            self.framing_buf.len(),
+            self.framing_ptr.saturating_add(count as usize),
+        );
+        fd.write_all(&self.framing_buf[self.framing_ptr..end])
+            .map_err(Error::WriteError)?;
+        let nw = (end - self.framing_ptr) as u64;
+        self.framing_ptr = end;
+        Ok(nw)
+    }
+
+    /// Set up the next part of the range to send, once the current one is exhausted.
+    /// Returns false if there's nothing left to send.
+    fn advance(&mut self, chainstate: &StacksChainState) -> Result<bool, Error> {
+        self.inner = None;
+        if self.block_ptr >= self.blocks.len() {
+            return Ok(false);
+        }
+
+        let item = &self.blocks[self.block_ptr];
+        match self.phase {
+            BlockRangeStreamPhase::ConsensusHashes => {
+                let mut framing_buf = vec![];
+                write_next(&mut framing_buf, &item.consensus_hash).map_err(Error::CodecError)?;
+                write_next(&mut framing_buf, &item.parent_consensus_hash)
+                    .map_err(Error::CodecError)?;
+                self.framing_buf = framing_buf;
+                self.framing_ptr = 0;
+                self.phase = BlockRangeStreamPhase::Block;
+            }
+            BlockRangeStreamPhase::Block => {
+                self.inner = Some(Box::new(StreamCursor::new_block(
+                    item.index_block_hash.clone(),
+                )));
+                self.phase = BlockRangeStreamPhase::ConfirmedMicroblocks;
+            }
+            BlockRangeStreamPhase::ConfirmedMicroblocks => {
+                match item.tail_index_microblock_hash {
+                    Some(ref tail_index_microblock_hash) => {
+                        self.inner = Some(Box::new(StreamCursor::new_microblock_confirmed(
+                            chainstate,
+                            tail_index_microblock_hash.clone(),
+                        )?));
+                    }
+                    None => {
+                        // empty stream -- just the length prefix
+                        self.framing_buf = 0u32.to_be_bytes().to_vec();
+                        self.framing_ptr = 0;
+                    }
+                }
+                self.phase = BlockRangeStreamPhase::ConsensusHashes;
+                self.block_ptr += 1;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Stream the next `count` bytes of the block range.  Returns 0 once the range has been sent.
+    fn stream_to<W: Write>(
+        &mut self,
+        mempool: &MemPoolDB,
+        chainstate: &mut StacksChainState,
+        fd: &mut W,
+        count: u64,
+    ) -> Result<u64, Error> {
+        // NOTE: a confirmed microblock stream can write its length prefix in addition to `count`
+        // bytes, so this can overshoot a little.
+        let mut num_written = 0;
+        while num_written < count {
+            let to_write = count - num_written;
+            let nw = if self.framing_ptr < self.framing_buf.len() {
+                self.stream_framing(fd, to_write)?
+            } else if let Some(ref mut inner) = self.inner {
+                inner.stream_to(mempool, chainstate, fd, to_write)?
+            } else {
+                0
+            };
+
+            if nw == 0 {
+                // EOF on this part; move on to the next
+                if !self.advance(chainstate)? {
+                    break;
+                }
+                continue;
+            }
+
+            self.add_bytes(nw);
+            num_written += nw;
+        }
+        Ok(num_written)
+    }
+}
+
 impl StreamCursor {
     pub fn new_block(index_block_hash: StacksBlockId) -> StreamCursor {
         StreamCursor::Block(BlockStreamData {
@@ -582,6 +678,85 @@ impl StreamCursor {
         }))
     }
 
+    /// Stream the anchored blocks at heights `start_height` through `end_height` on the fork
+    /// ending at `tip`, each followed by the microblock stream it confirms, as a
+    /// consensus-serialized `Vec<BlockRangeEntry>`.  Heights above `tip` are left out.
+    /// Fails with `NoSuchBlockError` if any block or confirmed microblock stream in the range is
+    /// not stored (or has been pruned).
+    pub fn new_block_range(
+        chainstate: &StacksChainState,
+        tip: &StacksBlockId,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<StreamCursor, Error> {
+        let tip_info = StacksChainState::load_staging_block_info(chainstate.db(), tip)?
+            .ok_or(Error::NoSuchBlockError)?;
+        let end_height = cmp::min(end_height, tip_info.height);
+
+        let index_conn = chainstate.index_conn()?;
+        let mut blocks = vec![];
+        for height in start_height..=end_height {
+            let index_block_hash = index_conn
+                .get_ancestor_block_hash(height, tip)?
+                .ok_or(Error::NoSuchBlockError)?;
+            let block_info =
+                StacksChainState::load_staging_block_info(chainstate.db(), &index_block_hash)?
+                    .ok_or(Error::NoSuchBlockError)?;
+            if !StacksChainState::has_block_indexed(&chainstate.blocks_path, &index_block_hash)? {
+                // not stored (or pruned)
+                return Err(Error::NoSuchBlockError);
+            }
+
+            let tail_index_microblock_hash = if block_info.parent_microblock_hash
+                == EMPTY_MICROBLOCK_PARENT_HASH
+                && block_info.parent_microblock_seq == 0
+            {
+                None
+            } else {
+                Some(StacksBlockHeader::make_index_block_hash(
+                    &block_info.parent_consensus_hash,
+                    &block_info.parent_microblock_hash,
+                ))
+            };
+            if let Some(tail_index_microblock_hash) = tail_index_microblock_hash.as_ref() {
+                if StacksChainState::is_microblock_pruned(
+                    chainstate.db(),
+                    tail_index_microblock_hash,
+                )? {
+                    // the confirmed stream can't be sent in full
+                    return Err(Error::NoSuchBlockError);
+                }
+            }
+
+            blocks.push(BlockRangeItem {
+                consensus_hash: block_info.consensus_hash,
+                parent_consensus_hash: block_info.parent_consensus_hash,
+                index_block_hash,
+                tail_index_microblock_hash,
+            });
+        }
+
+        test_debug!(
+            "Request for blocks {}-{} from {}: {} block(s)",
+            start_height,
+            end_height,
+            tip,
+            blocks.len()
+        );
+
+        // the range is sent as a consensus-serialized vector, so lead with its length
+        let framing_buf = (blocks.len() as u32).to_be_bytes().to_vec();
+        Ok(StreamCursor::BlockRange(BlockRangeStreamData {
+            blocks,
+            block_ptr: 0,
+            framing_buf,
+            framing_ptr: 0,
+            inner: None,
+            phase: BlockRangeStreamPhase::ConsensusHashes,
+            total_bytes: 0,
+        }))
+    }
+
     pub fn new_tx_stream(
         tx_query: MemPoolSyncData,
         max_txs: u64,
@@ -637,6 +812,7 @@ impl StreamCursor {
             StreamCursor::Block(ref stream) => stream.offset(),
             StreamCursor::Microblocks(ref stream) => stream.offset(),
             StreamCursor::Headers(ref stream) => stream.offset(),
+            StreamCursor::BlockRange(ref stream) => stream.offset(),
             // no-op for mempool txs
             StreamCursor::MempoolTxs(..) => 0,
         }
@@ -647,6 +823,7 @@ impl StreamCursor {
             StreamCursor::Block(ref mut stream) => stream.add_bytes(nw),
             StreamCursor::Microblocks(ref mut stream) => stream.add_bytes(nw),
             StreamCursor::Headers(ref mut stream) => stream.add_bytes(nw),
+            StreamCursor::BlockRange(ref mut stream) => stream.add_bytes(nw),
             // no-op fo mempool txs
             StreamCursor::MempoolTxs(..) => (),
         }
@@ -701,6 +878,9 @@ impl StreamCursor {
                 Ok(num_written)
             }
             StreamCursor::Block(ref mut stream) => chainstate.stream_block(fd, stream, count),
+            StreamCursor::BlockRange(ref mut stream) => {
+                stream.stream_to(mempool, chainstate, fd, count)
+            }
         }
     }
 }
@@ -724,6 +904,15 @@ impl Streamer for HeaderStreamData {
     }
 }
 
+impl Streamer for BlockRangeStreamData {
+    fn offset(&self) -> u64 {
+        self.total_bytes
+    }
+    fn add_bytes(&mut self, nw: u64) {
+        self.total_bytes += nw;
+    }
+}
+
 impl Streamer for BlockStreamData {
     fn offset(&self) -> u64 {
         self.offset
@@ -2357,7 +2546,8 @@ impl StacksChainState {
         let sql = "SELECT staging_blocks.consensus_hash, staging_blocks.processed, staging_blocks.orphaned, staging_microblocks.processed, staging_microblocks.orphaned \
                    FROM staging_blocks LEFT JOIN staging_microblocks \
                    ON staging_blocks.parent_microblock_hash = staging_microblocks.microblock_hash \
-                   WHERE staging_blocks.height >= ?1 AND staging_blocks.height <= ?2";
+                   WHERE staging_blocks.height >= ?1 AND staging_blocks.height <= ?2 \
+                   AND staging_blocks.index_block_hash NOT IN (SELECT index_block_hash FROM pruned_blocks)";
         let args: &[&dyn ToSql] = &[&u64_to_sql(start_height)?, &u64_to_sql(end_height)?];
 
         let mut stmt = self.db().prepare(sql)?;
@@ -3875,6 +4065,14 @@ impl StacksChainState {
                 &index_block_hash
             );
             return Ok(true);
+        } else if StacksChainState::is_block_pruned(conn, &index_block_hash)? {
+            debug!(
+                "Block already processed and pruned: {}/{} ({})",
+                consensus_hash,
+                &block.block_hash(),
+                &index_block_hash
+            );
+            return Ok(true);
         }
 
         Ok(false)
@@ -4601,7 +4799,16 @@ impl StacksChainState {
                         applied = true;
                     }
                     StacksEpochId::Epoch2_05 => {
-                        panic!("No defined transition from Epoch2_05 forward")
+                        assert_eq!(
+                            sortition_epoch.epoch_id,
+                            StacksEpochId::Epoch21,
+                            "Should only transition from Epoch2_05 to Epoch21"
+                        );
+                        receipts.push(clarity_tx.block.initialize_epoch_2_1()?);
+                        applied = true;
+                    }
+                    StacksEpochId::Epoch21 => {
+                        panic!("No defined transition from Epoch21 forward")
                     }
                 }
             }
@@ -98,6 +98,7 @@ pub mod accounts;
 pub mod blocks;
 pub mod contracts;
 pub mod headers;
+pub mod prune;
 pub mod transactions;
 pub mod unconfirmed;
 
@@ -194,6 +195,7 @@ impl DBConfig {
             StacksEpochId::Epoch10 => false,
             StacksEpochId::Epoch20 => self.version == "1" || self.version == "2",
             StacksEpochId::Epoch2_05 => self.version == "2",
+            StacksEpochId::Epoch21 => self.version == "2",
         }
     }
 }
@@ -505,6 +507,7 @@ pub enum StreamCursor {
     Microblocks(MicroblockStreamData),
     Headers(HeaderStreamData),
     MempoolTxs(TxStreamData),
+    BlockRange(BlockRangeStreamData),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -558,6 +561,42 @@ pub struct HeaderStreamData {
     corked: bool,
 }
 
+/// The parts of each block in a bulk block range, in the order they are streamed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BlockRangeStreamPhase {
+    ConsensusHashes,
+    Block,
+    ConfirmedMicroblocks,
+}
+
+/// A block in a bulk block range that we have yet to stream
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlockRangeItem {
+    pub consensus_hash: ConsensusHash,
+    pub parent_consensus_hash: ConsensusHash,
+    pub index_block_hash: StacksBlockId,
+    /// index hash of the last microblock in the stream this block confirms, if any
+    pub tail_index_microblock_hash: Option<StacksBlockId>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlockRangeStreamData {
+    /// blocks to send, in ascending height order
+    blocks: Vec<BlockRangeItem>,
+    /// index into `blocks` of the block being sent
+    block_ptr: usize,
+    /// framing bytes to send before (or instead of) the inner stream: the range's length
+    /// prefix, a block's consensus hashes, or an empty microblock stream
+    framing_buf: Vec<u8>,
+    framing_ptr: usize,
+    /// stream for the block or confirmed microblocks being sent
+    inner: Option<Box<StreamCursor>>,
+    /// what to send next for blocks[block_ptr]
+    phase: BlockRangeStreamPhase,
+    /// total number of bytes sent
+    total_bytes: u64,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct TxStreamData {
     /// Mempool sync data requested
@@ -578,7 +617,7 @@ pub struct TxStreamData {
     pub corked: bool,
 }
 
-pub const CHAINSTATE_VERSION: &'static str = "2";
+pub const CHAINSTATE_VERSION: &'static str = "3";
 
 const CHAINSTATE_INITIAL_SCHEMA: &'static [&'static str] = &[
     "PRAGMA foreign_keys = ON;",
@@ -731,6 +770,19 @@ const CHAINSTATE_SCHEMA_2: &'static [&'static str] = &[
     "#,
 ];
 
+const CHAINSTATE_SCHEMA_3: &'static [&'static str] = &[
+    // new in schema version 3
+    // table of processed blocks whose block and microblock data has been deleted by a pruned node
+    r#"
+    CREATE TABLE pruned_blocks(
+        index_block_hash TEXT PRIMARY KEY,
+        height INTEGER NOT NULL
+    );"#,
+    r#"
+    UPDATE db_config SET version = "3";
+    "#,
+];
+
 const CHAINSTATE_INDEXES: &'static [&'static str] = &[
     "CREATE INDEX IF NOT EXISTS index_block_hash_to_primary_key ON block_headers(index_block_hash,consensus_hash,block_hash);",
     "CREATE INDEX IF NOT EXISTS block_headers_hash_index ON block_headers(block_hash,block_height);",
@@ -941,6 +993,13 @@ impl StacksChainState {
                             tx.execute_batch(cmd)?;
                         }
                     }
+                    "2" => {
+                        // migrate to 3
+                        info!("Migrating chainstate schema from version 2 to 3");
+                        for cmd in CHAINSTATE_SCHEMA_3.iter() {
+                            tx.execute_batch(cmd)?;
+                        }
+                    }
                     _ => {
                         error!(
                             "Invalid chain state database: expected version = {}, got {}",
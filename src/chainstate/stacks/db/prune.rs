@@ -0,0 +1,448 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020-2022 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Pruned-node support.
+//!
+//! A pruned node deletes the raw block and microblock data of blocks it processed more than a
+//! given number of reward cycles ago.  It keeps their headers, and the MARF tries of the chain
+//! history, so it can still validate new blocks and answer queries about recent chain state.  It
+//! just can't serve the pruned blocks and microblocks to anyone else, so it stops advertising
+//! them in its block inventories.
+//!
+//! Optionally, a pruned node can also drop the MARF tries of stale forks that are older than the
+//! prune height, since no recent block can ever read them again.  This rewrites the MARFs, so it
+//! can only be done while nothing else has the chainstate open (see
+//! `StacksChainState::compact_marfs`).
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use rusqlite::types::ToSql;
+use rusqlite::OpenFlags;
+use rusqlite::NO_PARAMS;
+
+use crate::burnchains::Burnchain;
+use crate::chainstate::stacks::db::blocks::StagingBlock;
+use crate::chainstate::stacks::db::StacksChainState;
+use crate::chainstate::stacks::index::file::TrieFile;
+use crate::chainstate::stacks::Error;
+use crate::types::chainstate::StacksBlockId;
+use crate::util_lib::db::Error as db_error;
+use crate::util_lib::db::{query_row, query_rows, sqlite_open, u64_to_sql, DBConn};
+
+impl StacksChainState {
+    /// Has this block's data been pruned?
+    pub fn is_block_pruned(conn: &DBConn, index_block_hash: &StacksBlockId) -> Result<bool, Error> {
+        let sql = "SELECT 1 FROM pruned_blocks WHERE index_block_hash = ?1";
+        let args: &[&dyn ToSql] = &[index_block_hash];
+        let res: Option<i64> = query_row(conn, sql, args)?;
+        Ok(res.is_some())
+    }
+
+    /// Has the data of the microblock with this index hash been pruned?
+    pub fn is_microblock_pruned(
+        conn: &DBConn,
+        index_microblock_hash: &StacksBlockId,
+    ) -> Result<bool, Error> {
+        let sql =
+            "SELECT 1 FROM staging_microblocks WHERE index_microblock_hash = ?1 AND processed = 1 \
+                   AND microblock_hash NOT IN (SELECT block_hash FROM staging_microblocks_data)";
+        let args: &[&dyn ToSql] = &[index_microblock_hash];
+        let res: Option<i64> = query_row(conn, sql, args)?;
+        Ok(res.is_some())
+    }
+
+    /// Find the lowest Stacks block height whose data a pruned node keeps, if it keeps the last
+    /// `keep_reward_cycles` reward cycles' worth of blocks.  Everything below it can be pruned.
+    /// Returns Ok(None) if nothing can be pruned yet.
+    pub fn get_prune_height(
+        conn: &DBConn,
+        burnchain: &Burnchain,
+        keep_reward_cycles: u64,
+    ) -> Result<Option<u64>, Error> {
+        let sql = "SELECT MAX(burn_header_height) FROM block_headers";
+        let max_burn_height: Option<i64> = conn.query_row(sql, NO_PARAMS, |row| row.get(0))?;
+        let max_burn_height = match max_burn_height {
+            Some(h) => h as u64,
+            None => {
+                return Ok(None);
+            }
+        };
+
+        let reward_cycle = match burnchain.block_height_to_reward_cycle(max_burn_height) {
+            Some(rc) => rc,
+            None => {
+                return Ok(None);
+            }
+        };
+        if reward_cycle < keep_reward_cycles {
+            return Ok(None);
+        }
+
+        let prune_burn_height =
+            burnchain.reward_cycle_to_block_height(reward_cycle - keep_reward_cycles);
+        let sql = "SELECT MIN(block_height) FROM block_headers WHERE burn_header_height >= ?1";
+        let args: &[&dyn ToSql] = &[&u64_to_sql(prune_burn_height)?];
+        let prune_height: Option<i64> = conn.query_row(sql, args, |row| row.get(0))?;
+        Ok(prune_height.map(|h| h as u64))
+    }
+
+    /// Delete the data of up to `max_blocks` processed (or orphaned) blocks below the prune
+    /// height, as well as the microblock streams they confirmed.  Their headers are kept.
+    /// Returns the number of blocks pruned.
+    pub fn prune_blocks(
+        &mut self,
+        burnchain: &Burnchain,
+        keep_reward_cycles: u64,
+        max_blocks: u64,
+    ) -> Result<u64, Error> {
+        let prune_height =
+            match StacksChainState::get_prune_height(self.db(), burnchain, keep_reward_cycles)? {
+                Some(h) => h,
+                None => {
+                    return Ok(0);
+                }
+            };
+
+        let sql =
+            "SELECT * FROM staging_blocks WHERE height < ?1 AND (processed = 1 OR orphaned = 1) \
+                   AND index_block_hash NOT IN (SELECT index_block_hash FROM pruned_blocks) \
+                   ORDER BY height LIMIT ?2";
+        let args: &[&dyn ToSql] = &[&u64_to_sql(prune_height)?, &u64_to_sql(max_blocks)?];
+        let blocks: Vec<StagingBlock> = query_rows(self.db(), sql, args)?;
+        if blocks.len() == 0 {
+            return Ok(0);
+        }
+
+        let tx = self.db_tx_begin()?;
+        for block in blocks.iter() {
+            let index_block_hash =
+                StacksBlockId::new(&block.consensus_hash, &block.anchored_block_hash);
+            tx.execute(
+                "INSERT OR REPLACE INTO pruned_blocks (index_block_hash, height) VALUES (?1, ?2)",
+                &[&index_block_hash as &dyn ToSql, &u64_to_sql(block.height)?],
+            )?;
+
+            // the microblock stream this block confirmed was produced by its parent
+            let parent_index_block_hash = StacksBlockId::new(
+                &block.parent_consensus_hash,
+                &block.parent_anchored_block_hash,
+            );
+            tx.execute(
+                "DELETE FROM staging_microblocks_data WHERE block_hash IN \
+                 (SELECT microblock_hash FROM staging_microblocks WHERE index_block_hash = ?1 AND sequence <= ?2)",
+                &[
+                    &parent_index_block_hash as &dyn ToSql,
+                    &block.parent_microblock_seq,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        // only delete the blocks once they're recorded as pruned, so we never mistake a pruned
+        // block for one we still need to download
+        for block in blocks.iter() {
+            let index_block_hash =
+                StacksBlockId::new(&block.consensus_hash, &block.anchored_block_hash);
+            let block_path =
+                StacksChainState::get_index_block_path(&self.blocks_path, &index_block_hash)?;
+            if let Err(e) = fs::remove_file(&block_path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(Error::DBError(db_error::IOError(e)));
+                }
+            }
+        }
+
+        debug!(
+            "Pruned {} blocks below height {} from {}",
+            blocks.len(),
+            prune_height,
+            &self.blocks_path
+        );
+        Ok(blocks.len() as u64)
+    }
+
+    /// Find the processed blocks below `prune_height` that are not ancestors of any block at or
+    /// above it.  Their MARF tries can never be read again.
+    fn get_stale_fork_blocks(
+        conn: &DBConn,
+        prune_height: u64,
+    ) -> Result<HashSet<StacksBlockId>, Error> {
+        let mut stmt = conn
+            .prepare("SELECT index_block_hash, parent_block_id, block_height FROM block_headers")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+
+        let mut parents = HashMap::new();
+        let mut recent = vec![];
+        while let Some(row) = rows.next()? {
+            let index_block_hash: StacksBlockId = row.get_unwrap("index_block_hash");
+            let parent_block_id: StacksBlockId = row.get_unwrap("parent_block_id");
+            let block_height: i64 = row.get_unwrap("block_height");
+            if (block_height as u64) >= prune_height {
+                recent.push(index_block_hash.clone());
+            }
+            parents.insert(index_block_hash, (parent_block_id, block_height as u64));
+        }
+
+        if recent.len() == 0 {
+            // no recent blocks to anchor the live set
+            return Ok(HashSet::new());
+        }
+
+        // everything reachable from a recent block is live
+        let mut live = HashSet::new();
+        for block_id in recent.into_iter() {
+            let mut cursor = block_id;
+            while live.insert(cursor.clone()) {
+                match parents.get(&cursor) {
+                    Some((parent_block_id, _)) => {
+                        cursor = parent_block_id.clone();
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(parents
+            .into_iter()
+            .filter_map(|(block_id, (_, block_height))| {
+                if block_height < prune_height && !live.contains(&block_id) {
+                    Some(block_id)
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Drop the MARF tries of stale forks below the prune height from both the headers index and
+    /// the Clarity state, and compact the MARFs' blobs files.  Returns the number of blocks whose
+    /// tries were dropped.
+    /// NOTE: this rewrites the MARFs in place, so nothing else may have this chainstate open.
+    pub fn compact_marfs(
+        chainstate_path: &str,
+        burnchain: &Burnchain,
+        keep_reward_cycles: u64,
+    ) -> Result<u64, Error> {
+        let index_path = StacksChainState::header_index_root_path(PathBuf::from(chainstate_path));
+        let index_path_str = index_path
+            .to_str()
+            .ok_or_else(|| Error::DBError(db_error::ParseError))?
+            .to_string();
+        let clarity_path =
+            StacksChainState::vm_state_index_marf_path(PathBuf::from(chainstate_path));
+        let clarity_path_str = clarity_path
+            .to_str()
+            .ok_or_else(|| Error::DBError(db_error::ParseError))?
+            .to_string();
+
+        let mut index_conn = sqlite_open(&index_path, OpenFlags::SQLITE_OPEN_READ_WRITE, true)?;
+        let prune_height =
+            match StacksChainState::get_prune_height(&index_conn, burnchain, keep_reward_cycles)? {
+                Some(h) => h,
+                None => {
+                    return Ok(0);
+                }
+            };
+
+        let stale = StacksChainState::get_stale_fork_blocks(&index_conn, prune_height)?;
+        if stale.len() == 0 {
+            return Ok(0);
+        }
+
+        info!(
+            "Drop MARF tries of {} stale fork blocks below height {} in {}",
+            stale.len(),
+            prune_height,
+            chainstate_path
+        );
+
+        let mut clarity_conn = sqlite_open(&clarity_path, OpenFlags::SQLITE_OPEN_READ_WRITE, true)?;
+        TrieFile::compact_trie_blobs(&mut clarity_conn, &clarity_path_str, &stale)?;
+        TrieFile::compact_trie_blobs(&mut index_conn, &index_path_str, &stale)?;
+        Ok(stale.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cmp;
+
+    use crate::chainstate::burn::db::sortdb::SortitionDB;
+    use crate::chainstate::burn::ConsensusHash;
+    use crate::chainstate::stacks::db::StreamCursor;
+    use crate::net::test::{TestPeer, TestPeerConfig};
+    use crate::types::chainstate::BlockHeaderHash;
+
+    #[test]
+    fn test_prune_blocks() {
+        let peer_config = TestPeerConfig::new("test_prune_blocks", 0, 0);
+        let mut peer = TestPeer::new(peer_config);
+        let burnchain = peer.config.burnchain.clone();
+
+        let mut blocks = vec![];
+        for _ in 0..10 {
+            let (burn_ops, stacks_block, microblocks) = peer.make_default_tenure();
+            let (burn_height, _, consensus_hash) = peer.next_burnchain_block(burn_ops);
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+            blocks.push((burn_height, consensus_hash, stacks_block.block_hash()));
+        }
+
+        let chainstate = peer.chainstate();
+        let prune_height = StacksChainState::get_prune_height(chainstate.db(), &burnchain, 1)
+            .unwrap()
+            .unwrap();
+        assert!(prune_height > 1);
+
+        // prune one block at a time, until there's nothing left below the prune height
+        let mut num_pruned = 0;
+        loop {
+            let pruned = chainstate.prune_blocks(&burnchain, 1, 1).unwrap();
+            if pruned == 0 {
+                break;
+            }
+            assert_eq!(pruned, 1);
+            num_pruned += pruned;
+        }
+        assert_eq!(num_pruned, prune_height - 1);
+
+        let header_hashes: Vec<(ConsensusHash, Option<BlockHeaderHash>)> = blocks
+            .iter()
+            .map(|(_, ch, bhh)| (ch.clone(), Some(bhh.clone())))
+            .collect();
+        let inv = chainstate.get_blocks_inventory(&header_hashes).unwrap();
+
+        for (i, (_, consensus_hash, block_hash)) in blocks.iter().enumerate() {
+            // the i-th tenure mined the block at height i + 1
+            let index_block_hash = StacksBlockId::new(consensus_hash, block_hash);
+            let pruned = (i as u64) + 1 < prune_height;
+            assert_eq!(
+                StacksChainState::is_block_pruned(chainstate.db(), &index_block_hash).unwrap(),
+                pruned
+            );
+            assert_eq!(
+                StacksChainState::has_block_indexed(&chainstate.blocks_path, &index_block_hash)
+                    .unwrap(),
+                !pruned
+            );
+            assert_eq!(inv.has_ith_block(i as u16), !pruned);
+
+            // headers are kept
+            assert!(StacksChainState::get_anchored_block_header_info(
+                chainstate.db(),
+                consensus_hash,
+                block_hash
+            )
+            .unwrap()
+            .is_some());
+        }
+
+        // the per-reward-cycle inventory (which is what peers are sent) agrees
+        let sortdb = peer.sortdb.take().unwrap();
+        let tip_sort_id = SortitionDB::get_canonical_sortition_tip(sortdb.conn()).unwrap();
+        let tip_height = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())
+            .unwrap()
+            .block_height;
+        let chainstate = peer.chainstate();
+        let max_reward_cycle = burnchain.block_height_to_reward_cycle(tip_height).unwrap();
+        for reward_cycle in 0..=max_reward_cycle {
+            let start_height = burnchain.reward_cycle_to_block_height(reward_cycle);
+            let mut cycle_hashes = vec![];
+            let mut expected_bits = vec![];
+            for burn_height in start_height
+                ..cmp::min(
+                    start_height + burnchain.pox_constants.reward_cycle_length as u64,
+                    tip_height + 1,
+                )
+            {
+                let sn = SortitionDB::get_ancestor_snapshot(
+                    &sortdb.index_conn(),
+                    burn_height,
+                    &tip_sort_id,
+                )
+                .unwrap()
+                .unwrap();
+                let stacks_block_opt = blocks
+                    .iter()
+                    .enumerate()
+                    .find(|(_, (h, _, _))| *h == burn_height);
+                match stacks_block_opt {
+                    Some((i, (_, consensus_hash, block_hash))) => {
+                        cycle_hashes.push((consensus_hash.clone(), Some(block_hash.clone())));
+                        expected_bits.push((i as u64) + 1 >= prune_height);
+                    }
+                    None => {
+                        cycle_hashes.push((sn.consensus_hash, None));
+                        expected_bits.push(false);
+                    }
+                }
+            }
+            let cycle_inv = chainstate
+                .get_blocks_inventory_for_reward_cycle(&burnchain, reward_cycle, &cycle_hashes)
+                .unwrap();
+            for (i, expected) in expected_bits.into_iter().enumerate() {
+                assert_eq!(cycle_inv.has_ith_block(i as u16), expected);
+            }
+        }
+        peer.sortdb = Some(sortdb);
+
+        // block ranges are only served if every block and confirmed stream in them is stored
+        let chainstate = peer.chainstate();
+        let tip = {
+            let (_, consensus_hash, block_hash) = blocks.last().unwrap();
+            StacksBlockId::new(consensus_hash, block_hash)
+        };
+        StreamCursor::new_block_range(chainstate, &tip, prune_height, blocks.len() as u64).unwrap();
+        match StreamCursor::new_block_range(chainstate, &tip, prune_height - 1, prune_height) {
+            Err(Error::NoSuchBlockError) => {}
+            res => panic!("Expected NoSuchBlockError, got {:?}", res.map(|_| ())),
+        }
+
+        // the first kept block's confirmed stream is gone too
+        let (_, consensus_hash, block_hash) = &blocks[(prune_height - 1) as usize];
+        let block_info = StacksChainState::load_staging_block_info(
+            chainstate.db(),
+            &StacksBlockId::new(consensus_hash, block_hash),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(block_info.parent_microblock_seq > 0);
+        let parent_index_block_hash = StacksBlockId::new(
+            &block_info.parent_consensus_hash,
+            &block_info.parent_anchored_block_hash,
+        );
+        chainstate
+            .db()
+            .execute(
+                "DELETE FROM staging_microblocks_data WHERE block_hash IN \
+                 (SELECT microblock_hash FROM staging_microblocks WHERE index_block_hash = ?1)",
+                &[&parent_index_block_hash],
+            )
+            .unwrap();
+        match StreamCursor::new_block_range(chainstate, &tip, prune_height, blocks.len() as u64) {
+            Err(Error::NoSuchBlockError) => {}
+            res => panic!("Expected NoSuchBlockError, got {:?}", res.map(|_| ())),
+        }
+        StreamCursor::new_block_range(chainstate, &tip, prune_height + 1, blocks.len() as u64)
+            .unwrap();
+    }
+}
@@ -335,6 +335,117 @@ impl TrieFile {
         trie_sql::set_migrated(db).expect("FATAL: failed to mark DB as migrated");
         Ok(())
     }
+
+    /// Drop the given confirmed tries from a MARF's DB, and rewrite its blobs file (if it has one)
+    /// so that the remaining tries are stored contiguously.  Returns the number of tries dropped.
+    /// Only tries that no remaining trie can reach via a back-pointer may be dropped.
+    /// NOTE: this is *not* thread-safe.  Do not call while the DB is being used by another thread.
+    /// If interrupted, the next open of the MARF finishes (or discards) the compaction -- see
+    /// `finish_pending_blobs_swap()`.
+    pub fn compact_trie_blobs<T: MarfTrieId>(
+        db: &mut Connection,
+        db_path: &str,
+        stale: &HashSet<T>,
+    ) -> Result<u64, Error> {
+        let num_dropped = TrieFile::commit_compacted_trie_blobs(db, db_path, stale)?;
+        if num_dropped == 0 {
+            return Ok(0);
+        }
+        TrieFile::finish_pending_blobs_swap(db, db_path, false)?;
+        TrieFile::post_migrate_vacuum(db, db_path);
+        Ok(num_dropped)
+    }
+
+    /// First half of `compact_trie_blobs()`: drop the stale tries, write the compacted blobs to
+    /// `{db_path}.blobs.compact`, and commit the new offsets along with a pending-swap marker.
+    /// The compacted file is not yet renamed into place.
+    pub fn commit_compacted_trie_blobs<T: MarfTrieId>(
+        db: &mut Connection,
+        db_path: &str,
+        stale: &HashSet<T>,
+    ) -> Result<u64, Error> {
+        let tx = tx_begin_immediate(db)?;
+        let mut num_dropped = 0;
+        for bhh in stale.iter() {
+            if trie_sql::drop_confirmed_trie(&tx, bhh)? {
+                num_dropped += 1;
+            }
+        }
+        if num_dropped == 0 {
+            return Ok(0);
+        }
+
+        if TrieFile::exists(db_path)? {
+            let blobs_path = format!("{}.blobs", db_path);
+            let compact_path = format!("{}.blobs.compact", db_path);
+            info!(
+                "Compact {} after dropping {} tries",
+                &blobs_path, num_dropped
+            );
+
+            let mut old_fd = fs::File::open(&blobs_path)?;
+            let mut new_fd = BufWriter::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&compact_path)?,
+            );
+
+            let mut new_offset = 0;
+            let mut trie_blob = vec![];
+            for (block_id, offset, length) in trie_sql::get_external_trie_offsets_lengths(&tx)? {
+                trie_blob.resize(length as usize, 0);
+                old_fd.seek(SeekFrom::Start(offset))?;
+                old_fd.read_exact(&mut trie_blob)?;
+                new_fd.write_all(&trie_blob)?;
+
+                trie_sql::set_external_trie_offset(&tx, block_id, new_offset)?;
+                new_offset += length;
+            }
+
+            let new_fd = new_fd
+                .into_inner()
+                .map_err(|e| Error::IOError(e.into_error()))?;
+            new_fd.sync_all()?;
+            trie_sql::set_pending_blobs_swap(&tx)?;
+        }
+        tx.commit()?;
+        Ok(num_dropped)
+    }
+
+    /// Second half of `compact_trie_blobs()`, also run whenever a MARF is opened: if the DB's
+    /// offsets refer to a compacted blobs file that has not been renamed into place, rename it.
+    /// A leftover compacted file without the marker is from an uncommitted compaction, and is
+    /// ignored (the next compaction truncates it).  The marker is only cleared if `readonly` is
+    /// false; a read-only open leaves it for the next read-write open.
+    pub fn finish_pending_blobs_swap(
+        db: &Connection,
+        db_path: &str,
+        readonly: bool,
+    ) -> Result<(), Error> {
+        if !trie_sql::has_pending_blobs_swap(db)? {
+            return Ok(());
+        }
+
+        let blobs_path = format!("{}.blobs", db_path);
+        let compact_path = format!("{}.blobs.compact", db_path);
+        match fs::rename(&compact_path, &blobs_path) {
+            Ok(()) => {
+                info!("Swapped in compacted {}", &blobs_path);
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(Error::IOError(e));
+                }
+                // already renamed
+            }
+        }
+        if !readonly {
+            trie_sql::clear_pending_blobs_swap(db)?;
+        }
+        Ok(())
+    }
 }
 
 /// NodeHashReader for TrieFile
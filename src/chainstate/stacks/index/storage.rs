@@ -1428,6 +1428,10 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
             trie_sql::create_tables_if_needed(&mut db)?;
         }
 
+        if marf_opts.external_blobs {
+            TrieFile::finish_pending_blobs_swap(&db, &db_path, readonly)?;
+        }
+
         let mut blobs = if marf_opts.external_blobs {
             Some(TrieFile::from_db_path(&db_path, readonly)?)
         } else {
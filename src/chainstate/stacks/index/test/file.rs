@@ -23,6 +23,7 @@ use crate::chainstate::stacks::index::*;
 use crate::util_lib::db::*;
 use rusqlite::Connection;
 use rusqlite::OpenFlags;
+use std::collections::HashSet;
 use std::fs;
 
 use super::*;
@@ -162,3 +163,204 @@ fn test_migrate_existing_trie_blobs() {
         }
     }
 }
+
+fn make_block_header(fork: u8, i: u64) -> BlockHeaderHash {
+    let mut block_hash_bytes = [0u8; 32];
+    block_hash_bytes[0] = fork;
+    block_hash_bytes[1..9].copy_from_slice(&i.to_be_bytes());
+    BlockHeaderHash(block_hash_bytes)
+}
+
+/// Build a MARF with external blobs that has a main chain of 8 blocks, and a stale fork of 4
+/// blocks off of its third block.  Returns the inserted data, the stale fork's blocks, and the
+/// main chain's tip.
+fn setup_compact_trie_blobs(
+    test_file: &str,
+) -> (
+    Vec<Vec<(String, MARFValue)>>,
+    HashSet<BlockHeaderHash>,
+    BlockHeaderHash,
+) {
+    let test_blobs_file = format!("{}.blobs", test_file);
+    let test_compact_file = format!("{}.blobs.compact", test_file);
+    for path in [test_file, &test_blobs_file, &test_compact_file].iter() {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    let data = make_test_insert_data(32, 12);
+    let mut stale = HashSet::new();
+
+    let marf_opts = MARFOpenOpts::new(TrieHashCalculationMode::Deferred, "noop", true);
+    let f = TrieFileStorage::open(test_file, marf_opts).unwrap();
+    let mut marf = MARF::from_storage(f);
+
+    let mut last_block_header = BlockHeaderHash::sentinel();
+    let mut fork_point = BlockHeaderHash::sentinel();
+    for i in 0..8 {
+        let block_header = make_block_header(0, i);
+        marf.begin(&last_block_header, &block_header).unwrap();
+        for (key, value) in data[i as usize].iter() {
+            let path = TriePath::from_key(key);
+            let leaf = TrieLeaf::from_value(&vec![], value.clone());
+            marf.insert_raw(path, leaf).unwrap();
+        }
+        marf.commit().unwrap();
+        if i == 2 {
+            fork_point = block_header.clone();
+        }
+        last_block_header = block_header;
+    }
+
+    let mut last_fork_header = fork_point;
+    for i in 0..4 {
+        let block_header = make_block_header(1, i);
+        marf.begin(&last_fork_header, &block_header).unwrap();
+        for (key, value) in data[8 + i as usize].iter() {
+            let path = TriePath::from_key(key);
+            let leaf = TrieLeaf::from_value(&vec![], value.clone());
+            marf.insert_raw(path, leaf).unwrap();
+        }
+        marf.commit().unwrap();
+        stale.insert(block_header.clone());
+        last_fork_header = block_header;
+    }
+    (data, stale, last_block_header)
+}
+
+/// Check that every key inserted on the main chain can be read from its tip.
+fn check_compacted_main_chain(
+    marf: &mut MARF<BlockHeaderHash>,
+    data: &[Vec<(String, MARFValue)>],
+    tip: &BlockHeaderHash,
+) {
+    for block_data in data[0..8].iter() {
+        for (key, value) in block_data.iter() {
+            let path = TriePath::from_key(key);
+            let marf_leaf = TrieLeaf::from_value(&vec![], value.clone());
+            let leaf = MARF::get_path(&mut marf.borrow_storage_backend(), tip, &path)
+                .unwrap()
+                .unwrap();
+            assert_eq!(leaf.data.to_vec(), marf_leaf.data.to_vec());
+        }
+    }
+}
+
+#[test]
+fn test_compact_trie_blobs() {
+    let test_file = "/tmp/test_compact_trie_blobs.sqlite";
+    let test_blobs_file = "/tmp/test_compact_trie_blobs.sqlite.blobs";
+    let (data, stale, last_block_header) = setup_compact_trie_blobs(test_file);
+
+    let blobs_len_before = fs::metadata(&test_blobs_file).unwrap().len();
+
+    let mut db = sqlite_open(&test_file, OpenFlags::SQLITE_OPEN_READ_WRITE, true).unwrap();
+    assert_eq!(
+        TrieFile::compact_trie_blobs(&mut db, &test_file, &stale).unwrap(),
+        4
+    );
+    for bhh in stale.iter() {
+        assert!(trie_sql::get_block_identifier(&db, bhh).is_err());
+    }
+
+    // nothing left to drop
+    assert_eq!(
+        TrieFile::compact_trie_blobs(&mut db, &test_file, &stale).unwrap(),
+        0
+    );
+    drop(db);
+
+    let blobs_len_after = fs::metadata(&test_blobs_file).unwrap().len();
+    assert!(blobs_len_after < blobs_len_before);
+
+    // everything on the main chain is still readable
+    let marf_opts = MARFOpenOpts::new(TrieHashCalculationMode::Deferred, "noop", true);
+    let f = TrieFileStorage::open(&test_file, marf_opts).unwrap();
+    let mut marf = MARF::from_storage(f);
+    check_compacted_main_chain(&mut marf, &data, &last_block_header);
+
+    // the main chain can still be extended
+    let next_block_header = make_block_header(0, 8);
+    marf.begin(&last_block_header, &next_block_header).unwrap();
+    let path = TriePath::from_key(&data[8][0].0);
+    marf.insert_raw(
+        path.clone(),
+        TrieLeaf::from_value(&vec![], data[8][0].1.clone()),
+    )
+    .unwrap();
+    marf.commit().unwrap();
+
+    let leaf = MARF::get_path(
+        &mut marf.borrow_storage_backend(),
+        &next_block_header,
+        &path,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        leaf.data.to_vec(),
+        TrieLeaf::from_value(&vec![], data[8][0].1.clone())
+            .data
+            .to_vec()
+    );
+}
+
+#[test]
+fn test_compact_trie_blobs_interrupted() {
+    let test_file = "/tmp/test_compact_trie_blobs_interrupted.sqlite";
+    let test_blobs_file = "/tmp/test_compact_trie_blobs_interrupted.sqlite.blobs";
+    let test_compact_file = "/tmp/test_compact_trie_blobs_interrupted.sqlite.blobs.compact";
+    let (data, stale, last_block_header) = setup_compact_trie_blobs(test_file);
+    let blobs_len_before = fs::metadata(&test_blobs_file).unwrap().len();
+
+    // interrupted before the new offsets were committed: a partial compacted file is left behind,
+    // but the DB still refers to the original blobs file.
+    fs::write(&test_compact_file, &[0xffu8; 1024]).unwrap();
+    {
+        let marf_opts = MARFOpenOpts::new(TrieHashCalculationMode::Deferred, "noop", true);
+        let f = TrieFileStorage::open(&test_file, marf_opts).unwrap();
+        let mut marf = MARF::from_storage(f);
+        check_compacted_main_chain(&mut marf, &data, &last_block_header);
+    }
+    assert_eq!(
+        fs::metadata(&test_blobs_file).unwrap().len(),
+        blobs_len_before
+    );
+
+    // interrupted after the new offsets were committed, but before the compacted file was
+    // renamed into place
+    let mut db = sqlite_open(&test_file, OpenFlags::SQLITE_OPEN_READ_WRITE, true).unwrap();
+    assert_eq!(
+        TrieFile::commit_compacted_trie_blobs(&mut db, &test_file, &stale).unwrap(),
+        4
+    );
+    assert!(trie_sql::has_pending_blobs_swap(&db).unwrap());
+    assert!(fs::metadata(&test_compact_file).is_ok());
+    assert_eq!(
+        fs::metadata(&test_blobs_file).unwrap().len(),
+        blobs_len_before
+    );
+    drop(db);
+
+    // a read-only open swaps in the compacted file, but leaves the marker
+    {
+        let marf_opts = MARFOpenOpts::new(TrieHashCalculationMode::Deferred, "noop", true);
+        let f = TrieFileStorage::open_readonly(&test_file, marf_opts).unwrap();
+        let mut marf = MARF::from_storage(f);
+        check_compacted_main_chain(&mut marf, &data, &last_block_header);
+        assert!(trie_sql::has_pending_blobs_swap(marf.sqlite_conn()).unwrap());
+    }
+    assert!(fs::metadata(&test_compact_file).is_err());
+    assert!(fs::metadata(&test_blobs_file).unwrap().len() < blobs_len_before);
+
+    // a read-write open clears the marker
+    let marf_opts = MARFOpenOpts::new(TrieHashCalculationMode::Deferred, "noop", true);
+    let f = TrieFileStorage::open(&test_file, marf_opts).unwrap();
+    let mut marf = MARF::from_storage(f);
+    assert!(!trie_sql::has_pending_blobs_swap(marf.sqlite_conn()).unwrap());
+    check_compacted_main_chain(&mut marf, &data, &last_block_header);
+    for bhh in stale.iter() {
+        assert!(trie_sql::get_block_identifier(marf.sqlite_conn(), bhh).is_err());
+    }
+}
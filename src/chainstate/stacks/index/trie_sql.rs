@@ -56,6 +56,7 @@ use crate::util_lib::db::query_count;
 use crate::util_lib::db::query_row;
 use crate::util_lib::db::query_rows;
 use crate::util_lib::db::sql_pragma;
+use crate::util_lib::db::table_exists;
 use crate::util_lib::db::tx_begin_immediate;
 use crate::util_lib::db::u64_to_sql;
 use stacks_common::util::log;
@@ -109,6 +110,11 @@ INSERT OR REPLACE INTO schema_version (version) VALUES (2);
 INSERT OR REPLACE INTO migrated_version (version) VALUES (1);
 ";
 
+static SQL_PENDING_BLOBS_SWAP_TABLE: &str = "
+-- set while a compacted .blobs file has been committed to but not yet renamed into place.
+CREATE TABLE IF NOT EXISTS pending_blobs_swap (pending INTEGER PRIMARY KEY);
+";
+
 pub static SQL_MARF_SCHEMA_VERSION: u64 = 2;
 
 pub fn create_tables_if_needed(conn: &mut Connection) -> Result<(), Error> {
@@ -117,6 +123,7 @@ pub fn create_tables_if_needed(conn: &mut Connection) -> Result<(), Error> {
     tx.execute_batch(SQL_MARF_DATA_TABLE)?;
     tx.execute_batch(SQL_MARF_MINED_TABLE)?;
     tx.execute_batch(SQL_EXTENSION_LOCKS_TABLE)?;
+    tx.execute_batch(SQL_PENDING_BLOBS_SWAP_TABLE)?;
 
     tx.commit().map_err(|e| e.into())
 }
@@ -557,6 +564,70 @@ pub fn get_external_blobs_length(conn: &Connection) -> Result<u64, Error> {
     Ok(max_len)
 }
 
+/// Get the block ID, offset, and length of each confirmed trie blob in the trie blobs file, in
+/// the order in which they're stored.
+pub fn get_external_trie_offsets_lengths(conn: &Connection) -> Result<Vec<(u32, u64, u64)>, Error> {
+    let qry = "SELECT block_id, external_offset, external_length FROM marf_data WHERE unconfirmed = 0 AND external_length > 0 ORDER BY external_offset";
+    let mut stmt = conn.prepare(qry)?;
+    let rows = stmt.query_and_then(NO_PARAMS, |row| {
+        let block_id: u32 = row.get_unwrap("block_id");
+        let offset_i64: i64 = row.get_unwrap("external_offset");
+        let length_i64: i64 = row.get_unwrap("external_length");
+        Ok((block_id, offset_i64 as u64, length_i64 as u64))
+    })?;
+    rows.collect()
+}
+
+/// Move a trie blob to a new offset in the trie blobs file -- i.e. we're compacting the file.
+pub fn set_external_trie_offset(
+    conn: &Connection,
+    block_id: u32,
+    offset: u64,
+) -> Result<(), Error> {
+    conn.execute(
+        "UPDATE marf_data SET external_offset = ?1 WHERE block_id = ?2",
+        &[&u64_to_sql(offset)? as &dyn ToSql, &block_id],
+    )?;
+    Ok(())
+}
+
+/// Drop a confirmed trie.  Returns true if there was a trie to drop.
+pub fn drop_confirmed_trie<T: MarfTrieId>(conn: &Connection, bhh: &T) -> Result<bool, Error> {
+    let num_rows = conn.execute(
+        "DELETE FROM marf_data WHERE block_hash = ? AND unconfirmed = 0",
+        &[bhh],
+    )?;
+    Ok(num_rows > 0)
+}
+
+/// Record that the offsets in marf_data now refer to the compacted .blobs file, which has yet to be
+/// renamed into place.  Must be called in the same transaction that rewrites the offsets.
+pub fn set_pending_blobs_swap(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(SQL_PENDING_BLOBS_SWAP_TABLE)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO pending_blobs_swap (pending) VALUES (1)",
+        NO_PARAMS,
+    )?;
+    Ok(())
+}
+
+/// Is there a committed .blobs compaction whose file has not been renamed into place?
+pub fn has_pending_blobs_swap(conn: &Connection) -> Result<bool, Error> {
+    if !table_exists(conn, "pending_blobs_swap")? {
+        return Ok(false);
+    }
+    let count = query_count(conn, "SELECT COUNT(*) FROM pending_blobs_swap", NO_PARAMS)?;
+    Ok(count > 0)
+}
+
+/// Clear the pending .blobs swap marker once the compacted file is in place.
+pub fn clear_pending_blobs_swap(conn: &Connection) -> Result<(), Error> {
+    if table_exists(conn, "pending_blobs_swap")? {
+        conn.execute("DELETE FROM pending_blobs_swap", NO_PARAMS)?;
+    }
+    Ok(())
+}
+
 /// Do we have a partially-migrated database?
 /// Either all tries have offset and length 0, or they all don't.  If we have a mixture, then we're
 /// corrupted.
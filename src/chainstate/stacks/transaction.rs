@@ -408,13 +408,29 @@ impl StacksTransaction {
 
     pub fn consensus_deserialize_with_len<R: Read>(
         fd: &mut R,
+    ) -> Result<(StacksTransaction, u64), codec_error> {
+        StacksTransaction::deserialize_with_len(fd, false)
+    }
+
+    /// Decode a transaction whose multisig spending conditions may not have all of their
+    /// signatures yet, such as one that is still being passed between its signers.  Such a
+    /// transaction is never valid, and consensus_deserialize() rejects it.
+    pub fn deserialize_partially_signed<R: Read>(
+        fd: &mut R,
+    ) -> Result<StacksTransaction, codec_error> {
+        StacksTransaction::deserialize_with_len(fd, true).map(|(result, _)| result)
+    }
+
+    fn deserialize_with_len<R: Read>(
+        fd: &mut R,
+        partially_signed: bool,
     ) -> Result<(StacksTransaction, u64), codec_error> {
         let mut bound_read = BoundReader::from_reader(fd, MAX_TRANSACTION_LEN.into());
         let fd = &mut bound_read;
 
         let version_u8: u8 = read_next(fd)?;
         let chain_id: u32 = read_next(fd)?;
-        let auth: TransactionAuth = read_next(fd)?;
+        let auth = TransactionAuth::deserialize(fd, partially_signed)?;
         let anchor_mode_u8: u8 = read_next(fd)?;
         let post_condition_mode_u8: u8 = read_next(fd)?;
         let post_conditions: Vec<TransactionPostCondition> = read_next(fd)?;
@@ -3831,6 +3847,55 @@ mod test {
         }
     }
 
+    #[test]
+    fn tx_stacks_transaction_deserialize_partially_signed() {
+        let privk_1 = StacksPrivateKey::from_hex(
+            "6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001",
+        )
+        .unwrap();
+        let privk_2 = StacksPrivateKey::from_hex(
+            "2a584d899fed1d24e26b524f202763c8ab30260167429f157f1c119f550fa6af01",
+        )
+        .unwrap();
+
+        let pubk_1 = StacksPublicKey::from_private(&privk_1);
+        let pubk_2 = StacksPublicKey::from_private(&privk_2);
+
+        let origin_auth = TransactionAuth::Standard(
+            TransactionSpendingCondition::new_multisig_p2sh(2, vec![pubk_1, pubk_2.clone()])
+                .unwrap(),
+        );
+        let tx = tx_stacks_transaction_test_txs(&origin_auth).remove(0);
+
+        let mut tx_signer = StacksTransactionSigner::new(&tx);
+        tx_signer.sign_origin(&privk_1).unwrap();
+        tx_signer.append_origin(&pubk_2).unwrap();
+        let partial_tx = tx_signer.get_tx_incomplete();
+        assert_eq!(partial_tx.auth().origin().num_signatures(), 1);
+
+        let mut bytes = vec![];
+        partial_tx.consensus_serialize(&mut bytes).unwrap();
+
+        // only a partially-signed decode accepts the missing signature
+        assert!(StacksTransaction::consensus_deserialize(&mut &bytes[..]).is_err());
+        let decoded = StacksTransaction::deserialize_partially_signed(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded, partial_tx);
+        assert!(decoded.verify().is_err());
+
+        // fully-signed transactions decode the same either way
+        let mut tx_signer = StacksTransactionSigner::new(&tx);
+        tx_signer.sign_origin(&privk_1).unwrap();
+        tx_signer.sign_origin(&privk_2).unwrap();
+        let signed_tx = tx_signer.get_tx().unwrap();
+
+        let mut bytes = vec![];
+        signed_tx.consensus_serialize(&mut bytes).unwrap();
+        assert_eq!(
+            StacksTransaction::deserialize_partially_signed(&mut &bytes[..]).unwrap(),
+            signed_tx
+        );
+    }
+
     #[test]
     fn tx_stacks_transaction_sign_verify_sponsored_p2sh() {
         let origin_privk = StacksPrivateKey::from_hex(
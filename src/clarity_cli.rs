@@ -26,7 +26,11 @@ use std::process;
 
 use clarity::util::get_epoch_time_ms;
 use clarity::vm::coverage::CoverageReporter;
+use clarity::vm::debug::{parse_breakpoint, Debugger};
+use clarity::vm::profiler::{CostProfiler, ProfileWeight, PROFILE_TABLE_ROWS};
 use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use rusqlite::types::ToSql;
 use rusqlite::Row;
 use rusqlite::Transaction;
@@ -36,11 +40,16 @@ use crate::chainstate::stacks::index::{storage::TrieFileStorage, MarfTrieId};
 use crate::util_lib::db::sqlite_open;
 use crate::util_lib::db::FromColumn;
 use stacks_common::address::c32::c32_address;
-use stacks_common::util::hash::{bytes_to_hex, Sha512Trunc256Sum};
+use stacks_common::address::{
+    C32_ADDRESS_VERSION_MAINNET_SINGLESIG, C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+};
+use stacks_common::util::hash::{bytes_to_hex, to_hex, Sha512Trunc256Sum};
 
 use crate::clarity::{
     vm::analysis,
     vm::analysis::contract_interface_builder::build_contract_interface,
+    vm::analysis::cost_bounds::compute_cost_bounds,
+    vm::analysis::lint::run_lints,
     vm::analysis::{errors::CheckError, errors::CheckResult, AnalysisDatabase, ContractAnalysis},
     vm::ast,
     vm::ast::build_ast_with_rules,
@@ -54,10 +63,15 @@ use crate::clarity::{
     },
     vm::errors::{Error, InterpreterResult, RuntimeErrorType},
     vm::eval_all,
-    vm::types::{OptionalData, PrincipalData, QualifiedContractIdentifier},
+    vm::formatter::format_source,
+    vm::fuzz::{self, FuzzFailure, ReplayFile, ValueGenerator},
+    vm::types::{
+        FunctionType, OptionalData, PrincipalData, QualifiedContractIdentifier,
+        StandardPrincipalData, TypeSignature,
+    },
     vm::ContractContext,
     vm::ContractName,
-    vm::{SymbolicExpression, SymbolicExpressionType, Value},
+    vm::{ClarityVersion, EvalHook, SymbolicExpression, SymbolicExpressionType, Value},
 };
 use stacks_common::util::log;
 
@@ -113,6 +127,7 @@ where command is one of:
 
   initialize         to initialize a local VM state database.
   check              to typecheck a potential contract definition.
+  fmt                to rewrite contract definitions into the canonical layout.
   launch             to launch a initialize a new contract in the local state database.
   eval               to evaluate (in read-only mode) a program in a given contract context.
   eval_at_chaintip   like `eval`, but does not advance to a new block.
@@ -121,6 +136,9 @@ where command is one of:
   eval_raw           to typecheck and evaluate an expression without a contract or database context.
   repl               to typecheck and evaluate expressions in a stdin/stdout loop.
   execute            to execute a public function of a defined contract.
+  debug              like `execute`, but steps through the function in an interactive debugger.
+  test               to run the `test-*` functions of the `*_test.clar` contracts in a directory.
+  fuzz               to call a function of a defined contract with random arguments.
   generate_address   to generate a random Stacks public address for testing purposes.
 ",
         invoked_by
@@ -215,6 +233,7 @@ fn run_analysis_free<C: ClarityStorage>(
         &mut marf_kv.get_analysis_db(),
         save_contract,
         LimitedCostTracker::new_free(),
+        ClarityVersion::default_for_epoch(DEFAULT_CLI_EPOCH),
     )
 }
 
@@ -243,6 +262,7 @@ fn run_analysis<C: ClarityStorage>(
         &mut marf_kv.get_analysis_db(),
         save_contract,
         cost_track,
+        ClarityVersion::default_for_epoch(DEFAULT_CLI_EPOCH),
     )
 }
 
@@ -390,14 +410,15 @@ where
     result
 }
 
-fn with_env_costs<F, R>(
+fn with_env_costs<'hooks, F, R>(
     mainnet: bool,
     header_db: &CLIHeadersDB,
     marf: &mut WritableMarfStore,
+    eval_hooks: Vec<&'hooks mut dyn EvalHook>,
     f: F,
 ) -> (R, ExecutionCost)
 where
-    F: FnOnce(&mut OwnedEnvironment) -> R,
+    F: FnOnce(&mut OwnedEnvironment<'_, 'hooks>) -> R,
 {
     let mut db = marf.as_clarity_db(header_db, &NULL_BURN_STATE_DB);
     let cost_track = LimitedCostTracker::new(
@@ -412,6 +433,9 @@ where
     )
     .unwrap();
     let mut vm_env = OwnedEnvironment::new_cost_limited(mainnet, db, cost_track, DEFAULT_CLI_EPOCH);
+    for hook in eval_hooks {
+        vm_env.add_eval_hook(hook);
+    }
     let result = f(&mut vm_env);
     let cost = vm_env.get_cost_total();
     (result, cost)
@@ -421,7 +445,10 @@ where
 ///  for program evaluation, not by consensus critical code.
 pub fn vm_execute(program: &str) -> Result<Option<Value>, Error> {
     let contract_id = QualifiedContractIdentifier::transient();
-    let mut contract_context = ContractContext::new(contract_id.clone());
+    let mut contract_context = ContractContext::new(
+        contract_id.clone(),
+        ClarityVersion::default_for_epoch(DEFAULT_CLI_EPOCH),
+    );
     let mut marf = MemoryBackingStore::new();
     let conn = marf.as_clarity_db();
     let mut global_context = GlobalContext::new(
@@ -430,7 +457,6 @@ pub fn vm_execute(program: &str) -> Result<Option<Value>, Error> {
         LimitedCostTracker::new_free(),
         DEFAULT_CLI_EPOCH,
     );
-    global_context.coverage_reporting = Some(CoverageReporter::new());
     global_context.execute(|g| {
         let parsed = ast::build_ast_with_rules(&contract_id, program, &mut (), ASTRules::Typical)?
             .expressions;
@@ -438,6 +464,15 @@ pub fn vm_execute(program: &str) -> Result<Option<Value>, Error> {
     })
 }
 
+/// Collect the evaluation hooks to install for a CLI command.
+fn eval_hooks_for(coverage: &mut Option<CoverageReporter>) -> Vec<&mut dyn EvalHook> {
+    let mut eval_hooks: Vec<&mut dyn EvalHook> = vec![];
+    if let Some(ref mut coverage) = coverage {
+        eval_hooks.push(coverage);
+    }
+    eval_hooks
+}
+
 fn save_coverage(
     coverage_folder: Option<String>,
     coverage: Option<CoverageReporter>,
@@ -459,6 +494,29 @@ fn save_coverage(
     }
 }
 
+/// Write the folded stacks of a profiled execution to `profile_file`, and a summary of the
+/// most expensive expressions to stderr.
+fn save_profile(
+    profile_file: Option<String>,
+    profiler: Option<CostProfiler>,
+    weight: ProfileWeight,
+) {
+    if let (Some(profile_file), Some(profiler)) = (profile_file, profiler) {
+        let mut file = friendly_expect(
+            fs::File::create(&profile_file),
+            &format!("Failed to create profile file {}", &profile_file),
+        );
+        friendly_expect(
+            profiler.write_folded(&mut file, weight),
+            "Failed to write profile",
+        );
+        friendly_expect(
+            profiler.write_table(&mut io::stderr(), weight, PROFILE_TABLE_ROWS),
+            "Failed to write profile summary",
+        );
+    }
+}
+
 struct CLIHeadersDB {
     db_path: String,
     conn: Connection,
@@ -825,6 +883,163 @@ fn install_boot_code<C: ClarityStorage>(header_db: &CLIHeadersDB, marf: &mut C)
         .unwrap();
 }
 
+/// Read a JSON array of `InitialAllocation`s from `filename`, or from stdin if it is `-`.
+fn read_initial_allocations(filename: &str) -> Vec<(PrincipalData, u64)> {
+    let json_in = if filename == "-" {
+        let mut buffer = String::new();
+        friendly_expect(
+            io::stdin().read_to_string(&mut buffer),
+            "Error reading from stdin.",
+        );
+        buffer
+    } else {
+        friendly_expect(
+            fs::read_to_string(filename),
+            &format!("Error reading file: {}", filename),
+        )
+    };
+    let allocations: Vec<InitialAllocation> =
+        friendly_expect(serde_json::from_str(&json_in), "Failure parsing JSON");
+
+    allocations
+        .into_iter()
+        .map(|a| {
+            (
+                friendly_expect(
+                    PrincipalData::parse(&a.principal),
+                    "Failed to parse principal in JSON",
+                ),
+                a.amount,
+            )
+        })
+        .collect()
+}
+
+/// Credit each principal in `allocations` with its initial STX balance.
+fn credit_initial_allocations(db: &mut ClarityDatabase, allocations: &[(PrincipalData, u64)]) {
+    db.begin();
+    for (principal, amount) in allocations.iter() {
+        let balance = STXBalance::initial(*amount as u128);
+        let mut snapshot = db.get_stx_balance_snapshot_genesis(principal);
+        snapshot.set_balance(balance);
+        snapshot.save();
+    }
+    db.commit();
+}
+
+/// A contract found by the `test` command.
+struct TestContract {
+    contract_identifier: QualifiedContractIdentifier,
+    src_file: String,
+    content: String,
+}
+
+/// Find the contracts in `dir`, returning the dependencies and the `*_test.clar` contracts
+/// separately.  Each contract is named after its file, and deployed by `deployer`.
+fn find_test_contracts(
+    dir: &str,
+    deployer: &StandardPrincipalData,
+) -> (Vec<TestContract>, Vec<TestContract>) {
+    let entries = friendly_expect(
+        fs::read_dir(dir),
+        &format!("Failed to read directory {}", dir),
+    );
+    let mut src_files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new("clar")))
+        .collect();
+    src_files.sort();
+
+    let mut dependencies = vec![];
+    let mut tests = vec![];
+    for path in src_files.into_iter() {
+        let src_file = path.to_string_lossy().to_string();
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let contract_name = friendly_expect(
+            ContractName::try_from(name.clone()),
+            &format!("Cannot name a contract after {}", &src_file),
+        );
+        let content = friendly_expect(
+            fs::read_to_string(&path),
+            &format!("Error reading file: {}", &src_file),
+        );
+        let contract = TestContract {
+            contract_identifier: QualifiedContractIdentifier::new(deployer.clone(), contract_name),
+            src_file,
+            content,
+        };
+        if name.ends_with("_test") {
+            tests.push(contract);
+        } else {
+            dependencies.push(contract);
+        }
+    }
+    (dependencies, tests)
+}
+
+/// Deploy `contracts` in whatever order satisfies their dependencies on each other.  Returns
+/// the analysis of each deployed contract, or the first contract that could not be deployed
+/// along with why.
+fn deploy_test_contracts(
+    header_db: &CLIHeadersDB,
+    marf: &mut WritableMarfStore,
+    contracts: Vec<TestContract>,
+    coverage: &mut Option<CoverageReporter>,
+) -> Result<Vec<ContractAnalysis>, (QualifiedContractIdentifier, String)> {
+    let mainnet = header_db.is_mainnet();
+    let mut deployed = vec![];
+    let mut pending = contracts;
+    while !pending.is_empty() {
+        let pending_count = pending.len();
+        let mut undeployed = vec![];
+        let mut last_error = None;
+        for contract in pending.into_iter() {
+            let mut ast = parse(&contract.contract_identifier, &contract.content)
+                .map_err(|e| (contract.contract_identifier.clone(), e.to_string()))?;
+            let analysis = match run_analysis(
+                &contract.contract_identifier,
+                &mut ast,
+                header_db,
+                marf,
+                true,
+            ) {
+                Ok(analysis) => analysis,
+                Err((error, _)) => {
+                    // this contract may depend on one which hasn't been deployed yet
+                    last_error =
+                        Some((contract.contract_identifier.clone(), error.err.to_string()));
+                    undeployed.push(contract);
+                    continue;
+                }
+            };
+            let (result, _) = with_env_costs(
+                mainnet,
+                header_db,
+                marf,
+                eval_hooks_for(coverage),
+                |vm_env| {
+                    vm_env.initialize_contract(
+                        contract.contract_identifier.clone(),
+                        &contract.content,
+                        ASTRules::PrecheckSize,
+                    )
+                },
+            );
+            result.map_err(|e| (contract.contract_identifier.clone(), e.to_string()))?;
+            deployed.push(analysis);
+        }
+        if undeployed.len() == pending_count {
+            // no progress was made, so the remaining contracts can't be deployed
+            return Err(last_error.expect("BUG: undeployed contracts without an error"));
+        }
+        pending = undeployed;
+    }
+    Ok(deployed)
+}
+
 pub fn add_costs(result: &mut serde_json::Value, costs: bool, runtime: ExecutionCost) {
     if costs {
         result["costs"] = serde_json::to_value(runtime).unwrap();
@@ -863,36 +1078,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             };
 
             let (db_name, allocations) = if argv.len() == 3 {
-                let filename = &argv[1];
-                let json_in = if filename == "-" {
-                    let mut buffer = String::new();
-                    friendly_expect(
-                        io::stdin().read_to_string(&mut buffer),
-                        "Error reading from stdin.",
-                    );
-                    buffer
-                } else {
-                    friendly_expect(
-                        fs::read_to_string(filename),
-                        &format!("Error reading file: {}", filename),
-                    )
-                };
-                let allocations: Vec<InitialAllocation> =
-                    friendly_expect(serde_json::from_str(&json_in), "Failure parsing JSON");
-
-                let allocations: Vec<_> = allocations
-                    .into_iter()
-                    .map(|a| {
-                        (
-                            friendly_expect(
-                                PrincipalData::parse(&a.principal),
-                                "Failed to parse principal in JSON",
-                            ),
-                            a.amount,
-                        )
-                    })
-                    .collect();
-
+                let allocations = read_initial_allocations(&argv[1]);
                 (&argv[2], allocations)
             } else if argv.len() == 2 {
                 (&argv[1], Vec::new())
@@ -927,21 +1113,13 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             in_block(header_db, marf_kv, |header_db, mut kv| {
                 {
                     let mut db = kv.as_clarity_db(&header_db, &NULL_BURN_STATE_DB);
-                    db.begin();
-                    for (principal, amount) in allocations.iter() {
-                        let balance = STXBalance::initial(*amount as u128);
-                        let total_balance = balance.get_total_balance();
-
-                        let mut snapshot = db.get_stx_balance_snapshot_genesis(principal);
-                        snapshot.set_balance(balance);
-                        snapshot.save();
-
-                        println!("{} credited: {} uSTX", principal, total_balance);
-                    }
-                    db.commit();
+                    credit_initial_allocations(&mut db, &allocations);
                 };
                 (header_db, kv, ())
             });
+            for (principal, amount) in allocations.iter() {
+                println!("{} credited: {} uSTX", principal, amount);
+            }
 
             if mainnet {
                 (
@@ -973,7 +1151,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
         "check" => {
             if args.len() < 2 {
                 eprintln!(
-                    "Usage: {} {} [program-file.clar] [--contract_id CONTRACT_ID] [--output_analysis] [--costs] [--testnet] (vm-state.db)",
+                    "Usage: {} {} [program-file.clar] [--contract_id CONTRACT_ID] [--output_analysis] [--costs] [--lint] [--testnet] (vm-state.db)",
                     invoked_by, args[0]
                 );
                 panic_test!();
@@ -1008,6 +1186,12 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 false
             };
 
+            let lint = if let Ok(Some(_)) = consume_arg(&mut argv, &["--lint"], false) {
+                true
+            } else {
+                false
+            };
+
             // NOTE: ignored if we're using a DB
             let mut testnet_given = false;
             let mainnet = if let Ok(Some(_)) = consume_arg(&mut argv, &["--testnet"], false) {
@@ -1088,18 +1272,119 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 "message": "Checks passed."
             });
 
-            add_costs(
-                &mut result,
-                costs,
-                contract_analysis.take_contract_cost_tracker().get_total(),
-            );
+            let mut cost_tracker = contract_analysis.take_contract_cost_tracker();
+            add_costs(&mut result, costs, cost_tracker.get_total());
 
             if output_analysis {
-                result["analysis"] =
-                    serde_json::to_value(&build_contract_interface(&contract_analysis)).unwrap();
+                let mut contract_interface = build_contract_interface(&contract_analysis);
+                let cost_bounds = friendly_expect(
+                    compute_cost_bounds(&contract_analysis, &mut cost_tracker)
+                        .map_err(|e| format!("{:?}", e)),
+                    "Failed to compute function cost bounds",
+                );
+                contract_interface.set_cost_bounds(&cost_bounds);
+                result["analysis"] = serde_json::to_value(&contract_interface).unwrap();
+
+                let over_limit: Vec<_> = cost_bounds
+                    .iter()
+                    .filter(|(_, bound)| bound.cost.exceeds(&BLOCK_LIMIT_MAINNET_205))
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                if !over_limit.is_empty() {
+                    result["exceeds_block_limit"] = json!(over_limit);
+                }
+            }
+
+            if lint {
+                result["warnings"] = serde_json::to_value(&run_lints(&contract_analysis)).unwrap();
             }
             (0, Some(result))
         }
+        "fmt" => {
+            let mut argv: Vec<String> = args.into_iter().map(|x| x.clone()).collect();
+            let check = if let Ok(Some(_)) = consume_arg(&mut argv, &["--check"], false) {
+                true
+            } else {
+                false
+            };
+            if argv.len() < 2 {
+                eprintln!(
+                    "Usage: {} {} [--check] [program-file.clar | -]...",
+                    invoked_by, argv[0]
+                );
+                panic_test!();
+            }
+
+            // when formatting stdin, stdout carries the formatted source, so the status
+            //  report goes to stderr instead.
+            let to_stdout = !check && argv[1..].iter().any(|filename| filename == "-");
+            let report = |exit_code: i32, result: serde_json::Value| {
+                if to_stdout {
+                    eprintln!("{}", &serde_json::to_string(&result).unwrap());
+                    (exit_code, None)
+                } else {
+                    (exit_code, Some(result))
+                }
+            };
+
+            let mut changed = vec![];
+            for filename in argv[1..].iter() {
+                let content = if filename == "-" {
+                    let mut buffer = String::new();
+                    friendly_expect(
+                        io::stdin().read_to_string(&mut buffer),
+                        "Error reading from stdin.",
+                    );
+                    buffer
+                } else {
+                    friendly_expect(
+                        fs::read_to_string(filename),
+                        &format!("Error reading file: {}", filename),
+                    )
+                };
+
+                let formatted = match format_source(&content) {
+                    Ok(formatted) => formatted,
+                    Err(error) => {
+                        let result = json!({
+                            "message": "Formatting failed.",
+                            "error": { "file": filename, "message": error.to_string() }
+                        });
+                        return report(1, result);
+                    }
+                };
+
+                if formatted != content {
+                    changed.push(filename.clone());
+                }
+                if check {
+                    continue;
+                }
+                if filename == "-" {
+                    // always echo the source, so that `fmt -` can be used as a filter
+                    print!("{}", formatted);
+                } else if formatted != content {
+                    friendly_expect(
+                        fs::write(filename, &formatted),
+                        &format!("Error writing file: {}", filename),
+                    );
+                }
+            }
+
+            if check {
+                let result = json!({
+                    "message": format!("{} file(s) would be reformatted.", changed.len()),
+                    "files": changed,
+                });
+                (if changed.len() > 0 { 1 } else { 0 }, Some(result))
+            } else {
+                let result = json!({
+                    "message": format!("{} file(s) reformatted.", changed.len()),
+                    "files": changed,
+                });
+                report(0, result)
+            }
+        }
         "repl" => {
             let mut argv: Vec<String> = args.into_iter().map(|x| x.clone()).collect();
             let mainnet = if let Ok(Some(_)) = consume_arg(&mut argv, &["--testnet"], false) {
@@ -1234,13 +1519,14 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             let mainnet = header_db.is_mainnet();
 
             let (_, _, result_and_cost) = in_block(header_db, marf_kv, |header_db, mut marf| {
-                let result_and_cost = with_env_costs(mainnet, &header_db, &mut marf, |vm_env| {
-                    vm_env.get_exec_environment(None).eval_read_only_with_rules(
-                        &evalInput.contract_identifier,
-                        &evalInput.content,
-                        ASTRules::PrecheckSize,
-                    )
-                });
+                let result_and_cost =
+                    with_env_costs(mainnet, &header_db, &mut marf, vec![], |vm_env| {
+                        vm_env.get_exec_environment(None).eval_read_only_with_rules(
+                            &evalInput.contract_identifier,
+                            &evalInput.content,
+                            ASTRules::PrecheckSize,
+                        )
+                    });
                 (header_db, marf, result_and_cost)
             });
 
@@ -1294,27 +1580,24 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             );
 
             let mainnet = header_db.is_mainnet();
+            let mut coverage = coverage_folder.as_ref().map(|_| CoverageReporter::new());
+            let eval_hooks = eval_hooks_for(&mut coverage);
             let result_and_cost = at_chaintip(vm_filename, marf_kv, |mut marf| {
-                let result_and_cost = with_env_costs(mainnet, &header_db, &mut marf, |vm_env| {
-                    if coverage_folder.is_some() {
-                        vm_env.set_coverage_reporter(CoverageReporter::new());
-                    }
-                    (
+                let result_and_cost =
+                    with_env_costs(mainnet, &header_db, &mut marf, eval_hooks, |vm_env| {
                         vm_env.get_exec_environment(None).eval_read_only_with_rules(
                             &evalInput.contract_identifier,
                             &evalInput.content,
                             ASTRules::PrecheckSize,
-                        ),
-                        vm_env.take_coverage_reporter(),
-                    )
-                });
-                let ((result, coverage), cost) = result_and_cost;
+                        )
+                    });
+                let (result, cost) = result_and_cost;
 
-                (marf, (result, cost, coverage))
+                (marf, (result, cost))
             });
 
             match result_and_cost {
-                (Ok(result), cost, coverage) => {
+                (Ok(result), cost) => {
                     save_coverage(coverage_folder, coverage, "eval");
                     let mut result_json = json!({
                         "output": serde_json::to_value(&result).unwrap(),
@@ -1326,7 +1609,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
 
                     (0, Some(result_json))
                 }
-                (Err(error), cost, coverage) => {
+                (Err(error), cost) => {
                     save_coverage(coverage_folder, coverage, "eval");
                     let mut result_json = json!({
                         "error": {
@@ -1380,13 +1663,14 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             );
             let mainnet = header_db.is_mainnet();
             let result_and_cost = at_block(chain_tip, marf_kv, |mut marf| {
-                let result_and_cost = with_env_costs(mainnet, &header_db, &mut marf, |vm_env| {
-                    vm_env.get_exec_environment(None).eval_read_only_with_rules(
-                        &contract_identifier,
-                        &content,
-                        ASTRules::PrecheckSize,
-                    )
-                });
+                let result_and_cost =
+                    with_env_costs(mainnet, &header_db, &mut marf, vec![], |vm_env| {
+                        vm_env.get_exec_environment(None).eval_read_only_with_rules(
+                            &contract_identifier,
+                            &content,
+                            ASTRules::PrecheckSize,
+                        )
+                    });
                 (marf, result_and_cost)
             });
 
@@ -1487,6 +1771,8 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 "Failed to open VM database.",
             );
             let mainnet = header_db.is_mainnet();
+            let mut coverage = coverage_folder.as_ref().map(|_| CoverageReporter::new());
+            let eval_hooks = eval_hooks_for(&mut coverage);
 
             let (_, _, analysis_result_and_cost) =
                 in_block(header_db, marf_kv, |header_db, mut marf| {
@@ -1495,28 +1781,27 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                     match analysis_result {
                         Err(e) => (header_db, marf, Err(e)),
                         Ok(analysis) => {
-                            let result_and_cost =
-                                with_env_costs(mainnet, &header_db, &mut marf, |vm_env| {
-                                    if coverage_folder.is_some() {
-                                        vm_env.set_coverage_reporter(CoverageReporter::new());
-                                    }
-                                    (
-                                        vm_env.initialize_contract(
-                                            contract_identifier,
-                                            &contract_content,
-                                            ASTRules::PrecheckSize,
-                                        ),
-                                        vm_env.take_coverage_reporter(),
+                            let result_and_cost = with_env_costs(
+                                mainnet,
+                                &header_db,
+                                &mut marf,
+                                eval_hooks,
+                                |vm_env| {
+                                    vm_env.initialize_contract(
+                                        contract_identifier,
+                                        &contract_content,
+                                        ASTRules::PrecheckSize,
                                     )
-                                });
-                            let ((result, coverage), cost) = result_and_cost;
-                            (header_db, marf, Ok((analysis, (result, cost, coverage))))
+                                },
+                            );
+                            let (result, cost) = result_and_cost;
+                            (header_db, marf, Ok((analysis, (result, cost))))
                         }
                     }
                 });
 
             match analysis_result_and_cost {
-                Ok((contract_analysis, (Ok((_x, asset_map, events)), cost, coverage))) => {
+                Ok((contract_analysis, (Ok((_x, asset_map, events)), cost))) => {
                     let mut result = json!({
                         "message": "Contract initialized!"
                     });
@@ -1560,8 +1845,22 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 ),
             }
         }
-        "execute" => {
+        "execute" | "debug" => {
             let mut argv: Vec<String> = args.into_iter().map(|x| x.clone()).collect();
+            let debugging = argv[0] == "debug";
+            let mut breakpoints = vec![];
+            if debugging {
+                while let Some(spec) = friendly_expect(
+                    consume_arg(&mut argv, &["--break"], true),
+                    "Expected a breakpoint after --break",
+                ) {
+                    breakpoints.push(friendly_expect(
+                        parse_breakpoint(&spec),
+                        &format!("Invalid breakpoint \"{}\"", spec),
+                    ));
+                }
+            }
+
             let coverage_folder = if let Ok(covarg) = consume_arg(&mut argv, &["--c"], true) {
                 covarg
             } else {
@@ -1578,9 +1877,27 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
             } else {
                 false
             };
+            let profile_file = if let Ok(profile_arg) = consume_arg(&mut argv, &["--profile"], true)
+            {
+                profile_arg
+            } else {
+                None
+            };
+            let profile_weight =
+                match consume_arg(&mut argv, &["--profile_weight"], true) {
+                    Ok(Some(weight)) => friendly_expect_opt(
+                        ProfileWeight::lookup_by_name(&weight),
+                        &format!("Unknown profile weight \"{}\", expected one of runtime, read_count, read_length, write_count or write_length", weight),
+                    ),
+                    _ => ProfileWeight::Runtime,
+                };
 
             if argv.len() < 5 {
-                eprintln!("Usage: {} {} [--costs] [--assets] [vm-state.db] [contract-identifier] [public-function-name] [sender-address] [args...]", invoked_by, argv[0]);
+                if debugging {
+                    eprintln!("Usage: {} {} [--break [contract-identifier:]line]... [--costs] [--assets] [--profile folded-stack-file [--profile_weight weight]] [vm-state.db] [contract-identifier] [public-function-name] [sender-address] [args...]", invoked_by, argv[0]);
+                } else {
+                    eprintln!("Usage: {} {} [--costs] [--assets] [--profile folded-stack-file [--profile_weight weight]] [vm-state.db] [contract-identifier] [public-function-name] [sender-address] [args...]", invoked_by, argv[0]);
+                }
                 panic_test!();
             }
 
@@ -1624,27 +1941,45 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 })
                 .collect();
 
-            let (_, _, result_and_cost) = in_block(header_db, marf_kv, |header_db, mut marf| {
-                let result_and_cost = with_env_costs(mainnet, &header_db, &mut marf, |vm_env| {
-                    if coverage_folder.is_some() {
-                        vm_env.set_coverage_reporter(CoverageReporter::new());
+            let mut coverage = coverage_folder.as_ref().map(|_| CoverageReporter::new());
+            let mut debugger = if debugging {
+                // the debugger talks on stderr, so that stdout is left for the result
+                let mut debugger = Debugger::new(io::BufReader::new(io::stdin()), io::stderr());
+                if !breakpoints.is_empty() {
+                    for (contract, line) in breakpoints.into_iter() {
+                        debugger.add_breakpoint(contract, line);
                     }
-                    (
+                    debugger.run_to_breakpoint();
+                }
+                Some(debugger)
+            } else {
+                None
+            };
+            let mut profiler = profile_file.as_ref().map(|_| CostProfiler::new());
+            let mut eval_hooks = eval_hooks_for(&mut coverage);
+            if let Some(ref mut profiler) = profiler {
+                eval_hooks.push(profiler);
+            }
+            if let Some(ref mut debugger) = debugger {
+                eval_hooks.push(debugger);
+            }
+            let (_, _, result_and_cost) = in_block(header_db, marf_kv, |header_db, mut marf| {
+                let result_and_cost =
+                    with_env_costs(mainnet, &header_db, &mut marf, eval_hooks, |vm_env| {
                         vm_env.execute_transaction(
                             sender,
                             contract_identifier,
                             &tx_name,
                             &arguments,
-                        ),
-                        vm_env.take_coverage_reporter(),
-                    )
-                });
-                let ((result, coverage), cost) = result_and_cost;
-                (header_db, marf, (result, cost, coverage))
+                        )
+                    });
+                let (result, cost) = result_and_cost;
+                (header_db, marf, (result, cost))
             });
+            save_profile(profile_file, profiler, profile_weight);
 
             match result_and_cost {
-                (Ok((x, asset_map, events)), cost, coverage) => {
+                (Ok((x, asset_map, events)), cost) => {
                     if let Value::Response(data) = x {
                         save_coverage(coverage_folder, coverage, "execute");
                         if data.committed {
@@ -1701,6 +2036,471 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) -> (i32, Option<serde_j
                 }
             }
         }
+        "test" => {
+            let mut argv: Vec<String> = args.into_iter().map(|x| x.clone()).collect();
+            let coverage_folder = if let Ok(covarg) = consume_arg(&mut argv, &["--c"], true) {
+                covarg
+            } else {
+                None
+            };
+            let mainnet = if let Ok(Some(_)) = consume_arg(&mut argv, &["--testnet"], false) {
+                false
+            } else {
+                true
+            };
+            let sender = match consume_arg(&mut argv, &["--sender"], true) {
+                Ok(Some(sender_in)) => friendly_expect(
+                    PrincipalData::parse_standard_principal(&sender_in),
+                    &format!("Unexpected result parsing sender: {}", sender_in),
+                ),
+                _ => StandardPrincipalData::transient(),
+            };
+            let block_height = match consume_arg(&mut argv, &["--block_height"], true) {
+                Ok(Some(height)) => Some(friendly_expect(
+                    height.parse::<u64>(),
+                    &format!("Failed to parse block height: {}", height),
+                )),
+                _ => None,
+            };
+            let allocations = match consume_arg(&mut argv, &["--allocations"], true) {
+                Ok(Some(filename)) => read_initial_allocations(&filename),
+                _ => vec![],
+            };
+
+            if argv.len() < 2 {
+                eprintln!(
+                    "Usage: {} {} [--testnet] [--sender sender-address] [--block_height height] [--allocations initial-allocations.json] [--c coverage-folder] [test-dir]",
+                    invoked_by, argv[0]
+                );
+                eprintln!("   Every contract in test-dir is deployed by the sender, then each `test-*` public function in the `*_test.clar` contracts");
+                eprintln!("   is run in a fresh copy of that state.  A test passes if it returns (ok ...), and fails if it returns (err ...).");
+                panic_test!();
+            }
+
+            let (dependencies, test_contracts) = find_test_contracts(&argv[1], &sender);
+            if let Some(ref coverage_folder) = coverage_folder {
+                for contract in dependencies.iter().chain(test_contracts.iter()) {
+                    let ast = friendly_expect(
+                        parse(&contract.contract_identifier, &contract.content),
+                        &format!("Failed to parse {}", &contract.src_file),
+                    );
+                    let mut coverage_file = PathBuf::from(coverage_folder);
+                    coverage_file.push(&format!(
+                        "test_{}_{}",
+                        &contract.contract_identifier.name,
+                        get_epoch_time_ms()
+                    ));
+                    coverage_file.set_extension("clarcovref");
+                    CoverageReporter::register_src_file(
+                        &contract.contract_identifier,
+                        &contract.src_file,
+                        &ast,
+                        &coverage_file,
+                    )
+                    .expect("Coverage reference file generation failure");
+                }
+            }
+
+            // set up the state that every test starts from in a scratch database
+            let mut state_path = env::temp_dir();
+            state_path.push(format!(
+                "clarity-test-{}",
+                to_hex(&rand::thread_rng().gen::<[u8; 16]>())
+            ));
+            let state_path = state_path.to_string_lossy().to_string();
+            let header_db = CLIHeadersDB::new(&state_path, mainnet);
+            let marf_kv = friendly_expect(
+                MarfedKV::open(&state_path, None, None),
+                "Failed to open VM database.",
+            );
+            let (header_db, marf_kv, _) = in_block(header_db, marf_kv, |header_db, mut marf| {
+                install_boot_code(&header_db, &mut marf);
+                {
+                    let mut db = marf.as_clarity_db(&header_db, &NULL_BURN_STATE_DB);
+                    credit_initial_allocations(&mut db, &allocations);
+                }
+                (header_db, marf, ())
+            });
+
+            let mut coverage = coverage_folder.as_ref().map(|_| CoverageReporter::new());
+            let (mut header_db, mut marf_kv, deployed) =
+                in_block(header_db, marf_kv, |header_db, mut marf| {
+                    let mut contracts = dependencies;
+                    contracts.extend(test_contracts);
+                    let deployed =
+                        deploy_test_contracts(&header_db, &mut marf, contracts, &mut coverage);
+                    (header_db, marf, deployed)
+                });
+            let deployed = match deployed {
+                Ok(deployed) => deployed,
+                Err((contract_identifier, error)) => {
+                    let _ = fs::remove_dir_all(&state_path);
+                    let result = json!({
+                        "error": {
+                            "deployment": format!("Failed to deploy {}", contract_identifier),
+                            "error": error,
+                        },
+                        "success": false,
+                    });
+                    return (1, Some(result));
+                }
+            };
+
+            // each test runs in the block after the chain tip
+            let mut tip = get_cli_chain_tip(header_db.conn());
+            if let Some(block_height) = block_height {
+                let deployed_height = marf_kv
+                    .get_marf()
+                    .get_block_height_of(&tip, &tip)
+                    .expect("FATAL: failed to query the MARF")
+                    .expect("FATAL: no height for the chain tip")
+                    as u64;
+                if block_height <= deployed_height {
+                    let _ = fs::remove_dir_all(&state_path);
+                    eprintln!(
+                        "The block height must be greater than {}, the height at which the contracts were deployed",
+                        deployed_height
+                    );
+                    panic_test!();
+                }
+                for _ in (deployed_height + 1)..block_height {
+                    let state =
+                        in_block(header_db, marf_kv, |header_db, marf| (header_db, marf, ()));
+                    header_db = state.0;
+                    marf_kv = state.1;
+                }
+                tip = get_cli_chain_tip(header_db.conn());
+            }
+
+            let mut results = vec![];
+            let mut failures = 0;
+            for analysis in deployed.iter() {
+                if !analysis.contract_identifier.name.ends_with("_test") {
+                    continue;
+                }
+                for (test_name, function_type) in analysis.public_function_types.iter() {
+                    if !test_name.starts_with("test-") {
+                        continue;
+                    }
+                    let takes_arguments = match function_type {
+                        FunctionType::Fixed(function) => !function.args.is_empty(),
+                        _ => true,
+                    };
+                    let outcome = if takes_arguments {
+                        Err("Test functions cannot take arguments".to_string())
+                    } else {
+                        let mut marf = marf_kv.begin(&tip, &StacksBlockId([2u8; 32]));
+                        let (result, _) = with_env_costs(
+                            mainnet,
+                            &header_db,
+                            &mut marf,
+                            eval_hooks_for(&mut coverage),
+                            |vm_env| {
+                                vm_env.execute_transaction(
+                                    PrincipalData::Standard(sender.clone()),
+                                    analysis.contract_identifier.clone(),
+                                    test_name,
+                                    &[],
+                                )
+                            },
+                        );
+                        marf.rollback_block();
+                        match result {
+                            Ok((Value::Response(data), ..)) => {
+                                if data.committed {
+                                    Ok(format!("{}", Value::Response(data)))
+                                } else {
+                                    Err(format!("{}", Value::Response(data)))
+                                }
+                            }
+                            Ok((value, ..)) => Err(format!(
+                                "Expected a ResponseType result from transaction, got {}",
+                                value
+                            )),
+                            Err(error) => Err(format!("{}", error)),
+                        }
+                    };
+
+                    let mut test_result = json!({
+                        "contract": analysis.contract_identifier.to_string(),
+                        "test": test_name.to_string(),
+                        "success": outcome.is_ok(),
+                    });
+                    match outcome {
+                        Ok(output) => test_result["output"] = json!(output),
+                        Err(error) => {
+                            failures += 1;
+                            test_result["error"] = json!(error);
+                        }
+                    }
+                    results.push(test_result);
+                }
+            }
+
+            drop(header_db);
+            drop(marf_kv);
+            let _ = fs::remove_dir_all(&state_path);
+            save_coverage(coverage_folder, coverage, "test");
+
+            let result = json!({
+                "message": format!("{} passed, {} failed", results.len() - failures, failures),
+                "success": failures == 0,
+                "tests": results,
+            });
+            (if failures == 0 { 0 } else { 1 }, Some(result))
+        }
+        "fuzz" => {
+            let mut argv: Vec<String> = args.into_iter().map(|x| x.clone()).collect();
+            let runs = match consume_arg(&mut argv, &["--runs"], true) {
+                Ok(Some(runs)) => friendly_expect(
+                    runs.parse::<u32>(),
+                    &format!("Failed to parse number of runs: {}", runs),
+                ),
+                _ => 100,
+            };
+            let seed = match consume_arg(&mut argv, &["--seed"], true) {
+                Ok(Some(seed)) => friendly_expect(
+                    seed.parse::<u64>(),
+                    &format!("Failed to parse seed: {}", seed),
+                ),
+                _ => rand::thread_rng().gen(),
+            };
+            let mut invariants = vec![];
+            while let Some(invariant) = friendly_expect(
+                consume_arg(&mut argv, &["--invariant"], true),
+                "Expected a function name after --invariant",
+            ) {
+                invariants.push(invariant);
+            }
+            let replay_file = if let Ok(replay_arg) = consume_arg(&mut argv, &["--replay"], true) {
+                replay_arg
+            } else {
+                None
+            };
+            let save_file = if let Ok(save_arg) = consume_arg(&mut argv, &["--save"], true) {
+                save_arg
+            } else {
+                None
+            };
+
+            if argv.len() < 5 {
+                eprintln!(
+                    "Usage: {} {} [--runs count] [--seed seed] [--invariant read-only-function-name]... [--save replay-file] [--replay replay-file] [vm-state.db] [contract-identifier] [function-name] [sender-address]",
+                    invoked_by, argv[0]
+                );
+                eprintln!("   Calls the function with random arguments in snapshots of the VM state, and reports the first arguments which");
+                eprintln!("   cause a runtime error, or after which an invariant (a read-only function without arguments) doesn't return true.");
+                eprintln!("   The failing arguments are shrunk, and saved to the replay file, which --replay runs again.");
+                panic_test!();
+            }
+
+            let vm_filename = &argv[1];
+            let header_db =
+                friendly_expect(CLIHeadersDB::resume(vm_filename), "Failed to open CLI DB");
+            let mut marf_kv = friendly_expect(
+                MarfedKV::open(vm_filename, None, None),
+                "Failed to open VM database.",
+            );
+            let mainnet = header_db.is_mainnet();
+            let contract_identifier = friendly_expect(
+                QualifiedContractIdentifier::parse(&argv[2]),
+                "Failed to parse contract identifier.",
+            );
+            let function_name = argv[3].clone();
+            let sender = PrincipalData::Standard(friendly_expect(
+                PrincipalData::parse_standard_principal(&argv[4]),
+                &format!("Unexpected result parsing sender: {}", &argv[4]),
+            ));
+
+            // every run starts from the chain tip
+            let tip = get_cli_chain_tip(header_db.conn());
+            let contract_analysis = {
+                let mut marf = marf_kv.begin(&tip, &StacksBlockId([2u8; 32]));
+                let contract_analysis = {
+                    let mut analysis_db = marf.as_analysis_db();
+                    analysis_db.begin();
+                    let contract_analysis = analysis_db.load_contract(&contract_identifier);
+                    analysis_db.roll_back();
+                    contract_analysis
+                };
+                marf.rollback_block();
+                friendly_expect_opt(
+                    contract_analysis,
+                    &format!("No such contract: {}", &contract_identifier),
+                )
+            };
+
+            let function_type = friendly_expect_opt(
+                contract_analysis
+                    .get_public_function_type(&function_name)
+                    .or_else(|| contract_analysis.get_read_only_function_type(&function_name)),
+                &format!(
+                    "No public or read-only function {} in {}",
+                    &function_name, &contract_identifier
+                ),
+            );
+            let argument_types: Vec<_> = match function_type {
+                FunctionType::Fixed(function) => function
+                    .args
+                    .iter()
+                    .map(|arg| arg.signature.clone())
+                    .collect(),
+                _ => vec![],
+            };
+            if let Some(argument_type) = argument_types.iter().find(|t| !fuzz::can_generate(t)) {
+                eprintln!("Cannot generate arguments of type {}", argument_type);
+                panic_test!();
+            }
+            for invariant in invariants.iter() {
+                let is_invariant = match contract_analysis.get_read_only_function_type(invariant) {
+                    Some(FunctionType::Fixed(function)) => {
+                        function.args.is_empty() && function.returns == TypeSignature::BoolType
+                    }
+                    _ => false,
+                };
+                if !is_invariant {
+                    eprintln!(
+                        "Invariant {} must be a read-only function without arguments which returns a bool",
+                        invariant
+                    );
+                    panic_test!();
+                }
+            }
+
+            let mut call = |arguments: &[Value]| {
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| SymbolicExpression::atom_value(argument.clone()))
+                    .collect();
+                let mut marf = marf_kv.begin(&tip, &StacksBlockId([2u8; 32]));
+                let (failure, _) =
+                    with_env_costs(mainnet, &header_db, &mut marf, vec![], |vm_env| {
+                        if let Err(error) = vm_env.execute_transaction(
+                            sender.clone(),
+                            contract_identifier.clone(),
+                            &function_name,
+                            &arguments,
+                        ) {
+                            return Some(FuzzFailure::RuntimeError {
+                                error: format!("{}", error),
+                            });
+                        }
+                        for invariant in invariants.iter() {
+                            let result = match vm_env.eval_read_only_with_rules(
+                                &contract_identifier,
+                                &format!("({})", invariant),
+                                ASTRules::PrecheckSize,
+                            ) {
+                                Ok((Value::Bool(true), ..)) => continue,
+                                Ok((value, ..)) => format!("{}", value),
+                                Err(error) => format!("{}", error),
+                            };
+                            return Some(FuzzFailure::InvariantViolated {
+                                invariant: invariant.clone(),
+                                result,
+                            });
+                        }
+                        None
+                    });
+                marf.rollback_block();
+                failure
+            };
+
+            if let Some(replay_file) = replay_file {
+                let replay: ReplayFile = friendly_expect(
+                    serde_json::from_str(&friendly_expect(
+                        fs::read_to_string(&replay_file),
+                        &format!("Error reading file: {}", replay_file),
+                    )),
+                    &format!("Failed to parse replay file {}", replay_file),
+                );
+                if replay.contract_identifier != contract_identifier.to_string()
+                    || replay.function != function_name
+                {
+                    eprintln!(
+                        "Replay file {} is for {} in {}",
+                        replay_file, replay.function, replay.contract_identifier
+                    );
+                    panic_test!();
+                }
+                let arguments = friendly_expect(
+                    replay.decode_arguments(&argument_types),
+                    &format!("Failed to decode the arguments in {}", replay_file),
+                );
+
+                let failure = call(&arguments);
+                let mut result = json!({
+                    "message": if failure.is_some() { "Replayed failure" } else { "Replay passed" },
+                    "success": failure.is_none(),
+                    "arguments": arguments.iter().map(|argument| format!("{}", argument)).collect::<Vec<_>>(),
+                });
+                if let Some(failure) = failure {
+                    result["failure"] = serde_json::to_value(&failure).unwrap();
+                    return (1, Some(result));
+                }
+                return (0, Some(result));
+            }
+
+            let known_principals = vec![
+                sender.clone(),
+                PrincipalData::Contract(contract_identifier.clone()),
+            ];
+            let generator = ValueGenerator::new(
+                known_principals,
+                if mainnet {
+                    C32_ADDRESS_VERSION_MAINNET_SINGLESIG
+                } else {
+                    C32_ADDRESS_VERSION_TESTNET_SINGLESIG
+                },
+            );
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let counterexample = fuzz::fuzz(&mut rng, &generator, &argument_types, runs, call);
+
+            let counterexample = match counterexample {
+                Some(counterexample) => counterexample,
+                None => {
+                    let result = json!({
+                        "message": format!("{} runs passed", runs),
+                        "success": true,
+                        "seed": seed,
+                    });
+                    return (0, Some(result));
+                }
+            };
+
+            let save_file = save_file.unwrap_or_else(|| {
+                let mut save_path = PathBuf::from(vm_filename);
+                save_path.push("fuzz");
+                save_path.push(format!("{}.{}.json", &contract_identifier, &function_name));
+                save_path.to_string_lossy().to_string()
+            });
+            if let Some(save_dir) = PathBuf::from(&save_file).parent() {
+                friendly_expect(
+                    fs::create_dir_all(save_dir),
+                    &format!("Failed to create {}", save_dir.display()),
+                );
+            }
+            let replay = ReplayFile::new(
+                contract_identifier.to_string(),
+                function_name.clone(),
+                sender.to_string(),
+                &counterexample,
+            );
+            friendly_expect(
+                fs::write(&save_file, serde_json::to_string_pretty(&replay).unwrap()),
+                &format!("Failed to write {}", save_file),
+            );
+
+            let result = json!({
+                "message": format!("Run {} of {} failed", counterexample.run + 1, runs),
+                "success": false,
+                "seed": seed,
+                "arguments": counterexample.arguments.iter().map(|argument| format!("{}", argument)).collect::<Vec<_>>(),
+                "failure": serde_json::to_value(&counterexample.failure).unwrap(),
+                "replay_file": save_file,
+            });
+            (1, Some(result))
+        }
         "make_lcov" => {
             let mut register_files = vec![];
             let mut coverage_files = vec![];
@@ -1819,6 +2619,235 @@ mod test {
         assert!(!header_db.is_mainnet());
     }
 
+    #[test]
+    fn test_run_tests() {
+        let test_dir = format!("/tmp/clarity_tests_{}", rand::thread_rng().gen::<i32>());
+        fs::create_dir_all(&test_dir).unwrap();
+        fs::write(
+            format!("{}/counter.clar", &test_dir),
+            r#"
+(define-data-var counter uint u0)
+(define-public (increment)
+  (begin
+    (var-set counter (+ (var-get counter) u1))
+    (if (> (var-get counter) u10) (err u1) (ok (var-get counter)))))
+(define-read-only (get-counter) (var-get counter))
+"#,
+        )
+        .unwrap();
+        fs::write(
+            format!("{}/counter_test.clar", &test_dir),
+            r#"
+(define-public (test-increment)
+  (begin
+    (try! (contract-call? .counter increment))
+    (asserts! (is-eq (contract-call? .counter get-counter) u1) (err u2))
+    (ok true)))
+(define-public (test-fresh-state)
+  (begin
+    (asserts! (is-eq (contract-call? .counter get-counter) u0) (err u3))
+    (ok true)))
+(define-public (test-block-height)
+  (begin
+    (asserts! (is-eq block-height u10) (err block-height))
+    (ok true)))
+(define-public (test-sender)
+  (if (is-eq tx-sender 'SP3X6QWWETNBZWGBK6DRGTR1KX50S74D3433WDGJY) (ok true) (err u4)))
+(define-public (not-a-test) (err u5))
+"#,
+        )
+        .unwrap();
+
+        let invoked = invoke_command("test", &["test".to_string(), test_dir.clone()]);
+        let exit = invoked.0;
+        let result = invoked.1.unwrap();
+
+        assert_eq!(exit, 1);
+        assert_eq!(result["success"], false);
+        assert_eq!(result["message"], "2 passed, 2 failed");
+        let tests = result["tests"].as_array().unwrap();
+        assert_eq!(tests.len(), 4);
+        assert_eq!(tests[0]["test"], "test-block-height");
+        assert_eq!(tests[0]["error"], "(err u2)");
+        assert_eq!(tests[1]["test"], "test-fresh-state");
+        assert_eq!(tests[1]["success"], true);
+        assert_eq!(tests[2]["test"], "test-increment");
+        assert_eq!(tests[2]["output"], "(ok true)");
+        assert_eq!(tests[3]["test"], "test-sender");
+        assert_eq!(tests[3]["success"], false);
+
+        let invoked = invoke_command(
+            "test",
+            &[
+                "test".to_string(),
+                "--block_height".to_string(),
+                "10".to_string(),
+                "--sender".to_string(),
+                "SP3X6QWWETNBZWGBK6DRGTR1KX50S74D3433WDGJY".to_string(),
+                test_dir.clone(),
+            ],
+        );
+        let exit = invoked.0;
+        let result = invoked.1.unwrap();
+
+        assert_eq!(exit, 0);
+        assert_eq!(result["message"], "4 passed, 0 failed");
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_fmt() {
+        let contract = format!("/tmp/clarity_fmt_{}.clar", rand::thread_rng().gen::<i32>());
+        fs::copy("sample-contracts/tokens.clar", &contract).unwrap();
+
+        let invoked = invoke_command(
+            "test",
+            &["fmt".to_string(), "--check".to_string(), contract.clone()],
+        );
+        assert_eq!(invoked.0, 1);
+        assert_eq!(invoked.1.unwrap()["files"], json!([contract.clone()]));
+
+        let invoked = invoke_command("test", &["fmt".to_string(), contract.clone()]);
+        assert_eq!(invoked.0, 0);
+        let formatted = fs::read_to_string(&contract).unwrap();
+
+        let invoked = invoke_command(
+            "test",
+            &["fmt".to_string(), "--check".to_string(), contract.clone()],
+        );
+        assert_eq!(invoked.0, 0);
+        assert_eq!(fs::read_to_string(&contract).unwrap(), formatted);
+
+        let invoked = invoke_command("test", &["check".to_string(), contract.clone()]);
+        assert_eq!(invoked.0, 0);
+
+        fs::remove_file(&contract).unwrap();
+    }
+
+    #[test]
+    fn test_cost_bounds() {
+        let contract = format!(
+            "/tmp/clarity_cost_bounds_{}.clar",
+            rand::thread_rng().gen::<i32>()
+        );
+        fs::write(
+            &contract,
+            "(define-map scores int int)
+            (define-private (add-score (key int) (total int))
+              (+ total (default-to 0 (map-get? scores key))))
+            (define-read-only (get-score (key int))
+              (map-get? scores key))
+            (define-read-only (sum-scores (keys (list 20000 int)))
+              (fold add-score keys 0))",
+        )
+        .unwrap();
+
+        let invoked = invoke_command(
+            "test",
+            &[
+                "check".to_string(),
+                "--output_analysis".to_string(),
+                contract.clone(),
+            ],
+        );
+        assert_eq!(invoked.0, 0);
+        let result = invoked.1.unwrap();
+
+        let functions = result["analysis"]["functions"].as_array().unwrap();
+        let get_score = functions
+            .iter()
+            .find(|function| function["name"] == "get-score")
+            .unwrap();
+        assert_eq!(get_score["cost_bound"]["cost"]["read_count"], 1);
+        assert_eq!(get_score["cost_bound"]["complete"], true);
+        assert!(get_score["cost_bound"]["cost"]["runtime"].as_u64().unwrap() > 0);
+
+        // every one of the 20000 keys may be read, which is more than a block allows
+        let sum_scores = functions
+            .iter()
+            .find(|function| function["name"] == "sum-scores")
+            .unwrap();
+        assert_eq!(sum_scores["cost_bound"]["cost"]["read_count"], 20000);
+        assert_eq!(result["exceeds_block_limit"], json!(["sum-scores"]));
+
+        fs::remove_file(&contract).unwrap();
+    }
+
+    #[test]
+    fn test_fuzz() {
+        let db_name = format!("/tmp/db_{}", rand::thread_rng().gen::<i32>());
+        let contract = format!("{}/counter.clar", &db_name);
+        invoke_command("test", &["initialize".to_string(), db_name.clone()]);
+        fs::write(
+            &contract,
+            "(define-data-var total uint u0)
+            (define-read-only (total-is-small) (< (var-get total) u1000))
+            (define-public (add (amount uint))
+              (begin (var-set total (+ (var-get total) amount)) (ok (var-get total))))
+            (define-public (pick (items (list 5 int)) (index uint))
+              (ok (unwrap-panic (element-at items index))))",
+        )
+        .unwrap();
+        let invoked = invoke_command(
+            "test",
+            &[
+                "launch".to_string(),
+                "S1G2081040G2081040G2081040G208105NK8PE5.counter".to_string(),
+                contract.clone(),
+                db_name.clone(),
+            ],
+        );
+        assert_eq!(invoked.0, 0);
+
+        let fuzz = |function: &str, options: &[&str]| {
+            let mut args = vec!["fuzz".to_string(), "--seed".to_string(), "1".to_string()];
+            args.extend(options.iter().map(|option| option.to_string()));
+            args.extend(
+                [
+                    db_name.as_str(),
+                    "S1G2081040G2081040G2081040G208105NK8PE5.counter",
+                    function,
+                    "S1G2081040G2081040G2081040G208105NK8PE5",
+                ]
+                .iter()
+                .map(|arg| arg.to_string()),
+            );
+            invoke_command("test", &args)
+        };
+
+        // out of range indexes abort, and shrink to the smallest one
+        let (exit, result) = fuzz("pick", &[]);
+        let result = result.unwrap();
+        assert_eq!(exit, 1);
+        assert_eq!(result["arguments"], json!(["()", "u0"]));
+        assert_eq!(result["failure"]["kind"], "runtime_error");
+        let replay_file = result["replay_file"].as_str().unwrap().to_string();
+
+        let (exit, result) = fuzz("pick", &["--replay", &replay_file]);
+        assert_eq!(exit, 1);
+        assert_eq!(result.unwrap()["failure"]["kind"], "runtime_error");
+
+        // nothing goes wrong in `add`, without an invariant
+        let (exit, result) = fuzz("add", &["--runs", "20"]);
+        assert_eq!(exit, 0);
+        assert_eq!(result.unwrap()["message"], "20 runs passed");
+
+        let (exit, result) = fuzz("add", &["--invariant", "total-is-small"]);
+        let result = result.unwrap();
+        assert_eq!(exit, 1);
+        assert_eq!(
+            result["failure"],
+            json!({ "kind": "invariant_violated", "invariant": "total-is-small", "result": "false" })
+        );
+        let amount: u128 = result["arguments"][0].as_str().unwrap()[1..]
+            .parse()
+            .unwrap();
+        assert!(amount >= 1000);
+
+        fs::remove_dir_all(&db_name).unwrap();
+    }
+
     #[test]
     fn test_samples() {
         let db_name = format!("/tmp/db_{}", rand::thread_rng().gen::<i32>());
@@ -1926,6 +2955,23 @@ mod test {
         assert!(result["message"].as_str().unwrap().len() > 0);
         assert!(result["analysis"] != json!(null));
 
+        eprintln!("check names with lints");
+        let invoked = invoke_command(
+            "test",
+            &[
+                "check".to_string(),
+                "--lint".to_string(),
+                "sample-contracts/names.clar".to_string(),
+                db_name.clone(),
+            ],
+        );
+
+        let exit = invoked.0;
+        let result = invoked.1.unwrap();
+
+        assert_eq!(exit, 0);
+        assert!(result["warnings"].is_array());
+
         eprintln!("check names with cost");
         let invoked = invoke_command(
             "test",
@@ -17,12 +17,14 @@
 use std::convert::TryFrom;
 use std::error;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use crate::chainstate::stacks::boot::BOOT_CODE_COSTS_2_TESTNET;
 use crate::chainstate::stacks::boot::{
-    BOOT_CODE_COSTS, BOOT_CODE_COSTS_2, BOOT_CODE_COST_VOTING_TESTNET as BOOT_CODE_COST_VOTING,
-    BOOT_CODE_POX_TESTNET, COSTS_2_NAME,
+    BOOT_CODE_COSTS, BOOT_CODE_COSTS_2, BOOT_CODE_COSTS_3, BOOT_CODE_COSTS_3_TESTNET,
+    BOOT_CODE_COST_VOTING_TESTNET as BOOT_CODE_COST_VOTING, BOOT_CODE_POX_TESTNET, COSTS_2_NAME,
+    COSTS_3_NAME,
 };
 use crate::chainstate::stacks::db::StacksAccount;
 use crate::chainstate::stacks::db::StacksChainState;
@@ -71,6 +73,7 @@ use clarity::vm::types::{
     AssetIdentifier, PrincipalData, QualifiedContractIdentifier, TypeSignature, Value,
 };
 use clarity::vm::ContractName;
+use clarity::vm::EvalHook;
 use stacks_common::types::chainstate::BurnchainHeaderHash;
 
 use crate::util_lib::db::Error as DatabaseError;
@@ -103,8 +106,13 @@ use clarity::vm::clarity::TransactionConnection;
 pub struct ClarityInstance {
     datastore: MarfedKV,
     mainnet: bool,
+    eval_hook: Option<SharedEvalHook>,
 }
 
+/// An `EvalHook` which is installed in every transaction processed by a `ClarityInstance`,
+/// shared so that its owner can inspect it between blocks.
+pub type SharedEvalHook = Arc<Mutex<dyn EvalHook + Send>>;
+
 ///
 /// This struct represents a "sealed" or "finished" Clarity block that
 /// has *not* yet been committed. This struct allows consumers of the
@@ -131,6 +139,7 @@ pub struct ClarityBlockConnection<'a, 'b> {
     cost_track: Option<LimitedCostTracker>,
     mainnet: bool,
     epoch: StacksEpochId,
+    eval_hook: Option<SharedEvalHook>,
 }
 
 ///
@@ -146,6 +155,7 @@ pub struct ClarityTransactionConnection<'a, 'b> {
     cost_track: &'a mut Option<LimitedCostTracker>,
     mainnet: bool,
     epoch: StacksEpochId,
+    eval_hook: &'a Option<SharedEvalHook>,
 }
 
 pub struct ClarityReadOnlyConnection<'a> {
@@ -219,7 +229,17 @@ impl<'a, 'b> ClarityBlockConnection<'a, 'b> {
 
 impl ClarityInstance {
     pub fn new(mainnet: bool, datastore: MarfedKV) -> ClarityInstance {
-        ClarityInstance { datastore, mainnet }
+        ClarityInstance {
+            datastore,
+            mainnet,
+            eval_hook: None,
+        }
+    }
+
+    /// Install (or with `None`, remove) a hook to be invoked around every expression evaluated
+    /// by the transactions in blocks begun after this call.
+    pub fn set_eval_hook(&mut self, eval_hook: Option<SharedEvalHook>) {
+        self.eval_hook = eval_hook;
     }
 
     pub fn with_marf<F, R>(&mut self, f: F) -> R
@@ -289,6 +309,7 @@ impl ClarityInstance {
             cost_track,
             mainnet: self.mainnet,
             epoch: epoch.epoch_id,
+            eval_hook: self.eval_hook.clone(),
         }
     }
 
@@ -312,6 +333,7 @@ impl ClarityInstance {
             cost_track,
             mainnet: self.mainnet,
             epoch,
+            eval_hook: self.eval_hook.clone(),
         }
     }
 
@@ -337,6 +359,7 @@ impl ClarityInstance {
             cost_track,
             mainnet: self.mainnet,
             epoch,
+            eval_hook: self.eval_hook.clone(),
         };
 
         let use_mainnet = self.mainnet;
@@ -436,6 +459,7 @@ impl ClarityInstance {
             cost_track,
             mainnet: self.mainnet,
             epoch: epoch.epoch_id,
+            eval_hook: self.eval_hook.clone(),
         }
     }
 
@@ -737,6 +761,84 @@ impl<'a, 'b> ClarityBlockConnection<'a, 'b> {
         })
     }
 
+    pub fn initialize_epoch_2_1(&mut self) -> Result<StacksTransactionReceipt, Error> {
+        // use the `using!` statement to ensure that the old cost_tracker is placed
+        //  back in all branches after initialization
+        using!(self.cost_track, "cost tracker", |old_cost_tracker| {
+            // epoch initialization is *free*
+            self.cost_track.replace(LimitedCostTracker::new_free());
+
+            let mainnet = self.mainnet;
+
+            // get the boot code account information
+            //  for processing the pox contract initialization
+            let tx_version = if mainnet {
+                TransactionVersion::Mainnet
+            } else {
+                TransactionVersion::Testnet
+            };
+
+            let boot_code_address = boot_code_addr(mainnet);
+
+            let boot_code_auth = boot_code_tx_auth(boot_code_address);
+
+            let boot_code_nonce = self.with_clarity_db_readonly(|db| {
+                db.get_account_nonce(&boot_code_address.clone().into())
+            });
+
+            let boot_code_account = boot_code_acc(boot_code_address, boot_code_nonce);
+
+            // instantiate costs 3 contract...
+            let cost_3_code = if mainnet {
+                &*BOOT_CODE_COSTS_3
+            } else {
+                &*BOOT_CODE_COSTS_3_TESTNET
+            };
+
+            let payload = TransactionPayload::SmartContract(TransactionSmartContract {
+                name: ContractName::try_from(COSTS_3_NAME)
+                    .expect("FATAL: invalid boot-code contract name"),
+                code_body: StacksString::from_str(cost_3_code)
+                    .expect("FATAL: invalid boot code body"),
+            });
+
+            let costs_3_contract_tx =
+                StacksTransaction::new(tx_version.clone(), boot_code_auth.clone(), payload);
+
+            let initialization_receipt = self.as_transaction(|tx_conn| {
+                // bump the epoch in the Clarity DB
+                tx_conn
+                    .with_clarity_db(|db| {
+                        db.set_clarity_epoch_version(StacksEpochId::Epoch21);
+                        Ok(())
+                    })
+                    .unwrap();
+
+                // initialize with a synthetic transaction
+                let receipt = StacksChainState::process_transaction_payload(
+                    tx_conn,
+                    &costs_3_contract_tx,
+                    &boot_code_account,
+                    ASTRules::PrecheckSize,
+                )
+                .expect("FATAL: Failed to process Costs 3 contract initialization");
+
+                receipt
+            });
+
+            if initialization_receipt.result != Value::okay_true()
+                || initialization_receipt.post_condition_aborted
+            {
+                panic!(
+                    "FATAL: Failure processing Costs 3 contract initialization: {:#?}",
+                    &initialization_receipt
+                );
+            }
+
+            (old_cost_tracker, Ok(initialization_receipt))
+        })
+    }
+
     pub fn start_transaction_processing<'c>(&'c mut self) -> ClarityTransactionConnection<'c, 'a> {
         let store = &mut self.datastore;
         let cost_track = &mut self.cost_track;
@@ -753,6 +855,7 @@ impl<'a, 'b> ClarityBlockConnection<'a, 'b> {
             log: Some(log),
             mainnet,
             epoch: self.epoch,
+            eval_hook: &self.eval_hook,
         }
     }
 
@@ -854,8 +957,16 @@ impl<'a, 'b> TransactionConnection for ClarityTransactionConnection<'a, 'b> {
                 // wrap the whole contract-call in a claritydb transaction,
                 //   so we can abort on call_back's boolean retun
                 db.begin();
+                let mut eval_hook = self.eval_hook.as_ref().map(|eval_hook| {
+                    eval_hook
+                        .lock()
+                        .expect("FATAL: failed to lock the Clarity eval hook")
+                });
                 let mut vm_env =
                     OwnedEnvironment::new_cost_limited(self.mainnet, db, cost_track, self.epoch);
+                if let Some(ref mut eval_hook) = eval_hook {
+                    vm_env.add_eval_hook(&mut **eval_hook);
+                }
                 let result = to_do(&mut vm_env);
                 let (mut db, cost_track) = vm_env
                     .destruct()
@@ -34,6 +34,7 @@ use clarity::vm::database::ClarityDatabase;
 use clarity::vm::errors::{CheckErrors, Error, RuntimeErrorType};
 use clarity::vm::events::StacksTransactionEvent;
 use clarity::vm::functions::NativeFunctions;
+use clarity::vm::profiler::{CostProfiler, ProfileWeight};
 use clarity::vm::representations::SymbolicExpression;
 use clarity::vm::test_util::{
     execute, execute_on_network, symbols_from_values, TEST_BURN_STATE_DB, TEST_HEADER_DB,
@@ -41,6 +42,7 @@ use clarity::vm::test_util::{
 use clarity::vm::types::{
     AssetIdentifier, PrincipalData, QualifiedContractIdentifier, ResponseData, Value,
 };
+use clarity::vm::ClarityVersion;
 use stacks_common::util::hash::hex_bytes;
 use std::collections::HashMap;
 
@@ -137,7 +139,9 @@ pub fn get_simple_test(function: &NativeFunctions) -> &'static str {
         GetTokenSupply => "(ft-get-supply ft-foo)",
         AtBlock => "(at-block 0x55c9861be5cff984a20ce6d99d4aa65941412889bdc665094136429b84f8c2ee 1)",   // first stacksblockid
         GetStxBalance => "(stx-get-balance 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
+        GetStxAccount => "(stx-account 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
         StxTransfer => "(stx-transfer? u1 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
+        StxTransferMemo => "(stx-transfer-memo? u1 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR 0x89995432)",
         StxBurn => "(stx-burn? u1 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)",
     }
 }
@@ -169,24 +173,29 @@ where
         )
         .commit_block();
 
-    let tip = if epoch == StacksEpochId::Epoch2_05 {
+    let mut tip = first_block.clone();
+
+    if epoch >= StacksEpochId::Epoch2_05 {
         let next_block = StacksBlockId([1 as u8; 32]);
-        let mut clarity_conn = clarity_instance.begin_block(
-            &first_block,
-            &next_block,
-            &TEST_HEADER_DB,
-            &TEST_BURN_STATE_DB,
-        );
+        let mut clarity_conn =
+            clarity_instance.begin_block(&tip, &next_block, &TEST_HEADER_DB, &TEST_BURN_STATE_DB);
         clarity_conn.initialize_epoch_2_05().unwrap();
         clarity_conn.commit_block();
-        next_block
-    } else {
-        first_block.clone()
-    };
+        tip = next_block.clone();
+    }
+
+    if epoch >= StacksEpochId::Epoch21 {
+        let next_block = StacksBlockId([2 as u8; 32]);
+        let mut clarity_conn =
+            clarity_instance.begin_block(&tip, &next_block, &TEST_HEADER_DB, &TEST_BURN_STATE_DB);
+        clarity_conn.initialize_epoch_2_1().unwrap();
+        clarity_conn.commit_block();
+        tip = next_block.clone();
+    }
 
     let mut marf_kv = clarity_instance.destroy();
 
-    let mut store = marf_kv.begin(&tip, &StacksBlockId([2 as u8; 32]));
+    let mut store = marf_kv.begin(&tip, &StacksBlockId([3 as u8; 32]));
 
     to_do(OwnedEnvironment::new_max_limit(
         store.as_clarity_db(&TEST_HEADER_DB, &TEST_BURN_STATE_DB),
@@ -875,6 +884,10 @@ fn test_all(use_mainnet: bool) {
     let baseline = test_tracked_costs("1", use_mainnet, StacksEpochId::Epoch20);
 
     for f in NativeFunctions::ALL.iter() {
+        // natives introduced in a later Clarity version are not defined in this epoch
+        if f.get_version() > ClarityVersion::default_for_epoch(StacksEpochId::Epoch20) {
+            continue;
+        }
         let test = get_simple_test(f);
         let cost = test_tracked_costs(test, use_mainnet, StacksEpochId::Epoch20);
         assert!(cost.exceeds(&baseline));
@@ -897,6 +910,10 @@ fn epoch_205_test_all(use_mainnet: bool) {
     let baseline = test_tracked_costs("1", use_mainnet, StacksEpochId::Epoch2_05);
 
     for f in NativeFunctions::ALL.iter() {
+        // natives introduced in a later Clarity version are not defined in this epoch
+        if f.get_version() > ClarityVersion::default_for_epoch(StacksEpochId::Epoch2_05) {
+            continue;
+        }
         let test = get_simple_test(f);
         let cost = test_tracked_costs(test, use_mainnet, StacksEpochId::Epoch2_05);
         assert!(cost.exceeds(&baseline));
@@ -913,6 +930,28 @@ fn epoch_205_test_all_testnet() {
     epoch_205_test_all(false)
 }
 
+// test each individual cost function can be correctly invoked as
+//  Clarity code executes in Epoch 2.1
+fn epoch_21_test_all(use_mainnet: bool) {
+    let baseline = test_tracked_costs("1", use_mainnet, StacksEpochId::Epoch21);
+
+    for f in NativeFunctions::ALL.iter() {
+        let test = get_simple_test(f);
+        let cost = test_tracked_costs(test, use_mainnet, StacksEpochId::Epoch21);
+        assert!(cost.exceeds(&baseline));
+    }
+}
+
+#[test]
+fn epoch_21_test_all_mainnet() {
+    epoch_21_test_all(true)
+}
+
+#[test]
+fn epoch_21_test_all_testnet() {
+    epoch_21_test_all(false)
+}
+
 fn test_cost_contract_short_circuits(use_mainnet: bool) {
     let marf_kv = MarfedKV::temporary();
     let mut clarity_instance = ClarityInstance::new(use_mainnet, marf_kv);
@@ -1536,3 +1575,85 @@ fn test_cost_voting_integration(use_mainnet: bool) {
 fn test_cost_voting_integration_testnet() {
     test_cost_voting_integration(false)
 }
+
+#[test]
+fn test_cost_profiler() {
+    let contract = "(define-data-var counter int 0)
+(define-private (double (x int))
+  (* x 2))
+(define-public (bump (n int))
+  (let ((d (double n)))
+    (var-set counter (+ (var-get counter) d))
+    (ok (var-get counter))))";
+
+    with_owned_env(StacksEpochId::Epoch2_05, false, |mut owned_env| {
+        let contract_id = QualifiedContractIdentifier::local("profiled").unwrap();
+        owned_env
+            .initialize_contract(contract_id.clone(), contract, ASTRules::PrecheckSize)
+            .unwrap();
+
+        // re-open the environment, so that the profiler only sees the call to `bump`
+        let (db, cost_track) = owned_env.destruct().unwrap();
+        let mut profiler = CostProfiler::new();
+        let mut owned_env =
+            OwnedEnvironment::new_cost_limited(false, db, cost_track, StacksEpochId::Epoch2_05);
+        owned_env.add_eval_hook(&mut profiler);
+        let before = owned_env.get_cost_total();
+        owned_env
+            .execute_transaction(
+                PrincipalData::from(contract_id.issuer.clone()),
+                contract_id.clone(),
+                "bump",
+                &symbols_from_values(vec![Value::Int(5)]),
+            )
+            .unwrap();
+        let mut spent = owned_env.get_cost_total();
+        spent.sub(&before).unwrap();
+        drop(owned_env);
+
+        let label = |function: &str, line: u32| format!("{} ({}:{})", function, &contract_id, line);
+        let entries = profiler.entries(ProfileWeight::Runtime);
+        let entry = |function: &str, line: u32| {
+            let label = label(function, line);
+            entries
+                .iter()
+                .find(|entry| entry.label == label)
+                .expect(&format!("No profile entry for {}", label))
+                .clone()
+        };
+
+        // the let is the body of `bump`, so everything but the function call itself is in it
+        let body = entry("let", 5);
+        assert_eq!(body.calls, 1);
+        assert!(body.total_cost.runtime <= spent.runtime);
+        assert!(body.self_cost.runtime < body.total_cost.runtime);
+
+        // `double` is charged for its own body
+        let double = entry("double", 5);
+        let multiply = entry("*", 3);
+        assert!(
+            double.total_cost.runtime >= double.self_cost.runtime + multiply.total_cost.runtime
+        );
+
+        let var_set = entry("var-set", 6);
+        assert_eq!(var_set.self_cost.write_count, 1);
+        assert_eq!(
+            var_set.total_cost.read_count,
+            var_set.self_cost.read_count + 1
+        );
+        assert_eq!(entry("var-get", 7).self_cost.read_count, 1);
+
+        let mut folded = vec![];
+        profiler
+            .write_folded(&mut folded, ProfileWeight::Runtime)
+            .unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let double_stack = format!(
+            "{};{};{} ",
+            label("let", 5),
+            label("double", 5),
+            label("*", 3)
+        );
+        assert!(folded.lines().any(|line| line.starts_with(&double_stack)));
+    });
+}
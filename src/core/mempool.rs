@@ -1660,6 +1660,12 @@ impl MemPoolDB {
         )
     }
 
+    /// Get the txids of all transactions across all tips
+    pub fn get_all_txids(conn: &DBConn) -> Result<Vec<Txid>, db_error> {
+        let sql = "SELECT txid FROM mempool";
+        query_rows::<Txid, _>(conn, &sql, NO_PARAMS)
+    }
+
     /// Get all transactions across all tips
     #[cfg(test)]
     pub fn get_all_txs(conn: &DBConn) -> Result<Vec<MemPoolTxInfo>, db_error> {
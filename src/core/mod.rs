@@ -55,6 +55,7 @@ pub const PEER_VERSION_TESTNET: u32 = 0xfacade05;
 pub const PEER_VERSION_EPOCH_1_0: u8 = 0x00;
 pub const PEER_VERSION_EPOCH_2_0: u8 = 0x00;
 pub const PEER_VERSION_EPOCH_2_05: u8 = 0x05;
+pub const PEER_VERSION_EPOCH_2_1: u8 = 0x06;
 
 // network identifiers
 pub const NETWORK_ID_MAINNET: u32 = 0x17000000;
@@ -336,6 +337,8 @@ pub trait StacksEpochExtension {
     #[cfg(test)]
     fn unit_test_2_05(epoch_2_0_block_height: u64) -> Vec<StacksEpoch>;
     #[cfg(test)]
+    fn unit_test_2_1(epoch_2_0_block_height: u64) -> Vec<StacksEpoch>;
+    #[cfg(test)]
     fn unit_test_pre_2_05(epoch_2_0_block_height: u64) -> Vec<StacksEpoch>;
 }
 
@@ -403,6 +406,27 @@ impl StacksEpochExtension for StacksEpoch {
         ]
     }
 
+    #[cfg(test)]
+    fn unit_test_2_1(first_burnchain_height: u64) -> Vec<StacksEpoch> {
+        info!(
+            "StacksEpoch unit_test first_burn_height = {}",
+            first_burnchain_height
+        );
+
+        let mut epochs = StacksEpoch::unit_test_2_05(first_burnchain_height);
+        let epoch_2_05 = epochs.last_mut().expect("BUG: no 2.05 epoch");
+        epoch_2_05.end_height = first_burnchain_height + 8;
+        let block_limit = epoch_2_05.block_limit.clone();
+        epochs.push(StacksEpoch {
+            epoch_id: StacksEpochId::Epoch21,
+            start_height: first_burnchain_height + 8,
+            end_height: STACKS_EPOCH_MAX,
+            block_limit,
+            network_epoch: PEER_VERSION_EPOCH_2_1,
+        });
+        epochs
+    }
+
     #[cfg(test)]
     fn unit_test(stacks_epoch_id: StacksEpochId, first_burnchain_height: u64) -> Vec<StacksEpoch> {
         match stacks_epoch_id {
@@ -410,6 +434,7 @@ impl StacksEpochExtension for StacksEpoch {
                 StacksEpoch::unit_test_pre_2_05(first_burnchain_height)
             }
             StacksEpochId::Epoch2_05 => StacksEpoch::unit_test_2_05(first_burnchain_height),
+            StacksEpochId::Epoch21 => StacksEpoch::unit_test_2_1(first_burnchain_height),
         }
     }
 }
@@ -33,7 +33,9 @@ extern crate slog;
 
 use std::io;
 use std::io::prelude::*;
+use std::path::PathBuf;
 use std::process;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::{collections::HashMap, env};
 use std::{convert::TryFrom, fs};
@@ -54,6 +56,7 @@ use blockstack_lib::burnchains::Address;
 use blockstack_lib::burnchains::Burnchain;
 use blockstack_lib::burnchains::Txid;
 use blockstack_lib::chainstate::burn::ConsensusHash;
+use blockstack_lib::chainstate::snapshot::export_snapshot;
 use blockstack_lib::chainstate::stacks::db::blocks::DummyEventDispatcher;
 use blockstack_lib::chainstate::stacks::db::blocks::StagingBlock;
 use blockstack_lib::chainstate::stacks::db::ChainStateBootData;
@@ -65,6 +68,7 @@ use blockstack_lib::chainstate::stacks::miner::*;
 use blockstack_lib::chainstate::stacks::StacksBlockHeader;
 use blockstack_lib::chainstate::stacks::*;
 use blockstack_lib::clarity::vm::costs::ExecutionCost;
+use blockstack_lib::clarity::vm::profiler::{CostProfiler, ProfileWeight, PROFILE_TABLE_ROWS};
 use blockstack_lib::clarity::vm::types::StacksAddressExtensions;
 use blockstack_lib::codec::StacksMessageCodec;
 use blockstack_lib::core::*;
@@ -698,6 +702,55 @@ check if the associated microblocks can be downloaded
         process::exit(0);
     }
 
+    if argv[1] == "export-snapshot" {
+        if argv.len() < 5 {
+            eprintln!(
+                "Usage: {} export-snapshot WORKING_DIR OUTPUT_DIR REWARD_CYCLE [mainnet|testnet|regtest]\n\nWORKING_DIR is the node's working directory for the network (e.g. /path/to/mainnet).",
+                argv[0]
+            );
+            process::exit(1);
+        }
+        let working_dir = &argv[2];
+        let output_dir = &argv[3];
+        let reward_cycle: u64 = argv[4]
+            .parse()
+            .expect("Failed to parse REWARD_CYCLE argument");
+        let network = argv.get(5).map(|s| s.as_str()).unwrap_or("mainnet");
+        let (mainnet, chain_id) = match network {
+            "mainnet" => (true, CHAIN_ID_MAINNET),
+            "testnet" | "regtest" => (false, CHAIN_ID_TESTNET),
+            _ => {
+                eprintln!("Unrecognized network '{}'", network);
+                process::exit(1);
+            }
+        };
+
+        let mut burnchain = Burnchain::new(working_dir, "bitcoin", network)
+            .expect("Failed to instantiate burnchain");
+        let sortdb_path = format!("{}/burnchain/sortition", working_dir);
+        let sortdb = SortitionDB::open(&sortdb_path, false)
+            .expect(&format!("Failed to open {}", &sortdb_path));
+        burnchain.first_block_height = sortdb.first_block_height;
+        drop(sortdb);
+
+        let manifest = export_snapshot(
+            &PathBuf::from(working_dir),
+            &PathBuf::from(output_dir),
+            &burnchain,
+            mainnet,
+            chain_id,
+            reward_cycle,
+        )
+        .expect("Failed to export snapshot");
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&manifest.anchor).unwrap()
+        );
+        println!("Exported {} files to {}", manifest.files.len(), output_dir);
+        process::exit(0);
+    }
+
     if argv[1] == "try-mine" {
         if argv.len() < 3 {
             eprintln!(
@@ -1002,9 +1055,18 @@ simulating a miner.
     }
 
     if argv[1] == "process-block" {
+        let profile_file = consume_profile_arg(&mut argv);
+        if argv.len() < 4 {
+            eprintln!(
+                "Usage: {} process-block CHAINSTATE_PATH SORTITION_DB_PATH [--profile FOLDED_STACK_FILE]",
+                &argv[0]
+            );
+            process::exit(1);
+        }
         let path = &argv[2];
         let sort_path = &argv[3];
         let (mut chainstate, _) = StacksChainState::open(false, 0x80000000, path, None).unwrap();
+        let profiler = install_profiler(&mut chainstate, &profile_file);
         let mut sortition_db = SortitionDB::open(sort_path, true).unwrap();
         let sortition_tip = SortitionDB::get_canonical_burn_chain_tip(sortition_db.conn())
             .unwrap()
@@ -1014,12 +1076,16 @@ simulating a miner.
         chainstate
             .process_next_staging_block(&mut tx, null_event_dispatcher)
             .unwrap();
+        if let (Some(profile_file), Some(profiler)) = (profile_file, profiler) {
+            write_profile(&profile_file, &profiler);
+        }
         return;
     }
 
     if argv[1] == "replay-chainstate" {
+        let profile_file = consume_profile_arg(&mut argv);
         if argv.len() < 7 {
-            eprintln!("Usage: {} OLD_CHAINSTATE_PATH OLD_SORTITION_DB_PATH OLD_BURNCHAIN_DB_PATH NEW_CHAINSTATE_PATH NEW_BURNCHAIN_DB_PATH", &argv[0]);
+            eprintln!("Usage: {} OLD_CHAINSTATE_PATH OLD_SORTITION_DB_PATH OLD_BURNCHAIN_DB_PATH NEW_CHAINSTATE_PATH NEW_BURNCHAIN_DB_PATH [--profile FOLDED_STACK_FILE]", &argv[0]);
             process::exit(1);
         }
 
@@ -1076,6 +1142,7 @@ simulating a miner.
             first_block: 0,
             magic_bytes: BLOCKSTACK_MAGIC_MAINNET.clone(),
             epochs: None,
+            proxy: None,
         };
 
         let indexer = BitcoinIndexer::new(
@@ -1123,6 +1190,7 @@ simulating a miner.
             None,
         )
         .unwrap();
+        let profiler = install_profiler(&mut new_chainstate, &profile_file);
 
         let all_snapshots = old_sortition_db.get_all_snapshots().unwrap();
         let all_stacks_blocks =
@@ -1333,6 +1401,9 @@ simulating a miner.
             next_arrival,
             stacks_blocks_arrival_order.len()
         );
+        if let (Some(profile_file), Some(profiler)) = (profile_file, profiler) {
+            write_profile(&profile_file, &profiler);
+        }
         return;
     }
 
@@ -1342,6 +1413,51 @@ simulating a miner.
     }
 }
 
+/// Remove a `--profile FOLDED_STACK_FILE` option from `argv`, returning the file name.
+fn consume_profile_arg(argv: &mut Vec<String>) -> Option<String> {
+    let index = argv.iter().position(|arg| arg == "--profile")?;
+    if index + 1 >= argv.len() {
+        eprintln!("Expected a file name after --profile");
+        process::exit(1);
+    }
+    argv.remove(index);
+    Some(argv.remove(index))
+}
+
+/// Profile the Clarity execution of every transaction `chainstate` processes, if a profile
+/// was requested.
+fn install_profiler(
+    chainstate: &mut StacksChainState,
+    profile_file: &Option<String>,
+) -> Option<Arc<Mutex<CostProfiler>>> {
+    profile_file.as_ref()?;
+    let profiler = Arc::new(Mutex::new(CostProfiler::new()));
+    chainstate
+        .clarity_state
+        .set_eval_hook(Some(profiler.clone()));
+    Some(profiler)
+}
+
+/// Write the runtime folded stacks of a profile to `profile_file`, and a summary of the most
+/// expensive expressions to stderr.
+fn write_profile(profile_file: &str, profiler: &Mutex<CostProfiler>) {
+    let profiler = profiler
+        .lock()
+        .expect("FATAL: failed to lock the cost profiler");
+    let mut file = File::create(profile_file)
+        .unwrap_or_else(|e| panic!("Failed to create profile file {}: {:?}", profile_file, e));
+    profiler
+        .write_folded(&mut file, ProfileWeight::Runtime)
+        .expect("Failed to write profile");
+    profiler
+        .write_table(
+            &mut io::stderr(),
+            ProfileWeight::Runtime,
+            PROFILE_TABLE_ROWS,
+        )
+        .expect("Failed to write profile summary");
+}
+
 fn tip_mine() {
     let argv: Vec<String> = env::args().collect();
     if argv.len() < 6 {
@@ -313,6 +313,33 @@ pub fn increment_msg_counter(name: String) {
         .inc();
 }
 
+#[allow(unused_variables)]
+pub fn update_p2p_msg_bandwidth(name: &str, direction: &str, value: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::P2P_MSG_BANDWIDTH_VEC
+        .with_label_values(&[name, direction])
+        .inc_by(value as i64);
+}
+
+#[allow(unused_variables)]
+pub fn update_p2p_peer_bandwidth(peer: &str, direction: &str, value: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::P2P_PEER_BANDWIDTH_VEC
+        .with_label_values(&[peer, direction])
+        .inc_by(value as i64);
+}
+
+/// Stop reporting a peer's bandwidth once we've disconnected from it, so the per-peer series
+/// don't grow without bound.
+#[allow(unused_variables)]
+pub fn clear_p2p_peer_bandwidth(peer: &str) {
+    #[cfg(feature = "monitoring_prom")]
+    {
+        let _ = prometheus::P2P_PEER_BANDWIDTH_VEC.remove_label_values(&[peer, "rx"]);
+        let _ = prometheus::P2P_PEER_BANDWIDTH_VEC.remove_label_values(&[peer, "tx"]);
+    }
+}
+
 pub fn increment_stx_mempool_gc() {
     #[cfg(feature = "monitoring_prom")]
     prometheus::STX_MEMPOOL_GC.inc();
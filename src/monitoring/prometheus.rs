@@ -172,6 +172,18 @@ lazy_static! {
         &["name"]
     ).unwrap();
 
+    pub static ref P2P_MSG_BANDWIDTH_VEC: IntCounterVec = register_int_counter_vec!(
+        "stacks_node_p2p_message_bytes",
+        "Total p2p message bytes by type of message and direction",
+        &["name", "direction"]
+    ).unwrap();
+
+    pub static ref P2P_PEER_BANDWIDTH_VEC: IntCounterVec = register_int_counter_vec!(
+        "stacks_node_p2p_peer_bytes",
+        "Total p2p bytes by peer address and direction",
+        &["peer", "direction"]
+    ).unwrap();
+
 
     pub static ref STX_MEMPOOL_GC: IntCounter = register_int_counter!(opts!(
         "stacks_node_mempool_gc_count",
@@ -35,6 +35,7 @@ use crate::chainstate::burn::db::sortdb;
 use crate::chainstate::burn::db::sortdb::{BlockHeaderCache, SortitionDB};
 use crate::chainstate::stacks::db::StacksChainState;
 use crate::chainstate::stacks::StacksPublicKey;
+use crate::codec::PREAMBLE_ENCODED_SIZE;
 use crate::monitoring;
 use crate::net::asn::ASEntry4;
 use crate::net::codec::*;
@@ -45,6 +46,9 @@ use crate::net::db::PeerDB;
 use crate::net::db::*;
 use crate::net::neighbors::MAX_NEIGHBOR_BLOCK_DELAY;
 use crate::net::relay::*;
+use crate::net::transport::TransportCipher;
+use crate::net::transport::TransportKeyExchange;
+use crate::net::transport::TransportState;
 use crate::net::Error as net_error;
 use crate::net::GetBlocksInv;
 use crate::net::GetPoxInv;
@@ -136,10 +140,14 @@ pub struct NeighborStats {
     pub msgs_err: u64,
     pub healthpoints: VecDeque<NeighborHealthPoint>,
     pub msg_rx_counts: HashMap<StacksMessageID, u64>,
+    pub msg_rx_bytes: HashMap<StacksMessageID, u64>,
+    pub msg_tx_bytes: HashMap<StacksMessageID, u64>,
     pub block_push_rx_counts: VecDeque<(u64, u64)>, // (timestamp, num bytes)
     pub microblocks_push_rx_counts: VecDeque<(u64, u64)>, // (timestamp, num bytes)
     pub transaction_push_rx_counts: VecDeque<(u64, u64)>, // (timestamp, num bytes)
     pub relayed_messages: HashMap<NeighborAddress, RelayStats>,
+    /// reputation score change accrued since it was last written to the PeerDB
+    pub reputation_delta: i64,
 }
 
 impl NeighborStats {
@@ -159,13 +167,20 @@ impl NeighborStats {
             msgs_err: 0,
             healthpoints: VecDeque::new(),
             msg_rx_counts: HashMap::new(),
+            msg_rx_bytes: HashMap::new(),
+            msg_tx_bytes: HashMap::new(),
             block_push_rx_counts: VecDeque::new(),
             microblocks_push_rx_counts: VecDeque::new(),
             transaction_push_rx_counts: VecDeque::new(),
             relayed_messages: HashMap::new(),
+            reputation_delta: 0,
         }
     }
 
+    pub fn add_reputation_event(&mut self, event: ReputationEvent) -> () {
+        self.reputation_delta = self.reputation_delta.saturating_add(event.score_delta());
+    }
+
     pub fn add_healthpoint(&mut self, success: bool) -> () {
         let hp = NeighborHealthPoint {
             success: success,
@@ -284,6 +299,28 @@ impl NeighborStats {
     pub fn get_message_recv_count(&self, msg_id: StacksMessageID) -> u64 {
         *(self.msg_rx_counts.get(&msg_id).unwrap_or(&0))
     }
+
+    /// Record that we received a message of the given type and (plaintext) size from this peer
+    pub fn add_message_recv_bytes(&mut self, msg_id: StacksMessageID, num_bytes: u64) -> () {
+        let total = self.msg_rx_bytes.entry(msg_id).or_insert(0);
+        *total = total.saturating_add(num_bytes);
+    }
+
+    /// Record that we sent a message of the given type and (plaintext) size to this peer
+    pub fn add_message_send_bytes(&mut self, msg_id: StacksMessageID, num_bytes: u64) -> () {
+        let total = self.msg_tx_bytes.entry(msg_id).or_insert(0);
+        *total = total.saturating_add(num_bytes);
+    }
+
+    /// Determine how many bytes of a particular message this peer has sent us
+    pub fn get_message_recv_bytes(&self, msg_id: StacksMessageID) -> u64 {
+        *(self.msg_rx_bytes.get(&msg_id).unwrap_or(&0))
+    }
+
+    /// Determine how many bytes of a particular message we have sent this peer
+    pub fn get_message_send_bytes(&self, msg_id: StacksMessageID) -> u64 {
+        *(self.msg_tx_bytes.get(&msg_id).unwrap_or(&0))
+    }
 }
 
 /// P2P ongoing conversation with another Stacks peer
@@ -323,6 +360,13 @@ pub struct ConversationP2P {
 
     // system epochs
     epochs: Vec<StacksEpoch>,
+
+    // encrypted transport setup
+    transport: TransportState,
+
+    // if we dialed one of this peer's alternate addresses, the hash of the public key they were
+    // advertised for.  Cleared once the peer's HandshakeAccept shows it has that key.
+    pub dialed_alternate_key_hash: Option<Hash160>,
 }
 
 impl fmt::Display for ConversationP2P {
@@ -493,11 +537,16 @@ impl ConversationP2P {
         conn_id: usize,
         epochs: Vec<StacksEpoch>,
     ) -> ConversationP2P {
+        let mut connection = ConnectionP2P::new(StacksP2P::new(), conn_opts, None);
+        connection.set_bandwidth_limits(
+            conn_opts.max_peer_inbound_bandwidth,
+            conn_opts.max_peer_outbound_bandwidth,
+        );
         ConversationP2P {
             instantiated: get_epoch_time_secs(),
             network_id: network_id,
             version: version,
-            connection: ConnectionP2P::new(StacksP2P::new(), conn_opts, None),
+            connection: connection,
             conn_id: conn_id,
             heartbeat: conn_opts.heartbeat,
             burnchain: burnchain.clone(),
@@ -523,6 +572,9 @@ impl ConversationP2P {
             reply_handles: VecDeque::new(),
 
             epochs: epochs,
+
+            transport: TransportState::Plaintext,
+            dialed_alternate_key_hash: None,
         }
     }
 
@@ -635,6 +687,35 @@ impl ConversationP2P {
         (peer_services & expected_bits) == expected_bits
     }
 
+    /// Does this remote neighbor support the encrypted transport?  It will if it has the
+    /// ENCRYPTION bit set.
+    pub fn supports_encryption(peer_services: u16) -> bool {
+        (peer_services & (ServiceFlags::ENCRYPTION as u16)) != 0
+    }
+
+    /// Does this remote neighbor support compact block relay?  It will if it has the
+    /// COMPACT_BLOCKS bit set.
+    pub fn supports_compact_blocks(peer_services: u16) -> bool {
+        (peer_services & (ServiceFlags::COMPACT_BLOCKS as u16)) != 0
+    }
+
+    /// Does this remote neighbor advertise alternate addresses?  It will if it has the
+    /// MULTI_ADDRESS bit set.
+    pub fn supports_multi_address(peer_services: u16) -> bool {
+        (peer_services & (ServiceFlags::MULTI_ADDRESS as u16)) != 0
+    }
+
+    /// Can this remote neighbor relay stem-phase transactions?  It will if it has the DANDELION
+    /// bit set.
+    pub fn supports_dandelion(peer_services: u16) -> bool {
+        (peer_services & (ServiceFlags::DANDELION as u16)) != 0
+    }
+
+    /// Is our traffic with this peer encrypted in both directions?
+    pub fn is_encrypted(&self) -> bool {
+        self.transport.is_encrypted()
+    }
+
     /// Determine whether or not a given (height, burn_header_hash) pair _disagrees_ with our
     /// burnchain view.  If it does, return true.  If it doesn't (including if the given pair is
     /// simply absent from the chain_view), then return False.
@@ -900,6 +981,19 @@ impl ConversationP2P {
         self.sign_and_reply(local_peer, burnchain_view, preamble, nack_payload)
     }
 
+    /// Account for the bytes of a message we are about to send.
+    fn count_sent_bytes(&mut self, msg: &StacksMessage) -> () {
+        let msg_id = msg.payload.get_message_id();
+        let num_bytes = (PREAMBLE_ENCODED_SIZE as u64) + (msg.preamble.payload_len as u64);
+        self.stats.add_message_send_bytes(msg_id, num_bytes);
+        monitoring::update_p2p_msg_bandwidth(msg.get_message_name(), "tx", num_bytes);
+    }
+
+    /// Did our last read or write to this peer stop early because it hit its bandwidth limit?
+    pub fn is_throttled(&self) -> bool {
+        self.connection.is_throttled()
+    }
+
     /// Queue up this message to this peer, and update our stats.
     /// This is a non-blocking operation. The caller needs to call .try_flush() or .flush() on the
     /// returned Write to finish sending.
@@ -914,6 +1008,7 @@ impl ConversationP2P {
         msg.consensus_serialize(&mut handle)?;
 
         self.stats.msgs_tx += 1;
+        self.count_sent_bytes(&msg);
 
         debug!(
             "{:?}: relay-send({}) {} seq {}",
@@ -922,6 +1017,30 @@ impl ConversationP2P {
         Ok(handle)
     }
 
+    /// Queue up this message to this peer, and encrypt everything we send to it afterwards.
+    fn relay_signed_message_then_encrypt(
+        &mut self,
+        msg: StacksMessage,
+        cipher: TransportCipher,
+    ) -> Result<ReplyHandleP2P, net_error> {
+        let _name = msg.payload.get_message_description();
+        let _seq = msg.request_id();
+
+        let mut handle = self
+            .connection
+            .make_relay_handle_then_encrypt(self.conn_id, cipher)?;
+        msg.consensus_serialize(&mut handle)?;
+
+        self.stats.msgs_tx += 1;
+        self.count_sent_bytes(&msg);
+
+        debug!(
+            "{:?}: relay-send({}) {} seq {}, then encrypt",
+            &self, self.stats.msgs_tx, _name, _seq
+        );
+        Ok(handle)
+    }
+
     /// Queue up this message to this peer, and update our stats.  Expect a reply.
     /// This is a non-blocking operation.  The caller needs to call .try_flush() or .flush() on the
     /// returned handle to finish sending.
@@ -939,6 +1058,7 @@ impl ConversationP2P {
         msg.consensus_serialize(&mut handle)?;
 
         self.stats.msgs_tx += 1;
+        self.count_sent_bytes(&msg);
 
         debug!(
             "{:?}: request-send({}) {} seq {}",
@@ -1156,6 +1276,7 @@ impl ConversationP2P {
              "services" => &to_hex(&handshake_data.services.to_be_bytes()),
              "expires_block_height" => handshake_data.expire_block_height,
              "supports_mempool_query" => Self::supports_mempool_query(handshake_data.services),
+             "supports_encryption" => Self::supports_encryption(handshake_data.services),
         );
 
         if updated {
@@ -1202,9 +1323,28 @@ impl ConversationP2P {
     /// Called from the p2p network thread.
     fn handle_handshake_accept(
         &mut self,
+        local_peer: &LocalPeer,
+        burnchain_view: &BurnchainView,
         preamble: &Preamble,
         handshake_accept: &HandshakeAcceptData,
     ) -> Result<(), net_error> {
+        if let Some(expected_key_hash) = self.dialed_alternate_key_hash.as_ref() {
+            // make sure we reached the peer whose addresses these are
+            let pubk = handshake_accept
+                .handshake
+                .node_public_key
+                .to_public_key()
+                .map_err(|e| net_error::DeserializeError(e.into()))?;
+            if Hash160::from_node_public_key(&pubk) != *expected_key_hash {
+                debug!(
+                    "{:?}: invalid handshake accept -- peer at alternate address does not have key {}",
+                    &self, expected_key_hash
+                );
+                return Err(net_error::InvalidHandshake);
+            }
+            self.dialed_alternate_key_hash = None;
+        }
+
         self.update_from_handshake_data(preamble, &handshake_accept.handshake)?;
         self.peer_heartbeat =
             if handshake_accept.heartbeat_interval > (MAX_PEER_HEARTBEAT_INTERVAL as u32) {
@@ -1233,9 +1373,295 @@ impl ConversationP2P {
             handshake_accept.handshake.expire_block_height,
             self.peer_heartbeat
         );
+
+        self.send_peer_addresses(local_peer, burnchain_view)?;
+        self.start_encrypted_transport(local_peer, burnchain_view)
+    }
+
+    /// Would we and this peer both exchange alternate addresses?
+    fn wants_peer_addresses(&self, local_peer: &LocalPeer) -> bool {
+        Self::supports_multi_address(local_peer.services)
+            && Self::supports_multi_address(self.peer_services)
+    }
+
+    /// Our own entry in a `PeerAddresses` message:  the address we handshake with, and every
+    /// other address we can be reached at.
+    fn local_peer_addresses(local_peer: &LocalPeer) -> NeighborAddresses {
+        let handshake = HandshakeData::from_local_peer(local_peer);
+        let own_addr = PeerHost::IP(handshake.addrbytes.clone(), handshake.port);
+        NeighborAddresses {
+            addr: NeighborAddress {
+                addrbytes: handshake.addrbytes,
+                port: handshake.port,
+                public_key_hash: Hash160::from_node_public_key(&StacksPublicKey::from_private(
+                    &local_peer.private_key,
+                )),
+            },
+            addresses: local_peer
+                .public_addresses
+                .iter()
+                .filter(|addr| **addr != own_addr)
+                .take(MAX_PEER_ADDRESSES as usize)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Tell the peer about our alternate addresses.  Only done if we opened this connection and
+    /// both we and the peer support it; the peer answers with its own.
+    fn send_peer_addresses(
+        &mut self,
+        local_peer: &LocalPeer,
+        burnchain_view: &BurnchainView,
+    ) -> Result<(), net_error> {
+        if !self.stats.outbound || !self.wants_peer_addresses(local_peer) {
+            return Ok(());
+        }
+
+        let addrs_msg = self.sign_message(
+            burnchain_view,
+            &local_peer.private_key,
+            StacksMessageType::PeerAddresses(PeerAddressesData {
+                neighbors: vec![Self::local_peer_addresses(local_peer)],
+            }),
+        )?;
+        let handle = self.relay_signed_message(addrs_msg)?;
+        self.reply_handles.push_back(handle);
+        Ok(())
+    }
+
+    /// Handle an inbound PeerAddresses.  An entry for the peer itself replaces whatever
+    /// alternate addresses we had for it, and if the peer opened the connection, we answer with
+    /// our own.  Entries for other peers (i.e. sent alongside a Neighbors reply) are second-hand,
+    /// so they are only stored for peers we know no alternate addresses for yet, and only up to
+    /// MAX_SECOND_HAND_PEER_ADDRESSES peers' worth.  Either way, addresses are only stored for
+    /// peers in our frontier whose public key matches the entry's.
+    /// Called from the p2p network thread.
+    fn handle_peer_addresses(
+        &mut self,
+        local_peer: &LocalPeer,
+        peerdb: &mut PeerDB,
+        burnchain_view: &BurnchainView,
+        peer_addresses: &PeerAddressesData,
+    ) -> Result<Option<StacksMessage>, net_error> {
+        if !self.wants_peer_addresses(local_peer) {
+            debug!(
+                "{:?}: Got PeerAddresses, but multiple addresses were not negotiated",
+                &self
+            );
+            return Ok(None);
+        }
+
+        let own_addr = self.to_handshake_neighbor_address();
+        let mut first_hand = false;
+        let mut num_second_hand =
+            PeerDB::count_second_hand_peer_addresses(peerdb.conn(), self.peer_network_id)?;
+        let mut tx = peerdb.tx_begin().map_err(net_error::DBError)?;
+        for entry in peer_addresses.neighbors.iter() {
+            let is_own_entry = entry.addr == own_addr;
+            first_hand = first_hand || is_own_entry;
+
+            if Neighbor::from_neighbor_address(
+                &tx,
+                self.peer_network_id,
+                burnchain_view.burn_block_height,
+                &entry.addr,
+            )?
+            .is_none()
+            {
+                test_debug!(
+                    "{:?}: not storing addresses for {:?}, which is not in the frontier under this key",
+                    &self,
+                    &entry.addr
+                );
+                continue;
+            }
+
+            if is_own_entry {
+                debug!(
+                    "{:?}: peer is also reachable at {:?}",
+                    &self, &entry.addresses
+                );
+                PeerDB::set_peer_addresses(
+                    &mut tx,
+                    self.peer_network_id,
+                    &entry.addr,
+                    &entry.addresses,
+                    true,
+                )?;
+            } else if num_second_hand < MAX_SECOND_HAND_PEER_ADDRESSES
+                && PeerDB::get_peer_addresses_origin(
+                    &tx,
+                    self.peer_network_id,
+                    &entry.addr.addrbytes,
+                    entry.addr.port,
+                )?
+                .is_none()
+            {
+                PeerDB::set_peer_addresses(
+                    &mut tx,
+                    self.peer_network_id,
+                    &entry.addr,
+                    &entry.addresses,
+                    false,
+                )?;
+                num_second_hand += 1;
+            }
+        }
+        tx.commit()
+            .map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+
+        if first_hand && !self.stats.outbound {
+            let reply = StacksMessage::from_chain_view(
+                self.version,
+                self.network_id,
+                burnchain_view,
+                StacksMessageType::PeerAddresses(PeerAddressesData {
+                    neighbors: vec![Self::local_peer_addresses(local_peer)],
+                }),
+            );
+            return Ok(Some(reply));
+        }
+        Ok(None)
+    }
+
+    /// Would we and this peer both use the encrypted transport?
+    fn wants_encryption(&self, local_peer: &LocalPeer) -> bool {
+        Self::supports_encryption(local_peer.services)
+            && Self::supports_encryption(self.peer_services)
+    }
+
+    /// Begin setting up the encrypted transport by sending the peer our ephemeral key.  Only
+    /// done if we opened this connection, both we and the peer support it, and we haven't
+    /// already started.
+    fn start_encrypted_transport(
+        &mut self,
+        local_peer: &LocalPeer,
+        burnchain_view: &BurnchainView,
+    ) -> Result<(), net_error> {
+        if !self.stats.outbound || !self.wants_encryption(local_peer) {
+            return Ok(());
+        }
+        match self.transport {
+            TransportState::Plaintext => {}
+            _ => {
+                return Ok(());
+            }
+        }
+
+        let key_exchange = TransportKeyExchange::new(true);
+        let key_msg = self.sign_message(
+            burnchain_view,
+            &local_peer.private_key,
+            StacksMessageType::TransportKey(TransportKeyData {
+                ephemeral_key: key_exchange.public_key_buffer(),
+            }),
+        )?;
+        let handle = self.relay_signed_message(key_msg)?;
+        self.reply_handles.push_back(handle);
+
+        debug!("{:?}: Begin encrypted transport", &self);
+        self.transport = TransportState::KeySent(key_exchange);
+        Ok(())
+    }
+
+    /// Handle an inbound TransportKey.  If we opened the connection, this is the peer's answer to
+    /// ours, and we finish with a TransportReady; otherwise, we answer with our own key.  Either
+    /// way, everything we send after our reply is encrypted.
+    /// Called from the p2p network thread.
+    fn handle_transport_key(
+        &mut self,
+        local_peer: &LocalPeer,
+        burnchain_view: &BurnchainView,
+        preamble: &Preamble,
+        transport_key: &TransportKeyData,
+    ) -> Result<(), net_error> {
+        let peer_key = match self.connection.get_public_key() {
+            Some(pubk) => pubk,
+            None => {
+                return Err(net_error::InvalidMessage);
+            }
+        };
+        if !self.wants_encryption(local_peer) {
+            debug!(
+                "{:?}: Got TransportKey, but encryption was not negotiated",
+                &self
+            );
+            return Err(net_error::InvalidMessage);
+        }
+
+        match mem::replace(&mut self.transport, TransportState::Plaintext) {
+            TransportState::Plaintext if !self.stats.outbound => {
+                let key_exchange = TransportKeyExchange::new(false);
+                let (send_cipher, recv_cipher) = key_exchange.finish(
+                    &local_peer.private_key,
+                    &peer_key,
+                    &transport_key.ephemeral_key,
+                )?;
+
+                // the peer keeps sending in plaintext until its TransportReady
+                self.connection.resume_recv(None)?;
+
+                let reply = self.sign_reply(
+                    burnchain_view,
+                    &local_peer.private_key,
+                    StacksMessageType::TransportKey(TransportKeyData {
+                        ephemeral_key: key_exchange.public_key_buffer(),
+                    }),
+                    preamble.seq,
+                )?;
+                let handle = self.relay_signed_message_then_encrypt(reply, send_cipher)?;
+                self.reply_handles.push_back(handle);
+
+                self.transport = TransportState::KeyReplied(recv_cipher);
+            }
+            TransportState::KeySent(key_exchange) => {
+                let (send_cipher, recv_cipher) = key_exchange.finish(
+                    &local_peer.private_key,
+                    &peer_key,
+                    &transport_key.ephemeral_key,
+                )?;
+
+                // the peer encrypts everything after its TransportKey
+                self.connection.resume_recv(Some(recv_cipher))?;
+
+                let ready = self.sign_message(
+                    burnchain_view,
+                    &local_peer.private_key,
+                    StacksMessageType::TransportReady,
+                )?;
+                let handle = self.relay_signed_message_then_encrypt(ready, send_cipher)?;
+                self.reply_handles.push_back(handle);
+
+                debug!("{:?}: Transport is now encrypted", &self);
+                self.transport = TransportState::Encrypted;
+            }
+            _ => {
+                debug!("{:?}: Unexpected TransportKey", &self);
+                return Err(net_error::InvalidMessage);
+            }
+        }
         Ok(())
     }
 
+    /// Handle an inbound TransportReady: the peer encrypts everything after it.
+    /// Called from the p2p network thread.
+    fn handle_transport_ready(&mut self) -> Result<(), net_error> {
+        match mem::replace(&mut self.transport, TransportState::Plaintext) {
+            TransportState::KeyReplied(recv_cipher) => {
+                self.connection.resume_recv(Some(recv_cipher))?;
+
+                debug!("{:?}: Transport is now encrypted", &self);
+                self.transport = TransportState::Encrypted;
+                Ok(())
+            }
+            _ => {
+                debug!("{:?}: Unexpected TransportReady", &self);
+                Err(net_error::InvalidMessage)
+            }
+        }
+    }
+
     /// Reply to a ping with a pong.
     /// Called from the p2p network thread.
     fn handle_ping(
@@ -1291,6 +1717,9 @@ impl ConversationP2P {
             neighbors.clear();
         }
 
+        // v3 onion stand-in addresses are only dialable by us, since we know their names
+        neighbors.retain(|n| !n.addr.addrbytes.is_onion_standin());
+
         let neighbor_addrs: Vec<NeighborAddress> = neighbors
             .iter()
             .map(|n| NeighborAddress::from_neighbor(n))
@@ -1303,6 +1732,41 @@ impl ConversationP2P {
             neighbor_addrs.len()
         );
 
+        // if the peer can use them, follow up with the neighbors' alternate addresses (but only
+        // the ones they told us themselves, under the key we're vouching for)
+        let mut neighbor_addresses = vec![];
+        if self.wants_peer_addresses(local_peer) {
+            for (neighbor, neighbor_addr) in neighbors.iter().zip(neighbor_addrs.iter()) {
+                match PeerDB::get_peer_addresses_origin(
+                    peer_dbconn,
+                    neighbor.addr.network_id,
+                    &neighbor.addr.addrbytes,
+                    neighbor.addr.port,
+                )
+                .map_err(net_error::DBError)?
+                {
+                    Some((public_key_hash, true))
+                        if public_key_hash == neighbor_addr.public_key_hash => {}
+                    _ => {
+                        continue;
+                    }
+                }
+                let addresses = PeerDB::get_peer_addresses(
+                    peer_dbconn,
+                    neighbor.addr.network_id,
+                    &neighbor.addr.addrbytes,
+                    neighbor.addr.port,
+                )
+                .map_err(net_error::DBError)?;
+                if addresses.len() > 0 {
+                    neighbor_addresses.push(NeighborAddresses {
+                        addr: neighbor_addr.clone(),
+                        addresses,
+                    });
+                }
+            }
+        }
+
         let payload = StacksMessageType::Neighbors(NeighborsData {
             neighbors: neighbor_addrs,
         });
@@ -1315,6 +1779,27 @@ impl ConversationP2P {
             e
         })?;
 
+        if neighbor_addresses.len() > 0 {
+            let addrs_msg = self.sign_message(
+                chain_view,
+                &local_peer.private_key,
+                StacksMessageType::PeerAddresses(PeerAddressesData {
+                    neighbors: neighbor_addresses,
+                }),
+            )?;
+            match self.relay_signed_message(addrs_msg) {
+                Ok(handle) => {
+                    self.reply_handles.push_back(handle);
+                }
+                Err(e) => {
+                    debug!(
+                        "{:?}: Failed to send neighbors' alternate addresses: {:?}",
+                        &self, &e
+                    );
+                }
+            }
+        }
+
         Ok(reply_handle)
     }
 
@@ -1510,6 +1995,82 @@ impl ConversationP2P {
         self.sign_and_reply(local_peer, burnchain_view, preamble, response)
     }
 
+    /// Create a response to an inbound GetBlockTxns request, but unsigned.
+    /// Nacks if we don't have the block, or if any of the indexes are out of range.
+    pub fn make_getblocktxns_response(
+        local_peer: &LocalPeer,
+        chainstate: &StacksChainState,
+        get_block_txns: &GetBlockTxnsData,
+    ) -> Result<StacksMessageType, net_error> {
+        let block = match StacksChainState::load_block(
+            &chainstate.blocks_path,
+            &get_block_txns.consensus_hash,
+            &get_block_txns.block_hash,
+        ) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                debug!(
+                    "{:?}: No such block {}/{}",
+                    local_peer, &get_block_txns.consensus_hash, &get_block_txns.block_hash
+                );
+                return Ok(StacksMessageType::Nack(NackData::new(
+                    NackErrorCodes::NoSuchBlock,
+                )));
+            }
+            Err(e) => {
+                warn!(
+                    "{:?}: Failed to load block {}/{}: {:?}",
+                    local_peer, &get_block_txns.consensus_hash, &get_block_txns.block_hash, &e
+                );
+                return Err(net_error::ChainstateError(format!("{:?}", &e)));
+            }
+        };
+
+        let mut txs = Vec::with_capacity(get_block_txns.indexes.len());
+        for i in get_block_txns.indexes.iter() {
+            match block.txs.get(*i as usize) {
+                Some(tx) => txs.push(tx.clone()),
+                None => {
+                    debug!(
+                        "{:?}: Invalid GetBlockTxns for {}/{}: no tx {} (block has {})",
+                        local_peer,
+                        &get_block_txns.consensus_hash,
+                        &get_block_txns.block_hash,
+                        i,
+                        block.txs.len()
+                    );
+                    return Ok(StacksMessageType::Nack(NackData::new(
+                        NackErrorCodes::InvalidMessage,
+                    )));
+                }
+            }
+        }
+
+        Ok(StacksMessageType::BlockTxns(BlockTxnsData {
+            consensus_hash: get_block_txns.consensus_hash.clone(),
+            block_hash: get_block_txns.block_hash.clone(),
+            txs,
+        }))
+    }
+
+    /// Handle an inbound GetBlockTxns request, sent by a peer rebuilding a compact block we
+    /// pushed it.
+    /// Returns a reply handle to the generated message (possibly a nack)
+    fn handle_getblocktxns(
+        &mut self,
+        local_peer: &LocalPeer,
+        chainstate: &StacksChainState,
+        burnchain_view: &BurnchainView,
+        preamble: &Preamble,
+        get_block_txns: &GetBlockTxnsData,
+    ) -> Result<ReplyHandleP2P, net_error> {
+        monitoring::increment_msg_counter("p2p_get_block_txns".to_string());
+
+        let response =
+            ConversationP2P::make_getblocktxns_response(local_peer, chainstate, get_block_txns)?;
+        self.sign_and_reply(local_peer, burnchain_view, preamble, response)
+    }
+
     /// Create a response an inbound GetPoxInv request, but unsigned.
     /// Returns a reply handle to the generated message (possibly a nack)
     pub fn make_getpoxinv_response(
@@ -1696,6 +2257,8 @@ impl ConversationP2P {
         if !self.process_relayers(local_peer, preamble, &relayers) {
             debug!("Drop pushed blocks -- invalid relayers {:?}", &relayers);
             self.stats.msgs_err += 1;
+            self.stats
+                .add_reputation_event(ReputationEvent::InvalidMessage);
             return Err(net_error::InvalidMessage);
         }
 
@@ -1711,6 +2274,8 @@ impl ConversationP2P {
                 self.connection.options.max_block_push_bandwidth,
                 self.stats.get_block_push_bandwidth()
             );
+            self.stats
+                .add_reputation_event(ReputationEvent::BandwidthExceeded);
             return self
                 .reply_nack(local_peer, chain_view, preamble, NackErrorCodes::Throttled)
                 .and_then(|handle| Ok(Some(handle)));
@@ -1736,6 +2301,8 @@ impl ConversationP2P {
                 &relayers
             );
             self.stats.msgs_err += 1;
+            self.stats
+                .add_reputation_event(ReputationEvent::InvalidMessage);
             return Err(net_error::InvalidMessage);
         }
 
@@ -1747,6 +2314,8 @@ impl ConversationP2P {
                 > (self.connection.options.max_microblocks_push_bandwidth as f64)
         {
             debug!("Neighbor {:?} exceeded max microblocks-push bandwidth of {} bytes/sec (currently at {})", &self.to_neighbor_key(), self.connection.options.max_microblocks_push_bandwidth, self.stats.get_microblocks_push_bandwidth());
+            self.stats
+                .add_reputation_event(ReputationEvent::BandwidthExceeded);
             return self
                 .reply_nack(local_peer, chain_view, preamble, NackErrorCodes::Throttled)
                 .and_then(|handle| Ok(Some(handle)));
@@ -1771,6 +2340,8 @@ impl ConversationP2P {
                 &relayers
             );
             self.stats.msgs_err += 1;
+            self.stats
+                .add_reputation_event(ReputationEvent::InvalidMessage);
             return Err(net_error::InvalidMessage);
         }
 
@@ -1782,6 +2353,8 @@ impl ConversationP2P {
                 > (self.connection.options.max_transaction_push_bandwidth as f64)
         {
             debug!("Neighbor {:?} exceeded max transaction-push bandwidth of {} bytes/sec (currently at {})", &self.to_neighbor_key(), self.connection.options.max_transaction_push_bandwidth, self.stats.get_transaction_push_bandwidth());
+            self.stats
+                .add_reputation_event(ReputationEvent::BandwidthExceeded);
             return self
                 .reply_nack(local_peer, chain_view, preamble, NackErrorCodes::Throttled)
                 .and_then(|handle| Ok(Some(handle)));
@@ -1823,7 +2396,14 @@ impl ConversationP2P {
                 &msg.preamble,
                 get_blocks_inv,
             ),
-            StacksMessageType::Blocks(_) => {
+            StacksMessageType::GetBlockTxns(ref get_block_txns) => self.handle_getblocktxns(
+                local_peer,
+                chainstate,
+                chain_view,
+                &msg.preamble,
+                get_block_txns,
+            ),
+            StacksMessageType::Blocks(_) | StacksMessageType::CompactBlock(_) => {
                 monitoring::increment_stx_blocks_received_counter();
 
                 // not handled here, but do some accounting -- we can't receive blocks too often,
@@ -1841,6 +2421,21 @@ impl ConversationP2P {
                     }
                 }
             }
+            StacksMessageType::BlockTxns(_) => {
+                // the rest of a compact block -- counts towards the same limits as pushed blocks
+                match self.validate_blocks_push(
+                    local_peer,
+                    chain_view,
+                    &msg.preamble,
+                    msg.relayers.clone(),
+                )? {
+                    Some(handle) => Ok(handle),
+                    None => {
+                        // will forward upstream
+                        return Ok(Some(msg));
+                    }
+                }
+            }
             StacksMessageType::Microblocks(_) => {
                 monitoring::increment_stx_micro_blocks_received_counter();
 
@@ -1859,7 +2454,7 @@ impl ConversationP2P {
                     }
                 }
             }
-            StacksMessageType::Transaction(_) => {
+            StacksMessageType::Transaction(_) | StacksMessageType::StemTransaction(_) => {
                 monitoring::increment_txs_received_counter();
 
                 // not handled here, but do some accounting -- we can't receive too many
@@ -1895,6 +2490,11 @@ impl ConversationP2P {
         }
     }
 
+    /// Label under which this peer's traffic is reported to monitoring
+    pub fn peer_bandwidth_label(&self) -> String {
+        format!("{}", &self.peer_addrbytes.to_socketaddr(self.peer_port))
+    }
+
     /// Load data into our connection
     pub fn recv<R: Read>(&mut self, r: &mut R) -> Result<usize, net_error> {
         let mut total_recved = 0;
@@ -1906,6 +2506,11 @@ impl ConversationP2P {
                     if num_recved > 0 {
                         self.stats.last_recv_time = get_epoch_time_secs();
                         self.stats.bytes_rx += num_recved as u64;
+                        monitoring::update_p2p_peer_bandwidth(
+                            &self.peer_bandwidth_label(),
+                            "rx",
+                            num_recved as u64,
+                        );
                     } else {
                         break;
                     }
@@ -1941,6 +2546,11 @@ impl ConversationP2P {
                     if num_sent > 0 {
                         self.stats.last_send_time = get_epoch_time_secs();
                         self.stats.bytes_tx += num_sent as u64;
+                        monitoring::update_p2p_peer_bandwidth(
+                            &self.peer_bandwidth_label(),
+                            "tx",
+                            num_sent as u64,
+                        );
                     } else {
                         break;
                     }
@@ -2009,6 +2619,8 @@ impl ConversationP2P {
                         &self
                     );
                     self.stats.msgs_err += 1;
+                    self.stats
+                        .add_reputation_event(ReputationEvent::InvalidMessage);
                     self.stats.add_healthpoint(false);
                     return Err(e);
                 }
@@ -2049,7 +2661,7 @@ impl ConversationP2P {
             }
             StacksMessageType::HandshakeAccept(ref data) => {
                 test_debug!("{:?}: Got HandshakeAccept", &self);
-                self.handle_handshake_accept(&msg.preamble, data)
+                self.handle_handshake_accept(local_peer, burnchain_view, &msg.preamble, data)
                     .and_then(|_| Ok(None))
             }
             StacksMessageType::Ping(_) => {
@@ -2080,6 +2692,22 @@ impl ConversationP2P {
                 test_debug!("{:?}: Got NatPunchReply({})", &self, _m.nonce);
                 Ok(None)
             }
+            StacksMessageType::TransportKey(ref data) => {
+                test_debug!("{:?}: Got TransportKey", &self);
+                consume = true;
+                self.handle_transport_key(local_peer, burnchain_view, &msg.preamble, data)
+                    .and_then(|_| Ok(None))
+            }
+            StacksMessageType::TransportReady => {
+                test_debug!("{:?}: Got TransportReady", &self);
+                consume = true;
+                self.handle_transport_ready().and_then(|_| Ok(None))
+            }
+            StacksMessageType::PeerAddresses(ref data) => {
+                test_debug!("{:?}: Got PeerAddresses", &self);
+                consume = true;
+                self.handle_peer_addresses(local_peer, peerdb, burnchain_view, data)
+            }
             _ => {
                 test_debug!(
                     "{:?}: Got a data-plane message (type {})",
@@ -2121,7 +2749,7 @@ impl ConversationP2P {
             StacksMessageType::HandshakeAccept(ref data) => {
                 if solicited {
                     test_debug!("{:?}: Got unauthenticated HandshakeAccept", &self);
-                    self.handle_handshake_accept(&msg.preamble, data)
+                    self.handle_handshake_accept(local_peer, burnchain_view, &msg.preamble, data)
                         .and_then(|_| Ok(None))
                 } else {
                     test_debug!("{:?}: Unsolicited unauthenticated HandshakeAccept", &self);
@@ -2171,6 +2799,15 @@ impl ConversationP2P {
                 // it's okay to forward this back (i.e. don't consume)
                 Ok(None)
             }
+            StacksMessageType::TransportKey(_) | StacksMessageType::TransportReady => {
+                // the transport can only be switched once we know the peer's key
+                debug!(
+                    "{:?}: Got unauthenticated {}; dropping connection",
+                    &self,
+                    msg.payload.get_message_name()
+                );
+                return Err(net_error::InvalidMessage);
+            }
             _ => {
                 test_debug!(
                     "{:?}: Got unauthenticated message (type {}), will NACK",
@@ -2212,20 +2849,32 @@ impl ConversationP2P {
         header_cache: &mut BlockHeaderCache,
         burnchain_view: &BurnchainView,
     ) -> Result<Vec<StacksMessage>, net_error> {
-        let num_inbound = self.connection.inbox_len();
-        test_debug!("{:?}: {} messages pending", &self, num_inbound);
+        let _num_inbound = self.connection.inbox_len();
+        test_debug!("{:?}: {} messages pending", &self, _num_inbound);
 
         let mut unsolicited = vec![];
-        for _ in 0..num_inbound {
+
+        // NOTE: handling a message can add more messages to the inbox -- namely, the ones
+        // received after a message that switches the transport.
+        loop {
             let update_stats; // whether or not this message can count towards this peer's liveness stats
             let mut msg = match self.connection.next_inbox_message() {
                 None => {
-                    continue;
+                    break;
                 }
                 Some(m) => m,
             };
 
+            let msg_bytes = (PREAMBLE_ENCODED_SIZE as u64) + (msg.preamble.payload_len as u64);
+            self.stats
+                .add_message_recv_bytes(msg.payload.get_message_id(), msg_bytes);
+            monitoring::update_p2p_msg_bandwidth(msg.get_message_name(), "rx", msg_bytes);
+
             if !self.validate_inbound_message(&msg, burnchain_view)? {
+                if self.connection.protocol.pauses_transport(&msg) {
+                    // we won't know how to read whatever comes after it
+                    return Err(net_error::InvalidMessage);
+                }
                 continue;
             }
 
@@ -2802,6 +3451,159 @@ mod test {
                 Secp256k1PublicKey::from_private(&local_peer_2.private_key)
             );
             assert_eq!(convo_1.data_url, "http://peer2.com".into());
+
+            // both sides accounted for the bytes of each message type
+            let handshake_bytes = convo_1
+                .stats
+                .get_message_send_bytes(StacksMessageID::Handshake);
+            assert!(handshake_bytes > 0);
+            assert_eq!(
+                convo_2
+                    .stats
+                    .get_message_recv_bytes(StacksMessageID::Handshake),
+                handshake_bytes
+            );
+
+            let accept_bytes = convo_2
+                .stats
+                .get_message_send_bytes(StacksMessageID::HandshakeAccept);
+            assert!(accept_bytes > 0);
+            assert_eq!(
+                convo_1
+                    .stats
+                    .get_message_recv_bytes(StacksMessageID::HandshakeAccept),
+                accept_bytes
+            );
+        })
+    }
+
+    #[test]
+    fn convo_handshake_accept_alternate_address() {
+        with_timeout(100, || {
+            let conn_opts = ConnectionOptions::default();
+
+            let socketaddr_1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+            let socketaddr_2 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8081);
+
+            let burnchain = testing_burnchain_config();
+
+            let mut chain_view = BurnchainView {
+                burn_block_height: 12348,
+                burn_block_hash: BurnchainHeaderHash([0x11; 32]),
+                burn_stable_block_height: 12341,
+                burn_stable_block_hash: BurnchainHeaderHash([0x22; 32]),
+                last_burn_block_hashes: HashMap::new(),
+            };
+            chain_view.make_test_data();
+
+            let (mut peerdb_1, mut sortdb_1, pox_id_1, mut chainstate_1) = make_test_chain_dbs(
+                "convo_handshake_accept_alternate_address_1",
+                &burnchain,
+                0x9abcdef0,
+                12350,
+                "http://peer1.com".into(),
+                &vec![],
+                &vec![],
+            );
+            let (mut peerdb_2, mut sortdb_2, pox_id_2, mut chainstate_2) = make_test_chain_dbs(
+                "convo_handshake_accept_alternate_address_2",
+                &burnchain,
+                0x9abcdef0,
+                12351,
+                "http://peer2.com".into(),
+                &vec![],
+                &vec![],
+            );
+
+            db_setup(&mut peerdb_1, &mut sortdb_1, &socketaddr_1, &chain_view);
+            db_setup(&mut peerdb_2, &mut sortdb_2, &socketaddr_2, &chain_view);
+
+            let local_peer_1 = PeerDB::get_local_peer(&peerdb_1.conn()).unwrap();
+            let local_peer_2 = PeerDB::get_local_peer(&peerdb_2.conn()).unwrap();
+            let key_hash_2 = Hash160::from_node_public_key(&Secp256k1PublicKey::from_private(
+                &local_peer_2.private_key,
+            ));
+
+            // convo_1 dials peer 2 at an alternate address twice: once expecting some other
+            // key, and once expecting peer 2's key
+            for (expected_key_hash, accepted) in
+                [(Hash160([0xff; 20]), false), (key_hash_2, true)].iter()
+            {
+                let mut convo_1 = ConversationP2P::new(
+                    123,
+                    456,
+                    &burnchain,
+                    &socketaddr_2,
+                    &conn_opts,
+                    true,
+                    0,
+                    StacksEpoch::unit_test_pre_2_05(0),
+                );
+                let mut convo_2 = ConversationP2P::new(
+                    123,
+                    456,
+                    &burnchain,
+                    &socketaddr_1,
+                    &conn_opts,
+                    true,
+                    0,
+                    StacksEpoch::unit_test_pre_2_05(0),
+                );
+                convo_1.dialed_alternate_key_hash = Some(expected_key_hash.clone());
+
+                let handshake_data_1 = HandshakeData::from_local_peer(&local_peer_1);
+                let handshake_1 = convo_1
+                    .sign_message(
+                        &chain_view,
+                        &local_peer_1.private_key,
+                        StacksMessageType::Handshake(handshake_data_1),
+                    )
+                    .unwrap();
+                let mut rh_1 = convo_1.send_signed_request(handshake_1, 1000000).unwrap();
+
+                convo_send_recv(&mut convo_1, vec![&mut rh_1], &mut convo_2);
+                convo_2
+                    .chat(
+                        &local_peer_2,
+                        &mut peerdb_2,
+                        &sortdb_2,
+                        &pox_id_2,
+                        &mut chainstate_2,
+                        &mut BlockHeaderCache::new(),
+                        &chain_view,
+                    )
+                    .unwrap();
+
+                convo_send_recv(&mut convo_2, vec![&mut rh_1], &mut convo_1);
+                let res = convo_1.chat(
+                    &local_peer_1,
+                    &mut peerdb_1,
+                    &sortdb_1,
+                    &pox_id_1,
+                    &mut chainstate_1,
+                    &mut BlockHeaderCache::new(),
+                    &chain_view,
+                );
+
+                if *accepted {
+                    res.unwrap();
+                    assert!(convo_1.dialed_alternate_key_hash.is_none());
+                    assert_eq!(
+                        convo_1.connection.get_public_key().unwrap(),
+                        Secp256k1PublicKey::from_private(&local_peer_2.private_key)
+                    );
+                } else {
+                    match res {
+                        Err(net_error::InvalidHandshake) => {}
+                        _ => {
+                            assert!(false);
+                        }
+                    }
+
+                    // never took on the other peer's key
+                    assert!(convo_1.connection.get_public_key().is_none());
+                }
+            }
         })
     }
 
@@ -3372,6 +4174,202 @@ mod test {
         }
     }
 
+    /// Handshake two peers -- both of which support the encrypted transport if `encrypt` is true,
+    /// and only the first otherwise -- and verify that they switch to the encrypted transport
+    /// (or don't), and that messages still flow afterwards.
+    fn run_convo_encrypted_transport(test_name: &str, encrypt: bool) {
+        let conn_opts = ConnectionOptions::default();
+
+        let socketaddr_1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let socketaddr_2 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8081);
+
+        let burnchain = testing_burnchain_config();
+
+        let mut chain_view = BurnchainView {
+            burn_block_height: 12348,
+            burn_block_hash: BurnchainHeaderHash([0x11; 32]),
+            burn_stable_block_height: 12341,
+            burn_stable_block_hash: BurnchainHeaderHash([0x22; 32]),
+            last_burn_block_hashes: HashMap::new(),
+        };
+        chain_view.make_test_data();
+
+        let (mut peerdb_1, mut sortdb_1, pox_id_1, mut chainstate_1) = make_test_chain_dbs(
+            &format!("{}_1", test_name),
+            &burnchain,
+            0x9abcdef0,
+            12350,
+            "http://peer1.com".into(),
+            &vec![],
+            &vec![],
+        );
+        let (mut peerdb_2, mut sortdb_2, pox_id_2, mut chainstate_2) = make_test_chain_dbs(
+            &format!("{}_2", test_name),
+            &burnchain,
+            0x9abcdef0,
+            12351,
+            "http://peer2.com".into(),
+            &vec![],
+            &vec![],
+        );
+
+        db_setup(&mut peerdb_1, &mut sortdb_1, &socketaddr_1, &chain_view);
+        db_setup(&mut peerdb_2, &mut sortdb_2, &socketaddr_2, &chain_view);
+
+        let mut local_peer_1 = PeerDB::get_local_peer(&peerdb_1.conn()).unwrap();
+        let mut local_peer_2 = PeerDB::get_local_peer(&peerdb_2.conn()).unwrap();
+
+        local_peer_1.services |= ServiceFlags::ENCRYPTION as u16;
+        if encrypt {
+            local_peer_2.services |= ServiceFlags::ENCRYPTION as u16;
+        }
+
+        // convo_1 is outbound (i.e. it opened the connection), and convo_2 is inbound
+        let mut convo_1 = ConversationP2P::new(
+            123,
+            456,
+            &burnchain,
+            &socketaddr_2,
+            &conn_opts,
+            true,
+            0,
+            StacksEpoch::unit_test_pre_2_05(0),
+        );
+        let mut convo_2 = ConversationP2P::new(
+            123,
+            456,
+            &burnchain,
+            &socketaddr_1,
+            &conn_opts,
+            false,
+            0,
+            StacksEpoch::unit_test_pre_2_05(0),
+        );
+
+        let mut chat_1 = |convo_1: &mut ConversationP2P| {
+            convo_1
+                .chat(
+                    &local_peer_1,
+                    &mut peerdb_1,
+                    &sortdb_1,
+                    &pox_id_1,
+                    &mut chainstate_1,
+                    &mut BlockHeaderCache::new(),
+                    &chain_view,
+                )
+                .unwrap()
+        };
+        let mut chat_2 = |convo_2: &mut ConversationP2P| {
+            convo_2
+                .chat(
+                    &local_peer_2,
+                    &mut peerdb_2,
+                    &sortdb_2,
+                    &pox_id_2,
+                    &mut chainstate_2,
+                    &mut BlockHeaderCache::new(),
+                    &chain_view,
+                )
+                .unwrap()
+        };
+
+        // convo_1 handshakes with convo_2
+        let handshake_1 = convo_1
+            .sign_message(
+                &chain_view,
+                &local_peer_1.private_key,
+                StacksMessageType::Handshake(HandshakeData::from_local_peer(&local_peer_1)),
+            )
+            .unwrap();
+        let mut rh_handshake_1 = convo_1.send_signed_request(handshake_1, 1000000).unwrap();
+
+        convo_send_recv(&mut convo_1, vec![&mut rh_handshake_1], &mut convo_2);
+        let unhandled_2 = chat_2(&mut convo_2);
+        assert_eq!(unhandled_2.len(), 1);
+
+        // convo_1 gets the HandshakeAccept, and (if convo_2 supports it) starts the key exchange
+        convo_send_recv(&mut convo_2, vec![&mut rh_handshake_1], &mut convo_1);
+        let unhandled_1 = chat_1(&mut convo_1);
+        assert_eq!(unhandled_1.len(), 0);
+
+        match rh_handshake_1.recv(0).unwrap().payload {
+            StacksMessageType::HandshakeAccept(..) => {}
+            _ => panic!("Did not get a HandshakeAccept"),
+        }
+
+        // TransportKey from convo_1 to convo_2
+        convo_send_recv(&mut convo_1, vec![], &mut convo_2);
+        assert_eq!(chat_2(&mut convo_2).len(), 0);
+
+        // TransportKey from convo_2 to convo_1
+        convo_send_recv(&mut convo_2, vec![], &mut convo_1);
+        assert_eq!(chat_1(&mut convo_1).len(), 0);
+
+        // TransportReady from convo_1 to convo_2
+        convo_send_recv(&mut convo_1, vec![], &mut convo_2);
+        assert_eq!(chat_2(&mut convo_2).len(), 0);
+
+        assert_eq!(convo_1.is_encrypted(), encrypt);
+        assert_eq!(convo_2.is_encrypted(), encrypt);
+        assert_eq!(convo_1.connection.is_encrypted(), encrypt);
+        assert_eq!(convo_2.connection.is_encrypted(), encrypt);
+
+        // convo_1 pings convo_2.  The ping only appears on the wire in plaintext if the transport
+        // is not encrypted.
+        let ping_data_1 = PingData::new();
+        let ping_1 = convo_1
+            .sign_message(
+                &chain_view,
+                &local_peer_1.private_key,
+                StacksMessageType::Ping(ping_data_1.clone()),
+            )
+            .unwrap();
+        let ping_bytes = ping_1.serialize_to_vec();
+        let mut rh_ping_1 = convo_1.send_signed_request(ping_1, 1000000).unwrap();
+
+        let mut wire = vec![];
+        loop {
+            let flushed = rh_ping_1.try_flush().unwrap();
+            let nw = convo_1.send(&mut wire).unwrap();
+            if flushed && nw == 0 {
+                break;
+            }
+        }
+        assert_eq!(
+            wire.windows(ping_bytes.len()).any(|w| w == &ping_bytes[..]),
+            !encrypt
+        );
+
+        let (mut pipe_read, mut pipe_write) = Pipe::new();
+        pipe_read.set_nonblocking(true);
+        pipe_write.write_all(&wire).unwrap();
+        pipe_write.try_flush().unwrap();
+        convo_2.recv(&mut pipe_read).unwrap();
+        assert_eq!(chat_2(&mut convo_2).len(), 0);
+
+        // convo_2 pongs back
+        convo_send_recv(&mut convo_2, vec![&mut rh_ping_1], &mut convo_1);
+        assert_eq!(chat_1(&mut convo_1).len(), 0);
+
+        match rh_ping_1.recv(0).unwrap().payload {
+            StacksMessageType::Pong(ref data) => {
+                assert_eq!(data.nonce, ping_data_1.nonce);
+            }
+            _ => panic!("Did not get a Pong"),
+        }
+    }
+
+    #[test]
+    fn convo_encrypted_transport() {
+        run_convo_encrypted_transport("convo_encrypted_transport", true);
+    }
+
+    #[test]
+    fn convo_encrypted_transport_fallback() {
+        // convo_2 does not support encryption, so both sides stay in plaintext
+        run_convo_encrypted_transport("convo_encrypted_transport_fallback", false);
+    }
+
     #[test]
     fn convo_handshake_ping_loop() {
         let conn_opts = ConnectionOptions::default();
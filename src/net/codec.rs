@@ -478,6 +478,37 @@ impl StacksMessageCodec for BlocksData {
     }
 }
 
+impl StacksMessageCodec for BlockRangeEntry {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.consensus_hash)?;
+        write_next(fd, &self.parent_consensus_hash)?;
+        write_next(fd, &self.block)?;
+        write_next(fd, &self.confirmed_microblocks)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<BlockRangeEntry, codec_error> {
+        let consensus_hash = read_next(fd)?;
+        let parent_consensus_hash = read_next(fd)?;
+        let block = {
+            let mut bound_read = BoundReader::from_reader(fd, MAX_BLOCK_LEN as u64);
+            read_next(&mut bound_read)
+        }?;
+        let confirmed_microblocks: Vec<StacksMicroblock> = {
+            // loose upper-bound
+            let mut bound_read = BoundReader::from_reader(fd, MAX_MESSAGE_LEN as u64);
+            read_next(&mut bound_read)
+        }?;
+
+        Ok(BlockRangeEntry {
+            consensus_hash,
+            parent_consensus_hash,
+            block,
+            confirmed_microblocks,
+        })
+    }
+}
+
 impl StacksMessageCodec for MicroblocksData {
     fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
         write_next(fd, &self.index_anchor_block)?;
@@ -714,6 +745,197 @@ impl StacksMessageCodec for NatPunchData {
     }
 }
 
+impl StacksMessageCodec for TransportKeyData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.ephemeral_key)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TransportKeyData, codec_error> {
+        let ephemeral_key: StacksPublicKeyBuffer = read_next(fd)?;
+        Ok(TransportKeyData { ephemeral_key })
+    }
+}
+
+impl StacksMessageCodec for CompactBlockData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.consensus_hash)?;
+        write_next(fd, &self.header)?;
+        write_next(fd, &self.seed)?;
+        write_next(fd, &self.tx_tags)?;
+        write_next(fd, &self.prefilled_txs)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<CompactBlockData, codec_error> {
+        let consensus_hash: ConsensusHash = read_next(fd)?;
+        let header: StacksBlockHeader = read_next(fd)?;
+        let seed: [u8; 32] = read_next(fd)?;
+        let tx_tags: Vec<TxTag> = read_next_at_most(fd, COMPACT_BLOCK_MAX_TXS)?;
+        let prefilled_txs: Vec<StacksTransaction> = {
+            // loose upper-bound
+            let mut bound_read = BoundReader::from_reader(fd, MAX_MESSAGE_LEN as u64);
+            read_next_at_most(&mut bound_read, COMPACT_BLOCK_MAX_TXS)
+        }?;
+
+        if prefilled_txs.len() > tx_tags.len() {
+            return Err(codec_error::DeserializeError(
+                "Invalid CompactBlockData: more prefilled transactions than transactions"
+                    .to_string(),
+            ));
+        }
+
+        Ok(CompactBlockData {
+            consensus_hash,
+            header,
+            seed,
+            tx_tags,
+            prefilled_txs,
+        })
+    }
+}
+
+impl StacksMessageCodec for GetBlockTxnsData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.consensus_hash)?;
+        write_next(fd, &self.block_hash)?;
+        write_next(fd, &self.indexes)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<GetBlockTxnsData, codec_error> {
+        let consensus_hash: ConsensusHash = read_next(fd)?;
+        let block_hash: BlockHeaderHash = read_next(fd)?;
+        let indexes: Vec<u32> = read_next_at_most(fd, COMPACT_BLOCK_MAX_TXS)?;
+        Ok(GetBlockTxnsData {
+            consensus_hash,
+            block_hash,
+            indexes,
+        })
+    }
+}
+
+impl StacksMessageCodec for BlockTxnsData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.consensus_hash)?;
+        write_next(fd, &self.block_hash)?;
+        write_next(fd, &self.txs)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<BlockTxnsData, codec_error> {
+        let consensus_hash: ConsensusHash = read_next(fd)?;
+        let block_hash: BlockHeaderHash = read_next(fd)?;
+        let txs: Vec<StacksTransaction> = {
+            // loose upper-bound
+            let mut bound_read = BoundReader::from_reader(fd, MAX_MESSAGE_LEN as u64);
+            read_next_at_most(&mut bound_read, COMPACT_BLOCK_MAX_TXS)
+        }?;
+        Ok(BlockTxnsData {
+            consensus_hash,
+            block_hash,
+            txs,
+        })
+    }
+}
+
+/// Wire tags for each `PeerHost` variant
+const PEER_HOST_IP: u8 = 0x01;
+const PEER_HOST_DNS: u8 = 0x02;
+
+impl StacksMessageCodec for PeerHost {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        match *self {
+            PeerHost::IP(ref addrbytes, ref port) => {
+                write_next(fd, &PEER_HOST_IP)?;
+                write_next(fd, addrbytes)?;
+                write_next(fd, port)?;
+            }
+            PeerHost::DNS(ref name, ref port) => {
+                if name.len() == 0
+                    || name.len() > (MAX_PEER_HOSTNAME_LEN as usize)
+                    || !name.is_ascii()
+                {
+                    return Err(codec_error::SerializeError(format!(
+                        "Invalid peer hostname '{}'",
+                        name
+                    )));
+                }
+                write_next(fd, &PEER_HOST_DNS)?;
+                write_next(fd, &(name.len() as u8))?;
+                fd.write_all(name.as_bytes())
+                    .map_err(codec_error::WriteError)?;
+                write_next(fd, port)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<PeerHost, codec_error> {
+        let tag: u8 = read_next(fd)?;
+        match tag {
+            PEER_HOST_IP => {
+                let addrbytes: PeerAddress = read_next(fd)?;
+                let port: u16 = read_next(fd)?;
+                Ok(PeerHost::IP(addrbytes, port))
+            }
+            PEER_HOST_DNS => {
+                let len: u8 = read_next(fd)?;
+                if len == 0 || len > MAX_PEER_HOSTNAME_LEN {
+                    return Err(codec_error::DeserializeError(format!(
+                        "Invalid peer hostname length {}",
+                        len
+                    )));
+                }
+                let mut bytes = vec![0u8; len as usize];
+                fd.read_exact(&mut bytes).map_err(codec_error::ReadError)?;
+                let name = String::from_utf8(bytes).map_err(|_| {
+                    codec_error::DeserializeError("Peer hostname is not UTF-8".to_string())
+                })?;
+                if !name.is_ascii() {
+                    return Err(codec_error::DeserializeError(
+                        "Peer hostname is not ASCII".to_string(),
+                    ));
+                }
+                let port: u16 = read_next(fd)?;
+                Ok(PeerHost::DNS(name, port))
+            }
+            _ => Err(codec_error::DeserializeError(format!(
+                "Unknown peer host type {}",
+                tag
+            ))),
+        }
+    }
+}
+
+impl StacksMessageCodec for NeighborAddresses {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.addr)?;
+        write_next(fd, &self.addresses)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<NeighborAddresses, codec_error> {
+        let addr: NeighborAddress = read_next(fd)?;
+        let addresses: Vec<PeerHost> = read_next_at_most::<_, PeerHost>(fd, MAX_PEER_ADDRESSES)?;
+        Ok(NeighborAddresses { addr, addresses })
+    }
+}
+
+impl StacksMessageCodec for PeerAddressesData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
+        write_next(fd, &self.neighbors)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<PeerAddressesData, codec_error> {
+        // no more entries than there can be neighbors in a Neighbors reply
+        let neighbors: Vec<NeighborAddresses> =
+            read_next_at_most::<_, NeighborAddresses>(fd, MAX_NEIGHBORS_DATA_LEN)?;
+        Ok(PeerAddressesData { neighbors })
+    }
+}
+
 impl StacksMessageCodec for MemPoolSyncData {
     fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), codec_error> {
         match *self {
@@ -787,6 +1009,13 @@ impl StacksMessageType {
             StacksMessageType::Pong(ref _m) => StacksMessageID::Pong,
             StacksMessageType::NatPunchRequest(ref _m) => StacksMessageID::NatPunchRequest,
             StacksMessageType::NatPunchReply(ref _m) => StacksMessageID::NatPunchReply,
+            StacksMessageType::TransportKey(ref _m) => StacksMessageID::TransportKey,
+            StacksMessageType::TransportReady => StacksMessageID::TransportReady,
+            StacksMessageType::CompactBlock(ref _m) => StacksMessageID::CompactBlock,
+            StacksMessageType::GetBlockTxns(ref _m) => StacksMessageID::GetBlockTxns,
+            StacksMessageType::BlockTxns(ref _m) => StacksMessageID::BlockTxns,
+            StacksMessageType::PeerAddresses(ref _m) => StacksMessageID::PeerAddresses,
+            StacksMessageType::StemTransaction(ref _m) => StacksMessageID::StemTransaction,
         }
     }
 
@@ -811,6 +1040,13 @@ impl StacksMessageType {
             StacksMessageType::Pong(ref _m) => "Pong",
             StacksMessageType::NatPunchRequest(ref _m) => "NatPunchRequest",
             StacksMessageType::NatPunchReply(ref _m) => "NatPunchReply",
+            StacksMessageType::TransportKey(ref _m) => "TransportKey",
+            StacksMessageType::TransportReady => "TransportReady",
+            StacksMessageType::CompactBlock(ref _m) => "CompactBlock",
+            StacksMessageType::GetBlockTxns(ref _m) => "GetBlockTxns",
+            StacksMessageType::BlockTxns(ref _m) => "BlockTxns",
+            StacksMessageType::PeerAddresses(ref _m) => "PeerAddresses",
+            StacksMessageType::StemTransaction(ref _m) => "StemTransaction",
         }
     }
 
@@ -869,6 +1105,37 @@ impl StacksMessageType {
             StacksMessageType::NatPunchReply(ref m) => {
                 format!("NatPunchReply({},{}:{})", m.nonce, &m.addrbytes, m.port)
             }
+            StacksMessageType::TransportKey(ref m) => {
+                format!("TransportKey({})", &to_hex(&m.ephemeral_key.to_bytes()))
+            }
+            StacksMessageType::TransportReady => "TransportReady".to_string(),
+            StacksMessageType::CompactBlock(ref m) => format!(
+                "CompactBlock({}/{},{},{})",
+                &m.consensus_hash,
+                &m.header.block_hash(),
+                m.tx_tags.len(),
+                m.prefilled_txs.len()
+            ),
+            StacksMessageType::GetBlockTxns(ref m) => format!(
+                "GetBlockTxns({}/{},{:?})",
+                &m.consensus_hash, &m.block_hash, &m.indexes
+            ),
+            StacksMessageType::BlockTxns(ref m) => format!(
+                "BlockTxns({}/{},{:?})",
+                &m.consensus_hash,
+                &m.block_hash,
+                m.txs.iter().map(|tx| tx.txid()).collect::<Vec<Txid>>()
+            ),
+            StacksMessageType::PeerAddresses(ref m) => format!(
+                "PeerAddresses({:?})",
+                m.neighbors
+                    .iter()
+                    .map(|n| (&n.addr, &n.addresses))
+                    .collect::<Vec<_>>()
+            ),
+            StacksMessageType::StemTransaction(ref m) => {
+                format!("StemTransaction({})", m.txid())
+            }
         }
     }
 }
@@ -902,6 +1169,13 @@ impl StacksMessageCodec for StacksMessageID {
             x if x == StacksMessageID::Pong as u8 => StacksMessageID::Pong,
             x if x == StacksMessageID::NatPunchRequest as u8 => StacksMessageID::NatPunchRequest,
             x if x == StacksMessageID::NatPunchReply as u8 => StacksMessageID::NatPunchReply,
+            x if x == StacksMessageID::TransportKey as u8 => StacksMessageID::TransportKey,
+            x if x == StacksMessageID::TransportReady as u8 => StacksMessageID::TransportReady,
+            x if x == StacksMessageID::CompactBlock as u8 => StacksMessageID::CompactBlock,
+            x if x == StacksMessageID::GetBlockTxns as u8 => StacksMessageID::GetBlockTxns,
+            x if x == StacksMessageID::BlockTxns as u8 => StacksMessageID::BlockTxns,
+            x if x == StacksMessageID::PeerAddresses as u8 => StacksMessageID::PeerAddresses,
+            x if x == StacksMessageID::StemTransaction as u8 => StacksMessageID::StemTransaction,
             _ => {
                 return Err(codec_error::DeserializeError(
                     "Unknown message ID".to_string(),
@@ -935,6 +1209,13 @@ impl StacksMessageCodec for StacksMessageType {
             StacksMessageType::Pong(ref m) => write_next(fd, m)?,
             StacksMessageType::NatPunchRequest(ref nonce) => write_next(fd, nonce)?,
             StacksMessageType::NatPunchReply(ref m) => write_next(fd, m)?,
+            StacksMessageType::TransportKey(ref m) => write_next(fd, m)?,
+            StacksMessageType::TransportReady => {}
+            StacksMessageType::CompactBlock(ref m) => write_next(fd, m)?,
+            StacksMessageType::GetBlockTxns(ref m) => write_next(fd, m)?,
+            StacksMessageType::BlockTxns(ref m) => write_next(fd, m)?,
+            StacksMessageType::PeerAddresses(ref m) => write_next(fd, m)?,
+            StacksMessageType::StemTransaction(ref m) => write_next(fd, m)?,
         }
         Ok(())
     }
@@ -1012,6 +1293,31 @@ impl StacksMessageCodec for StacksMessageType {
                 let m: NatPunchData = read_next(fd)?;
                 StacksMessageType::NatPunchReply(m)
             }
+            StacksMessageID::TransportKey => {
+                let m: TransportKeyData = read_next(fd)?;
+                StacksMessageType::TransportKey(m)
+            }
+            StacksMessageID::TransportReady => StacksMessageType::TransportReady,
+            StacksMessageID::CompactBlock => {
+                let m: CompactBlockData = read_next(fd)?;
+                StacksMessageType::CompactBlock(m)
+            }
+            StacksMessageID::GetBlockTxns => {
+                let m: GetBlockTxnsData = read_next(fd)?;
+                StacksMessageType::GetBlockTxns(m)
+            }
+            StacksMessageID::BlockTxns => {
+                let m: BlockTxnsData = read_next(fd)?;
+                StacksMessageType::BlockTxns(m)
+            }
+            StacksMessageID::PeerAddresses => {
+                let m: PeerAddressesData = read_next(fd)?;
+                StacksMessageType::PeerAddresses(m)
+            }
+            StacksMessageID::StemTransaction => {
+                let m: StacksTransaction = read_next(fd)?;
+                StacksMessageType::StemTransaction(m)
+            }
             StacksMessageID::Reserved => {
                 return Err(codec_error::DeserializeError(
                     "Unsupported message ID 'reserved'".to_string(),
@@ -1285,6 +1591,14 @@ impl ProtocolFamily for StacksP2P {
     ) -> Result<(), net_error> {
         message.consensus_serialize(fd).map_err(|e| e.into())
     }
+
+    /// Messages that set up the encrypted transport change how the bytes after them are read
+    fn pauses_transport(&mut self, message: &StacksMessage) -> bool {
+        match message.payload {
+            StacksMessageType::TransportKey(_) | StacksMessageType::TransportReady => true,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1295,6 +1609,11 @@ pub mod test {
 
     use super::*;
 
+    use crate::chainstate::stacks::test::make_codec_test_block;
+    use crate::chainstate::stacks::StacksMicroblockHeader;
+    use crate::chainstate::stacks::TransactionAnchorMode;
+    use stacks_common::util::hash::{MerkleTree, Sha512Trunc256Sum};
+
     fn check_overflow<T>(r: Result<T, net_error>) -> bool {
         match r {
             Ok(_) => {
@@ -1933,6 +2252,193 @@ pub mod test {
         check_codec_and_corruption::<NatPunchData>(&data, &bytes);
     }
 
+    #[test]
+    fn codec_TransportKey() {
+        let data = TransportKeyData {
+            ephemeral_key: StacksPublicKeyBuffer([0x02; 33]),
+        };
+        let bytes = vec![
+            // ephemeral key
+            0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02,
+            0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02,
+            0x02, 0x02, 0x02, 0x02, 0x02,
+        ];
+
+        check_codec_and_corruption::<TransportKeyData>(&data, &bytes);
+    }
+
+    #[test]
+    fn codec_GetBlockTxns() {
+        let data = GetBlockTxnsData {
+            consensus_hash: ConsensusHash([0x11; 20]),
+            block_hash: BlockHeaderHash([0x22; 32]),
+            indexes: vec![1, 0x01020304],
+        };
+        let bytes = vec![
+            // consensus hash
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, // block hash
+            0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22,
+            0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0x22,
+            0x22, 0x22, 0x22, 0x22, // length
+            0x00, 0x00, 0x00, 0x02, // indexes
+            0x00, 0x00, 0x00, 0x01, 0x01, 0x02, 0x03, 0x04,
+        ];
+
+        check_codec_and_corruption::<GetBlockTxnsData>(&data, &bytes);
+    }
+
+    #[test]
+    fn codec_PeerAddressesData() {
+        let data = PeerAddressesData {
+            neighbors: vec![NeighborAddresses {
+                addr: NeighborAddress {
+                    addrbytes: PeerAddress([
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
+                        0x01, 0x02, 0x03, 0x04,
+                    ]),
+                    port: 12345,
+                    public_key_hash: Hash160([0x11; 20]),
+                },
+                addresses: vec![
+                    PeerHost::IP(PeerAddress([0x20; 16]), 0x0102),
+                    PeerHost::DNS("a.io".to_string(), 0x0304),
+                ],
+            }],
+        };
+        let bytes = vec![
+            // length
+            0x00, 0x00, 0x00, 0x01, // addrbytes
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x01, 0x02,
+            0x03, 0x04, // port
+            0x30, 0x39, // public key hash
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, // number of addresses
+            0x00, 0x00, 0x00, 0x02, // IP tag
+            0x01, // IP address
+            0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+            0x20, 0x20, // port
+            0x01, 0x02, // DNS tag
+            0x02, // hostname length
+            0x04, // hostname
+            0x61, 0x2e, 0x69, 0x6f, // port
+            0x03, 0x04,
+        ];
+
+        check_codec_and_corruption::<PeerAddressesData>(&data, &bytes);
+    }
+
+    #[test]
+    fn codec_PeerHost_invalid() {
+        // unknown tag
+        assert!(PeerHost::consensus_deserialize(&mut &[0x03, 0x00, 0x01][..]).is_err());
+
+        // empty hostname
+        assert!(PeerHost::consensus_deserialize(&mut &[0x02, 0x00, 0x00, 0x01][..]).is_err());
+
+        // overlong hostname
+        let name = "a".repeat((MAX_PEER_HOSTNAME_LEN as usize) + 1);
+        let mut bytes = vec![];
+        assert!(PeerHost::DNS(name, 80)
+            .consensus_serialize(&mut bytes)
+            .is_err());
+
+        // too many addresses
+        let mut bytes = vec![];
+        let addrs = NeighborAddresses {
+            addr: NeighborAddress {
+                addrbytes: PeerAddress([0x01; 16]),
+                port: 12345,
+                public_key_hash: Hash160([0x11; 20]),
+            },
+            addresses: (0..(MAX_PEER_ADDRESSES + 1))
+                .map(|i| PeerHost::IP(PeerAddress([0x02; 16]), i as u16))
+                .collect(),
+        };
+        addrs.consensus_serialize(&mut bytes).unwrap();
+        assert!(NeighborAddresses::consensus_deserialize(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn codec_CompactBlock() {
+        let block = make_codec_test_block(5);
+        let data = CompactBlockData {
+            consensus_hash: ConsensusHash([0x11; 20]),
+            header: block.header.clone(),
+            seed: [0x33; 32],
+            tx_tags: vec![TxTag([0x44; 8]), TxTag([0x55; 8])],
+            prefilled_txs: vec![block.txs[0].clone()],
+        };
+
+        let mut bytes = vec![];
+        data.consensus_hash.consensus_serialize(&mut bytes).unwrap();
+        block.header.consensus_serialize(&mut bytes).unwrap();
+        bytes.append(&mut vec![0x33; 32]);
+        bytes.append(&mut vec![0x00, 0x00, 0x00, 0x02]);
+        bytes.append(&mut vec![0x44; 8]);
+        bytes.append(&mut vec![0x55; 8]);
+        bytes.append(&mut vec![0x00, 0x00, 0x00, 0x01]);
+        block.txs[0].consensus_serialize(&mut bytes).unwrap();
+
+        check_codec_and_corruption::<CompactBlockData>(&data, &bytes);
+
+        // can't prefill more txs than the block has
+        let mut bad_data = data.clone();
+        bad_data.prefilled_txs = block.txs[0..3].to_vec();
+        let bad_bytes = bad_data.serialize_to_vec();
+        assert!(CompactBlockData::consensus_deserialize(&mut &bad_bytes[..]).is_err());
+    }
+
+    #[test]
+    fn codec_BlockRangeEntry() {
+        let block = make_codec_test_block(5);
+        let microblocks: Vec<StacksMicroblock> = (0..2)
+            .map(|i| {
+                let mut tx = block.txs[i + 1].clone();
+                tx.anchor_mode = TransactionAnchorMode::Any;
+                let txid_vecs = vec![tx.txid().as_bytes().to_vec()];
+                let merkle_tree = MerkleTree::<Sha512Trunc256Sum>::new(&txid_vecs);
+                StacksMicroblock {
+                    header: StacksMicroblockHeader {
+                        version: 0x12,
+                        sequence: 1 - i as u16,
+                        prev_block: BlockHeaderHash([0x33; 32]),
+                        tx_merkle_root: merkle_tree.root(),
+                        signature: MessageSignature::empty(),
+                    },
+                    txs: vec![tx],
+                }
+            })
+            .collect();
+
+        let data = BlockRangeEntry {
+            consensus_hash: ConsensusHash([0x11; 20]),
+            parent_consensus_hash: ConsensusHash([0x22; 20]),
+            block: block.clone(),
+            confirmed_microblocks: microblocks.clone(),
+        };
+
+        let mut bytes = vec![];
+        bytes.append(&mut vec![0x11; 20]);
+        bytes.append(&mut vec![0x22; 20]);
+        block.consensus_serialize(&mut bytes).unwrap();
+        microblocks.consensus_serialize(&mut bytes).unwrap();
+
+        check_codec_and_corruption::<BlockRangeEntry>(&data, &bytes);
+
+        // no confirmed microblocks
+        let mut data = data.clone();
+        data.confirmed_microblocks = vec![];
+
+        let mut bytes = vec![];
+        bytes.append(&mut vec![0x11; 20]);
+        bytes.append(&mut vec![0x22; 20]);
+        block.consensus_serialize(&mut bytes).unwrap();
+        bytes.append(&mut vec![0x00, 0x00, 0x00, 0x00]);
+
+        check_codec_and_corruption::<BlockRangeEntry>(&data, &bytes);
+    }
+
     #[test]
     fn codec_StacksMessage() {
         let payloads: Vec<StacksMessageType> = vec![
@@ -2049,6 +2555,41 @@ pub mod test {
                 port: 12345,
                 nonce: 0x12345678,
             }),
+            StacksMessageType::TransportKey(TransportKeyData {
+                ephemeral_key: StacksPublicKeyBuffer([0x03; 33]),
+            }),
+            StacksMessageType::TransportReady,
+            StacksMessageType::CompactBlock(CompactBlockData {
+                consensus_hash: ConsensusHash([0x11; 20]),
+                header: make_codec_test_block(1).header,
+                seed: [0x33; 32],
+                tx_tags: vec![TxTag([0x44; 8])],
+                prefilled_txs: vec![],
+            }),
+            StacksMessageType::GetBlockTxns(GetBlockTxnsData {
+                consensus_hash: ConsensusHash([0x11; 20]),
+                block_hash: BlockHeaderHash([0x22; 32]),
+                indexes: vec![0, 1, 2],
+            }),
+            StacksMessageType::BlockTxns(BlockTxnsData {
+                consensus_hash: ConsensusHash([0x11; 20]),
+                block_hash: BlockHeaderHash([0x22; 32]),
+                txs: make_codec_test_block(3).txs,
+            }),
+            StacksMessageType::PeerAddresses(PeerAddressesData {
+                neighbors: vec![NeighborAddresses {
+                    addr: NeighborAddress {
+                        addrbytes: PeerAddress([0x01; 16]),
+                        port: 12345,
+                        public_key_hash: Hash160([0x11; 20]),
+                    },
+                    addresses: vec![
+                        PeerHost::IP(PeerAddress([0x02; 16]), 23456),
+                        PeerHost::DNS("seed.example.com".to_string(), 20444),
+                    ],
+                }],
+            }),
+            StacksMessageType::StemTransaction(make_codec_test_block(1).txs[0].clone()),
         ];
 
         let mut maximal_relayers: Vec<RelayData> = vec![];
@@ -0,0 +1,773 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020-2022 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compact block relay.
+//!
+//! When two peers both advertise `ServiceFlags::COMPACT_BLOCKS`, a pushed block is sent as a
+//! `CompactBlock` instead of a `Blocks` message.  A compact block carries the block header, a
+//! random seed, and one 8-byte `TxTag` per transaction, plus the block's coinbase in full.  Most
+//! of a block's transactions will have already reached the recipient through the mempool, so the
+//! recipient matches the tags against its mempool and asks the sender for whatever is left with
+//! a `GetBlockTxns`.  Once it has every transaction, it checks them against the header's Merkle
+//! root and hands the block to the relayer as though it had been pushed whole.
+//!
+//! If the sender doesn't answer in time, or the rebuilt block doesn't match its header (e.g.
+//! because of a tag collision), the recipient falls back to treating the compact block as a
+//! `BlocksAvailable` announcement, so the block downloader fetches the block instead.
+
+use std::collections::HashMap;
+use std::mem;
+
+use rand::thread_rng;
+use rand::RngCore;
+
+use crate::chainstate::burn::db::sortdb::SortitionDB;
+use crate::chainstate::stacks::db::StacksChainState;
+use crate::chainstate::stacks::TransactionPayload;
+use crate::chainstate::stacks::{StacksBlock, StacksBlockHeader, StacksTransaction};
+use crate::core::mempool::{MemPoolDB, TxTag};
+use crate::net::chat::ConversationP2P;
+use crate::net::p2p::PeerNetwork;
+use crate::net::{
+    BlockTxnsData, BlocksAvailableData, BlocksData, BlocksDatum, CompactBlockData,
+    GetBlockTxnsData, NeighborKey, NetworkResult, StacksMessageType,
+};
+use crate::types::chainstate::StacksBlockId;
+use crate::util_lib::db::Error as db_error;
+
+use stacks_common::types::chainstate::{BlockHeaderHash, ConsensusHash};
+use stacks_common::util::get_epoch_time_secs;
+use stacks_common::util::hash::{MerkleTree, Sha512Trunc256Sum};
+
+impl CompactBlockData {
+    /// Describe a block we're about to push to a peer.  Coinbases are sent in full, since the
+    /// recipient can't have them in its mempool.
+    pub fn from_block(consensus_hash: &ConsensusHash, block: &StacksBlock) -> CompactBlockData {
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+
+        let tx_tags = block
+            .txs
+            .iter()
+            .map(|tx| TxTag::from(&seed, &tx.txid()))
+            .collect();
+
+        let prefilled_txs = block
+            .txs
+            .iter()
+            .filter(|tx| match tx.payload {
+                TransactionPayload::Coinbase(..) => true,
+                _ => false,
+            })
+            .cloned()
+            .collect();
+
+        CompactBlockData {
+            consensus_hash: consensus_hash.clone(),
+            header: block.header.clone(),
+            seed,
+            tx_tags,
+            prefilled_txs,
+        }
+    }
+
+    pub fn index_block_hash(&self) -> StacksBlockId {
+        StacksBlockHeader::make_index_block_hash(&self.consensus_hash, &self.header.block_hash())
+    }
+}
+
+/// A block announced to us as a `CompactBlock`, which we're still rebuilding
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingCompactBlock {
+    pub consensus_hash: ConsensusHash,
+    pub header: StacksBlockHeader,
+    seed: [u8; 32],
+    tx_tags: Vec<TxTag>,
+    txs: Vec<Option<StacksTransaction>>,
+    /// the conversation that sent us the compact block
+    pub event_id: usize,
+    pub neighbor_key: NeighborKey,
+    /// indexes of the transactions we asked the sender for, and when we asked
+    pub requested: Vec<u32>,
+    pub requested_at: u64,
+}
+
+impl PendingCompactBlock {
+    pub fn new(
+        data: &CompactBlockData,
+        event_id: usize,
+        neighbor_key: NeighborKey,
+    ) -> PendingCompactBlock {
+        let mut pending = PendingCompactBlock {
+            consensus_hash: data.consensus_hash.clone(),
+            header: data.header.clone(),
+            seed: data.seed.clone(),
+            tx_tags: data.tx_tags.clone(),
+            txs: vec![None; data.tx_tags.len()],
+            event_id,
+            neighbor_key,
+            requested: vec![],
+            requested_at: 0,
+        };
+        pending.fill_from_txs(data.prefilled_txs.iter());
+        pending
+    }
+
+    pub fn block_hash(&self) -> BlockHeaderHash {
+        self.header.block_hash()
+    }
+
+    pub fn index_block_hash(&self) -> StacksBlockId {
+        StacksBlockHeader::make_index_block_hash(&self.consensus_hash, &self.header.block_hash())
+    }
+
+    /// Put each of the given transactions into every still-empty slot whose tag it matches.
+    /// Returns the number of slots filled.
+    pub fn fill_from_txs<'a, I>(&mut self, txs: I) -> usize
+    where
+        I: Iterator<Item = &'a StacksTransaction>,
+    {
+        let wanted = self.wanted_tags();
+        let mut filled = 0;
+        for tx in txs {
+            let tag = TxTag::from(&self.seed, &tx.txid());
+            if let Some(indexes) = wanted.get(&tag) {
+                for i in indexes.iter() {
+                    if self.txs[*i].is_none() {
+                        self.txs[*i] = Some(tx.clone());
+                        filled += 1;
+                    }
+                }
+            }
+        }
+        filled
+    }
+
+    /// Fill in as many transactions as we can from the mempool.
+    /// Returns the number of slots filled.
+    pub fn fill_from_mempool(&mut self, mempool: &MemPoolDB) -> Result<usize, db_error> {
+        let wanted = self.wanted_tags();
+        if wanted.len() == 0 {
+            return Ok(0);
+        }
+
+        let mut filled = 0;
+        for txid in MemPoolDB::get_all_txids(mempool.conn())?.into_iter() {
+            let tag = TxTag::from(&self.seed, &txid);
+            let indexes = match wanted.get(&tag) {
+                Some(indexes) => indexes,
+                None => {
+                    continue;
+                }
+            };
+            let tx = match MemPoolDB::get_tx(mempool.conn(), &txid)? {
+                Some(tx_info) => tx_info.tx,
+                None => {
+                    continue;
+                }
+            };
+            for i in indexes.iter() {
+                if self.txs[*i].is_none() {
+                    self.txs[*i] = Some(tx.clone());
+                    filled += 1;
+                }
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Fill in the transactions we asked the sender for.  They must be in the order we asked for
+    /// them, and each must match its tag.
+    /// Returns false (and fills nothing) if they don't.
+    pub fn fill_requested(&mut self, txs: &[StacksTransaction]) -> bool {
+        if txs.len() != self.requested.len() {
+            return false;
+        }
+        for (i, tx) in self.requested.iter().zip(txs.iter()) {
+            let i = *i as usize;
+            if i >= self.tx_tags.len() || self.tx_tags[i] != TxTag::from(&self.seed, &tx.txid()) {
+                return false;
+            }
+        }
+        for (i, tx) in self.requested.iter().zip(txs.iter()) {
+            self.txs[*i as usize] = Some(tx.clone());
+        }
+        self.requested.clear();
+        true
+    }
+
+    /// Indexes of the transactions we don't have yet
+    pub fn missing(&self) -> Vec<u32> {
+        self.txs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, tx_opt)| match tx_opt {
+                Some(_) => None,
+                None => Some(i as u32),
+            })
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.txs.iter().all(|tx_opt| tx_opt.is_some())
+    }
+
+    /// Assemble the block.  Returns None if we're missing transactions, or if the transactions we
+    /// have don't match the header's Merkle root.
+    pub fn into_block(self) -> Option<StacksBlock> {
+        if !self.is_complete() {
+            return None;
+        }
+        let txs: Vec<StacksTransaction> = self.txs.into_iter().map(|tx| tx.unwrap()).collect();
+
+        let txid_vecs = txs.iter().map(|tx| tx.txid().as_bytes().to_vec()).collect();
+        let merkle_tree = MerkleTree::<Sha512Trunc256Sum>::new(&txid_vecs);
+        if merkle_tree.root() != self.header.tx_merkle_root {
+            return None;
+        }
+
+        Some(StacksBlock {
+            header: self.header,
+            txs,
+        })
+    }
+
+    /// Map each tag we still need a transaction for to the slots it goes into
+    fn wanted_tags(&self) -> HashMap<TxTag, Vec<usize>> {
+        let mut wanted: HashMap<TxTag, Vec<usize>> = HashMap::new();
+        for (i, tag) in self.tx_tags.iter().enumerate() {
+            if self.txs[i].is_none() {
+                wanted.entry(tag.clone()).or_insert(vec![]).push(i);
+            }
+        }
+        wanted
+    }
+}
+
+/// Compact block relay state for the peer network
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBlockState {
+    /// `CompactBlock` and `BlockTxns` messages received since the last pass, keyed by the event
+    /// they arrived on
+    pub inbound: Vec<(usize, StacksMessageType)>,
+    /// blocks we've asked for missing transactions for
+    pub pending: HashMap<StacksBlockId, PendingCompactBlock>,
+}
+
+impl CompactBlockState {
+    pub fn new() -> CompactBlockState {
+        CompactBlockState {
+            inbound: vec![],
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl PeerNetwork {
+    /// Do we and the given neighbor both speak compact block relay?
+    pub fn supports_compact_blocks(&self, neighbor_key: &NeighborKey) -> bool {
+        if !ConversationP2P::supports_compact_blocks(self.local_peer.services) {
+            return false;
+        }
+        match self.events.get(neighbor_key) {
+            Some(event_id) => match self.peers.get(event_id) {
+                Some(convo) => ConversationP2P::supports_compact_blocks(convo.peer_services),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Get the payload to send a neighbor in place of the given one.  A `Blocks` payload with a
+    /// single block is sent as a `CompactBlock` to neighbors that support it; everything else is
+    /// sent as-is.
+    pub fn compact_block_payload(
+        &self,
+        neighbor_key: &NeighborKey,
+        payload: &StacksMessageType,
+    ) -> StacksMessageType {
+        if let StacksMessageType::Blocks(ref blocks_data) = payload {
+            if blocks_data.blocks.len() == 1 && self.supports_compact_blocks(neighbor_key) {
+                let BlocksDatum(ref consensus_hash, ref block) = blocks_data.blocks[0];
+                return StacksMessageType::CompactBlock(CompactBlockData::from_block(
+                    consensus_hash,
+                    block,
+                ));
+            }
+        }
+        payload.clone()
+    }
+
+    /// Handle an unsolicited CompactBlock.
+    /// Queue it up for reconstruction if it's for a block we need from a winning sortition.
+    /// Returns whether or not to buffer it (if buffer is true).
+    /// Mask errors.
+    pub(crate) fn handle_unsolicited_CompactBlock(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        event_id: usize,
+        compact_block: &CompactBlockData,
+        buffer: bool,
+    ) -> bool {
+        let (remote_neighbor_key, remote_is_authenticated) = match self.peers.get(&event_id) {
+            Some(convo) => (convo.to_neighbor_key(), convo.is_authenticated()),
+            None => {
+                test_debug!(
+                    "{:?}: No such neighbor event={}",
+                    &self.local_peer,
+                    event_id
+                );
+                return false;
+            }
+        };
+
+        if !remote_is_authenticated {
+            // drop -- a correct peer will have authenticated before sending this message
+            test_debug!(
+                "{:?}: Drop unauthenticated CompactBlock from {:?}",
+                &self.local_peer,
+                &remote_neighbor_key
+            );
+            return false;
+        }
+
+        let consensus_hash = &compact_block.consensus_hash;
+        let block_hash = compact_block.header.block_hash();
+
+        debug!(
+            "{:?}: Process CompactBlock({}/{}) from {:?} with {} txs",
+            &self.local_peer,
+            consensus_hash,
+            &block_hash,
+            &remote_neighbor_key,
+            compact_block.tx_tags.len()
+        );
+
+        let sn = match SortitionDB::get_block_snapshot_consensus(&sortdb.conn(), consensus_hash) {
+            Ok(Some(sn)) => sn,
+            Ok(None) => {
+                if buffer {
+                    debug!(
+                        "{:?}: Will buffer unsolicited CompactBlock({}/{}) -- consensus hash not (yet) recognized",
+                        &self.local_peer, consensus_hash, &block_hash
+                    );
+                }
+                return buffer;
+            }
+            Err(e) => {
+                info!(
+                    "{:?}: Failed to query block snapshot for {}: {:?}",
+                    &self.local_peer, consensus_hash, &e
+                );
+                return false;
+            }
+        };
+
+        if !sn.pox_valid {
+            info!(
+                "{:?}: Failed to query snapshot for {}: not on the valid PoX fork",
+                &self.local_peer, consensus_hash
+            );
+            return false;
+        }
+
+        if sn.winning_stacks_block_hash != block_hash {
+            info!(
+                "{:?}: Ignoring compact block {} -- winning block was {} (sortition: {})",
+                &self.local_peer, &block_hash, sn.winning_stacks_block_hash, sn.sortition
+            );
+            return false;
+        }
+
+        match PeerNetwork::need_block_or_microblock_stream(
+            sortdb,
+            chainstate,
+            consensus_hash,
+            false,
+        ) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(
+                    "{:?}: Already have block {}/{}; ignoring compact block",
+                    &self.local_peer, consensus_hash, &block_hash
+                );
+                return false;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to determine if we need block for consensus hash {}: {:?}",
+                    consensus_hash, &e
+                );
+                return false;
+            }
+        }
+
+        self.compact_blocks.inbound.push((
+            event_id,
+            StacksMessageType::CompactBlock(compact_block.clone()),
+        ));
+        false
+    }
+
+    /// Handle an unsolicited BlockTxns by queuing it up for the next compact block pass.
+    pub(crate) fn handle_unsolicited_BlockTxns(&mut self, event_id: usize, txns: &BlockTxnsData) {
+        self.compact_blocks
+            .inbound
+            .push((event_id, StacksMessageType::BlockTxns(txns.clone())));
+    }
+
+    /// Stop trying to rebuild a compact block, and have the downloader fetch it from the peer
+    /// that announced it instead.
+    fn compact_block_fallback(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        event_id: usize,
+        consensus_hash: &ConsensusHash,
+        ibd: bool,
+    ) {
+        let sn = match SortitionDB::get_block_snapshot_consensus(&sortdb.conn(), consensus_hash) {
+            Ok(Some(sn)) => sn,
+            Ok(None) => {
+                return;
+            }
+            Err(e) => {
+                info!(
+                    "{:?}: Failed to query block snapshot for {}: {:?}",
+                    &self.local_peer, consensus_hash, &e
+                );
+                return;
+            }
+        };
+        let available = BlocksAvailableData {
+            available: vec![(consensus_hash.clone(), sn.burn_header_hash)],
+        };
+        self.handle_unsolicited_BlocksAvailable(
+            sortdb, chainstate, event_id, &available, ibd, false,
+        );
+    }
+
+    /// Assemble a fully-filled compact block and pass it along to the relayer, or fall back to
+    /// downloading it if it doesn't match its header.
+    fn finish_compact_block(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        pending: PendingCompactBlock,
+        network_result: &mut NetworkResult,
+        ibd: bool,
+    ) {
+        let consensus_hash = pending.consensus_hash.clone();
+        let block_hash = pending.block_hash();
+        let event_id = pending.event_id;
+        let neighbor_key = pending.neighbor_key.clone();
+
+        match pending.into_block() {
+            Some(block) => {
+                debug!(
+                    "{:?}: Rebuilt compact block {}/{} from {:?}",
+                    &self.local_peer, &consensus_hash, &block_hash, &neighbor_key
+                );
+                let blocks_data = BlocksData {
+                    blocks: vec![BlocksDatum(consensus_hash, block)],
+                };
+                network_result
+                    .pushed_blocks
+                    .entry(neighbor_key)
+                    .or_insert(vec![])
+                    .push(blocks_data);
+            }
+            None => {
+                info!(
+                    "{:?}: Rebuilt compact block {}/{} from {:?} does not match its header; will download it",
+                    &self.local_peer, &consensus_hash, &block_hash, &neighbor_key
+                );
+                self.compact_block_fallback(sortdb, chainstate, event_id, &consensus_hash, ibd);
+            }
+        }
+    }
+
+    /// Rebuild the compact blocks we've been sent, asking their senders for any transactions our
+    /// mempool doesn't have.  Rebuilt blocks are added to the network result's pushed blocks.
+    /// Compact blocks we can't rebuild in time are downloaded instead.
+    pub fn process_compact_blocks(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        mempool: &MemPoolDB,
+        network_result: &mut NetworkResult,
+        ibd: bool,
+    ) {
+        let inbound = mem::replace(&mut self.compact_blocks.inbound, vec![]);
+        for (event_id, payload) in inbound.into_iter() {
+            match payload {
+                StacksMessageType::CompactBlock(compact_block) => {
+                    let index_block_hash = compact_block.index_block_hash();
+                    if self.compact_blocks.pending.contains_key(&index_block_hash) {
+                        debug!(
+                            "{:?}: Already rebuilding compact block {}",
+                            &self.local_peer, &index_block_hash
+                        );
+                        continue;
+                    }
+                    let neighbor_key = match self.peers.get(&event_id) {
+                        Some(convo) => convo.to_neighbor_key(),
+                        None => {
+                            continue;
+                        }
+                    };
+
+                    let mut pending =
+                        PendingCompactBlock::new(&compact_block, event_id, neighbor_key.clone());
+                    if let Err(e) = pending.fill_from_mempool(mempool) {
+                        warn!(
+                            "{:?}: Failed to read mempool for compact block {}: {:?}",
+                            &self.local_peer, &index_block_hash, &e
+                        );
+                    }
+
+                    if pending.is_complete() {
+                        self.finish_compact_block(sortdb, chainstate, pending, network_result, ibd);
+                        continue;
+                    }
+
+                    let missing = pending.missing();
+                    debug!(
+                        "{:?}: Ask {:?} for {} of {} txs in compact block {}",
+                        &self.local_peer,
+                        &neighbor_key,
+                        missing.len(),
+                        compact_block.tx_tags.len(),
+                        &index_block_hash
+                    );
+
+                    let request = StacksMessageType::GetBlockTxns(GetBlockTxnsData {
+                        consensus_hash: pending.consensus_hash.clone(),
+                        block_hash: pending.block_hash(),
+                        indexes: missing.clone(),
+                    });
+                    let res = self
+                        .sign_for_peer(&neighbor_key, request)
+                        .and_then(|msg| self.relay_signed_message(&neighbor_key, msg));
+
+                    match res {
+                        Ok(_) => {
+                            pending.requested = missing;
+                            pending.requested_at = get_epoch_time_secs();
+                            self.compact_blocks
+                                .pending
+                                .insert(index_block_hash, pending);
+                        }
+                        Err(e) => {
+                            info!(
+                                "{:?}: Failed to ask {:?} for compact block txs: {:?}",
+                                &self.local_peer, &neighbor_key, &e
+                            );
+                            self.compact_block_fallback(
+                                sortdb,
+                                chainstate,
+                                event_id,
+                                &compact_block.consensus_hash,
+                                ibd,
+                            );
+                        }
+                    }
+                }
+                StacksMessageType::BlockTxns(txns) => {
+                    let index_block_hash = StacksBlockHeader::make_index_block_hash(
+                        &txns.consensus_hash,
+                        &txns.block_hash,
+                    );
+                    let mut pending = match self.compact_blocks.pending.remove(&index_block_hash) {
+                        Some(pending) => pending,
+                        None => {
+                            debug!(
+                                "{:?}: Drop BlockTxns for {} -- not rebuilding it",
+                                &self.local_peer, &index_block_hash
+                            );
+                            continue;
+                        }
+                    };
+
+                    if pending.event_id != event_id {
+                        debug!(
+                            "{:?}: Drop BlockTxns for {} from event {} -- asked event {}",
+                            &self.local_peer, &index_block_hash, event_id, pending.event_id
+                        );
+                        self.compact_blocks
+                            .pending
+                            .insert(index_block_hash, pending);
+                        continue;
+                    }
+
+                    if pending.fill_requested(&txns.txs) {
+                        self.finish_compact_block(sortdb, chainstate, pending, network_result, ibd);
+                    } else {
+                        info!(
+                            "{:?}: Invalid BlockTxns for {} from {:?}; will download it",
+                            &self.local_peer, &index_block_hash, &pending.neighbor_key
+                        );
+                        self.compact_block_fallback(
+                            sortdb,
+                            chainstate,
+                            event_id,
+                            &pending.consensus_hash,
+                            ibd,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // give up on compact blocks whose senders didn't answer in time
+        let now = get_epoch_time_secs();
+        let expired: Vec<StacksBlockId> = self
+            .compact_blocks
+            .pending
+            .iter()
+            .filter_map(|(index_block_hash, pending)| {
+                if pending.requested_at + self.connection_opts.compact_block_timeout < now {
+                    Some(index_block_hash.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for index_block_hash in expired.into_iter() {
+            if let Some(pending) = self.compact_blocks.pending.remove(&index_block_hash) {
+                info!(
+                    "{:?}: Timed out waiting for compact block txs for {} from {:?}; will download it",
+                    &self.local_peer, &index_block_hash, &pending.neighbor_key
+                );
+                self.compact_block_fallback(
+                    sortdb,
+                    chainstate,
+                    pending.event_id,
+                    &pending.consensus_hash,
+                    ibd,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::chainstate::stacks::test::make_codec_test_block;
+    use crate::net::PeerAddress;
+
+    fn test_neighbor_key() -> NeighborKey {
+        NeighborKey {
+            peer_version: 0x12345678,
+            network_id: 0x80000000,
+            addrbytes: PeerAddress([0u8; 16]),
+            port: 20444,
+        }
+    }
+
+    #[test]
+    fn test_compact_block_prefills_coinbase() {
+        let block = make_codec_test_block(5);
+        let compact = CompactBlockData::from_block(&ConsensusHash([0x11; 20]), &block);
+
+        assert_eq!(compact.header, block.header);
+        assert_eq!(compact.tx_tags.len(), block.txs.len());
+        assert_eq!(compact.prefilled_txs, vec![block.txs[0].clone()]);
+
+        let pending = PendingCompactBlock::new(&compact, 1, test_neighbor_key());
+        let expected_missing: Vec<u32> = (1..block.txs.len() as u32).collect();
+        assert_eq!(pending.missing(), expected_missing);
+        assert!(!pending.is_complete());
+    }
+
+    #[test]
+    fn test_compact_block_rebuild_from_known_txs() {
+        let block = make_codec_test_block(5);
+        let compact = CompactBlockData::from_block(&ConsensusHash([0x11; 20]), &block);
+        let mut pending = PendingCompactBlock::new(&compact, 1, test_neighbor_key());
+
+        // we "have" every other transaction in the block, plus one that isn't in the block
+        let mut known: Vec<StacksTransaction> =
+            block.txs.iter().skip(1).step_by(2).cloned().collect();
+        let mut extra_block = make_codec_test_block(2);
+        extra_block.txs[1].set_tx_fee(12345);
+        known.push(extra_block.txs[1].clone());
+
+        let filled = pending.fill_from_txs(known.iter());
+        assert_eq!(filled, known.len() - 1);
+
+        // ask for the rest
+        let missing = pending.missing();
+        assert!(missing.len() > 0);
+        pending.requested = missing.clone();
+
+        let missing_txs: Vec<StacksTransaction> = missing
+            .iter()
+            .map(|i| block.txs[*i as usize].clone())
+            .collect();
+        assert!(pending.fill_requested(&missing_txs));
+        assert!(pending.is_complete());
+
+        assert_eq!(pending.into_block(), Some(block));
+    }
+
+    #[test]
+    fn test_compact_block_reject_bad_txns() {
+        let block = make_codec_test_block(5);
+        let compact = CompactBlockData::from_block(&ConsensusHash([0x11; 20]), &block);
+        let mut pending = PendingCompactBlock::new(&compact, 1, test_neighbor_key());
+
+        let missing = pending.missing();
+        pending.requested = missing.clone();
+
+        // wrong number of txs
+        let too_few: Vec<StacksTransaction> = missing
+            .iter()
+            .skip(1)
+            .map(|i| block.txs[*i as usize].clone())
+            .collect();
+        assert!(!pending.fill_requested(&too_few));
+
+        // right number of txs, but out of order
+        let mut out_of_order: Vec<StacksTransaction> = missing
+            .iter()
+            .map(|i| block.txs[*i as usize].clone())
+            .collect();
+        out_of_order.reverse();
+        assert!(!pending.fill_requested(&out_of_order));
+
+        // nothing was filled in
+        assert_eq!(pending.missing(), missing);
+    }
+
+    #[test]
+    fn test_compact_block_merkle_mismatch() {
+        let block = make_codec_test_block(5);
+        let mut compact = CompactBlockData::from_block(&ConsensusHash([0x11; 20]), &block);
+
+        // header commits to different transactions than the tags describe
+        compact.header.tx_merkle_root = Sha512Trunc256Sum([0x22; 32]);
+        let mut pending = PendingCompactBlock::new(&compact, 1, test_neighbor_key());
+        pending.fill_from_txs(block.txs.iter());
+        assert!(pending.is_complete());
+        assert_eq!(pending.into_block(), None);
+    }
+}
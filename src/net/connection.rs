@@ -19,6 +19,7 @@ use std::convert::TryFrom;
 use std::io;
 use std::io::{Read, Write};
 use std::net;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::time::Duration;
@@ -43,12 +44,16 @@ use crate::net::HttpRequestPreamble;
 use crate::net::HttpResponsePreamble;
 use crate::net::MessageSequence;
 use crate::net::PeerAddress;
+use crate::net::PeerHost;
 use crate::net::Preamble;
 use crate::net::ProtocolFamily;
 use crate::net::RelayData;
 use crate::net::StacksHttp;
 use crate::net::StacksP2P;
 
+use crate::net::transport::TransportCipher;
+use crate::util_lib::strings::UrlString;
+
 use crate::net::download::BLOCK_DOWNLOAD_INTERVAL;
 use crate::net::inv::{INV_REWARD_CYCLES, INV_SYNC_INTERVAL};
 use crate::net::neighbors::{
@@ -60,6 +65,7 @@ use clarity::vm::{costs::ExecutionCost, types::BOUND_VALUE_SERIALIZATION_HEX};
 
 use crate::chainstate::burn::ConsensusHash;
 
+use stacks_common::util::get_epoch_time_ms;
 use stacks_common::util::get_epoch_time_secs;
 use stacks_common::util::hash::to_hex;
 use stacks_common::util::log;
@@ -286,6 +292,8 @@ impl<P: ProtocolFamily> Write for NetworkReplyHandle<P> {
 struct InflightMessage<P: ProtocolFamily> {
     pipe_read: Option<PipeRead>,
     notify: Option<ReceiverNotify<P>>,
+    // if set, everything sent after this message is encrypted with this cipher
+    then_encrypt: Option<TransportCipher>,
 }
 
 #[derive(Debug)]
@@ -301,6 +309,18 @@ struct ConnectionInbox<P: ProtocolFamily> {
     buf: Vec<u8>,
     message_ptr: usize, // index into buf where the message begins
     payload_ptr: usize, // for payloads of unknown length, this points to where to read next
+
+    // if set, incoming bytes are encrypted transport frames
+    cipher: Option<TransportCipher>,
+    // if true, we stopped parsing after a message that changes how the bytes after it are read,
+    // and hold those bytes back until we're told how to proceed.
+    paused: bool,
+    paused_bytes: Vec<u8>,
+
+    // if set, limits how many bytes per second we read from the socket
+    bandwidth_limit: Option<TokenBucket>,
+    // if true, we stopped reading from the socket because we ran out of bandwidth
+    throttled: bool,
 }
 
 #[derive(Debug)]
@@ -313,8 +333,64 @@ struct ConnectionOutbox<P: ProtocolFamily> {
     socket_out_buf: Vec<u8>,
     socket_out_ptr: usize,
 
+    // if set, outgoing bytes are sent as encrypted transport frames
+    cipher: Option<TransportCipher>,
+
     // in-flight messages
     inflight: VecDeque<ReceiverNotify<P>>,
+
+    // if set, limits how many bytes per second we write to the socket
+    bandwidth_limit: Option<TokenBucket>,
+    // if true, we stopped writing to the socket because we ran out of bandwidth
+    throttled: bool,
+}
+
+/// Token bucket for limiting how many bytes per second a connection sends or receives.  It holds
+/// up to one second's worth of bytes, and refills continuously.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    /// bytes per second
+    rate: u64,
+    /// bytes that can be sent or received right now
+    tokens: u64,
+    /// when we last added tokens, in milliseconds since the epoch
+    last_refill_ms: u128,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> TokenBucket {
+        TokenBucket::new_at(rate, get_epoch_time_ms())
+    }
+
+    pub fn new_at(rate: u64, now_ms: u128) -> TokenBucket {
+        TokenBucket {
+            rate,
+            tokens: rate,
+            last_refill_ms: now_ms,
+        }
+    }
+
+    /// How many bytes can be sent or received as of `now_ms`?
+    pub fn available_at(&mut self, now_ms: u128) -> u64 {
+        if now_ms > self.last_refill_ms {
+            let added = (self.rate as u128) * (now_ms - self.last_refill_ms) / 1000;
+            if added > 0 {
+                self.tokens = ((self.tokens as u128) + added).min(self.rate as u128) as u64;
+                self.last_refill_ms = now_ms;
+            }
+        }
+        self.tokens
+    }
+
+    /// How many bytes can be sent or received right now?
+    pub fn available(&mut self) -> u64 {
+        self.available_at(get_epoch_time_ms())
+    }
+
+    /// Use up `num_bytes` bytes' worth of tokens
+    pub fn consume(&mut self, num_bytes: u64) -> () {
+        self.tokens = self.tokens.saturating_sub(num_bytes);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -380,6 +456,50 @@ pub struct ConnectionOptions {
     pub mempool_max_tx_query: u64,
     /// how long a mempool sync is allowed to take, in total, before timing out
     pub mempool_sync_timeout: u64,
+    /// whether or not to advertise (and use) the encrypted p2p transport
+    pub encrypted_transport: bool,
+    /// SOCKS5 proxy (e.g. Tor) to make outbound connections through, if any
+    pub proxy: Option<SocketAddr>,
+    /// whether or not to advertise (and use) compact block relay
+    pub compact_blocks: bool,
+    /// how long to wait for a peer to send us the transactions missing from a compact block
+    /// before falling back to downloading the whole block, in seconds
+    pub compact_block_timeout: u64,
+    /// how long it takes for a peer's reputation score to decay by half, in seconds
+    pub reputation_half_life: u64,
+    /// peers whose reputation score falls to or below this are banned
+    pub reputation_ban_threshold: i64,
+    /// if set, run as a pruned node: delete the data of blocks and microblocks processed more
+    /// than this many reward cycles ago, and stop advertising them
+    pub prune_reward_cycles: Option<u64>,
+    /// how many blocks to prune at a time
+    pub prune_batch_size: u64,
+    /// data URL of a trusted archive node to fetch contiguous ranges of historic blocks from, if any
+    pub archive_data_url: Option<UrlString>,
+    /// maximum number of bytes per second to receive from any one peer or HTTP client (0 means no
+    /// limit)
+    pub max_peer_inbound_bandwidth: u64,
+    /// maximum number of bytes per second to send to any one peer or HTTP client (0 means no
+    /// limit)
+    pub max_peer_outbound_bandwidth: u64,
+    /// whether or not to advertise (and use) alternate peer addresses
+    pub multi_address: bool,
+    /// other addresses (IPv4, IPv6, or DNS names) this node can be reached at, besides its
+    /// public IP address
+    pub public_addresses: Vec<PeerHost>,
+    /// whether or not to send new transactions through a stem phase (forwarded along a single
+    /// random path of peers) before they are broadcast.  Transactions uploaded to us are checked,
+    /// but not stored to our mempool until they are broadcast.
+    pub dandelion: bool,
+    /// percent chance that a peer relaying a stem-phase transaction broadcasts it instead of
+    /// forwarding it along the stem
+    pub dandelion_fluff_probability: u8,
+    /// base number of seconds to wait for a stem-phase transaction to come back to us as a
+    /// broadcast before broadcasting it ourselves.  The actual wait is randomized between this
+    /// and twice this.
+    pub dandelion_embargo: u64,
+    /// how often (in seconds) to pick a new peer to forward stem-phase transactions to
+    pub dandelion_epoch: u64,
 
     // fault injection
     pub disable_neighbor_walk: bool,
@@ -466,6 +586,23 @@ impl std::default::Default for ConnectionOptions {
             mempool_sync_interval: 30, // number of seconds in-between mempool sync
             mempool_max_tx_query: 128, // maximum number of transactions to visit per mempool query
             mempool_sync_timeout: 180, // how long a mempool sync can go for (3 minutes)
+            encrypted_transport: false, // plaintext unless asked
+            proxy: None,               // connect directly
+            compact_blocks: false,     // push full blocks unless asked
+            compact_block_timeout: 15, // wait at most 15 seconds for missing compact block txs
+            reputation_half_life: 86400, // reputations halve every day
+            reputation_ban_threshold: -500, // e.g. a few invalid blocks
+            prune_reward_cycles: None, // archival node unless asked
+            prune_batch_size: 256,
+            archive_data_url: None, // only download from inventoried peers unless asked
+            max_peer_inbound_bandwidth: 0, // infinite per-peer download bandwidth allowed
+            max_peer_outbound_bandwidth: 0, // infinite per-peer upload bandwidth allowed
+            multi_address: false,   // one address per peer unless asked
+            public_addresses: vec![],
+            dandelion: false, // broadcast new transactions right away unless asked
+            dandelion_fluff_probability: 10, // stems are about 10 hops long on average
+            dandelion_embargo: 30,
+            dandelion_epoch: 600, // new stem relay every 10 minutes
 
             // no faults on by default
             disable_neighbor_walk: false,
@@ -508,6 +645,11 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
             buf: vec![],
             message_ptr: 0,
             payload_ptr: 0,
+            cipher: None,
+            paused: false,
+            paused_bytes: vec![],
+            bandwidth_limit: None,
+            throttled: false,
         }
     }
 
@@ -803,6 +945,7 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
             }
 
             let mut consumed_message = false;
+            let mut pause = false;
             let bytes_consumed_message = {
                 let mut preamble_opt = self.preamble.take();
                 let bytes_consumed = if let Some(ref mut preamble) = preamble_opt {
@@ -817,6 +960,7 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
                                 message.request_id(),
                                 bytes_consumed
                             );
+                            pause = protocol.pauses_transport(&message);
                             self.inbox.push_back(message);
                             consumed_message = true;
                         }
@@ -837,6 +981,9 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
             }
 
             offset += bytes_consumed_message;
+            if pause {
+                return self.pause(&buf[offset..]);
+            }
             if offset == buf.len() {
                 break;
             }
@@ -847,6 +994,7 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
         if self.buf.len() > 0 {
             loop {
                 let mut consumed_message = false;
+                let mut pause = false;
 
                 if self.preamble.is_none() {
                     let (preamble_opt, _bytes_consumed) = self.consume_preamble(protocol, &[])?;
@@ -868,6 +1016,7 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
                             Some(message) => {
                                 // queue up
                                 test_debug!("Consumed buffered message '{}' (request {}) from {} input buffer bytes", message.get_message_name(), message.request_id(), _bytes_consumed);
+                                pause = protocol.pauses_transport(&message);
                                 self.inbox.push_back(message);
                                 consumed_message = true;
                             }
@@ -882,6 +1031,10 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
                     }
                 }
 
+                if pause {
+                    return self.pause(&[]);
+                }
+
                 if !consumed_message {
                     // nothing more to do
                     break;
@@ -892,6 +1045,66 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
         Ok(())
     }
 
+    /// Stop parsing after a message that changes how the bytes after it are to be read.  Whatever
+    /// we have received after it -- the unparsed bytes in our buffer, plus `unconsumed` -- is held
+    /// back until resume() is called.
+    fn pause(&mut self, unconsumed: &[u8]) -> Result<(), net_error> {
+        if self.cipher.is_some() {
+            // the transport can only be switched once
+            debug!("Received a transport-switching message over an encrypted transport");
+            return Err(net_error::InvalidMessage);
+        }
+
+        let mut held = std::mem::replace(&mut self.buf, vec![]);
+        held.extend_from_slice(unconsumed);
+
+        self.paused_bytes = held;
+        self.paused = true;
+        self.preamble = None;
+        self.message_ptr = 0;
+        self.payload_ptr = 0;
+        Ok(())
+    }
+
+    /// Resume parsing after pause(), decrypting everything from here on with `cipher_opt` if
+    /// given.
+    fn resume(
+        &mut self,
+        protocol: &mut P,
+        cipher_opt: Option<TransportCipher>,
+    ) -> Result<(), net_error> {
+        if !self.paused {
+            return Err(net_error::InvalidMessage);
+        }
+        self.paused = false;
+        if cipher_opt.is_some() {
+            self.cipher = cipher_opt;
+        }
+        let held = std::mem::replace(&mut self.paused_bytes, vec![]);
+        self.consume_bytes(protocol, &held)
+    }
+
+    /// Parse bytes received from the socket -- decrypting them first if the transport is
+    /// encrypted -- and enqueue the messages they contain into the inbox.  If parsing is paused,
+    /// the bytes are held back instead.
+    fn consume_bytes(&mut self, protocol: &mut P, bytes: &[u8]) -> Result<(), net_error> {
+        if self.paused {
+            if self.paused_bytes.len() + bytes.len() > MAX_MESSAGE_LEN as usize {
+                return Err(net_error::InboxOverflow);
+            }
+            self.paused_bytes.extend_from_slice(bytes);
+            return Ok(());
+        }
+
+        let plaintext = match self.cipher {
+            Some(ref mut cipher) => cipher.open_frames(bytes)?,
+            None => {
+                return self.consume_messages(protocol, bytes);
+            }
+        };
+        self.consume_messages(protocol, &plaintext)
+    }
+
     /// Read bytes from an input stream, buffer them up, try to parse the buffer
     /// into messages, and enqueue the messages into the inbox.
     /// Returns net_error::RecvError if we couldn't read from the fd
@@ -903,13 +1116,23 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
         let mut blocked = false;
         let mut total_read = 0;
         let mut socket_closed = false;
+        self.throttled = false;
         while !blocked {
             // get the next bytes
             // NOTE: it's important that buf not be too big, since up to buf.len()-1 bytes may need
             // to be copied if a message boundary isn't aligned with buf (which is usually the
             // case).
             let mut buf = [0u8; 4096];
-            let num_read = match fd.read(&mut buf) {
+            let max_read = match self.bandwidth_limit {
+                Some(ref mut bucket) => (bucket.available() as usize).min(buf.len()),
+                None => buf.len(),
+            };
+            if max_read == 0 {
+                // out of bandwidth; leave the rest in the socket for later
+                self.throttled = true;
+                break;
+            }
+            let num_read = match fd.read(&mut buf[0..max_read]) {
                 Ok(0) => {
                     // remote fd is closed, but do try to consume all remaining bytes in the buffer
                     socket_closed = true;
@@ -939,6 +1162,9 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
             }?;
 
             total_read += num_read;
+            if let Some(ref mut bucket) = self.bandwidth_limit {
+                bucket.consume(num_read as u64);
+            }
 
             if num_read > 0 || total_read > 0 {
                 trace!("read {} bytes; {} total", num_read, total_read);
@@ -946,7 +1172,7 @@ impl<P: ProtocolFamily> ConnectionInbox<P> {
 
             if num_read > 0 {
                 // decode into message stream
-                self.consume_messages(protocol, &buf[0..num_read])?;
+                self.consume_bytes(protocol, &buf[0..num_read])?;
             }
         }
 
@@ -976,7 +1202,10 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
             pending_message_fd: None,
             socket_out_buf: vec![],
             socket_out_ptr: 0,
+            cipher: None,
             inflight: VecDeque::new(),
+            bandwidth_limit: None,
+            throttled: false,
         }
     }
 
@@ -1006,7 +1235,11 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
 
         match receiver_notify_opt {
             None => {}
-            Some(receiver_notify) => {
+            Some(mut receiver_notify) => {
+                if let Some(cipher) = receiver_notify.then_encrypt.take() {
+                    test_debug!("Connection outbox is now encrypted");
+                    self.cipher = Some(cipher);
+                }
                 if receiver_notify.notify.is_some() {
                     self.inflight.push_back(receiver_notify.notify.unwrap());
                 }
@@ -1018,6 +1251,7 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
         &mut self,
         pipe_read: PipeRead,
         recv_notify: Option<ReceiverNotify<P>>,
+        then_encrypt: Option<TransportCipher>,
     ) -> Result<(), net_error> {
         if self.outbox.len() > self.outbox_maxlen {
             test_debug!(
@@ -1031,6 +1265,7 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
         let inflight = InflightMessage {
             pipe_read: Some(pipe_read),
             notify: recv_notify,
+            then_encrypt,
         };
         self.outbox.push_back(inflight);
         Ok(())
@@ -1042,6 +1277,7 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
         let mut total_sent = 0;
         let mut blocked = false;
         let mut disconnected = false;
+        self.throttled = false;
         while !blocked && !disconnected {
             let mut message_eof = false;
 
@@ -1091,7 +1327,12 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
                         },
                     };
 
-                    self.socket_out_buf.extend_from_slice(&buf[0..nr_input]);
+                    match self.cipher {
+                        Some(ref mut cipher) => {
+                            cipher.seal_frames(&buf[0..nr_input], &mut self.socket_out_buf)?
+                        }
+                        None => self.socket_out_buf.extend_from_slice(&buf[0..nr_input]),
+                    }
 
                     test_debug!(
                         "Connection buffered {} bytes from pipe ({} total, ptr = {}, blocked = {})",
@@ -1111,8 +1352,24 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
 
             if self.socket_out_ptr < self.socket_out_buf.len() {
                 // have pending bytes.
-                // send as many bytes as we can
-                let num_written_res = fd.write(&self.socket_out_buf[self.socket_out_ptr..]);
+                // send as many bytes as we can (and as our bandwidth limit allows)
+                let mut max_write = self.socket_out_buf.len() - self.socket_out_ptr;
+                if let Some(ref mut bucket) = self.bandwidth_limit {
+                    let available = bucket.available() as usize;
+                    if available < max_write {
+                        max_write = available;
+
+                        // don't buffer any more from the pipe; stop once this is written
+                        self.throttled = true;
+                        blocked = true;
+                    }
+                }
+                if max_write == 0 {
+                    break;
+                }
+                let num_written_res = fd.write(
+                    &self.socket_out_buf[self.socket_out_ptr..(self.socket_out_ptr + max_write)],
+                );
                 let num_written = match num_written_res {
                     Ok(0) => {
                         // indicates that the remote peer is no longer receiving
@@ -1137,6 +1394,9 @@ impl<P: ProtocolFamily> ConnectionOutbox<P> {
                 }?;
 
                 self.socket_out_ptr += num_written;
+                if let Some(ref mut bucket) = self.bandwidth_limit {
+                    bucket.consume(num_written as u64);
+                }
 
                 test_debug!(
                     "Connection wrote {} bytes to socket (buffer len = {}, ptr = {})",
@@ -1312,7 +1572,8 @@ impl<P: ProtocolFamily + Clone> NetworkConnection<P> {
         let mut recv_handle = NetworkReplyHandle::new(recv_ch, pipe_write, socket_event_id);
         recv_handle.set_deadline(timeout + get_epoch_time_secs());
 
-        self.outbox.queue_message(pipe_read, Some(recv_notify))?;
+        self.outbox
+            .queue_message(pipe_read, Some(recv_notify), None)?;
         Ok(recv_handle)
     }
 
@@ -1323,12 +1584,58 @@ impl<P: ProtocolFamily + Clone> NetworkConnection<P> {
         socket_event_id: usize,
     ) -> Result<NetworkReplyHandle<P>, net_error> {
         let (pipe_read, pipe_write) = Pipe::new();
-        self.outbox.queue_message(pipe_read, None)?;
+        self.outbox.queue_message(pipe_read, None, None)?;
+
+        let send_handle = NetworkReplyHandle::new_relay(pipe_write, socket_event_id);
+        Ok(send_handle)
+    }
+
+    /// Forward a message and expect no reply, and encrypt everything sent after it with the given
+    /// cipher.
+    pub fn make_relay_handle_then_encrypt(
+        &mut self,
+        socket_event_id: usize,
+        cipher: TransportCipher,
+    ) -> Result<NetworkReplyHandle<P>, net_error> {
+        let (pipe_read, pipe_write) = Pipe::new();
+        self.outbox.queue_message(pipe_read, None, Some(cipher))?;
 
         let send_handle = NetworkReplyHandle::new_relay(pipe_write, socket_event_id);
         Ok(send_handle)
     }
 
+    /// Resume receiving after a message that switches the transport, decrypting everything
+    /// received from here on with the given cipher (if any).  Messages that had already arrived
+    /// are parsed into the inbox.
+    pub fn resume_recv(&mut self, cipher_opt: Option<TransportCipher>) -> Result<(), net_error> {
+        self.inbox.resume(&mut self.protocol, cipher_opt)
+    }
+
+    /// Is the transport encrypted in both directions?
+    pub fn is_encrypted(&self) -> bool {
+        self.inbox.cipher.is_some() && self.outbox.cipher.is_some()
+    }
+
+    /// Limit how many bytes per second this connection receives and sends.  0 means no limit.
+    pub fn set_bandwidth_limits(&mut self, max_inbound: u64, max_outbound: u64) -> () {
+        self.inbox.bandwidth_limit = if max_inbound > 0 {
+            Some(TokenBucket::new(max_inbound))
+        } else {
+            None
+        };
+        self.outbox.bandwidth_limit = if max_outbound > 0 {
+            Some(TokenBucket::new(max_outbound))
+        } else {
+            None
+        };
+    }
+
+    /// Did the last recv or send stop early because we ran out of bandwidth?  If so, there may
+    /// be more to do even though the socket won't report itself ready again.
+    pub fn is_throttled(&self) -> bool {
+        self.inbox.throttled || self.outbox.throttled
+    }
+
     /// Send data
     pub fn send_data<W: Write>(&mut self, fd: &mut W) -> Result<usize, net_error> {
         self.outbox.send_bytes(fd)
@@ -2136,6 +2443,74 @@ mod test {
         })
     }
 
+    #[test]
+    fn token_bucket_refill() {
+        let mut bucket = TokenBucket::new_at(1000, 1_000_000);
+        assert_eq!(bucket.available_at(1_000_000), 1000);
+
+        bucket.consume(600);
+        assert_eq!(bucket.available_at(1_000_000), 400);
+
+        // refills in proportion to elapsed time
+        assert_eq!(bucket.available_at(1_000_100), 500);
+
+        // can't go negative
+        bucket.consume(10_000);
+        assert_eq!(bucket.available_at(1_000_100), 0);
+
+        // never holds more than a second's worth
+        assert_eq!(bucket.available_at(1_010_000), 1000);
+    }
+
+    #[test]
+    fn connection_recv_bandwidth_limit() {
+        let privkey = Secp256k1PrivateKey::new();
+        let conn_opts = ConnectionOptions::default();
+
+        let mut ping_buf = vec![];
+        let mut ping_size = 0;
+        for i in 0..5 {
+            let mut ping = StacksMessage::new(
+                0x12345678,
+                0x9abcdef0,
+                12345 + i,
+                &BurnchainHeaderHash([0x11; 32]),
+                12339 + i,
+                &BurnchainHeaderHash([0x22; 32]),
+                StacksMessageType::Ping(PingData {
+                    nonce: (0x01020304 + i) as u32,
+                }),
+            );
+            ping.sign(i as u32, &privkey).unwrap();
+
+            let mut tmp = vec![];
+            ping.consensus_serialize(&mut tmp).unwrap();
+            ping_size = tmp.len();
+            ping_buf.append(&mut tmp);
+        }
+
+        // no limit: everything gets read
+        let mut conn = ConnectionP2P::new(StacksP2P::new(), &conn_opts, None);
+        {
+            let mut ping_fd = NetCursor::new(ping_buf.as_mut_slice());
+            let num_read = conn.recv_data(&mut ping_fd).unwrap();
+            assert_eq!(num_read, 5 * ping_size);
+            assert!(!conn.is_throttled());
+        }
+        assert_eq!(conn.drain_inbox().len(), 5);
+
+        // limited to two pings' worth of bytes per second
+        let mut conn = ConnectionP2P::new(StacksP2P::new(), &conn_opts, None);
+        conn.set_bandwidth_limits((2 * ping_size) as u64, 0);
+        {
+            let mut ping_fd = NetCursor::new(ping_buf.as_mut_slice());
+            let num_read = conn.recv_data(&mut ping_fd).unwrap();
+            assert_eq!(num_read, 2 * ping_size);
+            assert!(conn.is_throttled());
+        }
+        assert_eq!(conn.drain_inbox().len(), 2);
+    }
+
     #[test]
     fn connection_send_recv_timeout() {
         let privkey = Secp256k1PrivateKey::new();
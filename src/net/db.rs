@@ -29,7 +29,9 @@ use crate::util_lib::db::sqlite_open;
 use crate::util_lib::db::tx_begin_immediate;
 use crate::util_lib::db::DBConn;
 use crate::util_lib::db::Error as db_error;
-use crate::util_lib::db::{query_count, query_row, query_rows, u64_to_sql, FromColumn, FromRow};
+use crate::util_lib::db::{
+    query_count, query_row, query_row_columns, query_rows, u64_to_sql, FromColumn, FromRow,
+};
 
 use stacks_common::util;
 use stacks_common::util::hash::{
@@ -55,7 +57,9 @@ use crate::net::Neighbor;
 use crate::net::NeighborAddress;
 use crate::net::NeighborKey;
 use crate::net::PeerAddress;
+use crate::net::PeerHost;
 use crate::net::ServiceFlags;
+use crate::net::MAX_PEER_ADDRESSES;
 
 use crate::burnchains::PrivateKey;
 use crate::burnchains::PublicKey;
@@ -68,12 +72,29 @@ pub const PEERDB_VERSION: &'static str = "1";
 
 const NUM_SLOTS: usize = 8;
 
+// Sort order for sampling peers at random, biased by reputation.  Each peer gets a random weight
+// in [0, 2048), offset by its score (which is in [-1000, 1000]), so peers with bad reputations
+// are rarely chosen over peers with good ones, but are not excluded outright.
+const PEER_REPUTATION_RANDOM_ORDER: &'static str = "(RANDOM() & 2047) + \
+    COALESCE((SELECT score FROM peer_reputation WHERE peer_reputation.network_id = frontier.network_id AND \
+    peer_reputation.addrbytes = frontier.addrbytes AND peer_reputation.port = frontier.port), 0) DESC";
+
 impl PeerAddress {
     pub fn to_bin(&self) -> String {
         to_bin(&self.0)
     }
 }
 
+impl FromColumn<PeerHost> for PeerHost {
+    fn from_column<'a>(row: &'a Row, column_name: &str) -> Result<PeerHost, db_error> {
+        let host_str: String = row.get_unwrap(column_name);
+        host_str.parse::<PeerHost>().map_err(|_e| {
+            error!("Unparseable peer host {}", host_str);
+            db_error::ParseError
+        })
+    }
+}
+
 impl FromColumn<PeerAddress> for PeerAddress {
     fn from_column<'a>(row: &'a Row, column_name: &str) -> Result<PeerAddress, db_error> {
         let addrbytes_bin: String = row.get_unwrap(column_name);
@@ -113,6 +134,9 @@ pub struct LocalPeer {
 
     // filled in and curated at runtime
     pub public_ip_address: Option<(PeerAddress, u16)>,
+    /// Other addresses this peer can be reached at (e.g. an IPv6 address if the public IP
+    /// address is IPv4, or a DNS name), advertised to peers that support multiple addresses.
+    pub public_addresses: Vec<PeerHost>,
 }
 
 impl fmt::Display for LocalPeer {
@@ -182,6 +206,7 @@ impl LocalPeer {
             services: services as u16,
             data_url: data_url,
             public_ip_address: None,
+            public_addresses: vec![],
         }
     }
 
@@ -234,6 +259,7 @@ impl FromRow<LocalPeer> for LocalPeer {
             services: services,
             data_url: data_url,
             public_ip_address: None,
+            public_addresses: vec![],
         })
     }
 }
@@ -293,6 +319,93 @@ impl FromRow<Neighbor> for Neighbor {
     }
 }
 
+/// Bounds on a peer's reputation score
+pub const PEER_REPUTATION_MAX: i64 = 1000;
+pub const PEER_REPUTATION_MIN: i64 = -1000;
+
+/// Something a peer did that makes us trust it more or less
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReputationEvent {
+    /// pushed us a block that turned out to be valid
+    ValidBlockPush,
+    /// served us a block or microblock stream we asked it for
+    BlockDownloaded,
+    /// pushed or served us a block or microblock stream that turned out to be invalid
+    InvalidBlock,
+    /// sent us a malformed or otherwise invalid p2p message
+    InvalidMessage,
+    /// pushed us more data than our bandwidth limits allow
+    BandwidthExceeded,
+    /// advertised data in its inventory that it then didn't have
+    StaleInventory,
+    /// could not be reached when we tried to download from it
+    Unreachable,
+}
+
+impl ReputationEvent {
+    /// How much this event changes a peer's score
+    pub fn score_delta(&self) -> i64 {
+        match self {
+            ReputationEvent::ValidBlockPush => 5,
+            ReputationEvent::BlockDownloaded => 2,
+            ReputationEvent::InvalidBlock => -200,
+            ReputationEvent::InvalidMessage => -50,
+            ReputationEvent::BandwidthExceeded => -25,
+            ReputationEvent::StaleInventory => -20,
+            ReputationEvent::Unreachable => -5,
+        }
+    }
+}
+
+/// A peer's persisted reputation.  It is keyed by address, not by frontier slot, so it survives
+/// the peer's eviction from the frontier.  Scores decay towards 0 over time, so peers that stop
+/// misbehaving are eventually forgiven (and peers that stop behaving well are eventually
+/// forgotten).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerReputation {
+    pub network_id: u32,
+    pub addrbytes: PeerAddress,
+    pub port: u16,
+    /// the score as of the last decay pass
+    pub score: i64,
+    /// the score right after the last event; decay is always computed from this, so that
+    /// repeated decay passes don't compound their rounding
+    pub event_score: i64,
+    /// when the last event happened
+    pub last_update: u64,
+}
+
+impl PeerReputation {
+    /// What this reputation's score decays to at time `now`, given a half-life in seconds.
+    pub fn decayed_score(&self, now: u64, half_life: u64) -> i64 {
+        if half_life == 0 || now <= self.last_update {
+            return self.event_score;
+        }
+        let halvings = ((now - self.last_update) as f64) / (half_life as f64);
+        ((self.event_score as f64) * 0.5f64.powf(halvings)).trunc() as i64
+    }
+}
+
+impl FromRow<PeerReputation> for PeerReputation {
+    fn from_row<'a>(row: &'a Row) -> Result<PeerReputation, db_error> {
+        let network_id: u32 = row.get_unwrap("network_id");
+        let addrbytes: PeerAddress = PeerAddress::from_column(row, "addrbytes")?;
+        let port: u16 = row.get_unwrap("port");
+        let score: i64 = row.get_unwrap("score");
+        let event_score: i64 = row.get_unwrap("event_score");
+        let last_update = u64::from_column(row, "last_update")?;
+
+        Ok(PeerReputation {
+            network_id,
+            addrbytes,
+            port,
+            score,
+            event_score,
+            last_update,
+        })
+    }
+}
+
 // In what is likely an abuse of Sqlite, the peer database is structured such that the `frontier`
 // table stores peers keyed by a deterministically-chosen random "slot," instead of their IP/port.
 // (i.e. the slot is determined by a cryptographic the hash of the IP/port).  The reason for this
@@ -360,6 +473,36 @@ const PEERDB_INITIAL_SCHEMA: &'static [&'static str] = &[
     );"#,
 ];
 
+// Peer reputations are kept separately from the frontier.  This table was added after the
+// initial schema, so it is created on open if missing.
+const PEERDB_REPUTATION_SCHEMA: &'static [&'static str] = &[r#"
+    CREATE TABLE IF NOT EXISTS peer_reputation(
+        network_id INTEGER NOT NULL,
+        addrbytes TEXT NOT NULL,
+        port INTEGER NOT NULL,
+        score INTEGER NOT NULL,
+        event_score INTEGER NOT NULL,
+        last_update INTEGER NOT NULL,
+
+        PRIMARY KEY(network_id,addrbytes,port)
+    );"#];
+
+// Alternate addresses that peers advertise, besides the one they handshake with, along with the
+// hash of the public key they were advertised for.  Only kept for peers in the frontier.  Like the
+// reputation table, this is created on open if missing.
+const PEERDB_ADDRESSES_SCHEMA: &'static [&'static str] = &[r#"
+    CREATE TABLE IF NOT EXISTS peer_addresses(
+        network_id INTEGER NOT NULL,
+        addrbytes TEXT NOT NULL,
+        port INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        public_key_hash TEXT NOT NULL,
+        -- 1 if the peer told us itself; 0 if another peer told us
+        first_hand INTEGER NOT NULL,
+
+        PRIMARY KEY(network_id,addrbytes,port,address)
+    );"#];
+
 const PEERDB_INDEXES: &'static [&'static str] =
     &["CREATE INDEX IF NOT EXISTS peer_address_index ON frontier(network_id,addrbytes,port);"];
 
@@ -443,10 +586,30 @@ impl PeerDB {
 
         tx.commit().map_err(db_error::SqliteError)?;
 
+        self.add_reputation_table()?;
+        self.add_peer_addresses_table()?;
         self.add_indexes()?;
         Ok(())
     }
 
+    fn add_reputation_table(&mut self) -> Result<(), db_error> {
+        let tx = self.tx_begin()?;
+        for row_text in PEERDB_REPUTATION_SCHEMA {
+            tx.execute_batch(row_text).map_err(db_error::SqliteError)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn add_peer_addresses_table(&mut self) -> Result<(), db_error> {
+        let tx = self.tx_begin()?;
+        for row_text in PEERDB_ADDRESSES_SCHEMA {
+            tx.execute_batch(row_text).map_err(db_error::SqliteError)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     fn add_indexes(&mut self) -> Result<(), db_error> {
         let tx = self.tx_begin()?;
         for row_text in PEERDB_INDEXES {
@@ -606,6 +769,8 @@ impl PeerDB {
             }
         }
         if readwrite {
+            db.add_reputation_table()?;
+            db.add_peer_addresses_table()?;
             db.add_indexes()?;
         }
         Ok(db)
@@ -872,12 +1037,27 @@ impl PeerDB {
         Ok(allow_rows)
     }
 
+    /// Can this peer address be stored?  A v3 onion stand-in address can only be dialed by a
+    /// node that knows its name (i.e. from its config), so one learned from elsewhere is useless
+    /// to us, and would be useless to us after a restart.
+    fn is_storable_address(addrbytes: &PeerAddress) -> bool {
+        !addrbytes.is_onion_standin() || addrbytes.to_onion_host().is_some()
+    }
+
     /// Insert or replace a neighbor into a given slot
     pub fn insert_or_replace_peer<'a>(
         tx: &mut Transaction<'a>,
         neighbor: &Neighbor,
         slot: u32,
     ) -> Result<(), db_error> {
+        if !PeerDB::is_storable_address(&neighbor.addr.addrbytes) {
+            debug!(
+                "Will not store onion stand-in address {:?} of an unknown name",
+                &neighbor.addr
+            );
+            return Ok(());
+        }
+
         let neighbor_args: &[&dyn ToSql] = &[
             &neighbor.addr.peer_version,
             &neighbor.addr.network_id,
@@ -896,6 +1076,20 @@ impl PeerDB {
             &slot,
         ];
 
+        // the peer in this slot (if any) is evicted, and its alternate addresses go with it
+        if let Some(evicted) = PeerDB::get_peer_at(tx, neighbor.addr.network_id, slot)? {
+            if evicted.addr.addrbytes != neighbor.addr.addrbytes
+                || evicted.addr.port != neighbor.addr.port
+            {
+                PeerDB::drop_peer_addresses(
+                    tx,
+                    evicted.addr.network_id,
+                    &evicted.addr.addrbytes,
+                    evicted.addr.port,
+                )?;
+            }
+        }
+
         tx.execute("INSERT OR REPLACE INTO frontier (peer_version, network_id, addrbytes, port, public_key, expire_block_height, last_contact_time, asn, org, allowed, denied, in_degree, out_degree, initial, slot) \
                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)", neighbor_args)
             .map_err(db_error::SqliteError)?;
@@ -919,6 +1113,7 @@ impl PeerDB {
             ],
         )
         .map_err(db_error::SqliteError)?;
+        PeerDB::drop_peer_addresses(tx, network_id, peer_addr, peer_port)?;
 
         Ok(())
     }
@@ -1085,6 +1280,14 @@ impl PeerDB {
         tx: &mut Transaction<'a>,
         neighbor: &Neighbor,
     ) -> Result<bool, db_error> {
+        if !PeerDB::is_storable_address(&neighbor.addr.addrbytes) {
+            debug!(
+                "Will not store onion stand-in address {:?} of an unknown name",
+                &neighbor.addr
+            );
+            return Ok(false);
+        }
+
         let present = PeerDB::get_peer(
             tx,
             neighbor.addr.network_id,
@@ -1259,7 +1462,8 @@ impl PeerDB {
         Ok(())
     }
 
-    /// Get random neighbors, optionally always including allowed neighbors
+    /// Get random neighbors, optionally always including allowed neighbors.
+    /// Neighbors that aren't always-allowed are sampled in favor of better reputations.
     pub fn get_random_neighbors(
         conn: &DBConn,
         network_id: u32,
@@ -1300,11 +1504,12 @@ impl PeerDB {
         // fill in with non-allowed, randomly-chosen, fresh peers
         let random_peers_qry = if always_include_allowed {
             "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND denied < ?3 AND \
-                 (allowed >= 0 AND allowed <= ?4) AND (peer_version & 0x000000ff) >= ?5 ORDER BY RANDOM() LIMIT ?6".to_string()
+                 (allowed >= 0 AND allowed <= ?4) AND (peer_version & 0x000000ff) >= ?5 ORDER BY {} LIMIT ?6"
         } else {
             "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND denied < ?3 AND \
-                 (allowed < 0 OR (allowed >= 0 AND allowed <= ?4)) AND (peer_version & 0x000000ff) >= ?5 ORDER BY RANDOM() LIMIT ?6".to_string()
-        };
+                 (allowed < 0 OR (allowed >= 0 AND allowed <= ?4)) AND (peer_version & 0x000000ff) >= ?5 ORDER BY {} LIMIT ?6"
+        }
+        .replace("{}", PEER_REPUTATION_RANDOM_ORDER);
 
         let random_peers_args: &[&dyn ToSql] = &[
             &network_id,
@@ -1413,6 +1618,209 @@ impl PeerDB {
         let rows = query_rows::<Neighbor, _>(conn, &qry, NO_PARAMS)?;
         Ok(rows)
     }
+
+    /// Get a peer's reputation record, if we have one
+    pub fn get_peer_reputation(
+        conn: &DBConn,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+    ) -> Result<Option<PeerReputation>, db_error> {
+        let qry =
+            "SELECT * FROM peer_reputation WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3";
+        let args: &[&dyn ToSql] = &[&network_id, &peer_addr.to_bin(), &peer_port];
+        query_row::<PeerReputation, _>(conn, qry, args)
+    }
+
+    /// Get a peer's reputation score.  Peers we know nothing about have a score of 0.
+    pub fn get_peer_reputation_score(
+        conn: &DBConn,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+    ) -> Result<i64, db_error> {
+        Ok(
+            PeerDB::get_peer_reputation(conn, network_id, peer_addr, peer_port)?
+                .map(|rep| rep.score)
+                .unwrap_or(0),
+        )
+    }
+
+    /// Get all peer reputation records
+    pub fn get_peer_reputations(conn: &DBConn) -> Result<Vec<PeerReputation>, db_error> {
+        let qry = "SELECT * FROM peer_reputation ORDER BY score ASC, addrbytes ASC, port ASC";
+        query_rows::<PeerReputation, _>(conn, qry, NO_PARAMS)
+    }
+
+    fn put_peer_reputation<'a>(
+        tx: &mut Transaction<'a>,
+        rep: &PeerReputation,
+    ) -> Result<(), db_error> {
+        let args: &[&dyn ToSql] = &[
+            &rep.network_id,
+            &rep.addrbytes.to_bin(),
+            &rep.port,
+            &rep.score,
+            &rep.event_score,
+            &u64_to_sql(rep.last_update)?,
+        ];
+        tx.execute("INSERT OR REPLACE INTO peer_reputation (network_id, addrbytes, port, score, event_score, last_update) VALUES (?1,?2,?3,?4,?5,?6)", args)
+            .map_err(db_error::SqliteError)?;
+        Ok(())
+    }
+
+    /// Add `delta` to a peer's reputation score, after first decaying it to `now`.  The score is
+    /// kept within [PEER_REPUTATION_MIN, PEER_REPUTATION_MAX].
+    /// Returns the new score.
+    pub fn update_peer_reputation<'a>(
+        tx: &mut Transaction<'a>,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+        delta: i64,
+        now: u64,
+        half_life: u64,
+    ) -> Result<i64, db_error> {
+        let old_score = match PeerDB::get_peer_reputation(tx, network_id, peer_addr, peer_port)? {
+            Some(rep) => rep.decayed_score(now, half_life),
+            None => 0,
+        };
+        let score = old_score
+            .saturating_add(delta)
+            .max(PEER_REPUTATION_MIN)
+            .min(PEER_REPUTATION_MAX);
+
+        PeerDB::put_peer_reputation(
+            tx,
+            &PeerReputation {
+                network_id,
+                addrbytes: peer_addr.clone(),
+                port: peer_port,
+                score,
+                event_score: score,
+                last_update: now,
+            },
+        )?;
+        Ok(score)
+    }
+
+    /// Decay every peer's reputation to `now`, and forget peers whose scores have decayed to 0.
+    /// Each score is decayed from the peer's last event, not from the previous pass.
+    pub fn decay_peer_reputations<'a>(
+        tx: &mut Transaction<'a>,
+        now: u64,
+        half_life: u64,
+    ) -> Result<(), db_error> {
+        for mut rep in PeerDB::get_peer_reputations(tx)?.into_iter() {
+            let score = rep.decayed_score(now, half_life);
+            if score == 0 {
+                let args: &[&dyn ToSql] = &[&rep.network_id, &rep.addrbytes.to_bin(), &rep.port];
+                tx.execute(
+                    "DELETE FROM peer_reputation WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3",
+                    args,
+                )
+                .map_err(db_error::SqliteError)?;
+            } else if score != rep.score {
+                rep.score = score;
+                PeerDB::put_peer_reputation(tx, &rep)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the alternate addresses a peer has advertised to us
+    pub fn get_peer_addresses(
+        conn: &DBConn,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+    ) -> Result<Vec<PeerHost>, db_error> {
+        let qry = "SELECT address FROM peer_addresses WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3 ORDER BY address ASC";
+        let args: &[&dyn ToSql] = &[&network_id, &peer_addr.to_bin(), &peer_port];
+        query_row_columns::<PeerHost, _>(conn, qry, args, "address")
+    }
+
+    /// Get the hash of the public key a peer's alternate addresses were advertised for, and
+    /// whether or not the peer advertised them itself.
+    pub fn get_peer_addresses_origin(
+        conn: &DBConn,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+    ) -> Result<Option<(Hash160, bool)>, db_error> {
+        let qry = "SELECT public_key_hash, first_hand FROM peer_addresses WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3 LIMIT 1";
+        let args: &[&dyn ToSql] = &[&network_id, &peer_addr.to_bin(), &peer_port];
+        let mut stmt = conn.prepare(qry).map_err(db_error::SqliteError)?;
+        let mut rows = stmt.query(args).map_err(db_error::SqliteError)?;
+        match rows.next().map_err(db_error::SqliteError)? {
+            Some(row) => {
+                let public_key_hash_hex: String = row.get_unwrap("public_key_hash");
+                let first_hand: bool = row.get_unwrap("first_hand");
+                let public_key_hash =
+                    Hash160::from_hex(&public_key_hash_hex).map_err(|_| db_error::ParseError)?;
+                Ok(Some((public_key_hash, first_hand)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Count the peers whose alternate addresses we only know second-hand
+    pub fn count_second_hand_peer_addresses(
+        conn: &DBConn,
+        network_id: u32,
+    ) -> Result<u64, db_error> {
+        let qry = "SELECT COUNT(*) FROM (SELECT DISTINCT addrbytes, port FROM peer_addresses WHERE network_id = ?1 AND first_hand = 0)";
+        let args: &[&dyn ToSql] = &[&network_id];
+        let count = query_count(conn, qry, args)?;
+        Ok(count as u64)
+    }
+
+    /// Replace the alternate addresses advertised for the peer at `neighbor_addr`.  The peer's
+    /// own address is never stored as one of its alternates.  `first_hand` is true if the peer
+    /// told us itself.
+    pub fn set_peer_addresses<'a>(
+        tx: &mut Transaction<'a>,
+        network_id: u32,
+        neighbor_addr: &NeighborAddress,
+        addresses: &[PeerHost],
+        first_hand: bool,
+    ) -> Result<(), db_error> {
+        PeerDB::drop_peer_addresses(tx, network_id, &neighbor_addr.addrbytes, neighbor_addr.port)?;
+
+        let own_addr = PeerHost::IP(neighbor_addr.addrbytes.clone(), neighbor_addr.port);
+        for address in addresses.iter().take(MAX_PEER_ADDRESSES as usize) {
+            if *address == own_addr {
+                continue;
+            }
+            let args: &[&dyn ToSql] = &[
+                &network_id,
+                &neighbor_addr.addrbytes.to_bin(),
+                &neighbor_addr.port,
+                &address.to_string(),
+                &neighbor_addr.public_key_hash.to_hex(),
+                &first_hand,
+            ];
+            tx.execute("INSERT OR REPLACE INTO peer_addresses (network_id, addrbytes, port, address, public_key_hash, first_hand) VALUES (?1,?2,?3,?4,?5,?6)", args)
+                .map_err(db_error::SqliteError)?;
+        }
+        Ok(())
+    }
+
+    /// Forget a peer's alternate addresses
+    pub fn drop_peer_addresses<'a>(
+        tx: &mut Transaction<'a>,
+        network_id: u32,
+        peer_addr: &PeerAddress,
+        peer_port: u16,
+    ) -> Result<(), db_error> {
+        let args: &[&dyn ToSql] = &[&network_id, &peer_addr.to_bin(), &peer_port];
+        tx.execute(
+            "DELETE FROM peer_addresses WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3",
+            args,
+        )
+        .map_err(db_error::SqliteError)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1597,6 +2005,58 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_try_insert_peer_onion_standin() {
+        let host = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        let known = PeerAddress::from_onion_host(host).unwrap();
+        let mut unknown = known.clone();
+        unknown.0[15] ^= 0xff;
+
+        let mut db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &vec![],
+        )
+        .unwrap();
+
+        for (i, addrbytes) in [known.clone(), unknown.clone()].iter().enumerate() {
+            let neighbor = Neighbor {
+                addr: NeighborKey {
+                    peer_version: 0x12345678,
+                    network_id: 0x9abcdef0,
+                    addrbytes: addrbytes.clone(),
+                    port: 20444,
+                },
+                public_key: Secp256k1PublicKey::from_private(&Secp256k1PrivateKey::new()),
+                expire_block: 23456,
+                last_contact_time: 1552509642,
+                allowed: -1,
+                denied: -1,
+                asn: 34567,
+                org: 45678,
+                in_degree: 1,
+                out_degree: 1,
+            };
+
+            let mut tx = db.tx_begin().unwrap();
+            let res = PeerDB::try_insert_peer(&mut tx, &neighbor).unwrap();
+            PeerDB::insert_or_replace_peer(&mut tx, &neighbor, i as u32).unwrap();
+            tx.commit().unwrap();
+
+            // only the stand-in whose name we parsed is stored
+            assert_eq!(res, i == 0);
+            assert_eq!(
+                PeerDB::get_peer(db.conn(), 0x9abcdef0, addrbytes, 20444)
+                    .unwrap()
+                    .is_some(),
+                i == 0
+            );
+        }
+    }
+
     #[test]
     fn test_initial_neighbors() {
         let mut initial_neighbors = vec![];
@@ -2392,4 +2852,357 @@ mod test {
         let local_peer = PeerDB::get_local_peer(db.conn()).unwrap();
         assert_eq!(local_peer.private_key, key2);
     }
+
+    #[test]
+    fn test_peer_addresses() {
+        let mut db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &vec![],
+        )
+        .unwrap();
+
+        let peer_addr = PeerAddress::from_ipv4(1, 2, 3, 4);
+        let neighbor_addr = NeighborAddress {
+            addrbytes: peer_addr.clone(),
+            port: 20444,
+            public_key_hash: Hash160([0x11; 20]),
+        };
+        let addresses = vec![
+            PeerHost::IP(PeerAddress([0x20; 16]), 20444),
+            PeerHost::DNS("node.example.com".to_string(), 20444),
+            // skipped -- this is the peer's own address
+            PeerHost::IP(peer_addr.clone(), 20444),
+        ];
+
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            PeerDB::get_peer_addresses_origin(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            None
+        );
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_peer_addresses(&mut tx, 0x9abcdef0, &neighbor_addr, &addresses, false)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let stored = PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.contains(&addresses[0]));
+        assert!(stored.contains(&addresses[1]));
+        assert_eq!(
+            PeerDB::get_peer_addresses_origin(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            Some((Hash160([0x11; 20]), false))
+        );
+        assert_eq!(
+            PeerDB::count_second_hand_peer_addresses(db.conn(), 0x9abcdef0).unwrap(),
+            1
+        );
+
+        // other peers and networks are unaffected
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20445).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef1, &peer_addr, 20444).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            PeerDB::count_second_hand_peer_addresses(db.conn(), 0x9abcdef1).unwrap(),
+            0
+        );
+
+        // addresses are replaced, not merged
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_peer_addresses(&mut tx, 0x9abcdef0, &neighbor_addr, &addresses[1..2], true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            vec![addresses[1].clone()]
+        );
+        assert_eq!(
+            PeerDB::get_peer_addresses_origin(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            Some((Hash160([0x11; 20]), true))
+        );
+        assert_eq!(
+            PeerDB::count_second_hand_peer_addresses(db.conn(), 0x9abcdef0).unwrap(),
+            0
+        );
+
+        // addresses are dropped when the peer is evicted from the frontier
+        let mut neighbor = Neighbor {
+            addr: NeighborKey {
+                peer_version: 0x12345678,
+                network_id: 0x9abcdef0,
+                addrbytes: peer_addr.clone(),
+                port: 20444,
+            },
+            public_key: Secp256k1PublicKey::from_hex(
+                "02fa66b66f8971a8cd4d20ffded09674e030f0f33883f337f34b95ad4935bac0e3",
+            )
+            .unwrap(),
+            expire_block: 23456,
+            last_contact_time: 1552509642,
+            allowed: -1,
+            denied: -1,
+            asn: 34567,
+            org: 45678,
+            in_degree: 1,
+            out_degree: 1,
+        };
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::insert_or_replace_peer(&mut tx, &neighbor, 0).unwrap();
+
+            // updating the peer in its own slot keeps them
+            PeerDB::insert_or_replace_peer(&mut tx, &neighbor, 0).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            vec![addresses[1].clone()]
+        );
+
+        neighbor.addr.port = 20445;
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::insert_or_replace_peer(&mut tx, &neighbor, 0).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20444).unwrap(),
+            vec![]
+        );
+
+        // ...or dropped from it
+        let neighbor_addr = NeighborAddress {
+            addrbytes: peer_addr.clone(),
+            port: 20445,
+            public_key_hash: Hash160([0x11; 20]),
+        };
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_peer_addresses(&mut tx, 0x9abcdef0, &neighbor_addr, &addresses, true)
+                .unwrap();
+            PeerDB::drop_peer(&mut tx, 0x9abcdef0, &peer_addr, 20445).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(
+            PeerDB::get_peer_addresses(db.conn(), 0x9abcdef0, &peer_addr, 20445).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_peer_reputation() {
+        let mut db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &vec![],
+        )
+        .unwrap();
+
+        let addr = PeerAddress::from_ipv4(1, 2, 3, 4);
+        let half_life = 1000;
+
+        assert_eq!(
+            PeerDB::get_peer_reputation(db.conn(), 0x9abcdef0, &addr, 12345).unwrap(),
+            None
+        );
+        assert_eq!(
+            PeerDB::get_peer_reputation_score(db.conn(), 0x9abcdef0, &addr, 12345).unwrap(),
+            0
+        );
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            let score = PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &addr,
+                12345,
+                ReputationEvent::InvalidBlock.score_delta(),
+                1000,
+                half_life,
+            )
+            .unwrap();
+            assert_eq!(score, -200);
+
+            // a different port is a different peer
+            let score = PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &addr,
+                12346,
+                ReputationEvent::ValidBlockPush.score_delta(),
+                1000,
+                half_life,
+            )
+            .unwrap();
+            assert_eq!(score, 5);
+
+            // score decays by half before the next event is applied
+            let score = PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &addr,
+                12345,
+                ReputationEvent::InvalidMessage.score_delta(),
+                2000,
+                half_life,
+            )
+            .unwrap();
+            assert_eq!(score, -150);
+
+            // scores are clamped
+            let score = PeerDB::update_peer_reputation(
+                &mut tx, 0x9abcdef0, &addr, 12345, -10000, 2000, half_life,
+            )
+            .unwrap();
+            assert_eq!(score, PEER_REPUTATION_MIN);
+            tx.commit().unwrap();
+        }
+
+        let rep = PeerDB::get_peer_reputation(db.conn(), 0x9abcdef0, &addr, 12345)
+            .unwrap()
+            .unwrap();
+        assert_eq!(rep.score, PEER_REPUTATION_MIN);
+        assert_eq!(rep.last_update, 2000);
+        assert_eq!(rep.decayed_score(3000, half_life), PEER_REPUTATION_MIN / 2);
+        assert_eq!(PeerDB::get_peer_reputations(db.conn()).unwrap().len(), 2);
+
+        // decay everything; the small score decays to 0 and is forgotten
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::decay_peer_reputations(&mut tx, 5000, half_life).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let reps = PeerDB::get_peer_reputations(db.conn()).unwrap();
+        assert_eq!(reps.len(), 1);
+        assert_eq!(reps[0].port, 12345);
+        assert_eq!(reps[0].score, PEER_REPUTATION_MIN / 8);
+        assert_eq!(reps[0].event_score, PEER_REPUTATION_MIN);
+        assert_eq!(reps[0].last_update, 2000);
+        assert_eq!(
+            PeerDB::get_peer_reputation_score(db.conn(), 0x9abcdef0, &addr, 12346).unwrap(),
+            0
+        );
+
+        // frequent decay passes don't compound their rounding: a small score decays no faster
+        // when decayed often than when decayed once
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &addr,
+                12346,
+                ReputationEvent::ValidBlockPush.score_delta(),
+                5000,
+                half_life,
+            )
+            .unwrap();
+            for now in (5000..5700).step_by(50) {
+                PeerDB::decay_peer_reputations(&mut tx, now, half_life).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+        let rep = PeerDB::get_peer_reputation(db.conn(), 0x9abcdef0, &addr, 12346)
+            .unwrap()
+            .unwrap();
+        assert_eq!(rep.score, 3);
+        assert_eq!(rep.score, rep.decayed_score(5650, half_life));
+    }
+
+    #[test]
+    fn test_get_random_neighbors_by_reputation() {
+        let mut initial_neighbors = vec![];
+        for i in 0..2 {
+            initial_neighbors.push(Neighbor {
+                addr: NeighborKey {
+                    peer_version: 0x18000000,
+                    network_id: 0x9abcdef0,
+                    addrbytes: PeerAddress([i as u8; 16]),
+                    port: i,
+                },
+                public_key: Secp256k1PublicKey::from_private(&Secp256k1PrivateKey::new()),
+                expire_block: (i + 23456) as u64,
+                last_contact_time: (1552509642 + (i as u64)) as u64,
+                allowed: -1,
+                denied: -1,
+                asn: (34567 + i) as u32,
+                org: (45678 + i) as u32,
+                in_degree: 1,
+                out_degree: 1,
+            });
+        }
+
+        let mut db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &initial_neighbors,
+        )
+        .unwrap();
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &initial_neighbors[0].addr.addrbytes,
+                initial_neighbors[0].addr.port,
+                PEER_REPUTATION_MIN,
+                1000,
+                1000,
+            )
+            .unwrap();
+            PeerDB::update_peer_reputation(
+                &mut tx,
+                0x9abcdef0,
+                &initial_neighbors[1].addr.addrbytes,
+                initial_neighbors[1].addr.port,
+                PEER_REPUTATION_MAX,
+                1000,
+                1000,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        // the well-behaved peer is almost always chosen over the misbehaving one
+        let mut num_good = 0;
+        for _ in 0..100 {
+            let n1 =
+                PeerDB::get_random_neighbors(db.conn(), 0x9abcdef0, 0x00, 1, 23455, false).unwrap();
+            assert_eq!(n1.len(), 1);
+            if n1[0].addr == initial_neighbors[1].addr {
+                num_good += 1;
+            }
+        }
+        assert!(num_good >= 95);
+
+        // but the misbehaving one can still be chosen
+        let n2 =
+            PeerDB::get_random_neighbors(db.conn(), 0x9abcdef0, 0x00, 2, 23455, false).unwrap();
+        assert_eq!(n2.len(), 2);
+    }
 }
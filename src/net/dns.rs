@@ -35,12 +35,14 @@ use crate::net::NeighborKey;
 use crate::net::PeerAddress;
 
 use crate::net::codec::*;
+use crate::net::socks;
 use crate::net::*;
 
 use crate::util_lib::db::Error as db_error;
 use stacks_common::util::sleep_ms;
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -122,6 +124,9 @@ pub struct DNSResolver {
 
     // used mainly for testing
     hardcoded: HashMap<(String, u16), Vec<SocketAddr>>,
+
+    // if set, look up names through this SOCKS5 proxy instead of the local resolver
+    proxy: Option<SocketAddr>,
 }
 
 /// The DNSClient provides an API to send DNS requests and poll DNS results. The client forwards
@@ -145,10 +150,17 @@ impl DNSResolver {
             outbound: dns_chan_tx,
             max_inflight: max_inflight,
             hardcoded: HashMap::new(),
+            proxy: None,
         };
         (resolver, client)
     }
 
+    /// Resolve names through a SOCKS5 proxy that supports Tor's RESOLVE extension, so the lookups
+    /// don't leak to the local resolver.
+    pub fn set_proxy(&mut self, proxy: Option<SocketAddr>) -> () {
+        self.proxy = proxy;
+    }
+
     pub fn add_hardcoded(&mut self, host: &str, port: u16, addrs: Vec<SocketAddr>) -> () {
         self.hardcoded.insert((host.to_string(), port), addrs);
     }
@@ -158,6 +170,30 @@ impl DNSResolver {
             return DNSResponse::new(req, Ok(addrs.to_vec()));
         }
 
+        // onion names "resolve" to their onion addresses, which the proxy will turn back into
+        // names when we connect
+        if let Some(addrbytes) = PeerAddress::from_onion_host(&req.host) {
+            let port = req.port;
+            return DNSResponse::new(req, Ok(vec![addrbytes.to_socketaddr(port)]));
+        }
+
+        if let Some(ref proxy) = self.proxy {
+            test_debug!("Resolve {}:{} via proxy {}", &req.host, req.port, proxy);
+            let timeout = Duration::from_millis(
+                req.timeout
+                    .saturating_sub(get_epoch_time_ms())
+                    .max(1)
+                    .min(u64::MAX as u128) as u64,
+            );
+            return match socks::resolve_blocking(proxy, &req.host, timeout) {
+                Ok(ip) => {
+                    let port = req.port;
+                    DNSResponse::new(req, Ok(vec![SocketAddr::new(ip, port)]))
+                }
+                Err(e) => DNSResponse::error(req, format!("DNS resolve error: {:?}", &e)),
+            };
+        }
+
         // TODO: this is a blocking operation, but there's not really a good solution here other
         // than to just do this in a separate thread :shrug:
         test_debug!("Resolve {}:{}", &req.host, req.port);
@@ -378,10 +414,14 @@ impl DNSClient {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use crate::net::socks::test::TestSocksProxy;
+    use crate::net::socks::SocksTarget;
     use crate::net::test::*;
     use stacks_common::util::*;
     use std::collections::HashMap;
     use std::error::Error;
+    use std::net::{IpAddr, Ipv4Addr};
 
     #[test]
     fn dns_start_stop() {
@@ -390,6 +430,63 @@ mod test {
         thread_handle.join().unwrap();
     }
 
+    #[test]
+    fn dns_resolve_through_proxy() {
+        let mut hosts = HashMap::new();
+        hosts.insert(
+            "seed.example.com".to_string(),
+            IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+        );
+        let proxy = TestSocksProxy::spawn(hosts);
+
+        let (mut resolver, _client) = DNSResolver::new(10);
+        resolver.set_proxy(Some(proxy.addr.clone()));
+
+        let deadline = get_epoch_time_ms() + 10_000;
+        let resp = resolver.resolve(DNSRequest::new(
+            "seed.example.com".to_string(),
+            20444,
+            deadline,
+        ));
+        assert_eq!(
+            resp.result,
+            Ok(vec![SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+                20444
+            )])
+        );
+
+        let resp = resolver.resolve(DNSRequest::new(
+            "nope.example.com".to_string(),
+            20444,
+            deadline,
+        ));
+        assert!(resp.result.is_err());
+
+        // onion names never go to the proxy (or the local resolver)
+        let resp = resolver.resolve(DNSRequest::new(
+            "expyuzz4wqqyqhjn.onion".to_string(),
+            20444,
+            deadline,
+        ));
+        let addrs = resp.result.unwrap();
+        assert_eq!(addrs.len(), 1);
+        let addrbytes = PeerAddress::from_socketaddr(&addrs[0]);
+        assert!(addrbytes.is_onion());
+        assert_eq!(
+            addrbytes.to_onion_host(),
+            Some("expyuzz4wqqyqhjn.onion".to_string())
+        );
+
+        assert_eq!(
+            proxy.get_targets(),
+            vec![
+                SocksTarget::Host("seed.example.com".to_string(), 0),
+                SocksTarget::Host("nope.example.com".to_string(), 0)
+            ]
+        );
+    }
+
     #[test]
     fn dns_resolve_one_name() {
         let (mut client, thread_handle) = dns_thread_start(100);
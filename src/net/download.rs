@@ -17,6 +17,7 @@
  along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
 */
 
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
@@ -186,10 +187,65 @@ impl std::fmt::Display for BlockRequestKey {
     }
 }
 
+/// A request for a contiguous range of anchored blocks (and the microblock streams they confirm)
+/// from a trusted archive node.
+#[derive(Debug, PartialEq, Clone, Hash, Eq)]
+pub struct BlockRangeRequestKey {
+    pub data_url: UrlString,
+    pub start_height: u64,
+    pub end_height: u64,
+    pub download_start: u64,
+    pub canonical_stacks_tip_height: u64,
+}
+
+impl BlockRangeRequestKey {
+    pub fn new(
+        data_url: UrlString,
+        start_height: u64,
+        end_height: u64,
+        canonical_stacks_tip_height: u64,
+    ) -> BlockRangeRequestKey {
+        BlockRangeRequestKey {
+            data_url,
+            start_height,
+            end_height,
+            download_start: get_epoch_time_secs(),
+            canonical_stacks_tip_height,
+        }
+    }
+}
+
+impl Requestable for BlockRangeRequestKey {
+    fn get_url(&self) -> &UrlString {
+        &self.data_url
+    }
+
+    fn make_request_type(&self, peer_host: PeerHost) -> HttpRequestType {
+        HttpRequestType::GetBlockRange(
+            HttpRequestMetadata::from_host(peer_host, Some(self.canonical_stacks_tip_height)),
+            self.start_height,
+            self.end_height,
+            TipRequest::UseLatestAnchoredTip,
+        )
+    }
+}
+
+impl std::fmt::Display for BlockRangeRequestKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<Request<BlockRange>: {}-{} {:?}>",
+            self.start_height, self.end_height, self.data_url
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub enum BlockDownloaderState {
     DNSLookupBegin,
     DNSLookupFinish,
+    GetBlockRangeBegin,
+    GetBlockRangeFinish,
     GetBlocksBegin,
     GetBlocksFinish,
     GetMicroblocksBegin,
@@ -242,6 +298,17 @@ pub struct BlockDownloader {
     blocks: HashMap<BlockRequestKey, StacksBlock>,
     microblocks: HashMap<BlockRequestKey, Vec<StacksMicroblock>>,
 
+    /// In-flight request for a range of blocks from the archive node, and what it gave us.
+    /// Range blocks are (consensus hash, block, download time); range microblocks are (parent
+    /// consensus hash, stream, download time).
+    getblockrange_request: Option<(BlockRangeRequestKey, usize)>,
+    range_blocks: Vec<(ConsensusHash, StacksBlock, u64)>,
+    range_microblocks: Vec<(ConsensusHash, Vec<StacksMicroblock>, u64)>,
+
+    /// Stacks block height at which to start the next range request (0 means "just past our
+    /// canonical Stacks tip")
+    block_range_next_height: u64,
+
     /// statistics on peers' data-plane endpoints
     dead_peers: Vec<usize>,
     broken_peers: Vec<usize>,
@@ -292,6 +359,11 @@ impl BlockDownloader {
             blocks: HashMap::new(),
             microblocks: HashMap::new(),
 
+            getblockrange_request: None,
+            range_blocks: vec![],
+            range_microblocks: vec![],
+            block_range_next_height: 0,
+
             dead_peers: vec![],
             broken_peers: vec![],
             broken_neighbors: vec![],
@@ -317,12 +389,17 @@ impl BlockDownloader {
         self.blocks.clear();
         self.microblocks.clear();
 
+        self.getblockrange_request = None;
+        self.range_blocks.clear();
+        self.range_microblocks.clear();
+
         self.dead_peers.clear();
         self.broken_peers.clear();
         self.broken_neighbors.clear();
 
         // perserve sortition height
         // preserve download accounting
+        // preserve block range height
     }
 
     pub fn restart_scan(&mut self, sortition_start: u64) -> () {
@@ -424,12 +501,266 @@ impl BlockDownloader {
         if inflight == 0 {
             // done with DNS
             dns_client.clear_all_requests();
-            self.state = BlockDownloaderState::GetBlocksBegin;
+            self.state = BlockDownloaderState::GetBlockRangeBegin;
         }
 
         Ok(inflight == 0)
     }
 
+    /// Start fetching a range of blocks from the archive node, if we're asking for one.
+    pub fn getblockrange_begin(&mut self, request: Option<(BlockRangeRequestKey, usize)>) -> () {
+        assert_eq!(self.state, BlockDownloaderState::GetBlockRangeBegin);
+
+        self.state = if request.is_some() {
+            BlockDownloaderState::GetBlockRangeFinish
+        } else {
+            BlockDownloaderState::GetBlocksBegin
+        };
+        self.getblockrange_request = request;
+    }
+
+    /// Finish fetching a range of blocks from the archive node.  Return true once the reply
+    /// handle has been fulfilled (either with data, or with an error).
+    /// Blocks and microblock streams we get this way are not requested again from our
+    /// neighbors in this pass.
+    pub fn getblockrange_try_finish(
+        &mut self,
+        network: &mut PeerNetwork,
+        chainstate: &StacksChainState,
+    ) -> Result<bool, net_error> {
+        assert_eq!(self.state, BlockDownloaderState::GetBlockRangeFinish);
+
+        let (range_key, event_id) = match self.getblockrange_request.take() {
+            Some(request) => request,
+            None => {
+                self.state = BlockDownloaderState::GetBlocksBegin;
+                return Ok(true);
+            }
+        };
+
+        let mut pending = false;
+        let mut failed = false;
+        let mut entries = None;
+
+        PeerNetwork::with_http(network, |ref mut _network, ref mut http| {
+            match http.get_conversation(event_id) {
+                None => {
+                    if http.is_connecting(event_id) {
+                        debug!(
+                            "Event {} ({:?} for blocks {}-{}) is not connected yet",
+                            event_id,
+                            &range_key.data_url,
+                            range_key.start_height,
+                            range_key.end_height
+                        );
+                        pending = true;
+                    } else {
+                        debug!(
+                            "Event {} ({:?} for blocks {}-{}) failed to connect",
+                            event_id,
+                            &range_key.data_url,
+                            range_key.start_height,
+                            range_key.end_height
+                        );
+                        self.dead_peers.push(event_id);
+                        failed = true;
+                    }
+                }
+                Some(ref mut convo) => match convo.try_get_response() {
+                    None => {
+                        // still waiting
+                        debug!(
+                            "Event {} ({:?} for blocks {}-{}) is still waiting for a response",
+                            event_id,
+                            &range_key.data_url,
+                            range_key.start_height,
+                            range_key.end_height
+                        );
+                        pending = true;
+                    }
+                    Some(HttpResponseType::BlockRange(_md, range)) => {
+                        entries = Some(range);
+                    }
+                    Some(HttpResponseType::NotFound(_, msg)) => {
+                        info!(
+                            "Archive {:?} does not have blocks {}-{}: {}",
+                            &range_key.data_url, range_key.start_height, range_key.end_height, msg
+                        );
+                        failed = true;
+                    }
+                    Some(http_response) => {
+                        // wrong message response
+                        info!(
+                            "Got bad HTTP response from {:?}: {:?}",
+                            &range_key.data_url, &http_response
+                        );
+                        self.broken_peers.push(event_id);
+                        failed = true;
+                    }
+                },
+            }
+        });
+
+        if pending {
+            self.getblockrange_request = Some((range_key, event_id));
+            return Ok(false);
+        }
+
+        if let Some(entries) = entries {
+            if entries.len() == 0 {
+                // archive has nothing past this height yet
+                debug!(
+                    "Archive {:?} has no blocks starting at {}",
+                    &range_key.data_url, range_key.start_height
+                );
+                self.block_range_next_height = 0;
+            } else if !self.store_block_range(&range_key, entries, chainstate)? {
+                failed = true;
+            }
+        }
+
+        if failed {
+            // don't try this again for a while
+            self.blocked_urls.insert(
+                range_key.data_url,
+                get_epoch_time_secs() + BLOCK_DOWNLOAD_BAN_URL,
+            );
+            self.block_range_next_height = 0;
+        }
+
+        self.state = BlockDownloaderState::GetBlocksBegin;
+        Ok(true)
+    }
+
+    /// Check and store a range of blocks from the archive node, and drop the block and
+    /// microblock requests it fulfills.  The blocks must be a chain, and each confirmed
+    /// microblock stream must connect its parent block to its child.  Invalid streams are
+    /// skipped (and so will be asked of our neighbors instead).
+    /// Returns Ok(false) if the range is not a chain.
+    fn store_block_range(
+        &mut self,
+        range_key: &BlockRangeRequestKey,
+        entries: Vec<BlockRangeEntry>,
+        chainstate: &StacksChainState,
+    ) -> Result<bool, net_error> {
+        if (entries.len() as u64) > range_key.end_height + 1 - range_key.start_height {
+            info!(
+                "Archive {:?} sent {} blocks for range {}-{}",
+                &range_key.data_url,
+                entries.len(),
+                range_key.start_height,
+                range_key.end_height
+            );
+            return Ok(false);
+        }
+        for i in 1..entries.len() {
+            if entries[i].parent_consensus_hash != entries[i - 1].consensus_hash
+                || entries[i].block.header.parent_block != entries[i - 1].block.block_hash()
+            {
+                info!(
+                    "Archive {:?} sent a non-contiguous range at {}/{}",
+                    &range_key.data_url,
+                    &entries[i].consensus_hash,
+                    entries[i].block.block_hash()
+                );
+                return Ok(false);
+            }
+        }
+
+        let download_time = get_epoch_time_secs().saturating_sub(range_key.download_start);
+        let mut parent_block_header = match entries.first() {
+            Some(entry) => StacksChainState::load_block_header(
+                &chainstate.blocks_path,
+                &entry.parent_consensus_hash,
+                &entry.block.header.parent_block,
+            )?,
+            None => None,
+        };
+
+        let mut got_blocks = HashSet::new();
+        let mut got_microblocks = HashSet::new();
+        let num_entries = entries.len() as u64;
+
+        for entry in entries.into_iter() {
+            let BlockRangeEntry {
+                consensus_hash,
+                parent_consensus_hash,
+                block,
+                mut confirmed_microblocks,
+            } = entry;
+            let index_block_hash =
+                StacksBlockHeader::make_index_block_hash(&consensus_hash, &block.block_hash());
+
+            if confirmed_microblocks.len() > 0 {
+                // NOTE: microblock streams are served in reverse order, since they're forks
+                confirmed_microblocks.reverse();
+                let valid = match parent_block_header {
+                    Some(ref parent_block_header) => {
+                        StacksChainState::validate_parent_microblock_stream(
+                            parent_block_header,
+                            &block.header,
+                            &confirmed_microblocks,
+                            true,
+                        )
+                        .is_some()
+                    }
+                    None => false,
+                };
+                if valid {
+                    got_microblocks.insert(index_block_hash.clone());
+                    self.range_microblocks.push((
+                        parent_consensus_hash,
+                        confirmed_microblocks,
+                        download_time,
+                    ));
+                } else {
+                    debug!(
+                        "Microblock stream confirmed by {}/{} from archive {:?} is invalid",
+                        &consensus_hash,
+                        block.block_hash(),
+                        &range_key.data_url
+                    );
+                }
+            }
+
+            debug!(
+                "Got block {}/{} ({}) from archive {:?}",
+                &consensus_hash,
+                block.block_hash(),
+                &index_block_hash,
+                &range_key.data_url
+            );
+            parent_block_header = Some(block.header.clone());
+            got_blocks.insert(index_block_hash);
+            self.range_blocks
+                .push((consensus_hash, block, download_time));
+        }
+
+        // these could still be buffered up for storage, so don't ask for them again for a while
+        let now = get_epoch_time_secs();
+        for index_block_hash in got_blocks.iter() {
+            self.requested_blocks.insert(index_block_hash.clone(), now);
+        }
+        for index_block_hash in got_microblocks.iter() {
+            self.requested_microblocks
+                .insert(index_block_hash.clone(), now);
+        }
+
+        self.blocks_to_try
+            .retain(|_, requests| match requests.front() {
+                Some(request) => !got_blocks.contains(&request.index_block_hash),
+                None => true,
+            });
+        self.microblocks_to_try
+            .retain(|_, requests| match requests.front() {
+                Some(request) => !got_microblocks.contains(&request.index_block_hash),
+                None => true,
+            });
+
+        self.block_range_next_height = range_key.start_height + num_entries;
+        Ok(true)
+    }
+
     pub fn getblocks_begin(&mut self, requests: HashMap<BlockRequestKey, usize>) -> () {
         assert_eq!(self.state, BlockDownloaderState::GetBlocksBegin);
 
@@ -462,6 +793,10 @@ impl BlockDownloader {
                             pending_block_requests.insert(block_key, event_id);
                         } else {
                             self.dead_peers.push(event_id);
+                            network.record_reputation_event(
+                                &block_key.neighbor,
+                                ReputationEvent::Unreachable,
+                            );
 
                             // try again
                             self.requested_blocks.remove(&block_key.index_block_hash);
@@ -512,6 +847,10 @@ impl BlockDownloader {
                                         info!("Invalid block from {:?} ({:?}): did not ask for block {}/{}", &block_key.neighbor, &block_key.data_url, block_key.consensus_hash, block.block_hash());
                                         self.broken_peers.push(event_id);
                                         self.broken_neighbors.push(block_key.neighbor.clone());
+                                        network.record_reputation_event(
+                                            &block_key.neighbor,
+                                            ReputationEvent::InvalidMessage,
+                                        );
                                     } else {
                                         // got the block
                                         debug!(
@@ -520,6 +859,10 @@ impl BlockDownloader {
                                             &block_key.consensus_hash,
                                             block.block_hash()
                                         );
+                                        network.record_reputation_event(
+                                            &block_key.neighbor,
+                                            ReputationEvent::BlockDownloaded,
+                                        );
                                         self.blocks.insert(block_key, block);
                                     }
                                 }
@@ -532,6 +875,10 @@ impl BlockDownloader {
                                     // it was present, so the absence is the mark of a broken peer
                                     self.broken_peers.push(event_id);
                                     self.broken_neighbors.push(block_key.neighbor.clone());
+                                    network.record_reputation_event(
+                                        &block_key.neighbor,
+                                        ReputationEvent::StaleInventory,
+                                    );
                                 }
                                 _ => {
                                     // wrong message response
@@ -541,6 +888,10 @@ impl BlockDownloader {
                                     );
                                     self.broken_peers.push(event_id);
                                     self.broken_neighbors.push(block_key.neighbor.clone());
+                                    network.record_reputation_event(
+                                        &block_key.neighbor,
+                                        ReputationEvent::InvalidMessage,
+                                    );
                                 }
                             },
                         }
@@ -589,6 +940,10 @@ impl BlockDownloader {
                             pending_microblock_requests.insert(block_key, event_id);
                         } else {
                             self.dead_peers.push(event_id);
+                            network.record_reputation_event(
+                                &block_key.neighbor,
+                                ReputationEvent::Unreachable,
+                            );
 
                             // try again
                             self.requested_microblocks
@@ -635,6 +990,10 @@ impl BlockDownloader {
                                         info!("Got unexpected zero-length microblock stream from {:?} ({:?})", &block_key.neighbor, &block_key.data_url);
                                         self.broken_peers.push(event_id);
                                         self.broken_neighbors.push(block_key.neighbor.clone());
+                                        network.record_reputation_event(
+                                            &block_key.neighbor,
+                                            ReputationEvent::InvalidMessage,
+                                        );
                                     } else {
                                         // have microblocks (but we don't know yet if they're well-formed)
                                         debug!(
@@ -644,6 +1003,10 @@ impl BlockDownloader {
                                             &block_key.index_block_hash,
                                             microblocks[0].block_hash()
                                         );
+                                        network.record_reputation_event(
+                                            &block_key.neighbor,
+                                            ReputationEvent::BlockDownloaded,
+                                        );
                                         self.microblocks.insert(block_key, microblocks);
                                     }
                                 }
@@ -658,13 +1021,21 @@ impl BlockDownloader {
                                     // HOWEVER, there has been some bugs recently about nodes reporting
                                     // invalid microblock streams as present, even though they are
                                     // truly absent.  Don't punish these peers with a ban; just don't
-                                    // talk to them for a while.
+                                    // talk to them for a while, and count it against their reputation.
+                                    network.record_reputation_event(
+                                        &block_key.neighbor,
+                                        ReputationEvent::StaleInventory,
+                                    );
                                 }
                                 _ => {
                                     // wrong message response
                                     info!("Got bad HTTP response from {:?}", &block_key.data_url);
                                     self.broken_peers.push(event_id);
                                     self.broken_neighbors.push(block_key.neighbor.clone());
+                                    network.record_reputation_event(
+                                        &block_key.neighbor,
+                                        ReputationEvent::InvalidMessage,
+                                    );
                                 }
                             },
                         }
@@ -1091,6 +1462,16 @@ impl PeerNetwork {
             );
             return Ok(false);
         }
+        if StacksChainState::is_block_pruned(chainstate.db(), &index_block_hash)? {
+            test_debug!(
+                "{:?}: Block already processed and pruned: {}/{} ({})",
+                _local_peer,
+                consensus_hash,
+                block_hash,
+                &index_block_hash
+            );
+            return Ok(false);
+        }
         Ok(true)
     }
 
@@ -1892,6 +2273,10 @@ impl PeerNetwork {
                 }
             }
 
+            if let Some(ref archive_data_url) = network.connection_opts.archive_data_url {
+                urlset.insert(archive_data_url.clone());
+            }
+
             let mut urls = vec![];
             for url in urlset.drain() {
                 urls.push(url);
@@ -2023,6 +2408,73 @@ impl PeerNetwork {
         None
     }
 
+    /// Start fetching a range of blocks from the archive node, if one is configured and we still
+    /// need blocks.
+    pub fn block_getblockrange_begin(
+        &mut self,
+        mempool: &MemPoolDB,
+        chainstate: &mut StacksChainState,
+    ) -> Result<(), net_error> {
+        test_debug!("{:?}: block_getblockrange_begin", &self.local_peer);
+        let archive_data_url = self.connection_opts.archive_data_url.clone();
+        let canonical_stacks_tip_height = self.burnchain_tip.canonical_stacks_tip_height;
+        PeerNetwork::with_downloader_state(self, |ref mut network, ref mut downloader| {
+            let archive_data_url = match archive_data_url {
+                Some(url) if downloader.blocks_to_try.len() > 0 => url,
+                _ => {
+                    downloader.getblockrange_begin(None);
+                    return Ok(());
+                }
+            };
+
+            if let Some(deadline) = downloader.blocked_urls.get(&archive_data_url) {
+                if get_epoch_time_secs() < *deadline {
+                    debug!(
+                        "{:?}: Archive {:?} is blocked until {}",
+                        &network.local_peer, &archive_data_url, deadline
+                    );
+                    downloader.getblockrange_begin(None);
+                    return Ok(());
+                }
+            }
+
+            let start_height = cmp::max(
+                canonical_stacks_tip_height + 1,
+                downloader.block_range_next_height,
+            );
+            let end_height = start_height + MAX_BLOCK_RANGE - 1;
+
+            let mut keys = VecDeque::new();
+            keys.push_back(BlockRangeRequestKey::new(
+                archive_data_url,
+                start_height,
+                end_height,
+                canonical_stacks_tip_height,
+            ));
+
+            let request = PeerNetwork::begin_request(
+                network,
+                &downloader.dns_lookups,
+                &mut keys,
+                mempool,
+                chainstate,
+            );
+            downloader.getblockrange_begin(request);
+            Ok(())
+        })
+    }
+
+    /// Try to see if the block range is finished downloading
+    pub fn block_getblockrange_try_finish(
+        &mut self,
+        chainstate: &StacksChainState,
+    ) -> Result<bool, net_error> {
+        test_debug!("{:?}: block_getblockrange_try_finish", &self.local_peer);
+        PeerNetwork::with_downloader_state(self, |ref mut network, ref mut downloader| {
+            downloader.getblockrange_try_finish(network, chainstate)
+        })
+    }
+
     /// Start fetching blocks
     pub fn block_getblocks_begin(
         &mut self,
@@ -2151,6 +2603,12 @@ impl PeerNetwork {
             .unwrap_or(0);
 
         PeerNetwork::with_downloader_state(self, |ref mut network, ref mut downloader| {
+            // extract blocks and microblocks downloaded from the archive node (already checked)
+            downloader.num_blocks_downloaded += downloader.range_blocks.len() as u64;
+            downloader.num_microblocks_downloaded += downloader.range_microblocks.len() as u64;
+            blocks.append(&mut downloader.range_blocks);
+            microblocks.append(&mut downloader.range_microblocks);
+
             // extract blocks and microblocks downloaded
             for (request_key, block) in downloader.blocks.drain() {
                 debug!(
@@ -2500,6 +2958,12 @@ impl PeerNetwork {
                 BlockDownloaderState::DNSLookupFinish => {
                     self.block_dns_lookups_try_finish(dns_client)?;
                 }
+                BlockDownloaderState::GetBlockRangeBegin => {
+                    self.block_getblockrange_begin(mempool, chainstate)?;
+                }
+                BlockDownloaderState::GetBlockRangeFinish => {
+                    self.block_getblockrange_try_finish(chainstate)?;
+                }
                 BlockDownloaderState::GetBlocksBegin => {
                     self.block_getblocks_begin(mempool, chainstate)?;
                 }
@@ -3157,6 +3621,86 @@ pub mod test {
         })
     }
 
+    #[test]
+    #[ignore]
+    pub fn test_get_blocks_and_microblocks_2_peers_download_archive_range() {
+        with_timeout(600, || {
+            let mut max_block_range_height = 0;
+            run_get_blocks_and_microblocks(
+                "test_get_blocks_and_microblocks_2_peers_download_archive_range",
+                3270,
+                2,
+                |ref mut peer_configs| {
+                    // build initial network topology
+                    assert_eq!(peer_configs.len(), 2);
+
+                    peer_configs[0].connection_opts.disable_block_advertisement = true;
+                    peer_configs[1].connection_opts.disable_block_advertisement = true;
+
+                    // peer 0 fetches history in bulk from peer 1
+                    peer_configs[0].connection_opts.archive_data_url =
+                        Some(peer_configs[1].data_url.clone());
+
+                    let peer_0 = peer_configs[0].to_neighbor();
+                    let peer_1 = peer_configs[1].to_neighbor();
+                    peer_configs[0].add_neighbor(&peer_1);
+                    peer_configs[1].add_neighbor(&peer_0);
+                },
+                |num_blocks, ref mut peers| {
+                    // build up block data to replicate
+                    let mut block_data = vec![];
+                    for _ in 0..num_blocks {
+                        let (mut burn_ops, stacks_block, microblocks) =
+                            peers[1].make_default_tenure();
+
+                        let (_, burn_header_hash, consensus_hash) =
+                            peers[1].next_burnchain_block(burn_ops.clone());
+                        peers[1].process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+
+                        TestPeer::set_ops_burn_header_hash(&mut burn_ops, &burn_header_hash);
+
+                        peers[0].next_burnchain_block_raw(burn_ops);
+
+                        let sn = SortitionDB::get_canonical_burn_chain_tip(
+                            &peers[1].sortdb.as_ref().unwrap().conn(),
+                        )
+                        .unwrap();
+                        block_data.push((
+                            sn.consensus_hash.clone(),
+                            Some(stacks_block),
+                            Some(microblocks),
+                        ));
+                    }
+                    block_data
+                },
+                |_| {},
+                |peer| {
+                    // check peer health
+                    // nothing should break, and the archive should never get blocked
+                    match peer.network.block_downloader {
+                        Some(ref dl) => {
+                            assert_eq!(dl.broken_peers.len(), 0);
+                            assert_eq!(dl.dead_peers.len(), 0);
+                            if let Some(ref archive_data_url) =
+                                peer.network.connection_opts.archive_data_url
+                            {
+                                assert!(dl.blocked_urls.get(archive_data_url).is_none());
+                            }
+                            max_block_range_height =
+                                cmp::max(max_block_range_height, dl.block_range_next_height);
+                        }
+                        None => {}
+                    }
+                    true
+                },
+                |_| true,
+            );
+
+            // the archive served at least one range
+            assert!(max_block_range_height > 1);
+        })
+    }
+
     #[test]
     #[ignore]
     pub fn test_get_blocks_and_microblocks_5_peers_star() {
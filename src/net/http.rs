@@ -24,10 +24,10 @@ use std::io;
 use std::io::prelude::*;
 use std::io::{Read, Write};
 use std::mem;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, TcpStream};
 use std::str;
 use std::str::FromStr;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use percent_encoding::percent_decode_str;
 use regex::{Captures, Regex};
@@ -39,10 +39,11 @@ use url::{form_urlencoded, Url};
 use crate::burnchains::{Address, Txid};
 use crate::chainstate::burn::ConsensusHash;
 use crate::chainstate::stacks::{
-    StacksBlock, StacksMicroblock, StacksPublicKey, StacksTransaction,
+    StacksBlock, StacksMicroblock, StacksPublicKey, StacksTransaction, MAX_BLOCK_LEN,
 };
 use crate::deps::httparse;
 use crate::net::atlas::Attachment;
+use crate::net::BlockRangeEntry;
 use crate::net::ClientError;
 use crate::net::Error as net_error;
 use crate::net::Error::ClarityError;
@@ -68,6 +69,7 @@ use crate::net::UnconfirmedTransactionStatus;
 use crate::net::HTTP_PREAMBLE_MAX_ENCODED_SIZE;
 use crate::net::HTTP_PREAMBLE_MAX_NUM_HEADERS;
 use crate::net::HTTP_REQUEST_ID_RESERVED;
+use crate::net::MAX_BLOCK_RANGE;
 use crate::net::MAX_HEADERS;
 use crate::net::MAX_MICROBLOCKS_UNCONFIRMED;
 use crate::net::{CallReadOnlyRequestBody, TipRequest};
@@ -102,7 +104,11 @@ lazy_static! {
     static ref PATH_GETPOXINFO: Regex = Regex::new(r#"^/v2/pox$"#).unwrap();
     static ref PATH_GETNEIGHBORS: Regex = Regex::new(r#"^/v2/neighbors$"#).unwrap();
     static ref PATH_GETHEADERS: Regex = Regex::new(r#"^/v2/headers/([0-9]+)$"#).unwrap();
+    static ref PATH_GETSNAPSHOTANCHOR: Regex =
+        Regex::new(r#"^/v2/snapshot/anchor/([0-9]+)$"#).unwrap();
     static ref PATH_GETBLOCK: Regex = Regex::new(r#"^/v2/blocks/([0-9a-f]{64})$"#).unwrap();
+    static ref PATH_GETBLOCKRANGE: Regex =
+        Regex::new(r#"^/v2/blocks/range/([0-9]+)/([0-9]+)$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_INDEXED: Regex =
         Regex::new(r#"^/v2/microblocks/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_CONFIRMED: Regex =
@@ -1503,7 +1509,17 @@ impl HttpRequestType {
                 &HttpRequestType::parse_getneighbors,
             ),
             ("GET", &PATH_GETHEADERS, &HttpRequestType::parse_getheaders),
+            (
+                "GET",
+                &PATH_GETSNAPSHOTANCHOR,
+                &HttpRequestType::parse_getsnapshotanchor,
+            ),
             ("GET", &PATH_GETBLOCK, &HttpRequestType::parse_getblock),
+            (
+                "GET",
+                &PATH_GETBLOCKRANGE,
+                &HttpRequestType::parse_getblockrange,
+            ),
             (
                 "GET",
                 &PATH_GETMICROBLOCKS_INDEXED,
@@ -2077,6 +2093,34 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_getsnapshotanchor<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetSnapshotAnchor".to_string(),
+            ));
+        }
+
+        let reward_cycle = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to reward cycle".to_string(),
+            ))?
+            .as_str()
+            .parse::<u64>()
+            .map_err(|_| net_error::DeserializeError("Failed to parse reward cycle".to_string()))?;
+
+        Ok(HttpRequestType::GetSnapshotAnchor(
+            HttpRequestMetadata::from_preamble(preamble),
+            reward_cycle,
+        ))
+    }
+
     fn parse_getblock<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -2106,6 +2150,51 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_getblockrange<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetBlockRange".to_string(),
+            ));
+        }
+
+        let start_height_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to start height group".to_string(),
+            ))?
+            .as_str();
+
+        let end_height_str = captures
+            .get(2)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to end height group".to_string(),
+            ))?
+            .as_str();
+
+        let start_height: u64 = start_height_str
+            .parse()
+            .map_err(|_| net_error::DeserializeError("Failed to parse start height".to_string()))?;
+
+        let end_height: u64 = end_height_str
+            .parse()
+            .map_err(|_| net_error::DeserializeError("Failed to parse end height".to_string()))?;
+
+        let tip = HttpRequestType::get_chain_tip_query(query);
+
+        Ok(HttpRequestType::GetBlockRange(
+            HttpRequestMetadata::from_preamble(preamble),
+            start_height,
+            end_height,
+            tip,
+        ))
+    }
+
     fn parse_getmicroblocks_indexed<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -2672,7 +2761,9 @@ impl HttpRequestType {
             HttpRequestType::GetPoxInfo(ref md, ..) => md,
             HttpRequestType::GetNeighbors(ref md) => md,
             HttpRequestType::GetHeaders(ref md, ..) => md,
+            HttpRequestType::GetSnapshotAnchor(ref md, ..) => md,
             HttpRequestType::GetBlock(ref md, _) => md,
+            HttpRequestType::GetBlockRange(ref md, ..) => md,
             HttpRequestType::GetMicroblocksIndexed(ref md, _) => md,
             HttpRequestType::GetMicroblocksConfirmed(ref md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref md, _, _) => md,
@@ -2703,7 +2794,9 @@ impl HttpRequestType {
             HttpRequestType::GetPoxInfo(ref mut md, ..) => md,
             HttpRequestType::GetNeighbors(ref mut md) => md,
             HttpRequestType::GetHeaders(ref mut md, ..) => md,
+            HttpRequestType::GetSnapshotAnchor(ref mut md, ..) => md,
             HttpRequestType::GetBlock(ref mut md, _) => md,
+            HttpRequestType::GetBlockRange(ref mut md, ..) => md,
             HttpRequestType::GetMicroblocksIndexed(ref mut md, _) => md,
             HttpRequestType::GetMicroblocksConfirmed(ref mut md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref mut md, _, _) => md,
@@ -2759,9 +2852,18 @@ impl HttpRequestType {
                 quantity,
                 HttpRequestType::make_tip_query_string(tip_req, true)
             ),
+            HttpRequestType::GetSnapshotAnchor(_md, reward_cycle) => {
+                format!("/v2/snapshot/anchor/{}", reward_cycle)
+            }
             HttpRequestType::GetBlock(_md, block_hash) => {
                 format!("/v2/blocks/{}", block_hash.to_hex())
             }
+            HttpRequestType::GetBlockRange(_md, start_height, end_height, tip_req) => format!(
+                "/v2/blocks/range/{}/{}{}",
+                start_height,
+                end_height,
+                HttpRequestType::make_tip_query_string(tip_req, true)
+            ),
             HttpRequestType::GetMicroblocksIndexed(_md, block_hash) => {
                 format!("/v2/microblocks/{}", block_hash.to_hex())
             }
@@ -2906,7 +3008,9 @@ impl HttpRequestType {
             HttpRequestType::GetPoxInfo(..) => "/v2/pox",
             HttpRequestType::GetNeighbors(..) => "/v2/neighbors",
             HttpRequestType::GetHeaders(..) => "/v2/headers/:height",
+            HttpRequestType::GetSnapshotAnchor(..) => "/v2/snapshot/anchor/:reward_cycle",
             HttpRequestType::GetBlock(..) => "/v2/blocks/:hash",
+            HttpRequestType::GetBlockRange(..) => "/v2/blocks/range/:start_height/:end_height",
             HttpRequestType::GetMicroblocksIndexed(..) => "/v2/microblocks/:hash",
             HttpRequestType::GetMicroblocksConfirmed(..) => "/v2/microblocks/confirmed/:hash",
             HttpRequestType::GetMicroblocksUnconfirmed(..) => {
@@ -3016,6 +3120,37 @@ impl HttpRequestType {
                 )?;
                 fd.write_all(&mb_bytes).map_err(net_error::WriteError)?;
             }
+            HttpRequestType::FeeRateEstimate(md, payload, estimated_len) => {
+                let mut payload_bytes = vec![];
+                write_next(&mut payload_bytes, payload)?;
+
+                let request_body = FeeRateEstimateRequestBody {
+                    estimated_len: Some(*estimated_len),
+                    transaction_payload: to_hex(&payload_bytes),
+                };
+
+                let mut request_body_bytes = vec![];
+                serde_json::to_writer(&mut request_body_bytes, &request_body).map_err(|e| {
+                    net_error::SerializeError(format!(
+                        "Failed to serialize fee rate estimate request to JSON: {:?}",
+                        &e
+                    ))
+                })?;
+
+                HttpRequestPreamble::new_serialized(
+                    fd,
+                    &md.version,
+                    "POST",
+                    &self.request_path(),
+                    &md.peer,
+                    md.keep_alive,
+                    Some(request_body_bytes.len() as u32),
+                    Some(&HttpContentType::JSON),
+                    |fd| stacks_height_headers(fd, md),
+                )?;
+                fd.write_all(&request_body_bytes)
+                    .map_err(net_error::WriteError)?;
+            }
             HttpRequestType::GetMapEntry(
                 md,
                 _contract_addr,
@@ -3352,7 +3487,12 @@ impl HttpResponseType {
             (&PATH_GETPOXINFO, &HttpResponseType::parse_poxinfo),
             (&PATH_GETNEIGHBORS, &HttpResponseType::parse_neighbors),
             (&PATH_GETHEADERS, &HttpResponseType::parse_headers),
+            (
+                &PATH_GETSNAPSHOTANCHOR,
+                &HttpResponseType::parse_snapshot_anchor,
+            ),
             (&PATH_GETBLOCK, &HttpResponseType::parse_block),
+            (&PATH_GETBLOCKRANGE, &HttpResponseType::parse_block_range),
             (&PATH_GET_DATA_VAR, &HttpResponseType::parse_get_data_var),
             (&PATH_GET_MAP_ENTRY, &HttpResponseType::parse_get_map_entry),
             (
@@ -3409,6 +3549,10 @@ impl HttpResponseType {
                 &PATH_POST_MEMPOOL_QUERY,
                 &HttpResponseType::parse_post_mempool_query,
             ),
+            (
+                &PATH_POST_FEE_RATE_ESIMATE,
+                &HttpResponseType::parse_fee_rate_estimate,
+            ),
         ];
 
         // use url::Url to parse path and query string
@@ -3517,6 +3661,20 @@ impl HttpResponseType {
         ))
     }
 
+    fn parse_snapshot_anchor<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let anchor = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::SnapshotAnchor(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            anchor,
+        ))
+    }
+
     fn parse_block<R: Read>(
         _protocol: &mut StacksHttp,
         request_version: HttpVersion,
@@ -3532,6 +3690,32 @@ impl HttpResponseType {
         ))
     }
 
+    fn parse_block_range<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let blocks: Vec<BlockRangeEntry> = HttpResponseType::parse_bytestream(
+            preamble,
+            fd,
+            len_hint,
+            MAX_BLOCK_RANGE * 2 * (MAX_BLOCK_LEN as u64),
+        )?;
+        if (blocks.len() as u64) > MAX_BLOCK_RANGE {
+            return Err(net_error::DeserializeError(format!(
+                "Too many blocks in block range: {} > {}",
+                blocks.len(),
+                MAX_BLOCK_RANGE
+            )));
+        }
+        Ok(HttpResponseType::BlockRange(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            blocks,
+        ))
+    }
+
     fn parse_microblocks<R: Read>(
         _protocol: &mut StacksHttp,
         request_version: HttpVersion,
@@ -3562,6 +3746,21 @@ impl HttpResponseType {
         ))
     }
 
+    fn parse_fee_rate_estimate<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let estimate =
+            HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::TransactionFeeEstimation(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            estimate,
+        ))
+    }
+
     fn parse_get_data_var<R: Read>(
         _protocol: &mut StacksHttp,
         request_version: HttpVersion,
@@ -3985,8 +4184,10 @@ impl HttpResponseType {
             HttpResponseType::Neighbors(ref md, _) => md,
             HttpResponseType::HeaderStream(ref md) => md,
             HttpResponseType::Headers(ref md, _) => md,
+            HttpResponseType::SnapshotAnchor(ref md, _) => md,
             HttpResponseType::Block(ref md, _) => md,
             HttpResponseType::BlockStream(ref md) => md,
+            HttpResponseType::BlockRange(ref md, _) => md,
             HttpResponseType::Microblocks(ref md, _) => md,
             HttpResponseType::MicroblockStream(ref md) => md,
             HttpResponseType::TransactionID(ref md, _) => md,
@@ -4130,6 +4331,10 @@ impl HttpResponseType {
                 HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
                 HttpResponseType::send_json(protocol, md, fd, neighbor_data)?;
             }
+            HttpResponseType::SnapshotAnchor(ref md, ref anchor) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, anchor)?;
+            }
             HttpResponseType::GetAttachment(ref md, ref zonefile_data) => {
                 HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
                 HttpResponseType::send_json(protocol, md, fd, zonefile_data)?;
@@ -4188,6 +4393,18 @@ impl HttpResponseType {
                     |ref mut fd| keep_alive_headers(fd, md),
                 )?;
             }
+            HttpResponseType::BlockRange(ref md, ref blocks) => {
+                HttpResponsePreamble::new_serialized(
+                    fd,
+                    200,
+                    "OK",
+                    md.content_length.clone(),
+                    &HttpContentType::Bytes,
+                    md.request_id,
+                    |ref mut fd| keep_alive_headers(fd, md),
+                )?;
+                HttpResponseType::send_bytestream(protocol, md, fd, blocks)?;
+            }
             HttpResponseType::Microblocks(ref md, ref microblocks) => {
                 HttpResponsePreamble::new_serialized(
                     fd,
@@ -4398,7 +4615,9 @@ impl MessageSequence for StacksHttpMessage {
                 HttpRequestType::GetPoxInfo(_, _) => "HTTP(GetPoxInfo)",
                 HttpRequestType::GetNeighbors(_) => "HTTP(GetNeighbors)",
                 HttpRequestType::GetHeaders(..) => "HTTP(GetHeaders)",
+                HttpRequestType::GetSnapshotAnchor(..) => "HTTP(GetSnapshotAnchor)",
                 HttpRequestType::GetBlock(_, _) => "HTTP(GetBlock)",
+                HttpRequestType::GetBlockRange(..) => "HTTP(GetBlockRange)",
                 HttpRequestType::GetMicroblocksIndexed(_, _) => "HTTP(GetMicroblocksIndexed)",
                 HttpRequestType::GetMicroblocksConfirmed(_, _) => "HTTP(GetMicroblocksConfirmed)",
                 HttpRequestType::GetMicroblocksUnconfirmed(_, _, _) => {
@@ -4440,9 +4659,11 @@ impl MessageSequence for StacksHttpMessage {
                 HttpResponseType::PoxInfo(_, _) => "HTTP(PeerInfo)",
                 HttpResponseType::Neighbors(_, _) => "HTTP(Neighbors)",
                 HttpResponseType::Headers(..) => "HTTP(Headers)",
+                HttpResponseType::SnapshotAnchor(..) => "HTTP(SnapshotAnchor)",
                 HttpResponseType::HeaderStream(..) => "HTTP(HeaderStream)",
                 HttpResponseType::Block(_, _) => "HTTP(Block)",
                 HttpResponseType::BlockStream(_) => "HTTP(BlockStream)",
+                HttpResponseType::BlockRange(..) => "HTTP(BlockRange)",
                 HttpResponseType::Microblocks(_, _) => "HTTP(Microblocks)",
                 HttpResponseType::MicroblockStream(_) => "HTTP(MicroblockStream)",
                 HttpResponseType::TransactionID(_, _) => "HTTP(Transaction)",
@@ -4711,7 +4932,6 @@ impl StacksHttp {
     }
 
     /// Given a HTTP request, serialize it out
-    #[cfg(test)]
     pub fn serialize_request(req: &HttpRequestType) -> Result<Vec<u8>, net_error> {
         let mut http = StacksHttp::new("127.0.0.1:20443".parse().unwrap());
         let mut ret = vec![];
@@ -4720,7 +4940,6 @@ impl StacksHttp {
     }
 
     /// Given a fully-formed single HTTP response, parse it (used by clients).
-    #[cfg(test)]
     pub fn parse_response(
         request_path: &str,
         response_buf: &[u8],
@@ -4754,6 +4973,41 @@ impl StacksHttp {
             Ok(message)
         }
     }
+
+    /// Send a single request to a node over a new connection, and wait for its response.
+    /// Used by simple blocking clients, like blockstack-cli.
+    pub fn send_request_blocking(
+        addr: &SocketAddr,
+        request: &HttpRequestType,
+        timeout: Duration,
+    ) -> Result<HttpResponseType, net_error> {
+        // the node closes the connection once it has replied
+        let mut request = request.clone();
+        request.metadata_mut().keep_alive = false;
+        let request_bytes = StacksHttp::serialize_request(&request)?;
+
+        let mut sock = TcpStream::connect_timeout(addr, timeout)
+            .map_err(|e| net_error::SendError(format!("Failed to connect to {}: {}", addr, e)))?;
+        sock.set_read_timeout(Some(timeout))
+            .map_err(|_e| net_error::ConnectionError)?;
+        sock.set_write_timeout(Some(timeout))
+            .map_err(|_e| net_error::ConnectionError)?;
+        sock.write_all(&request_bytes)
+            .map_err(net_error::WriteError)?;
+
+        let mut response_bytes = vec![];
+        sock.read_to_end(&mut response_bytes)
+            .map_err(net_error::ReadError)?;
+        if response_bytes.len() == 0 {
+            return Err(net_error::PermanentlyDrained);
+        }
+        match StacksHttp::parse_response(&request.request_path(), &response_bytes)? {
+            StacksHttpMessage::Response(response) => Ok(response),
+            StacksHttpMessage::Request(_) => Err(net_error::DeserializeError(
+                "Invalid HTTP message: did not get a Response".to_string(),
+            )),
+        }
+    }
 }
 
 impl ProtocolFamily for StacksHttp {
@@ -4997,6 +5251,7 @@ mod test {
     use crate::net::codec::test::check_codec_and_corruption;
     use crate::net::test::*;
     use crate::net::RPCNeighbor;
+    use crate::net::RPCNeighborBandwidth;
     use crate::net::RPCNeighborsInfo;
     use stacks_common::util::hash::to_hex;
     use stacks_common::util::hash::Hash160;
@@ -6057,6 +6312,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_http_request_fee_rate_estimate_codec() {
+        let md = HttpRequestMetadata {
+            version: HttpVersion::Http11,
+            peer: PeerHost::DNS("www.foo.com".to_string(), 80),
+            keep_alive: true,
+            canonical_stacks_tip_height: None,
+        };
+        let request =
+            HttpRequestType::FeeRateEstimate(md, make_test_transaction().payload.clone(), 1000);
+
+        let bytes = StacksHttp::serialize_request(&request).unwrap();
+        assert!(str::from_utf8(&bytes)
+            .unwrap()
+            .starts_with("POST /v2/fees/transaction HTTP/1.1\r\n"));
+
+        let mut http = StacksHttp::new("127.0.0.1:20443".parse().unwrap());
+        let (preamble, offset) = http.read_preamble(&bytes).unwrap();
+        let (message, _) = http.read_payload(&preamble, &bytes[offset..]).unwrap();
+        match message {
+            StacksHttpMessage::Request(parsed) => assert_eq!(parsed, request),
+            _ => panic!("expected a request, got {:?}", &message),
+        }
+    }
+
     #[test]
     fn test_http_request_type_codec_err() {
         let bad_content_lengths = vec![
@@ -6112,6 +6392,8 @@ mod test {
                     )
                     .unwrap(),
                     authenticated: true,
+                    reputation: 0,
+                    bandwidth: None,
                 },
                 RPCNeighbor {
                     network_id: 3,
@@ -6126,6 +6408,15 @@ mod test {
                     )
                     .unwrap(),
                     authenticated: false,
+                    reputation: 0,
+                    bandwidth: Some(RPCNeighborBandwidth {
+                        bytes_rx: 100,
+                        bytes_tx: 200,
+                        message_bytes_rx: vec![("Transaction".to_string(), 100)]
+                            .into_iter()
+                            .collect(),
+                        message_bytes_tx: vec![("Blocks".to_string(), 200)].into_iter().collect(),
+                    }),
                 },
             ],
             inbound: vec![],
@@ -16,7 +16,7 @@
 
 use std::borrow::Borrow;
 use std::cmp::PartialEq;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::From;
 use std::convert::TryFrom;
 use std::error;
@@ -32,6 +32,7 @@ use std::net::Ipv6Addr;
 use std::net::SocketAddr;
 use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::RwLock;
 
 use rand::thread_rng;
 use rand::RngCore;
@@ -46,6 +47,7 @@ use url;
 use crate::burnchains::Txid;
 use crate::chainstate::burn::ConsensusHash;
 use crate::chainstate::coordinator::Error as coordinator_error;
+use crate::chainstate::snapshot::SnapshotAnchor;
 use crate::chainstate::stacks::db::blocks::MemPoolRejection;
 use crate::chainstate::stacks::index::Error as marf_error;
 use crate::chainstate::stacks::Error as chainstate_error;
@@ -114,6 +116,9 @@ pub mod chat;
 /// Implements serialization and deserialization for `StacksMessage` types.
 /// Also has functionality to sign, verify, and ensure well-formedness of messages.
 pub mod codec;
+/// Implements compact block relay: pushed blocks are sent as short transaction tags, and
+/// rebuilt by the recipient from its mempool.
+pub mod compact;
 pub mod connection;
 pub mod db;
 /// Implements `DNSResolver`, a simple DNS resolver state machine. Also implements `DNSClient`,
@@ -133,6 +138,13 @@ pub mod prune;
 pub mod relay;
 pub mod rpc;
 pub mod server;
+/// Implements a SOCKS5 client, used to route outbound connections (and host name lookups) through
+/// a proxy such as Tor when `ConnectionOptions::proxy` is set.
+pub mod socks;
+/// Implements the optional encrypted transport for the p2p network: the key schedule derived from
+/// the peers' node keys and an ephemeral key exchange, and the AEAD-framed byte stream used once
+/// both peers have switched over.
+pub mod transport;
 
 #[derive(Debug)]
 pub enum Error {
@@ -477,6 +489,45 @@ impl<'de> Deserialize<'de> for PeerAddress {
     }
 }
 
+/// OnionCat prefix (fd87:d87e:eb43::/48), under which onion service names are embedded into
+/// IPv6 address space.
+const ONIONCAT_PREFIX: [u8; 6] = [0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x43];
+
+/// Prefix (fd87:d87e:eb44::/48) of the stand-in addresses for v3 onion service names.  A v3 name
+/// encodes a 35-byte key and so can't be embedded into 16 bytes like a v2 name; instead, its
+/// address holds a hash of the name, and the name itself is kept in `ONION_V3_HOSTS`.
+const ONION_V3_PREFIX: [u8; 6] = [0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x44];
+
+/// Version byte at the end of a decoded v3 onion service name
+const ONION_V3_VERSION: u8 = 0x03;
+
+/// RFC 4648 base32 alphabet, as used in onion service names
+const ONION_BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+lazy_static! {
+    /// v3 onion service names, by their stand-in addresses.  Names are added as they are parsed
+    /// (from the config file or by the DNS resolver), so a stand-in address only means something
+    /// to the node that parsed its name.
+    static ref ONION_V3_HOSTS: RwLock<HashMap<PeerAddress, String>> = RwLock::new(HashMap::new());
+}
+
+/// Decode an onion service name (without `.onion`) from base32
+fn onion_base32_decode(name: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(name.len() * 5 / 8);
+    let mut acc: u32 = 0;
+    let mut nbits = 0;
+    for c in name.bytes() {
+        let value = ONION_BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        acc = (acc << 5) | value;
+        nbits += 5;
+        if nbits >= 8 {
+            nbits -= 8;
+            bytes.push(((acc >> nbits) & 0xff) as u8);
+        }
+    }
+    Some(bytes)
+}
+
 impl PeerAddress {
     pub fn from_slice(bytes: &[u8]) -> Option<PeerAddress> {
         if bytes.len() != 16 {
@@ -622,9 +673,87 @@ impl PeerAddress {
                 || (self.0[12] == 172 && self.0[13] >= 16 && self.0[13] <= 31)
                 || (self.0[12] == 192 && self.0[13] == 168)
         } else {
-            self.0[0] >= 0xfc
+            self.0[0] >= 0xfc && !self.is_onion()
         }
     }
+
+    /// Is this an onion service address (i.e. in the OnionCat range, or a v3 stand-in)?  Such an
+    /// address can only be reached through a SOCKS5 proxy that speaks Tor.
+    pub fn is_onion(&self) -> bool {
+        self.0[0..6] == ONIONCAT_PREFIX || self.0[0..6] == ONION_V3_PREFIX
+    }
+
+    /// Is this a stand-in address for a v3 onion service name?  A stand-in only means something
+    /// to a node that has parsed the name itself (i.e. from its config), so stand-ins are neither
+    /// relayed to other peers nor stored when learned from them.
+    pub fn is_onion_standin(&self) -> bool {
+        self.0[0..6] == ONION_V3_PREFIX
+    }
+
+    /// Get an address for an onion service host name.  A v2 name (16 characters, e.g.
+    /// `expyuzz4wqqyqhjn.onion`) is embedded into the address using the OnionCat encoding.  A v3
+    /// name (56 characters) is too long for that, so it gets a stand-in address, which
+    /// `to_onion_host()` maps back to the name.  Returns None if `host` isn't an onion name.
+    pub fn from_onion_host(host: &str) -> Option<PeerAddress> {
+        let name = host.to_ascii_lowercase();
+        let name = name.strip_suffix(".onion")?;
+        let decoded = match name.len() {
+            16 | 56 => onion_base32_decode(name)?,
+            _ => {
+                return None;
+            }
+        };
+
+        let mut bytes = [0u8; 16];
+        if decoded.len() == 10 {
+            bytes[0..6].copy_from_slice(&ONIONCAT_PREFIX);
+            bytes[6..16].copy_from_slice(&decoded);
+            return Some(PeerAddress(bytes));
+        }
+
+        if decoded.last() != Some(&ONION_V3_VERSION) {
+            return None;
+        }
+        let hash = Sha256Sum::from_data(name.as_bytes());
+        bytes[0..6].copy_from_slice(&ONION_V3_PREFIX);
+        bytes[6..16].copy_from_slice(&hash.as_bytes()[0..10]);
+        let addrbytes = PeerAddress(bytes);
+
+        ONION_V3_HOSTS
+            .write()
+            .expect("FATAL: onion host table lock poisoned")
+            .insert(addrbytes.clone(), format!("{}.onion", name));
+        Some(addrbytes)
+    }
+
+    /// Get the onion service host name for this address, if it is an onion address.  Returns
+    /// None for a v3 stand-in address whose name this node has never parsed.
+    pub fn to_onion_host(&self) -> Option<String> {
+        if self.is_onion_standin() {
+            return ONION_V3_HOSTS
+                .read()
+                .expect("FATAL: onion host table lock poisoned")
+                .get(self)
+                .cloned();
+        }
+        if !self.is_onion() {
+            return None;
+        }
+
+        let mut name = String::with_capacity(22);
+        let mut acc: u32 = 0;
+        let mut nbits = 0;
+        for b in self.0[6..16].iter() {
+            acc = (acc << 8) | (*b as u32);
+            nbits += 8;
+            while nbits >= 5 {
+                nbits -= 5;
+                name.push(ONION_BASE32_ALPHABET[((acc >> nbits) & 0x1f) as usize] as char);
+            }
+        }
+        name.push_str(".onion");
+        Some(name)
+    }
 }
 
 pub const STACKS_PUBLIC_KEY_ENCODED_SIZE: u32 = 33;
@@ -842,6 +971,10 @@ pub struct HandshakeData {
 pub enum ServiceFlags {
     RELAY = 0x01,
     RPC = 0x02,
+    ENCRYPTION = 0x04,
+    COMPACT_BLOCKS = 0x08,
+    MULTI_ADDRESS = 0x10,
+    DANDELION = 0x20,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -860,6 +993,7 @@ pub mod NackErrorCodes {
     pub const Throttled: u32 = 3;
     pub const InvalidPoxFork: u32 = 4;
     pub const InvalidMessage: u32 = 5;
+    pub const NoSuchBlock: u32 = 6;
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -879,6 +1013,59 @@ pub struct NatPunchData {
     pub nonce: u32,
 }
 
+/// Sent by each peer to set up the encrypted transport.  The initiator (the peer that opened the
+/// connection) sends its ephemeral key once the handshake completes; the responder answers with
+/// its own, and switches to encrypting everything it sends thereafter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransportKeyData {
+    pub ephemeral_key: StacksPublicKeyBuffer,
+}
+
+/// A pushed block, sent as its header and a short tag for each of its transactions (see
+/// `TxTag`), salted with a sender-chosen seed.  The recipient rebuilds the block from its mempool,
+/// and asks for whatever transactions it is missing with a `GetBlockTxns`.  Transactions the
+/// recipient can't have (i.e. the coinbase) are sent in full.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBlockData {
+    pub consensus_hash: ConsensusHash,
+    pub header: StacksBlockHeader,
+    pub seed: [u8; 32],
+    pub tx_tags: Vec<TxTag>,
+    pub prefilled_txs: Vec<StacksTransaction>,
+}
+
+/// Request for the transactions at the given indexes of a block announced with a `CompactBlock`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetBlockTxnsData {
+    pub consensus_hash: ConsensusHash,
+    pub block_hash: BlockHeaderHash,
+    pub indexes: Vec<u32>,
+}
+
+/// Reply to a `GetBlockTxns`, with the transactions in the order they were asked for
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTxnsData {
+    pub consensus_hash: ConsensusHash,
+    pub block_hash: BlockHeaderHash,
+    pub txs: Vec<StacksTransaction>,
+}
+
+/// The other addresses a peer can be reached at, besides the one in its handshake (e.g. an IPv6
+/// address for a peer that handshook over IPv4, or a DNS name).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighborAddresses {
+    pub addr: NeighborAddress,
+    pub addresses: Vec<PeerHost>,
+}
+
+/// Sent by peers that advertise `ServiceFlags::MULTI_ADDRESS`, either right after the handshake
+/// (with a single entry for the sender itself), or alongside a `Neighbors` reply (with an entry
+/// for each listed neighbor that has alternate addresses).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerAddressesData {
+    pub neighbors: Vec<NeighborAddresses>,
+}
+
 define_u8_enum!(MemPoolSyncDataID {
     BloomFilter = 0x01,
     TxTags = 0x02
@@ -918,6 +1105,16 @@ pub enum StacksMessageType {
     Pong(PongData),
     NatPunchRequest(u32),
     NatPunchReply(NatPunchData),
+    TransportKey(TransportKeyData),
+    TransportReady,
+    CompactBlock(CompactBlockData),
+    GetBlockTxns(GetBlockTxnsData),
+    BlockTxns(BlockTxnsData),
+    PeerAddresses(PeerAddressesData),
+    /// A transaction in its stem phase, sent to a single peer that advertises
+    /// `ServiceFlags::DANDELION`.  The recipient either keeps it in the stem phase by forwarding
+    /// it to its own stem relay, or "fluffs" it by broadcasting it as a `Transaction`.
+    StemTransaction(StacksTransaction),
 }
 
 /// Peer address variants
@@ -1096,6 +1293,17 @@ pub struct RPCPoxInfoData {
     pub next_reward_cycle_in: u64,
 }
 
+/// One block in a bulk block range response: the anchored block, the consensus hashes of its
+/// sortition and its parent's sortition, and the microblock stream it confirms.  Like
+/// `/v2/microblocks/confirmed`, the microblocks are in reverse sequence order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRangeEntry {
+    pub consensus_hash: ConsensusHash,
+    pub parent_consensus_hash: ConsensusHash,
+    pub block: StacksBlock,
+    pub confirmed_microblocks: Vec<StacksMicroblock>,
+}
+
 /// Headers response payload
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ExtendedStacksHeader {
@@ -1383,6 +1591,39 @@ pub struct RPCNeighbor {
     pub port: u16,
     pub public_key_hash: Hash160,
     pub authenticated: bool,
+    /// this node's reputation score for the neighbor
+    #[serde(default)]
+    pub reputation: i64,
+    /// bytes exchanged with the neighbor, if we're connected to it
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<RPCNeighborBandwidth>,
+}
+
+/// Bytes exchanged with a connected neighbor, in total and by message type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCNeighborBandwidth {
+    pub bytes_rx: u64,
+    pub bytes_tx: u64,
+    pub message_bytes_rx: BTreeMap<String, u64>,
+    pub message_bytes_tx: BTreeMap<String, u64>,
+}
+
+impl RPCNeighborBandwidth {
+    pub fn from_stats(stats: &chat::NeighborStats) -> RPCNeighborBandwidth {
+        let by_name = |counts: &HashMap<StacksMessageID, u64>| {
+            counts
+                .iter()
+                .map(|(msg_id, num_bytes)| (format!("{:?}", msg_id), *num_bytes))
+                .collect()
+        };
+        RPCNeighborBandwidth {
+            bytes_rx: stats.bytes_rx,
+            bytes_tx: stats.bytes_tx,
+            message_bytes_rx: by_name(&stats.msg_rx_bytes),
+            message_bytes_tx: by_name(&stats.msg_tx_bytes),
+        }
+    }
 }
 
 impl RPCNeighbor {
@@ -1394,8 +1635,20 @@ impl RPCNeighbor {
             port: nk.port,
             public_key_hash: pkh,
             authenticated: auth,
+            reputation: 0,
+            bandwidth: None,
         }
     }
+
+    pub fn with_reputation(mut self, reputation: i64) -> RPCNeighbor {
+        self.reputation = reputation;
+        self
+    }
+
+    pub fn with_bandwidth(mut self, stats: &chat::NeighborStats) -> RPCNeighbor {
+        self.bandwidth = Some(RPCNeighborBandwidth::from_stats(stats));
+        self
+    }
 }
 
 /// Struct given back from a call to `/v2/neighbors`.
@@ -1421,7 +1674,9 @@ pub enum HttpRequestType {
     GetPoxInfo(HttpRequestMetadata, TipRequest),
     GetNeighbors(HttpRequestMetadata),
     GetHeaders(HttpRequestMetadata, u64, TipRequest),
+    GetSnapshotAnchor(HttpRequestMetadata, u64),
     GetBlock(HttpRequestMetadata, StacksBlockId),
+    GetBlockRange(HttpRequestMetadata, u64, u64, TipRequest),
     GetMicroblocksIndexed(HttpRequestMetadata, StacksBlockId),
     GetMicroblocksConfirmed(HttpRequestMetadata, StacksBlockId),
     GetMicroblocksUnconfirmed(HttpRequestMetadata, StacksBlockId, u16),
@@ -1571,9 +1826,11 @@ pub enum HttpResponseType {
     PoxInfo(HttpResponseMetadata, RPCPoxInfoData),
     Neighbors(HttpResponseMetadata, RPCNeighborsInfo),
     Headers(HttpResponseMetadata, Vec<ExtendedStacksHeader>),
+    SnapshotAnchor(HttpResponseMetadata, SnapshotAnchor),
     HeaderStream(HttpResponseMetadata),
     Block(HttpResponseMetadata, StacksBlock),
     BlockStream(HttpResponseMetadata),
+    BlockRange(HttpResponseMetadata, Vec<BlockRangeEntry>),
     Microblocks(HttpResponseMetadata, Vec<StacksMicroblock>),
     MicroblockStream(HttpResponseMetadata),
     TransactionID(HttpResponseMetadata, Txid),
@@ -1634,6 +1891,13 @@ pub enum StacksMessageID {
     Pong = 16,
     NatPunchRequest = 17,
     NatPunchReply = 18,
+    TransportKey = 19,
+    TransportReady = 20,
+    CompactBlock = 21,
+    GetBlockTxns = 22,
+    BlockTxns = 23,
+    PeerAddresses = 24,
+    StemTransaction = 25,
     // reserved
     Reserved = 255,
 }
@@ -1714,6 +1978,12 @@ pub trait ProtocolFamily {
     /// and writing out a Preamble for its Message.
     fn write_message<W: Write>(&mut self, fd: &mut W, message: &Self::Message)
         -> Result<(), Error>;
+
+    /// Does this message change how the bytes that follow it are to be read (e.g. by turning on
+    /// encryption)?  If so, the connection stops parsing after it until told how to proceed.
+    fn pauses_transport(&mut self, _message: &Self::Message) -> bool {
+        false
+    }
 }
 
 // these implement the ProtocolFamily trait
@@ -1744,6 +2014,10 @@ pub const GETPOXINV_MAX_BITLEN: u64 = 8;
 // message.
 pub const BLOCKS_PUSHED_MAX: u32 = 32;
 
+// maximum number of transactions a compact block can describe (or a peer can ask for from one).
+// Blocks are at most MAX_BLOCK_LEN bytes, so this is far more than any block will have.
+pub const COMPACT_BLOCK_MAX_TXS: u32 = 65536;
+
 impl_byte_array_message_codec!(PeerAddress, 16);
 impl_byte_array_message_codec!(Txid, 32);
 
@@ -1877,6 +2151,22 @@ pub const MAX_MICROBLOCKS_UNCONFIRMED: usize = 1024;
 // maximum number of block headers we'll get streamed to us
 pub const MAX_HEADERS: usize = 2100;
 
+// maximum number of anchored blocks (and their confirmed microblock streams) we'll stream in one
+// bulk block range response
+pub const MAX_BLOCK_RANGE: u64 = 32;
+
+// maximum number of alternate addresses a peer can advertise
+pub const MAX_PEER_ADDRESSES: u32 = 8;
+
+// maximum length of a DNS name in an advertised address
+pub const MAX_PEER_HOSTNAME_LEN: u8 = 253;
+
+// maximum number of peers whose alternate addresses we store on another peer's word
+pub const MAX_SECOND_HAND_PEER_ADDRESSES: u64 = 1024;
+
+// maximum number of neighbors for which we remember which of their addresses to dial next
+pub const MAX_DIAL_ADDRESS_HINTS: usize = 4096;
+
 // how long a peer will be denied for if it misbehaves
 #[cfg(test)]
 pub const DENY_BAN_DURATION: u64 = 30; // seconds
@@ -1899,6 +2189,7 @@ pub struct NetworkResult {
     pub uploaded_microblocks: Vec<MicroblocksData>,    // microblocks sent to us by the http server
     pub attachments: Vec<(AttachmentInstance, Attachment)>,
     pub synced_transactions: Vec<StacksTransaction>, // transactions we downloaded via a mempool sync
+    pub stem_transactions: Vec<StacksTransaction>,   // stem-phase transactions pushed to us
     pub num_state_machine_passes: u64,
     pub num_inv_sync_passes: u64,
     pub num_download_passes: u64,
@@ -1925,6 +2216,7 @@ impl NetworkResult {
             uploaded_microblocks: vec![],
             attachments: vec![],
             synced_transactions: vec![],
+            stem_transactions: vec![],
             num_state_machine_passes: num_state_machine_passes,
             num_inv_sync_passes: num_inv_sync_passes,
             num_download_passes: num_download_passes,
@@ -1946,6 +2238,7 @@ impl NetworkResult {
         self.pushed_transactions.len() > 0
             || self.uploaded_transactions.len() > 0
             || self.synced_transactions.len() > 0
+            || self.stem_transactions.len() > 0
     }
 
     pub fn has_attachments(&self) -> bool {
@@ -2001,6 +2294,9 @@ impl NetworkResult {
                                 .insert(neighbor_key.clone(), vec![(message.relayers, tx_data)]);
                         }
                     }
+                    StacksMessageType::StemTransaction(tx_data) => {
+                        self.stem_transactions.push(tx_data);
+                    }
                     _ => {
                         // forward along
                         if let Some(messages) = self.unhandled_messages.get_mut(&neighbor_key) {
@@ -2762,6 +3058,22 @@ pub mod test {
                     config.private_key_expire,
                 )
                 .unwrap();
+                if config.connection_opts.compact_blocks
+                    || config.connection_opts.multi_address
+                    || config.connection_opts.dandelion
+                {
+                    let mut services = (ServiceFlags::RELAY as u16) | (ServiceFlags::RPC as u16);
+                    if config.connection_opts.compact_blocks {
+                        services |= ServiceFlags::COMPACT_BLOCKS as u16;
+                    }
+                    if config.connection_opts.multi_address {
+                        services |= ServiceFlags::MULTI_ADDRESS as u16;
+                    }
+                    if config.connection_opts.dandelion {
+                        services |= ServiceFlags::DANDELION as u16;
+                    }
+                    PeerDB::set_local_services(&mut tx, services).unwrap();
+                }
 
                 tx.commit().unwrap();
             }
@@ -748,6 +748,10 @@ impl NeighborWalk {
             if neighbor.addrbytes.is_anynet() {
                 continue;
             }
+            if neighbor.addrbytes.is_onion_standin() {
+                // only the node that parsed the onion name can reach this
+                continue;
+            }
             ret.push(neighbor);
         }
         ret
@@ -1803,6 +1807,8 @@ impl PeerNetwork {
                                 "{:?}: Failed to connect to {:?}: {:?}",
                                 &self.local_peer, nk, &_e
                             );
+                            // try its next address (if it has one) next time
+                            self.rotate_dial_address(nk);
                             return Err(net_error::PeerNotConnected);
                         }
                     }
@@ -1812,6 +1818,7 @@ impl PeerNetwork {
                     // is the peer network still working?
                     if !self.is_connecting(*event_id) {
                         debug!("{:?}: Failed to connect to {:?} (event {} no longer connecting; assumed timed out)", &self.local_peer, *event_id, nk);
+                        self.rotate_dial_address(nk);
                         return Err(net_error::PeerNotConnected);
                     }
 
@@ -3109,6 +3116,8 @@ mod test {
     use crate::net::asn::*;
     use crate::net::chat::*;
     use crate::net::db::*;
+    use crate::net::socks::test::TestSocksProxy;
+    use crate::net::socks::SocksTarget;
     use crate::net::test::*;
     use crate::util_lib::test::*;
     use stacks_common::util::hash::*;
@@ -3116,6 +3125,57 @@ mod test {
 
     const TEST_IN_OUT_DEGREES: u64 = 0x1;
 
+    #[test]
+    #[ignore]
+    fn test_step_walk_1_neighbor_proxied() {
+        with_timeout(600, || {
+            let proxy = TestSocksProxy::spawn(HashMap::new());
+
+            let mut peer_1_config = TestPeerConfig::from_port(32060);
+            let peer_2_config = TestPeerConfig::from_port(32062);
+
+            // peer 1 crawls peer 2 through the proxy
+            peer_1_config.connection_opts.proxy = Some(proxy.addr.clone());
+            peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+
+            let mut peer_1 = TestPeer::new(peer_1_config);
+            let mut peer_2 = TestPeer::new(peer_2_config);
+
+            let mut walk_1_count = 0;
+            while walk_1_count < 20
+                || peer_1
+                    .network
+                    .get_neighbor_stats(&peer_2.to_neighbor().addr)
+                    .is_none()
+            {
+                let _ = peer_1.step();
+                let _ = peer_2.step();
+
+                walk_1_count = peer_1.network.walk_total_step_count;
+                test_debug!("peer 1 took {} walk steps", walk_1_count);
+            }
+
+            // peer 1 handshaked with peer 2
+            let stats_1 = peer_1
+                .network
+                .get_neighbor_stats(&peer_2.to_neighbor().addr)
+                .unwrap();
+            assert!(stats_1.last_handshake_time > 0);
+            assert!(stats_1.bytes_rx > 0);
+            assert!(stats_1.bytes_tx > 0);
+
+            // ...and did so through the proxy
+            let neighbor_2 = peer_2.to_neighbor();
+            let target_2 = SocksTarget::Addr(
+                neighbor_2
+                    .addr
+                    .addrbytes
+                    .to_socketaddr(neighbor_2.addr.port),
+            );
+            assert!(proxy.get_targets().contains(&target_2));
+        })
+    }
+
     #[test]
     #[ignore]
     fn test_step_walk_1_neighbor_plain() {
@@ -3232,6 +3292,157 @@ mod test {
         })
     }
 
+    #[test]
+    #[ignore]
+    fn test_step_walk_1_neighbor_multi_address() {
+        with_timeout(600, || {
+            let mut peer_1_config = TestPeerConfig::from_port(31880);
+            let mut peer_2_config = TestPeerConfig::from_port(31882);
+
+            let peer_1_ipv6 = PeerHost::IP(
+                PeerAddress::from_socketaddr(&"[::1]:31880".parse::<SocketAddr>().unwrap()),
+                31880,
+            );
+            let peer_1_dns = PeerHost::DNS("peer-1.example.com".to_string(), 31880);
+            let peer_2_dns = PeerHost::DNS("peer-2.example.com".to_string(), 31882);
+
+            peer_1_config.connection_opts.multi_address = true;
+            peer_1_config.connection_opts.public_addresses =
+                vec![peer_1_ipv6.clone(), peer_1_dns.clone()];
+            peer_2_config.connection_opts.multi_address = true;
+            peer_2_config.connection_opts.public_addresses = vec![peer_2_dns.clone()];
+
+            // addresses are only stored for peers in the frontier, so the peers know each other
+            peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+            peer_2_config.add_neighbor(&peer_1_config.to_neighbor());
+
+            let mut peer_1 = TestPeer::new(peer_1_config);
+            let mut peer_2 = TestPeer::new(peer_2_config);
+
+            let neighbor_1 = peer_1.to_neighbor();
+            let neighbor_2 = peer_2.to_neighbor();
+
+            let mut i = 0;
+            loop {
+                let _ = peer_1.step();
+                let _ = peer_2.step();
+
+                // each peer learned the other's addresses first-hand, whichever one dialed
+                let addrs_1 = PeerDB::get_peer_addresses(
+                    peer_2.network.peerdb.conn(),
+                    neighbor_1.addr.network_id,
+                    &neighbor_1.addr.addrbytes,
+                    neighbor_1.addr.port,
+                )
+                .unwrap();
+                let addrs_2 = PeerDB::get_peer_addresses(
+                    peer_1.network.peerdb.conn(),
+                    neighbor_2.addr.network_id,
+                    &neighbor_2.addr.addrbytes,
+                    neighbor_2.addr.port,
+                )
+                .unwrap();
+
+                i += 1;
+                if addrs_1.len() == 2 && addrs_2.len() == 1 {
+                    assert!(addrs_1.contains(&peer_1_ipv6));
+                    assert!(addrs_1.contains(&peer_1_dns));
+                    assert_eq!(addrs_2, vec![peer_2_dns.clone()]);
+                    break;
+                }
+            }
+
+            debug!("Exchanged addresses in {} step(s)", i);
+
+            // ...under the key each one handshook with
+            assert_eq!(
+                PeerDB::get_peer_addresses_origin(
+                    peer_2.network.peerdb.conn(),
+                    neighbor_1.addr.network_id,
+                    &neighbor_1.addr.addrbytes,
+                    neighbor_1.addr.port,
+                )
+                .unwrap(),
+                Some((Hash160::from_node_public_key(&neighbor_1.public_key), true))
+            );
+            assert_eq!(
+                PeerDB::get_peer_addresses_origin(
+                    peer_1.network.peerdb.conn(),
+                    neighbor_2.addr.network_id,
+                    &neighbor_2.addr.addrbytes,
+                    neighbor_2.addr.port,
+                )
+                .unwrap(),
+                Some((Hash160::from_node_public_key(&neighbor_2.public_key), true))
+            );
+
+            // DNS names aren't dialed directly
+            assert_eq!(
+                peer_1.network.get_dial_addresses(&neighbor_2.addr).unwrap(),
+                vec![neighbor_2
+                    .addr
+                    .addrbytes
+                    .to_socketaddr(neighbor_2.addr.port)]
+            );
+
+            // peer 1 is reachable over IPv6, so it tries a neighbor's IPv6 address first, and
+            // falls back to the others in turn
+            let nk = NeighborKey {
+                peer_version: neighbor_2.addr.peer_version,
+                network_id: neighbor_2.addr.network_id,
+                addrbytes: PeerAddress::from_ipv4(127, 0, 0, 1),
+                port: 31884,
+            };
+            let nk_ipv6 = "[::1]:31884".parse::<SocketAddr>().unwrap();
+            let nk_addr = NeighborAddress {
+                addrbytes: nk.addrbytes.clone(),
+                port: nk.port,
+                public_key_hash: Hash160([0x01; 20]),
+            };
+            {
+                let mut tx = peer_1.network.peerdb.tx_begin().unwrap();
+                PeerDB::set_peer_addresses(
+                    &mut tx,
+                    nk.network_id,
+                    &nk_addr,
+                    &[PeerHost::from_socketaddr(&nk_ipv6)],
+                    true,
+                )
+                .unwrap();
+                tx.commit().unwrap();
+            }
+            let candidates = peer_1.network.get_dial_addresses(&nk).unwrap();
+            assert_eq!(
+                candidates,
+                vec![nk_ipv6.clone(), nk.addrbytes.to_socketaddr(nk.port)]
+            );
+
+            assert!(peer_1.network.dial_address_index.get(&nk).is_none());
+            peer_1.network.rotate_dial_address(&nk);
+            assert_eq!(peer_1.network.dial_address_index.get(&nk), Some(&1));
+            peer_1.network.rotate_dial_address(&nk);
+            assert!(peer_1.network.dial_address_index.get(&nk).is_none());
+
+            // peer 2 isn't reachable over IPv6, so it wouldn't try the IPv6 address at all
+            {
+                let mut tx = peer_2.network.peerdb.tx_begin().unwrap();
+                PeerDB::set_peer_addresses(
+                    &mut tx,
+                    nk.network_id,
+                    &nk_addr,
+                    &[PeerHost::from_socketaddr(&nk_ipv6)],
+                    true,
+                )
+                .unwrap();
+                tx.commit().unwrap();
+            }
+            assert_eq!(
+                peer_2.network.get_dial_addresses(&nk).unwrap(),
+                vec![nk.addrbytes.to_socketaddr(nk.port)]
+            );
+        })
+    }
+
     #[test]
     #[ignore]
     fn test_step_walk_1_neighbor_plain_no_natpunch() {
@@ -47,17 +47,20 @@ use crate::chainstate::burn::db::sortdb::{BlockHeaderCache, SortitionDB};
 use crate::chainstate::burn::BlockSnapshot;
 use crate::chainstate::stacks::db::StacksChainState;
 use crate::chainstate::stacks::{MAX_BLOCK_LEN, MAX_TRANSACTION_LEN};
+use crate::monitoring;
 use crate::monitoring::{update_inbound_neighbors, update_outbound_neighbors};
 use crate::net::asn::ASEntry4;
 use crate::net::atlas::AtlasDB;
 use crate::net::atlas::{AttachmentInstance, AttachmentsDownloader};
 use crate::net::chat::ConversationP2P;
 use crate::net::chat::NeighborStats;
+use crate::net::compact::CompactBlockState;
 use crate::net::connection::ConnectionOptions;
 use crate::net::connection::NetworkReplyHandle;
 use crate::net::connection::ReplyHandleP2P;
 use crate::net::db::LocalPeer;
 use crate::net::db::PeerDB;
+use crate::net::db::ReputationEvent;
 use crate::net::download::BlockDownloader;
 use crate::net::inv::*;
 use crate::net::neighbors::*;
@@ -67,8 +70,10 @@ use crate::net::prune::*;
 use crate::net::relay::RelayerStats;
 use crate::net::relay::*;
 use crate::net::relay::*;
+use crate::net::rpc::CostBoundsCache;
 use crate::net::rpc::RPCHandlerArgs;
 use crate::net::server::*;
+use crate::net::socks::{SocksHandshake, SocksTarget};
 use crate::net::Error as net_error;
 use crate::net::Neighbor;
 use crate::net::NeighborKey;
@@ -99,6 +104,8 @@ pub enum NetworkRequest {
     ), // announce to all wanting neighbors that we have these confirmed microblock streams
     Relay(NeighborKey, StacksMessage),
     Broadcast(Vec<RelayData>, StacksMessageType),
+    Stem(StacksTransaction), // forward a transaction to our stem relay (or broadcast it, if we have none)
+    Reputation(Vec<(NeighborKey, ReputationEvent)>), // update neighbors' reputations
 }
 
 /// Handle for other threads to use to issue p2p network requests.
@@ -143,6 +150,15 @@ impl NetworkHandle {
         self.send_request(req)
     }
 
+    /// Report things peers did that affect their reputations
+    pub fn report_reputation_events(
+        &mut self,
+        events: Vec<(NeighborKey, ReputationEvent)>,
+    ) -> Result<(), net_error> {
+        let req = NetworkRequest::Reputation(events);
+        self.send_request(req)
+    }
+
     /// Advertize blocks
     pub fn advertize_blocks(
         &mut self,
@@ -184,6 +200,13 @@ impl NetworkHandle {
         let req = NetworkRequest::Broadcast(relay_hints, msg);
         self.send_request(req)
     }
+
+    /// Forward a stem-phase transaction to our current stem relay via the p2p network thread.
+    /// If we don't have one, the transaction is broadcast instead.
+    pub fn stem_transaction(&mut self, tx: StacksTransaction) -> Result<(), net_error> {
+        let req = NetworkRequest::Stem(tx);
+        self.send_request(req)
+    }
 }
 
 impl NetworkHandleServer {
@@ -249,7 +272,12 @@ pub struct PeerNetwork {
     pub sockets: HashMap<usize, mio_net::TcpStream>,
     pub events: HashMap<NeighborKey, usize>,
     pub connecting: HashMap<usize, (mio_net::TcpStream, bool, u64)>, // (socket, outbound?, connection sent timestamp)
+    pub proxy_handshakes: HashMap<usize, (SocksHandshake, SocketAddr)>, // (SOCKS5 negotiation, address the proxy is connecting us to)
+    pub dialed_alternates: HashMap<usize, (SocketAddr, Hash160)>, // (event ID, (neighbor's own address, public key hash its alternates were advertised for)) for connections to one of a neighbor's alternate addresses
+    pub dial_address_index: HashMap<NeighborKey, usize>, // which of a neighbor's addresses to dial next
+    pub throttled_events: HashSet<usize>, // connections that stopped reading or writing because they hit their bandwidth limit
     pub bans: HashSet<usize>,
+    pub stem_relay: Option<(NeighborKey, u64)>, // (neighbor we forward stem-phase transactions to, when we pick a new one)
 
     // ongoing messages the network is sending via the p2p interface (not bound to a specific
     // conversation).
@@ -297,6 +325,9 @@ pub struct PeerNetwork {
     // (maintained by the downloader state machine)
     pub header_cache: BlockHeaderCache,
 
+    // cached worst-case function costs, for handling contract ABI requests
+    pub cost_bounds_cache: CostBoundsCache,
+
     // peer block download state
     pub block_downloader: Option<BlockDownloader>,
 
@@ -329,6 +360,7 @@ pub struct PeerNetwork {
     public_ip_learned_at: u64,
     public_ip_reply_handle: Option<ReplyHandleP2P>,
     public_ip_retries: u64,
+    public_ip_alternate: Option<PeerHost>, // our public address in the other address family, if learned (multi_address only)
 
     // how many loops of the state-machine have occured?
     // Used to coordinate with the chain synchronization logic to ensure that the node has at least
@@ -352,6 +384,14 @@ pub struct PeerNetwork {
     // can't process yet, but might be able to process on the next chain view update
     pub pending_messages: HashMap<usize, Vec<StacksMessage>>,
 
+    // compact blocks we're reconstructing, and compact block messages to process
+    pub compact_blocks: CompactBlockState,
+
+    // reputation score changes for peers we're no longer connected to, which have yet to be
+    // written to the peer DB (connected peers accrue them in their conversation stats)
+    pending_reputation: HashMap<NeighborKey, i64>,
+    last_reputation_decay: u64,
+
     // fault injection -- force disconnects
     fault_last_disconnect: u64,
 }
@@ -371,6 +411,7 @@ impl PeerNetwork {
         let pub_ip = connection_opts.public_ip_address.clone();
         let pub_ip_learned = pub_ip.is_none();
         local_peer.public_ip_address = pub_ip.clone();
+        local_peer.public_addresses = connection_opts.public_addresses.clone();
 
         if connection_opts.disable_inbound_handshakes {
             debug!("{:?}: disable inbound handshakes", &local_peer);
@@ -404,7 +445,12 @@ impl PeerNetwork {
             sockets: HashMap::new(),
             events: HashMap::new(),
             connecting: HashMap::new(),
+            proxy_handshakes: HashMap::new(),
+            dialed_alternates: HashMap::new(),
+            throttled_events: HashSet::new(),
+            dial_address_index: HashMap::new(),
             bans: HashSet::new(),
+            stem_relay: None,
 
             relay_handles: HashMap::new(),
             relayer_stats: RelayerStats::new(),
@@ -434,6 +480,7 @@ impl PeerNetwork {
             pox_id: PoxId::initial(),
             tip_sort_id: SortitionId([0x00; 32]),
             header_cache: BlockHeaderCache::new(),
+            cost_bounds_cache: CostBoundsCache::new(),
 
             block_downloader: None,
             attachments_downloader: None,
@@ -461,6 +508,7 @@ impl PeerNetwork {
             public_ip_confirmed: false,
             public_ip_reply_handle: None,
             public_ip_retries: 0,
+            public_ip_alternate: None,
 
             num_state_machine_passes: 0,
             num_inv_sync_passes: 0,
@@ -473,6 +521,11 @@ impl PeerNetwork {
 
             pending_messages: HashMap::new(),
 
+            compact_blocks: CompactBlockState::new(),
+
+            pending_reputation: HashMap::new(),
+            last_reputation_decay: get_epoch_time_secs(),
+
             fault_last_disconnect: 0,
         };
 
@@ -732,6 +785,7 @@ impl PeerNetwork {
             &relay_hints
         );
         for nk in neighbor_keys.drain(..) {
+            let payload = self.compact_block_payload(&nk, &message_payload);
             if let Some(event_id) = self.events.get(&nk) {
                 let event_id = *event_id;
                 if let Some(convo) = self.peers.get_mut(&event_id) {
@@ -760,13 +814,13 @@ impl PeerNetwork {
                         &self.local_peer,
                         &self.chain_view,
                         relay_hints.clone(),
-                        message_payload.clone(),
+                        payload.clone(),
                     ) {
                         Ok(rh) => {
                             debug!(
                                 "{:?}: Broadcasted '{}' to {:?}",
                                 &self.local_peer,
-                                message_payload.get_message_description(),
+                                payload.get_message_description(),
                                 &nk
                             );
                             self.add_relay_handle(event_id, rh);
@@ -888,12 +942,74 @@ impl PeerNetwork {
                 test_debug!("{:?}: network not connected", &self.local_peer);
                 return Err(net_error::NotConnected);
             }
-            Some(ref mut network) => {
-                let sock = NetworkState::connect(&neighbor.addrbytes.to_socketaddr(neighbor.port))?;
+            Some(_) => {
+                let addr = neighbor.addrbytes.to_socketaddr(neighbor.port);
+                let mut dial_addr = if self.connection_opts.proxy.is_none() {
+                    self.get_dial_address(neighbor)?
+                } else {
+                    addr.clone()
+                };
+                let mut alternate_key_hash = None;
+                if dial_addr != addr {
+                    // remember whose key to expect at this address
+                    match PeerDB::get_peer_addresses_origin(
+                        self.peerdb.conn(),
+                        neighbor.network_id,
+                        &neighbor.addrbytes,
+                        neighbor.port,
+                    )? {
+                        Some((public_key_hash, _)) => {
+                            alternate_key_hash = Some(public_key_hash);
+                        }
+                        None => {
+                            dial_addr = addr.clone();
+                        }
+                    }
+                }
+                let network = self.network.as_mut().expect("BUG: network not connected");
+                if neighbor.addrbytes.is_onion() && self.connection_opts.proxy.is_none() {
+                    debug!(
+                        "{:?}: cannot connect to onion address {:?} without a proxy",
+                        &self.local_peer, neighbor
+                    );
+                    return Err(net_error::ConnectionError);
+                }
+                let target = match SocksTarget::from_peer_address(
+                    &neighbor.addrbytes,
+                    neighbor.port,
+                ) {
+                    Some(target) => target,
+                    None => {
+                        debug!(
+                            "{:?}: cannot connect to onion stand-in address {:?} of an unknown name",
+                            &self.local_peer, neighbor
+                        );
+                        return Err(net_error::ConnectionError);
+                    }
+                };
+
+                // if we have a proxy, then connect to it instead, and ask it for the neighbor
+                let sock = NetworkState::connect(
+                    self.connection_opts.proxy.as_ref().unwrap_or(&dial_addr),
+                )?;
                 let hint_event_id = network.next_event_id()?;
                 let registered_event_id =
                     network.register(self.p2p_network_handle, hint_event_id, &sock)?;
 
+                if let Some(public_key_hash) = alternate_key_hash {
+                    // keep track of this connection by the neighbor's own address
+                    debug!(
+                        "{:?}: connect to {:?} at alternate address {}",
+                        &self.local_peer, neighbor, &dial_addr
+                    );
+                    self.dialed_alternates
+                        .insert(registered_event_id, (addr, public_key_hash));
+                }
+
+                if self.connection_opts.proxy.is_some() {
+                    self.proxy_handshakes
+                        .insert(registered_event_id, (SocksHandshake::connect(target), addr));
+                }
                 self.connecting
                     .insert(registered_event_id, (sock, true, get_epoch_time_secs()));
                 registered_event_id
@@ -903,6 +1019,106 @@ impl PeerNetwork {
         Ok(next_event_id)
     }
 
+    /// Can we reach IPv6 addresses?  We assume so if we're bound to, or reachable at, an IPv6
+    /// address.
+    fn can_dial_ipv6(&self) -> bool {
+        !self.bind_nk.addrbytes.is_ipv4()
+            || self
+                .local_peer
+                .public_ip_address
+                .as_ref()
+                .map(|(addrbytes, _)| !addrbytes.is_ipv4())
+                .unwrap_or(false)
+            || self
+                .local_peer
+                .public_addresses
+                .iter()
+                .any(|addr| match addr {
+                    PeerHost::IP(addrbytes, _) => !addrbytes.is_ipv4(),
+                    PeerHost::DNS(..) => false,
+                })
+    }
+
+    /// Get the addresses we can dial a neighbor at, in the order we'd try them:  its own address
+    /// and whichever of its alternate IP addresses are in an address family we can reach, IPv6
+    /// first.  DNS alternates are not dialed directly.
+    pub fn get_dial_addresses(&self, neighbor: &NeighborKey) -> Result<Vec<SocketAddr>, net_error> {
+        let addr = neighbor.addrbytes.to_socketaddr(neighbor.port);
+        if !self.connection_opts.multi_address {
+            return Ok(vec![addr]);
+        }
+
+        let ipv6 = self.can_dial_ipv6();
+        let mut candidates = vec![addr];
+        for alt in PeerDB::get_peer_addresses(
+            self.peerdb.conn(),
+            neighbor.network_id,
+            &neighbor.addrbytes,
+            neighbor.port,
+        )?
+        .into_iter()
+        {
+            let (alt_addrbytes, alt_port) = match alt {
+                PeerHost::IP(addrbytes, port) => (addrbytes, port),
+                PeerHost::DNS(..) => {
+                    continue;
+                }
+            };
+            if (!ipv6 && !alt_addrbytes.is_ipv4())
+                || alt_addrbytes.is_anynet()
+                || alt_addrbytes.is_onion()
+                || PeerDB::is_peer_denied(
+                    self.peerdb.conn(),
+                    neighbor.network_id,
+                    &alt_addrbytes,
+                    alt_port,
+                )?
+            {
+                continue;
+            }
+            let alt_addr = alt_addrbytes.to_socketaddr(alt_port);
+            if !candidates.contains(&alt_addr) {
+                candidates.push(alt_addr);
+            }
+        }
+
+        if ipv6 {
+            // stable, so the neighbor's own address is still tried first within its family
+            candidates.sort_by_key(|addr| addr.is_ipv4());
+        }
+        Ok(candidates)
+    }
+
+    /// Get the address to dial a neighbor at next
+    fn get_dial_address(&self, neighbor: &NeighborKey) -> Result<SocketAddr, net_error> {
+        let candidates = self.get_dial_addresses(neighbor)?;
+        let index = self.dial_address_index.get(neighbor).cloned().unwrap_or(0);
+        Ok(candidates[index % candidates.len()].clone())
+    }
+
+    /// We failed to connect to a neighbor, so dial it at its next address the next time.
+    pub fn rotate_dial_address(&mut self, neighbor: &NeighborKey) -> () {
+        if !self.connection_opts.multi_address {
+            return;
+        }
+        let num_candidates = match self.get_dial_addresses(neighbor) {
+            Ok(candidates) => candidates.len(),
+            Err(_) => {
+                return;
+            }
+        };
+        let index = self.dial_address_index.get(neighbor).cloned().unwrap_or(0) + 1;
+        if index % num_candidates == 0 {
+            // tried them all; start over
+            self.dial_address_index.remove(neighbor);
+        } else {
+            if self.dial_address_index.len() >= MAX_DIAL_ADDRESS_HINTS {
+                self.dial_address_index.clear();
+            }
+            self.dial_address_index.insert(neighbor.clone(), index);
+        }
+    }
+
     /// Given a list of neighbors keys, find the _set_ of neighbor keys that represent unique
     /// connections.  This is used by the broadcast logic to ensure that we only send a message to
     /// a peer once, even if we have both an inbound and outbound connection to it.
@@ -945,12 +1161,21 @@ impl PeerNetwork {
         // coalesce
         let mut outbound_neighbors = vec![];
         let mut inbound_neighbors = vec![];
+        let mut reputations = HashMap::new();
 
         for (_, convo) in self.peers.iter() {
             if !convo.is_authenticated() {
                 continue;
             }
             let nk = convo.to_neighbor_key();
+            let rep_nk = convo.best_effort_neighbor_key();
+            let score = PeerDB::get_peer_reputation_score(
+                self.peerdb.conn(),
+                rep_nk.network_id,
+                &rep_nk.addrbytes,
+                rep_nk.port,
+            )?;
+            reputations.insert(nk.clone(), score);
             if convo.is_outbound() {
                 outbound_neighbors.push(nk);
             } else {
@@ -966,6 +1191,8 @@ impl PeerNetwork {
             payload,
             RELAY_DUPLICATE_INFERENCE_WARMUP,
         );
+        RelayerStats::weigh_relay_rankings_by_reputation(&mut outbound_dist, &reputations);
+        RelayerStats::weigh_relay_rankings_by_reputation(&mut inbound_dist, &reputations);
 
         let mut relay_pubkhs = HashSet::new();
         for rhint in relay_hints {
@@ -1040,6 +1267,12 @@ impl PeerNetwork {
                 }
                 Ok(())
             }
+            NetworkRequest::Reputation(events) => {
+                for (neighbor_key, event) in events.into_iter() {
+                    self.record_reputation_event(&neighbor_key, event);
+                }
+                Ok(())
+            }
             NetworkRequest::AdvertizeBlocks(blocks, block_data) => {
                 if !(cfg!(test) && self.connection_opts.disable_block_advertisement) {
                     self.advertize_blocks(blocks, block_data)?;
@@ -1092,6 +1325,103 @@ impl PeerNetwork {
                 self.broadcast_message(neighbor_keys, relay_hints, msg);
                 Ok(())
             }
+            NetworkRequest::Stem(tx) => {
+                self.stem_transaction(tx);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the neighbor to forward stem-phase transactions to.  We keep the same one for
+    /// `dandelion_epoch` seconds, so all of the transactions we stem during that time take the
+    /// same path, and pick a new one at random from our authenticated outbound peers that
+    /// support stem relay once it expires or disconnects.
+    fn get_stem_relay(&mut self) -> Option<NeighborKey> {
+        let now = get_epoch_time_secs();
+        if let Some((ref nk, expires)) = self.stem_relay {
+            if expires > now {
+                if let Some(event_id) = self.events.get(nk) {
+                    if self
+                        .peers
+                        .get(event_id)
+                        .map(|convo| convo.is_authenticated())
+                        .unwrap_or(false)
+                    {
+                        return Some(nk.clone());
+                    }
+                }
+            }
+        }
+
+        let candidates: Vec<NeighborKey> = self
+            .peers
+            .values()
+            .filter(|convo| {
+                convo.is_outbound()
+                    && convo.is_authenticated()
+                    && ConversationP2P::supports_dandelion(convo.peer_services)
+            })
+            .map(|convo| convo.to_neighbor_key())
+            .collect();
+
+        self.stem_relay = candidates
+            .choose(&mut thread_rng())
+            .map(|nk| (nk.clone(), now + self.connection_opts.dandelion_epoch));
+
+        if let Some((ref nk, _)) = self.stem_relay {
+            debug!("{:?}: New stem relay is {:?}", &self.local_peer, nk);
+        }
+        self.stem_relay.as_ref().map(|(nk, _)| nk.clone())
+    }
+
+    /// Forward a stem-phase transaction to our stem relay.  If we don't have one, or can't send
+    /// to it, then broadcast the transaction instead.
+    pub fn stem_transaction(&mut self, tx: StacksTransaction) -> () {
+        if let Some(nk) = self.get_stem_relay() {
+            let payload = StacksMessageType::StemTransaction(tx.clone());
+            match self
+                .sign_for_peer(&nk, payload)
+                .and_then(|msg| self.relay_signed_message(&nk, msg))
+            {
+                Ok(_) => {
+                    debug!(
+                        "{:?}: Stemmed tx {} to {:?}",
+                        &self.local_peer,
+                        &tx.txid(),
+                        &nk
+                    );
+                    return;
+                }
+                Err(e) => {
+                    info!(
+                        "{:?}: Failed to stem tx {} to {:?}: {:?}",
+                        &self.local_peer,
+                        &tx.txid(),
+                        &nk,
+                        &e
+                    );
+                    self.stem_relay = None;
+                }
+            }
+        }
+
+        debug!(
+            "{:?}: No stem relay; broadcast tx {}",
+            &self.local_peer,
+            &tx.txid()
+        );
+        match self.sample_broadcast_peers(&vec![], &tx) {
+            Ok(neighbor_keys) => {
+                self.broadcast_message(neighbor_keys, vec![], StacksMessageType::Transaction(tx));
+            }
+            Err(e) => {
+                warn!(
+                    "{:?}: Failed to sample broadcast peers for tx {}: {:?}",
+                    &self.local_peer,
+                    &tx.txid(),
+                    &e
+                );
+            }
         }
     }
 
@@ -1227,6 +1557,85 @@ impl PeerNetwork {
         Ok(disconnect)
     }
 
+    /// Record something a peer did that affects its reputation.  If we're connected to it, the
+    /// score change is attributed to the address it handshaked with (so inbound peers aren't
+    /// scored by their ephemeral ports).
+    pub fn record_reputation_event(&mut self, neighbor_key: &NeighborKey, event: ReputationEvent) {
+        debug!(
+            "{:?}: Reputation event for {:?}: {:?}",
+            &self.local_peer, neighbor_key, &event
+        );
+        if let Some(event_id) = self.events.get(neighbor_key) {
+            if let Some(convo) = self.peers.get_mut(event_id) {
+                convo.stats.add_reputation_event(event);
+                return;
+            }
+        }
+        let delta = self
+            .pending_reputation
+            .entry(neighbor_key.clone())
+            .or_insert(0);
+        *delta = delta.saturating_add(event.score_delta());
+    }
+
+    /// Write accrued reputation score changes to the peer DB, and periodically decay all scores.
+    /// Connected peers whose scores fall to or below the ban threshold get banned.
+    fn process_reputation(&mut self) -> Result<(), net_error> {
+        let mut updates = vec![];
+        for (event_id, convo) in self.peers.iter_mut() {
+            if convo.stats.reputation_delta != 0 {
+                updates.push((
+                    Some(*event_id),
+                    convo.best_effort_neighbor_key(),
+                    convo.stats.reputation_delta,
+                ));
+                convo.stats.reputation_delta = 0;
+            }
+        }
+        for (neighbor_key, delta) in self.pending_reputation.drain() {
+            updates.push((None, neighbor_key, delta));
+        }
+
+        let now = get_epoch_time_secs();
+        let half_life = self.connection_opts.reputation_half_life;
+        let do_decay = half_life > 0 && self.last_reputation_decay + half_life / 24 < now;
+        if updates.len() == 0 && !do_decay {
+            return Ok(());
+        }
+
+        let mut tx = self.peerdb.tx_begin()?;
+        for (event_id_opt, neighbor_key, delta) in updates.into_iter() {
+            let score = PeerDB::update_peer_reputation(
+                &mut tx,
+                neighbor_key.network_id,
+                &neighbor_key.addrbytes,
+                neighbor_key.port,
+                delta,
+                now,
+                half_life,
+            )?;
+            debug!(
+                "{:?}: Reputation of {:?} is now {} ({:+})",
+                &self.local_peer, &neighbor_key, score, delta
+            );
+            if score <= self.connection_opts.reputation_ban_threshold {
+                if let Some(event_id) = event_id_opt {
+                    info!(
+                        "{:?}: Reputation of {:?} fell to {}; will ban",
+                        &self.local_peer, &neighbor_key, score
+                    );
+                    self.bans.insert(event_id);
+                }
+            }
+        }
+        if do_decay {
+            PeerDB::decay_peer_reputations(&mut tx, now, half_life)?;
+            self.last_reputation_decay = now;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Get the neighbor if we know of it and it's public key is unexpired.
     fn lookup_peer(
         &self,
@@ -1391,13 +1800,16 @@ impl PeerNetwork {
     /// connection events).  If this method fails for some reason, it'll de-register the socket
     /// from the poller.
     /// outbound is true if we are the peer that started the connection (otherwise it's false)
+    /// proxied_addr is the remote peer's address if the socket goes through a proxy (in which case
+    /// the socket's peer address is the proxy's), or if we dialed one of its alternate addresses
     fn register_peer(
         &mut self,
         event_id: usize,
         socket: mio_net::TcpStream,
         outbound: bool,
+        proxied_addr: Option<SocketAddr>,
     ) -> Result<(), net_error> {
-        let client_addr = match socket.peer_addr() {
+        let client_addr = match proxied_addr.map(Ok).unwrap_or_else(|| socket.peer_addr()) {
             Ok(addr) => addr,
             Err(e) => {
                 debug!(
@@ -1503,6 +1915,8 @@ impl PeerNetwork {
 
     /// Deregister a socket from our p2p network instance.
     fn deregister_socket(&mut self, event_id: usize, socket: mio_net::TcpStream) -> () {
+        self.proxy_handshakes.remove(&event_id);
+        self.dialed_alternates.remove(&event_id);
         match self.network {
             Some(ref mut network) => {
                 let _ = network.deregister(event_id, &socket);
@@ -1552,9 +1966,28 @@ impl PeerNetwork {
                 }
             }
         }
+        self.proxy_handshakes.remove(&event_id);
+        self.dialed_alternates.remove(&event_id);
+        self.throttled_events.remove(&event_id);
 
         self.relay_handles.remove(&event_id);
-        self.peers.remove(&event_id);
+        if let Some(convo) = self.peers.remove(&event_id) {
+            monitoring::clear_p2p_peer_bandwidth(&convo.peer_bandwidth_label());
+
+            if convo.dialed_alternate_key_hash.is_some() {
+                // never got a valid handshake at this alternate address, so try the next one
+                self.rotate_dial_address(&convo.to_neighbor_key());
+            }
+
+            // don't lose reputation changes we haven't stored yet
+            if convo.stats.reputation_delta != 0 {
+                let delta = self
+                    .pending_reputation
+                    .entry(convo.best_effort_neighbor_key())
+                    .or_insert(0);
+                *delta = delta.saturating_add(convo.stats.reputation_delta);
+            }
+        }
         self.pending_messages.remove(&event_id);
     }
 
@@ -1654,7 +2087,7 @@ impl PeerNetwork {
             };
 
             // start tracking it
-            if let Err(_e) = self.register_peer(event_id, client_sock, false) {
+            if let Err(_e) = self.register_peer(event_id, client_sock, false, None) {
                 // NOTE: register_peer will deregister the socket for us
                 continue;
             }
@@ -1746,13 +2179,63 @@ impl PeerNetwork {
         Ok((unhandled, !convo_dead))
     }
 
+    /// Drive the SOCKS5 negotiation on a connecting socket, if it goes through a proxy.
+    /// Returns Ok(Some(None)) if the socket is not proxied, Ok(Some(Some(addr))) if the proxy has
+    /// connected us to addr, and Ok(None) if we're still waiting on the proxy.
+    fn advance_proxy_handshake(
+        &mut self,
+        event_id: usize,
+    ) -> Result<Option<Option<SocketAddr>>, net_error> {
+        let done = match (
+            self.proxy_handshakes.get_mut(&event_id),
+            self.connecting.get_mut(&event_id),
+        ) {
+            (Some((handshake, _)), Some((socket, ..))) => handshake.try_advance(socket),
+            _ => {
+                return Ok(Some(None));
+            }
+        };
+        match done {
+            Ok(true) => Ok(self
+                .proxy_handshakes
+                .remove(&event_id)
+                .map(|(_, addr)| Some(addr))),
+            Ok(false) => Ok(None),
+            Err(e) => {
+                self.proxy_handshakes.remove(&event_id);
+                Err(e)
+            }
+        }
+    }
+
     /// Process any newly-connecting sockets
     fn process_connecting_sockets(&mut self, poll_state: &mut NetworkPollState) -> () {
         for event_id in poll_state.ready.iter() {
             if self.connecting.contains_key(event_id) {
+                let proxied_addr = match self.advance_proxy_handshake(*event_id) {
+                    Ok(Some(proxied_addr_opt)) => proxied_addr_opt,
+                    Ok(None) => {
+                        // still talking to the proxy
+                        continue;
+                    }
+                    Err(_e) => {
+                        debug!(
+                            "{:?}: Failed to connect through proxy on event {}: {:?}",
+                            &self.local_peer, event_id, &_e
+                        );
+                        if let Some((socket, ..)) = self.connecting.remove(event_id) {
+                            self.deregister_socket(*event_id, socket);
+                        }
+                        continue;
+                    }
+                };
+
+                let alternate = self.dialed_alternates.remove(event_id);
+                let proxied_addr =
+                    proxied_addr.or(alternate.as_ref().map(|(addr, _)| addr.clone()));
                 let (socket, outbound, _) = self.connecting.remove(event_id).unwrap();
                 let sock_str = format!("{:?}", &socket);
-                if let Err(_e) = self.register_peer(*event_id, socket, outbound) {
+                if let Err(_e) = self.register_peer(*event_id, socket, outbound, proxied_addr) {
                     debug!(
                         "{:?}: Failed to register connecting socket on event {} ({}): {:?}",
                         &self.local_peer, event_id, sock_str, &_e
@@ -1762,6 +2245,11 @@ impl PeerNetwork {
                         "{:?}: Registered peer on event {}: {:?} (outbound={})",
                         &self.local_peer, event_id, sock_str, outbound
                     );
+                    if let Some((_, public_key_hash)) = alternate {
+                        if let Some(convo) = self.peers.get_mut(event_id) {
+                            convo.dialed_alternate_key_hash = Some(public_key_hash);
+                        }
+                    }
                 }
             }
         }
@@ -2216,32 +2704,27 @@ impl PeerNetwork {
 
         debug!("{:?}: begin obtaining public IP address", &self.local_peer);
 
-        // pick a random outbound conversation to one of the initial neighbors
-        let mut idx = thread_rng().gen::<usize>() % self.peers.len();
-        for _ in 0..self.peers.len() + 1 {
-            let event_id = match self.peers.keys().skip(idx).next() {
-                Some(eid) => *eid,
-                None => {
-                    idx = 0;
-                    continue;
-                }
-            };
-            idx = (idx + 1) % self.peers.len();
+        let candidates = self.public_ip_candidates()?;
+
+        // if we can, ask over the address family we don't know our address in yet
+        let missing_family = self.missing_public_ip_family();
+        let preferred: Vec<usize> = candidates
+            .iter()
+            .filter(|(_, is_ipv4)| Some(*is_ipv4) == missing_family)
+            .map(|(event_id, _)| *event_id)
+            .collect();
+        let event_ids: Vec<usize> = if preferred.len() > 0 {
+            preferred
+        } else {
+            candidates
+                .into_iter()
+                .map(|(event_id, _)| event_id)
+                .collect()
+        };
 
+        if event_ids.len() > 0 {
+            let event_id = event_ids[thread_rng().gen::<usize>() % event_ids.len()];
             if let Some(convo) = self.peers.get_mut(&event_id) {
-                if !convo.is_authenticated() || !convo.is_outbound() {
-                    continue;
-                }
-
-                if !PeerDB::is_initial_peer(
-                    self.peerdb.conn(),
-                    convo.peer_network_id,
-                    &convo.peer_addrbytes,
-                    convo.peer_port,
-                )? {
-                    continue;
-                }
-
                 debug!("Ask {:?} for my IP address", &convo);
 
                 let nonce = thread_rng().gen::<u32>();
@@ -2269,7 +2752,6 @@ impl PeerNetwork {
                 })?;
 
                 self.public_ip_reply_handle = Some(rh);
-                break;
             }
         }
 
@@ -2283,6 +2765,71 @@ impl PeerNetwork {
         return Ok(true);
     }
 
+    /// Find the conversations we can ask for our public IP address:  authenticated, outbound
+    /// conversations with our initial neighbors.  Returns their event IDs, and whether or not we
+    /// reach them over IPv4 (i.e. which of our addresses they would see).
+    fn public_ip_candidates(&self) -> Result<Vec<(usize, bool)>, net_error> {
+        let mut candidates = vec![];
+        for (event_id, convo) in self.peers.iter() {
+            if !convo.is_authenticated() || !convo.is_outbound() {
+                continue;
+            }
+
+            if !PeerDB::is_initial_peer(
+                self.peerdb.conn(),
+                convo.peer_network_id,
+                &convo.peer_addrbytes,
+                convo.peer_port,
+            )? {
+                continue;
+            }
+
+            // the conversation's address is the neighbor's own, but we may have dialed one of its
+            // alternates (or a proxy), so go by the socket
+            let is_ipv4 = match self.sockets.get(event_id).map(|sock| sock.peer_addr()) {
+                Some(Ok(addr)) => addr.is_ipv4(),
+                _ => convo.peer_addrbytes.is_ipv4(),
+            };
+            candidates.push((*event_id, is_ipv4));
+        }
+        Ok(candidates)
+    }
+
+    /// If we advertise multiple addresses, and know our public IP address in only one address
+    /// family, then which family are we missing?  Returns Some(true) for IPv4, Some(false) for
+    /// IPv6.  Not applicable if we go through a proxy, since peers would only see the proxy's.
+    fn missing_public_ip_family(&self) -> Option<bool> {
+        if !self.connection_opts.multi_address
+            || self.connection_opts.proxy.is_some()
+            || self.public_ip_alternate.is_some()
+        {
+            return None;
+        }
+        self.local_peer
+            .public_ip_address
+            .as_ref()
+            .map(|(addrbytes, _)| !addrbytes.is_ipv4())
+    }
+
+    /// We learned our public address in the other address family from a NAT punch reply, so
+    /// advertise it as an alternate (replacing the one we learned before, if any).
+    fn learn_public_ip_alternate(&mut self, addrbytes: PeerAddress, port: u16) {
+        let learned = PeerHost::IP(addrbytes, port);
+        if let Some(old) = self.public_ip_alternate.take() {
+            self.local_peer.public_addresses.retain(|addr| *addr != old);
+        }
+        if !self.local_peer.public_addresses.contains(&learned)
+            && self.local_peer.public_addresses.len() < (MAX_PEER_ADDRESSES as usize)
+        {
+            debug!(
+                "{:?}: learned that I am also reachable at {}",
+                &self.local_peer, &learned
+            );
+            self.local_peer.public_addresses.push(learned.clone());
+        }
+        self.public_ip_alternate = Some(learned);
+    }
+
     /// Disconnect from all peers
     fn disconnect_all(&mut self) -> () {
         let mut all_event_ids = vec![];
@@ -2333,6 +2880,27 @@ impl PeerNetwork {
                             "{:?}: learned that my IP address is {:?}",
                             &self.local_peer, &data.addrbytes
                         );
+
+                        let other_family = self.connection_opts.multi_address
+                            && self
+                                .local_peer
+                                .public_ip_address
+                                .as_ref()
+                                .map(|(addrbytes, _)| {
+                                    addrbytes.is_ipv4() != data.addrbytes.is_ipv4()
+                                })
+                                .unwrap_or(false);
+                        if other_family || !self.public_ip_learned {
+                            // we already know our address in the peer's family (or were
+                            // given it), so this is an alternate
+                            if other_family {
+                                self.learn_public_ip_alternate(data.addrbytes, self.bind_nk.port);
+                            }
+                            self.public_ip_learned_at = get_epoch_time_secs();
+                            self.public_ip_retries = 0;
+                            return Ok(true);
+                        }
+
                         self.public_ip_confirmed = true;
                         self.public_ip_learned_at = get_epoch_time_secs();
                         self.public_ip_retries = 0;
@@ -2383,6 +2951,20 @@ impl PeerNetwork {
 
     /// Do we need to (re)fetch our public IP?
     fn need_public_ip(&mut self) -> bool {
+        if let Some(is_ipv4) = self.missing_public_ip_family() {
+            // go learn our address in the other family, if we can reach an initial neighbor
+            // over it
+            if let Ok(candidates) = self.public_ip_candidates() {
+                if candidates.iter().any(|(_, ipv4)| *ipv4 == is_ipv4) {
+                    test_debug!(
+                        "{:?}: learn my public {} address",
+                        &self.local_peer,
+                        if is_ipv4 { "IPv4" } else { "IPv6" }
+                    );
+                    return true;
+                }
+            }
+        }
         if !self.public_ip_learned {
             // IP was given, not learned.  nothing to do
             test_debug!("{:?}: IP address was given to us", &self.local_peer);
@@ -2427,6 +3009,9 @@ impl PeerNetwork {
 
         self.public_ip_reply_handle = None;
         self.public_ip_confirmed = false;
+        if let Some(old) = self.public_ip_alternate.take() {
+            self.local_peer.public_addresses.retain(|addr| *addr != old);
+        }
 
         if self.public_ip_learned {
             // will go relearn it if it wasn't given
@@ -4128,7 +4713,7 @@ impl PeerNetwork {
             // check limits:
             // at most 1 BlocksAvailable
             // at most 1 MicroblocksAvailable
-            // at most 1 BlocksData
+            // at most 1 BlocksData (or CompactBlock)
             // at most $self.connection_opts.max_buffered_microblocks MicroblocksDatas
             let mut blocks_available = 0;
             let mut microblocks_available = 0;
@@ -4142,7 +4727,7 @@ impl PeerNetwork {
                     StacksMessageType::MicroblocksAvailable(_) => {
                         microblocks_available += 1;
                     }
-                    StacksMessageType::Blocks(_) => {
+                    StacksMessageType::Blocks(_) | StacksMessageType::CompactBlock(_) => {
                         blocks_data += 1;
                     }
                     StacksMessageType::Microblocks(_) => {
@@ -4171,11 +4756,15 @@ impl PeerNetwork {
                     return;
                 }
             }
-            if let StacksMessageType::Blocks(_) = &msg.payload {
+            if let StacksMessageType::Blocks(_) | StacksMessageType::CompactBlock(_) = &msg.payload
+            {
                 if blocks_data >= self.connection_opts.max_buffered_blocks {
                     debug!(
-                        "{:?}: Drop BlocksData from event {} -- already have {} buffered",
-                        &self.local_peer, event_id, blocks_data
+                        "{:?}: Drop {} from event {} -- already have {} buffered",
+                        &self.local_peer,
+                        msg.payload.get_message_description(),
+                        event_id,
+                        blocks_data
                     );
                     return;
                 }
@@ -4206,7 +4795,7 @@ impl PeerNetwork {
     }
 
     /// Do we need a block or microblock stream, given its sortition's consensus hash?
-    fn need_block_or_microblock_stream(
+    pub(crate) fn need_block_or_microblock_stream(
         sortdb: &SortitionDB,
         chainstate: &StacksChainState,
         consensus_hash: &ConsensusHash,
@@ -4234,7 +4823,7 @@ impl PeerNetwork {
     /// Update our inv for this peer.
     /// Mask errors.
     /// Return whether or not we need to buffer this message
-    fn handle_unsolicited_BlocksAvailable(
+    pub(crate) fn handle_unsolicited_BlocksAvailable(
         &mut self,
         sortdb: &SortitionDB,
         chainstate: &StacksChainState,
@@ -4683,6 +5272,21 @@ impl PeerNetwork {
                 // only forward to the relayer if we don't need to buffer it.
                 (to_buffer, true)
             }
+            StacksMessageType::CompactBlock(ref compact_block) => {
+                // queue it up to be rebuilt; the rebuilt block goes to the relayer
+                let to_buffer = self.handle_unsolicited_CompactBlock(
+                    sortdb,
+                    chainstate,
+                    event_id,
+                    compact_block,
+                    buffer,
+                );
+                (to_buffer, false)
+            }
+            StacksMessageType::BlockTxns(ref txns) => {
+                self.handle_unsolicited_BlockTxns(event_id, txns);
+                (false, false)
+            }
             _ => (false, true),
         }
     }
@@ -4895,10 +5499,11 @@ impl PeerNetwork {
         }
     }
 
-    /// Get the local peer from the peer DB, but also preserve the public IP address
+    /// Get the local peer from the peer DB, but also preserve the public IP address(es)
     pub fn load_local_peer(&self) -> Result<LocalPeer, net_error> {
         let mut lp = PeerDB::get_local_peer(&self.peerdb.conn())?;
         lp.public_ip_address = self.local_peer.public_ip_address.clone();
+        lp.public_addresses = self.local_peer.public_addresses.clone();
         Ok(lp)
     }
 
@@ -4980,6 +5585,33 @@ impl PeerNetwork {
         Ok(ret)
     }
 
+    /// If we're a pruned node, delete the data of a batch of blocks (and the microblock streams
+    /// they confirmed) that are older than the reward cycles we keep.
+    fn prune_chainstate(&mut self, chainstate: &mut StacksChainState) {
+        let keep_reward_cycles = match self.connection_opts.prune_reward_cycles {
+            Some(rc) => rc,
+            None => {
+                return;
+            }
+        };
+        match chainstate.prune_blocks(
+            &self.burnchain,
+            keep_reward_cycles,
+            self.connection_opts.prune_batch_size,
+        ) {
+            Ok(0) => {}
+            Ok(num_pruned) => {
+                debug!(
+                    "{:?}: Pruned {} blocks older than {} reward cycles",
+                    &self.local_peer, num_pruned, keep_reward_cycles
+                );
+            }
+            Err(e) => {
+                warn!("{:?}: Failed to prune blocks: {:?}", &self.local_peer, &e);
+            }
+        }
+    }
+
     /// Update p2p networking state.
     /// -- accept new connections
     /// -- send data on ready sockets
@@ -5010,9 +5642,24 @@ impl PeerNetwork {
         // find out who is inbound and unauthenticated
         let unauthenticated_inbounds = self.find_unauthenticated_inbound_convos();
 
+        // sockets are edge-triggered, so a connection that stopped early because it hit its
+        // bandwidth limit won't be reported ready again until new data arrives.  Revisit them.
+        for event_id in self.throttled_events.drain() {
+            if self.sockets.contains_key(&event_id) && !poll_state.ready.contains(&event_id) {
+                poll_state.ready.push(event_id);
+            }
+        }
+
         // run existing conversations, clear out broken ones, and get back messages forwarded to us
         let (error_events, unsolicited_messages) =
             self.process_ready_sockets(sortdb, chainstate, &mut poll_state);
+        for event_id in poll_state.ready.iter() {
+            if let Some(convo) = self.peers.get(event_id) {
+                if convo.is_throttled() {
+                    self.throttled_events.insert(*event_id);
+                }
+            }
+        }
         for error_event in error_events {
             debug!(
                 "{:?}: Failed connection on event {}",
@@ -5024,6 +5671,9 @@ impl PeerNetwork {
             self.handle_unsolicited_messages(sortdb, chainstate, unsolicited_messages, ibd, true);
         network_result.consume_unsolicited(unhandled_messages);
 
+        // rebuild any compact blocks we were sent
+        self.process_compact_blocks(sortdb, chainstate, mempool, network_result, ibd);
+
         // schedule now-authenticated inbound convos for pingback
         self.schedule_network_pingbacks(unauthenticated_inbounds);
 
@@ -5039,6 +5689,15 @@ impl PeerNetwork {
             ibd,
             network_result,
         );
+
+        // store peers' reputation changes, and ban peers whose reputations fell too low
+        if let Err(e) = self.process_reputation() {
+            warn!(
+                "{:?}: Failed to update peer reputations: {:?}",
+                &self.local_peer, &e
+            );
+        }
+
         if do_prune {
             // prune back our connections if it's been a while
             // (only do this if we're done with all other tasks).
@@ -5053,6 +5712,9 @@ impl PeerNetwork {
                 }
             }
             self.prune_connections();
+
+            // if we're a pruned node, delete old block data
+            self.prune_chainstate(chainstate);
         }
 
         // In parallel, do a neighbor walk
@@ -5137,7 +5799,7 @@ impl PeerNetwork {
     /// Store a single transaction
     /// Return true if stored; false if it was a dup or if it's temporarily blacklisted.
     /// Has to be done here, since only the p2p network has the unconfirmed state.
-    fn store_transaction(
+    pub fn store_transaction(
         mempool: &mut MemPoolDB,
         sortdb: &SortitionDB,
         chainstate: &mut StacksChainState,
@@ -6580,4 +7242,94 @@ mod test {
             assert_eq!(peer_2_mempool_txs.len(), 0);
         });
     }
+
+    /// Connect two peers, have peer 1 stem a transaction, and return what peer 2 received as
+    /// (stem-phase transactions, broadcast transactions)
+    fn run_stem_transaction_2_peers(
+        test_name: &str,
+        port_base: u16,
+        peer_2_dandelion: bool,
+    ) -> (
+        Option<NeighborKey>,
+        Vec<StacksTransaction>,
+        Vec<StacksTransaction>,
+    ) {
+        let mut peer_1_config = TestPeerConfig::new(test_name, port_base, port_base + 1);
+        let mut peer_2_config = TestPeerConfig::new(test_name, port_base + 2, port_base + 3);
+
+        peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+        peer_2_config.add_neighbor(&peer_1_config.to_neighbor());
+
+        peer_1_config.connection_opts.dandelion = true;
+        peer_2_config.connection_opts.dandelion = peer_2_dandelion;
+
+        let mut peer_1 = TestPeer::new(peer_1_config);
+        let mut peer_2 = TestPeer::new(peer_2_config);
+        let peer_2_nk = peer_2.to_neighbor().addr;
+
+        // wait for peer 1 to handshake with peer 2
+        while peer_1
+            .network
+            .get_neighbor_stats(&peer_2_nk)
+            .map(|stats| stats.last_handshake_time == 0)
+            .unwrap_or(true)
+        {
+            let _ = peer_1.step();
+            let _ = peer_2.step();
+        }
+
+        let stem_relay = peer_1.network.get_stem_relay();
+
+        let tx = make_codec_test_block(1).txs[0].clone();
+        peer_1.network.stem_transaction(tx.clone());
+
+        let mut stem_txs = vec![];
+        let mut pushed_txs = vec![];
+        for _ in 0..100 {
+            let _ = peer_1.step();
+            if let Ok(mut result) = peer_2.step() {
+                stem_txs.append(&mut result.stem_transactions);
+                for (_, tx_data) in result.pushed_transactions.drain() {
+                    pushed_txs.extend(tx_data.into_iter().map(|(_, tx)| tx));
+                }
+            }
+            if stem_txs.len() > 0 || pushed_txs.len() > 0 {
+                break;
+            }
+        }
+        (stem_relay, stem_txs, pushed_txs)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_stem_transaction_2_peers() {
+        with_timeout(600, || {
+            let (stem_relay, stem_txs, pushed_txs) =
+                run_stem_transaction_2_peers("test_stem_transaction_2_peers", 2710, true);
+
+            // peer 2 supports stem relay, so peer 1 sends it the stem-phase transaction
+            let stem_relay = stem_relay.unwrap();
+            assert_eq!(stem_relay.addrbytes, PeerAddress::from_ipv4(127, 0, 0, 1));
+            assert_eq!(stem_relay.port, 2712);
+            assert_eq!(stem_txs.len(), 1);
+            assert_eq!(pushed_txs.len(), 0);
+        })
+    }
+
+    #[test]
+    #[ignore]
+    fn test_stem_transaction_2_peers_no_stem_relay() {
+        with_timeout(600, || {
+            let (stem_relay, stem_txs, pushed_txs) = run_stem_transaction_2_peers(
+                "test_stem_transaction_2_peers_no_stem_relay",
+                2714,
+                false,
+            );
+
+            // peer 2 doesn't support stem relay, so peer 1 broadcasts the transaction
+            assert!(stem_relay.is_none());
+            assert_eq!(stem_txs.len(), 0);
+            assert_eq!(pushed_txs.len(), 1);
+        })
+    }
 }
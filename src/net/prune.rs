@@ -156,6 +156,40 @@ impl PeerNetwork {
         // return Ordering::Equal;
     }
 
+    /// Get the reputation scores of the neighbors we're connected to, keyed by the neighbor keys
+    /// in self.events.  Scores include changes not yet stored to the peer DB.
+    fn neighbor_reputations(&self) -> Result<HashMap<NeighborKey, i64>, net_error> {
+        let mut reputations = HashMap::new();
+        for (nk, event_id) in self.events.iter() {
+            if let Some(convo) = self.peers.get(event_id) {
+                let rep_nk = convo.best_effort_neighbor_key();
+                let score = PeerDB::get_peer_reputation_score(
+                    self.peerdb.conn(),
+                    rep_nk.network_id,
+                    &rep_nk.addrbytes,
+                    rep_nk.port,
+                )?;
+                reputations.insert(
+                    nk.clone(),
+                    score.saturating_add(convo.stats.reputation_delta),
+                );
+            }
+        }
+        Ok(reputations)
+    }
+
+    /// Sort function for a neighbor list in order to compare by reputation.  Reputations are
+    /// bucketed by 100 points, so small differences (e.g. a few downloaded blocks) don't matter.
+    fn compare_neighbor_reputation(
+        reputations: &HashMap<NeighborKey, i64>,
+        nk1: &NeighborKey,
+        nk2: &NeighborKey,
+    ) -> Ordering {
+        let rep_bucket_1 = reputations.get(nk1).unwrap_or(&0).div_euclid(100);
+        let rep_bucket_2 = reputations.get(nk2).unwrap_or(&0).div_euclid(100);
+        rep_bucket_1.cmp(&rep_bucket_2)
+    }
+
     /// Sample an org based on its weight
     fn sample_org_by_neighbor_count(org_weights: &HashMap<u32, usize>) -> u32 {
         let mut rng = thread_rng();
@@ -192,6 +226,7 @@ impl PeerNetwork {
         }
 
         let mut org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), preserve)?;
+        let reputations = self.neighbor_reputations()?;
         let mut ret = vec![];
         let orgs: Vec<u32> = org_neighbors
             .keys()
@@ -202,7 +237,7 @@ impl PeerNetwork {
             .collect();
 
         for org in orgs.iter() {
-            // sort each neighbor list by uptime and health.
+            // sort each neighbor list by reputation, and then by uptime and health.
             // bucket uptime geometrically by powers of 2 -- a node that's been up for X seconds is
             // likely to be up for X more seconds, so we only really want to distinguish between nodes that
             // have wildly different uptimes.
@@ -210,8 +245,10 @@ impl PeerNetwork {
             match org_neighbors.get_mut(&org) {
                 None => {}
                 Some(ref mut neighbor_infos) => {
-                    neighbor_infos.sort_by(|&(ref _nk1, ref stats1), &(ref _nk2, ref stats2)| {
-                        PeerNetwork::compare_neighbor_uptime_health(stats1, stats2)
+                    neighbor_infos.sort_by(|&(ref nk1, ref stats1), &(ref nk2, ref stats2)| {
+                        PeerNetwork::compare_neighbor_reputation(&reputations, nk1, nk2).then_with(
+                            || PeerNetwork::compare_neighbor_uptime_health(stats1, stats2),
+                        )
                     });
                 }
             }
@@ -352,11 +389,16 @@ impl PeerNetwork {
             }
         }
 
-        // sort in order by first-contact time (oldest first)
+        // sort in order by reputation (best first), and then by first-contact time (oldest first)
+        let reputations = self.neighbor_reputations().unwrap_or_default();
         for (_, stats_list) in ip_neighbor.iter_mut() {
             stats_list.sort_by(
-                |&(ref _e1, ref _nk1, ref stats1), &(ref _e2, ref _nk2, ref stats2)| {
-                    if stats1.first_contact_time < stats2.first_contact_time {
+                |&(ref _e1, ref nk1, ref stats1), &(ref _e2, ref nk2, ref stats2)| {
+                    let rep_order =
+                        PeerNetwork::compare_neighbor_reputation(&reputations, nk2, nk1);
+                    if rep_order != Ordering::Equal {
+                        rep_order
+                    } else if stats1.first_contact_time < stats2.first_contact_time {
                         Ordering::Less
                     } else if stats1.first_contact_time > stats2.first_contact_time {
                         Ordering::Greater
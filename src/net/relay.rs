@@ -29,6 +29,7 @@ use rand::Rng;
 
 use crate::burnchains::Burnchain;
 use crate::burnchains::BurnchainView;
+use crate::burnchains::Txid;
 use crate::chainstate::burn::db::sortdb::{SortitionDB, SortitionDBConn, SortitionHandleConn};
 use crate::chainstate::burn::BlockSnapshot;
 use crate::chainstate::burn::ConsensusHash;
@@ -38,6 +39,7 @@ use crate::chainstate::stacks::events::StacksTransactionReceipt;
 use crate::chainstate::stacks::StacksBlockHeader;
 use crate::chainstate::stacks::TransactionPayload;
 use crate::clarity_vm::clarity::Error as clarity_error;
+use crate::codec::StacksMessageCodec;
 use crate::core::mempool::MemPoolDB;
 use crate::core::mempool::*;
 use crate::net::chat::*;
@@ -71,10 +73,29 @@ pub const MAX_RELAYER_STATS: usize = 4096;
 pub const MAX_RECENT_MESSAGES: usize = 256;
 pub const MAX_RECENT_MESSAGE_AGE: usize = 600; // seconds; equal to the expected epoch length
 pub const RELAY_DUPLICATE_INFERENCE_WARMUP: usize = 128;
+pub const MAX_STEM_TRANSACTIONS: usize = 1024; // past this, new transactions are broadcast right away
 
 pub struct Relayer {
     /// Connection to the p2p thread
     p2p: NetworkHandle,
+    /// Transactions we sent along a stem, and are waiting to see broadcast
+    stem_txs: HashMap<Txid, StemTransaction>,
+    /// Whether or not to stem new transactions before broadcasting them
+    dandelion: bool,
+    /// Percent chance of broadcasting a stem-phase transaction instead of forwarding it
+    dandelion_fluff_probability: u8,
+    /// Base number of seconds to wait for a stem-phase transaction to be broadcast
+    dandelion_embargo: u64,
+}
+
+/// A transaction we forwarded along a stem.  It isn't in our mempool yet, so a mempool sync can't
+/// reveal that it passed through us.  If we don't see it broadcast by the time its embargo
+/// expires, then we store and broadcast it ourselves.
+#[derive(Debug, Clone)]
+struct StemTransaction {
+    tx: StacksTransaction,
+    /// when we give up waiting and broadcast it
+    embargo_deadline: u64,
 }
 
 #[derive(Debug)]
@@ -391,6 +412,24 @@ impl RelayerStats {
         Ok(ret)
     }
 
+    /// Scale a relay ranking distribution by the neighbors' reputation scores, so that peers
+    /// with better reputations are more likely to be chosen.  A neighbor's weight grows from 1x
+    /// (worst possible reputation) to 8x (best possible reputation).  Neighbors without a
+    /// reputation are treated as having a score of 0.
+    pub fn weigh_relay_rankings_by_reputation(
+        rankings: &mut HashMap<NeighborKey, usize>,
+        reputations: &HashMap<NeighborKey, i64>,
+    ) {
+        for (nk, rank) in rankings.iter_mut() {
+            let score = *(reputations.get(nk).unwrap_or(&0));
+            let score = score.max(PEER_REPUTATION_MIN).min(PEER_REPUTATION_MAX);
+            let weight = 1
+                + ((score - PEER_REPUTATION_MIN) * 7 / (PEER_REPUTATION_MAX - PEER_REPUTATION_MIN))
+                    as usize;
+            *rank = rank.saturating_mul(weight);
+        }
+    }
+
     /// Sample a set of neighbors according to our relay data.
     /// Sampling is done *without* replacement, so the resulting neighbors list will have length
     /// min(count, rankings.len())
@@ -449,12 +488,22 @@ impl RelayerStats {
 
 impl Relayer {
     pub fn new(handle: NetworkHandle) -> Relayer {
-        Relayer { p2p: handle }
+        Relayer::new_with_options(handle, &ConnectionOptions::default())
+    }
+
+    pub fn new_with_options(handle: NetworkHandle, opts: &ConnectionOptions) -> Relayer {
+        Relayer {
+            p2p: handle,
+            stem_txs: HashMap::new(),
+            dandelion: opts.dandelion,
+            dandelion_fluff_probability: opts.dandelion_fluff_probability,
+            dandelion_embargo: opts.dandelion_embargo,
+        }
     }
 
     pub fn from_p2p(network: &mut PeerNetwork) -> Relayer {
         let handle = network.new_handle(1024);
-        Relayer::new(handle)
+        Relayer::new_with_options(handle, &network.connection_opts)
     }
 
     /// Given blocks pushed to us, verify that they correspond to expected block data.
@@ -1488,6 +1537,230 @@ impl Relayer {
         Ok(ret)
     }
 
+    /// Forget stem-phase transactions that have since been broadcast to us, either pushed by a
+    /// peer or fetched via a mempool sync.  Someone else fluffed them, so we don't need to.
+    fn clear_fluffed_stem_transactions(&mut self, network_result: &NetworkResult) -> () {
+        if self.stem_txs.len() == 0 {
+            return;
+        }
+        let broadcast_txs = network_result
+            .pushed_transactions
+            .values()
+            .flat_map(|tx_data| tx_data.iter().map(|(_, tx)| tx))
+            .chain(network_result.synced_transactions.iter());
+
+        for tx in broadcast_txs {
+            if self.stem_txs.remove(&tx.txid()).is_some() {
+                debug!("Stem-phase tx {} was broadcast", &tx.txid());
+            }
+        }
+    }
+
+    /// Send a transaction to our stem relay, and start its embargo timer.  The timer is
+    /// randomized so peers can't tell from when we fluff it whether or not we're its origin.
+    fn stem_transaction(&mut self, tx: StacksTransaction) -> () {
+        let embargo = self.dandelion_embargo
+            + thread_rng().gen_range(0, self.dandelion_embargo.saturating_add(1));
+        self.stem_txs.insert(
+            tx.txid(),
+            StemTransaction {
+                tx: tx.clone(),
+                embargo_deadline: get_epoch_time_secs() + embargo,
+            },
+        );
+        if let Err(e) = self.p2p.stem_transaction(tx) {
+            warn!("Failed to stem transaction: {:?}", &e);
+        }
+    }
+
+    /// Have the p2p thread broadcast a transaction to a sample of our neighbors.
+    fn broadcast_transaction(&mut self, tx: StacksTransaction) -> () {
+        let msg = StacksMessageType::Transaction(tx);
+        if let Err(e) = self.p2p.broadcast_message(vec![], msg) {
+            warn!("Failed to broadcast transaction: {:?}", &e);
+        }
+    }
+
+    /// Relay the stem-phase transactions pushed to us, as well as the transactions uploaded to us
+    /// (which start their stems here).  Each pushed one is either fluffed -- stored to our mempool
+    /// and broadcast -- with probability `dandelion_fluff_probability`, or checked and forwarded
+    /// to our own stem relay without being stored.  Since every hop flips its own coin, the number
+    /// of hops a transaction is stemmed along is random.  Uploaded ones are always stemmed, unless
+    /// we're already stemming `MAX_STEM_TRANSACTIONS` transactions, in which case every new one
+    /// is fluffed.
+    /// Returns the transactions we fluffed (and stored).
+    fn process_stem_transactions(
+        &mut self,
+        network_result: &mut NetworkResult,
+        uploaded_txs: Vec<StacksTransaction>,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        mempool: &mut MemPoolDB,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<Vec<StacksTransaction>, net_error> {
+        let mut fluffed = vec![];
+        if network_result.stem_transactions.len() == 0 && uploaded_txs.len() == 0 {
+            return Ok(fluffed);
+        }
+
+        let (canonical_consensus_hash, canonical_block_hash) =
+            SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn())?;
+        let sn = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())?;
+
+        let stem_txs = uploaded_txs.into_iter().map(|tx| (tx, true)).chain(
+            network_result
+                .stem_transactions
+                .drain(..)
+                .map(|tx| (tx, false)),
+        );
+
+        for (tx, uploaded) in stem_txs {
+            let txid = tx.txid();
+            if self.stem_txs.contains_key(&txid) || mempool.has_tx(&txid) {
+                // already relayed it; don't let it loop
+                continue;
+            }
+            if Relayer::do_static_problematic_checks()
+                && !Relayer::static_check_problematic_relayed_tx(
+                    chainstate.mainnet,
+                    &tx,
+                    ASTRules::PrecheckSize,
+                )
+                .is_ok()
+            {
+                info!(
+                    "Stem-phase transaction {} is problematic; will not store or relay",
+                    &txid
+                );
+                continue;
+            }
+
+            let fluff = !self.dandelion
+                || self.stem_txs.len() >= MAX_STEM_TRANSACTIONS
+                || (!uploaded
+                    && thread_rng().gen_range(0, 100) < (self.dandelion_fluff_probability as u32));
+
+            if fluff {
+                if PeerNetwork::store_transaction(
+                    mempool,
+                    sortdb,
+                    chainstate,
+                    &sn,
+                    &canonical_consensus_hash,
+                    &canonical_block_hash,
+                    tx.clone(),
+                    event_observer,
+                ) {
+                    debug!("Fluff stem-phase tx {}", &txid);
+                    fluffed.push(tx.clone());
+                    self.broadcast_transaction(tx);
+                }
+            } else {
+                // don't store it (so it can't be found by a mempool sync), but don't forward
+                // anything we wouldn't accept either
+                let tx_size = tx.serialize_to_vec().len() as u64;
+                if let Err(e) = chainstate.will_admit_mempool_tx(
+                    &canonical_consensus_hash,
+                    &canonical_block_hash,
+                    &tx,
+                    tx_size,
+                ) {
+                    debug!("Drop stem-phase tx {}: {:?}", &txid, &e);
+                    continue;
+                }
+                debug!("Stem tx {}", &txid);
+                self.stem_transaction(tx);
+            }
+        }
+        Ok(fluffed)
+    }
+
+    /// Fail-safe: store and broadcast each stem-phase transaction whose embargo expired before it
+    /// showed up in our mempool, in case a node along its stem dropped it.
+    /// Returns the transactions we stored to our mempool in the process.
+    fn fluff_expired_stem_transactions(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        mempool: &mut MemPoolDB,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<Vec<StacksTransaction>, net_error> {
+        let mut fluffed = vec![];
+        let now = get_epoch_time_secs();
+        let expired: Vec<Txid> = self
+            .stem_txs
+            .iter()
+            .filter(|(_, stem)| stem.embargo_deadline <= now)
+            .map(|(txid, _)| txid.clone())
+            .collect();
+
+        if expired.len() == 0 {
+            return Ok(fluffed);
+        }
+
+        let (canonical_consensus_hash, canonical_block_hash) =
+            SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn())?;
+        let sn = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())?;
+
+        for txid in expired.into_iter() {
+            let stem = match self.stem_txs.remove(&txid) {
+                Some(stem) => stem,
+                None => continue,
+            };
+            if !PeerNetwork::store_transaction(
+                mempool,
+                sortdb,
+                chainstate,
+                &sn,
+                &canonical_consensus_hash,
+                &canonical_block_hash,
+                stem.tx.clone(),
+                event_observer,
+            ) {
+                // it showed up after all (or it's no longer valid)
+                continue;
+            }
+            info!(
+                "Stem-phase tx {} was not broadcast before its embargo expired; broadcasting it",
+                &txid
+            );
+            fluffed.push(stem.tx.clone());
+            self.broadcast_transaction(stem.tx);
+        }
+        Ok(fluffed)
+    }
+
+    /// Store the transactions uploaded to us that were held back from the mempool to be stemmed,
+    /// but that we won't relay after all (i.e. because we're in IBD).
+    /// Returns the ones we stored.
+    fn store_uploaded_transactions(
+        uploaded_txs: Vec<StacksTransaction>,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        mempool: &mut MemPoolDB,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<Vec<StacksTransaction>, net_error> {
+        let (canonical_consensus_hash, canonical_block_hash) =
+            SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn())?;
+        let sn = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())?;
+
+        Ok(uploaded_txs
+            .into_iter()
+            .filter(|tx| {
+                PeerNetwork::store_transaction(
+                    mempool,
+                    sortdb,
+                    chainstate,
+                    &sn,
+                    &canonical_consensus_hash,
+                    &canonical_block_hash,
+                    tx.clone(),
+                    event_observer,
+                )
+            })
+            .collect())
+    }
+
     pub fn advertize_blocks(
         &mut self,
         available: BlocksAvailableMap,
@@ -1614,6 +1887,8 @@ impl Relayer {
         let mut num_new_blocks = 0;
         let mut num_new_confirmed_microblocks = 0;
         let mut num_new_unconfirmed_microblocks = 0;
+        let block_pushers: Vec<NeighborKey> =
+            network_result.pushed_blocks.keys().cloned().collect();
         match Relayer::process_new_blocks(network_result, sortdb, chainstate, coord_comms) {
             Ok((new_blocks, new_confirmed_microblocks, new_microblocks, bad_block_neighbors)) => {
                 // report quantities of new data in the receipts
@@ -1622,7 +1897,23 @@ impl Relayer {
                 num_new_unconfirmed_microblocks = new_microblocks.len() as u64;
 
                 // attempt to relay messages (note that this is all best-effort).
-                // punish bad peers
+                // reward peers that pushed us valid blocks, and punish bad peers
+                let reputation_events: Vec<_> = block_pushers
+                    .into_iter()
+                    .filter(|nk| !bad_block_neighbors.contains(nk))
+                    .map(|nk| (nk, ReputationEvent::ValidBlockPush))
+                    .chain(
+                        bad_block_neighbors
+                            .iter()
+                            .map(|nk| (nk.clone(), ReputationEvent::InvalidBlock)),
+                    )
+                    .collect();
+                if reputation_events.len() > 0 {
+                    if let Err(e) = self.p2p.report_reputation_events(reputation_events) {
+                        warn!("Failed to report peer reputation events: {:?}", &e);
+                    }
+                }
+
                 if bad_block_neighbors.len() > 0 {
                     debug!(
                         "{:?}: Ban {} peers",
@@ -1696,8 +1987,19 @@ impl Relayer {
 
         let mut mempool_txs_added = vec![];
 
+        // with stem relay on, transactions uploaded to us are not in our mempool yet -- they get
+        // stemmed instead of broadcast, so neighbors can't easily tell that they started here
+        let uploaded_txs: Vec<StacksTransaction> = if self.dandelion {
+            network_result.uploaded_transactions.drain(..).collect()
+        } else {
+            vec![]
+        };
+
         // only care about transaction forwarding if not IBD
         if !ibd {
+            // anything we stemmed that has since been broadcast no longer needs its fail-safe
+            self.clear_fluffed_stem_transactions(network_result);
+
             // store all transactions, and forward the novel ones to neighbors
             test_debug!(
                 "{:?}: Process {} transaction(s)",
@@ -1728,6 +2030,31 @@ impl Relayer {
                     warn!("Failed to broadcast transaction: {:?}", &e);
                 }
             }
+
+            // pass along stem-phase transactions
+            let mut fluffed_txs = self.process_stem_transactions(
+                network_result,
+                uploaded_txs,
+                sortdb,
+                chainstate,
+                mempool,
+                event_observer,
+            )?;
+            mempool_txs_added.append(&mut fluffed_txs);
+
+            let mut fluffed_txs =
+                self.fluff_expired_stem_transactions(sortdb, chainstate, mempool, event_observer)?;
+            mempool_txs_added.append(&mut fluffed_txs);
+        } else if uploaded_txs.len() > 0 {
+            // we don't relay transactions during IBD, so there's nothing to hide these from
+            let mut stored_txs = Relayer::store_uploaded_transactions(
+                uploaded_txs,
+                sortdb,
+                chainstate,
+                mempool,
+                event_observer,
+            )?;
+            mempool_txs_added.append(&mut stored_txs);
         }
 
         let mut processed_unconfirmed_state = Default::default();
@@ -1864,10 +2191,13 @@ impl PeerNetwork {
     ) -> () {
         let blk_hash = block.block_hash();
         let ch = consensus_hash.clone();
-        let payload = BlocksData {
-            blocks: vec![BlocksDatum(consensus_hash, block)],
-        };
-        let message = match self.sign_for_peer(recipient, StacksMessageType::Blocks(payload)) {
+        let payload = self.compact_block_payload(
+            recipient,
+            &StacksMessageType::Blocks(BlocksData {
+                blocks: vec![BlocksDatum(consensus_hash, block)],
+            }),
+        );
+        let message = match self.sign_for_peer(recipient, payload) {
             Ok(m) => m,
             Err(e) => {
                 warn!(
@@ -2566,6 +2896,18 @@ pub mod test {
         assert_eq!(ranking.len(), 2);
         assert_eq!(*ranking.get(&nk_2).unwrap(), 4 - 2 + 1);
         assert_eq!(*ranking.get(&nk_3).unwrap(), 4 - 2 + 1);
+
+        // reputations scale the rankings from 1x to 8x
+        let mut ranking = relay_stats
+            .get_outbound_relay_rankings(&peerdb, &vec![nk_1.clone(), nk_2.clone(), nk_3.clone()])
+            .unwrap();
+        let mut reputations = HashMap::new();
+        reputations.insert(nk_1.clone(), PEER_REPUTATION_MIN);
+        reputations.insert(nk_2.clone(), PEER_REPUTATION_MAX);
+        RelayerStats::weigh_relay_rankings_by_reputation(&mut ranking, &reputations);
+        assert_eq!(*ranking.get(&nk_1).unwrap(), (5 - 1 + 1) * 1);
+        assert_eq!(*ranking.get(&nk_2).unwrap(), (5 - 2 + 1) * 8);
+        assert_eq!(*ranking.get(&nk_3).unwrap(), (5 - 2 + 1) * 4);
     }
 
     #[test]
@@ -2931,9 +3273,13 @@ pub mod test {
         .unwrap();
         let consensus_hash = sn.consensus_hash;
 
-        let msg = StacksMessageType::Blocks(BlocksData {
-            blocks: vec![BlocksDatum(consensus_hash, block)],
-        });
+        // sent as a compact block if both peers support it
+        let msg = peer.network.compact_block_payload(
+            dest,
+            &StacksMessageType::Blocks(BlocksData {
+                blocks: vec![BlocksDatum(consensus_hash, block)],
+            }),
+        );
         push_message(peer, dest, relay_hints, msg)
     }
 
@@ -3103,6 +3449,7 @@ pub mod test {
     fn test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(
         outbound_test: bool,
         disable_push: bool,
+        compact_blocks: bool,
     ) {
         with_timeout(600, move || {
             let original_blocks_and_microblocks = RefCell::new(vec![]);
@@ -3141,6 +3488,11 @@ pub mod test {
                         peer_configs[1].connection_opts.disable_microblock_push = true;
                     }
 
+                    if compact_blocks {
+                        peer_configs[0].connection_opts.compact_blocks = true;
+                        peer_configs[1].connection_opts.compact_blocks = true;
+                    }
+
                     let peer_0 = peer_configs[0].to_neighbor();
                     let peer_1 = peer_configs[1].to_neighbor();
 
@@ -3335,6 +3687,13 @@ pub mod test {
                                 .get_message_recv_count(StacksMessageID::BlocksInv),
                             0
                         );
+                        if compact_blocks {
+                            // blocks only ever arrive as compact blocks
+                            assert_eq!(
+                                convo.stats.get_message_recv_count(StacksMessageID::Blocks),
+                                0
+                            );
+                        }
                     }
                 },
                 |ref peer| {
@@ -3353,7 +3712,7 @@ pub mod test {
     fn test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks_outbound() {
         // simulates node 0 pushing blocks to node 1, but node 0 is publicly routable.
         // nodes rely on blocksavailable/microblocksavailable to discover blocks
-        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(true, true)
+        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(true, true, false)
     }
 
     #[test]
@@ -3361,7 +3720,7 @@ pub mod test {
     fn test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks_inbound() {
         // simulates node 0 pushing blocks to node 1, where node 0 is behind a NAT
         // nodes rely on blocksavailable/microblocksavailable to discover blocks
-        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(false, true)
+        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(false, true, false)
     }
 
     #[test]
@@ -3369,7 +3728,7 @@ pub mod test {
     fn test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks_outbound_direct() {
         // simulates node 0 pushing blocks to node 1, but node 0 is publicly routable.
         // nodes may push blocks and microblocks directly to each other
-        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(true, false)
+        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(true, false, false)
     }
 
     #[test]
@@ -3377,7 +3736,15 @@ pub mod test {
     fn test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks_inbound_direct() {
         // simulates node 0 pushing blocks to node 1, where node 0 is behind a NAT
         // nodes may push blocks and microblocks directly to each other
-        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(false, false)
+        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(false, false, false)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_get_blocks_and_microblocks_2_peers_push_compact_blocks_and_microblocks() {
+        // simulates node 0 pushing blocks to node 1 as compact blocks, which node 1 rebuilds by
+        // asking node 0 for the transactions it doesn't have
+        test_get_blocks_and_microblocks_2_peers_push_blocks_and_microblocks(true, false, true)
     }
 
     #[test]
@@ -5200,6 +5567,128 @@ pub mod test {
         assert_eq!(txs_relayed.len(), 0);
     }
 
+    #[test]
+    fn test_stem_transaction_embargo() {
+        let mut peer_config = TestPeerConfig::new("test_stem_transaction_embargo", 2718, 2719);
+        peer_config.initial_balances = vec![(
+            PrincipalData::from(peer_config.spending_account.origin_address().unwrap()),
+            1000000,
+        )];
+        let mut peer = TestPeer::new(peer_config);
+
+        let (burn_ops, stacks_block, microblocks) = peer.make_default_tenure();
+        let (_, _, consensus_hash) = peer.next_burnchain_block(burn_ops);
+        peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+
+        let tx = make_test_smart_contract_transaction(
+            &mut peer,
+            "test-stem-contract",
+            &consensus_hash,
+            &stacks_block.block_hash(),
+        );
+        let txid = tx.txid();
+
+        let mut opts = ConnectionOptions::default();
+        opts.dandelion = true;
+        opts.dandelion_embargo = 0;
+
+        let (sx, rx) = std::sync::mpsc::sync_channel(MAX_STEM_TRANSACTIONS + 10);
+        let mut relayer = Relayer::new_with_options(NetworkHandle::new(sx), &opts);
+
+        let sortdb = peer.sortdb.take().unwrap();
+        let mut stacks_node = peer.stacks_node.take().unwrap();
+        let mut mempool = peer.mempool.take().unwrap();
+
+        // a transaction uploaded to us gets forwarded to our stem relay, but doesn't go into our
+        // mempool yet
+        let mut network_result = NetworkResult::new(0, 0, 0, 0);
+        let fluffed = relayer
+            .process_stem_transactions(
+                &mut network_result,
+                vec![tx.clone()],
+                &sortdb,
+                &mut stacks_node.chainstate,
+                &mut mempool,
+                None,
+            )
+            .unwrap();
+        assert_eq!(fluffed.len(), 0);
+        assert!(relayer.stem_txs.contains_key(&txid));
+        assert!(!mempool.has_tx(&txid));
+        match rx.try_recv().unwrap() {
+            NetworkRequest::Stem(stemmed_tx) => assert_eq!(stemmed_tx, tx),
+            _ => panic!("Expected a stem request"),
+        }
+
+        // seeing it broadcast clears its fail-safe
+        let mut network_result = NetworkResult::new(0, 0, 0, 0);
+        network_result.synced_transactions.push(tx.clone());
+        relayer.clear_fluffed_stem_transactions(&network_result);
+        assert!(relayer.stem_txs.is_empty());
+
+        // not seeing it broadcast before its embargo expires makes us store and broadcast it
+        relayer.stem_transaction(tx.clone());
+        let _ = rx.try_recv().unwrap();
+
+        let stored = relayer
+            .fluff_expired_stem_transactions(
+                &sortdb,
+                &mut stacks_node.chainstate,
+                &mut mempool,
+                None,
+            )
+            .unwrap();
+        assert_eq!(stored, vec![tx.clone()]);
+        assert!(relayer.stem_txs.is_empty());
+        assert!(mempool.has_tx(&txid));
+        match rx.try_recv().unwrap() {
+            NetworkRequest::Broadcast(relayers, StacksMessageType::Transaction(fluffed_tx)) => {
+                assert_eq!(relayers.len(), 0);
+                assert_eq!(fluffed_tx, tx);
+            }
+            _ => panic!("Expected a broadcast request"),
+        }
+
+        // once we're stemming as many transactions as we'll track, new ones get fluffed right away
+        mempool.drop_txs(&[txid.clone()]).unwrap();
+        for i in 0..MAX_STEM_TRANSACTIONS {
+            let mut txid_bytes = [0u8; 32];
+            txid_bytes[0..8].copy_from_slice(&(i as u64).to_be_bytes());
+            relayer.stem_txs.insert(
+                Txid(txid_bytes),
+                StemTransaction {
+                    tx: tx.clone(),
+                    embargo_deadline: u64::MAX,
+                },
+            );
+        }
+
+        let mut network_result = NetworkResult::new(0, 0, 0, 0);
+        let fluffed = relayer
+            .process_stem_transactions(
+                &mut network_result,
+                vec![tx.clone()],
+                &sortdb,
+                &mut stacks_node.chainstate,
+                &mut mempool,
+                None,
+            )
+            .unwrap();
+        assert_eq!(fluffed, vec![tx.clone()]);
+        assert_eq!(relayer.stem_txs.len(), MAX_STEM_TRANSACTIONS);
+        assert!(mempool.has_tx(&txid));
+        match rx.try_recv().unwrap() {
+            NetworkRequest::Broadcast(_, StacksMessageType::Transaction(fluffed_tx)) => {
+                assert_eq!(fluffed_tx, tx);
+            }
+            _ => panic!("Expected a broadcast request"),
+        }
+
+        peer.sortdb = Some(sortdb);
+        peer.stacks_node = Some(stacks_node);
+        peer.mempool = Some(mempool);
+    }
+
     // TODO: process bans
     // TODO: test sending invalid blocks-available and microblocks-available (should result in a ban)
     // TODO: test sending invalid transactions (should result in a ban)
@@ -17,6 +17,7 @@
  along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
 */
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
@@ -36,13 +37,15 @@ use crate::burnchains::BurnchainView;
 use crate::burnchains::*;
 use crate::chainstate::burn::db::sortdb::SortitionDB;
 use crate::chainstate::burn::ConsensusHash;
+use crate::chainstate::snapshot::Error as snapshot_error;
+use crate::chainstate::snapshot::SnapshotAnchor;
 use crate::chainstate::stacks::db::blocks::CheckError;
 use crate::chainstate::stacks::db::{
     blocks::MINIMUM_TX_FEE_RATE_PER_BYTE, StacksChainState, StreamCursor,
 };
 use crate::chainstate::stacks::Error as chain_error;
 use crate::chainstate::stacks::*;
-use crate::clarity_vm::clarity::ClarityConnection;
+use crate::clarity_vm::clarity::{ClarityConnection, ClarityReadOnlyConnection};
 use crate::codec::StacksMessageCodec;
 use crate::core::mempool::*;
 use crate::cost_estimates::metrics::CostMetric;
@@ -67,6 +70,7 @@ use crate::net::HttpResponseType;
 use crate::net::MemPoolSyncData;
 use crate::net::MicroblocksData;
 use crate::net::NeighborAddress;
+use crate::net::NeighborKey;
 use crate::net::NeighborsData;
 use crate::net::PeerAddress;
 use crate::net::PeerHost;
@@ -80,6 +84,7 @@ use crate::net::UnconfirmedTransactionResponse;
 use crate::net::UnconfirmedTransactionStatus;
 use crate::net::UrlString;
 use crate::net::HTTP_REQUEST_ID_RESERVED;
+use crate::net::MAX_BLOCK_RANGE;
 use crate::net::MAX_HEADERS;
 use crate::net::MAX_NEIGHBORS_DATA_LEN;
 use crate::net::{
@@ -95,8 +100,10 @@ use crate::util_lib::db::Error as db_error;
 use clarity::vm::database::clarity_store::make_contract_hash_key;
 use clarity::vm::types::TraitIdentifier;
 use clarity::vm::{
+    analysis::cost_bounds::{compute_cost_bounds, CostBound},
     analysis::errors::CheckErrors,
-    ast::ASTRules,
+    analysis::run_analysis,
+    ast::{build_ast_with_rules, ASTRules},
     costs::{ExecutionCost, LimitedCostTracker},
     database::{
         clarity_store::ContractCommitment, BurnStateDB, ClarityDatabase, ClaritySerializable,
@@ -105,7 +112,7 @@ use clarity::vm::{
     errors::Error as ClarityRuntimeError,
     errors::Error::Unchecked,
     errors::InterpreterError,
-    types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData},
+    types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData, TypeSignature},
     ClarityName, ContractName, SymbolicExpression, Value,
 };
 use stacks_common::util::get_epoch_time_secs;
@@ -116,6 +123,7 @@ use crate::chainstate::stacks::StacksBlockHeader;
 use crate::clarity_vm::database::marf::MarfedKV;
 use stacks_common::types::chainstate::BlockHeaderHash;
 use stacks_common::types::chainstate::{BurnchainHeaderHash, StacksAddress, StacksBlockId};
+use stacks_common::types::StacksEpochId;
 use stacks_common::types::StacksPublicKeyBuffer;
 
 use crate::{
@@ -129,6 +137,16 @@ use super::{RPCPoxCurrentCycleInfo, RPCPoxNextCycleInfo};
 
 pub const STREAM_CHUNK_SIZE: u64 = 4096;
 
+/// Worst-case function costs computed for the contract ABI endpoint, keyed by contract,
+/// hash of the contract source, epoch, and number of applied cost-function proposals.
+pub type CostBoundsCache = HashMap<
+    (QualifiedContractIdentifier, Sha256Sum, StacksEpochId, u128),
+    BTreeMap<ClarityName, CostBound>,
+>;
+
+/// Maximum number of contracts whose cost bounds are cached
+pub const MAX_COST_BOUNDS_CACHE_LEN: usize = 1024;
+
 #[derive(Default)]
 pub struct RPCHandlerArgs<'a> {
     pub exit_at_block_height: Option<u64>,
@@ -444,18 +462,25 @@ impl RPCNeighborsInfo {
         chain_view: &BurnchainView,
         peerdb: &PeerDB,
     ) -> Result<RPCNeighborsInfo, net_error> {
+        let reputation_of = |nk: &NeighborKey| {
+            PeerDB::get_peer_reputation_score(peerdb.conn(), nk.network_id, &nk.addrbytes, nk.port)
+                .map_err(net_error::DBError)
+        };
+
         let bootstrap_nodes =
             PeerDB::get_bootstrap_peers(peerdb.conn(), network_id).map_err(net_error::DBError)?;
-        let bootstrap = bootstrap_nodes
-            .into_iter()
-            .map(|n| {
+        let mut bootstrap = vec![];
+        for n in bootstrap_nodes.into_iter() {
+            let reputation = reputation_of(&n.addr)?;
+            bootstrap.push(
                 RPCNeighbor::from_neighbor_key_and_pubkh(
                     n.addr.clone(),
                     Hash160::from_node_public_key(&n.public_key),
                     true,
                 )
-            })
-            .collect();
+                .with_reputation(reputation),
+            );
+        }
 
         let neighbor_sample = PeerDB::get_random_neighbors(
             peerdb.conn(),
@@ -467,34 +492,37 @@ impl RPCNeighborsInfo {
         )
         .map_err(net_error::DBError)?;
 
-        let sample: Vec<RPCNeighbor> = neighbor_sample
-            .into_iter()
-            .map(|n| {
+        let mut sample = vec![];
+        for n in neighbor_sample.into_iter() {
+            let reputation = reputation_of(&n.addr)?;
+            sample.push(
                 RPCNeighbor::from_neighbor_key_and_pubkh(
                     n.addr.clone(),
                     Hash160::from_node_public_key(&n.public_key),
                     true,
                 )
-            })
-            .collect();
+                .with_reputation(reputation),
+            );
+        }
 
         let mut inbound = vec![];
         let mut outbound = vec![];
         for (_, convo) in peers.iter() {
             let nk = convo.to_neighbor_key();
             let naddr = convo.to_neighbor_address();
+            let reputation = reputation_of(&convo.best_effort_neighbor_key())?
+                .saturating_add(convo.stats.reputation_delta);
+            let rpc_neighbor = RPCNeighbor::from_neighbor_key_and_pubkh(
+                nk,
+                naddr.public_key_hash,
+                convo.is_authenticated(),
+            )
+            .with_reputation(reputation)
+            .with_bandwidth(&convo.stats);
             if convo.is_outbound() {
-                outbound.push(RPCNeighbor::from_neighbor_key_and_pubkh(
-                    nk,
-                    naddr.public_key_hash,
-                    convo.is_authenticated(),
-                ));
+                outbound.push(rpc_neighbor);
             } else {
-                inbound.push(RPCNeighbor::from_neighbor_key_and_pubkh(
-                    nk,
-                    naddr.public_key_hash,
-                    convo.is_authenticated(),
-                ));
+                inbound.push(rpc_neighbor);
             }
         }
 
@@ -517,8 +545,13 @@ impl ConversationHttp {
     ) -> ConversationHttp {
         let mut stacks_http = StacksHttp::new(peer_addr.clone());
         stacks_http.maximum_call_argument_size = conn_opts.maximum_call_argument_size;
+        let mut connection = ConnectionHttp::new(stacks_http, conn_opts, None);
+        connection.set_bandwidth_limits(
+            conn_opts.max_peer_inbound_bandwidth,
+            conn_opts.max_peer_outbound_bandwidth,
+        );
         ConversationHttp {
-            connection: ConnectionHttp::new(stacks_http, conn_opts, None),
+            connection: connection,
             conn_id: conn_id,
             timeout: conn_opts.timeout,
             reply_streams: VecDeque::new(),
@@ -558,6 +591,12 @@ impl ConversationHttp {
         self.pending_request.is_some()
     }
 
+    /// Did our last read or write on this conversation stop early because it hit its bandwidth
+    /// limit?
+    pub fn is_throttled(&self) -> bool {
+        self.connection.is_throttled()
+    }
+
     /// Start a HTTP request from this peer, and expect a response.
     /// Returns the request handle; does not set the handle into this connection.
     fn start_request(&mut self, req: HttpRequestType) -> Result<ReplyHandleHttp, net_error> {
@@ -696,6 +735,44 @@ impl ConversationHttp {
         }
     }
 
+    /// Handle a GET snapshot anchor.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_getsnapshotanchor<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        burnchain: &Burnchain,
+        reward_cycle: u64,
+        canonical_stacks_tip_height: u64,
+    ) -> Result<(), net_error> {
+        let response_metadata =
+            HttpResponseMetadata::from_http_request_type(req, Some(canonical_stacks_tip_height));
+
+        match SnapshotAnchor::load(sortdb, chainstate, burnchain, reward_cycle) {
+            Ok(Some(anchor)) => {
+                let response = HttpResponseType::SnapshotAnchor(response_metadata, anchor);
+                response.send(http, fd)
+            }
+            Ok(None) | Err(snapshot_error::NoSuchAnchor) => {
+                let response = HttpResponseType::NotFound(
+                    response_metadata,
+                    format!("No snapshot anchor for reward cycle {}", reward_cycle),
+                );
+                response.send(http, fd)
+            }
+            Err(e) => {
+                warn!("Failed to load snapshot anchor {:?}: {:?}", req, &e);
+                let response = HttpResponseType::ServerError(
+                    response_metadata,
+                    "Failed to load snapshot anchor".to_string(),
+                );
+                response.send(http, fd)
+            }
+        }
+    }
+
     fn handle_getattachmentsinv<W: Write>(
         http: &mut StacksHttp,
         fd: &mut W,
@@ -910,12 +987,15 @@ impl ConversationHttp {
         // do we have this block?
         match StacksChainState::has_block_indexed(&chainstate.blocks_path, index_block_hash) {
             Ok(false) => {
-                return ConversationHttp::handle_notfound(
-                    http,
-                    fd,
-                    response_metadata,
-                    format!("No such block {}", index_block_hash.to_hex()),
-                );
+                let msg = match StacksChainState::is_block_pruned(chainstate.db(), index_block_hash)
+                {
+                    Ok(true) => format!(
+                        "Block {} has been pruned by this node",
+                        index_block_hash.to_hex()
+                    ),
+                    _ => format!("No such block {}", index_block_hash.to_hex()),
+                };
+                return ConversationHttp::handle_notfound(http, fd, response_metadata, msg);
             }
             Err(e) => {
                 // nope -- error trying to check
@@ -935,6 +1015,67 @@ impl ConversationHttp {
         }
     }
 
+    /// Handle a GET block range.  Start streaming the reply.
+    /// The response's preamble (but not the block data) will be synchronously written to the fd
+    /// (so use a fd that can buffer!)
+    /// Return a StreamCursor struct for the blocks that we're sending, so we can continue to
+    /// make progress sending them.
+    fn handle_getblockrange<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        tip: &StacksBlockId,
+        start_height: u64,
+        end_height: u64,
+        chainstate: &StacksChainState,
+        canonical_stacks_tip_height: u64,
+    ) -> Result<Option<StreamCursor>, net_error> {
+        let response_metadata =
+            HttpResponseMetadata::from_http_request_type(req, Some(canonical_stacks_tip_height));
+        if start_height == 0
+            || start_height > end_height
+            || end_height - start_height >= MAX_BLOCK_RANGE
+        {
+            // bad request
+            let response = HttpResponseType::BadRequestJSON(
+                response_metadata,
+                serde_json::Value::String(format!(
+                    "Invalid request: block range must start above 0 and span at most {} blocks",
+                    MAX_BLOCK_RANGE
+                )),
+            );
+            return response.send(http, fd).and_then(|_| Ok(None));
+        }
+
+        match StreamCursor::new_block_range(chainstate, tip, start_height, end_height) {
+            Ok(stream) => {
+                let response = HttpResponseType::BlockStream(response_metadata);
+                response.send(http, fd).and_then(|_| Ok(Some(stream)))
+            }
+            Err(chain_error::NoSuchBlockError) => ConversationHttp::handle_notfound(
+                http,
+                fd,
+                response_metadata,
+                format!(
+                    "Blocks {}-{} off of {} are not all available from this node",
+                    start_height, end_height, tip
+                ),
+            ),
+            Err(e) => {
+                // nope -- error trying to check
+                warn!("Failed to serve block range {:?}: {:?}", req, &e);
+                let response = HttpResponseType::ServerError(
+                    response_metadata,
+                    format!(
+                        "Failed to query blocks {}-{} off of {}",
+                        start_height, end_height, tip
+                    ),
+                );
+                response.send(http, fd).and_then(|_| Ok(None))
+            }
+        }
+    }
+
     /// Handle a GET confirmed microblock stream, by _anchor block hash_.  Start streaming the reply.
     /// The response's preamble (but not the block data) will be synchronously written to the fd
     /// (so use a fd that can buffer!)
@@ -952,6 +1093,20 @@ impl ConversationHttp {
         let response_metadata =
             HttpResponseMetadata::from_http_request_type(req, Some(canonical_stacks_tip_height));
 
+        if let Ok(true) =
+            StacksChainState::is_block_pruned(chainstate.db(), index_anchor_block_hash)
+        {
+            return ConversationHttp::handle_notfound(
+                http,
+                fd,
+                response_metadata,
+                format!(
+                    "Microblock stream confirmed by anchor block {} has been pruned by this node",
+                    &index_anchor_block_hash
+                ),
+            );
+        }
+
         match chainstate.has_processed_microblocks(index_anchor_block_hash) {
             Ok(true) => {}
             Ok(false) => {
@@ -1089,6 +1244,21 @@ impl ConversationHttp {
                 );
             }
             Ok(true) => {
+                if let Ok(true) = StacksChainState::is_microblock_pruned(
+                    chainstate.db(),
+                    tail_index_microblock_hash,
+                ) {
+                    return ConversationHttp::handle_notfound(
+                        http,
+                        fd,
+                        response_metadata,
+                        format!(
+                            "Microblock stream ending with {} has been pruned by this node",
+                            &tail_index_microblock_hash
+                        ),
+                    );
+                }
+
                 // yup! start streaming it back
                 let (response, stream_opt) = match StreamCursor::new_microblock_confirmed(
                     chainstate,
@@ -1532,6 +1702,75 @@ impl ConversationHttp {
         response.send(http, fd).map(|_| ())
     }
 
+    /// Compute the worst-case costs of a contract's public and read-only functions under
+    /// the current cost contract.  The stored analysis doesn't keep the contract's typed
+    /// expressions, so the contract has to be re-analyzed from its source; the result is
+    /// cached by contract source, epoch and the number of applied cost-function proposals.
+    fn contract_cost_bounds(
+        clarity_tx: &mut ClarityReadOnlyConnection,
+        mainnet: bool,
+        contract_identifier: &QualifiedContractIdentifier,
+        cache: &mut CostBoundsCache,
+    ) -> Option<BTreeMap<ClarityName, CostBound>> {
+        let epoch = clarity_tx.get_epoch();
+        let (contract_src, cost_votes) = clarity_tx.with_clarity_db_readonly(|clarity_db| {
+            let contract_src = clarity_db.get_contract_src(contract_identifier)?;
+            let cost_votes = clarity_db
+                .get_value("vm-costs::last_processed_count", &TypeSignature::UIntType)
+                .map(|result| result.value.expect_u128())
+                .unwrap_or(0);
+            Some((contract_src, cost_votes))
+        })?;
+
+        let cache_key = (
+            contract_identifier.clone(),
+            Sha256Sum::from_data(contract_src.as_bytes()),
+            epoch,
+            cost_votes,
+        );
+        if let Some(cost_bounds) = cache.get(&cache_key) {
+            return Some(cost_bounds.clone());
+        }
+
+        let mut cost_track = clarity_tx.with_clarity_db_readonly(|clarity_db| {
+            LimitedCostTracker::new_mid_block(
+                mainnet,
+                ExecutionCost::max_value(),
+                clarity_db,
+                epoch,
+            )
+            .ok()
+        })?;
+        let mut expressions = build_ast_with_rules(
+            contract_identifier,
+            &contract_src,
+            &mut (),
+            ASTRules::PrecheckSize,
+        )
+        .ok()?
+        .expressions;
+        let cost_bounds = clarity_tx.with_analysis_db_readonly(|db| {
+            // analyze the contract under the Clarity version it was deployed with
+            let clarity_version = db.load_contract(contract_identifier)?.clarity_version;
+            let contract_analysis = run_analysis(
+                contract_identifier,
+                &mut expressions,
+                db,
+                false,
+                LimitedCostTracker::new_free(),
+                clarity_version,
+            )
+            .ok()?;
+            compute_cost_bounds(&contract_analysis, &mut cost_track).ok()
+        })?;
+
+        if cache.len() >= MAX_COST_BOUNDS_CACHE_LEN {
+            cache.clear();
+        }
+        cache.insert(cache_key, cost_bounds.clone());
+        Some(cost_bounds)
+    }
+
     /// Handle a GET to fetch a contract's analysis data, given the chain tip.  Note that this isn't
     /// something that's anchored to the blockchain, and can be different across different versions
     /// of Stacks -- callers must trust the Stacks node to return correct analysis data.
@@ -1547,18 +1786,30 @@ impl ConversationHttp {
         contract_addr: &StacksAddress,
         contract_name: &ContractName,
         canonical_stacks_tip_height: u64,
+        cost_bounds_cache: &mut CostBoundsCache,
     ) -> Result<(), net_error> {
         let response_metadata =
             HttpResponseMetadata::from_http_request_type(req, Some(canonical_stacks_tip_height));
         let contract_identifier =
             QualifiedContractIdentifier::new(contract_addr.clone().into(), contract_name.clone());
 
+        let mainnet = chainstate.mainnet;
+
         let response =
             match chainstate.maybe_read_only_clarity_tx(&sortdb.index_conn(), tip, |clarity_tx| {
-                clarity_tx.with_analysis_db_readonly(|db| {
+                let mut contract_interface = clarity_tx.with_analysis_db_readonly(|db| {
                     let contract = db.load_contract(&contract_identifier)?;
                     contract.contract_interface
-                })
+                })?;
+                if let Some(cost_bounds) = ConversationHttp::contract_cost_bounds(
+                    clarity_tx,
+                    mainnet,
+                    &contract_identifier,
+                    cost_bounds_cache,
+                ) {
+                    contract_interface.set_cost_bounds(&cost_bounds);
+                }
+                Some(contract_interface)
             }) {
                 Ok(Some(Some(data))) => HttpResponseType::GetContractABI(response_metadata, data),
                 Ok(Some(None)) => HttpResponseType::NotFound(
@@ -1913,7 +2164,8 @@ impl ConversationHttp {
     /// Handle a transaction.  Directly submit it to the mempool so the client can see any
     /// rejection reasons up-front (different from how the peer network handles it).  Indicate
     /// whether or not the transaction was accepted (and thus needs to be forwarded) in the return
-    /// value.
+    /// value.  If `stem` is set, then the transaction is only checked, and is left for the relayer
+    /// to store once it broadcasts it.
     fn handle_post_transaction<W: Write>(
         http: &mut StacksHttp,
         fd: &mut W,
@@ -1929,6 +2181,7 @@ impl ConversationHttp {
         event_observer: Option<&dyn MemPoolEventDispatcher>,
         canonical_stacks_tip_height: u64,
         ast_rules: ASTRules,
+        stem: bool,
     ) -> Result<bool, net_error> {
         let txid = tx.txid();
         let response_metadata =
@@ -1953,6 +2206,26 @@ impl ConversationHttp {
                     HttpResponseType::TransactionID(response_metadata, txid),
                     false,
                 )
+            } else if stem {
+                // don't store it until it's fluffed, so a mempool sync can't reveal that it
+                // started here.  Just make sure we'd accept it.
+                let tx_size = tx.serialize_to_vec().len() as u64;
+                match chainstate.will_admit_mempool_tx(&consensus_hash, &block_hash, &tx, tx_size) {
+                    Ok(_) => {
+                        debug!("Will stem POSTed transaction {}", &txid);
+                        (
+                            HttpResponseType::TransactionID(response_metadata, txid),
+                            true,
+                        )
+                    }
+                    Err(e) => {
+                        debug!("Mempool rejected POSTed transaction {}: {:?}", &txid, &e);
+                        (
+                            HttpResponseType::BadRequestJSON(response_metadata, e.into_json(&txid)),
+                            false,
+                        )
+                    }
+                }
             } else {
                 let tip = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn())?;
                 let stacks_epoch = sortdb
@@ -2317,6 +2590,19 @@ impl ConversationHttp {
                 )?;
                 None
             }
+            HttpRequestType::GetSnapshotAnchor(ref _md, ref reward_cycle) => {
+                ConversationHttp::handle_getsnapshotanchor(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    sortdb,
+                    chainstate,
+                    &network.burnchain,
+                    *reward_cycle,
+                    network.burnchain_tip.canonical_stacks_tip_height,
+                )?;
+                None
+            }
             HttpRequestType::GetHeaders(ref _md, ref quantity, ref tip_req) => {
                 if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
                     &mut self.connection.protocol,
@@ -2350,6 +2636,35 @@ impl ConversationHttp {
                     network.burnchain_tip.canonical_stacks_tip_height,
                 )?
             }
+            HttpRequestType::GetBlockRange(
+                ref _md,
+                ref start_height,
+                ref end_height,
+                ref tip_req,
+            ) => {
+                if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    tip_req,
+                    sortdb,
+                    chainstate,
+                    network.burnchain_tip.canonical_stacks_tip_height,
+                )? {
+                    ConversationHttp::handle_getblockrange(
+                        &mut self.connection.protocol,
+                        &mut reply,
+                        &req,
+                        &tip,
+                        *start_height,
+                        *end_height,
+                        chainstate,
+                        network.burnchain_tip.canonical_stacks_tip_height,
+                    )?
+                } else {
+                    None
+                }
+            }
             HttpRequestType::GetMicroblocksIndexed(ref _md, ref index_head_hash) => {
                 ConversationHttp::handle_getmicroblocks_indexed(
                     &mut self.connection.protocol,
@@ -2521,6 +2836,7 @@ impl ConversationHttp {
                         contract_addr,
                         contract_name,
                         network.burnchain_tip.canonical_stacks_tip_height,
+                        &mut network.cost_bounds_cache,
                     )?;
                 }
                 None
@@ -2623,6 +2939,7 @@ impl ConversationHttp {
                             handler_opts.event_observer.as_deref(),
                             network.burnchain_tip.canonical_stacks_tip_height,
                             network.ast_rules,
+                            network.connection_opts.dandelion,
                         )?;
                         if accepted {
                             // forward to peer network
@@ -3241,6 +3558,14 @@ impl ConversationHttp {
         )
     }
 
+    /// Make a new get-snapshot-anchor request to this endpoint
+    pub fn new_getsnapshotanchor(&self, reward_cycle: u64) -> HttpRequestType {
+        HttpRequestType::GetSnapshotAnchor(
+            HttpRequestMetadata::from_host(self.peer_host.clone(), None),
+            reward_cycle,
+        )
+    }
+
     /// Make a new getblock request to this endpoint
     pub fn new_getblock(&self, index_block_hash: StacksBlockId) -> HttpRequestType {
         HttpRequestType::GetBlock(
@@ -3249,6 +3574,21 @@ impl ConversationHttp {
         )
     }
 
+    /// Make a new get-block-range request to this endpoint
+    pub fn new_getblockrange(
+        &self,
+        start_height: u64,
+        end_height: u64,
+        tip_req: TipRequest,
+    ) -> HttpRequestType {
+        HttpRequestType::GetBlockRange(
+            HttpRequestMetadata::from_host(self.peer_host.clone(), None),
+            start_height,
+            end_height,
+            tip_req,
+        )
+    }
+
     /// Make a new get-microblocks request to this endpoint
     pub fn new_getmicroblocks_indexed(
         &self,
@@ -3462,6 +3802,7 @@ impl ConversationHttp {
 mod test {
     use std::cell::RefCell;
     use std::convert::TryInto;
+    use std::fs;
     use std::iter::FromIterator;
 
     use crate::burnchains::Burnchain;
@@ -3479,6 +3820,7 @@ mod test {
     use crate::net::http::*;
     use crate::net::test::*;
     use crate::net::*;
+    use clarity::vm::analysis::contract_interface_builder::ContractInterfaceFunctionAccess;
     use clarity::vm::types::*;
     use stacks_common::address::*;
     use stacks_common::util::get_epoch_time_secs;
@@ -4079,6 +4421,41 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_rpc_bandwidth_limit() {
+        let peer_addr = "127.0.0.1:20443".parse::<SocketAddr>().unwrap();
+        let peer_host = PeerHost::from_socketaddr(&peer_addr);
+
+        let mut request_buf = vec![];
+        for _ in 0..5 {
+            request_buf.extend_from_slice(
+                format!("GET /v2/info HTTP/1.1\r\nHost: {}\r\n\r\n", &peer_host).as_bytes(),
+            );
+        }
+        let request_len = request_buf.len() / 5;
+
+        // no limit: everything gets read
+        let conn_opts = ConnectionOptions::default();
+        let mut convo =
+            ConversationHttp::new(peer_addr.clone(), None, peer_host.clone(), &conn_opts, 0);
+        let num_read = convo
+            .recv(&mut NetCursor::new(request_buf.as_slice()))
+            .unwrap();
+        assert_eq!(num_read, 5 * request_len);
+        assert!(!convo.is_throttled());
+
+        // limited to two requests' worth of bytes per second
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.max_peer_inbound_bandwidth = (2 * request_len) as u64;
+        let mut convo =
+            ConversationHttp::new(peer_addr.clone(), None, peer_host.clone(), &conn_opts, 0);
+        let num_read = convo
+            .recv(&mut NetCursor::new(request_buf.as_slice()))
+            .unwrap();
+        assert_eq!(num_read, 2 * request_len);
+        assert!(convo.is_throttled());
+    }
+
     /// This test tests two things:
     /// (1) the get info RPC call
     /// (2) whether the ConversationHttp object gets correctly updated with a peer's canonical
@@ -4264,6 +4641,307 @@ mod test {
         );
     }
 
+    #[test]
+    #[ignore]
+    fn test_rpc_getsnapshotanchor() {
+        let server_anchor = RefCell::new(None);
+        test_rpc(
+            "test_rpc_getsnapshotanchor",
+            40820,
+            40821,
+            50820,
+            50821,
+            true,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let sortdb = peer_server.sortdb.as_ref().unwrap();
+                let chainstate = &peer_server.stacks_node.as_ref().unwrap().chainstate;
+                let anchor =
+                    SnapshotAnchor::load(sortdb, chainstate, &peer_server.config.burnchain, 0)
+                        .unwrap()
+                        .unwrap();
+                *server_anchor.borrow_mut() = Some(anchor);
+                convo_client.new_getsnapshotanchor(0)
+            },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             convo_client,
+             convo_server| {
+                match http_response {
+                    HttpResponseType::SnapshotAnchor(_, anchor) => {
+                        assert_eq!(Some((*anchor).clone()), *server_anchor.borrow());
+                        assert_eq!(anchor.reward_cycle, 0);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_getsnapshotanchor_future_reward_cycle() {
+        test_rpc(
+            "test_rpc_getsnapshotanchor_future_reward_cycle",
+            40822,
+            40823,
+            50822,
+            50823,
+            true,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| { convo_client.new_getsnapshotanchor(1000000) },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             convo_client,
+             convo_server| {
+                match http_response {
+                    HttpResponseType::NotFound(..) => true,
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_getblockrange() {
+        let server_block_cell = RefCell::new(None);
+
+        test_rpc(
+            "test_rpc_getblockrange",
+            40824,
+            40825,
+            50824,
+            50825,
+            true,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let sortdb = peer_server.sortdb.as_ref().unwrap();
+                let chainstate = &peer_server.stacks_node.as_ref().unwrap().chainstate;
+                let (consensus_hash, block_hash) =
+                    SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn()).unwrap();
+                let block = StacksChainState::load_block(
+                    &chainstate.blocks_path,
+                    &consensus_hash,
+                    &block_hash,
+                )
+                .unwrap()
+                .unwrap();
+                *server_block_cell.borrow_mut() = Some((consensus_hash, block));
+
+                // the server has only mined one block
+                convo_client.new_getblockrange(1, 1, TipRequest::UseLatestAnchoredTip)
+            },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             ref convo_client,
+             ref convo_server| {
+                match http_response {
+                    HttpResponseType::BlockRange(_, entries) => {
+                        let (consensus_hash, block) = server_block_cell.borrow().clone().unwrap();
+                        assert_eq!(entries.len(), 1);
+                        assert_eq!(entries[0].consensus_hash, consensus_hash);
+                        assert_eq!(entries[0].block, block);
+                        assert_eq!(entries[0].confirmed_microblocks.len(), 0);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_getblockrange_too_big() {
+        test_rpc(
+            "test_rpc_getblockrange_too_big",
+            40826,
+            40827,
+            50826,
+            50827,
+            true,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                convo_client.new_getblockrange(
+                    1,
+                    MAX_BLOCK_RANGE + 1,
+                    TipRequest::UseLatestAnchoredTip,
+                )
+            },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             ref convo_client,
+             ref convo_server| {
+                match http_response {
+                    HttpResponseType::BadRequest(..) => true,
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_post_transaction_stem() {
+        let posted_txid = RefCell::new(Txid([0u8; 32]));
+
+        test_rpc(
+            "test_rpc_post_transaction_stem",
+            40830,
+            40831,
+            50830,
+            50831,
+            false,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                peer_server.network.connection_opts.dandelion = true;
+
+                let privk = StacksPrivateKey::from_hex(
+                    "9f1f85a512a96a244e4c0d762788500687feb97481639572e3bffbd6860e6ab001",
+                )
+                .unwrap();
+                let recipient = StacksAddress::from_public_keys(
+                    C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+                    &AddressHashMode::SerializeP2PKH,
+                    1,
+                    &vec![StacksPublicKey::from_private(&StacksPrivateKey::new())],
+                )
+                .unwrap();
+
+                let mut tx = StacksTransaction::new(
+                    TransactionVersion::Testnet,
+                    TransactionAuth::from_p2pkh(&privk).unwrap(),
+                    TransactionPayload::TokenTransfer(
+                        recipient.to_account_principal(),
+                        123,
+                        TokenTransferMemo([0u8; 34]),
+                    ),
+                );
+                tx.chain_id = 0x80000000;
+                tx.set_tx_fee(1000);
+                tx.set_origin_nonce(2);
+
+                let mut tx_signer = StacksTransactionSigner::new(&tx);
+                tx_signer.sign_origin(&privk).unwrap();
+                let tx = tx_signer.get_tx().unwrap();
+
+                *posted_txid.borrow_mut() = tx.txid();
+                convo_client.new_post_transaction(tx)
+            },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             ref convo_client,
+             ref convo_server| {
+                let txid = posted_txid.borrow().clone();
+                match http_response {
+                    HttpResponseType::TransactionID(_, accepted_txid) => {
+                        assert_eq!(*accepted_txid, txid);
+                    }
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        return false;
+                    }
+                }
+
+                // accepted, but held back from the mempool until it's fluffed, so that a mempool
+                // sync can't reveal it
+                !peer_server.mempool.as_ref().unwrap().has_tx(&txid)
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_pruned_getblock() {
+        test_rpc(
+            "test_rpc_pruned_getblock",
+            40828,
+            40829,
+            50828,
+            50829,
+            true,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let sortdb = peer_server.sortdb.as_ref().unwrap();
+                let (consensus_hash, block_hash) =
+                    SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn()).unwrap();
+                let index_block_hash =
+                    StacksBlockHeader::make_index_block_hash(&consensus_hash, &block_hash);
+
+                // prune the server's only block
+                let chainstate = &mut peer_server.stacks_node.as_mut().unwrap().chainstate;
+                let block_path = StacksChainState::get_index_block_path(
+                    &chainstate.blocks_path,
+                    &index_block_hash,
+                )
+                .unwrap();
+                let tx = chainstate.db_tx_begin().unwrap();
+                tx.execute(
+                    "INSERT INTO pruned_blocks (index_block_hash, height) VALUES (?1, 1)",
+                    &[&index_block_hash],
+                )
+                .unwrap();
+                tx.commit().unwrap();
+                fs::remove_file(&block_path).unwrap();
+                assert!(
+                    StacksChainState::is_block_pruned(chainstate.db(), &index_block_hash).unwrap()
+                );
+
+                convo_client.new_getblock(index_block_hash)
+            },
+            |ref http_request,
+             ref http_response,
+             ref mut peer_client,
+             ref mut peer_server,
+             ref convo_client,
+             ref convo_server| {
+                match http_response {
+                    HttpResponseType::NotFound(_, msg) => msg.contains("has been pruned"),
+                    _ => {
+                        error!("Invalid response: {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_rpc_getneighbors() {
@@ -5908,7 +6586,10 @@ mod test {
              ref convo_server| {
                 let req_md = http_request.metadata().clone();
                 match http_response {
-                    HttpResponseType::GetContractABI(response_md, data) => true,
+                    HttpResponseType::GetContractABI(response_md, data) => {
+                        // the server keeps the contract's cost bounds for later requests
+                        peer_server.network.cost_bounds_cache.len() == 1
+                    }
                     _ => {
                         error!("Invalid response; {:?}", &http_response);
                         false
@@ -5947,7 +6628,13 @@ mod test {
              ref convo_server| {
                 let req_md = http_request.metadata().clone();
                 match http_response {
-                    HttpResponseType::GetContractABI(response_md, data) => true,
+                    HttpResponseType::GetContractABI(response_md, data) => {
+                        // each public and read-only function reports its worst-case cost
+                        data.functions.iter().all(|function| {
+                            function.cost_bound.is_some()
+                                == (function.access != ContractInterfaceFunctionAccess::private)
+                        })
+                    }
                     _ => {
                         error!("Invalid response; {:?}", &http_response);
                         false
@@ -22,6 +22,7 @@ use std::io::ErrorKind;
 use std::io::{Read, Write};
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 
 use std::sync::mpsc::sync_channel;
@@ -38,6 +39,7 @@ use crate::net::http::*;
 use crate::net::p2p::{PeerMap, PeerNetwork};
 use crate::net::poll::*;
 use crate::net::rpc::*;
+use crate::net::socks::{SocksHandshake, SocksTarget};
 use crate::net::Error as net_error;
 use crate::net::*;
 
@@ -70,6 +72,12 @@ pub struct HttpPeer {
         ),
     >,
 
+    // outbound connections that are negotiating with our SOCKS5 proxy
+    pub proxy_handshakes: HashMap<usize, (SocksHandshake, SocketAddr)>,
+
+    // conversations that stopped reading or writing because they hit their bandwidth limit
+    pub throttled_events: HashSet<usize>,
+
     // server network handle
     pub http_server_handle: usize,
 
@@ -84,6 +92,8 @@ impl HttpPeer {
             sockets: HashMap::new(),
 
             connecting: HashMap::new(),
+            proxy_handshakes: HashMap::new(),
+            throttled_events: HashSet::new(),
             http_server_handle: server_handle,
 
             connection_opts: conn_opts,
@@ -147,11 +157,35 @@ impl HttpPeer {
             return Err(net_error::AlreadyConnected(event_id, http_nk));
         }
 
-        let sock = NetworkState::connect(&addr)?;
+        let peer_addr = PeerAddress::from_socketaddr(&addr);
+        if peer_addr.is_onion() && self.connection_opts.proxy.is_none() {
+            debug!(
+                "HTTP: cannot connect to onion address {} without a proxy",
+                &addr
+            );
+            return Err(net_error::ConnectionError);
+        }
+        let target = match SocksTarget::from_peer_address(&peer_addr, addr.port()) {
+            Some(target) => target,
+            None => {
+                debug!(
+                    "HTTP: cannot connect to onion stand-in address {} of an unknown name",
+                    &addr
+                );
+                return Err(net_error::ConnectionError);
+            }
+        };
+
+        // if we have a proxy, then connect to it instead, and ask it for the origin
+        let sock = NetworkState::connect(self.connection_opts.proxy.as_ref().unwrap_or(&addr))?;
         let hint_event_id = network_state.next_event_id()?;
         let next_event_id =
             network_state.register(self.http_server_handle, hint_event_id, &sock)?;
 
+        if self.connection_opts.proxy.is_some() {
+            self.proxy_handshakes
+                .insert(next_event_id, (SocksHandshake::connect(target), addr));
+        }
         self.connecting.insert(
             next_event_id,
             (sock, Some(data_url), request, get_epoch_time_secs()),
@@ -212,6 +246,7 @@ impl HttpPeer {
     /// Low-level method to register a socket/event pair on the p2p network interface.
     /// Call only once the socket is connected (called once the socket triggers ready).
     /// Will destroy the socket if we can't register for whatever reason.
+    /// proxied_addr is the remote peer's address if the socket goes through a proxy.
     fn register_http(
         &mut self,
         network_state: &mut NetworkState,
@@ -221,8 +256,9 @@ impl HttpPeer {
         mut socket: mio_net::TcpStream,
         outbound_url: Option<UrlString>,
         initial_request: Option<HttpRequestType>,
+        proxied_addr: Option<SocketAddr>,
     ) -> Result<(), net_error> {
-        let client_addr = match socket.peer_addr() {
+        let client_addr = match proxied_addr.map(Ok).unwrap_or_else(|| socket.peer_addr()) {
             Ok(addr) => addr,
             Err(e) => {
                 warn!("Failed to get peer address of {:?}: {:?}", &socket, &e);
@@ -300,6 +336,8 @@ impl HttpPeer {
                 let _ = network_state.deregister(event_id, &sock);
             }
         }
+        self.proxy_handshakes.remove(&event_id);
+        self.throttled_events.remove(&event_id);
     }
 
     /// Remove slow/unresponsive peers
@@ -413,6 +451,7 @@ impl HttpPeer {
                 client_sock,
                 None,
                 None,
+                None,
             ) {
                 // NOTE: register_http will deregister the socket for us
                 continue;
@@ -537,6 +576,35 @@ impl HttpPeer {
         self.connecting.contains_key(&event_id)
     }
 
+    /// Drive the SOCKS5 negotiation on a connecting socket, if it goes through a proxy.
+    /// Returns Ok(Some(None)) if the socket is not proxied, Ok(Some(Some(addr))) if the proxy has
+    /// connected us to addr, and Ok(None) if we're still waiting on the proxy.
+    fn advance_proxy_handshake(
+        &mut self,
+        event_id: usize,
+    ) -> Result<Option<Option<SocketAddr>>, net_error> {
+        let done = match (
+            self.proxy_handshakes.get_mut(&event_id),
+            self.connecting.get_mut(&event_id),
+        ) {
+            (Some((handshake, _)), Some((socket, ..))) => handshake.try_advance(socket),
+            _ => {
+                return Ok(Some(None));
+            }
+        };
+        match done {
+            Ok(true) => Ok(self
+                .proxy_handshakes
+                .remove(&event_id)
+                .map(|(_, addr)| Some(addr))),
+            Ok(false) => Ok(None),
+            Err(e) => {
+                self.proxy_handshakes.remove(&event_id);
+                Err(e)
+            }
+        }
+    }
+
     /// Process newly-connected sockets
     fn process_connecting_sockets(
         &mut self,
@@ -547,6 +615,22 @@ impl HttpPeer {
     ) -> () {
         for event_id in poll_state.ready.iter() {
             if self.connecting.contains_key(event_id) {
+                let proxied_addr = match self.advance_proxy_handshake(*event_id) {
+                    Ok(Some(proxied_addr_opt)) => proxied_addr_opt,
+                    Ok(None) => {
+                        // still talking to the proxy
+                        continue;
+                    }
+                    Err(_e) => {
+                        debug!(
+                            "HTTP event {} failed to connect through proxy: {:?}",
+                            event_id, &_e
+                        );
+                        self.deregister_http(network_state, *event_id);
+                        continue;
+                    }
+                };
+
                 let (socket, data_url, initial_request_opt, _) =
                     self.connecting.remove(event_id).unwrap();
 
@@ -560,6 +644,7 @@ impl HttpPeer {
                     socket,
                     data_url.clone(),
                     initial_request_opt,
+                    proxied_addr,
                 ) {
                     debug!(
                         "Failed to register HTTP connection ({}, {:?})",
@@ -687,6 +772,14 @@ impl HttpPeer {
         // set up connected sockets
         self.process_connecting_sockets(network_state, mempool, chainstate, &mut poll_state);
 
+        // sockets are edge-triggered, so a conversation that stopped early because it hit its
+        // bandwidth limit won't be reported ready again until new data arrives.  Revisit them.
+        for event_id in self.throttled_events.drain() {
+            if self.sockets.contains_key(&event_id) && !poll_state.ready.contains(&event_id) {
+                poll_state.ready.push(event_id);
+            }
+        }
+
         // run existing conversations, clear out broken ones, and get back messages forwarded to us
         let (stacks_msgs, error_events) = self.process_ready_sockets(
             &mut poll_state,
@@ -696,6 +789,13 @@ impl HttpPeer {
             mempool,
             handler_args,
         );
+        for event_id in poll_state.ready.iter() {
+            if let Some(convo) = self.peers.get(event_id) {
+                if convo.is_throttled() {
+                    self.throttled_events.insert(*event_id);
+                }
+            }
+        }
         for error_event in error_events {
             debug!("Failed HTTP connection on event {}", error_event);
             self.deregister_http(network_state, error_event);
@@ -0,0 +1,700 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020-2022 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A minimal SOCKS5 client (RFC 1928), without authentication.
+//!
+//! `SocksHandshake` is a state machine that can be driven over a non-blocking socket, so the p2p
+//! and HTTP servers can negotiate with the proxy as the socket becomes readable and writable.
+//! `connect_blocking()` and `resolve_blocking()` drive it over a blocking socket instead, for
+//! callers (DNS lookups, bitcoind) that already block.
+//!
+//! Besides CONNECT, the handshake supports Tor's RESOLVE extension (command 0xF0), so host names
+//! can be looked up through the proxy instead of leaking to the local resolver.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::net::Error as net_error;
+use crate::net::PeerAddress;
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_AUTH_NONE: u8 = 0x00;
+const SOCKS_CMD_CONNECT: u8 = 0x01;
+/// Tor extension: resolve a host name, and return its address in the reply
+const SOCKS_CMD_RESOLVE: u8 = 0xf0;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+const SOCKS_REPLY_SUCCEEDED: u8 = 0x00;
+
+/// Where to ask the proxy to connect to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocksTarget {
+    Addr(SocketAddr),
+    Host(String, u16),
+}
+
+impl SocksTarget {
+    /// The target for a peer address.  Onion addresses are turned back into their host names,
+    /// since only the proxy knows how to reach them.  Returns None for a v3 onion stand-in
+    /// address whose name this node doesn't know, since it can't be reached at all.
+    pub fn from_peer_address(addrbytes: &PeerAddress, port: u16) -> Option<SocksTarget> {
+        match addrbytes.to_onion_host() {
+            Some(host) => Some(SocksTarget::Host(host, port)),
+            None if addrbytes.is_onion_standin() => None,
+            None => Some(SocksTarget::Addr(addrbytes.to_socketaddr(port))),
+        }
+    }
+
+    /// The target for a host name or IP address literal
+    pub fn from_host(host: &str, port: u16) -> SocksTarget {
+        match host.parse::<IpAddr>() {
+            Ok(ip) => SocksTarget::Addr(SocketAddr::new(ip, port)),
+            Err(_) => SocksTarget::Host(host.to_string(), port),
+        }
+    }
+
+    /// Append the SOCKS5 encoding of this target (ATYP, address, port) to `out`
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), net_error> {
+        let port = match *self {
+            SocksTarget::Addr(SocketAddr::V4(ref addr)) => {
+                out.push(SOCKS_ATYP_IPV4);
+                out.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            SocksTarget::Addr(SocketAddr::V6(ref addr)) => {
+                out.push(SOCKS_ATYP_IPV6);
+                out.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            SocksTarget::Host(ref host, port) => {
+                if host.len() == 0 || host.len() > 255 {
+                    return Err(net_error::SerializeError(format!(
+                        "Invalid SOCKS5 host name '{}'",
+                        host
+                    )));
+                }
+                out.push(SOCKS_ATYP_DOMAIN);
+                out.push(host.len() as u8);
+                out.extend_from_slice(host.as_bytes());
+                port
+            }
+        };
+        out.extend_from_slice(&port.to_be_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SocksState {
+    /// waiting for the proxy to pick an authentication method
+    MethodReply,
+    /// waiting for the proxy to answer our request
+    Reply,
+    Done,
+}
+
+/// The client side of a SOCKS5 negotiation
+#[derive(Debug)]
+pub struct SocksHandshake {
+    state: SocksState,
+    command: u8,
+    target: SocksTarget,
+    out_buf: Vec<u8>,
+    out_ptr: usize,
+    in_buf: Vec<u8>,
+    /// the address the proxy gave back in its reply
+    bound_addr: Option<SocketAddr>,
+}
+
+impl SocksHandshake {
+    fn new(command: u8, target: SocksTarget) -> SocksHandshake {
+        SocksHandshake {
+            state: SocksState::MethodReply,
+            command,
+            target,
+            // greeting: version 5, one method, no authentication
+            out_buf: vec![SOCKS_VERSION, 0x01, SOCKS_AUTH_NONE],
+            out_ptr: 0,
+            in_buf: vec![],
+            bound_addr: None,
+        }
+    }
+
+    /// Ask the proxy to connect us to `target`
+    pub fn connect(target: SocksTarget) -> SocksHandshake {
+        SocksHandshake::new(SOCKS_CMD_CONNECT, target)
+    }
+
+    /// Ask the proxy to resolve `host` (Tor extension)
+    pub fn resolve(host: &str) -> SocksHandshake {
+        SocksHandshake::new(SOCKS_CMD_RESOLVE, SocksTarget::Host(host.to_string(), 0))
+    }
+
+    /// Is the negotiation finished?
+    pub fn is_done(&self) -> bool {
+        self.state == SocksState::Done
+    }
+
+    /// The address in the proxy's reply.  For a RESOLVE, this is the answer.
+    pub fn bound_addr(&self) -> Option<SocketAddr> {
+        self.bound_addr.clone()
+    }
+
+    /// How many bytes of the proxy's current reply do we need, given what we have so far?
+    fn reply_len(&self) -> usize {
+        match self.state {
+            SocksState::MethodReply => 2,
+            SocksState::Reply => {
+                // VER REP RSV ATYP ADDR PORT
+                if self.in_buf.len() < 5 {
+                    return 5;
+                }
+                match self.in_buf[3] {
+                    SOCKS_ATYP_IPV4 => 4 + 4 + 2,
+                    SOCKS_ATYP_IPV6 => 4 + 16 + 2,
+                    _ => 4 + 1 + (self.in_buf[4] as usize) + 2,
+                }
+            }
+            SocksState::Done => 0,
+        }
+    }
+
+    /// Handle a complete reply from the proxy
+    fn process_reply(&mut self) -> Result<(), net_error> {
+        if self.in_buf[0] != SOCKS_VERSION {
+            debug!("Proxy replied with SOCKS version {}", self.in_buf[0]);
+            return Err(net_error::ConnectionError);
+        }
+        match self.state {
+            SocksState::MethodReply => {
+                if self.in_buf[1] != SOCKS_AUTH_NONE {
+                    debug!(
+                        "Proxy requires SOCKS5 authentication method {}",
+                        self.in_buf[1]
+                    );
+                    return Err(net_error::ConnectionError);
+                }
+
+                let mut request = vec![SOCKS_VERSION, self.command, 0x00];
+                self.target.encode(&mut request)?;
+                self.out_buf = request;
+                self.out_ptr = 0;
+                self.state = SocksState::Reply;
+            }
+            SocksState::Reply => {
+                if self.in_buf[1] != SOCKS_REPLY_SUCCEEDED {
+                    debug!(
+                        "Proxy could not reach {:?}: SOCKS5 reply {}",
+                        &self.target, self.in_buf[1]
+                    );
+                    return Err(net_error::ConnectionError);
+                }
+
+                let len = self.in_buf.len();
+                let port = u16::from_be_bytes([self.in_buf[len - 2], self.in_buf[len - 1]]);
+                self.bound_addr = match self.in_buf[3] {
+                    SOCKS_ATYP_IPV4 => {
+                        let mut octets = [0u8; 4];
+                        octets.copy_from_slice(&self.in_buf[4..8]);
+                        Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::from(octets)), port))
+                    }
+                    SOCKS_ATYP_IPV6 => {
+                        let mut octets = [0u8; 16];
+                        octets.copy_from_slice(&self.in_buf[4..20]);
+                        Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port))
+                    }
+                    _ => None,
+                };
+                self.state = SocksState::Done;
+            }
+            SocksState::Done => {}
+        }
+        self.in_buf.clear();
+        Ok(())
+    }
+
+    /// Advance the negotiation as far as the socket allows.  Returns Ok(true) once the proxy has
+    /// answered our request, and Ok(false) if we need to wait for the socket.  Only the proxy's
+    /// replies are read from the socket -- anything the target sends afterwards is left for the
+    /// caller.
+    pub fn try_advance<S: Read + Write>(&mut self, fd: &mut S) -> Result<bool, net_error> {
+        while self.state != SocksState::Done {
+            // send what we have to send
+            while self.out_ptr < self.out_buf.len() {
+                match fd.write(&self.out_buf[self.out_ptr..]) {
+                    Ok(0) => {
+                        return Err(net_error::PeerNotConnected);
+                    }
+                    Ok(n) => {
+                        self.out_ptr += n;
+                    }
+                    Err(e) => {
+                        // a non-blocking socket may not have finished connecting to the proxy
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut
+                            || e.kind() == io::ErrorKind::NotConnected
+                        {
+                            return Ok(false);
+                        }
+                        return Err(net_error::WriteError(e));
+                    }
+                }
+            }
+
+            // read the reply, but no further
+            while self.in_buf.len() < self.reply_len() {
+                let mut buf = vec![0u8; self.reply_len() - self.in_buf.len()];
+                match fd.read(&mut buf) {
+                    Ok(0) => {
+                        return Err(net_error::PermanentlyDrained);
+                    }
+                    Ok(n) => {
+                        self.in_buf.extend_from_slice(&buf[0..n]);
+                    }
+                    Err(e) => {
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut
+                        {
+                            return Ok(false);
+                        }
+                        return Err(net_error::ReadError(e));
+                    }
+                }
+            }
+
+            self.process_reply()?;
+        }
+        Ok(true)
+    }
+}
+
+/// Run a handshake to completion over a fresh blocking connection to the proxy
+fn run_blocking(
+    proxy: &SocketAddr,
+    mut handshake: SocksHandshake,
+    timeout: Duration,
+) -> Result<(net::TcpStream, SocksHandshake), net_error> {
+    let mut stream = net::TcpStream::connect_timeout(proxy, timeout).map_err(|e| {
+        debug!("Failed to connect to SOCKS5 proxy {}: {:?}", proxy, &e);
+        net_error::ConnectionError
+    })?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|_e| net_error::ConnectionError)?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(|_e| net_error::ConnectionError)?;
+
+    // the socket blocks, so the handshake only stops early on a timeout
+    if !handshake.try_advance(&mut stream)? {
+        return Err(net_error::RecvTimeout);
+    }
+    Ok((stream, handshake))
+}
+
+/// Connect to `target` through the SOCKS5 proxy at `proxy`, blocking until the proxy has
+/// connected us (or `timeout` passes).
+pub fn connect_blocking(
+    proxy: &SocketAddr,
+    target: SocksTarget,
+    timeout: Duration,
+) -> Result<net::TcpStream, net_error> {
+    let (stream, _) = run_blocking(proxy, SocksHandshake::connect(target), timeout)?;
+    Ok(stream)
+}
+
+/// Look up `host` through the SOCKS5 proxy at `proxy` (which must support Tor's RESOLVE
+/// extension), blocking until it answers (or `timeout` passes).
+pub fn resolve_blocking(
+    proxy: &SocketAddr,
+    host: &str,
+    timeout: Duration,
+) -> Result<IpAddr, net_error> {
+    let (_, handshake) = run_blocking(proxy, SocksHandshake::resolve(host), timeout)?;
+    match handshake.bound_addr() {
+        Some(addr) => Ok(addr.ip()),
+        None => Err(net_error::LookupError(format!(
+            "Proxy did not resolve '{}' to an address",
+            host
+        ))),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::HashMap;
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::*;
+
+    /// Read a SOCKS5 target (ATYP, address, port) from a client
+    fn read_target(stream: &mut TcpStream) -> io::Result<SocksTarget> {
+        let mut atyp = [0u8; 1];
+        stream.read_exact(&mut atyp)?;
+        let target = match atyp[0] {
+            SOCKS_ATYP_IPV4 => {
+                let mut octets = [0u8; 4];
+                stream.read_exact(&mut octets)?;
+                let mut port = [0u8; 2];
+                stream.read_exact(&mut port)?;
+                SocksTarget::Addr(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::from(octets)),
+                    u16::from_be_bytes(port),
+                ))
+            }
+            SOCKS_ATYP_IPV6 => {
+                let mut octets = [0u8; 16];
+                stream.read_exact(&mut octets)?;
+                let mut port = [0u8; 2];
+                stream.read_exact(&mut port)?;
+                SocksTarget::Addr(SocketAddr::new(
+                    IpAddr::V6(Ipv6Addr::from(octets)),
+                    u16::from_be_bytes(port),
+                ))
+            }
+            _ => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len)?;
+                let mut host = vec![0u8; len[0] as usize];
+                stream.read_exact(&mut host)?;
+                let mut port = [0u8; 2];
+                stream.read_exact(&mut port)?;
+                SocksTarget::Host(
+                    String::from_utf8_lossy(&host).to_string(),
+                    u16::from_be_bytes(port),
+                )
+            }
+        };
+        Ok(target)
+    }
+
+    /// Write a SOCKS5 reply with the given code and bound address
+    fn write_reply(stream: &mut TcpStream, code: u8, addr: &SocketAddr) -> io::Result<()> {
+        let mut reply = vec![SOCKS_VERSION, code, 0x00];
+        SocksTarget::Addr(addr.clone()).encode(&mut reply).unwrap();
+        stream.write_all(&reply)
+    }
+
+    /// Serve one SOCKS5 client: CONNECT is forwarded to the real address (with host names looked
+    /// up in `hosts`), and RESOLVE is answered from `hosts`.  The target asked for is recorded in
+    /// `targets` before the proxy replies.
+    fn serve_client(
+        mut client: TcpStream,
+        hosts: &HashMap<String, IpAddr>,
+        targets: &Mutex<Vec<SocksTarget>>,
+    ) -> io::Result<()> {
+        let mut greeting = [0u8; 2];
+        client.read_exact(&mut greeting)?;
+        let mut methods = vec![0u8; greeting[1] as usize];
+        client.read_exact(&mut methods)?;
+        client.write_all(&[SOCKS_VERSION, SOCKS_AUTH_NONE])?;
+
+        let mut request = [0u8; 3];
+        client.read_exact(&mut request)?;
+        let target = read_target(&mut client)?;
+        targets.lock().unwrap().push(target.clone());
+        let unspecified = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+
+        let addr = match target {
+            SocksTarget::Addr(ref addr) => Some(addr.clone()),
+            SocksTarget::Host(ref host, port) => {
+                hosts.get(host).map(|ip| SocketAddr::new(ip.clone(), port))
+            }
+        };
+        let addr = match addr {
+            Some(addr) => addr,
+            None => {
+                // host unreachable
+                write_reply(&mut client, 0x04, &unspecified)?;
+                return Ok(());
+            }
+        };
+
+        if request[1] == SOCKS_CMD_RESOLVE {
+            write_reply(&mut client, SOCKS_REPLY_SUCCEEDED, &addr)?;
+            return Ok(());
+        }
+
+        let upstream = match TcpStream::connect(&addr) {
+            Ok(s) => s,
+            Err(_) => {
+                // connection refused
+                write_reply(&mut client, 0x05, &unspecified)?;
+                return Ok(());
+            }
+        };
+        write_reply(&mut client, SOCKS_REPLY_SUCCEEDED, &upstream.local_addr()?)?;
+
+        // shuttle bytes in both directions until either side hangs up
+        let mut client_read = client.try_clone()?;
+        let mut upstream_write = upstream.try_clone()?;
+        let mut upstream_read = upstream;
+        let mut client_write = client;
+        thread::spawn(move || {
+            let _ = io::copy(&mut client_read, &mut upstream_write);
+            let _ = upstream_write.shutdown(Shutdown::Write);
+        });
+        thread::spawn(move || {
+            let _ = io::copy(&mut upstream_read, &mut client_write);
+            let _ = client_write.shutdown(Shutdown::Write);
+        });
+        Ok(())
+    }
+
+    /// A stand-in SOCKS5 proxy for tests.  It listens on a local port, and serves each client in
+    /// its own thread.  Every target asked for is recorded, so tests can check that traffic went
+    /// through it.
+    pub struct TestSocksProxy {
+        pub addr: SocketAddr,
+        pub targets: Arc<Mutex<Vec<SocksTarget>>>,
+    }
+
+    impl TestSocksProxy {
+        pub fn spawn(hosts: HashMap<String, IpAddr>) -> TestSocksProxy {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let targets = Arc::new(Mutex::new(vec![]));
+            let thread_targets = targets.clone();
+            thread::spawn(move || {
+                for client in listener.incoming() {
+                    let client = match client {
+                        Ok(c) => c,
+                        Err(_) => {
+                            continue;
+                        }
+                    };
+                    let hosts = hosts.clone();
+                    let targets = thread_targets.clone();
+                    thread::spawn(move || {
+                        let _ = serve_client(client, &hosts, &targets);
+                    });
+                }
+            });
+            TestSocksProxy { addr, targets }
+        }
+
+        pub fn get_targets(&self) -> Vec<SocksTarget> {
+            self.targets.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn test_socks_connect_blocking() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let server_thread = thread::spawn(move || {
+            let (mut sock, _) = server.accept().unwrap();
+            let mut buf = [0u8; 5];
+            sock.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"hello");
+            sock.write_all(b"world").unwrap();
+        });
+
+        let mut hosts = HashMap::new();
+        hosts.insert("example.onion".to_string(), server_addr.ip());
+        let proxy = TestSocksProxy::spawn(hosts);
+
+        // by host name
+        let mut stream = connect_blocking(
+            &proxy.addr,
+            SocksTarget::Host("example.onion".to_string(), server_addr.port()),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        stream.write_all(b"hello").unwrap();
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"world");
+        server_thread.join().unwrap();
+
+        assert_eq!(
+            proxy.get_targets(),
+            vec![SocksTarget::Host(
+                "example.onion".to_string(),
+                server_addr.port()
+            )]
+        );
+
+        // unknown host names and refused connections are errors
+        assert!(connect_blocking(
+            &proxy.addr,
+            SocksTarget::Host("nope.onion".to_string(), 1234),
+            Duration::from_secs(10),
+        )
+        .is_err());
+        assert!(connect_blocking(
+            &proxy.addr,
+            SocksTarget::Addr(server_addr),
+            Duration::from_secs(10),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_socks_resolve_blocking() {
+        let mut hosts = HashMap::new();
+        hosts.insert(
+            "seed.example.com".to_string(),
+            IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+        );
+        hosts.insert(
+            "seed6.example.com".to_string(),
+            IpAddr::V6(Ipv6Addr::new(1, 2, 3, 4, 5, 6, 7, 8)),
+        );
+        let proxy = TestSocksProxy::spawn(hosts);
+
+        assert_eq!(
+            resolve_blocking(&proxy.addr, "seed.example.com", Duration::from_secs(10)).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))
+        );
+        assert_eq!(
+            resolve_blocking(&proxy.addr, "seed6.example.com", Duration::from_secs(10)).unwrap(),
+            IpAddr::V6(Ipv6Addr::new(1, 2, 3, 4, 5, 6, 7, 8))
+        );
+        assert!(
+            resolve_blocking(&proxy.addr, "nope.example.com", Duration::from_secs(10)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_socks_handshake_partial_io() {
+        // feed the proxy's replies one byte at a time, and make sure nothing past them is read
+        struct TrickleStream {
+            input: Vec<u8>,
+            ptr: usize,
+            ready: usize,
+            output: Vec<u8>,
+        }
+        impl Read for TrickleStream {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.ptr >= self.ready || buf.len() == 0 {
+                    return Err(io::Error::from(io::ErrorKind::WouldBlock));
+                }
+                buf[0] = self.input[self.ptr];
+                self.ptr += 1;
+                Ok(1)
+            }
+        }
+        impl Write for TrickleStream {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.output.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut input = vec![SOCKS_VERSION, SOCKS_AUTH_NONE];
+        input.extend_from_slice(&[
+            SOCKS_VERSION,
+            SOCKS_REPLY_SUCCEEDED,
+            0x00,
+            SOCKS_ATYP_DOMAIN,
+        ]);
+        input.push(4);
+        input.extend_from_slice(b"abcd");
+        input.extend_from_slice(&[0x12, 0x34]);
+        input.extend_from_slice(b"peer data");
+
+        let mut stream = TrickleStream {
+            input,
+            ptr: 0,
+            ready: 0,
+            output: vec![],
+        };
+
+        let target = SocksTarget::from_peer_address(
+            &PeerAddress::from_onion_host("expyuzz4wqqyqhjn.onion").unwrap(),
+            20444,
+        )
+        .unwrap();
+        assert_eq!(
+            target,
+            SocksTarget::Host("expyuzz4wqqyqhjn.onion".to_string(), 20444)
+        );
+
+        let mut handshake = SocksHandshake::connect(target);
+        let mut done = false;
+        for i in 0..stream.input.len() {
+            stream.ready = i;
+            done = handshake.try_advance(&mut stream).unwrap();
+            if done {
+                break;
+            }
+        }
+        assert!(done);
+        assert!(handshake.is_done());
+
+        // the peer's data was left alone
+        assert_eq!(&stream.input[stream.ptr..], b"peer data");
+
+        // we sent the greeting and the CONNECT request
+        let mut expected = vec![SOCKS_VERSION, 0x01, SOCKS_AUTH_NONE];
+        expected.extend_from_slice(&[SOCKS_VERSION, SOCKS_CMD_CONNECT, 0x00, SOCKS_ATYP_DOMAIN]);
+        expected.push(22);
+        expected.extend_from_slice(b"expyuzz4wqqyqhjn.onion");
+        expected.extend_from_slice(&20444u16.to_be_bytes());
+        assert_eq!(stream.output, expected);
+    }
+
+    #[test]
+    fn test_socks_target_v3_onion() {
+        let host = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        let addrbytes = PeerAddress::from_onion_host(host).unwrap();
+        assert!(addrbytes.is_onion());
+        assert!(!addrbytes.is_in_private_range());
+        assert_eq!(addrbytes.to_onion_host(), Some(host.to_string()));
+
+        // the name is parsed case-insensitively, to the same address
+        assert_eq!(
+            PeerAddress::from_onion_host(&host.to_ascii_uppercase()),
+            Some(addrbytes.clone())
+        );
+
+        let target = SocksTarget::from_peer_address(&addrbytes, 20444).unwrap();
+        assert_eq!(target, SocksTarget::Host(host.to_string(), 20444));
+
+        // a v3 stand-in address whose name was never parsed can't be mapped back
+        let mut unknown = addrbytes.clone();
+        unknown.0[15] ^= 0xff;
+        assert!(unknown.is_onion());
+        assert_eq!(unknown.to_onion_host(), None);
+        assert!(unknown.is_onion_standin());
+
+        // ...so the proxy can't be asked to reach it
+        assert_eq!(SocksTarget::from_peer_address(&unknown, 20444), None);
+
+        // a 56-character name needs the v3 version byte
+        assert_eq!(
+            PeerAddress::from_onion_host(
+                "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczaa.onion"
+            ),
+            None
+        );
+        // and only 16- and 56-character names are onion names
+        assert_eq!(
+            PeerAddress::from_onion_host("duckduckgogg42xjoc72x3sja.onion"),
+            None
+        );
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020-2022 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The encrypted p2p transport.
+//!
+//! Once two peers that both advertise `ServiceFlags::ENCRYPTION` have completed a handshake, the
+//! peer that opened the connection (the initiator) sends a `TransportKey` message with a fresh
+//! ephemeral key.  The other peer (the responder) answers with a `TransportKey` of its own, and
+//! encrypts everything it sends thereafter.  The initiator then sends `TransportReady`, and
+//! encrypts everything it sends thereafter.
+//!
+//! The session keys are derived Noise-style from three ECDH results -- ephemeral-ephemeral,
+//! initiator-ephemeral-to-responder-static, and initiator-static-to-responder-ephemeral -- so
+//! they are bound to both peers' node keys (the same keys that sign the handshake) as well as to
+//! the ephemeral keys.  Each direction gets its own key.
+//!
+//! Encrypted bytes are sent as a sequence of frames, each of which is a 2-byte big-endian length
+//! followed by that many bytes of AES-256-GCM ciphertext and tag.  The length is authenticated as
+//! associated data, and the nonce is a per-direction frame counter.
+
+use std::fmt;
+
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::Aes256Gcm;
+use sha2::{Digest, Sha256};
+
+use crate::net::Error as net_error;
+use crate::types::PrivateKey;
+use crate::types::StacksPublicKeyBuffer;
+use stacks_common::util::secp256k1::Secp256k1PrivateKey;
+use stacks_common::util::secp256k1::Secp256k1PublicKey;
+
+/// Domain separator for the transport key schedule
+const TRANSPORT_PROTOCOL_NAME: &[u8] = b"Stacks_P2P_secp256k1_AES256GCM_SHA256";
+
+/// Length of the AES-GCM authentication tag appended to each frame
+pub const TRANSPORT_TAG_LEN: usize = 16;
+
+/// Largest amount of plaintext that goes into a single frame
+pub const TRANSPORT_MAX_FRAME_PLAINTEXT: usize = 16384;
+
+/// Length of a frame's length prefix
+const TRANSPORT_FRAME_HEADER_LEN: usize = 2;
+
+/// HMAC-SHA256 over the concatenation of `data`
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let mut block_key = [0u8; 64];
+    if key.len() > 64 {
+        block_key[0..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block_key[0..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(&block_key.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    for d in data.iter() {
+        inner.update(d);
+    }
+    let inner_hash = inner.finalize();
+
+    let mut outer = Sha256::new();
+    outer.update(&block_key.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(&inner_hash);
+
+    let mut ret = [0u8; 32];
+    ret.copy_from_slice(&outer.finalize());
+    ret
+}
+
+/// Elliptic-curve Diffie-Hellman between one of our private keys and one of the peer's public keys
+fn ecdh(privkey: &Secp256k1PrivateKey, pubkey: &Secp256k1PublicKey) -> Result<[u8; 32], net_error> {
+    let secret_key = secp256k1::SecretKey::from_slice(&privkey.to_bytes()[0..32])
+        .map_err(|_e| net_error::InvalidHandshake)?;
+    let public_key = secp256k1::PublicKey::from_slice(&pubkey.to_bytes_compressed())
+        .map_err(|_e| net_error::InvalidHandshake)?;
+    let shared = secp256k1::ecdh::SharedSecret::new(&public_key, &secret_key);
+
+    let mut ret = [0u8; 32];
+    ret.copy_from_slice(&shared[0..32]);
+    Ok(ret)
+}
+
+/// One direction of an encrypted transport.  The sending side seals plaintext into frames; the
+/// receiving side opens frames, buffering any trailing partial frame until the rest of it arrives.
+pub struct TransportCipher {
+    aead: Aes256Gcm,
+    /// number of frames sealed or opened so far; used as the nonce
+    counter: u64,
+    /// bytes of a frame we have only partially received
+    partial_frame: Vec<u8>,
+}
+
+impl fmt::Debug for TransportCipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TransportCipher(counter={},partial={})",
+            self.counter,
+            self.partial_frame.len()
+        )
+    }
+}
+
+impl TransportCipher {
+    pub fn new(key: &[u8; 32]) -> TransportCipher {
+        TransportCipher {
+            aead: Aes256Gcm::new(GenericArray::from_slice(key)),
+            counter: 0,
+            partial_frame: vec![],
+        }
+    }
+
+    /// Get the nonce for the next frame, and advance the counter
+    fn next_nonce(&mut self) -> Result<[u8; 12], net_error> {
+        let mut nonce = [0u8; 12];
+        nonce[4..12].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or(net_error::ConnectionBroken)?;
+        Ok(nonce)
+    }
+
+    /// Encrypt `plaintext` into one or more frames, and append them to `out`
+    pub fn seal_frames(&mut self, plaintext: &[u8], out: &mut Vec<u8>) -> Result<(), net_error> {
+        for chunk in plaintext.chunks(TRANSPORT_MAX_FRAME_PLAINTEXT) {
+            let frame_len = ((chunk.len() + TRANSPORT_TAG_LEN) as u16).to_be_bytes();
+            let nonce = self.next_nonce()?;
+            let ciphertext = self
+                .aead
+                .encrypt(
+                    GenericArray::from_slice(&nonce),
+                    Payload {
+                        msg: chunk,
+                        aad: &frame_len,
+                    },
+                )
+                .map_err(|_e| {
+                    net_error::SerializeError("Failed to encrypt transport frame".to_string())
+                })?;
+
+            out.extend_from_slice(&frame_len);
+            out.extend_from_slice(&ciphertext);
+        }
+        Ok(())
+    }
+
+    /// Decrypt as many whole frames as can be found in the bytes received so far, and return
+    /// their plaintext.  Fails if any frame does not authenticate.
+    pub fn open_frames(&mut self, bytes: &[u8]) -> Result<Vec<u8>, net_error> {
+        let mut buf = std::mem::replace(&mut self.partial_frame, vec![]);
+        buf.extend_from_slice(bytes);
+
+        let mut plaintext = vec![];
+        let mut offset = 0;
+        while buf.len() - offset >= TRANSPORT_FRAME_HEADER_LEN {
+            let frame_len_bytes = [buf[offset], buf[offset + 1]];
+            let frame_len = u16::from_be_bytes(frame_len_bytes) as usize;
+            if frame_len < TRANSPORT_TAG_LEN
+                || frame_len > TRANSPORT_MAX_FRAME_PLAINTEXT + TRANSPORT_TAG_LEN
+            {
+                return Err(net_error::DeserializeError(format!(
+                    "Invalid transport frame length {}",
+                    frame_len
+                )));
+            }
+
+            let frame_start = offset + TRANSPORT_FRAME_HEADER_LEN;
+            if buf.len() - frame_start < frame_len {
+                break;
+            }
+
+            let nonce = self.next_nonce()?;
+            let frame_plaintext = self
+                .aead
+                .decrypt(
+                    GenericArray::from_slice(&nonce),
+                    Payload {
+                        msg: &buf[frame_start..(frame_start + frame_len)],
+                        aad: &frame_len_bytes,
+                    },
+                )
+                .map_err(|_e| {
+                    net_error::DeserializeError("Failed to decrypt transport frame".to_string())
+                })?;
+
+            plaintext.extend_from_slice(&frame_plaintext);
+            offset = frame_start + frame_len;
+        }
+
+        self.partial_frame = buf.split_off(offset);
+        Ok(plaintext)
+    }
+}
+
+/// Our half of the transport key exchange with a peer
+pub struct TransportKeyExchange {
+    /// did we open the connection?
+    initiator: bool,
+    ephemeral_key: Secp256k1PrivateKey,
+}
+
+impl fmt::Debug for TransportKeyExchange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TransportKeyExchange(initiator={},ephemeral_key={})",
+            self.initiator,
+            &self.public_key_buffer()
+        )
+    }
+}
+
+impl TransportKeyExchange {
+    pub fn new(initiator: bool) -> TransportKeyExchange {
+        let mut ephemeral_key = Secp256k1PrivateKey::new();
+        ephemeral_key.set_compress_public(true);
+        TransportKeyExchange {
+            initiator,
+            ephemeral_key,
+        }
+    }
+
+    /// The ephemeral public key to send to the peer
+    pub fn public_key_buffer(&self) -> StacksPublicKeyBuffer {
+        StacksPublicKeyBuffer::from_public_key(&Secp256k1PublicKey::from_private(
+            &self.ephemeral_key,
+        ))
+    }
+
+    /// Given our node key, the peer's node key, and the peer's ephemeral key, derive the
+    /// ciphers for the session.  Returns (send cipher, recv cipher).
+    pub fn finish(
+        &self,
+        local_key: &Secp256k1PrivateKey,
+        peer_key: &Secp256k1PublicKey,
+        peer_ephemeral_key: &StacksPublicKeyBuffer,
+    ) -> Result<(TransportCipher, TransportCipher), net_error> {
+        let peer_ephemeral_pubkey = peer_ephemeral_key
+            .to_public_key()
+            .map_err(|_e| net_error::InvalidHandshake)?;
+
+        let local_static =
+            StacksPublicKeyBuffer::from_public_key(&Secp256k1PublicKey::from_private(local_key));
+        let peer_static = StacksPublicKeyBuffer::from_public_key(peer_key);
+        let local_ephemeral = self.public_key_buffer();
+
+        let ee = ecdh(&self.ephemeral_key, &peer_ephemeral_pubkey)?;
+        let (es, se) = if self.initiator {
+            (
+                ecdh(&self.ephemeral_key, peer_key)?,
+                ecdh(local_key, &peer_ephemeral_pubkey)?,
+            )
+        } else {
+            (
+                ecdh(local_key, &peer_ephemeral_pubkey)?,
+                ecdh(&self.ephemeral_key, peer_key)?,
+            )
+        };
+
+        // both sides hash the transcript in initiator-then-responder order
+        let (i_static, r_static, i_ephemeral, r_ephemeral) = if self.initiator {
+            (
+                &local_static,
+                &peer_static,
+                &local_ephemeral,
+                peer_ephemeral_key,
+            )
+        } else {
+            (
+                &peer_static,
+                &local_static,
+                peer_ephemeral_key,
+                &local_ephemeral,
+            )
+        };
+
+        let mut transcript = Sha256::new();
+        transcript.update(TRANSPORT_PROTOCOL_NAME);
+        transcript.update(i_static.as_bytes());
+        transcript.update(r_static.as_bytes());
+        transcript.update(i_ephemeral.as_bytes());
+        transcript.update(r_ephemeral.as_bytes());
+
+        let mut chaining_key = [0u8; 32];
+        chaining_key.copy_from_slice(&transcript.finalize());
+        for dh in [ee, es, se].iter() {
+            chaining_key = hmac_sha256(&chaining_key, &[&dh[..]]);
+        }
+
+        let initiator_key = hmac_sha256(&chaining_key, &[&[0x01u8][..]]);
+        let responder_key = hmac_sha256(&chaining_key, &[&[0x02u8][..]]);
+
+        let initiator_cipher = TransportCipher::new(&initiator_key);
+        let responder_cipher = TransportCipher::new(&responder_key);
+        if self.initiator {
+            Ok((initiator_cipher, responder_cipher))
+        } else {
+            Ok((responder_cipher, initiator_cipher))
+        }
+    }
+}
+
+/// Where a conversation is in setting up the encrypted transport
+#[derive(Debug)]
+pub enum TransportState {
+    /// Traffic is not encrypted (the default, and the only state if either peer lacks
+    /// `ServiceFlags::ENCRYPTION`)
+    Plaintext,
+    /// We opened the connection and sent our ephemeral key, and are waiting for the peer's
+    KeySent(TransportKeyExchange),
+    /// We answered the peer's ephemeral key and now encrypt what we send.  The peer's traffic will
+    /// be encrypted with this cipher once it sends `TransportReady`.
+    KeyReplied(TransportCipher),
+    /// Traffic is encrypted in both directions
+    Encrypted,
+}
+
+impl TransportState {
+    pub fn is_encrypted(&self) -> bool {
+        match *self {
+            TransportState::Encrypted => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::RngCore;
+
+    use super::*;
+
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        bytes
+    }
+
+    /// Run the key exchange between two node keys, and return the initiator's and responder's
+    /// (send, recv) ciphers
+    fn make_session(
+        initiator_key: &Secp256k1PrivateKey,
+        responder_key: &Secp256k1PrivateKey,
+    ) -> (
+        (TransportCipher, TransportCipher),
+        (TransportCipher, TransportCipher),
+    ) {
+        let initiator = TransportKeyExchange::new(true);
+        let responder = TransportKeyExchange::new(false);
+
+        let initiator_ciphers = initiator
+            .finish(
+                initiator_key,
+                &Secp256k1PublicKey::from_private(responder_key),
+                &responder.public_key_buffer(),
+            )
+            .unwrap();
+        let responder_ciphers = responder
+            .finish(
+                responder_key,
+                &Secp256k1PublicKey::from_private(initiator_key),
+                &initiator.public_key_buffer(),
+            )
+            .unwrap();
+
+        (initiator_ciphers, responder_ciphers)
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        let mac = hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]);
+        assert_eq!(
+            stacks_common::util::hash::to_hex(&mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_transport_round_trip() {
+        let alice = Secp256k1PrivateKey::new();
+        let bob = Secp256k1PrivateKey::new();
+
+        let ((mut alice_send, mut alice_recv), (mut bob_send, mut bob_recv)) =
+            make_session(&alice, &bob);
+
+        for len in [
+            0,
+            1,
+            100,
+            TRANSPORT_MAX_FRAME_PLAINTEXT,
+            3 * TRANSPORT_MAX_FRAME_PLAINTEXT + 7,
+        ]
+        .iter()
+        {
+            let plaintext = random_bytes(*len);
+
+            let mut frames = vec![];
+            alice_send.seal_frames(&plaintext, &mut frames).unwrap();
+            if plaintext.len() > 0 {
+                assert_ne!(&frames[..], &plaintext[..]);
+            }
+
+            // deliver one byte at a time -- partial frames are buffered
+            let mut received = vec![];
+            for byte in frames.iter() {
+                received.append(&mut bob_recv.open_frames(&[*byte]).unwrap());
+            }
+            assert_eq!(received, plaintext);
+
+            // and the other direction, all at once
+            let mut frames = vec![];
+            bob_send.seal_frames(&plaintext, &mut frames).unwrap();
+            assert_eq!(alice_recv.open_frames(&frames).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_transport_rejects_bad_frames() {
+        let alice = Secp256k1PrivateKey::new();
+        let bob = Secp256k1PrivateKey::new();
+
+        // tampered ciphertext
+        let ((mut alice_send, _), (_, mut bob_recv)) = make_session(&alice, &bob);
+        let mut frames = vec![];
+        alice_send.seal_frames(&[1, 2, 3, 4], &mut frames).unwrap();
+        frames[3] ^= 0x01;
+        assert!(bob_recv.open_frames(&frames).is_err());
+
+        // replayed frame
+        let ((mut alice_send, _), (_, mut bob_recv)) = make_session(&alice, &bob);
+        let mut frames = vec![];
+        alice_send.seal_frames(&[1, 2, 3, 4], &mut frames).unwrap();
+        assert_eq!(bob_recv.open_frames(&frames).unwrap(), vec![1, 2, 3, 4]);
+        assert!(bob_recv.open_frames(&frames).is_err());
+
+        // a frame sealed for the other direction
+        let ((mut alice_send, mut alice_recv), _) = make_session(&alice, &bob);
+        let mut frames = vec![];
+        alice_send.seal_frames(&[1, 2, 3, 4], &mut frames).unwrap();
+        assert!(alice_recv.open_frames(&frames).is_err());
+
+        // a peer that claims someone else's node key derives different keys
+        let mallory = Secp256k1PrivateKey::new();
+        let alice_kx = TransportKeyExchange::new(true);
+        let mallory_kx = TransportKeyExchange::new(false);
+        let (mut alice_send, _) = alice_kx
+            .finish(
+                &alice,
+                &Secp256k1PublicKey::from_private(&bob),
+                &mallory_kx.public_key_buffer(),
+            )
+            .unwrap();
+        let (_, mut mallory_recv) = mallory_kx
+            .finish(
+                &mallory,
+                &Secp256k1PublicKey::from_private(&alice),
+                &alice_kx.public_key_buffer(),
+            )
+            .unwrap();
+        let mut frames = vec![];
+        alice_send.seal_frames(&[1, 2, 3, 4], &mut frames).unwrap();
+        assert!(mallory_recv.open_frames(&frames).is_err());
+
+        // bogus frame length
+        let ((_, _), (_, mut bob_recv)) = make_session(&alice, &bob);
+        assert!(bob_recv.open_frames(&[0x00, 0x01, 0xff]).is_err());
+    }
+}
@@ -8,7 +8,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use serde::Serialize;
 use serde_json::value::RawValue;
@@ -44,6 +44,8 @@ use stacks::chainstate::coordinator::comm::CoordinatorChannels;
 use stacks::chainstate::stacks::address::StacksAddressExtensions;
 use stacks::codec::StacksMessageCodec;
 use stacks::core::StacksEpoch;
+use stacks::net::socks;
+use stacks::net::socks::SocksTarget;
 use stacks::util::hash::{hex_bytes, Hash160};
 use stacks::util::secp256k1::Secp256k1PublicKey;
 use stacks::util::sleep_ms;
@@ -243,6 +245,7 @@ impl BitcoinRegtestController {
                 first_block: burnchain_params.first_block_height,
                 magic_bytes: burnchain_config.magic_bytes,
                 epochs: burnchain_config.epochs,
+                proxy: config.connection_options.proxy.clone(),
             }
         };
 
@@ -287,6 +290,7 @@ impl BitcoinRegtestController {
                 first_block: burnchain_params.first_block_height,
                 magic_bytes: burnchain_config.magic_bytes,
                 epochs: burnchain_config.epochs,
+                proxy: config.connection_options.proxy.clone(),
             }
         };
 
@@ -2051,15 +2055,37 @@ impl BitcoinRPCRequest {
         request.append_header("Content-Type", "application/json");
         request.set_body(body);
 
-        let mut response = async_std::task::block_on(async move {
-            let stream = match TcpStream::connect(config.burnchain.get_rpc_socket_addr()).await {
-                Ok(stream) => stream,
-                Err(err) => {
-                    return Err(RPCError::Network(format!(
-                        "Bitcoin RPC: connection failed - {:?}",
-                        err
-                    )))
+        // if we have a proxy, the connection to it is made up front, and blocks
+        let proxied_stream = match config.connection_options.proxy {
+            Some(ref proxy) => {
+                let target =
+                    SocksTarget::from_host(&config.burnchain.peer_host, config.burnchain.rpc_port);
+                let timeout = Duration::from_secs(config.burnchain.timeout.into());
+                match socks::connect_blocking(proxy, target, timeout) {
+                    Ok(stream) => Some(TcpStream::from(stream)),
+                    Err(err) => {
+                        return Err(RPCError::Network(format!(
+                            "Bitcoin RPC: connection via {} failed - {:?}",
+                            proxy, err
+                        )))
+                    }
                 }
+            }
+            None => None,
+        };
+
+        let mut response = async_std::task::block_on(async move {
+            let stream = match proxied_stream {
+                Some(stream) => stream,
+                None => match TcpStream::connect(config.burnchain.get_rpc_socket_addr()).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        return Err(RPCError::Network(format!(
+                            "Bitcoin RPC: connection failed - {:?}",
+                            err
+                        )))
+                    }
+                },
             };
 
             match client::connect(stream, request).await {
@@ -1,9 +1,10 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fs;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use rand::RngCore;
 
@@ -28,11 +29,15 @@ use stacks::cost_estimates::CostEstimator;
 use stacks::cost_estimates::FeeEstimator;
 use stacks::cost_estimates::PessimisticEstimator;
 use stacks::net::connection::ConnectionOptions;
-use stacks::net::{Neighbor, NeighborKey, PeerAddress};
+use stacks::net::socks;
+use stacks::net::{
+    Neighbor, NeighborKey, PeerAddress, PeerHost, MAX_PEER_ADDRESSES, MAX_PEER_HOSTNAME_LEN,
+};
 use stacks::util::get_epoch_time_ms;
 use stacks::util::hash::hex_bytes;
 use stacks::util::secp256k1::Secp256k1PrivateKey;
 use stacks::util::secp256k1::Secp256k1PublicKey;
+use stacks::util_lib::strings::UrlString;
 use stacks::vm::types::{AssetIdentifier, PrincipalData, QualifiedContractIdentifier};
 
 const DEFAULT_SATS_PER_VB: u64 = 50;
@@ -105,6 +110,20 @@ mod tests {
             .unwrap_err()
         );
 
+        assert_eq!(
+            format!("node.bootstrap_snapshot_min_peers should be at least 1"),
+            Config::from_config_file(
+                ConfigFile::from_str(
+                    r#"
+                    [node]
+                    bootstrap_snapshot_min_peers = 0
+                    "#,
+                )
+                .unwrap()
+            )
+            .unwrap_err()
+        );
+
         let expected_err_prefix =
             "Invalid burnchain.peer_host: failed to lookup address information:";
         let actual_err_msg = Config::from_config_file(
@@ -396,6 +415,20 @@ lazy_static! {
 
 impl Config {
     pub fn from_config_file(config_file: ConfigFile) -> Result<Config, String> {
+        // needed up front, since host names get looked up through it
+        let proxy = match config_file
+            .connection_options
+            .as_ref()
+            .and_then(|opts| opts.proxy.as_ref())
+        {
+            Some(proxy) => Some(
+                proxy
+                    .parse::<SocketAddr>()
+                    .map_err(|e| format!("Invalid connection_options.proxy: {}", &e))?,
+            ),
+            None => None,
+        };
+
         let default_node_config = NodeConfig::default();
         let (mut node, bootstrap_node, deny_nodes) = match config_file.node {
             Some(node) => {
@@ -450,6 +483,30 @@ impl Config {
                         .pox_sync_sample_secs
                         .unwrap_or(default_node_config.pox_sync_sample_secs),
                     use_test_genesis_chainstate: node.use_test_genesis_chainstate,
+                    bootstrap_snapshot: node.bootstrap_snapshot,
+                    bootstrap_snapshot_peers: node
+                        .bootstrap_snapshot_peers
+                        .map(|peers| {
+                            peers
+                                .split(",")
+                                .map(|peer| peer.trim().to_string())
+                                .filter(|peer| peer.len() > 0)
+                                .collect()
+                        })
+                        .unwrap_or(default_node_config.bootstrap_snapshot_peers),
+                    bootstrap_snapshot_min_peers: match node.bootstrap_snapshot_min_peers {
+                        Some(0) => {
+                            return Err(format!(
+                                "node.bootstrap_snapshot_min_peers should be at least 1"
+                            ));
+                        }
+                        Some(min_peers) => min_peers,
+                        None => default_node_config.bootstrap_snapshot_min_peers,
+                    },
+                    prune_reward_cycles: node.prune_reward_cycles,
+                    compact_marf: node
+                        .compact_marf
+                        .unwrap_or(default_node_config.compact_marf),
                 };
                 (node_config, node.bootstrap_node, node.deny_nodes)
             }
@@ -514,6 +571,8 @@ impl Config {
                         .commit_anchor_block_within
                         .unwrap_or(default_burnchain_config.commit_anchor_block_within),
                     peer_host: match burnchain.peer_host {
+                        // the proxy will look it up when we connect
+                        Some(peer_host) if proxy.is_some() => peer_host,
                         Some(peer_host) => {
                             // Using std::net::LookupHost would be preferable, but it's
                             // unfortunately unstable at this point.
@@ -629,7 +688,12 @@ impl Config {
         }
 
         if let Some(bootstrap_node) = bootstrap_node {
-            node.set_bootstrap_nodes(bootstrap_node, burnchain.chain_id, burnchain.peer_version);
+            node.set_bootstrap_nodes(
+                bootstrap_node,
+                burnchain.chain_id,
+                burnchain.peer_version,
+                proxy.as_ref(),
+            );
         } else {
             if burnchain.mode == "mainnet" {
                 let bootstrap_node = ConfigFile::mainnet().node.unwrap().bootstrap_node.unwrap();
@@ -637,11 +701,17 @@ impl Config {
                     bootstrap_node,
                     burnchain.chain_id,
                     burnchain.peer_version,
+                    proxy.as_ref(),
                 );
             }
         }
         if let Some(deny_nodes) = deny_nodes {
-            node.set_deny_nodes(deny_nodes, burnchain.chain_id, burnchain.peer_version);
+            node.set_deny_nodes(
+                deny_nodes,
+                burnchain.chain_id,
+                burnchain.peer_version,
+                proxy.as_ref(),
+            );
         }
 
         let initial_balances: Vec<InitialBalance> = match config_file.ustx_balance {
@@ -692,16 +762,20 @@ impl Config {
             _ => (),
         };
 
-        let connection_options = match config_file.connection_options {
+        let mut connection_options = match config_file.connection_options {
             Some(opts) => {
                 let ip_addr = match opts.public_ip_address {
                     Some(public_ip_address) => {
-                        let addr = public_ip_address.parse::<SocketAddr>().unwrap();
+                        let addr = resolve_hostport(&public_ip_address, proxy.as_ref()).unwrap();
                         debug!("addr.parse {:?}", addr);
                         Some((PeerAddress::from_socketaddr(&addr), addr.port()))
                     }
                     None => None,
                 };
+                let public_addresses = match opts.public_addresses.as_ref() {
+                    Some(addrs) => parse_public_addresses(addrs)?,
+                    None => vec![],
+                };
                 let mut read_only_call_limit = HELIUM_DEFAULT_CONNECTION_OPTIONS
                     .read_only_call_limit
                     .clone();
@@ -826,6 +900,56 @@ impl Config {
                     }),
                     public_ip_address: ip_addr,
                     disable_inbound_walks: opts.disable_inbound_walks.unwrap_or(false),
+                    encrypted_transport: opts.encrypted_transport.unwrap_or(false),
+                    proxy: proxy.clone(),
+                    archive_data_url: match opts.archive_data_url.as_ref() {
+                        Some(url) => {
+                            let url = UrlString::try_from(url.as_str()).map_err(|e| {
+                                format!("Invalid connection_options.archive_data_url: {:?}", &e)
+                            })?;
+                            url.parse_to_block_url().map_err(|e| {
+                                format!("Invalid connection_options.archive_data_url: {:?}", &e)
+                            })?;
+                            Some(url)
+                        }
+                        None => None,
+                    },
+                    compact_blocks: opts.compact_blocks.unwrap_or(false),
+                    multi_address: opts.multi_address.unwrap_or(public_addresses.len() > 0),
+                    public_addresses,
+                    max_peer_inbound_bandwidth: opts.max_peer_inbound_bandwidth.unwrap_or_else(
+                        || HELIUM_DEFAULT_CONNECTION_OPTIONS.max_peer_inbound_bandwidth,
+                    ),
+                    max_peer_outbound_bandwidth: opts.max_peer_outbound_bandwidth.unwrap_or_else(
+                        || HELIUM_DEFAULT_CONNECTION_OPTIONS.max_peer_outbound_bandwidth,
+                    ),
+                    dandelion: opts.dandelion.unwrap_or(false),
+                    dandelion_fluff_probability: match opts.dandelion_fluff_probability {
+                        Some(pct) if pct > 100 => {
+                            return Err(format!(
+                                "Invalid connection_options.dandelion_fluff_probability: {} is not a percentage",
+                                pct
+                            ));
+                        }
+                        Some(pct) => pct,
+                        None => HELIUM_DEFAULT_CONNECTION_OPTIONS.dandelion_fluff_probability,
+                    },
+                    dandelion_embargo: opts
+                        .dandelion_embargo
+                        .unwrap_or_else(|| HELIUM_DEFAULT_CONNECTION_OPTIONS.dandelion_embargo),
+                    dandelion_epoch: opts
+                        .dandelion_epoch
+                        .unwrap_or_else(|| HELIUM_DEFAULT_CONNECTION_OPTIONS.dandelion_epoch),
+                    reputation_half_life: opts.reputation_half_life.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .reputation_half_life
+                            .clone()
+                    }),
+                    reputation_ban_threshold: opts.reputation_ban_threshold.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .reputation_ban_threshold
+                            .clone()
+                    }),
                     disable_inbound_handshakes: opts.disable_inbound_handshakes.unwrap_or(false),
                     disable_block_download: opts.disable_block_download.unwrap_or(false),
                     force_disconnect_interval: opts.force_disconnect_interval,
@@ -842,6 +966,9 @@ impl Config {
             None => HELIUM_DEFAULT_CONNECTION_OPTIONS.clone(),
         };
 
+        // pruning is a property of the node, but the p2p network is what carries it out
+        connection_options.prune_reward_cycles = node.prune_reward_cycles;
+
         let estimation = match config_file.fee_estimation {
             Some(f) => FeeEstimationConfig::from(f),
             None => FeeEstimationConfig::default(),
@@ -1157,6 +1284,16 @@ pub struct NodeConfig {
     pub marf_defer_hashing: bool,
     pub pox_sync_sample_secs: u64,
     pub use_test_genesis_chainstate: Option<bool>,
+    /// Directory of a chainstate snapshot to bootstrap from, if the node has no chain state yet
+    pub bootstrap_snapshot: Option<String>,
+    /// RPC endpoints (host:port) of the peers that must vouch for the snapshot's anchor
+    pub bootstrap_snapshot_peers: Vec<String>,
+    /// Minimum number of those peers that must agree with the snapshot's anchor
+    pub bootstrap_snapshot_min_peers: usize,
+    /// If set, delete block and microblock data older than this many reward cycles
+    pub prune_reward_cycles: Option<u64>,
+    /// If pruning, also drop stale forks' MARF tries below the prune height on startup
+    pub compact_marf: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -1389,6 +1526,87 @@ impl FeeEstimationConfig {
     }
 }
 
+/// Parse a comma-separated list of HOST:PORT addresses this node can also be reached at.  Hosts
+/// that aren't IP addresses are advertised as DNS names, and are not resolved.
+fn parse_public_addresses(addrs: &str) -> Result<Vec<PeerHost>, String> {
+    let mut public_addresses = vec![];
+    for hostport in addrs
+        .split(',')
+        .map(|hp| hp.trim())
+        .filter(|hp| hp.len() > 0)
+    {
+        if let Ok(addr) = hostport.parse::<SocketAddr>() {
+            public_addresses.push(PeerHost::from_socketaddr(&addr));
+            continue;
+        }
+        let (host, port) = match hostport.rsplit_once(':') {
+            Some((host, port_str)) => (
+                host,
+                port_str
+                    .parse::<u16>()
+                    .map_err(|_e| format!("Invalid port in '{}'", hostport))?,
+            ),
+            None => {
+                return Err(format!(
+                    "Invalid address '{}': expected HOST:PORT",
+                    hostport
+                ));
+            }
+        };
+        if host.len() == 0
+            || host.len() > (MAX_PEER_HOSTNAME_LEN as usize)
+            || !host.is_ascii()
+            || host.contains(':')
+        {
+            return Err(format!("Invalid host name in '{}'", hostport));
+        }
+        public_addresses.push(PeerHost::DNS(host.to_string(), port));
+    }
+    if public_addresses.len() > (MAX_PEER_ADDRESSES as usize) {
+        return Err(format!(
+            "Too many connection_options.public_addresses: at most {} are allowed",
+            MAX_PEER_ADDRESSES
+        ));
+    }
+    Ok(public_addresses)
+}
+
+/// Resolve a `HOST:PORT` string to a socket address.  Onion host names map to their onion
+/// addresses, and if there's a proxy, other host names are looked up through it.
+fn resolve_hostport(hostport: &str, proxy: Option<&SocketAddr>) -> Result<SocketAddr, String> {
+    if let Ok(addr) = hostport.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let (host, port) = match hostport.rsplit_once(':') {
+        Some((host, port_str)) => (
+            host,
+            port_str
+                .parse::<u16>()
+                .map_err(|_e| format!("Invalid port in '{}'", hostport))?,
+        ),
+        None => {
+            return Err(format!(
+                "Invalid address '{}': expected HOST:PORT",
+                hostport
+            ));
+        }
+    };
+    if let Some(addrbytes) = PeerAddress::from_onion_host(host) {
+        return Ok(addrbytes.to_socketaddr(port));
+    }
+    debug!("Resolve '{}'", hostport);
+    match proxy {
+        Some(proxy) => socks::resolve_blocking(proxy, host, Duration::from_secs(30))
+            .map(|ip| SocketAddr::new(ip, port))
+            .map_err(|e| format!("Failed to resolve '{}' via {}: {:?}", host, proxy, &e)),
+        None => hostport
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve '{}': {}", hostport, &e))?
+            .next()
+            .ok_or_else(|| format!("No IP address could be queried for '{}'", hostport)),
+    }
+}
+
 impl NodeConfig {
     fn default() -> NodeConfig {
         let mut rng = rand::thread_rng();
@@ -1431,6 +1649,11 @@ impl NodeConfig {
             marf_defer_hashing: true,
             pox_sync_sample_secs: 30,
             use_test_genesis_chainstate: None,
+            bootstrap_snapshot: None,
+            bootstrap_snapshot_peers: vec![],
+            bootstrap_snapshot_min_peers: 3,
+            prune_reward_cycles: None,
+            compact_marf: false,
         }
     }
 
@@ -1459,7 +1682,13 @@ impl NodeConfig {
         }
     }
 
-    pub fn add_bootstrap_node(&mut self, bootstrap_node: &str, chain_id: u32, peer_version: u32) {
+    pub fn add_bootstrap_node(
+        &mut self,
+        bootstrap_node: &str,
+        chain_id: u32,
+        peer_version: u32,
+        proxy: Option<&SocketAddr>,
+    ) {
         let parts: Vec<&str> = bootstrap_node.split("@").collect();
         if parts.len() != 2 {
             panic!(
@@ -1470,8 +1699,7 @@ impl NodeConfig {
         let (pubkey_str, hostport) = (parts[0], parts[1]);
         let pubkey = Secp256k1PublicKey::from_hex(pubkey_str)
             .expect(&format!("Invalid public key '{}'", pubkey_str));
-        debug!("Resolve '{}'", &hostport);
-        let sockaddr = hostport.to_socket_addrs().unwrap().next().unwrap();
+        let sockaddr = resolve_hostport(hostport, proxy).unwrap();
         let neighbor = NodeConfig::default_neighbor(sockaddr, pubkey, chain_id, peer_version);
         self.bootstrap_node.push(neighbor);
     }
@@ -1481,17 +1709,24 @@ impl NodeConfig {
         bootstrap_nodes: String,
         chain_id: u32,
         peer_version: u32,
+        proxy: Option<&SocketAddr>,
     ) {
         let parts: Vec<&str> = bootstrap_nodes.split(",").collect();
         for part in parts.into_iter() {
             if part.len() > 0 {
-                self.add_bootstrap_node(&part, chain_id, peer_version);
+                self.add_bootstrap_node(&part, chain_id, peer_version, proxy);
             }
         }
     }
 
-    pub fn add_deny_node(&mut self, deny_node: &str, chain_id: u32, peer_version: u32) {
-        let sockaddr = deny_node.to_socket_addrs().unwrap().next().unwrap();
+    pub fn add_deny_node(
+        &mut self,
+        deny_node: &str,
+        chain_id: u32,
+        peer_version: u32,
+        proxy: Option<&SocketAddr>,
+    ) {
+        let sockaddr = resolve_hostport(deny_node, proxy).unwrap();
         let neighbor = NodeConfig::default_neighbor(
             sockaddr,
             Secp256k1PublicKey::from_private(&Secp256k1PrivateKey::new()),
@@ -1501,11 +1736,17 @@ impl NodeConfig {
         self.deny_nodes.push(neighbor);
     }
 
-    pub fn set_deny_nodes(&mut self, deny_nodes: String, chain_id: u32, peer_version: u32) {
+    pub fn set_deny_nodes(
+        &mut self,
+        deny_nodes: String,
+        chain_id: u32,
+        peer_version: u32,
+        proxy: Option<&SocketAddr>,
+    ) {
         let parts: Vec<&str> = deny_nodes.split(",").collect();
         for part in parts.into_iter() {
             if part.len() > 0 {
-                self.add_deny_node(&part, chain_id, peer_version);
+                self.add_deny_node(&part, chain_id, peer_version, proxy);
             }
         }
     }
@@ -1594,6 +1835,20 @@ pub struct ConnectionOptionsFile {
     pub inv_reward_cycles: Option<u64>,
     pub public_ip_address: Option<String>,
     pub disable_inbound_walks: Option<bool>,
+    pub encrypted_transport: Option<bool>,
+    pub proxy: Option<String>,
+    pub archive_data_url: Option<String>,
+    pub compact_blocks: Option<bool>,
+    pub multi_address: Option<bool>,
+    pub public_addresses: Option<String>,
+    pub max_peer_inbound_bandwidth: Option<u64>,
+    pub max_peer_outbound_bandwidth: Option<u64>,
+    pub dandelion: Option<bool>,
+    pub dandelion_fluff_probability: Option<u8>,
+    pub dandelion_embargo: Option<u64>,
+    pub dandelion_epoch: Option<u64>,
+    pub reputation_half_life: Option<u64>,
+    pub reputation_ban_threshold: Option<i64>,
     pub disable_inbound_handshakes: Option<bool>,
     pub disable_block_download: Option<bool>,
     pub force_disconnect_interval: Option<u64>,
@@ -1624,6 +1879,11 @@ pub struct NodeConfigFile {
     pub marf_defer_hashing: Option<bool>,
     pub pox_sync_sample_secs: Option<u64>,
     pub use_test_genesis_chainstate: Option<bool>,
+    pub bootstrap_snapshot: Option<String>,
+    pub bootstrap_snapshot_peers: Option<String>,
+    pub bootstrap_snapshot_min_peers: Option<usize>,
+    pub prune_reward_cycles: Option<u64>,
+    pub compact_marf: Option<bool>,
 }
 
 #[derive(Clone, Deserialize, Debug)]
@@ -31,6 +31,7 @@ pub mod neon_node;
 pub mod node;
 pub mod operations;
 pub mod run_loop;
+pub mod snapshot;
 pub mod syncctl;
 pub mod tenure;
 
@@ -208,6 +209,7 @@ fn main() {
         || conf.burnchain.mode == "krypton"
         || conf.burnchain.mode == "mainnet"
     {
+        snapshot::bootstrap_from_snapshot(&conf);
         let mut run_loop = neon::RunLoop::new(conf);
         run_loop.start(None, mine_start.unwrap_or(0));
     } else {
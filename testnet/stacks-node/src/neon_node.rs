@@ -3705,14 +3705,25 @@ impl StacksNode {
             tx.commit().unwrap();
         }
 
-        // update services to indicate we can support mempool sync
+        // update services to indicate we can support mempool sync (and, if configured, the
+        // encrypted transport, compact block relay, alternate addresses, and stem-phase
+        // transaction relay)
         {
+            let mut services = (ServiceFlags::RPC as u16) | (ServiceFlags::RELAY as u16);
+            if config.connection_options.encrypted_transport {
+                services |= ServiceFlags::ENCRYPTION as u16;
+            }
+            if config.connection_options.compact_blocks {
+                services |= ServiceFlags::COMPACT_BLOCKS as u16;
+            }
+            if config.connection_options.multi_address {
+                services |= ServiceFlags::MULTI_ADDRESS as u16;
+            }
+            if config.connection_options.dandelion {
+                services |= ServiceFlags::DANDELION as u16;
+            }
             let mut tx = peerdb.tx_begin().unwrap();
-            PeerDB::set_local_services(
-                &mut tx,
-                (ServiceFlags::RPC as u16) | (ServiceFlags::RELAY as u16),
-            )
-            .unwrap();
+            PeerDB::set_local_services(&mut tx, services).unwrap();
             tx.commit().unwrap();
         }
 
@@ -3789,6 +3800,7 @@ impl StacksNode {
     /// Continuously receives, until told otherwise.
     pub fn p2p_main(mut p2p_thread: PeerThread, event_dispatcher: EventDispatcher) {
         let (mut dns_resolver, mut dns_client) = DNSResolver::new(10);
+        dns_resolver.set_proxy(p2p_thread.config.connection_options.proxy.clone());
         // spawn a daemon thread that runs the DNS resolver.
         // It will die when the rest of the system dies.
         {
@@ -371,6 +371,25 @@ impl RunLoop {
             }
         }
 
+        // Drop stale forks' MARF tries, if we're a pruned node.  This must happen before anything
+        // else opens the chainstate.
+        if let Some(keep_reward_cycles) = self.config.node.prune_reward_cycles {
+            if self.config.node.compact_marf && self.config.get_chainstate_path().exists() {
+                match StacksChainState::compact_marfs(
+                    &self.config.get_chainstate_path_str(),
+                    &burnchain_controller.get_burnchain(),
+                    keep_reward_cycles,
+                ) {
+                    Ok(num_dropped) => {
+                        info!("Dropped MARF tries of {} stale fork blocks", num_dropped);
+                    }
+                    Err(e) => {
+                        panic!("FATAL: failed to compact chainstate MARFs: {:?}", &e);
+                    }
+                }
+            }
+        }
+
         info!("Start syncing Bitcoin headers, feel free to grab a cup of coffee, this can take a while");
 
         let burnchain_config = burnchain_controller.get_burnchain();
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use stacks::chainstate::snapshot::{
+    install_snapshot, truncate_installed_snapshot, uninstall_snapshot, verify_installed_snapshot,
+    Error as SnapshotError, SnapshotAnchor, SnapshotManifest,
+};
+use stacks::net::http::StacksHttp;
+use stacks::net::{HttpRequestMetadata, HttpRequestType, HttpResponseType, PeerHost};
+
+use crate::config::Config;
+
+/// How long to wait for a peer to report its snapshot anchor
+const SNAPSHOT_PEER_TIMEOUT_SECS: u64 = 30;
+
+/// Split a peer's `host:port` and resolve it.
+fn resolve_peer(peer: &str) -> Result<(String, u16, SocketAddr), String> {
+    let (host, port) = match peer.rsplit_once(':') {
+        Some((host, port)) => (
+            host.to_string(),
+            port.parse::<u16>()
+                .map_err(|_e| format!("Invalid port in '{}'", peer))?,
+        ),
+        None => {
+            return Err(format!("Expected host:port, got '{}'", peer));
+        }
+    };
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {:?}", peer, &e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", peer))?;
+    Ok((host, port, addr))
+}
+
+/// Ask a peer's RPC interface (at `addr`) for its anchor at the given reward cycle.
+/// Returns Ok(None) if the peer doesn't have one.
+fn query_peer_anchor(
+    peer: &str,
+    host: String,
+    port: u16,
+    addr: &SocketAddr,
+    reward_cycle: u64,
+) -> Result<Option<SnapshotAnchor>, String> {
+    let request = HttpRequestType::GetSnapshotAnchor(
+        HttpRequestMetadata::from_host(PeerHost::from_host_port(host, port), None),
+        reward_cycle,
+    );
+    let response = StacksHttp::send_request_blocking(
+        addr,
+        &request,
+        Duration::from_secs(SNAPSHOT_PEER_TIMEOUT_SECS),
+    )
+    .map_err(|e| format!("Failed to query {}: {:?}", peer, &e))?;
+
+    match response {
+        HttpResponseType::SnapshotAnchor(_, anchor) => Ok(Some(anchor)),
+        HttpResponseType::NotFound(..) => Ok(None),
+        resp => Err(format!("Unexpected response from {}: {:?}", peer, &resp)),
+    }
+}
+
+/// Check the manifest's anchor against the anchors reported by the configured peers.  Every peer
+/// that answers must agree with the manifest, and at least `bootstrap_snapshot_min_peers` of them
+/// must answer.  Peers are counted by the address they resolve to, so listing the same node
+/// twice (or under two names) doesn't count twice.
+fn check_anchor_with_peers(config: &Config, manifest: &SnapshotManifest) -> Result<(), String> {
+    let reward_cycle = manifest.anchor.reward_cycle;
+    let mut queried = HashSet::new();
+    let mut agreed = 0;
+    for peer in config.node.bootstrap_snapshot_peers.iter() {
+        let (host, port, addr) = match resolve_peer(peer) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!("{}", &e);
+                continue;
+            }
+        };
+        if !queried.insert(addr.clone()) {
+            warn!(
+                "Peer {} resolves to {}, which was already queried; not counting it again",
+                peer, &addr
+            );
+            continue;
+        }
+        match query_peer_anchor(peer, host, port, &addr, reward_cycle) {
+            Ok(Some(anchor)) => {
+                if anchor != manifest.anchor {
+                    return Err(format!(
+                        "Peer {} reports anchor {:?} for reward cycle {}, but the snapshot has {:?}",
+                        peer, &anchor, reward_cycle, &manifest.anchor
+                    ));
+                }
+                info!(
+                    "Peer {} agrees with snapshot anchor for reward cycle {}",
+                    peer, reward_cycle
+                );
+                agreed += 1;
+            }
+            Ok(None) => {
+                warn!(
+                    "Peer {} has no snapshot anchor for reward cycle {}",
+                    peer, reward_cycle
+                );
+            }
+            Err(e) => {
+                warn!("{}", &e);
+            }
+        }
+    }
+
+    if agreed < config.node.bootstrap_snapshot_min_peers {
+        return Err(format!(
+            "Only {} peer(s) confirmed the snapshot anchor for reward cycle {}, but {} are required",
+            agreed, reward_cycle, config.node.bootstrap_snapshot_min_peers
+        ));
+    }
+    Ok(())
+}
+
+/// If the node is configured with a bootstrap snapshot and has no chain state yet, verify the
+/// snapshot and install it into the node's working directory.  Panics if the snapshot can't be
+/// trusted, since starting from it anyway (or from genesis, silently) is not what the operator
+/// asked for.
+pub fn bootstrap_from_snapshot(config: &Config) {
+    let snapshot_dir = match config.node.bootstrap_snapshot {
+        Some(ref dir) => PathBuf::from(dir),
+        None => {
+            return;
+        }
+    };
+
+    let chainstate_path = config.get_chainstate_path();
+    if chainstate_path.exists() {
+        info!(
+            "Chain state already exists at {:?}; not bootstrapping from snapshot {:?}",
+            &chainstate_path, &snapshot_dir
+        );
+        return;
+    }
+    let node_dir = chainstate_path
+        .parent()
+        .expect("FATAL: chainstate path has no parent directory")
+        .to_path_buf();
+
+    let manifest = SnapshotManifest::load(&snapshot_dir).expect(&format!(
+        "FATAL: failed to load snapshot {:?}",
+        &snapshot_dir
+    ));
+
+    if manifest.mainnet != config.is_mainnet() || manifest.chain_id != config.burnchain.chain_id {
+        panic!(
+            "FATAL: snapshot {:?} is for a different network (mainnet: {}, chain ID: {})",
+            &snapshot_dir, manifest.mainnet, manifest.chain_id
+        );
+    }
+
+    info!(
+        "Verifying snapshot {:?} at reward cycle {}",
+        &snapshot_dir, manifest.anchor.reward_cycle
+    );
+    if let Err(e) = manifest.verify_files(&snapshot_dir) {
+        panic!("FATAL: snapshot {:?} is corrupt: {}", &snapshot_dir, &e);
+    }
+    if let Err(msg) = check_anchor_with_peers(config, &manifest) {
+        panic!(
+            "FATAL: refusing to bootstrap from snapshot {:?}: {}",
+            &snapshot_dir, &msg
+        );
+    }
+
+    info!(
+        "Installing snapshot {:?} into {:?}",
+        &snapshot_dir, &node_dir
+    );
+    if let Err(e) = install_snapshot(&snapshot_dir, &node_dir, &manifest) {
+        // don't clobber chain state that was there before we started
+        if !matches!(e, SnapshotError::AlreadyExists(_)) {
+            let _ = uninstall_snapshot(&node_dir);
+        }
+        panic!(
+            "FATAL: failed to install snapshot {:?}: {}",
+            &snapshot_dir, &e
+        );
+    }
+    if let Err(e) = verify_installed_snapshot(&node_dir, &manifest) {
+        let _ = uninstall_snapshot(&node_dir);
+        panic!(
+            "FATAL: installed snapshot {:?} does not match its manifest: {}",
+            &snapshot_dir, &e
+        );
+    }
+    if let Err(e) = truncate_installed_snapshot(&node_dir, &manifest) {
+        let _ = uninstall_snapshot(&node_dir);
+        panic!(
+            "FATAL: failed to truncate installed snapshot {:?} to its anchor: {}",
+            &snapshot_dir, &e
+        );
+    }
+
+    info!(
+        "Bootstrapped from snapshot {:?}: reward cycle {}, consensus hash {}, index root {}",
+        &snapshot_dir,
+        manifest.anchor.reward_cycle,
+        &manifest.anchor.consensus_hash,
+        &manifest.anchor.index_root
+    );
+}
@@ -5921,6 +5921,7 @@ fn atlas_integration_test() {
         bootstrap_node_url,
         conf_follower_node.burnchain.chain_id,
         conf_follower_node.burnchain.peer_version,
+        None,
     );
     conf_follower_node.node.miner = false;
     conf_follower_node
@@ -6448,6 +6449,7 @@ fn antientropy_integration_test() {
         bootstrap_node_url,
         conf_follower_node.burnchain.chain_id,
         conf_follower_node.burnchain.peer_version,
+        None,
     );
     conf_follower_node.node.miner = false;
     conf_follower_node
@@ -8091,6 +8093,7 @@ fn spawn_follower_node(
         ),
         conf.burnchain.chain_id,
         conf.burnchain.peer_version,
+        None,
     );
 
     conf.events_observers.push(EventObserverConfig {
@@ -9870,6 +9873,7 @@ fn test_competing_miners_build_on_same_chain(
             ),
             chain_id,
             peer_version,
+            None,
         );
     }
 